@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use stackrankdice::map_file::MapFile;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+        if let Ok(map) = MapFile::parse(source) {
+            // Whatever parses must survive a round trip
+            let reparsed = MapFile::parse(&map.to_string()).unwrap();
+            assert_eq!(reparsed.to_string(), map.to_string());
+        }
+    }
+});
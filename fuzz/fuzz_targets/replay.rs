@@ -0,0 +1,28 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use stackrankdice::game::{generate_board, GameState};
+use stackrankdice::replay::Replay;
+use stackrankdice::rules::GameRules;
+use stackrankdice::tiered_prng::get_randomness;
+
+fuzz_target!(|data: &[u8]| {
+    let source = match std::str::from_utf8(data) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+
+    let replay = match Replay::parse(source) {
+        Ok(replay) => replay,
+        Err(_) => return,
+    };
+
+    // Applying moves to a real board must reject illegal moves instead of panicking
+    let rules = GameRules::with_players(replay.number_of_players);
+    let mut game_state = GameState {
+        board: generate_board(&rules, get_randomness(4242)),
+        number_of_players: rules.number_of_players,
+        ..Default::default()
+    };
+    let _ = replay.apply(&mut game_state, &rules);
+});
@@ -0,0 +1,300 @@
+//! Player settings making the game easier to play.
+
+use bevy::prelude::*;
+
+use crate::board::{is_region_playable, StackRankDiceGameBoardElement};
+use crate::events::EventBoardChanged;
+use crate::game::{GameState, Region};
+use crate::geometry::center;
+use crate::highlights::{HighlightStyle, HighlightStyles};
+use crate::input::control_pressed;
+use crate::profiles::PlayerProfiles;
+use crate::ui::{BoardLabel, DiceRollUI};
+
+/// Key that toggles the high-contrast board
+const HIGH_CONTRAST_KEY: KeyCode = KeyCode::F5;
+
+/// Width of the black borders between regions on the high-contrast board
+const HIGH_CONTRAST_OUTLINE_WIDTH: f32 = 4.0;
+
+/// Height of the dice count labels, above the tallest stack of dice
+const DICE_COUNT_LABEL_HEIGHT: f32 = 4.2;
+
+/// Factor the HUD text is scaled by in couch mode
+const COUCH_TEXT_SCALE: f32 = 1.75;
+
+/// Factor the borders between regions are widened by in couch mode
+const COUCH_OUTLINE_SCALE: f32 = 2.5;
+
+/// Factor the views of the dice thrown in battles are enlarged by in couch mode, less than the
+/// text as the views already cover the screen
+const COUCH_DICE_SCALE: f32 = 1.3;
+
+#[derive(Resource, Default, Clone, Debug)]
+pub struct AccessibilitySettings {
+    /// Keep the camera still instead of moving and shaking it during battles
+    pub reduced_motion: bool,
+    /// Draw regions in solid saturated colors with thick black borders and label their dice
+    /// counts
+    pub high_contrast: bool,
+    /// Large print for a TV across the room: bigger text, dice totals and borders
+    pub couch_mode: bool,
+}
+
+impl AccessibilitySettings {
+    /// Factor the font size of every text of the HUD is scaled by
+    pub fn text_scale(&self) -> f32 {
+        match self.couch_mode {
+            true => COUCH_TEXT_SCALE,
+            false => 1.0,
+        }
+    }
+
+    /// Factor the width of the borders between regions is scaled by
+    pub fn outline_scale(&self) -> f32 {
+        match self.couch_mode {
+            true => COUCH_OUTLINE_SCALE,
+            false => 1.0,
+        }
+    }
+
+    /// Factor the views of the dice thrown in battles are scaled by
+    pub fn dice_scale(&self) -> f32 {
+        match self.couch_mode {
+            true => COUCH_DICE_SCALE,
+            false => 1.0,
+        }
+    }
+
+    /// Outlines of the regions with these settings. On the high-contrast board every region
+    /// without highlight gets a thick black border, whatever outline its owner picked, and the
+    /// highlights are at least as wide so they stand out of it. Couch mode widens them all.
+    pub fn highlight_styles(&self) -> HighlightStyles {
+        let mut styles = HighlightStyles::default();
+        if self.high_contrast {
+            styles.none = HighlightStyle {
+                colour: Color::BLACK,
+                width: HIGH_CONTRAST_OUTLINE_WIDTH,
+            };
+            styles.owner_outlines = false;
+        }
+
+        let minimum = match self.high_contrast {
+            true => HIGH_CONTRAST_OUTLINE_WIDTH,
+            false => 0.0,
+        };
+        let scale = self.outline_scale();
+        for style in [
+            &mut styles.selected,
+            &mut styles.hovered,
+            &mut styles.bot_choice,
+            &mut styles.legal_target,
+            &mut styles.bot_candidate,
+            &mut styles.last_battle,
+            &mut styles.turn_start,
+            &mut styles.playable,
+            &mut styles.none,
+        ] {
+            style.width = style.width.max(minimum) * scale;
+        }
+        styles
+    }
+}
+
+/// Color of a region owned by a player of `color` on the high-contrast board: fully saturated,
+/// and darkened instead of washed out when the player in turn can't act on it
+pub fn high_contrast_color(color: Color, playable: bool) -> Color {
+    let (hue, saturation) = match color.as_hsla() {
+        Color::Hsla {
+            hue, saturation, ..
+        } => (hue, saturation),
+        _ => (0.0, 0.0),
+    };
+
+    Color::hsl(
+        hue,
+        // greys stay grey
+        if saturation > 0.1 { 1.0 } else { 0.0 },
+        if playable { 0.5 } else { 0.2 },
+    )
+}
+
+/// White count of the dice of a region, pinned over it
+#[derive(Component)]
+pub(crate) struct DiceCountLabel;
+
+fn spawn_dice_count_label(commands: &mut Commands, font: &Handle<Font>, region: &Region) {
+    let [x, _, z] = center(1.0, &region.center_hex(), &[0.0; 3]);
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                padding: UiRect::horizontal(Val::Px(4.0)),
+                ..default()
+            },
+            // a black plate keeps the digits readable over any region color
+            background_color: Color::BLACK.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                region.num_dice.to_string(),
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 28.0,
+                    color: Color::WHITE,
+                },
+            ));
+        })
+        .insert(Name::new("Dice Count Label"))
+        .insert(BoardLabel {
+            world_position: Vec3::new(x, DICE_COUNT_LABEL_HEIGHT, z),
+        })
+        .insert(DiceCountLabel)
+        .insert(StackRankDiceGameBoardElement);
+}
+
+/// Toggles the high-contrast board, and restyles every board drawn while it is on: the region
+/// tiles get unlit solid colors, so lighting doesn't vary them, and a label with their dice count.
+/// Their thick black outlines come from the [`HighlightStyles`] of the settings.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn high_contrast_board(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut settings: ResMut<AccessibilitySettings>,
+    mut applied: Local<bool>,
+    game_state: Res<GameState>,
+    asset_server: Res<AssetServer>,
+    profiles: Res<PlayerProfiles>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    hexes: Query<(&Region, &Handle<StandardMaterial>)>,
+    new_hexes: Query<(), Added<Region>>,
+    labels: Query<Entity, With<DiceCountLabel>>,
+    mut event_board_changed_writer: EventWriter<EventBoardChanged>,
+) {
+    if keys.just_pressed(HIGH_CONTRAST_KEY) && !control_pressed(&keys) {
+        settings.high_contrast = !settings.high_contrast;
+    }
+    // the settings screen toggles it too
+    let toggled = settings.high_contrast != *applied;
+    *applied = settings.high_contrast;
+
+    // redraw the board with its usual look
+    if toggled && !settings.high_contrast {
+        for entity in labels.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        event_board_changed_writer.send(EventBoardChanged);
+    }
+
+    if !settings.high_contrast || (!toggled && new_hexes.is_empty()) {
+        return;
+    }
+
+    for entity in labels.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    for (region, material) in hexes.iter() {
+        if let Some(material) = materials.get_mut(material) {
+            material.base_color = high_contrast_color(
+                profiles.color(region.owner),
+                is_region_playable(region, &game_state),
+            );
+            material.unlit = true;
+        }
+
+        spawn_dice_count_label(&mut commands, &font, region);
+    }
+}
+
+/// Outlines the regions as the settings ask whenever they change
+pub(crate) fn apply_highlight_styles(
+    settings: Res<AccessibilitySettings>,
+    mut styles: ResMut<HighlightStyles>,
+) {
+    if settings.is_changed() {
+        *styles = settings.highlight_styles();
+    }
+}
+
+/// Scales the text of every HUD element as it is spawned in couch mode, and the text already
+/// shown when couch mode is turned on or off
+pub(crate) fn scale_hud_text(
+    settings: Res<AccessibilitySettings>,
+    mut applied: Local<Option<f32>>,
+    mut text_query: Query<(&mut Text, ChangeTrackers<Text>)>,
+) {
+    let scale = settings.text_scale();
+    let rescale = scale / applied.unwrap_or(1.0);
+    *applied = Some(scale);
+
+    for (mut text, tracker) in text_query.iter_mut() {
+        let factor = match tracker.is_added() {
+            true => scale,
+            false => rescale,
+        };
+        if factor == 1.0 {
+            continue;
+        }
+        for section in text.sections.iter_mut() {
+            section.style.font_size *= factor;
+        }
+    }
+}
+
+/// Enlarges the views of the dice thrown in battles in couch mode
+pub(crate) fn scale_dice_views(
+    settings: Res<AccessibilitySettings>,
+    mut view_query: Query<&mut Transform, (With<DiceRollUI>, With<UiImage>)>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    for mut transform in view_query.iter_mut() {
+        transform.scale = Vec3::splat(settings.dice_scale());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles::OutlineStyle;
+
+    #[test]
+    fn high_contrast_colors_are_saturated() {
+        let color = high_contrast_color(Color::rgb(0.6, 0.3, 0.3), true);
+        let dimmed = high_contrast_color(Color::rgb(0.6, 0.3, 0.3), false);
+
+        assert_eq!(color.as_hsla_f32()[1], 1.0);
+        assert!(dimmed.as_hsla_f32()[2] < color.as_hsla_f32()[2]);
+        assert_eq!(high_contrast_color(Color::GRAY, true).as_hsla_f32()[1], 0.0);
+    }
+
+    #[test]
+    fn high_contrast_outlines_are_thick_and_black() {
+        let settings = AccessibilitySettings {
+            high_contrast: true,
+            ..default()
+        };
+        let styles = settings.highlight_styles();
+
+        let border = styles.owner_style(OutlineStyle::Glow, Color::RED);
+        assert_eq!(border.colour, Color::BLACK);
+        assert_eq!(border.width, HIGH_CONTRAST_OUTLINE_WIDTH);
+        assert!(styles.last_battle.width >= HIGH_CONTRAST_OUTLINE_WIDTH);
+        assert_eq!(
+            styles.hovered.colour,
+            HighlightStyles::default().hovered.colour
+        );
+
+        let usual = AccessibilitySettings::default().highlight_styles();
+        assert_eq!(
+            usual.owner_style(OutlineStyle::Glow, Color::RED).colour,
+            Color::RED
+        );
+    }
+}
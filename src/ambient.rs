@@ -0,0 +1,200 @@
+//! Ambient animation keeping the board alive between moves: the dice of the player in turn bob
+//! gently, the region lights slowly brighten and dim, and the playable region under the cursor
+//! shimmers.
+//!
+//! Everything is computed from the elapsed time, so the animation needs no state beyond the rest
+//! position of every die. Toggled with [`AMBIENT_KEY`], off from the start with
+//! `--reduced-motion`.
+
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::board::{is_region_playable, RegionDie, RegionLight, REGION_LIGHT_INTENSITY};
+use crate::game::{GameState, Region, RegionId};
+use crate::highlights::HighlightState;
+
+/// Key that toggles the ambient animation
+const AMBIENT_KEY: KeyCode = KeyCode::F2;
+
+/// Height the dice bob up and down by
+const BOB_HEIGHT: f32 = 0.04;
+
+/// Seconds of a bob of the dice
+const BOB_PERIOD: f32 = 2.4;
+
+/// Seconds of a cycle of the region lights
+const LIGHT_CYCLE_PERIOD: f32 = 30.0;
+
+/// Share of their intensity the region lights gain and lose over a cycle
+const LIGHT_CYCLE_DEPTH: f32 = 0.2;
+
+/// Seconds of a shimmer of the hovered region
+const SHIMMER_PERIOD: f32 = 1.2;
+
+/// Brightest glow of the hovered region
+const SHIMMER_STRENGTH: f32 = 0.25;
+
+#[derive(Resource, Clone, Debug)]
+pub struct AmbientAnimation {
+    pub enabled: bool,
+    /// Material of the tile shimmering, to put out once it stops
+    shimmering: Option<Handle<StandardMaterial>>,
+}
+
+impl Default for AmbientAnimation {
+    fn default() -> Self {
+        AmbientAnimation::new(true)
+    }
+}
+
+impl AmbientAnimation {
+    pub fn new(enabled: bool) -> Self {
+        AmbientAnimation {
+            enabled,
+            shimmering: None,
+        }
+    }
+}
+
+/// Height a die of `region` is lifted by at `time` seconds. Neighbouring regions bob out of step,
+/// the dice of a stack together.
+pub fn bob_offset(time: f32, region: RegionId) -> f32 {
+    let phase = region as f32 * 0.9;
+    BOB_HEIGHT * (TAU * time / BOB_PERIOD + phase).sin()
+}
+
+/// Factor the intensity of the region lights is scaled by at `time` seconds
+pub fn light_factor(time: f32) -> f32 {
+    1.0 + LIGHT_CYCLE_DEPTH * (TAU * time / LIGHT_CYCLE_PERIOD).sin()
+}
+
+/// Glow of the hovered region at `time` seconds, from none to [`SHIMMER_STRENGTH`]
+pub fn shimmer(time: f32) -> f32 {
+    SHIMMER_STRENGTH * 0.5 * (1.0 - (TAU * time / SHIMMER_PERIOD).cos())
+}
+
+/// Position a die rests at when it isn't bobbing
+#[derive(Component)]
+pub(crate) struct RestPosition(Vec3);
+
+pub(crate) fn toggle_ambient_animation(
+    keys: Res<Input<KeyCode>>,
+    mut ambient: ResMut<AmbientAnimation>,
+) {
+    if keys.just_pressed(AMBIENT_KEY) {
+        ambient.enabled = !ambient.enabled;
+    }
+}
+
+/// Dice spawned since the last frame, without the position they rest at yet
+type NewDice = (Added<RegionDie>, Without<RestPosition>);
+
+pub(crate) fn bob_dice(
+    mut commands: Commands,
+    time: Res<Time>,
+    ambient: Res<AmbientAnimation>,
+    game_state: Res<GameState>,
+    new_dice_query: Query<(Entity, &Transform), NewDice>,
+    mut dice_query: Query<(&RegionDie, &RestPosition, &mut Transform)>,
+) {
+    for (entity, transform) in new_dice_query.iter() {
+        commands
+            .entity(entity)
+            .insert(RestPosition(transform.translation));
+    }
+
+    let elapsed = time.elapsed_seconds();
+    for (die, rest, mut transform) in dice_query.iter_mut() {
+        let bobbing = ambient.enabled
+            && game_state
+                .board
+                .regions
+                .get(die.0)
+                .map_or(false, |region| region.owner == game_state.turn_of_player);
+        let translation = match bobbing {
+            true => rest.0 + Vec3::Y * bob_offset(elapsed, die.0),
+            false => rest.0,
+        };
+
+        if transform.translation != translation {
+            transform.translation = translation;
+        }
+    }
+}
+
+pub(crate) fn cycle_region_lights(
+    time: Res<Time>,
+    ambient: Res<AmbientAnimation>,
+    mut light_query: Query<&mut PointLight, With<RegionLight>>,
+) {
+    let intensity = match ambient.enabled {
+        true => REGION_LIGHT_INTENSITY * light_factor(time.elapsed_seconds()),
+        false => REGION_LIGHT_INTENSITY,
+    };
+
+    for mut light in light_query.iter_mut() {
+        if light.intensity != intensity {
+            light.intensity = intensity;
+        }
+    }
+}
+
+/// Makes the playable region under the cursor glow in and out, in whatever material its
+/// highlight gave it
+pub(crate) fn shimmer_hovered_region(
+    time: Res<Time>,
+    mut ambient: ResMut<AmbientAnimation>,
+    highlight_state: Res<HighlightState>,
+    game_state: Res<GameState>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    tile_query: Query<(&Region, &Handle<StandardMaterial>)>,
+) {
+    let hovered = highlight_state
+        .hovered
+        .filter(|_| ambient.enabled)
+        .and_then(|hovered| tile_query.iter().find(|(region, _)| region.id == hovered))
+        .filter(|(region, _)| is_region_playable(region, &game_state))
+        .map(|(_, material)| material.clone());
+
+    if ambient.shimmering != hovered {
+        if let Some(material) = ambient
+            .shimmering
+            .take()
+            .and_then(|handle| materials.get_mut(&handle))
+        {
+            material.emissive = Color::BLACK;
+        }
+        ambient.shimmering = hovered;
+    }
+
+    if let Some(material) = ambient
+        .shimmering
+        .as_ref()
+        .and_then(|handle| materials.get_mut(handle))
+    {
+        let glow = shimmer(time.elapsed_seconds());
+        material.emissive = Color::rgb(glow, glow, glow);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ambient_motion_stays_subtle() {
+        for step in 0..200 {
+            let time = step as f32 * 0.37;
+
+            assert!(bob_offset(time, 3).abs() <= BOB_HEIGHT);
+            assert!((0.8..=1.2).contains(&light_factor(time)));
+            assert!((0.0..=SHIMMER_STRENGTH).contains(&shimmer(time)));
+        }
+
+        // regions bob out of step, and shimmers start dark
+        assert_ne!(bob_offset(1.0, 0), bob_offset(1.0, 1));
+        assert_eq!(shimmer(0.0), 0.0);
+        assert_eq!(light_factor(0.0), 1.0);
+    }
+}
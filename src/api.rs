@@ -0,0 +1,1015 @@
+//! JSON API over WebSocket for external UIs, broadcast overlays and bots written in other
+//! languages, served by a running game.
+//!
+//! Built with the `api` feature and enabled with `--api-port`. Clients send JSON requests as text
+//! messages:
+//!
+//! ```text
+//! {"type": "subscribe"}        the state now and whenever it changes
+//! {"type": "unsubscribe"}
+//! {"type": "state"}            the state once
+//! {"type": "legal_moves"}      moves of the player in turn
+//! {"type": "move", "player": 1, "action": "attack", "from": 4, "to": 7}
+//! {"type": "move", "player": 1, "action": "reinforce", "region": 4}
+//! {"type": "move", "player": 1, "action": "end_turn"}
+//! {"type": "join", "version": "0.0.1", "protocol": 1}     claims a free seat, see crate::lobby
+//! {"type": "join", "version": "0.0.1", "protocol": 1, "rules": "3f2a9c0d1e4b5a67"}
+//! {"type": "spectate", "version": "0.0.1", "protocol": 1}  a snapshot, then every change
+//! ```
+//!
+//! Joining is the compatibility handshake of [`crate::handshake`]: the host refuses clients of
+//! another protocol, and clients sending the hash of the rules they expect when the host plays
+//! other rules. The answer carries the version, protocol and rules hash of the host.
+//!
+//! Spectators joining a game in progress get a [`ApiMessage::Snapshot`] of the whole board, then
+//! a compact [`ApiMessage::Delta`] of the regions and turn whenever the game changes, computed
+//! with [`GameState::diff`]. Every message of the stream is numbered and every delta carries the
+//! hash of the board it leads to: a spectator that misses one, or whose board hashes otherwise,
+//! sends `spectate` again to resync from a new snapshot, see [`crate::spectate`]. When the
+//! host rolls verifiable dice, spectators also get every roll with the key proving it, and the
+//! secret seed of the dice once the game is over, see [`crate::fair_dice`].
+//!
+//! Moves are only taken for seats handed to the API with `--api-seat`, and played through their
+//! [`RemoteConnection`] like any remote seat. A client has to join the game to move: the seat it
+//! claimed only takes the moves of that client until it disconnects. Sockets are served on
+//! background threads, a slow client never slows the game down.
+
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::delta::StateDelta;
+use crate::fair_dice::{key_hex, Commitment, FairDice, FairRoll};
+use crate::game::{GameState, RegionId, TurnPhase};
+use crate::handshake::{
+    deserialize_rules_hash, serialize_rules_hash, Handshake, Incompatible, PROTOCOL_VERSION,
+};
+use crate::relay::RelayRoom;
+use crate::rules::GameRules;
+use crate::seats::{SeatAction, SeatController, Seats};
+use crate::stamp::{rules_hash, APP_VERSION};
+
+/// Connection number of a client, in the order they connected
+pub type ClientId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ApiAction {
+    Attack { from: RegionId, to: RegionId },
+    Reinforce { region: RegionId },
+    EndTurn,
+}
+
+impl From<ApiAction> for SeatAction {
+    fn from(action: ApiAction) -> Self {
+        match action {
+            ApiAction::Attack { from, to } => SeatAction::Attack { from, to },
+            ApiAction::Reinforce { region } => SeatAction::Reinforce(region),
+            ApiAction::EndTurn => SeatAction::EndTurn,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ApiRequest {
+    Subscribe,
+    Unsubscribe,
+    State,
+    LegalMoves,
+    Move {
+        player: usize,
+        #[serde(flatten)]
+        action: ApiAction,
+    },
+    /// Claims a free seat for the client, which has to speak the same protocol as the host, and
+    /// play the same rules if it sends their hash. Clients from before the handshake send no
+    /// protocol and are refused.
+    Join {
+        version: String,
+        #[serde(default)]
+        protocol: u32,
+        #[serde(default, deserialize_with = "deserialize_rules_hash")]
+        rules: Option<u64>,
+    },
+    /// Streams the board to the client, which has to speak the same protocol as the host
+    Spectate {
+        version: String,
+        #[serde(default)]
+        protocol: u32,
+    },
+}
+
+impl ApiRequest {
+    /// Handshake of a client joining the game, that of this game for other requests
+    pub fn handshake(&self) -> Handshake {
+        match self {
+            ApiRequest::Join {
+                version,
+                protocol,
+                rules,
+            } => Handshake {
+                version: version.clone(),
+                protocol: *protocol,
+                rules_hash: *rules,
+            },
+            ApiRequest::Spectate { version, protocol } => Handshake {
+                version: version.clone(),
+                protocol: *protocol,
+                rules_hash: None,
+            },
+            _ => Handshake::local(None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegionView {
+    pub id: RegionId,
+    /// Stable key of the region, see [`crate::game::RegionKey`]
+    pub key: String,
+    pub name: String,
+    pub owner: usize,
+    pub dice: usize,
+    pub has_moved: bool,
+    pub hexes: Vec<(isize, isize)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateView {
+    pub turn: usize,
+    pub player: usize,
+    /// `attack` or `reinforcement`
+    pub phase: String,
+    pub winner: Option<usize>,
+    /// Dice each player has earned but not placed yet
+    pub reserves: Vec<usize>,
+    pub regions: Vec<RegionView>,
+}
+
+impl StateView {
+    pub fn new(game_state: &GameState) -> Self {
+        StateView {
+            turn: game_state.turn_counter,
+            player: game_state.turn_of_player,
+            phase: phase_name(game_state.turn_phase),
+            winner: game_state.winner(),
+            reserves: (0..game_state.number_of_players)
+                .map(|player| game_state.reserve(player))
+                .collect(),
+            regions: game_state
+                .board
+                .regions
+                .iter()
+                .map(|region| RegionView {
+                    id: region.id,
+                    key: region.key().to_string(),
+                    name: region.label(),
+                    owner: region.owner,
+                    dice: region.num_dice,
+                    has_moved: region.has_moved,
+                    hexes: region.hexes.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ApiMessage {
+    State(StateView),
+    LegalMoves {
+        player: usize,
+        moves: Vec<ApiAction>,
+    },
+    Accepted,
+    Error {
+        message: String,
+    },
+    /// The client joined the game and plays `player`. Carries the version, protocol and rules
+    /// hash of the host.
+    Joined {
+        player: usize,
+        version: String,
+        protocol: u32,
+        #[serde(serialize_with = "serialize_rules_hash")]
+        rules: u64,
+    },
+    /// The client can't join, `reason` is [`INCOMPATIBLE_VERSION`], [`INCOMPATIBLE_RULES`] or
+    /// [`LOBBY_FULL`]. Carries the version, protocol and rules hash of the host.
+    JoinRefused {
+        reason: String,
+        version: String,
+        protocol: u32,
+        #[serde(serialize_with = "serialize_rules_hash")]
+        rules: u64,
+    },
+    /// The whole board, opening the board stream of a spectator
+    Snapshot {
+        seq: u64,
+        state: StateView,
+    },
+    Delta(DeltaView),
+    /// The rolls to come are drawn from the chain of [`crate::fair_dice`] leading to `head`, the
+    /// key of the last roll or the commitment, after `rolled` rolls
+    DiceChain {
+        head: String,
+        rolled: u64,
+    },
+    /// Dice of a battle rolled by the host
+    Roll(FairRoll),
+    /// Secret seed of the chain of a game that is over
+    Reveal {
+        seed: String,
+    },
+}
+
+/// Region that changed since the previous message of a board stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegionChange {
+    pub id: RegionId,
+    pub owner: usize,
+    pub dice: usize,
+    pub has_moved: bool,
+}
+
+/// What changed on the board since the previous message of a board stream. Fields left out
+/// didn't change.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeltaView {
+    /// Number of the message in the stream, one more than the previous one
+    pub seq: u64,
+    pub regions: Vec<RegionChange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turn: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserves: Option<Vec<usize>>,
+    /// Zobrist hash of the board once changed, in hex, for spectators to check they didn't
+    /// diverge from the host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl DeltaView {
+    /// Delta number `seq` of the stream, leading to the state hashed as `hash`
+    pub fn new(seq: u64, delta: &StateDelta, hash: u64) -> Self {
+        DeltaView {
+            seq,
+            regions: delta
+                .regions
+                .iter()
+                .map(|region| RegionChange {
+                    id: region.id,
+                    owner: region.after.owner,
+                    dice: region.after.num_dice,
+                    has_moved: region.after.has_moved,
+                })
+                .collect(),
+            turn: delta.turn_counter.map(|(_, turn)| turn),
+            player: delta.turn_of_player.map(|(_, player)| player),
+            phase: delta.turn_phase.map(|(_, phase)| phase_name(phase)),
+            reserves: delta
+                .reserves
+                .as_ref()
+                .map(|(_, reserves)| reserves.clone()),
+            hash: Some(format!("{:016x}", hash)),
+        }
+    }
+}
+
+pub fn phase_name(phase: TurnPhase) -> String {
+    match phase {
+        TurnPhase::Attack => "attack",
+        TurnPhase::Reinforcement => "reinforcement",
+    }
+    .to_string()
+}
+
+/// Reason to refuse a client speaking another protocol than the host
+pub const INCOMPATIBLE_VERSION: &str = "incompatible_version";
+
+/// Reason to refuse a client expecting other rules than the ones of the host
+pub const INCOMPATIBLE_RULES: &str = "incompatible_rules";
+
+/// Reason to refuse a client when every seat handed to the API is taken
+pub const LOBBY_FULL: &str = "lobby_full";
+
+#[allow(dead_code)]
+pub fn parse_request(text: &str) -> Result<ApiRequest, String> {
+    serde_json::from_str(text).map_err(|err| format!("invalid request: {}", err))
+}
+
+/// Moves the player in turn can make
+pub fn legal_moves(game_state: &GameState, rules: &GameRules) -> Vec<ApiAction> {
+    if game_state.winner().is_some() {
+        return Vec::new();
+    }
+
+    match game_state.turn_phase {
+        TurnPhase::Attack => game_state
+            .attacks(rules)
+            .filter(|(region_1, _)| !region_1.has_moved)
+            .map(|(region_1, region_2)| ApiAction::Attack {
+                from: region_1.id,
+                to: region_2.id,
+            })
+            .chain([ApiAction::EndTurn])
+            .collect(),
+        TurnPhase::Reinforcement => game_state
+            .board
+            .regions
+            .iter()
+            .filter(|region| game_state.can_place_reinforcement(region, rules))
+            .map(|region| ApiAction::Reinforce { region: region.id })
+            .collect(),
+    }
+}
+
+/// Answer to a client with the handshake `client` joining the game of `rules`, when the seats
+/// `taken` are already claimed by other clients
+pub fn join_lobby(
+    client: &Handshake,
+    rules: &GameRules,
+    seats: &Seats,
+    taken: &[usize],
+) -> ApiMessage {
+    let host = Handshake::local(Some(rules));
+    let refuse = |reason: &str| ApiMessage::JoinRefused {
+        reason: reason.to_string(),
+        version: APP_VERSION.to_string(),
+        protocol: host.protocol,
+        rules: rules_hash(rules),
+    };
+    match host.check(client) {
+        Err(Incompatible::Version { .. }) => return refuse(INCOMPATIBLE_VERSION),
+        Err(Incompatible::Rules { .. }) => return refuse(INCOMPATIBLE_RULES),
+        Ok(()) => {}
+    }
+
+    let free_seat = seats
+        .controllers
+        .iter()
+        .enumerate()
+        .find(|(player, controller)| {
+            matches!(controller, SeatController::Remote(_)) && !taken.contains(player)
+        })
+        .map(|(player, _)| player);
+    match free_seat {
+        Some(player) => ApiMessage::Joined {
+            player,
+            version: APP_VERSION.to_string(),
+            protocol: host.protocol,
+            rules: rules_hash(rules),
+        },
+        None => refuse(LOBBY_FULL),
+    }
+}
+
+/// Answer to a spectator with the handshake `client` joining the game of `rules`, the snapshot
+/// numbered `seq` opening its board stream
+pub fn spectate(
+    client: &Handshake,
+    game_state: &GameState,
+    rules: &GameRules,
+    seq: u64,
+) -> ApiMessage {
+    match Handshake::local(None).check(client) {
+        Ok(()) => ApiMessage::Snapshot {
+            seq,
+            state: StateView::new(game_state),
+        },
+        Err(_) => ApiMessage::JoinRefused {
+            reason: INCOMPATIBLE_VERSION.to_string(),
+            version: APP_VERSION.to_string(),
+            protocol: PROTOCOL_VERSION,
+            rules: rules_hash(rules),
+        },
+    }
+}
+
+/// Where the chain of the dice is, told spectators after their snapshot
+pub fn dice_chain(fair_dice: &FairDice) -> ApiMessage {
+    let (head, rolled) = fair_dice.head();
+    ApiMessage::DiceChain {
+        head: key_hex(&head),
+        rolled,
+    }
+}
+
+/// Whether `client` claimed the seat of `player` by joining the game, which it has to before
+/// moving for it
+pub fn claimed_by(claimed: &HashMap<ClientId, usize>, client: ClientId, player: usize) -> bool {
+    claimed.get(&client) == Some(&player)
+}
+
+/// Answer to a request, subscriptions and seats claimed by clients aside
+pub fn respond(
+    request: &ApiRequest,
+    game_state: &GameState,
+    rules: &GameRules,
+    seats: &Seats,
+) -> ApiMessage {
+    let error = |message: String| ApiMessage::Error { message };
+    match request {
+        ApiRequest::Subscribe | ApiRequest::State => ApiMessage::State(StateView::new(game_state)),
+        ApiRequest::Unsubscribe => ApiMessage::Accepted,
+        ApiRequest::LegalMoves => ApiMessage::LegalMoves {
+            player: game_state.turn_of_player,
+            moves: legal_moves(game_state, rules),
+        },
+        ApiRequest::Move { player, action } => {
+            let connection = match seats.controllers.get(*player) {
+                Some(SeatController::Remote(connection)) => connection,
+                _ => return error(format!("player {} isn't played through the API", player)),
+            };
+            if *player != game_state.turn_of_player {
+                return error(format!("it isn't the turn of player {}", player));
+            }
+            if !legal_moves(game_state, rules).contains(action) {
+                return error(format!("{:?} isn't a legal move", action));
+            }
+
+            connection.push((*action).into());
+            ApiMessage::Accepted
+        }
+        ApiRequest::Join { .. } => join_lobby(&request.handshake(), rules, seats, &[]),
+        ApiRequest::Spectate { .. } => spectate(&request.handshake(), game_state, rules, 0),
+    }
+}
+
+/// Connection to the background threads serving the API
+#[derive(Resource)]
+pub struct ApiServer {
+    requests: Mutex<Receiver<(ClientId, Result<ApiRequest, String>)>>,
+    /// Outgoing messages of every connected client
+    clients: Arc<Mutex<HashMap<ClientId, Sender<String>>>>,
+    subscribers: HashSet<ClientId>,
+    /// Seat claimed by every client that joined the game
+    claimed: HashMap<ClientId, usize>,
+    spectators: HashSet<ClientId>,
+    /// Number of the last message of the board stream
+    stream_seq: u64,
+    /// State the last message of the board stream brought spectators to, none without spectators
+    streamed: Option<GameState>,
+    /// Chain of the dice spectators were told about
+    dice_chain: Option<Commitment>,
+}
+
+impl ApiServer {
+    /// Starts serving the API on `port` of the local machine, or of every network interface of
+    /// the machine if `public`, and to the players joining `relay` if any
+    pub fn start(port: u16, public: bool, relay: Option<RelayRoom>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let clients = Arc::new(Mutex::new(HashMap::new()));
+        let ip = match public {
+            true => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            false => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        };
+
+        #[cfg(feature = "api")]
+        {
+            if let Some(room) = relay {
+                let (sender, clients) = (sender.clone(), clients.clone());
+                std::thread::spawn(move || websocket::relay(room, sender, clients));
+            }
+            let clients = clients.clone();
+            std::thread::spawn(move || websocket::listen(ip, port, sender, clients));
+        }
+
+        #[cfg(not(feature = "api"))]
+        {
+            warn!(
+                "API port {} on {} ignored, the API needs the `api` feature",
+                port, ip
+            );
+            drop((sender, relay));
+        }
+
+        ApiServer {
+            requests: Mutex::new(receiver),
+            clients,
+            subscribers: HashSet::new(),
+            claimed: HashMap::new(),
+            spectators: HashSet::new(),
+            stream_seq: 0,
+            streamed: None,
+            dice_chain: None,
+        }
+    }
+
+    /// Number of clients that joined the game and still play their seat
+    pub fn joined_players(&self) -> usize {
+        self.claimed.len()
+    }
+
+    /// Sends spectators what changed since the last message of the board stream, or a new
+    /// snapshot once the game moved to another board
+    fn stream(&mut self, game_state: &GameState) {
+        let message = match &self.streamed {
+            Some(streamed) if streamed.board.region_keys() == game_state.board.region_keys() => {
+                let delta = streamed.diff(game_state);
+                if delta.is_empty() {
+                    return;
+                }
+                self.stream_seq += 1;
+                ApiMessage::Delta(DeltaView::new(
+                    self.stream_seq,
+                    &delta,
+                    game_state.zobrist_hash(),
+                ))
+            }
+            _ => {
+                self.stream_seq += 1;
+                ApiMessage::Snapshot {
+                    seq: self.stream_seq,
+                    state: StateView::new(game_state),
+                }
+            }
+        };
+
+        self.streamed = Some(game_state.clone());
+        for client in self.spectators.iter() {
+            self.send(*client, &message);
+        }
+    }
+
+    /// Sends spectators the rolls of the dice since the last frame, and the seeds to reveal. A new
+    /// chain is announced once the seed of the last one was revealed.
+    fn stream_dice(&mut self, fair_dice: &mut FairDice, game_over: bool) {
+        let mut messages: Vec<ApiMessage> = fair_dice
+            .take_rolls()
+            .into_iter()
+            .map(ApiMessage::Roll)
+            .collect();
+        if let Some(seed) = fair_dice.take_reveal(game_over) {
+            messages.push(ApiMessage::Reveal {
+                seed: key_hex(&seed),
+            });
+        }
+        if self.dice_chain != Some(fair_dice.commitment()) {
+            self.dice_chain = Some(fair_dice.commitment());
+            messages.push(dice_chain(fair_dice));
+        }
+
+        for message in messages.iter() {
+            for client in self.spectators.iter() {
+                self.send(*client, message);
+            }
+        }
+    }
+
+    fn send(&self, client: ClientId, message: &ApiMessage) {
+        let text = serde_json::to_string(message).unwrap_or_default();
+        if let Some(outbox) = self.clients.lock().unwrap().get(&client) {
+            // a closed outbox means the client left, its thread cleans up after it
+            let _ = outbox.send(text);
+        }
+    }
+}
+
+#[cfg(feature = "api")]
+mod websocket {
+    use std::collections::HashMap;
+    use std::io::ErrorKind;
+    use std::net::{IpAddr, TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use bevy::prelude::*;
+    use tungstenite::{Error, Message};
+
+    use super::{parse_request, ApiRequest, ClientId};
+    use crate::relay::{self, RelayRoom};
+
+    /// How long a client thread waits for a request before sending what is waiting in its outbox
+    const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+    /// Id of the next client, shared by direct and relayed clients
+    static NEXT_CLIENT: AtomicUsize = AtomicUsize::new(0);
+
+    /// Accepts clients until the game closes, every client on its own thread
+    pub(super) fn listen(
+        ip: IpAddr,
+        port: u16,
+        requests: Sender<(ClientId, Result<ApiRequest, String>)>,
+        clients: Arc<Mutex<HashMap<ClientId, Sender<String>>>>,
+    ) {
+        let listener = match TcpListener::bind((ip, port)) {
+            Ok(listener) => listener,
+            Err(err) => {
+                warn!("Failed to serve the API on port {}: {}", port, err);
+                return;
+            }
+        };
+        info!("Serving the API on ws://{}:{}", ip, port);
+
+        for stream in listener.incoming().flatten() {
+            accept(stream, &requests, &clients);
+        }
+    }
+
+    /// Accepts the clients joining `room` on its relay until the relay closes the room
+    pub(super) fn relay(
+        room: RelayRoom,
+        requests: Sender<(ClientId, Result<ApiRequest, String>)>,
+        clients: Arc<Mutex<HashMap<ClientId, Sender<String>>>>,
+    ) {
+        if let Err(err) = relay::host(&room, |stream| accept(stream, &requests, &clients)) {
+            warn!(
+                "Failed to host room {} on {}: {}",
+                room.room, room.relay, err
+            );
+        }
+    }
+
+    /// Serves a new client on its own thread
+    fn accept(
+        stream: TcpStream,
+        requests: &Sender<(ClientId, Result<ApiRequest, String>)>,
+        clients: &Arc<Mutex<HashMap<ClientId, Sender<String>>>>,
+    ) {
+        let client = NEXT_CLIENT.fetch_add(1, Ordering::Relaxed);
+        let (outbox, outgoing) = mpsc::channel();
+        clients.lock().unwrap().insert(client, outbox);
+        let (requests, clients) = (requests.clone(), clients.clone());
+        std::thread::spawn(move || {
+            serve(client, stream, requests, outgoing);
+            clients.lock().unwrap().remove(&client);
+        });
+    }
+
+    fn serve(
+        client: ClientId,
+        stream: TcpStream,
+        requests: Sender<(ClientId, Result<ApiRequest, String>)>,
+        outgoing: Receiver<String>,
+    ) {
+        let mut socket = match tungstenite::accept(stream) {
+            Ok(socket) => socket,
+            Err(err) => {
+                warn!("Failed to accept API client {}: {}", client, err);
+                return;
+            }
+        };
+        if socket
+            .get_mut()
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .is_err()
+        {
+            return;
+        }
+
+        loop {
+            for text in outgoing.try_iter() {
+                if socket.send(Message::Text(text)).is_err() {
+                    return;
+                }
+            }
+
+            match socket.read() {
+                Ok(Message::Text(text)) => {
+                    if requests.send((client, parse_request(&text))).is_err() {
+                        return;
+                    }
+                }
+                Ok(Message::Close(_)) => return,
+                Ok(_) => {}
+                Err(Error::Io(err))
+                    if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(_) => return,
+            }
+        }
+    }
+}
+
+pub(crate) fn serve_api(
+    api: Option<ResMut<ApiServer>>,
+    game_state: Res<GameState>,
+    rules: Res<GameRules>,
+    seats: Res<Seats>,
+    mut fair_dice: Option<ResMut<FairDice>>,
+) {
+    let mut api = match api {
+        Some(api) => api,
+        None => return,
+    };
+
+    let connected: Vec<ClientId> = api.clients.lock().unwrap().keys().copied().collect();
+    // seats of the clients that left are free to join again
+    api.claimed.retain(|client, _| connected.contains(client));
+
+    let requests: Vec<(ClientId, Result<ApiRequest, String>)> =
+        api.requests.lock().unwrap().try_iter().collect();
+    for (client, request) in requests {
+        let unclaimed = match &request {
+            Ok(ApiRequest::Move { player, .. }) => !claimed_by(&api.claimed, client, *player),
+            _ => false,
+        };
+
+        let message = match request {
+            Ok(request @ ApiRequest::Join { .. }) => {
+                let taken: Vec<usize> = api.claimed.values().copied().collect();
+                let message = join_lobby(&request.handshake(), &rules, &seats, &taken);
+                if let ApiMessage::Joined { player, .. } = message {
+                    api.claimed.insert(client, player);
+                }
+                message
+            }
+            Ok(request @ ApiRequest::Spectate { .. }) => {
+                // a spectator joining right after a change gets it twice, in the snapshot and
+                // in the next delta, which only sets the regions to what they already are
+                let message = spectate(&request.handshake(), &game_state, &rules, api.stream_seq);
+                if let ApiMessage::Snapshot { .. } = message {
+                    api.spectators.insert(client);
+                    if api.streamed.is_none() {
+                        api.streamed = Some(game_state.clone());
+                    }
+                }
+                message
+            }
+            Ok(ApiRequest::Move { player, .. }) if unclaimed => ApiMessage::Error {
+                message: format!(
+                    "player {} isn't played by this client, join the game",
+                    player
+                ),
+            },
+            Ok(request) => {
+                match request {
+                    ApiRequest::Subscribe => {
+                        api.subscribers.insert(client);
+                    }
+                    ApiRequest::Unsubscribe => {
+                        api.subscribers.remove(&client);
+                    }
+                    _ => {}
+                }
+                respond(&request, &game_state, &rules, &seats)
+            }
+            Err(message) => ApiMessage::Error { message },
+        };
+        api.send(client, &message);
+        // rolls not sent yet follow the chain, which doesn't count them
+        if let (ApiMessage::Snapshot { .. }, Some(fair_dice)) = (&message, fair_dice.as_deref()) {
+            api.send(client, &dice_chain(fair_dice));
+        }
+    }
+
+    if game_state.is_changed() && !api.subscribers.is_empty() {
+        api.subscribers.retain(|client| connected.contains(client));
+
+        let state = ApiMessage::State(StateView::new(&game_state));
+        for client in api.subscribers.iter() {
+            api.send(*client, &state);
+        }
+    }
+
+    api.spectators.retain(|client| connected.contains(client));
+    if api.spectators.is_empty() {
+        api.streamed = None;
+    } else if game_state.is_changed() {
+        api.stream(&game_state);
+    }
+    if let Some(fair_dice) = fair_dice.as_deref_mut() {
+        api.stream_dice(fair_dice, game_state.winner().is_some());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seats::RemoteConnection;
+    use crate::test_support;
+
+    fn game_state() -> GameState {
+        test_support::game_state("players 2\nregion 0 3 0,0\nregion 1 2 1,0\nregion 1 2 5,5\n")
+    }
+
+    #[test]
+    fn parses_requests() {
+        assert_eq!(
+            parse_request(r#"{"type": "subscribe"}"#),
+            Ok(ApiRequest::Subscribe)
+        );
+        assert_eq!(
+            parse_request(
+                r#"{"type": "move", "player": 1, "action": "attack", "from": 4, "to": 7}"#
+            ),
+            Ok(ApiRequest::Move {
+                player: 1,
+                action: ApiAction::Attack { from: 4, to: 7 },
+            })
+        );
+        assert!(parse_request(r#"{"type": "teleport"}"#).is_err());
+    }
+
+    #[test]
+    fn lists_legal_moves() {
+        let game_state = game_state();
+        let message = respond(
+            &ApiRequest::LegalMoves,
+            &game_state,
+            &GameRules::default(),
+            &Seats::default(),
+        );
+
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"type":"legal_moves","player":0,"moves":[{"action":"attack","from":0,"to":1},{"action":"end_turn"}]}"#
+        );
+    }
+
+    #[test]
+    fn moves_are_only_taken_for_api_seats() {
+        let game_state = game_state();
+        let rules = GameRules::default();
+        let attack = |player| ApiRequest::Move {
+            player,
+            action: ApiAction::Attack { from: 0, to: 1 },
+        };
+
+        let local = Seats::default();
+        assert!(matches!(
+            respond(&attack(0), &game_state, &rules, &local),
+            ApiMessage::Error { .. }
+        ));
+
+        let api = Seats {
+            controllers: vec![
+                SeatController::Remote(RemoteConnection::default()),
+                SeatController::Remote(RemoteConnection::default()),
+            ],
+        };
+        assert_eq!(
+            respond(&attack(0), &game_state, &rules, &api),
+            ApiMessage::Accepted
+        );
+        // not in turn
+        assert!(matches!(
+            respond(&attack(1), &game_state, &rules, &api),
+            ApiMessage::Error { .. }
+        ));
+        // illegal
+        let backwards = ApiRequest::Move {
+            player: 0,
+            action: ApiAction::Attack { from: 1, to: 0 },
+        };
+        assert!(matches!(
+            respond(&backwards, &game_state, &rules, &api),
+            ApiMessage::Error { .. }
+        ));
+    }
+
+    #[test]
+    fn clients_join_free_api_seats() {
+        let seats = Seats {
+            controllers: vec![
+                SeatController::LocalHuman,
+                SeatController::Remote(RemoteConnection::default()),
+                SeatController::Remote(RemoteConnection::default()),
+            ],
+        };
+
+        let rules = GameRules::default();
+        let client = Handshake::local(None);
+
+        assert_eq!(
+            join_lobby(&client, &rules, &seats, &[1]),
+            ApiMessage::Joined {
+                player: 2,
+                version: APP_VERSION.to_string(),
+                protocol: PROTOCOL_VERSION,
+                rules: rules_hash(&rules),
+            }
+        );
+        assert_eq!(
+            join_lobby(&client, &rules, &seats, &[1, 2]),
+            ApiMessage::JoinRefused {
+                reason: LOBBY_FULL.to_string(),
+                version: APP_VERSION.to_string(),
+                protocol: PROTOCOL_VERSION,
+                rules: rules_hash(&rules),
+            }
+        );
+    }
+
+    #[test]
+    fn only_joined_clients_move() {
+        let claimed = HashMap::from([(3, 1)]);
+
+        assert!(claimed_by(&claimed, 3, 1));
+        // another client, or a seat the client didn't claim
+        assert!(!claimed_by(&claimed, 4, 1));
+        assert!(!claimed_by(&claimed, 3, 2));
+    }
+
+    #[test]
+    fn joins_check_the_protocol_and_rules() {
+        let seats = Seats {
+            controllers: vec![
+                SeatController::LocalHuman,
+                SeatController::Remote(RemoteConnection::default()),
+            ],
+        };
+        let rules = GameRules::default();
+        let join = |text: &str| {
+            join_lobby(
+                &parse_request(text).unwrap().handshake(),
+                &rules,
+                &seats,
+                &[],
+            )
+        };
+
+        // clients from before the handshake send no protocol
+        assert!(matches!(
+            join(r#"{"type": "join", "version": "0.0.0-old"}"#),
+            ApiMessage::JoinRefused { reason, .. } if reason == INCOMPATIBLE_VERSION
+        ));
+        assert!(matches!(
+            join(r#"{"type": "join", "version": "0.0.0-old", "protocol": 1, "rules": "00000000000000ff"}"#),
+            ApiMessage::JoinRefused { reason, .. } if reason == INCOMPATIBLE_RULES
+        ));
+        let expected_rules = format!(
+            r#"{{"type": "join", "version": "0.0.0-old", "protocol": 1, "rules": "{:016x}"}}"#,
+            rules_hash(&rules)
+        );
+        assert!(matches!(
+            join(&expected_rules),
+            ApiMessage::Joined { player: 1, .. }
+        ));
+        assert!(parse_request(r#"{"type": "join", "version": "1.2.3", "rules": "nope"}"#).is_err());
+
+        let joined = serde_json::to_string(&join(&expected_rules)).unwrap();
+        assert_eq!(
+            joined,
+            format!(
+                r#"{{"type":"joined","player":1,"version":"{}","protocol":{},"rules":"{:016x}"}}"#,
+                APP_VERSION,
+                PROTOCOL_VERSION,
+                rules_hash(&rules)
+            )
+        );
+    }
+
+    #[test]
+    fn spectators_get_a_snapshot() {
+        let game_state = game_state();
+        let rules = GameRules::default();
+        let request =
+            parse_request(r#"{"type": "spectate", "version": "0.0.0-old", "protocol": 1}"#)
+                .unwrap();
+
+        assert_eq!(
+            spectate(&request.handshake(), &game_state, &rules, 3),
+            ApiMessage::Snapshot {
+                seq: 3,
+                state: StateView::new(&game_state),
+            }
+        );
+        let old = parse_request(r#"{"type": "spectate", "version": "0.0.0-old"}"#).unwrap();
+        assert!(matches!(
+            spectate(&old.handshake(), &game_state, &rules, 3),
+            ApiMessage::JoinRefused { reason, .. } if reason == INCOMPATIBLE_VERSION
+        ));
+
+        let mut after = game_state.clone();
+        after.board.regions[1].num_dice = 5;
+        let delta = serde_json::to_string(&ApiMessage::Delta(DeltaView::new(
+            4,
+            &game_state.diff(&after),
+            after.zobrist_hash(),
+        )))
+        .unwrap();
+        assert_eq!(
+            delta,
+            format!(
+                r#"{{"type":"delta","seq":4,"regions":[{{"id":1,"owner":1,"dice":5,"has_moved":false}}],"hash":"{:016x}"}}"#,
+                after.zobrist_hash()
+            )
+        );
+    }
+
+    #[test]
+    fn states_serialize_with_their_type() {
+        let text = serde_json::to_string(&respond(
+            &ApiRequest::State,
+            &game_state(),
+            &GameRules::default(),
+            &Seats::default(),
+        ))
+        .unwrap();
+
+        assert!(text.starts_with(r#"{"type":"state","turn":0,"player":0,"phase":"attack""#));
+    }
+}
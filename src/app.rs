@@ -1,14 +1,144 @@
 use bevy::prelude::*;
-use bevy_dice::{DicePlugin, DicePluginSettings};
+use bevy_dice::{DicePlugin, DicePluginSettings, DiceRollResult, DiceRollStartEvent};
 use bevy_mod_outline::*;
 use bevy_rapier3d::prelude::{NoUserData, RapierPhysicsPlugin};
 
+#[cfg(feature = "inspector")]
+use bevy_inspector_egui::WorldInspectorPlugin;
+
+use crate::accessibility::{
+    apply_highlight_styles, high_contrast_board, scale_dice_views, scale_hud_text,
+    AccessibilitySettings,
+};
+use crate::ambient::{
+    bob_dice, cycle_region_lights, shimmer_hovered_region, toggle_ambient_animation,
+    AmbientAnimation,
+};
+use crate::api::serve_api;
+use crate::autosave::{
+    autosave_game_over, autosave_moves, check_autosave, resume_prompt_input, Autosave,
+};
+use crate::battle_log::{
+    battle_log_clicked, battle_log_update, battle_report_text_update, setup_battle_log_ui,
+    BattleReport,
+};
+use crate::blitz::{
+    blitz_orders_text_update, blitz_rounds, classic_turns, collect_blitz_orders, setup_blitz_ui,
+    BlitzRound,
+};
 use crate::board::draw_board;
-use crate::game::{generate_board, GameState, SelectedRegion};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::bug_report::init_logging;
+use crate::bug_report::{report_issue, save_bug_report};
+use crate::camera::{camera_rig_battles, camera_rig_update};
+use crate::chaos::{global_events, GlobalEventLog};
+use crate::chapters::{record_timeline, TimelineExport};
+use crate::clock::{
+    clock_text_update, run_player_clocks, setup_clock_ui, stop_player_clocks, PlayerClocks,
+};
+use crate::commentary::{commentary_text_update, setup_commentary_ui, Commentary};
+use crate::dice_audit::{audit_dice, DiceAuditSettings};
+use crate::diplomacy::{
+    diplomacy_input, diplomacy_text_update, expire_truce_proposals, setup_diplomacy_ui, Diplomacy,
+    EventTruceAgreed,
+};
+use crate::display::{apply_display_settings, display_settings_input, DisplaySettings};
+use crate::game::{
+    generate_board, Board, GameLogEntry, GameState, Pact, Region, SelectedRegion, TurnPhase,
+};
+use crate::gpu_picking::{GpuPickingPlugin, PickingMode};
+use crate::hex_debug::{hex_debug_overlay, HexDebugOverlay};
+use crate::hot_reload::{refresh_theme_materials, reload_changed_map};
+use crate::idle::{
+    auto_pass_idle_player, idle_text_update, setup_idle_ui, track_idle, IdleSettings, IdleTracker,
+};
+use crate::input::{camera_controls, emit_input_actions, EventInputAction, InputMap};
+use crate::lobby::{
+    finish_join, join_screen_input, lobby_text_update, setup_join_screen, setup_lobby_ui, JoinForm,
+};
+use crate::logic_step::{add_game_logic_stage, GameLogicStage};
+use crate::memory_budget::{roll_numeric_dice, MemoryBudget};
+use crate::menu::{
+    despawn_menu_screen, enter_game_over, game_over_input, main_menu_input, map_thumbnail_update,
+    pause_in_main_menu, settings_input, setup_game_setup, setup_input, setup_main_menu,
+    setup_settings_screen, start_game, AppState, GameSetup, OpenMainMenu, SettingsMenu,
+};
+use crate::names::{announce_conquests, region_tooltip_update, setup_region_tooltip};
+use crate::new_game::{hide_dropped_dice_rolls, EventNewGame};
+use crate::objectives::{
+    objective_text_update, objective_turn_start, setup_objective_ui, ObjectiveTracker,
+};
+use crate::odds::{odds_text_update, setup_odds_ui};
+use crate::ownership_heatmap::{ownership_heatmap, OwnershipHeatmap};
+use crate::pause::{
+    freeze_physics, game_running, pause_input, pause_on_focus_loss, pause_overlay_update,
+    setup_pause_ui, Pause,
+};
+use crate::persistence::{load_game, persistence_input, save_game, LoadGameEvent, SaveGameEvent};
+use crate::pings::{ping_marker_update, ping_regions, spawn_ping_markers, EventRegionPinged};
+use crate::player_stats::{player_stats_text_update, setup_player_stats_ui};
+use crate::presence::{update_rich_presence, RichPresence};
+use crate::profiles::PlayerProfiles;
+use crate::puzzle::{judge_puzzle, puzzle_text_update, retry_puzzle, setup_puzzle_ui};
+use crate::recorder::{record_battles, BattleRecorder};
+use crate::rematch::{rematch_clicked, remember_first_board, Rematch};
+use crate::rules::{GameRules, ReinforcementRule, TieRule};
+use crate::scenario::{scenario_triggers, ScenarioTriggers};
+use crate::scene::{board_from_scene, export_board_scene, spawn_board_scene};
+use crate::screenshot::ScreenshotPlugin;
+use crate::seats::{drive_seats, BotIntent, BotPacing, Seats, TurnDriver};
+use crate::session::{match_score_text_update, setup_match_ui, start_next_game, MatchSession};
+use crate::snapshots::{record_snapshots, SnapshotRing};
+use crate::sounds::{
+    load_sound_manifest, play_sounds, update_sound_manifest, EventSound, SoundManifest,
+    SoundManifestLoader, SoundRouter,
+};
+use crate::spectate::{follow_spectated_game, send_joined_moves};
+use crate::speedrun::{
+    setup_speedrun_ui, speedrun_game_over, speedrun_text_update, speedrun_timer,
+};
+use crate::stalemate::{detect_stalemate, StalemateTracker};
+use crate::telemetry::{telemetry_game_over, telemetry_sent, Telemetry};
+use crate::threats::{threat_overlay, ThreatOverlay};
 use crate::tiered_prng::get_randomness;
-use crate::ui::{dice_roll_result_text_update, player_turn_text_update, setup_ui};
+use crate::timeline::{
+    announce_turning_points, setup_timeline_ui, timeline_game_over, timeline_text_update,
+    track_standings, EventAreaBroken, EventLeadChanged, EventPlayerEliminated, Timeline,
+};
+use crate::turn_banner::{announce_turn_start, turn_banner_update, TurnStartCue};
+use crate::ui::{
+    board_labels_follow_camera, bot_thinking_label, dice_roll_result_text_update,
+    player_turn_text_update, setup_ui, toast_update,
+};
 use crate::{events::*, highlights, tiered_prng};
 
+/// Label of the startup system of `bevy_dice` setting up the dice views, which names it with a
+/// string
+const DICE_PLUGIN_INIT: &str = "dice_plugin_init";
+
+/// Startup systems the others are ordered after
+#[derive(SystemLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StartupLabel {
+    /// The camera and the HUD, spawned by `setup_ui`
+    Setup,
+}
+
+/// Steps of a battle in the update stage, in the order they run within a frame, so a battle
+/// started, rolled or finished in a frame moves on in the same frame. Seats picking the regions
+/// to fight and the rolls being shown long enough are timed in the [`GameLogicStage`], the battle
+/// moves on from them the next frame.
+#[derive(SystemLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BattleLabel {
+    /// The dice of both regions start rolling
+    Clash,
+    /// The dice settled, their faces are logged
+    RollResult,
+    /// The outcome is applied to the board and the turn moves on
+    Resolution,
+}
+
+/// Adds the game to `app`, with a window, sound and picking unless `testing`. The window opens
+/// with the [`DisplaySettings`] inserted before, if any.
 pub fn build_app(
     app: &mut App,
     world_seed: u64,
@@ -16,62 +146,532 @@ pub fn build_app(
     number_of_players: usize,
     testing: bool,
 ) {
-    // Generate game map
-    let map = generate_board(number_of_players, get_randomness(world_seed));
+    if !testing {
+        let display = app
+            .world
+            .get_resource::<DisplaySettings>()
+            .cloned()
+            .unwrap_or_default();
+        let plugins = DefaultPlugins
+            .set(WindowPlugin {
+                window: display.window_descriptor(),
+                ..default()
+            })
+            // map authors see their textures and models change without restarting the game
+            .set(AssetPlugin {
+                watch_for_changes: cfg!(feature = "hot-reload"),
+                ..default()
+            });
+        // the log subscriber of the game replaces the one of the LogPlugin, keeping the recent
+        // lines for bug reports
+        #[cfg(not(target_arch = "wasm32"))]
+        let plugins = {
+            app.insert_resource(init_logging());
+            plugins.disable::<bevy::log::LogPlugin>()
+        };
+        app.add_plugins(plugins)
+            .add_plugin(AudioPlugin)
+            .add_plugin(OutlinePlugin);
+    }
 
-    // Source of randomness for the game
-    let prng_resource = tiered_prng::PrngResource {
+    app.add_plugin(GameLogicPlugin {
         world_seed,
         env_seed,
-    };
+        number_of_players,
+    })
+    .add_plugin(BoardPlugin)
+    .add_plugin(InputPlugin)
+    .add_plugin(UiPlugin)
+    .add_plugin(DebugPlugin);
+}
 
-    if !testing {
-        app.add_plugins(DefaultPlugins);
-        app.add_plugin(bevy_kira_audio::prelude::AudioPlugin);
-        app.add_plugin(OutlinePlugin);
-        app.add_plugins(highlights::StackRankDicePickingPlugins);
-    }
+/// Whether the app opens a window, the plugins drawing to it are left out otherwise
+fn has_window(app: &App) -> bool {
+    app.world.contains_resource::<bevy::window::Windows>()
+}
 
-    app
-        // PRNG setup
-        .insert_resource(prng_resource)
-        // Plugins
-        .add_plugin(tiered_prng::PrngPlugin) // Adds Prng based resources for subcomponents
-        .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
-        .add_plugin(DicePlugin)
-        // Resources
-        .insert_resource(DicePluginSettings {
+/// Rules, state and flow of the game: battles, turns, seats, matches and everything that follows
+/// the moves, such as clocks, autosaves and the timeline. Owns the game events.
+pub struct GameLogicPlugin {
+    pub world_seed: u64,
+    pub env_seed: u64,
+    pub number_of_players: usize,
+}
+
+impl Plugin for GameLogicPlugin {
+    fn build(&self, app: &mut App) {
+        // rules set up before the app was built, such as the board generator picked on the
+        // command line
+        let rules = app
+            .world
+            .get_resource::<GameRules>()
+            .cloned()
+            .unwrap_or_else(|| GameRules::with_players(self.number_of_players));
+
+        let initial_state = app
+            .world
+            .get_resource::<OpenMainMenu>()
+            .copied()
+            .unwrap_or_default()
+            .initial_state();
+
+        // Generate game map, the board of a game set up from the main menu waits for the setup
+        let map = match initial_state {
+            AppState::MainMenu => Board::default(),
+            _ => generate_board(&rules, get_randomness(self.world_seed)),
+        };
+
+        // Source of randomness for the game
+        let prng_resource = tiered_prng::PrngResource {
+            world_seed: self.world_seed,
+            env_seed: self.env_seed,
+        };
+
+        register_types(app);
+
+        let budget = app
+            .world
+            .get_resource::<MemoryBudget>()
+            .copied()
+            .unwrap_or_default();
+
+        app
+            // App states
+            .add_state(initial_state)
+            // PRNG setup
+            .insert_resource(prng_resource)
+            // Plugins
+            .add_plugin(tiered_prng::PrngPlugin) // Adds Prng based resources for subcomponents
+            .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+            // Resources
+            .insert_resource(budget)
+            .insert_resource(GameState {
+                board: map,
+                number_of_players: self.number_of_players,
+                turn_of_player: 0,
+                first_player: 0,
+                compensation: Vec::new(),
+                turn_counter: 0,
+                game_log: Vec::new(),
+                pacts: Vec::new(),
+                reserves: Vec::new(),
+                turn_phase: TurnPhase::Attack,
+                decided_winner: None,
+            })
+            .insert_resource(rules)
+            .init_resource::<Autosave>()
+            .init_resource::<ScenarioTriggers>()
+            .init_resource::<GlobalEventLog>()
+            .init_resource::<Diplomacy>()
+            .init_resource::<ObjectiveTracker>()
+            .init_resource::<Seats>()
+            .init_resource::<PlayerProfiles>()
+            .init_resource::<Timeline>()
+            .init_resource::<PlayerClocks>()
+            .init_resource::<BattleRecorder>()
+            .init_resource::<TimelineExport>()
+            .init_resource::<IdleSettings>()
+            .init_resource::<IdleTracker>()
+            .init_resource::<RichPresence>()
+            .init_resource::<Telemetry>()
+            .init_resource::<TurnDriver>()
+            .init_resource::<BotIntent>()
+            .init_resource::<BotPacing>()
+            .init_resource::<PendingDiceRolls>()
+            .init_resource::<StalemateTracker>()
+            .init_resource::<SnapshotRing>()
+            .init_resource::<Rematch>()
+            .init_resource::<BlitzRound>()
+            .init_resource::<Pause>()
+            .init_resource::<SoundRouter>()
+            .init_resource::<GameSetup>()
+            .init_resource::<SettingsMenu>()
+            .init_resource::<JoinForm>()
+            // Startup Systems
+            .add_startup_system(check_autosave.after(StartupLabel::Setup))
+            .add_startup_system(remember_first_board)
+            // Event Handlers
+            .add_system(
+                event_player_move_start
+                    .label(BattleLabel::Clash)
+                    .with_run_criteria(classic_turns),
+            )
+            .add_system(
+                event_dice_roll_result
+                    .label(BattleLabel::RollResult)
+                    .after(BattleLabel::Clash),
+            )
+            .add_system(
+                event_player_move_end
+                    .label(BattleLabel::Resolution)
+                    .after(BattleLabel::RollResult)
+                    .with_run_criteria(classic_turns),
+            )
+            .add_system(
+                pass_blocked_player
+                    .after(BattleLabel::Resolution)
+                    .with_run_criteria(classic_turns),
+            )
+            // Blitz mode
+            .add_system(
+                collect_blitz_orders
+                    .label(BattleLabel::Resolution)
+                    .with_run_criteria(blitz_rounds),
+            )
+            .add_system(event_game_over.after(BattleLabel::Resolution))
+            .add_system(hide_dropped_dice_rolls)
+            // Network games followed from another machine
+            .add_system(send_joined_moves)
+            .add_system(detect_stalemate.after(BattleLabel::Resolution))
+            // Idle players, the idle time stands still while the game is paused
+            .add_system(track_idle.with_run_criteria(game_running))
+            .add_system(
+                auto_pass_idle_player
+                    .with_run_criteria(game_running)
+                    .after(track_idle),
+            )
+            // Match
+            .add_system(start_next_game)
+            // App states
+            .add_system(pause_in_main_menu)
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(enter_game_over.after(event_game_over)),
+            )
+            // Scenario
+            .add_system(scenario_triggers)
+            // Global events
+            .add_system(global_events)
+            // Diplomacy
+            .add_system(expire_truce_proposals)
+            // Objectives
+            .add_system(objective_turn_start)
+            .add_system(record_battles)
+            .add_system(record_timeline)
+            .add_system(update_rich_presence)
+            .add_system(telemetry_game_over)
+            .add_system(telemetry_sent)
+            // Clocks
+            .add_system(stop_player_clocks)
+            // Speedrun
+            .add_system(speedrun_game_over)
+            // Puzzles
+            .add_system(judge_puzzle)
+            // Timeline
+            .add_system(track_standings)
+            // Snapshots
+            .add_system(record_snapshots)
+            .add_system(serve_api)
+            // Autosave
+            .add_system(autosave_moves)
+            .add_system(autosave_game_over.after(autosave_moves))
+            // Saved games
+            .add_system(save_game)
+            .add_system(load_game)
+            // Pause
+            .add_system(freeze_physics)
+            // Sounds
+            .add_system(play_sounds.after(BattleLabel::Resolution))
+            // Events
+            .add_event::<EventPlayerMoveStart>()
+            .add_event::<EventPlayerMoveEnd>()
+            .add_event::<EventBattleResolved>()
+            .add_event::<EventReinforcementPlaced>()
+            .add_event::<EventBoardChanged>()
+            .add_event::<EventGameOver>()
+            .add_event::<EventNewGame>()
+            .add_event::<EventTurnStart>()
+            .add_event::<EventTurnPassed>()
+            .add_event::<EventTurnEnd>()
+            .add_event::<EventRegionPinged>()
+            .add_event::<EventTruceAgreed>()
+            .add_event::<EventPlayerEliminated>()
+            .add_event::<EventAreaBroken>()
+            .add_event::<EventLeadChanged>()
+            .add_event::<SaveGameEvent>()
+            .add_event::<LoadGameEvent>()
+            .add_event::<EventSound>();
+
+        // The dice plugin throws the dice of the battles in views of their own, the low memory
+        // budget rolls them without views
+        let dice_settings = DicePluginSettings {
             render_size: (640 * 2, 720 * 2),
             number_of_fields: 2,
             ..default()
-        })
-        .insert_resource(GameState {
-            board: map,
-            number_of_players,
-            turn_of_player: 0,
-            turn_counter: 0,
-            game_log: Vec::new(),
-        })
-        .insert_resource(ClearColor(Color::BLACK))
-        .init_resource::<SelectedRegion>()
-        // Startup Systems
-        .add_startup_system(setup_ui.after("dice_plugin_init").label("setup"))
-        .add_startup_system(draw_board.after("setup"))
-        // UI Systems
-        .add_system(player_turn_text_update)
-        .add_system(dice_roll_result_text_update)
-        // Control Handling
-        .add_system_to_stage(CoreStage::PostUpdate, event_region_selected)
-        // Event Handlers
-        .add_system(event_player_move_start)
-        .add_system(event_dice_roll_result)
-        .add_system(event_dice_rolls_complete)
-        .add_system(event_player_move_end)
-        .add_system(event_game_over)
+        };
+        match budget.dice_views() {
+            true => {
+                app.add_plugin(DicePlugin).insert_resource(dice_settings);
+            }
+            false => {
+                app.insert_resource(dice_settings)
+                    .add_event::<DiceRollStartEvent>()
+                    .add_event::<DiceRollResult>()
+                    .add_system(
+                        roll_numeric_dice
+                            .after(BattleLabel::Clash)
+                            .before(BattleLabel::RollResult),
+                    );
+            }
+        }
+
+        // Systems timing the game, at the same pace whatever the frame rate, standing still
+        // while the game is paused
+        add_game_logic_stage(app);
+        app.add_system_set_to_stage(
+            GameLogicStage,
+            SystemSet::new()
+                .with_run_criteria(game_running)
+                .with_system(drive_seats)
+                .with_system(event_dice_rolls_complete)
+                .with_system(run_player_clocks)
+                .with_system(speedrun_timer),
+        );
+    }
+}
+
+/// The board in the world: region tiles, dice, highlights, markers and the camera
+pub struct BoardPlugin;
+
+impl Plugin for BoardPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ClearColor(Color::BLACK))
+            .init_resource::<OwnershipHeatmap>()
+            .init_resource::<AmbientAnimation>()
+            .init_resource::<highlights::HighlightState>()
+            .init_resource::<highlights::HighlightStyles>()
+            .add_startup_system(draw_board.after(StartupLabel::Setup))
+            .add_startup_system(spawn_board_scene.after(StartupLabel::Setup))
+            .add_system(event_board_changed.after(BattleLabel::Resolution))
+            .add_system(camera_rig_battles)
+            .add_system(camera_rig_update.after(camera_rig_battles))
+            .add_system(highlights::update_highlight_state)
+            .add_system(apply_highlight_styles)
+            .add_system(
+                highlights::highlight_outlines
+                    .after(highlights::update_highlight_state)
+                    .after(apply_highlight_styles),
+            )
+            .add_system(ownership_heatmap)
+            // Ambient animation
+            .add_system(toggle_ambient_animation)
+            .add_system(bob_dice)
+            .add_system(cycle_region_lights)
+            .add_system(shimmer_hovered_region.after(highlights::update_highlight_state))
+            .add_system(high_contrast_board)
+            // Pings
+            .add_system(spawn_ping_markers.after(ping_regions))
+            .add_system(ping_marker_update)
+            // Scenes
+            .add_system(board_from_scene)
+            // Hot reload
+            .add_system(reload_changed_map)
+            .add_system(refresh_theme_materials);
+    }
+}
+
+/// Mouse and keyboard: region picking, the input map and the keys of the game
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        if has_window(app) {
+            app.add_plugins(highlights::StackRankDicePickingPlugins)
+                .add_system(pause_on_focus_loss);
+            if app.world.get_resource::<PickingMode>() == Some(&PickingMode::Gpu) {
+                app.add_plugin(GpuPickingPlugin);
+            }
+        }
+
+        app.init_resource::<InputMap>()
+            .init_resource::<SelectedRegion>()
+            .add_event::<EventInputAction>()
+            // Control Handling
+            .add_system_to_stage(CoreStage::PostUpdate, emit_input_actions)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                event_region_selected
+                    .after(emit_input_actions)
+                    .with_run_criteria(game_running),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, pause_input.after(emit_input_actions))
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                highlights::sync_picking_selection.after(event_region_selected),
+            )
+            .add_system(event_end_turn.with_run_criteria(game_running))
+            .add_system(camera_controls)
+            .add_system(diplomacy_input)
+            .add_system(ping_regions)
+            .add_system(retry_puzzle)
+            .add_system(resume_prompt_input)
+            .add_system(persistence_input)
+            // Menus
+            .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(main_menu_input))
+            .add_system_set(
+                SystemSet::on_update(AppState::Setup)
+                    .with_system(setup_input)
+                    .with_system(map_thumbnail_update)
+                    .with_system(start_game),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Join)
+                    .with_system(join_screen_input)
+                    .with_system(finish_join),
+            )
+            .add_system_set(SystemSet::on_update(AppState::Settings).with_system(settings_input))
+            .add_system_set(SystemSet::on_update(AppState::GameOver).with_system(game_over_input));
+    }
+}
+
+/// Text, panels and banners laid over the board
+pub struct UiPlugin;
+
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AccessibilitySettings>()
+            .init_resource::<DisplaySettings>()
+            .init_resource::<BattleReport>()
+            .init_resource::<TurnStartCue>()
+            .init_resource::<Commentary>()
+            // Startup Systems
+            .add_startup_system(setup_ui.after(DICE_PLUGIN_INIT).label(StartupLabel::Setup))
+            .add_startup_system(setup_match_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_diplomacy_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_objective_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_region_tooltip.after(StartupLabel::Setup))
+            .add_startup_system(setup_timeline_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_clock_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_speedrun_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_puzzle_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_odds_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_battle_log_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_player_stats_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_idle_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_commentary_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_blitz_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_pause_ui.after(StartupLabel::Setup))
+            .add_startup_system(setup_lobby_ui.after(StartupLabel::Setup))
+            // UI Systems
+            .add_system(player_turn_text_update)
+            .add_system(dice_roll_result_text_update)
+            .add_system(match_score_text_update)
+            .add_system(toast_update)
+            .add_system(bot_thinking_label)
+            .add_system(board_labels_follow_camera)
+            .add_system(scale_hud_text)
+            .add_system(scale_dice_views)
+            .add_system(announce_turn_start)
+            .add_system(turn_banner_update.after(announce_turn_start))
+            .add_system(idle_text_update.after(track_idle))
+            .add_system(diplomacy_text_update)
+            .add_system(objective_text_update)
+            // Territory names
+            .add_system(region_tooltip_update)
+            .add_system(announce_conquests)
+            .add_system(clock_text_update)
+            .add_system(speedrun_text_update)
+            .add_system(puzzle_text_update)
+            // Timeline
+            .add_system(announce_turning_points.after(track_standings))
+            .add_system(timeline_text_update.after(track_standings))
+            .add_system(timeline_game_over.after(event_game_over))
+            .add_system(odds_text_update)
+            .add_system(player_stats_text_update)
+            .add_system(commentary_text_update)
+            .add_system(blitz_orders_text_update)
+            .add_system(pause_overlay_update)
+            .add_system(lobby_text_update)
+            .add_system(follow_spectated_game)
+            // Battle log
+            .add_system(battle_log_update)
+            .add_system(battle_log_clicked)
+            .add_system(battle_report_text_update.after(battle_log_clicked))
+            // Rematch
+            .add_system(rematch_clicked)
+            // Menus
+            .add_system_set(SystemSet::on_enter(AppState::MainMenu).with_system(setup_main_menu))
+            .add_system_set(SystemSet::on_exit(AppState::MainMenu).with_system(despawn_menu_screen))
+            .add_system_set(SystemSet::on_enter(AppState::Setup).with_system(setup_game_setup))
+            .add_system_set(SystemSet::on_exit(AppState::Setup).with_system(despawn_menu_screen))
+            .add_system_set(SystemSet::on_enter(AppState::Join).with_system(setup_join_screen))
+            .add_system_set(SystemSet::on_exit(AppState::Join).with_system(despawn_menu_screen))
+            .add_system_set(
+                SystemSet::on_enter(AppState::Settings).with_system(setup_settings_screen),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Settings).with_system(despawn_menu_screen))
+            // Display
+            .add_system(display_settings_input)
+            .add_system(apply_display_settings.after(display_settings_input));
+    }
+}
+
+/// Sound, played by the systems of the other plugins through the `Audio` resource
+pub struct AudioPlugin;
+
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(bevy_kira_audio::prelude::AudioPlugin)
+            .add_asset::<SoundManifest>()
+            .init_asset_loader::<SoundManifestLoader>()
+            .add_startup_system(load_sound_manifest)
+            .add_system(update_sound_manifest);
+    }
+}
+
+/// Debug overlays, checks and tools, and the world inspector with the `inspector` feature
+pub struct DebugPlugin;
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        #[cfg(feature = "inspector")]
+        if has_window(app) {
+            app.add_plugin(WorldInspectorPlugin::new());
+        }
+        if has_window(app) {
+            app.add_plugin(ScreenshotPlugin);
+        }
+
+        app.init_resource::<ThreatOverlay>()
+            .init_resource::<HexDebugOverlay>()
+            .init_resource::<DiceAuditSettings>()
+            .add_system(threat_overlay)
+            .add_system(hex_debug_overlay)
+            .add_system_to_stage(CoreStage::Last, audit_dice)
+            .add_system(export_board_scene)
+            .add_system(report_issue)
+            .add_system(save_bug_report.after(report_issue));
+    }
+}
+
+/// Registers game types with the type registry so they can be used by inspectors and scenes
+fn register_types(app: &mut App) {
+    app
+        // Game state
+        .register_type::<GameRules>()
+        .register_type::<ReinforcementRule>()
+        .register_type::<TieRule>()
+        .register_type::<GameState>()
+        .register_type::<Board>()
+        .register_type::<Region>()
+        .register_type::<GameLogEntry>()
+        .register_type::<Pact>()
+        .register_type::<TurnPhase>()
+        .register_type::<MatchSession>()
         // Events
-        .add_event::<EventPlayerMoveStart>()
-        .add_event::<EventPlayerMoveEnd>()
-        .add_event::<EventGameOver>()
-        .add_event::<EventTurnStart>()
-        .add_event::<EventTurnEnd>();
+        .register_type::<EventPlayerMoveStart>()
+        .register_type::<EventPlayerMoveEnd>()
+        .register_type::<EventBattleResolved>()
+        .register_type::<EventReinforcementPlaced>()
+        .register_type::<EventBoardChanged>()
+        .register_type::<EventGameOver>()
+        .register_type::<EventNewGame>()
+        .register_type::<EventTurnStart>()
+        .register_type::<EventTurnPassed>()
+        .register_type::<EventTurnEnd>()
+        .register_type::<EventRegionPinged>()
+        .register_type::<EventTruceAgreed>()
+        .register_type::<EventPlayerEliminated>()
+        .register_type::<EventAreaBroken>()
+        .register_type::<EventLeadChanged>();
 }
@@ -0,0 +1,401 @@
+use std::io;
+
+use bevy::prelude::*;
+
+use crate::board::BoardDrawer;
+use crate::diplomacy::EventTruceAgreed;
+use crate::events::{
+    EventBattleResolved, EventGameOver, EventReinforcementPlaced, EventTurnPassed,
+};
+use crate::game::GameState;
+use crate::handshake::{Handshake, Incompatible};
+use crate::new_game::NewGame;
+use crate::pings::EventRegionPinged;
+use crate::profiles::PlayerProfiles;
+use crate::replay::{BoardHeader, Replay, ReplayMove};
+use crate::rules::GameRules;
+use crate::session::new_game_state_started_by;
+use crate::stamp::ExportStamp;
+use crate::storage::{storage, Location, Storage};
+use crate::tiered_prng::PrngResource;
+use crate::ui::StackRankDiceUI;
+
+/// Move log of the game in progress, relative to the data directory.
+/// Removed once a game finishes, so finding it at launch means the last game was interrupted.
+pub const AUTOSAVE_PATH: &str = "autosave.replay";
+
+/// Comment of the move log holding the rules of its game as JSON, so the game resumes with the
+/// rules it was set up with whatever rules the game is launched with
+const RULES_COMMENT: &str = "# rules ";
+
+/// Append-only move log of the game in progress. The game is reconstructed from the world seed,
+/// so boards loaded from map or scene files can't be resumed.
+#[derive(Resource)]
+pub struct Autosave {
+    storage: Box<dyn Storage>,
+    /// Whether moves of the current game are being logged
+    active: bool,
+    /// Whether the current game was loaded from a save, which can't be replayed from its world
+    /// seed. Its moves aren't logged.
+    detached: bool,
+}
+
+impl Default for Autosave {
+    fn default() -> Self {
+        Autosave {
+            storage: storage(Location::Data),
+            active: false,
+            detached: false,
+        }
+    }
+}
+
+impl Autosave {
+    /// Starts a new move log of a game played with `rules`, overwriting the previous one
+    fn start(&mut self, replay: &Replay, stamp: &ExportStamp, rules: &GameRules) -> io::Result<()> {
+        self.storage
+            .write(AUTOSAVE_PATH, &stamped_with_rules(replay, stamp, rules))?;
+
+        self.active = true;
+        Ok(())
+    }
+
+    /// Continues an existing move log
+    fn resume(&mut self) -> io::Result<()> {
+        self.storage.read(AUTOSAVE_PATH)?;
+
+        self.active = true;
+        Ok(())
+    }
+
+    /// Appends a move and makes sure it was stored before returning
+    fn append(&mut self, replay_move: &ReplayMove) -> io::Result<()> {
+        if self.active {
+            self.storage
+                .append(AUTOSAVE_PATH, &format!("{}\n", replay_move))?;
+        }
+
+        Ok(())
+    }
+
+    /// Move log of the game in progress, none when it isn't logged
+    pub fn move_log(&self) -> Option<String> {
+        if !self.active {
+            return None;
+        }
+
+        self.storage.read(AUTOSAVE_PATH).ok()
+    }
+
+    /// Stops logging the moves of the current game, which was loaded from a save, and removes
+    /// the log of the game it replaced
+    pub(crate) fn detach(&mut self) -> io::Result<()> {
+        self.detached = true;
+        self.clear()
+    }
+
+    /// Removes the log of the game replaced by a new one, the first move of the new game starts
+    /// the next log
+    pub(crate) fn restart(&mut self) -> io::Result<()> {
+        self.detached = false;
+        self.clear()
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        if self.active {
+            self.active = false;
+            self.storage.remove(AUTOSAVE_PATH)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The move log `replay` as text, opening with comments holding `stamp` and `rules`
+fn stamped_with_rules(replay: &Replay, stamp: &ExportStamp, rules: &GameRules) -> String {
+    let rules = serde_json::to_string(rules).unwrap_or_default();
+    format!("# {}\n{}{}\n{}", stamp, RULES_COMMENT, rules, replay)
+}
+
+/// Rules saved in the move log `source`, none for logs written before the rules were saved
+fn saved_rules(source: &str) -> Option<GameRules> {
+    source
+        .lines()
+        .find_map(|line| line.strip_prefix(RULES_COMMENT))
+        .and_then(|rules| serde_json::from_str(rules).ok())
+}
+
+/// An interrupted game found at launch, waiting for the player to resume or discard it
+#[derive(Resource)]
+pub(crate) struct ResumePrompt {
+    replay: Replay,
+    /// Rules the game resumes with
+    rules: GameRules,
+    /// Why the game can't be resumed, when it was saved by an incompatible version or with
+    /// other rules. It can only be discarded then.
+    incompatible: Option<Incompatible>,
+}
+
+/// Rules the interrupted `replay` resumes with: the `saved` ones, or `rules` for its number of
+/// players and board for logs written before the rules were saved
+fn resumed_rules(rules: &GameRules, replay: &Replay, saved: Option<GameRules>) -> GameRules {
+    saved.unwrap_or_else(|| {
+        replay.rules(&GameRules {
+            number_of_players: replay.number_of_players,
+            ..rules.clone()
+        })
+    })
+}
+
+/// Text of the resume prompt
+#[derive(Component)]
+pub(crate) struct ResumePromptUI;
+
+pub(crate) fn check_autosave(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    autosave: Res<Autosave>,
+    rules: Res<GameRules>,
+) {
+    let source = match autosave.storage.read(AUTOSAVE_PATH) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+
+    let replay = match Replay::parse(&source) {
+        Ok(replay) => replay,
+        Err(err) => {
+            warn!("Ignoring unreadable autosave {}: {}", AUTOSAVE_PATH, err);
+            return;
+        }
+    };
+
+    if replay.moves.is_empty() {
+        return;
+    }
+
+    // the autosave only replays the same moves to the same position with the same protocol
+    // and rules
+    let resumed_rules = resumed_rules(&rules, &replay, saved_rules(&source));
+    let local = Handshake::local(Some(&resumed_rules));
+    let incompatible = Handshake::from_stamp(&source).and_then(|saved| local.check(&saved).err());
+    let text = match &incompatible {
+        Some(err) => {
+            warn!("Can't resume the autosave {}: {}", AUTOSAVE_PATH, err);
+            format!("Can't resume interrupted game,\n{}\nDiscard it? (N)", err)
+        }
+        None => "Resume interrupted game? (Y/N)".to_string(),
+    };
+
+    commands.insert_resource(ResumePrompt {
+        replay,
+        rules: resumed_rules,
+        incompatible,
+    });
+    commands
+        .spawn(
+            TextBundle::from_section(
+                text,
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 50.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_text_alignment(TextAlignment::TOP_CENTER)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Percent(50.0),
+                    left: Val::Percent(30.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(ResumePromptUI)
+        .insert(StackRankDiceUI);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn resume_prompt_input(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    prompt: Option<Res<ResumePrompt>>,
+    prompt_ui_query: Query<Entity, With<ResumePromptUI>>,
+    mut autosave: ResMut<Autosave>,
+    mut prng: ResMut<PrngResource>,
+    mut rules: ResMut<GameRules>,
+    mut game_state: ResMut<GameState>,
+    profiles: Res<PlayerProfiles>,
+    mut board_drawer: BoardDrawer,
+    mut new_game: NewGame,
+) {
+    let prompt = match prompt {
+        Some(prompt) => prompt,
+        None => return,
+    };
+
+    let resume = keys.just_pressed(KeyCode::Y) && prompt.incompatible.is_none();
+    if !resume && !keys.just_pressed(KeyCode::N) {
+        return;
+    }
+
+    commands.remove_resource::<ResumePrompt>();
+    for e in prompt_ui_query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+
+    if !resume {
+        return;
+    }
+
+    let replay = &prompt.replay;
+    let resumed_rules = prompt.rules.clone();
+    let mut resumed = new_game_state_started_by(
+        &resumed_rules,
+        replay.world_seed,
+        replay.first_player(&resumed_rules),
+    );
+
+    if let Err(err) = replay.apply(&mut resumed, &resumed_rules) {
+        warn!("Failed to resume game from {}: {}", AUTOSAVE_PATH, err);
+        return;
+    }
+
+    if let Err(err) = autosave.resume() {
+        warn!("Failed to reopen {}: {}", AUTOSAVE_PATH, err);
+    }
+
+    *rules = resumed_rules;
+    *game_state = resumed;
+    new_game.reset();
+    prng.world_seed = replay.world_seed;
+    prng.env_seed = replay.env_seed;
+
+    board_drawer.redraw(&game_state, &profiles);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn autosave_moves(
+    mut commands: Commands,
+    mut event_battle_resolved_reader: EventReader<EventBattleResolved>,
+    mut event_reinforcement_placed_reader: EventReader<EventReinforcementPlaced>,
+    mut event_turn_passed_reader: EventReader<EventTurnPassed>,
+    mut event_region_pinged_reader: EventReader<EventRegionPinged>,
+    mut event_truce_agreed_reader: EventReader<EventTruceAgreed>,
+    mut event_game_over_reader: EventReader<EventGameOver>,
+    prompt_ui_query: Query<Entity, With<ResumePromptUI>>,
+    mut autosave: ResMut<Autosave>,
+    prng: Res<PrngResource>,
+    game_state: Res<GameState>,
+    rules: Res<GameRules>,
+) {
+    let battles = event_battle_resolved_reader
+        .iter()
+        .map(|e| ReplayMove::Attack {
+            attacker: e.region_1,
+            defender: e.region_2,
+            attacker_roll: e.region_1_dice_result.to_vec(),
+            defender_roll: e.region_2_dice_result.to_vec(),
+            transferred: e.transferred,
+        });
+    let reinforcements = event_reinforcement_placed_reader
+        .iter()
+        .map(|e| ReplayMove::Reinforce(e.region));
+    let passes = event_turn_passed_reader.iter().map(|_| ReplayMove::Pass);
+    let pings = event_region_pinged_reader.iter().map(|e| ReplayMove::Ping {
+        player: e.player,
+        region: e.region,
+    });
+    let truces = event_truce_agreed_reader.iter().map(|e| ReplayMove::Truce {
+        player_1: e.pact.player_1,
+        player_2: e.pact.player_2,
+        expires_at_turn: e.pact.expires_at_turn,
+    });
+    // a game won by owning the whole board ends with its last attack
+    let winners = event_game_over_reader
+        .iter()
+        .filter(|e| game_state.decided_winner == Some(e.winner))
+        .map(|e| ReplayMove::Winner(e.winner));
+
+    for replay_move in battles
+        .chain(reinforcements)
+        .chain(passes)
+        .chain(pings)
+        .chain(truces)
+        .chain(winners)
+    {
+        if autosave.detached {
+            continue;
+        }
+
+        // The first move of a new game replaces the previous autosave
+        if !autosave.active {
+            commands.remove_resource::<ResumePrompt>();
+            for e in prompt_ui_query.iter() {
+                commands.entity(e).despawn_recursive();
+            }
+
+            let mut replay =
+                Replay::new(prng.world_seed, prng.env_seed, game_state.number_of_players);
+            replay.first_player = Some(game_state.first_player);
+            replay.board = Some(BoardHeader::new(&rules));
+            replay.region_keys = game_state.board.region_keys();
+            let stamp = ExportStamp::new(&prng, &rules);
+            if let Err(err) = autosave.start(&replay, &stamp, &rules) {
+                warn!("Failed to create {}: {}", AUTOSAVE_PATH, err);
+                return;
+            }
+        }
+
+        if let Err(err) = autosave.append(&replay_move) {
+            warn!("Failed to autosave to {}: {}", AUTOSAVE_PATH, err);
+        }
+    }
+}
+
+pub(crate) fn autosave_game_over(
+    mut event_game_over_reader: EventReader<EventGameOver>,
+    mut autosave: ResMut<Autosave>,
+) {
+    if event_game_over_reader.iter().next().is_some() {
+        autosave.detached = false;
+        if let Err(err) = autosave.clear() {
+            warn!("Failed to remove {}: {}", AUTOSAVE_PATH, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn games_resume_with_their_rules() {
+        let rules = GameRules {
+            board_size: 28,
+            momentum: false,
+            ..GameRules::with_players(3)
+        };
+        let prng = PrngResource {
+            world_seed: 4,
+            env_seed: 2,
+        };
+        let replay = Replay::new(4, 2, 3);
+        let source = stamped_with_rules(&replay, &ExportStamp::new(&prng, &rules), &rules);
+
+        // launched with other rules
+        let launched = GameRules::default();
+        assert_eq!(Replay::parse(&source), Ok(replay.clone()));
+        let resumed = resumed_rules(&launched, &replay, saved_rules(&source));
+        assert_eq!(resumed, rules);
+        let saved = Handshake::from_stamp(&source).unwrap();
+        assert_eq!(Handshake::local(Some(&resumed)).check(&saved), Ok(()));
+
+        // logs written before the rules were saved go on with the launch rules
+        let old = replay.stamped(&ExportStamp::new(&prng, &rules));
+        let resumed = resumed_rules(&launched, &replay, saved_rules(&old));
+        assert_eq!(resumed, GameRules::with_players(3));
+    }
+}
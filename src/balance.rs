@@ -0,0 +1,232 @@
+//! Balance reports: headless games simulated over a grid of rules, to weigh a rule change with
+//! numbers instead of impressions.
+//!
+//! Every combination of dice per region, maximum dice and tie rule plays the same boards, so the
+//! rows of a report only differ by their rules. Wins are counted by seat, the order players move
+//! in starting from the first player, which shows the advantage of moving first.
+
+use std::fmt::Write;
+
+use crate::rules::{GameRules, TieRule};
+use crate::simulation::{simulate_batch, SimulationConfig};
+use crate::zobrist;
+
+/// Simulated games are stopped without a winner after this many turns
+pub const MAX_TURNS: usize = 1000;
+
+/// Rules of one row of a report, on top of the base rules
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BalanceCell {
+    pub dice_per_region: usize,
+    pub max_dice: usize,
+    pub tie_rule: TieRule,
+}
+
+impl BalanceCell {
+    pub fn rules(&self, base: &GameRules) -> GameRules {
+        GameRules {
+            dice_per_region: self.dice_per_region,
+            max_dice: self.max_dice,
+            tie_rule: self.tie_rule,
+            ..base.clone()
+        }
+    }
+}
+
+/// Results of the games played with the rules of a cell
+#[derive(Clone, Debug, PartialEq)]
+pub struct BalanceRow {
+    pub cell: BalanceCell,
+    pub games: usize,
+    /// Games won by the player of each seat, the first player in seat 0
+    pub seat_wins: Vec<usize>,
+    /// Games stopped after [`MAX_TURNS`] or stuck without a winner
+    pub unfinished: usize,
+    pub average_turns: f64,
+    pub average_battles: f64,
+}
+
+impl BalanceRow {
+    /// Share of the games won by the player of `seat`, from 0 to 1
+    pub fn win_rate(&self, seat: usize) -> f64 {
+        match self.games {
+            0 => 0.0,
+            games => self.seat_wins[seat] as f64 / games as f64,
+        }
+    }
+}
+
+/// Every combination of the given values, dice per region varying slowest
+pub fn grid(
+    dice_per_region: &[usize],
+    max_dice: &[usize],
+    tie_rules: &[TieRule],
+) -> Vec<BalanceCell> {
+    let mut cells = Vec::new();
+    for dice_per_region in dice_per_region {
+        for max_dice in max_dice {
+            for tie_rule in tie_rules {
+                cells.push(BalanceCell {
+                    dice_per_region: *dice_per_region,
+                    max_dice: *max_dice,
+                    tie_rule: *tie_rule,
+                });
+            }
+        }
+    }
+
+    cells
+}
+
+/// Plays `games` games for every cell of `cells`. Game `n` of every cell is played on the board
+/// of world seed `n + 1`, with the same seed for its moves and dice rolls.
+pub fn balance_report(base: &GameRules, cells: &[BalanceCell], games: usize) -> Vec<BalanceRow> {
+    let number_of_players = base.number_of_players;
+
+    cells
+        .iter()
+        .map(|cell| {
+            let rules = cell.rules(base);
+            let configs: Vec<SimulationConfig> = (0..games as u64)
+                .map(|game| SimulationConfig {
+                    rules: rules.clone(),
+                    world_seed: game + 1,
+                    env_seed: zobrist::mix(game),
+                    max_turns: MAX_TURNS,
+                })
+                .collect();
+            let summaries = simulate_batch(&configs);
+
+            let mut seat_wins = vec![0; number_of_players];
+            let mut unfinished = 0;
+            for summary in summaries.iter() {
+                match summary.winner {
+                    Some(winner) => {
+                        seat_wins[(winner + number_of_players - summary.first_player)
+                            % number_of_players] += 1
+                    }
+                    None => unfinished += 1,
+                }
+            }
+            let average = |total: usize| match games {
+                0 => 0.0,
+                games => total as f64 / games as f64,
+            };
+
+            BalanceRow {
+                cell: *cell,
+                games,
+                seat_wins,
+                unfinished,
+                average_turns: average(summaries.iter().map(|summary| summary.turns).sum()),
+                average_battles: average(summaries.iter().map(|summary| summary.battles).sum()),
+            }
+        })
+        .collect()
+}
+
+fn seats(rows: &[BalanceRow]) -> usize {
+    rows.first().map_or(0, |row| row.seat_wins.len())
+}
+
+/// The report as a markdown table, one row per cell
+pub fn to_markdown(rows: &[BalanceRow]) -> String {
+    let mut markdown = String::from("| Dice per region | Max dice | Ties | Games |");
+    for seat in 0..seats(rows) {
+        write!(markdown, " Seat {} wins |", seat + 1).unwrap();
+    }
+    markdown.push_str(" Unfinished | Avg turns | Avg battles |\n|---|---|---|---|");
+    for _ in 0..seats(rows) {
+        markdown.push_str("---|");
+    }
+    markdown.push_str("---|---|---|\n");
+
+    for row in rows {
+        write!(
+            markdown,
+            "| {} | {} | {:?} | {} |",
+            row.cell.dice_per_region, row.cell.max_dice, row.cell.tie_rule, row.games
+        )
+        .unwrap();
+        for seat in 0..row.seat_wins.len() {
+            write!(markdown, " {:.1}% |", row.win_rate(seat) * 100.0).unwrap();
+        }
+        writeln!(
+            markdown,
+            " {} | {:.1} | {:.1} |",
+            row.unfinished, row.average_turns, row.average_battles
+        )
+        .unwrap();
+    }
+
+    markdown
+}
+
+/// The report as CSV, win rates between 0 and 1
+pub fn to_csv(rows: &[BalanceRow]) -> String {
+    let mut csv = String::from("dice_per_region,max_dice,tie_rule,games");
+    for seat in 0..seats(rows) {
+        write!(csv, ",seat_{}_win_rate", seat + 1).unwrap();
+    }
+    csv.push_str(",unfinished,average_turns,average_battles\n");
+
+    for row in rows {
+        write!(
+            csv,
+            "{},{},{:?},{}",
+            row.cell.dice_per_region, row.cell.max_dice, row.cell.tie_rule, row.games
+        )
+        .unwrap();
+        for seat in 0..row.seat_wins.len() {
+            write!(csv, ",{:.4}", row.win_rate(seat)).unwrap();
+        }
+        writeln!(
+            csv,
+            ",{},{:.2},{:.2}",
+            row.unfinished, row.average_turns, row.average_battles
+        )
+        .unwrap();
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_game_of_every_cell_is_counted() {
+        let base = GameRules {
+            board_size: 8,
+            regions_per_player: 4,
+            ..GameRules::with_players(3)
+        };
+        let cells = grid(
+            &[2, 3],
+            &[6],
+            &[TieRule::DefenderWins, TieRule::AttackerWins],
+        );
+        let rows = balance_report(&base, &cells, 4);
+
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[1].cell.tie_rule, TieRule::AttackerWins);
+        for row in rows.iter() {
+            assert_eq!(row.seat_wins.len(), 3);
+            assert_eq!(row.seat_wins.iter().sum::<usize>() + row.unfinished, 4);
+        }
+        assert_eq!(balance_report(&base, &cells, 4), rows);
+
+        let markdown = to_markdown(&rows);
+        assert!(markdown.starts_with("| Dice per region | Max dice | Ties | Games |"));
+        assert!(markdown.contains("| Seat 3 wins |"));
+        assert_eq!(markdown.lines().count(), 2 + rows.len());
+        let csv = to_csv(&rows);
+        assert_eq!(csv.lines().count(), 1 + rows.len());
+        assert!(csv
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("2,6,DefenderWins,4,"));
+    }
+}
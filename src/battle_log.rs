@@ -0,0 +1,322 @@
+//! Battle log: the latest battles of the game listed on the side of the screen. Clicking one
+//! opens a report of the battle, rebuilt from its [`GameLogEntry`].
+
+use bevy::prelude::*;
+
+use crate::game::{Board, GameLogEntry, GameState};
+use crate::odds::attack_win_probability;
+use crate::profiles::PlayerProfiles;
+use crate::rules::GameRules;
+use crate::ui::StackRankDiceUI;
+
+/// Number of battles listed in the log panel
+const SHOWN_BATTLES: usize = 6;
+
+/// Index in the game log of the battle whose report is open
+#[derive(Resource, Default)]
+pub struct BattleReport(pub Option<usize>);
+
+/// Column listing the latest battles
+#[derive(Component)]
+pub(crate) struct BattleLogPanel;
+
+/// Entry of the log panel, with the index of its battle in the game log
+#[derive(Component)]
+pub(crate) struct BattleLogButton(usize);
+
+/// Text area with the report of the battle picked in the log panel
+#[derive(Component)]
+pub(crate) struct BattleReportText;
+
+fn faces(roll: &[usize]) -> String {
+    roll.iter()
+        .map(|face| face.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// One line summary of a battle for the log panel
+pub fn battle_summary(entry: &GameLogEntry, board: &Board) -> String {
+    let result = match entry.attacker_won {
+        true => "took",
+        false => "failed on",
+    };
+    format!(
+        "T{} {} {} {}",
+        entry.turn_counter,
+        board.regions[entry.region_1].label(),
+        result,
+        board.regions[entry.region_2].label()
+    )
+}
+
+/// Full report of a battle: both regions, every die face, the odds the attacker had and the dice
+/// transferred
+pub fn battle_report(
+    entry: &GameLogEntry,
+    board: &Board,
+    profiles: &PlayerProfiles,
+    rules: &GameRules,
+) -> String {
+    let attacker_total: usize = entry.region_1_dice_result.iter().sum();
+    let defender_total: usize = entry.region_2_dice_result.iter().sum();
+    let probability =
+        attack_win_probability(entry.region_1_num_dice, entry.region_2_num_dice, rules);
+
+    let result = match entry.attacker_won {
+        true => format!("{} conquers", profiles.name(entry.turn_of_player)),
+        false => format!("{} holds", profiles.name(entry.defending_player)),
+    };
+
+    format!(
+        "TURN {}\n{}: {} ({} dice)\nrolled {} = {}\n{}: {} ({} dice)\nrolled {} = {}\nwin chance {:.0}%\n{}, {} dice transferred",
+        entry.turn_counter,
+        profiles.name(entry.turn_of_player),
+        board.regions[entry.region_1].label(),
+        entry.region_1_num_dice,
+        faces(&entry.region_1_dice_result),
+        attacker_total,
+        profiles.name(entry.defending_player),
+        board.regions[entry.region_2].label(),
+        entry.region_2_num_dice,
+        faces(&entry.region_2_dice_result),
+        defender_total,
+        probability * 100.0,
+        result,
+        entry.transferred
+    )
+}
+
+pub(crate) fn setup_battle_log_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Percent(30.0),
+                    left: Val::Px(15.0),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Name::new("Battle Log"))
+        .insert(BattleLogPanel)
+        .insert(StackRankDiceUI);
+
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 24.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Percent(30.0),
+                    left: Val::Px(300.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Name::new("Battle Report Text"))
+        .insert(BattleReportText)
+        .insert(StackRankDiceUI);
+}
+
+/// Lists the latest resolved battles in the log panel, whenever the game log changes
+pub(crate) fn battle_log_update(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game_state: Res<GameState>,
+    mut report: ResMut<BattleReport>,
+    panel_query: Query<Entity, With<BattleLogPanel>>,
+    mut shown: Local<Vec<usize>>,
+) {
+    let battles: Vec<usize> = game_state
+        .game_log
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.resolved)
+        .map(|(index, _)| index)
+        .rev()
+        .take(SHOWN_BATTLES)
+        .collect();
+    if *shown == battles {
+        return;
+    }
+
+    // the log was cut short by a new game or an undo
+    if matches!(report.0, Some(index) if index >= game_state.game_log.len()) {
+        report.0 = None;
+    }
+
+    for panel in panel_query.iter() {
+        let mut panel = commands.entity(panel);
+        panel.despawn_descendants();
+        panel.with_children(|parent| {
+            for index in battles.iter() {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            margin: UiRect::bottom(Val::Px(4.0)),
+                            padding: UiRect::all(Val::Px(4.0)),
+                            ..default()
+                        },
+                        background_color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
+                        ..default()
+                    })
+                    .insert(BattleLogButton(*index))
+                    .with_children(|button| {
+                        button.spawn(TextBundle::from_section(
+                            battle_summary(&game_state.game_log[*index], &game_state.board),
+                            TextStyle {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                font_size: 20.0,
+                                color: Color::WHITE,
+                            },
+                        ));
+                    });
+            }
+        });
+    }
+
+    *shown = battles;
+}
+
+/// Opens the report of a clicked battle, or closes it when clicked again
+pub(crate) fn battle_log_clicked(
+    interaction_query: Query<(&Interaction, &BattleLogButton), Changed<Interaction>>,
+    mut report: ResMut<BattleReport>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction == Interaction::Clicked {
+            report.0 = match report.0 == Some(button.0) {
+                true => None,
+                false => Some(button.0),
+            };
+        }
+    }
+}
+
+pub(crate) fn battle_report_text_update(
+    report: Res<BattleReport>,
+    game_state: Res<GameState>,
+    profiles: Res<PlayerProfiles>,
+    rules: Res<GameRules>,
+    mut query: Query<&mut Text, With<BattleReportText>>,
+) {
+    let entry = report.0.and_then(|index| game_state.game_log.get(index));
+
+    for mut text in &mut query {
+        text.sections[0].value = match entry {
+            Some(entry) => battle_report(entry, &game_state.board, &profiles, &rules),
+            None => String::new(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{BattleOutcome, DiceRoll, Region};
+
+    fn game_state() -> GameState {
+        let mut board = Board::default();
+        for (id, (owner, num_dice)) in [(0, 3), (1, 2)].into_iter().enumerate() {
+            board.regions.push(Region {
+                id,
+                owner,
+                num_dice,
+                name: format!("Land {}", id),
+                ..Default::default()
+            });
+        }
+
+        GameState {
+            board,
+            number_of_players: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn log_keeps_the_battle_before_it_changed_the_board() {
+        let rules = GameRules::default();
+        let mut game_state = game_state();
+        game_state.log_attack(GameLogEntry {
+            region_1: 0,
+            region_2: 1,
+            region_1_dice_result: DiceRoll::from_slice(&[6, 5, 4]),
+            region_2_dice_result: DiceRoll::from_slice(&[1, 2]),
+            ..Default::default()
+        });
+        assert!(!game_state.game_log[0].resolved);
+
+        game_state.apply_battle_outcome(
+            0,
+            1,
+            &BattleOutcome {
+                attacker_won: true,
+                transferred: 2,
+                attacker_roll: DiceRoll::from_slice(&[6, 5, 4]),
+                defender_roll: DiceRoll::from_slice(&[1, 2]),
+            },
+            &rules,
+        );
+
+        let entry = &game_state.game_log[0];
+        assert!(entry.resolved && entry.attacker_won);
+        assert_eq!(entry.defending_player, 1);
+        assert_eq!((entry.region_1_num_dice, entry.region_2_num_dice), (3, 2));
+        assert_eq!(entry.transferred, 2);
+        assert_eq!(game_state.board.regions[1].owner, 0);
+    }
+
+    #[test]
+    fn report_shows_both_sides_and_the_odds() {
+        let rules = GameRules::default();
+        let game_state = game_state();
+        let entry = GameLogEntry {
+            turn_counter: 4,
+            turn_of_player: 0,
+            region_1: 0,
+            region_2: 1,
+            region_1_dice_result: DiceRoll::from_slice(&[2, 3, 1]),
+            region_2_dice_result: DiceRoll::from_slice(&[6, 4]),
+            defending_player: 1,
+            region_1_num_dice: 3,
+            region_2_num_dice: 2,
+            resolved: true,
+            attacker_won: false,
+            transferred: 1,
+        };
+
+        let report = battle_report(
+            &entry,
+            &game_state.board,
+            &PlayerProfiles::default(),
+            &rules,
+        );
+        let probability = attack_win_probability(3, 2, &rules) * 100.0;
+
+        assert_eq!(
+            report,
+            format!(
+                "TURN 4\nPlayer 1: Land 0 (3 dice)\nrolled 2 3 1 = 6\nPlayer 2: Land 1 (2 dice)\nrolled 6 4 = 10\nwin chance {:.0}%\nPlayer 2 holds, 1 dice transferred",
+                probability
+            )
+        );
+        assert_eq!(
+            battle_summary(&entry, &game_state.board),
+            "T4 Land 0 failed on Land 1"
+        );
+    }
+}
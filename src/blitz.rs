@@ -0,0 +1,458 @@
+//! Blitz mode, an experimental alternative to taking turns: every round each player gives a
+//! single attack order in secret, then all the orders resolve together.
+//!
+//! Orders are collected one player at a time, the player giving its order being the player in
+//! turn, so picking regions and the seats work as they do in a classic turn. An attack ordered by
+//! a click or by a seat becomes the order of the player instead of playing out, and ending the
+//! turn orders to hold. Players without any possible attack hold on their own.
+//!
+//! Once every player still in the game gave its order, [`resolve_blitz_round`] resolves them
+//! one by one in initiative order, starting with the player the initiative of the round falls
+//! to, which moves on by one player every round. Clashes are settled by that order: an order
+//! that isn't legal anymore when its turn comes fizzles, because its attacking region was
+//! conquered by an earlier order, or its target already belongs to the player. Reinforcements
+//! are then spread over the board for every player, placing them by hand isn't supported.
+
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::events::{
+    EventBattleResolved, EventBoardChanged, EventGameOver, EventPlayerMoveStart, EventTurnEnd,
+    EventTurnPassed, EventTurnStart,
+};
+use crate::game::{roll_dice, BattleOutcome, GameLogEntry, GameState, RegionId, SelectedRegion};
+use crate::profiles::PlayerProfiles;
+use crate::rules::{GameRules, ReinforcementRule};
+use crate::spectate::SpectatorStream;
+use crate::tiered_prng::PrngEnvResource;
+use crate::ui::StackRankDiceUI;
+
+/// Order of a player for a blitz round
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlitzOrder {
+    Attack {
+        from: RegionId,
+        to: RegionId,
+    },
+    /// The player doesn't attack this round
+    Hold,
+}
+
+/// Orders given for the current round, by player
+#[derive(Resource, Default, Debug)]
+pub struct BlitzRound {
+    orders: Vec<Option<BlitzOrder>>,
+}
+
+impl BlitzRound {
+    /// Forgets the orders given, for a new round or a fresh game
+    pub fn reset(&mut self) {
+        self.orders.clear();
+    }
+
+    /// Order `player` gave this round, if it gave one yet
+    pub fn order(&self, player: usize) -> Option<BlitzOrder> {
+        self.orders.get(player).copied().flatten()
+    }
+
+    /// Records the order of `player`, unless it already gave one this round. Returns whether the
+    /// order was recorded.
+    pub fn give(&mut self, player: usize, order: BlitzOrder) -> bool {
+        if self.orders.len() <= player {
+            self.orders.resize(player + 1, None);
+        }
+        if self.orders[player].is_some() {
+            return false;
+        }
+
+        self.orders[player] = Some(order);
+        true
+    }
+
+    /// Players still in the game that didn't give their order yet, in initiative order
+    pub fn waiting_for(&self, game_state: &GameState) -> Vec<usize> {
+        initiative(game_state)
+            .into_iter()
+            .filter(|player| self.order(*player).is_none())
+            .collect()
+    }
+}
+
+/// Players still owning a region, in the order their orders resolve this round
+pub fn initiative(game_state: &GameState) -> Vec<usize> {
+    let number_of_players = game_state.number_of_players.max(1);
+
+    (0..number_of_players)
+        .map(|seat| (game_state.turn_counter + seat) % number_of_players)
+        .filter(|player| {
+            game_state
+                .board
+                .regions
+                .iter()
+                .any(|region| region.owner == *player)
+        })
+        .collect()
+}
+
+/// Whether `player` has a region that can attack an opponent
+fn can_attack_any(game_state: &GameState, player: usize, rules: &GameRules) -> bool {
+    let regions = &game_state.board.regions;
+
+    regions
+        .iter()
+        .filter(|region_1| region_1.owner == player)
+        .any(|region_1| {
+            regions
+                .iter()
+                .any(|region_2| game_state.can_attack(region_1, region_2, rules))
+        })
+}
+
+/// An attack order once the round was resolved
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlitzClash {
+    pub player: usize,
+    pub from: RegionId,
+    pub to: RegionId,
+    /// Owner of the target when the order resolved
+    pub defending_player: usize,
+    /// Outcome of the battle, none when the order fizzled
+    pub outcome: Option<BattleOutcome>,
+}
+
+/// Resolves the orders of `round` in initiative order, rolling the dice of every battle with
+/// `rng`, then ends the round. Returns the attack orders, in the order they resolved.
+pub fn resolve_blitz_round(
+    game_state: &mut GameState,
+    round: &BlitzRound,
+    rules: &GameRules,
+    rng: &mut impl Rng,
+) -> Vec<BlitzClash> {
+    let mut clashes = Vec::new();
+
+    for player in initiative(game_state) {
+        let (from, to) = match round.order(player) {
+            Some(BlitzOrder::Attack { from, to }) => (from, to),
+            _ => continue,
+        };
+
+        let regions = &game_state.board.regions;
+        let (region_1, region_2) = match (regions.get(from), regions.get(to)) {
+            (Some(region_1), Some(region_2)) => (region_1, region_2),
+            _ => continue,
+        };
+        let mut clash = BlitzClash {
+            player,
+            from,
+            to,
+            defending_player: region_2.owner,
+            outcome: None,
+        };
+        if region_1.owner != player || !game_state.can_attack(region_1, region_2, rules) {
+            clashes.push(clash);
+            continue;
+        }
+
+        let attacker_roll = roll_dice(rules.attacker_roll_size(region_1.num_dice), rng);
+        let defender_roll = roll_dice(rules.defender_roll_size(region_2.num_dice), rng);
+        game_state.log_attack(GameLogEntry {
+            turn_counter: game_state.turn_counter,
+            turn_of_player: player,
+            region_1: from,
+            region_2: to,
+            region_1_dice_result: attacker_roll.clone(),
+            region_2_dice_result: defender_roll.clone(),
+            ..Default::default()
+        });
+        clash.outcome =
+            Some(game_state.resolve_battle(from, to, &attacker_roll, &defender_roll, rules, rng));
+        clashes.push(clash);
+
+        if game_state.winner().is_some() {
+            return clashes;
+        }
+    }
+
+    end_round(game_state, rules);
+    clashes
+}
+
+/// Spreads the reinforcements of every player and hands the initiative to the next player
+fn end_round(game_state: &mut GameState, rules: &GameRules) {
+    if rules.reinforcements != ReinforcementRule::None {
+        for player in initiative(game_state) {
+            game_state.turn_of_player = player;
+            game_state.start_reinforcement();
+            game_state.distribute_reinforcements(rules);
+            if let Some(reserve) = game_state.reserves.get_mut(player) {
+                *reserve = (*reserve).min(rules.reserve_cap);
+            }
+        }
+    }
+
+    game_state.advance_turn();
+    if let Some(first) = initiative(game_state).first() {
+        game_state.turn_of_player = *first;
+    }
+}
+
+/// Runs the systems playing classic turns, which blitz rounds replace. The host plays the
+/// battles of a game followed from another machine.
+pub(crate) fn classic_turns(
+    rules: Res<GameRules>,
+    stream: Option<Res<SpectatorStream>>,
+) -> ShouldRun {
+    match rules.blitz || stream.is_some() {
+        true => ShouldRun::No,
+        false => ShouldRun::Yes,
+    }
+}
+
+/// Runs the systems of blitz rounds
+pub(crate) fn blitz_rounds(
+    rules: Res<GameRules>,
+    stream: Option<Res<SpectatorStream>>,
+) -> ShouldRun {
+    match rules.blitz && stream.is_none() {
+        true => ShouldRun::Yes,
+        false => ShouldRun::No,
+    }
+}
+
+/// Takes the attack ordered or the end of turn asked by the player in turn as its order, and
+/// hands the turn to the next player to give one. Resolves the round once every player ordered.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn collect_blitz_orders(
+    mut event_player_move_start_reader: EventReader<EventPlayerMoveStart>,
+    mut event_turn_passed_reader: EventReader<EventTurnPassed>,
+    mut round: ResMut<BlitzRound>,
+    mut game_state: ResMut<GameState>,
+    rules: Res<GameRules>,
+    mut env_prng: ResMut<PrngEnvResource>,
+    mut selected_region: ResMut<SelectedRegion>,
+    mut event_battle_resolved_writer: EventWriter<EventBattleResolved>,
+    mut event_game_over_writer: EventWriter<EventGameOver>,
+    mut event_turn_end_writer: EventWriter<EventTurnEnd>,
+    mut event_turn_start_writer: EventWriter<EventTurnStart>,
+    mut event_board_changed_writer: EventWriter<EventBoardChanged>,
+) {
+    if game_state.winner().is_some() {
+        return;
+    }
+
+    let mut ordered = false;
+    for event in event_player_move_start_reader.iter() {
+        let player = match game_state.board.regions.get(event.region_1) {
+            Some(region) => region.owner,
+            None => continue,
+        };
+        let order = BlitzOrder::Attack {
+            from: event.region_1,
+            to: event.region_2,
+        };
+        ordered |= round.give(player, order);
+    }
+    for event in event_turn_passed_reader.iter() {
+        ordered |= round.give(event.player, BlitzOrder::Hold);
+    }
+    if ordered {
+        selected_region.deselect();
+    }
+
+    // players without any possible attack hold without being asked
+    let next = round.waiting_for(&game_state).into_iter().find(|player| {
+        if can_attack_any(&game_state, *player, &rules) {
+            return true;
+        }
+        round.give(*player, BlitzOrder::Hold);
+        false
+    });
+
+    if let Some(next) = next {
+        if game_state.turn_of_player != next {
+            game_state.turn_of_player = next;
+            event_board_changed_writer.send(EventBoardChanged);
+        }
+        return;
+    }
+
+    let player = game_state.turn_of_player;
+    let turn = game_state.turn_counter;
+    let clashes = resolve_blitz_round(&mut game_state, &round, &rules, &mut env_prng.rng);
+    round.reset();
+
+    for clash in clashes {
+        if let Some(outcome) = clash.outcome {
+            event_battle_resolved_writer.send(EventBattleResolved {
+                region_1: clash.from,
+                region_2: clash.to,
+                player_1: clash.player,
+                player_2: clash.defending_player,
+                region_1_dice_result: outcome.attacker_roll,
+                region_2_dice_result: outcome.defender_roll,
+                attacker_won: outcome.attacker_won,
+                transferred: outcome.transferred,
+                turn,
+            });
+        }
+    }
+
+    match game_state.winner() {
+        Some(winner) => event_game_over_writer.send(EventGameOver { winner }),
+        None => {
+            event_turn_end_writer.send(EventTurnEnd { player });
+            event_turn_start_writer.send(EventTurnStart {
+                player: game_state.turn_of_player,
+                turn: game_state.turn_counter,
+            });
+        }
+    }
+    event_board_changed_writer.send(EventBoardChanged);
+}
+
+/// Where every player stands in giving its order, without telling what it ordered
+pub fn pending_orders_text(
+    round: &BlitzRound,
+    game_state: &GameState,
+    profiles: &PlayerProfiles,
+) -> String {
+    let players: Vec<String> = initiative(game_state)
+        .into_iter()
+        .map(|player| {
+            let status = match round.order(player) {
+                Some(_) => "READY",
+                None if player == game_state.turn_of_player => "ORDERING",
+                None => "WAITING",
+            };
+            format!("{} {}", profiles.name(player), status)
+        })
+        .collect();
+
+    format!("ORDERS: {}", players.join(", "))
+}
+
+/// Text area with the orders of the round
+#[derive(Component)]
+pub(crate) struct BlitzOrdersText;
+
+pub(crate) fn setup_blitz_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 24.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(90.0),
+                    right: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Name::new("Blitz Orders Text"))
+        .insert(BlitzOrdersText)
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn blitz_orders_text_update(
+    round: Res<BlitzRound>,
+    game_state: Res<GameState>,
+    rules: Res<GameRules>,
+    profiles: Res<PlayerProfiles>,
+    mut query: Query<&mut Text, With<BlitzOrdersText>>,
+) {
+    for mut text in &mut query {
+        text.sections[0].value = match rules.blitz && game_state.winner().is_none() {
+            true => pending_orders_text(&round, &game_state, &profiles),
+            false => String::new(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+    use crate::map_file::MapFile;
+
+    fn blitz_game_state() -> GameState {
+        let map = MapFile::parse(
+            "players 3\nregion 0 8 0,0\nregion 1 8 1,0\nregion 2 1 2,0\nregion 2 1 9,9\n",
+        )
+        .unwrap();
+        GameState {
+            board: map.board,
+            number_of_players: 3,
+            turn_counter: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn initiative_moves_on_every_round() {
+        let mut game_state = blitz_game_state();
+
+        assert_eq!(initiative(&game_state), vec![1, 2, 0]);
+        game_state.turn_counter = 2;
+        assert_eq!(initiative(&game_state), vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn orders_resolve_in_initiative_order() {
+        let mut game_state = blitz_game_state();
+        let rules = GameRules::default();
+        let mut round = BlitzRound::default();
+
+        assert!(round.give(0, BlitzOrder::Attack { from: 0, to: 1 }));
+        assert!(round.give(1, BlitzOrder::Attack { from: 1, to: 2 }));
+        assert!(!round.give(1, BlitzOrder::Hold));
+        assert_eq!(round.waiting_for(&game_state), vec![2]);
+        round.give(2, BlitzOrder::Attack { from: 2, to: 1 });
+
+        let mut rng = ChaCha20Rng::seed_from_u64(3);
+        let clashes = resolve_blitz_round(&mut game_state, &round, &rules, &mut rng);
+
+        let players: Vec<usize> = clashes.iter().map(|clash| clash.player).collect();
+        assert_eq!(players, vec![1, 2, 0]);
+        // eight dice always beat a single one: the region of player 2 fell to the first order,
+        // its own order fizzled
+        assert!(clashes[0].outcome.as_ref().unwrap().attacker_won);
+        assert_eq!(clashes[1].outcome, None);
+        assert_eq!(
+            game_state.game_log.len(),
+            clashes
+                .iter()
+                .filter(|clash| clash.outcome.is_some())
+                .count()
+        );
+        assert_eq!(game_state.turn_counter, 2);
+        assert_eq!(game_state.turn_of_player, initiative(&game_state)[0]);
+        assert!(game_state
+            .board
+            .regions
+            .iter()
+            .all(|region| !region.has_moved));
+    }
+
+    #[test]
+    fn pending_orders_dont_tell_the_order() {
+        let game_state = blitz_game_state();
+        let mut round = BlitzRound::default();
+        round.give(2, BlitzOrder::Attack { from: 2, to: 1 });
+
+        assert_eq!(
+            pending_orders_text(&round, &game_state, &PlayerProfiles::default()),
+            "ORDERS: Player 2 WAITING, Player 3 READY, Player 1 ORDERING"
+        );
+    }
+}
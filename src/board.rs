@@ -1,18 +1,25 @@
+use std::collections::HashMap;
+
 use rand::Rng;
 
 use bevy::{
+    ecs::system::SystemParam,
     prelude::*,
     render::{mesh::Indices, render_resource::PrimitiveTopology},
 };
 
 use bevy_mod_outline::*;
 use bevy_mod_picking::PickableBundle;
+use bevy_mod_raycast::SimplifiedMesh;
 
-use crate::geometry::{center, flat_hexagon_points};
+use crate::geometry::{center, flat_hexagon_ring, hex_tile_buffers, MeshBuffers};
 use crate::hex::HexCoord;
+use crate::memory_budget::MemoryBudget;
+use crate::profiles::{DiceSkin, PlayerProfiles};
 use crate::tiered_prng::PrngMapResource;
+use crate::ui::BoardLabel;
 use crate::{
-    game::{GameState, Region},
+    game::{Board, GameState, Region, RegionId, SelectedRegion, TurnPhase},
     geometry,
 };
 
@@ -27,66 +34,63 @@ pub(crate) const PLAYER_COLORS: [Color; 8] = [
     Color::OLIVE,
 ];
 
-/// Generate a single hex mesh
-fn generate_hex_region_mesh(region: &Region) -> Mesh {
-    let hexes = region.hexes.clone();
-    let center = center(1.0, &region.center_hex(), &[0.0, 0.0, 0.0]);
+/// Color of the neutral regions
+pub(crate) const NEUTRAL_COLOR: Color = Color::GRAY;
 
-    let mut pts: Vec<[f32; 3]> = vec![];
-    let mut normals: Vec<[f32; 3]> = vec![];
-    let mut uvs: Vec<[f32; 2]> = vec![];
-    let mut indices: Vec<u32> = vec![];
+/// Thickness of the region tiles, the bottom face sits this far below the top one
+const TILE_THICKNESS: f32 = 0.0001;
 
-    for (hex_num, hex) in hexes.iter().enumerate() {
-        let c = HexCoord::new(hex.0, hex.1);
-        let hex_num = hex_num as u32;
+fn mesh_from_buffers(buffers: MeshBuffers) -> Mesh {
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_indices(Some(Indices::U32(buffers.indices)));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, buffers.positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, buffers.normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, buffers.uvs);
+    mesh
+}
 
-        // Populate the points for the top face, as a slightly scaled hexagon
-        flat_hexagon_points(&mut pts, 1.0, &c);
-        for _ in 0..9 {
-            normals.push([0., 1., 0.]);
-        }
-        for i in 0..=6 {
-            indices.push(18 * hex_num); // Center
-            indices.push(18 * hex_num + i + 1); // Point       East           North-east
-            indices.push(18 * hex_num + i + 2); // Next point  North-east     North-west
-        }
+/// Generate the mesh of a region, centered on its center hex
+fn generate_hex_region_mesh(region: &Region) -> Mesh {
+    let center = center(1.0, &region.center_hex(), &[0.0, 0.0, 0.0]);
+    let hexes: Vec<HexCoord> = region
+        .hexes
+        .iter()
+        .map(|hex| HexCoord::new(hex.0, hex.1))
+        .collect();
 
-        // Adjust location and duplicate points with an offset as a bottom face
-        for p in pts.len() - 9..pts.len() {
-            pts[p][0] -= center[0];
-            pts[p][1] -= center[1];
-            pts[p][2] -= center[2];
-            pts.push([pts[p][0], pts[p][1] - 0.0001, pts[p][2]]);
-        }
-        for _ in 0..9 {
-            normals.push([0., -1., 0.]);
-        }
+    mesh_from_buffers(hex_tile_buffers(
+        &hexes,
+        1.0,
+        TILE_THICKNESS,
+        &[-center[0], -center[1], -center[2]],
+    ))
+}
 
-        // Populate indices for bottom
-        for i in 0..=6 {
-            indices.push(18 * hex_num + 9); // Center
-            indices.push(18 * hex_num + i + 1 + 9); // Point       East           North-east
-            indices.push(18 * hex_num + i + 2 + 9); // Next point  North-east     North-west
-        }
+/// Generate the mesh regions are picked with: only the top face of every hex, a fan of six
+/// triangles, instead of the beveled prisms of the rendered mesh
+fn generate_hex_region_pick_mesh(region: &Region) -> Mesh {
+    let center = center(1.0, &region.center_hex(), &[0.0, 0.0, 0.0]);
+    let offset = [-center[0], -center[1], -center[2]];
 
-        // Populate indices sides
-        for i in 0..=6 {
-            indices.push(18 * hex_num + i + 2);
-            indices.push(18 * hex_num + i + 1 + 9);
-            indices.push(18 * hex_num + i + 2 + 9);
+    let mut pts: Vec<[f32; 3]> = vec![];
+    let mut indices: Vec<u32> = vec![];
 
-            indices.push(18 * hex_num + i + 2);
-            indices.push(18 * hex_num + i + 1);
-            indices.push(18 * hex_num + i + 1 + 9);
-        }
+    for hex in region.hexes.iter() {
+        let c = HexCoord::new(hex.0, hex.1);
+        let first = pts.len() as u32;
 
-        // Finally, UVs
-        for _ in 0..18 {
-            uvs.push([1.0, 1.0]);
+        pts.push(geometry::center(1.0, &c, &offset));
+        flat_hexagon_ring(&mut pts, 1.0, &c, &offset);
+        for i in 0..6 {
+            indices.push(first); // Center
+            indices.push(first + i + 1); // Point
+            indices.push(first + i + 2); // Next point
         }
     }
 
+    let normals = vec![[0., 1., 0.]; pts.len()];
+    let uvs = vec![[1.0, 1.0]; pts.len()];
+
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
     mesh.set_indices(Some(Indices::U32(indices)));
     mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, pts);
@@ -98,57 +102,108 @@ fn generate_hex_region_mesh(region: &Region) -> Mesh {
 #[derive(Component)]
 pub(crate) struct StackRankDiceGameBoardElement;
 
-pub(crate) fn draw_board(
-    asset_server: Res<AssetServer>,
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut map_prng: ResMut<PrngMapResource>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    game_state: ResMut<GameState>,
-) {
-    let board = game_state.board.clone();
+/// Die stacked on a region
+#[derive(Component)]
+pub(crate) struct RegionDie(pub RegionId);
 
-    // Draw board
-    for region in board.regions.iter() {
-        let color = PLAYER_COLORS[region.owner as usize];
+/// Light next to a region
+#[derive(Component)]
+pub(crate) struct RegionLight;
 
-        let center_coord = center(1.0, &region.center_hex(), &[0.0, 0.0, 0.0]);
+/// Intensity of the lights next to the regions
+pub(crate) const REGION_LIGHT_INTENSITY: f32 = 100.0;
 
-        #[allow(clippy::search_is_some)]
-        let is_region_playable = game_state
-            .game_log
-            .iter()
-            .find(|gl| {
-                gl.turn_counter == game_state.turn_counter
-                    && gl.region_1.id == region.id
-                    && region.owner == game_state.turn_of_player
-            })
-            .is_none();
+/// Whether the player in turn can still act on `region`: attack with it, or reinforce it
+pub(crate) fn is_region_playable(region: &Region, game_state: &GameState) -> bool {
+    match game_state.turn_phase {
+        TurnPhase::Attack => !(region.has_moved && region.owner == game_state.turn_of_player),
+        TurnPhase::Reinforcement => region.owner == game_state.turn_of_player,
+    }
+}
 
-        let material = match is_region_playable {
-            true => materials.add(StandardMaterial {
-                base_color: color,
-                metallic: 0.0,
-                reflectance: 0.0,
-                ..default()
-            }),
-            _ => materials.add(StandardMaterial {
-                base_color: color + Color::rgba(0.2, 0.2, 0.2, 0.9),
-                metallic: 0.0,
-                reflectance: 0.0,
-                ..default()
-            }),
-        };
+/// Material of the tiles of `region`, in the color of its owner, washed out when the player in
+/// turn can't act on it
+pub(crate) fn region_material(
+    region: &Region,
+    game_state: &GameState,
+    profiles: &PlayerProfiles,
+) -> StandardMaterial {
+    let color = profiles.color(region.owner);
+    StandardMaterial {
+        base_color: match is_region_playable(region, game_state) {
+            true => color,
+            false => color + Color::rgba(0.2, 0.2, 0.2, 0.9),
+        },
+        metallic: 0.0,
+        reflectance: 0.0,
+        ..default()
+    }
+}
+
+/// Material of the dice of a player with the `skin` they picked. Skins tint the textures of the
+/// classic dice, which keeps the pips black. Only the base color texture fits a low `budget`.
+pub(crate) fn dice_material(
+    asset_server: &AssetServer,
+    skin: DiceSkin,
+    color: Color,
+    budget: MemoryBudget,
+) -> StandardMaterial {
+    let base_color_texture = Some(asset_server.load("models/dice/textures/Dice_baseColor.png"));
+    let classic = match budget.detailed_textures() {
+        true => StandardMaterial {
+            base_color_texture,
+            normal_map_texture: Some(asset_server.load("models/dice/textures/Dice_normal.png")),
+            metallic_roughness_texture: Some(
+                asset_server.load("models/dice/textures/Dice_metallicRoughness.png"),
+            ),
+            ..default()
+        },
+        false => StandardMaterial {
+            base_color_texture,
+            ..default()
+        },
+    };
+
+    match skin {
+        DiceSkin::Classic => classic,
+        DiceSkin::Tinted => StandardMaterial {
+            base_color: color,
+            ..classic
+        },
+        DiceSkin::Gilded => StandardMaterial {
+            base_color: Color::GOLD,
+            metallic: 0.9,
+            perceptual_roughness: 0.3,
+            ..classic
+        },
+    }
+}
+
+/// Spawns the tiles of every region, pickable when the player in turn can act on them, and the
+/// rings marking objective regions. A low `budget` picks the tiles against their rendered mesh
+/// rather than a flat picking mesh of their own.
+pub(crate) fn spawn_region_tiles(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    rng: &mut impl Rng,
+    game_state: &GameState,
+    profiles: &PlayerProfiles,
+    budget: MemoryBudget,
+) {
+    for region in game_state.board.regions.iter() {
+        let center_coord = center(1.0, &region.center_hex(), &[0.0, 0.0, 0.0]);
+        let material = materials.add(region_material(region, game_state, profiles));
 
         let mut mesh = generate_hex_region_mesh(region);
         mesh.generate_outline_normals().unwrap();
         let mesh = meshes.add(mesh);
         // Theese micro-height differences are to make otline rendering visible.
         // Otherwise tiles with the same height will be rendered as one.
-        let height: f32 = 1.0 + map_prng.rng.gen_range(0.0..=0.0001);
+        let height: f32 = 1.0 + rng.gen_range(0.0..=0.0001);
         let mut bundle_command = commands.spawn(PbrBundle {
-            mesh: mesh.clone(),
-            material: material.clone(),
+            mesh,
+            material,
             transform: Transform::from_translation(Vec3::new(
                 center_coord[0],
                 center_coord[1] + height,
@@ -170,27 +225,50 @@ pub(crate) fn draw_board(
             .insert(Name::new("Hex"))
             .insert(StackRankDiceGameBoardElement);
 
-        if is_region_playable {
+        if is_region_playable(region, game_state) {
             bundle_command.insert(PickableBundle::default());
+            // Rays are cast against the flat top faces, the detailed mesh is only rendered
+            if budget.pick_meshes() {
+                bundle_command.insert(SimplifiedMesh {
+                    mesh: meshes.add(generate_hex_region_pick_mesh(region)),
+                });
+            }
         }
-    }
 
-    // Place dice on areas
-    let dice_mesh_handle = asset_server.load("models/dice/scene.gltf#Mesh0/Primitive0");
-    let material_handle = materials.add(StandardMaterial {
-        base_color_texture: Some(asset_server.load("models/dice/textures/Dice_baseColor.png")),
-        normal_map_texture: Some(asset_server.load("models/dice/textures/Dice_normal.png")),
-        metallic_roughness_texture: Some(
-            asset_server.load("models/dice/textures/Dice_metallicRoughness.png"),
-        ),
-        ..default()
-    });
+        // Ring around the dice of objective regions
+        if game_state.board.objectives.contains(&region.id) {
+            commands
+                .spawn(PbrBundle {
+                    mesh: meshes.add(Mesh::from(shape::Torus {
+                        radius: 0.8,
+                        ring_radius: 0.08,
+                        ..default()
+                    })),
+                    material: materials.add(StandardMaterial {
+                        base_color: Color::GOLD,
+                        emissive: Color::GOLD,
+                        ..default()
+                    }),
+                    transform: Transform::from_xyz(
+                        center_coord[0],
+                        center_coord[1] + height + 0.05,
+                        center_coord[2],
+                    ),
+                    ..default()
+                })
+                .insert(Name::new("ObjectiveMarker"))
+                .insert(StackRankDiceGameBoardElement);
+        }
+    }
+}
 
-    for region in board.regions.iter() {
-        let center_hex = region.center_hex();
-        let pos = geometry::center(1.0, &center_hex, &[0., 0.0, 0.]);
+/// Positions of the dice of `region`: a stack of four on its center hex, the next ones in a
+/// second stack behind it
+fn dice_positions(region: &Region) -> Vec<Vec3> {
+    let pos = geometry::center(1.0, &region.center_hex(), &[0., 0.0, 0.]);
 
-        for i in 0..region.num_dice {
+    (0..region.num_dice)
+        .map(|i| {
             let mut y_pos = 1.0 + pos[1] + 0.383 + (i as f32) * (2.0 * 0.383);
             let mut z_pos = pos[2];
             if i > 3 {
@@ -202,29 +280,201 @@ pub(crate) fn draw_board(
                 z_pos -= 0.383;
             }
 
-            commands
-                .spawn(PbrBundle {
-                    mesh: dice_mesh_handle.clone(),
-                    material: material_handle.clone(),
-                    transform: Transform::from_xyz(pos[0], y_pos, z_pos)
-                        .with_scale(Vec3::splat(0.4)),
-                    ..default()
-                })
-                .insert(OutlineStencil { offset: 1.0 })
-                .insert(Name::new("Dice"))
-                .insert(StackRankDiceGameBoardElement);
+            Vec3::new(pos[0], y_pos, z_pos)
+        })
+        .collect()
+}
+
+/// Height of the count of the dice of a region whose stack is cut short, above a full stack
+const DICE_COUNT_LABEL_HEIGHT: f32 = 4.0;
+
+/// Spawns the count of the dice of `region` over its stack, cut short by the memory budget
+fn spawn_dice_count_label(commands: &mut Commands, asset_server: &AssetServer, region: &Region) {
+    let [x, _, z] = center(1.0, &region.center_hex(), &[0.0; 3]);
+
+    commands
+        .spawn(
+            TextBundle::from_section(
+                region.num_dice.to_string(),
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 28.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                ..default()
+            }),
+        )
+        .insert(Name::new("Dice Count Label"))
+        .insert(BoardLabel {
+            world_position: Vec3::new(x, DICE_COUNT_LABEL_HEIGHT, z),
+        })
+        .insert(StackRankDiceGameBoardElement);
+}
+
+/// Spawns the dice stacked on every region, as many as `budget` allows
+pub(crate) fn spawn_dice_stacks(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    materials: &mut Assets<StandardMaterial>,
+    board: &Board,
+    profiles: &PlayerProfiles,
+    budget: MemoryBudget,
+) {
+    let dice_mesh_handle = asset_server.load("models/dice/scene.gltf#Mesh0/Primitive0");
+    let mut material_handles: HashMap<usize, Handle<StandardMaterial>> = HashMap::new();
+
+    for region in board.regions.iter() {
+        if budget.dice_shown(region.num_dice) < region.num_dice {
+            spawn_dice_count_label(commands, asset_server, region);
         }
+    }
+
+    for (region, owner, position) in board.regions.iter().flat_map(|region| {
+        dice_positions(region)
+            .into_iter()
+            .take(budget.dice_shown(region.num_dice))
+            .map(move |position| (region.id, region.owner, position))
+    }) {
+        let material_handle = material_handles
+            .entry(owner)
+            .or_insert_with(|| {
+                materials.add(dice_material(
+                    asset_server,
+                    profiles.dice_skin(owner),
+                    profiles.color(owner),
+                    budget,
+                ))
+            })
+            .clone();
+        commands
+            .spawn(PbrBundle {
+                mesh: dice_mesh_handle.clone(),
+                material: material_handle,
+                transform: Transform::from_translation(position).with_scale(Vec3::splat(0.4)),
+                ..default()
+            })
+            .insert(OutlineStencil { offset: 1.0 })
+            .insert(RegionDie(region))
+            .insert(Name::new("Dice"))
+            .insert(StackRankDiceGameBoardElement);
+    }
+}
+
+/// Spawns a light next to every region
+pub(crate) fn spawn_lights(commands: &mut Commands, board: &Board) {
+    for region in board.regions.iter() {
+        let pos = geometry::center(1.0, &region.center_hex(), &[0., 0.0, 0.]);
 
         commands
             .spawn(PointLightBundle {
                 point_light: PointLight {
-                    intensity: 100.0,
+                    intensity: REGION_LIGHT_INTENSITY,
                     ..Default::default()
                 },
                 transform: Transform::from_xyz(pos[0] + 2.0, 2.0, pos[2]),
                 ..Default::default()
             })
             .insert(Name::new("RegionLight"))
+            .insert(RegionLight)
             .insert(StackRankDiceGameBoardElement);
     }
 }
+
+/// What drawing the board takes, for the systems drawing it again over the last one
+#[derive(SystemParam)]
+pub(crate) struct BoardDrawer<'w, 's> {
+    commands: Commands<'w, 's>,
+    asset_server: Res<'w, AssetServer>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    /// Generator of the looks of the tiles, restored by loaded games
+    pub(crate) map_prng: ResMut<'w, PrngMapResource>,
+    materials: ResMut<'w, Assets<StandardMaterial>>,
+    budget: Res<'w, MemoryBudget>,
+    selected_region: ResMut<'w, SelectedRegion>,
+    board_elements_query: Query<'w, 's, Entity, With<StackRankDiceGameBoardElement>>,
+}
+
+impl<'w, 's> BoardDrawer<'w, 's> {
+    /// Spawns every element of the board: region tiles, dice and lights
+    pub(crate) fn draw(&mut self, game_state: &GameState, profiles: &PlayerProfiles) {
+        spawn_region_tiles(
+            &mut self.commands,
+            &mut self.meshes,
+            &mut self.materials,
+            &mut self.map_prng.rng,
+            game_state,
+            profiles,
+            *self.budget,
+        );
+        spawn_dice_stacks(
+            &mut self.commands,
+            &self.asset_server,
+            &mut self.materials,
+            &game_state.board,
+            profiles,
+            *self.budget,
+        );
+        spawn_lights(&mut self.commands, &game_state.board);
+    }
+
+    /// Despawns the board drawn and the selection made on it, and draws `game_state` instead
+    pub(crate) fn redraw(&mut self, game_state: &GameState, profiles: &PlayerProfiles) {
+        for entity in self.board_elements_query.iter() {
+            self.commands.entity(entity).despawn_recursive();
+        }
+
+        self.selected_region.deselect();
+        self.draw(game_state, profiles);
+    }
+}
+
+/// Draws the board the game starts on
+pub(crate) fn draw_board(
+    mut board_drawer: BoardDrawer,
+    game_state: Res<GameState>,
+    profiles: Res<PlayerProfiles>,
+) {
+    board_drawer.draw(&game_state, &profiles);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+
+    #[test]
+    fn dice_stack_in_two_columns() {
+        let map = MapFile::parse("players 2\nregion 0 6 0,0\nregion 1 2 5,5\n").unwrap();
+
+        let positions = dice_positions(&map.board.regions[0]);
+        assert_eq!(positions.len(), 6);
+        assert!(positions[..4].windows(2).all(|pair| pair[0].y < pair[1].y));
+        assert!(positions[..4].iter().all(|p| p.z == positions[0].z));
+        assert!(positions[4..].iter().all(|p| p.z > positions[0].z));
+
+        let positions = dice_positions(&map.board.regions[1]);
+        assert_eq!(positions.len(), 2);
+        assert!(positions.iter().all(|p| p.x == positions[0].x));
+    }
+
+    #[test]
+    fn pick_mesh_only_has_top_faces() {
+        let map = MapFile::parse("players 2\nregion 0 1 0,0 1,0 0,1\nregion 1 1 5,5\n").unwrap();
+        let region = &map.board.regions[0];
+
+        let pick_mesh = generate_hex_region_pick_mesh(region);
+        let render_mesh = generate_hex_region_mesh(region);
+        assert_eq!(pick_mesh.indices().unwrap().len(), 3 * 6 * 3);
+        assert!(pick_mesh.indices().unwrap().len() < render_mesh.indices().unwrap().len() / 3);
+
+        let positions = pick_mesh
+            .attribute(Mesh::ATTRIBUTE_POSITION)
+            .unwrap()
+            .as_float3()
+            .unwrap();
+        assert!(positions.iter().all(|p| p[1] == 0.0));
+    }
+}
@@ -0,0 +1,376 @@
+//! Bug reports: everything a maintainer needs to replay a game that went wrong, in one zip.
+//!
+//! Pressing [`REPORT_KEY`] saves a zip in [`BUG_REPORTS_DIR`] of the data directory with:
+//!
+//! - `report.txt`: the stamp of the game (release, seeds, rules hash) and how to replay it
+//! - `rules.txt`: the rules played
+//! - `moves.replay`: the move log of the game in progress (see [`crate::autosave`])
+//! - `log.txt`: the last [`RECENT_LOG_LINES`] lines of the log
+//! - `board.png`: the frame drawn in the window when the report was asked for
+//!
+//! The frame is captured by [`crate::screenshot`] and read back a frame or two later, the report
+//! is saved once it arrived, or without it when no frame could be captured. A link opening a
+//! GitHub issue prefilled with the stamp is logged next to the path of the zip.
+//!
+//! The recent log lines are kept by a layer of the log subscriber, installed by
+//! [`init_logging`] in place of the one of Bevy's `LogPlugin`.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Cursor, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::autosave::Autosave;
+use crate::rules::GameRules;
+use crate::screenshot::{Frame, Screenshots};
+use crate::stamp::ExportStamp;
+use crate::storage::{storage_dir, Location};
+use crate::tiered_prng::PrngResource;
+use crate::ui::spawn_toast;
+
+pub const BUG_REPORTS_DIR: &str = "bug_reports";
+
+/// Log lines kept for bug reports
+pub const RECENT_LOG_LINES: usize = 200;
+
+const REPORT_KEY: KeyCode = KeyCode::F12;
+
+/// Seconds a report waits for the frame before it is saved without it
+const SCREENSHOT_TIMEOUT_SECS: f32 = 2.0;
+
+/// Last lines of the log, shared with the log subscriber
+#[derive(Resource, Clone, Default)]
+pub struct RecentLogs {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl RecentLogs {
+    /// Keeps `line`, dropping the oldest line past [`RECENT_LOG_LINES`]
+    pub fn push(&self, line: String) {
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == RECENT_LOG_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod subscriber {
+    use std::fmt::{self, Write as _};
+
+    use bevy::utils::tracing::field::{Field, Visit};
+    use bevy::utils::tracing::{Event, Subscriber};
+    use tracing_subscriber::layer::{Context, Layer};
+
+    use super::RecentLogs;
+
+    /// Layer keeping every event logged in [`RecentLogs`]
+    pub(super) struct RecentLogLayer {
+        pub(super) logs: RecentLogs,
+    }
+
+    impl<S: Subscriber> Layer<S> for RecentLogLayer {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let mut message = MessageVisitor::default();
+            event.record(&mut message);
+            let metadata = event.metadata();
+            self.logs.push(format!(
+                "{} {}: {}",
+                metadata.level(),
+                metadata.target(),
+                message.0
+            ));
+        }
+    }
+
+    /// Message of an event followed by its other fields
+    #[derive(Default)]
+    struct MessageVisitor(String);
+
+    impl Visit for MessageVisitor {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == "message" {
+                let _ = write!(self.0, "{:?}", value);
+            } else {
+                let _ = write!(self.0, " {}={:?}", field.name(), value);
+            }
+        }
+    }
+}
+
+/// Installs the log subscriber of the game: the one of Bevy's `LogPlugin`, printing to the
+/// terminal and filtered by `RUST_LOG`, plus a layer keeping the recent lines for bug reports.
+/// The `LogPlugin` must be disabled.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn init_logging() -> RecentLogs {
+    use tracing_subscriber::prelude::*;
+    use tracing_subscriber::{fmt, EnvFilter, Registry};
+
+    let logs = RecentLogs::default();
+    // the default filter of the LogPlugin
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info,wgpu=error"));
+    let subscriber = Registry::default()
+        .with(filter)
+        .with(fmt::layer())
+        .with(subscriber::RecentLogLayer { logs: logs.clone() });
+
+    if let Err(err) = tracing_log::LogTracer::init() {
+        eprintln!(
+            "Failed to forward log records to the log subscriber: {}",
+            err
+        );
+    }
+    if bevy::utils::tracing::subscriber::set_global_default(subscriber).is_err() {
+        eprintln!("A log subscriber is already set, bug reports won't carry the log");
+    }
+
+    logs
+}
+
+/// Contents of a bug report
+pub struct BugReport {
+    pub stamp: ExportStamp,
+    pub rules: GameRules,
+    /// Move log of the game in progress, none for boards that aren't logged
+    pub moves: Option<String>,
+    pub logs: Vec<String>,
+    /// Frame drawn in the window, none when it couldn't be captured
+    pub screenshot: Option<Frame>,
+}
+
+impl BugReport {
+    /// Files of the report, by name in the zip
+    pub fn files(&self) -> Vec<(&'static str, Vec<u8>)> {
+        let readme = format!(
+            "{}\n\nReplay with `cargo run -- --world-seed {} --env-seed {}` and the rules of \
+             rules.txt, then play the moves of moves.replay.\n",
+            self.stamp, self.stamp.world_seed, self.stamp.env_seed
+        );
+        let moves = self.moves.clone().unwrap_or_else(|| {
+            "# no move log, boards loaded from map or scene files aren't logged\n".to_string()
+        });
+        let mut log = self.logs.join("\n");
+        log.push('\n');
+
+        let mut files = vec![
+            ("report.txt", readme.into_bytes()),
+            ("rules.txt", format!("{:#?}\n", self.rules).into_bytes()),
+            ("moves.replay", moves.into_bytes()),
+            ("log.txt", log.into_bytes()),
+        ];
+        if let Some(screenshot) = self.screenshot.as_ref() {
+            files.push(("board.png", screenshot.to_png(&self.stamp)));
+        }
+
+        files
+    }
+
+    /// Saves the report in [`BUG_REPORTS_DIR`] of the data directory
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let dir = storage_dir(Location::Data).join(BUG_REPORTS_DIR);
+        fs::create_dir_all(&dir)?;
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let path = dir.join(format!("bug_report_{}.zip", seconds));
+        fs::write(&path, zip(&self.files())?)?;
+
+        Ok(path)
+    }
+
+    /// Link opening a new issue on the repository of the game, prefilled with the stamp and
+    /// where the report was saved
+    pub fn issue_url(&self, path: &Path) -> String {
+        let body = format!(
+            "What happened:\n\n\nWhat was expected:\n\n\nBug report: {} (please attach it)\n{}\n",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            self.stamp
+        );
+
+        format!(
+            "{}/issues/new?title={}&body={}",
+            env!("CARGO_PKG_REPOSITORY"),
+            percent_encode("Bug report"),
+            percent_encode(&body)
+        )
+    }
+}
+
+/// Encodes `value` for a URL query, leaving only the unreserved characters as they are
+pub fn percent_encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+
+    encoded
+}
+
+/// Zip archive of `files`, compressed with deflate
+pub fn zip(files: &[(&str, Vec<u8>)]) -> io::Result<Vec<u8>> {
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, data) in files {
+        archive.start_file(*name, options)?;
+        archive.write_all(data)?;
+    }
+
+    Ok(archive.finish()?.into_inner())
+}
+
+/// Bug report waiting for the frame it was asked on
+#[derive(Resource)]
+pub(crate) struct PendingBugReport {
+    report: BugReport,
+    timeout: Timer,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn report_issue(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    autosave: Res<Autosave>,
+    logs: Option<Res<RecentLogs>>,
+    prng: Res<PrngResource>,
+    rules: Res<GameRules>,
+    screenshots: Option<ResMut<Screenshots>>,
+    pending: Option<Res<PendingBugReport>>,
+) {
+    if !keys.just_pressed(REPORT_KEY) || pending.is_some() {
+        return;
+    }
+
+    if let Some(mut screenshots) = screenshots {
+        screenshots.ask();
+    }
+    commands.insert_resource(PendingBugReport {
+        report: BugReport {
+            stamp: ExportStamp::new(&prng, &rules),
+            rules: rules.clone(),
+            moves: autosave.move_log(),
+            logs: logs.map(|logs| logs.lines()).unwrap_or_default(),
+            screenshot: None,
+        },
+        timeout: Timer::from_seconds(SCREENSHOT_TIMEOUT_SECS, TimerMode::Once),
+    });
+}
+
+/// Saves the pending bug report once its frame was captured, or without it once the capture
+/// timed out or when there is no window to capture
+pub(crate) fn save_bug_report(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    screenshots: Option<ResMut<Screenshots>>,
+    pending: Option<ResMut<PendingBugReport>>,
+) {
+    let mut pending = match pending {
+        Some(pending) => pending,
+        None => return,
+    };
+
+    pending.timeout.tick(time.delta());
+    let (screenshot, waiting) = match screenshots {
+        Some(mut screenshots) => (screenshots.take(), !pending.timeout.finished()),
+        None => (None, false),
+    };
+    if screenshot.is_none() && waiting {
+        return;
+    }
+
+    commands.remove_resource::<PendingBugReport>();
+    if screenshot.is_none() {
+        warn!("The bug report is saved without a screenshot, no frame was captured");
+    }
+    pending.report.screenshot = screenshot;
+    let report = &pending.report;
+    match report.save() {
+        Ok(path) => {
+            info!("Bug report saved to {}", path.display());
+            info!("Open an issue with it at {}", report.issue_url(&path));
+            spawn_toast(
+                &mut commands,
+                &asset_server,
+                format!("Bug report saved to {}", path.display()),
+            );
+        }
+        Err(err) => error!("Failed to save a bug report: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zips_store_the_files() {
+        let files = [
+            ("report.txt", b"seed 1 2\n".to_vec()),
+            ("board.png", vec![0, 1, 2]),
+        ];
+        let archive = zip(&files).unwrap();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+        assert_eq!(archive.len(), 2);
+        for (name, data) in files {
+            let mut file = archive.by_name(name).unwrap();
+            let mut read = Vec::new();
+            io::Read::read_to_end(&mut file, &mut read).unwrap();
+            assert_eq!(read, data);
+        }
+    }
+
+    #[test]
+    fn issue_links_are_encoded() {
+        assert_eq!(
+            percent_encode("seed 1&rules=é"),
+            "seed%201%26rules%3D%C3%A9"
+        );
+
+        let rules = GameRules::default();
+        let prng = PrngResource {
+            world_seed: 12,
+            env_seed: 34,
+        };
+        let report = BugReport {
+            stamp: ExportStamp::new(&prng, &rules),
+            rules,
+            moves: None,
+            logs: vec!["INFO stackrankdice: started".to_string()],
+            screenshot: Some(Frame {
+                width: 1,
+                height: 1,
+                pixels: vec![0, 0, 0, 255],
+            }),
+        };
+        let url = report.issue_url(Path::new("bug_reports/bug_report_1.zip"));
+
+        assert!(url.starts_with(
+            "https://github.com/quentusrex/stackrankdice/issues/new?title=Bug%20report&body="
+        ));
+        assert!(url.contains("bug_report_1.zip"));
+        assert!(!url.contains(' '));
+        assert_eq!(report.files().len(), 5);
+    }
+}
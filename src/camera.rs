@@ -0,0 +1,178 @@
+//! Board camera rig: frames both regions of a battle while it is rolled, shakes on its
+//! resolution and returns to the previous framing afterwards.
+
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::accessibility::AccessibilitySettings;
+use crate::events::{EventBattleResolved, EventPlayerMoveStart};
+use crate::game::GameState;
+use crate::geometry::center;
+
+const TWEEN_DURATION: Duration = Duration::from_millis(600);
+const SHAKE_DURATION: Duration = Duration::from_millis(300);
+const SHAKE_AMPLITUDE: f32 = 0.3;
+/// Closest the camera zooms in while framing a battle
+const MIN_BATTLE_SCALE: f32 = 4.0;
+
+/// Position and zoom of the board camera
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Framing {
+    translation: Vec3,
+    scale: f32,
+}
+
+impl Framing {
+    fn lerp(self, other: Framing, t: f32) -> Framing {
+        Framing {
+            translation: self.translation.lerp(other.translation, t),
+            scale: self.scale + (other.scale - self.scale) * t,
+        }
+    }
+}
+
+/// Tweens the board camera between framings
+#[derive(Component)]
+pub(crate) struct CameraRig {
+    /// Framing to return to once the battle is over
+    rest: Option<Framing>,
+    from: Framing,
+    to: Framing,
+    tween: Timer,
+    shake: Timer,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        let mut tween = Timer::new(TWEEN_DURATION, TimerMode::Once);
+        tween.tick(TWEEN_DURATION);
+        let mut shake = Timer::new(SHAKE_DURATION, TimerMode::Once);
+        shake.tick(SHAKE_DURATION);
+
+        let framing = Framing {
+            translation: Vec3::ZERO,
+            scale: 1.0,
+        };
+        CameraRig {
+            rest: None,
+            from: framing,
+            to: framing,
+            tween,
+            shake,
+        }
+    }
+}
+
+impl CameraRig {
+    fn tween_to(&mut self, from: Framing, to: Framing) {
+        self.from = from;
+        self.to = to;
+        self.tween.reset();
+    }
+
+    /// Framing the tween is at right now
+    fn framing(&self) -> Framing {
+        let t = self.tween.percent();
+        // ease in and out
+        self.from.lerp(self.to, t * t * (3.0 - 2.0 * t))
+    }
+}
+
+fn orthographic_scale(projection: &Projection) -> Option<f32> {
+    match projection {
+        Projection::Orthographic(projection) => Some(projection.scale),
+        Projection::Perspective(_) => None,
+    }
+}
+
+/// Starts framing a battle when it begins, and shaking and returning when it resolves
+pub(crate) fn camera_rig_battles(
+    settings: Res<AccessibilitySettings>,
+    game_state: Res<GameState>,
+    mut event_player_move_start_reader: EventReader<EventPlayerMoveStart>,
+    mut event_battle_resolved_reader: EventReader<EventBattleResolved>,
+    mut rig_query: Query<(&mut CameraRig, &Transform, &Projection)>,
+) {
+    let started = event_player_move_start_reader.iter().last();
+    let resolved = event_battle_resolved_reader.iter().count() > 0;
+    if settings.reduced_motion {
+        return;
+    }
+
+    for (mut rig, transform, projection) in rig_query.iter_mut() {
+        let scale = match orthographic_scale(projection) {
+            Some(scale) => scale,
+            None => continue,
+        };
+        let current = match rig.tween.finished() {
+            true => Framing {
+                translation: transform.translation,
+                scale,
+            },
+            false => rig.framing(),
+        };
+
+        if let Some(event) = started {
+            let rest = *rig.rest.get_or_insert(current);
+            let regions = &game_state.board.regions;
+            let [x_1, _, z_1] = center(1.0, &regions[event.region_1].center_hex(), &[0.0; 3]);
+            let [x_2, _, z_2] = center(1.0, &regions[event.region_2].center_hex(), &[0.0; 3]);
+
+            // the camera looks at the origin from its rest position
+            let middle = Vec3::new(x_1 + x_2, 0.0, z_1 + z_2) / 2.0;
+            let distance = Vec3::new(x_1 - x_2, 0.0, z_1 - z_2).length();
+            let target = Framing {
+                translation: rest.translation + middle,
+                scale: (distance * 2.0).clamp(MIN_BATTLE_SCALE, rest.scale),
+            };
+            rig.tween_to(current, target);
+        }
+
+        if resolved {
+            let rest = rig.rest.unwrap_or(current);
+            rig.tween_to(current, rest);
+            rig.shake.reset();
+        }
+    }
+}
+
+/// Moves the camera along its tween and shake
+pub(crate) fn camera_rig_update(
+    time: Res<Time>,
+    mut rig_query: Query<(&mut CameraRig, &mut Transform, &mut Projection)>,
+) {
+    for (mut rig, mut transform, mut projection) in rig_query.iter_mut() {
+        let tweening = !rig.tween.finished();
+        let shaking = !rig.shake.finished();
+        if !tweening && !shaking {
+            continue;
+        }
+
+        rig.tween.tick(time.delta());
+        rig.shake.tick(time.delta());
+
+        let framing = rig.framing();
+        let fading = 1.0 - rig.shake.percent();
+        let phase = rig.shake.elapsed_secs() * 40.0;
+        let shake = match rig.shake.finished() {
+            true => Vec3::ZERO,
+            false => {
+                (transform.right() * (phase * TAU / 7.0).sin() + transform.up() * phase.cos())
+                    * SHAKE_AMPLITUDE
+                    * fading
+            }
+        };
+
+        transform.translation = framing.translation + shake;
+        if let Projection::Orthographic(projection) = projection.as_mut() {
+            projection.scale = framing.scale;
+        }
+
+        // back at rest, the next battle starts from wherever the player moved the camera
+        if tweening && rig.tween.finished() && rig.rest == Some(rig.to) {
+            rig.rest = None;
+        }
+    }
+}
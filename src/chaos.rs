@@ -0,0 +1,184 @@
+//! Optional "chaos" rule: every few turns a random global event shakes up the board.
+
+use bevy::prelude::*;
+use rand::{seq::IteratorRandom, Rng};
+
+use crate::events::{EventBoardChanged, EventTurnStart};
+use crate::game::{GameState, NEUTRAL};
+use crate::profiles::PlayerProfiles;
+use crate::rules::GameRules;
+use crate::tiered_prng::PrngEnvResource;
+use crate::ui::spawn_toast;
+
+/// Number of regions reinforced by a windfall
+const WINDFALL_REGIONS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GlobalEvent {
+    /// Every region with more than one die loses a die
+    Earthquake,
+    /// The region turns neutral, left with a single die
+    Plague { region: usize },
+    /// Each listed region of the player gains a die
+    Windfall { player: usize, regions: Vec<usize> },
+}
+
+impl GlobalEvent {
+    pub fn random(game_state: &GameState, rng: &mut impl Rng) -> GlobalEvent {
+        let regions = &game_state.board.regions;
+        let held = regions.iter().filter(|region| region.owner != NEUTRAL);
+
+        match rng.gen_range(0..3) {
+            0 => GlobalEvent::Earthquake,
+            1 => match held.map(|region| region.id).choose(rng) {
+                Some(region) => GlobalEvent::Plague { region },
+                None => GlobalEvent::Earthquake,
+            },
+            _ => {
+                let player = held.choose(rng).map_or(NEUTRAL, |region| region.owner);
+                let regions = regions
+                    .iter()
+                    .filter(|region| region.owner == player)
+                    .map(|region| region.id)
+                    .choose_multiple(rng, WINDFALL_REGIONS);
+
+                GlobalEvent::Windfall { player, regions }
+            }
+        }
+    }
+
+    pub fn apply(&self, game_state: &mut GameState, rules: &GameRules) {
+        let regions = &mut game_state.board.regions;
+
+        match self {
+            GlobalEvent::Earthquake => {
+                for region in regions.iter_mut() {
+                    if region.num_dice > 1 {
+                        region.num_dice -= 1;
+                    }
+                }
+            }
+            GlobalEvent::Plague { region } => {
+                let region = &mut regions[*region];
+                region.owner = NEUTRAL;
+                region.num_dice = 1;
+                for hex in region.hexes.iter() {
+                    game_state.board.hexes.insert(*hex, NEUTRAL);
+                }
+            }
+            GlobalEvent::Windfall { regions: ids, .. } => {
+                for id in ids.iter() {
+                    regions[*id].num_dice = usize::min(regions[*id].num_dice + 1, rules.max_dice);
+                }
+            }
+        }
+    }
+
+    pub fn description(&self, profiles: &PlayerProfiles) -> String {
+        match self {
+            GlobalEvent::Earthquake => "Earthquake! Every stack loses a die".to_string(),
+            GlobalEvent::Plague { region } => {
+                format!("Plague strikes region {}, it turns neutral", region)
+            }
+            GlobalEvent::Windfall { player, .. } => {
+                format!("Windfall! {} gets reinforcements", profiles.name(*player))
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub struct GlobalEventLogEntry {
+    pub turn_counter: usize,
+    pub event: GlobalEvent,
+}
+
+/// Global events that fired during the game
+#[derive(Resource, Default)]
+pub struct GlobalEventLog {
+    pub entries: Vec<GlobalEventLogEntry>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn global_events(
+    mut commands: Commands,
+    mut event_turn_start_reader: EventReader<EventTurnStart>,
+    mut event_board_changed_writer: EventWriter<EventBoardChanged>,
+    mut env_prng: ResMut<PrngEnvResource>,
+    mut global_event_log: ResMut<GlobalEventLog>,
+    mut game_state: ResMut<GameState>,
+    rules: Res<GameRules>,
+    asset_server: Res<AssetServer>,
+    profiles: Res<PlayerProfiles>,
+) {
+    if rules.chaos_interval == 0 {
+        return;
+    }
+
+    for _ in event_turn_start_reader.iter() {
+        if game_state.turn_counter % rules.chaos_interval != 0 {
+            continue;
+        }
+
+        let event = GlobalEvent::random(&game_state, &mut env_prng.rng);
+        event.apply(&mut game_state, &rules);
+
+        info!("Turn {}: {:?}", game_state.turn_counter, event);
+        spawn_toast(&mut commands, &asset_server, event.description(&profiles));
+        event_board_changed_writer.send(EventBoardChanged);
+
+        global_event_log.entries.push(GlobalEventLogEntry {
+            turn_counter: game_state.turn_counter,
+            event,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::generate_board;
+    use crate::map_file::MapFile;
+    use crate::tiered_prng::get_randomness;
+
+    #[test]
+    fn events_keep_dice_in_range() {
+        let rules = GameRules::default();
+        let mut game_state = GameState {
+            board: generate_board(&rules, get_randomness(4242)),
+            number_of_players: rules.number_of_players,
+            ..Default::default()
+        };
+        let mut rng = get_randomness(17);
+
+        for _ in 0..100 {
+            GlobalEvent::random(&game_state, &mut rng).apply(&mut game_state, &rules);
+
+            for region in game_state.board.regions.iter() {
+                assert!((1..=rules.max_dice).contains(&region.num_dice));
+            }
+        }
+    }
+
+    #[test]
+    fn plagues_leave_neutral_regions() {
+        let map =
+            MapFile::parse("players 2\nregion 0 3 0,0\nregion 1 2 1,0\nregion 1 4 2,0\n").unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+
+        GlobalEvent::Plague { region: 2 }.apply(&mut game_state, &GameRules::default());
+        let region = &game_state.board.regions[2];
+        assert_eq!((region.owner, region.num_dice), (NEUTRAL, 1));
+        assert_eq!(game_state.board.hexes[&(2, 0)], NEUTRAL);
+        assert!(game_state.board.regions[1].is_opponent(region));
+        assert_eq!(game_state.winner(), None);
+
+        // neutral regions don't keep the last player from winning
+        game_state.board.regions[1].owner = 0;
+        assert_eq!(game_state.winner(), Some(0));
+    }
+}
@@ -0,0 +1,434 @@
+//! Timestamped event timeline of a game, for video editors and streamers: chapter markers and
+//! overlays lined up with a recording of the session.
+//!
+//! Enabled with `--export-timeline`. [`record_timeline`] reads the game events like any other
+//! observer: turn starts, battles, eliminations and the end of the game, timed from the first of
+//! them. Once the game is over, two files are written in [`TIMELINES_DIR`] of the data directory:
+//!
+//! - `timeline_<unix time>.json`, every event with its time in milliseconds, for overlays
+//! - `timeline_<unix time>.chapters.txt`, one `0:00 Title` line per turn and elimination, the
+//!   chapter format video sites and editors import
+//!
+//! The name holds the Unix time of the first event, to find the recording it belongs to.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+use crate::events::{EventBattleResolved, EventGameOver, EventTurnStart};
+use crate::game::{GameState, RegionId};
+use crate::profiles::PlayerProfiles;
+use crate::rules::GameRules;
+use crate::stamp::ExportStamp;
+use crate::storage::{storage_dir, Location};
+use crate::tiered_prng::PrngResource;
+use crate::timeline::EventPlayerEliminated;
+use crate::ui::spawn_toast;
+
+/// Folder of the exported timelines, relative to the data directory
+pub const TIMELINES_DIR: &str = "timelines";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TimelineEvent {
+    TurnStart {
+        player: usize,
+        name: String,
+    },
+    Battle {
+        attacker: usize,
+        defender: usize,
+        attacker_region: RegionId,
+        defender_region: RegionId,
+        attacker_roll: Vec<usize>,
+        defender_roll: Vec<usize>,
+        attacker_won: bool,
+    },
+    Eliminated {
+        player: usize,
+        name: String,
+    },
+    GameOver {
+        winner: usize,
+        name: String,
+    },
+}
+
+impl TimelineEvent {
+    /// Title of the chapter starting with this event, if it starts one
+    fn chapter(&self, turn: usize) -> Option<String> {
+        match self {
+            TimelineEvent::TurnStart { name, .. } => Some(format!("Turn {} - {}", turn + 1, name)),
+            TimelineEvent::Eliminated { name, .. } => Some(format!("{} is eliminated", name)),
+            TimelineEvent::GameOver { name, .. } => Some(format!("{} wins", name)),
+            TimelineEvent::Battle { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TimedEvent {
+    /// Milliseconds since the first event of the game
+    pub ms: u64,
+    pub turn: usize,
+    #[serde(flatten)]
+    pub event: TimelineEvent,
+}
+
+#[derive(Serialize)]
+struct TimelineFile<'a> {
+    stamp: String,
+    /// Unix time of the first event, in seconds
+    started_at: u64,
+    events: &'a [TimedEvent],
+}
+
+/// Events of the game in progress, when exporting them
+#[derive(Resource, Default)]
+pub struct TimelineExport {
+    pub enabled: bool,
+    /// Time of the first event since startup, and Unix time in seconds
+    started: Option<(Duration, u64)>,
+    events: Vec<TimedEvent>,
+    /// The game ended and its timeline was saved, nothing is recorded until the next game
+    over: bool,
+}
+
+impl TimelineExport {
+    pub fn new(enabled: bool) -> Self {
+        TimelineExport {
+            enabled,
+            ..default()
+        }
+    }
+
+    /// Adds `event` at `now`, since startup
+    pub fn record(&mut self, now: Duration, turn: usize, event: TimelineEvent) {
+        let (start, _) = *self.started.get_or_insert_with(|| {
+            let unix_time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default();
+            (now, unix_time)
+        });
+
+        self.events.push(TimedEvent {
+            ms: now.saturating_sub(start).as_millis() as u64,
+            turn,
+            event,
+        });
+    }
+
+    pub fn to_json(&self, stamp: &ExportStamp) -> String {
+        let file = TimelineFile {
+            stamp: stamp.to_string(),
+            started_at: self.started.map_or(0, |(_, unix_time)| unix_time),
+            events: &self.events,
+        };
+
+        serde_json::to_string_pretty(&file).expect("timelines serialize to JSON")
+    }
+
+    /// Chapter list of the timeline, starting at `0:00` like video sites require
+    pub fn chapters(&self) -> String {
+        let mut lines: Vec<String> = self
+            .events
+            .iter()
+            .filter_map(|timed| {
+                let title = timed.event.chapter(timed.turn)?;
+                Some(format!("{} {}", chapter_time(timed.ms), title))
+            })
+            .collect();
+        if !lines
+            .first()
+            .map_or(false, |line| line.starts_with("0:00 "))
+        {
+            lines.insert(0, "0:00 Start".to_string());
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// Writes the JSON timeline and its chapters in [`TIMELINES_DIR`], returns the path of the
+    /// JSON file
+    fn save(&self, stamp: &ExportStamp) -> io::Result<PathBuf> {
+        let dir = storage_dir(Location::Data).join(TIMELINES_DIR);
+        fs::create_dir_all(&dir)?;
+        let name = format!(
+            "timeline_{}",
+            self.started.map_or(0, |(_, unix_time)| unix_time)
+        );
+        let path = dir.join(format!("{}.json", name));
+        fs::write(&path, self.to_json(stamp))?;
+        fs::write(dir.join(format!("{}.chapters.txt", name)), self.chapters())?;
+
+        Ok(path)
+    }
+
+    /// Forgets the timeline of the current game, for a fresh one
+    pub(crate) fn reset(&mut self) {
+        self.started = None;
+        self.events.clear();
+        self.over = false;
+    }
+
+    /// Forgets the saved timeline of the game just over, and ignores the events coming after its
+    /// end
+    fn finish(&mut self) {
+        self.reset();
+        self.over = true;
+    }
+}
+
+/// `M:SS`, or `H:MM:SS` past an hour
+pub fn chapter_time(ms: u64) -> String {
+    let seconds = ms / 1000;
+    match seconds / 3600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+/// Puts the events read in one frame back in the order they happened: the bevy event queues
+/// of each kind are read one after the other. Turn starts and battles are sorted by their turn, a
+/// turn start before the battles of its turn. An elimination follows the last battle the player
+/// lost, the end of the game comes last. Events are returned with the turn they happened in,
+/// `turn` for those that don't tell.
+pub fn in_order(
+    turn_starts: Vec<(usize, TimelineEvent)>,
+    battles: Vec<(usize, TimelineEvent)>,
+    eliminations: Vec<TimelineEvent>,
+    game_overs: Vec<TimelineEvent>,
+    turn: usize,
+) -> Vec<(usize, TimelineEvent)> {
+    let mut events = turn_starts;
+    events.extend(battles);
+    // stable, battles of a turn stay in the order they were fought
+    events.sort_by_key(|(turn, event)| (*turn, !matches!(event, TimelineEvent::TurnStart { .. })));
+
+    for elimination in eliminations {
+        let player = match elimination {
+            TimelineEvent::Eliminated { player, .. } => player,
+            _ => continue,
+        };
+        let lost = events.iter().rposition(|(_, event)| {
+            matches!(event, TimelineEvent::Battle { defender, .. } if *defender == player)
+        });
+        match lost {
+            Some(index) => {
+                let turn = events[index].0;
+                events.insert(index + 1, (turn, elimination));
+            }
+            None => events.push((turn, elimination)),
+        }
+    }
+
+    let last_turn = events.last().map_or(turn, |(turn, _)| *turn);
+    events.extend(game_overs.into_iter().map(|event| (last_turn, event)));
+    events
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn record_timeline(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut export: ResMut<TimelineExport>,
+    game_state: Res<GameState>,
+    profiles: Res<PlayerProfiles>,
+    prng: Res<PrngResource>,
+    rules: Res<GameRules>,
+    mut event_turn_start_reader: EventReader<EventTurnStart>,
+    mut event_battle_resolved_reader: EventReader<EventBattleResolved>,
+    mut event_player_eliminated_reader: EventReader<EventPlayerEliminated>,
+    mut event_game_over_reader: EventReader<EventGameOver>,
+) {
+    let battles = event_battle_resolved_reader
+        .iter()
+        .map(|e| {
+            let battle = TimelineEvent::Battle {
+                attacker: e.player_1,
+                defender: e.player_2,
+                attacker_region: e.region_1,
+                defender_region: e.region_2,
+                attacker_roll: e.region_1_dice_result.to_vec(),
+                defender_roll: e.region_2_dice_result.to_vec(),
+                attacker_won: e.attacker_won,
+            };
+            (e.turn, battle)
+        })
+        .collect();
+    let eliminations = event_player_eliminated_reader
+        .iter()
+        .map(|e| TimelineEvent::Eliminated {
+            player: e.player,
+            name: profiles.name(e.player),
+        })
+        .collect();
+    let game_overs = event_game_over_reader
+        .iter()
+        .map(|e| TimelineEvent::GameOver {
+            winner: e.winner,
+            name: profiles.name(e.winner),
+        })
+        .collect();
+    let turn_starts = event_turn_start_reader
+        .iter()
+        .map(|e| {
+            let turn_start = TimelineEvent::TurnStart {
+                player: e.player,
+                name: profiles.name(e.player),
+            };
+            (e.turn, turn_start)
+        })
+        .collect();
+    let events = in_order(
+        turn_starts,
+        battles,
+        eliminations,
+        game_overs,
+        game_state.turn_counter,
+    );
+    if !export.enabled {
+        return;
+    }
+
+    for (turn, event) in events {
+        // the timeline of a game is over with it, until the next game starts
+        if export.over {
+            return;
+        }
+
+        let game_over = matches!(event, TimelineEvent::GameOver { .. });
+        export.record(time.elapsed(), turn, event);
+        if !game_over {
+            continue;
+        }
+
+        match export.save(&ExportStamp::new(&prng, &rules)) {
+            Ok(path) => spawn_toast(
+                &mut commands,
+                &asset_server,
+                format!("Timeline saved to {}", path.display()),
+            ),
+            Err(err) => warn!("Failed to save the event timeline: {}", err),
+        }
+        export.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn_start(player: usize) -> TimelineEvent {
+        TimelineEvent::TurnStart {
+            player,
+            name: format!("Player {}", player + 1),
+        }
+    }
+
+    #[test]
+    fn chapters_follow_turns_and_eliminations() {
+        let mut export = TimelineExport::new(true);
+        let start = Duration::from_secs(12);
+        export.record(start, 0, turn_start(0));
+        export.record(
+            start + Duration::from_millis(4_500),
+            0,
+            TimelineEvent::Battle {
+                attacker: 0,
+                defender: 1,
+                attacker_region: 3,
+                defender_region: 4,
+                attacker_roll: vec![6, 5],
+                defender_roll: vec![1],
+                attacker_won: true,
+            },
+        );
+        export.record(
+            start + Duration::from_secs(75),
+            0,
+            TimelineEvent::Eliminated {
+                player: 1,
+                name: "Sam".to_string(),
+            },
+        );
+        export.record(start + Duration::from_secs(3_725), 1, turn_start(0));
+
+        assert_eq!(export.events[1].ms, 4_500);
+        assert_eq!(
+            export.chapters(),
+            "0:00 Turn 1 - Player 1\n1:15 Sam is eliminated\n1:02:05 Turn 2 - Player 1\n"
+        );
+
+        let prng = PrngResource {
+            world_seed: 12,
+            env_seed: 34,
+        };
+        let json = export.to_json(&ExportStamp::new(&prng, &GameRules::default()));
+        assert!(json.contains("stackrankdice"));
+        assert!(json.contains("\"type\": \"battle\""));
+        assert!(json.contains("\"ms\": 4500"));
+        assert!(json.contains("\"attacker_won\": true"));
+    }
+
+    #[test]
+    fn chapters_start_at_zero() {
+        let mut export = TimelineExport::default();
+        export.record(
+            Duration::ZERO,
+            3,
+            TimelineEvent::Battle {
+                attacker: 0,
+                defender: 1,
+                attacker_region: 0,
+                defender_region: 1,
+                attacker_roll: vec![2],
+                defender_roll: vec![3],
+                attacker_won: false,
+            },
+        );
+        export.record(Duration::from_secs(9), 4, turn_start(1));
+
+        assert_eq!(export.chapters(), "0:00 Start\n0:09 Turn 5 - Player 2\n");
+    }
+
+    #[test]
+    fn events_keep_the_order_they_happened_in() {
+        let battle = |defender: usize| TimelineEvent::Battle {
+            attacker: 0,
+            defender,
+            attacker_region: 0,
+            defender_region: 1,
+            attacker_roll: vec![6],
+            defender_roll: vec![1],
+            attacker_won: true,
+        };
+        let eliminated = TimelineEvent::Eliminated {
+            player: 1,
+            name: "Player 2".to_string(),
+        };
+
+        let events = in_order(
+            vec![(4, turn_start(1)), (5, turn_start(0))],
+            vec![(3, battle(1)), (4, battle(2)), (5, battle(2))],
+            vec![eliminated.clone()],
+            Vec::new(),
+            5,
+        );
+        let order: Vec<(usize, TimelineEvent)> = vec![
+            (3, battle(1)),
+            (3, eliminated),
+            (4, turn_start(1)),
+            (4, battle(2)),
+            (5, turn_start(0)),
+            (5, battle(2)),
+        ];
+        assert_eq!(events, order);
+    }
+}
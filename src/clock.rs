@@ -0,0 +1,222 @@
+//! Chess clock: the thinking time each player spent on its turns, optionally capped by a total
+//! budget from [`GameRules::time_budget_secs`]. A player running out of time loses the game.
+//!
+//! A player is on the clock during both phases of its turn, but not while the dice of a battle
+//! are rolling.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::autosave::ResumePrompt;
+use crate::events::{DiceRollTimer, EventGameOver};
+use crate::game::GameState;
+use crate::logic_step::LOGIC_STEP;
+use crate::profiles::PlayerProfiles;
+use crate::rules::GameRules;
+use crate::ui::{spawn_toast, StackRankDiceUI};
+
+/// Thinking time of every player in the current game
+#[derive(Resource, Default)]
+pub struct PlayerClocks {
+    /// Time spent on its turns, indexed by player
+    pub used: Vec<Duration>,
+    /// The game is over, clocks don't run anymore
+    pub stopped: bool,
+}
+
+impl PlayerClocks {
+    /// Forgets the current game, for a fresh one
+    pub fn reset(&mut self) {
+        self.used.clear();
+        self.stopped = false;
+    }
+
+    pub fn used(&self, player: usize) -> Duration {
+        self.used.get(player).copied().unwrap_or_default()
+    }
+
+    /// Adds `delta` to the clock of `player`. Returns whether its budget just ran out.
+    pub fn tick(&mut self, player: usize, delta: Duration, rules: &GameRules) -> bool {
+        if self.used.len() <= player {
+            self.used.resize(player + 1, Duration::ZERO);
+        }
+        self.used[player] += delta;
+
+        match rules.time_budget() {
+            Some(budget) => self.used[player] >= budget,
+            None => false,
+        }
+    }
+}
+
+/// Winner of a game lost on time by `loser`: the other player owning the most regions,
+/// the first of them on a tie
+pub fn timeout_winner(game_state: &GameState, loser: usize) -> usize {
+    (0..game_state.number_of_players)
+        .filter(|player| *player != loser)
+        .max_by_key(|player| {
+            let regions = game_state
+                .board
+                .regions
+                .iter()
+                .filter(|region| region.owner == *player)
+                .count();
+            (regions, std::cmp::Reverse(*player))
+        })
+        .unwrap_or(loser)
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn run_player_clocks(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    rules: Res<GameRules>,
+    mut game_state: ResMut<GameState>,
+    profiles: Res<PlayerProfiles>,
+    mut clocks: ResMut<PlayerClocks>,
+    resume_prompt: Option<Res<ResumePrompt>>,
+    dice_roll_timer_query: Query<(), With<DiceRollTimer>>,
+    mut event_game_over_writer: EventWriter<EventGameOver>,
+) {
+    let on_the_clock = !clocks.stopped
+        && resume_prompt.is_none()
+        && dice_roll_timer_query.is_empty()
+        && game_state.winner().is_none();
+    if !on_the_clock {
+        return;
+    }
+
+    let player = game_state.turn_of_player;
+    if clocks.tick(player, LOGIC_STEP, &rules) {
+        clocks.stopped = true;
+        let winner = timeout_winner(&game_state, player);
+        if !game_state.decide_winner(winner) {
+            return;
+        }
+
+        spawn_toast(
+            &mut commands,
+            &asset_server,
+            format!("{} runs out of time", profiles.name(player)),
+        );
+        event_game_over_writer.send(EventGameOver { winner });
+    }
+}
+
+/// Stops the clocks once the game is over, however it ended
+pub(crate) fn stop_player_clocks(
+    mut event_game_over_reader: EventReader<EventGameOver>,
+    mut clocks: ResMut<PlayerClocks>,
+) {
+    if event_game_over_reader.iter().count() > 0 {
+        clocks.stopped = true;
+    }
+}
+
+/// Text area with the clock of every player
+#[derive(Component)]
+pub(crate) struct ClockText;
+
+pub(crate) fn setup_clock_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(5.0),
+                    right: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Name::new("Clock Text"))
+        .insert(ClockText)
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn clock_text_update(
+    clocks: Res<PlayerClocks>,
+    rules: Res<GameRules>,
+    game_state: Res<GameState>,
+    profiles: Res<PlayerProfiles>,
+    mut query: Query<&mut Text, With<ClockText>>,
+) {
+    // with a budget the clocks count down, without one they count up
+    let value = (0..game_state.number_of_players)
+        .map(|player| {
+            let shown = match rules.time_budget() {
+                Some(budget) => budget.saturating_sub(clocks.used(player)),
+                None => clocks.used(player),
+            };
+            format!(
+                "{} {}",
+                profiles.name(player).to_uppercase(),
+                format_duration(shown)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    for mut text in &mut query {
+        text.sections[0].value = value.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+
+    #[test]
+    fn budget_runs_out() {
+        let rules = GameRules {
+            time_budget_secs: 60,
+            ..default()
+        };
+        let mut clocks = PlayerClocks::default();
+
+        assert!(!clocks.tick(1, Duration::from_secs(59), &rules));
+        assert_eq!(clocks.used(0), Duration::ZERO);
+        assert!(clocks.tick(1, Duration::from_secs(1), &rules));
+
+        // without a budget the clock only counts
+        assert!(!clocks.tick(1, Duration::from_secs(600), &GameRules::default()));
+    }
+
+    #[test]
+    fn most_regions_win_on_timeout() {
+        let map = MapFile::parse(
+            "players 3\nregion 0 1 0,0\nregion 0 1 1,0\nregion 1 1 2,0\nregion 2 1 3,0\n",
+        )
+        .unwrap();
+        let game_state = GameState {
+            board: map.board,
+            number_of_players: 3,
+            ..Default::default()
+        };
+
+        assert_eq!(timeout_winner(&game_state, 0), 1);
+        assert_eq!(timeout_winner(&game_state, 1), 0);
+    }
+
+    #[test]
+    fn formats_minutes_and_seconds() {
+        assert_eq!(format_duration(Duration::from_secs(83)), "1:23");
+        assert_eq!(format_duration(Duration::from_secs(5)), "0:05");
+    }
+}
@@ -0,0 +1,223 @@
+//! Commentator view: every attack on the board, whoever makes it, with its odds and the dice it
+//! is expected to swing, updated as the position changes. Shown while watching a game without any
+//! local human player, and toggled with F7.
+
+use bevy::prelude::*;
+
+use crate::game::{GameState, RegionId};
+use crate::odds::attack_win_probability;
+use crate::rules::GameRules;
+use crate::seats::Seats;
+use crate::ui::StackRankDiceUI;
+
+/// Key that toggles the commentator view
+const PANEL_KEY: KeyCode = KeyCode::F7;
+
+/// Number of attacks listed in the panel, the best first
+const LISTED_ATTACKS: usize = 10;
+
+/// Whether the commentator view is shown, when it isn't decided by the seats
+#[derive(Resource, Default)]
+pub struct Commentary {
+    /// Shown or hidden with F7, overriding the default
+    pub toggled: Option<bool>,
+}
+
+/// An attack a player can make, with its odds
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttackAnalysis {
+    /// Owner of the attacking region
+    pub player: usize,
+    pub attacker: RegionId,
+    pub defender: RegionId,
+    /// Probability the attacker wins
+    pub probability: f64,
+    /// Dice swing expected from the attack, see [`expected_dice_swing`]
+    pub expected_value: f64,
+}
+
+/// Difference the attack is expected to make between the dice of the attacker and the dice of
+/// the defender. The loser of a battle loses its region and every die in it, which go to the
+/// winner when it has a single die to split.
+pub fn expected_dice_swing(attacker_dice: usize, defender_dice: usize, probability: f64) -> f64 {
+    let won = match attacker_dice > 1 {
+        true => defender_dice,
+        false => 2 * defender_dice,
+    };
+    let lost = match defender_dice > 1 {
+        true => attacker_dice,
+        false => 2 * attacker_dice,
+    };
+    probability * won as f64 - (1.0 - probability) * lost as f64
+}
+
+/// Every attack on the board, by any player, the highest expected value first. Regions of the
+/// player in turn that already moved are left out.
+pub fn analyse_attacks(game_state: &GameState, rules: &GameRules) -> Vec<AttackAnalysis> {
+    let regions = &game_state.board.regions;
+    let mut attacks: Vec<AttackAnalysis> = regions
+        .iter()
+        .filter(|attacker| !attacker.has_moved)
+        .flat_map(|attacker| {
+            regions
+                .iter()
+                .filter(move |defender| game_state.can_attack(attacker, defender, rules))
+                .map(move |defender| (attacker, defender))
+        })
+        .map(|(attacker, defender)| {
+            let probability = attack_win_probability(attacker.num_dice, defender.num_dice, rules);
+            AttackAnalysis {
+                player: attacker.owner,
+                attacker: attacker.id,
+                defender: defender.id,
+                probability,
+                expected_value: expected_dice_swing(
+                    attacker.num_dice,
+                    defender.num_dice,
+                    probability,
+                ),
+            }
+        })
+        .collect();
+
+    attacks.sort_by(|a, b| b.expected_value.total_cmp(&a.expected_value));
+    attacks
+}
+
+fn commentary_text(attacks: &[AttackAnalysis], game_state: &GameState) -> String {
+    let regions = &game_state.board.regions;
+    let mut lines = vec!["PLAYER  ATTACK  WIN  EV".to_string()];
+    lines.extend(attacks.iter().take(LISTED_ATTACKS).map(|attack| {
+        format!(
+            "P{}  {} ({}) > {} ({})  {:.0}%  {:+.1}",
+            attack.player + 1,
+            regions[attack.attacker].label(),
+            regions[attack.attacker].num_dice,
+            regions[attack.defender].label(),
+            regions[attack.defender].num_dice,
+            attack.probability * 100.0,
+            attack.expected_value
+        )
+    }));
+    if attacks.len() > LISTED_ATTACKS {
+        lines.push(format!("and {} more", attacks.len() - LISTED_ATTACKS));
+    }
+    lines.join("\n")
+}
+
+/// Text area with the attacks on the board
+#[derive(Component)]
+pub(crate) struct CommentaryText;
+
+pub(crate) fn setup_commentary_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Percent(20.0),
+                    right: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Visibility { is_visible: false })
+        .insert(Name::new("Commentary Text"))
+        .insert(CommentaryText)
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn commentary_text_update(
+    keys: Res<Input<KeyCode>>,
+    mut commentary: ResMut<Commentary>,
+    seats: Res<Seats>,
+    game_state: Res<GameState>,
+    rules: Res<GameRules>,
+    mut query: Query<(&mut Text, &mut Visibility), With<CommentaryText>>,
+) {
+    let spectating = !seats.has_local_human();
+    if keys.just_pressed(PANEL_KEY) {
+        commentary.toggled = Some(!commentary.toggled.unwrap_or(spectating));
+    }
+    let shown = commentary.toggled.unwrap_or(spectating);
+
+    for (mut text, mut visibility) in &mut query {
+        visibility.is_visible = shown;
+        if shown && (game_state.is_changed() || commentary.is_changed()) {
+            text.sections[0].value =
+                commentary_text(&analyse_attacks(&game_state, &rules), &game_state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+
+    #[test]
+    fn attacks_are_ranked_by_expected_value() {
+        let map = MapFile::parse(
+            "players 2\nregion 0 6 0,0\nregion 1 2 1,0\nregion 1 8 -1,0\nregion 0 1 5,5\n",
+        )
+        .unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        let rules = GameRules::default();
+
+        let attacks: Vec<_> = analyse_attacks(&game_state, &rules)
+            .into_iter()
+            .filter(|attack| attack.player == 0)
+            .collect();
+        assert_eq!(attacks.len(), 2);
+        assert_eq!((attacks[0].attacker, attacks[0].defender), (0, 1));
+        assert_eq!((attacks[1].attacker, attacks[1].defender), (0, 2));
+        assert!(attacks[0].probability > 0.9);
+        assert!(attacks[1].expected_value < 0.0);
+
+        game_state.board.regions[0].has_moved = true;
+        assert!(analyse_attacks(&game_state, &rules)
+            .iter()
+            .all(|attack| attack.player != 0));
+    }
+
+    #[test]
+    fn attacks_of_every_player_are_listed() {
+        let map = MapFile::parse(
+            "players 3\nregion 0 3 0,0\nregion 1 4 1,0\nregion 2 1 2,0\nregion 2 5 5,5\n",
+        )
+        .unwrap();
+        let game_state = GameState {
+            board: map.board,
+            number_of_players: 3,
+            ..Default::default()
+        };
+        let rules = GameRules::default();
+
+        let mut attacks: Vec<_> = analyse_attacks(&game_state, &rules)
+            .iter()
+            .map(|attack| (attack.player, attack.attacker, attack.defender))
+            .collect();
+        attacks.sort_unstable();
+        assert_eq!(attacks, vec![(0, 0, 1), (1, 1, 0), (1, 1, 2), (2, 2, 1)]);
+    }
+
+    #[test]
+    fn single_dice_change_hands() {
+        assert_eq!(expected_dice_swing(4, 3, 0.5), 0.5 * 3.0 - 0.5 * 4.0);
+        assert_eq!(expected_dice_swing(1, 3, 0.25), 0.25 * 6.0 - 0.75 * 1.0);
+        assert_eq!(expected_dice_swing(3, 1, 0.75), 0.75 * 1.0 - 0.25 * 6.0);
+    }
+}
@@ -0,0 +1,219 @@
+//! Minimal differences between two states of the same game.
+//!
+//! [`GameState::diff`] lists what changed between two states on the same board, and
+//! [`GameState::apply_delta`] replays those changes on the older state. The delta also prints as
+//! a human-readable list of divergences, one per line.
+
+use std::fmt;
+
+use crate::game::{GameLogEntry, GameState, Pact, TurnPhase};
+
+/// Part of a region that changes during a game
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegionSnapshot {
+    pub owner: usize,
+    pub num_dice: usize,
+    pub has_moved: bool,
+}
+
+/// A region that differs between two states
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionDelta {
+    pub id: usize,
+    pub before: RegionSnapshot,
+    pub after: RegionSnapshot,
+}
+
+/// Changes turning one game state into another. Fields left `None` didn't change.
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+pub struct StateDelta {
+    pub regions: Vec<RegionDelta>,
+    pub turn_of_player: Option<(usize, usize)>,
+    pub turn_counter: Option<(usize, usize)>,
+    pub turn_phase: Option<(TurnPhase, TurnPhase)>,
+    pub reserves: Option<(Vec<usize>, Vec<usize>)>,
+    pub pacts: Option<(Vec<Pact>, Vec<Pact>)>,
+    pub decided_winner: Option<(Option<usize>, Option<usize>)>,
+    /// Length the game log is cut to before `new_log_entries` are appended
+    pub game_log_len: usize,
+    pub new_log_entries: Vec<GameLogEntry>,
+}
+
+#[allow(dead_code)]
+impl StateDelta {
+    /// Whether both states are the same, ignoring log entries neither of them added
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+            && self.turn_of_player.is_none()
+            && self.turn_counter.is_none()
+            && self.turn_phase.is_none()
+            && self.reserves.is_none()
+            && self.pacts.is_none()
+            && self.decided_winner.is_none()
+            && self.new_log_entries.is_empty()
+    }
+}
+
+#[allow(dead_code)]
+fn changed<T: PartialEq + Clone>(before: &T, after: &T) -> Option<(T, T)> {
+    match before == after {
+        true => None,
+        false => Some((before.clone(), after.clone())),
+    }
+}
+
+#[allow(dead_code)]
+fn snapshot(game_state: &GameState, id: usize) -> RegionSnapshot {
+    let region = &game_state.board.regions[id];
+    RegionSnapshot {
+        owner: region.owner,
+        num_dice: region.num_dice,
+        has_moved: region.has_moved,
+    }
+}
+
+#[allow(dead_code)]
+impl GameState {
+    /// Changes turning this state into `other`. Both states must be played on the same board.
+    /// The game log is only ever appended to, so entries are compared by count.
+    pub fn diff(&self, other: &GameState) -> StateDelta {
+        let regions = (0..self.board.regions.len().min(other.board.regions.len()))
+            .map(|id| RegionDelta {
+                id,
+                before: snapshot(self, id),
+                after: snapshot(other, id),
+            })
+            .filter(|delta| delta.before != delta.after)
+            .collect();
+
+        let game_log_len = self.game_log.len().min(other.game_log.len());
+
+        StateDelta {
+            regions,
+            turn_of_player: changed(&self.turn_of_player, &other.turn_of_player),
+            turn_counter: changed(&self.turn_counter, &other.turn_counter),
+            turn_phase: changed(&self.turn_phase, &other.turn_phase),
+            reserves: changed(&self.reserves, &other.reserves),
+            pacts: changed(&self.pacts, &other.pacts),
+            decided_winner: changed(&self.decided_winner, &other.decided_winner),
+            game_log_len,
+            new_log_entries: other.game_log[game_log_len..].to_vec(),
+        }
+    }
+
+    /// Applies changes computed by [`GameState::diff`] on the state it was computed from
+    pub fn apply_delta(&mut self, delta: &StateDelta) {
+        for region_delta in delta.regions.iter() {
+            let region = &mut self.board.regions[region_delta.id];
+            region.owner = region_delta.after.owner;
+            region.num_dice = region_delta.after.num_dice;
+            region.has_moved = region_delta.after.has_moved;
+
+            for hex in region.hexes.iter() {
+                self.board.hexes.insert(*hex, region.owner);
+            }
+        }
+
+        if let Some((_, turn_of_player)) = delta.turn_of_player {
+            self.turn_of_player = turn_of_player;
+        }
+        if let Some((_, turn_counter)) = delta.turn_counter {
+            self.turn_counter = turn_counter;
+        }
+        if let Some((_, turn_phase)) = delta.turn_phase {
+            self.turn_phase = turn_phase;
+        }
+        if let Some((_, reserves)) = &delta.reserves {
+            self.reserves = reserves.clone();
+        }
+        if let Some((_, pacts)) = &delta.pacts {
+            self.pacts = pacts.clone();
+        }
+        if let Some((_, decided_winner)) = delta.decided_winner {
+            self.decided_winner = decided_winner;
+        }
+
+        self.game_log.truncate(delta.game_log_len);
+        self.game_log.extend(delta.new_log_entries.iter().cloned());
+    }
+}
+
+impl fmt::Display for StateDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for delta in self.regions.iter() {
+            let (before, after) = (delta.before, delta.after);
+            write!(f, "region {}:", delta.id)?;
+            if before.owner != after.owner {
+                write!(f, " owner {} -> {}", before.owner, after.owner)?;
+            }
+            if before.num_dice != after.num_dice {
+                write!(f, " dice {} -> {}", before.num_dice, after.num_dice)?;
+            }
+            if before.has_moved != after.has_moved {
+                write!(f, " moved {} -> {}", before.has_moved, after.has_moved)?;
+            }
+            writeln!(f)?;
+        }
+
+        if let Some((before, after)) = self.turn_of_player {
+            writeln!(f, "player to move: {} -> {}", before, after)?;
+        }
+        if let Some((before, after)) = self.turn_counter {
+            writeln!(f, "turn: {} -> {}", before, after)?;
+        }
+        if let Some((before, after)) = self.turn_phase {
+            writeln!(f, "phase: {:?} -> {:?}", before, after)?;
+        }
+        if let Some((before, after)) = &self.reserves {
+            writeln!(f, "reserves: {:?} -> {:?}", before, after)?;
+        }
+        if let Some((before, after)) = &self.pacts {
+            writeln!(f, "pacts: {} -> {}", before.len(), after.len())?;
+        }
+        if let Some((before, after)) = self.decided_winner {
+            writeln!(f, "decided winner: {:?} -> {:?}", before, after)?;
+        }
+        if !self.new_log_entries.is_empty() {
+            writeln!(f, "{} new log entries", self.new_log_entries.len())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+
+    #[test]
+    fn apply_delta_reaches_the_other_state() {
+        let map = MapFile::parse("players 2\nregion 0 3 0,0\nregion 1 2 1,0\n").unwrap();
+        let before = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+
+        let mut after = before.clone();
+        after.board.regions[1].owner = 0;
+        after.board.hexes.insert((1, 0), 0);
+        after.board.regions[1].num_dice = 1;
+        after.advance_turn();
+
+        let delta = before.diff(&after);
+        assert_eq!(delta.regions.len(), 1);
+        assert_eq!(
+            delta.to_string(),
+            "region 1: owner 1 -> 0 dice 2 -> 1\nplayer to move: 0 -> 1\nturn: 0 -> 1\n"
+        );
+
+        let mut patched = before.clone();
+        patched.apply_delta(&delta);
+        assert!(patched.diff(&after).is_empty());
+        assert_eq!(patched.board.hexes[&(1, 0)], 0);
+    }
+}
@@ -0,0 +1,340 @@
+//! Dice conservation audit: dice only appear through reinforcements and only disappear with the
+//! regions lost in battle, or with what the reserve cap cuts off at the end of a turn. The check
+//! runs between two game states, and a debug system runs it after every frame that changed the
+//! game, logging what changed on the board when dice went missing or appeared from nowhere.
+
+use std::fmt;
+
+use bevy::prelude::*;
+
+use crate::chaos::GlobalEventLog;
+use crate::game::{transfer_allowed, GameLogEntry, GameState, TurnPhase};
+use crate::rules::{GameRules, ReinforcementRule};
+use crate::scenario::ScenarioTriggers;
+
+/// Whether the audit system checks the game. On by default in debug builds.
+#[derive(Resource)]
+pub struct DiceAuditSettings {
+    pub enabled: bool,
+}
+
+impl Default for DiceAuditSettings {
+    fn default() -> Self {
+        DiceAuditSettings {
+            enabled: cfg!(debug_assertions),
+        }
+    }
+}
+
+/// Dice conservation violated between two game states
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiceAuditError {
+    /// A battle moved a number of dice its transfer rule doesn't allow
+    Transfer {
+        region_1: usize,
+        region_2: usize,
+        winner_dice: usize,
+        transferred: usize,
+    },
+    /// Dice on the board and in the reserves don't add up
+    Total {
+        expected: usize,
+        found: usize,
+        /// Every region and reserve that changed, one per line
+        diff: Vec<String>,
+    },
+}
+
+impl fmt::Display for DiceAuditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiceAuditError::Transfer {
+                region_1,
+                region_2,
+                winner_dice,
+                transferred,
+            } => write!(
+                f,
+                "battle between regions {} and {} transferred {} of the winner's {} dice",
+                region_1, region_2, transferred, winner_dice
+            ),
+            DiceAuditError::Total {
+                expected,
+                found,
+                diff,
+            } => {
+                write!(f, "expected {} dice, found {}", expected, found)?;
+                for line in diff.iter() {
+                    write!(f, "\n  {}", line)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Dice on the board and in every reserve
+pub fn total_dice(game_state: &GameState) -> usize {
+    let board: usize = game_state
+        .board
+        .regions
+        .iter()
+        .map(|region| region.num_dice)
+        .sum();
+    board + game_state.reserves.iter().sum::<usize>()
+}
+
+/// Dice the winner of a logged battle split with the region it conquered, and dice of the loser,
+/// as they were before it. A winning attacker leaves its garrison out of the split.
+fn battle_dice(entry: &GameLogEntry, rules: &GameRules) -> (usize, usize) {
+    match entry.attacker_won {
+        true => (
+            rules.attacker_transfer_dice(entry.region_1_num_dice),
+            entry.region_2_num_dice,
+        ),
+        false => (entry.region_2_num_dice, entry.region_1_num_dice),
+    }
+}
+
+/// Every region and reserve whose dice changed between `before` and `after`
+fn dice_diff(before: &GameState, after: &GameState) -> Vec<String> {
+    let mut diff: Vec<String> = before
+        .board
+        .regions
+        .iter()
+        .zip(after.board.regions.iter())
+        .filter(|(region_before, region_after)| {
+            region_before.num_dice != region_after.num_dice
+                || region_before.owner != region_after.owner
+        })
+        .map(|(region_before, region_after)| {
+            format!(
+                "region {}: {} dice of player {} -> {} dice of player {}",
+                region_before.id,
+                region_before.num_dice,
+                region_before.owner,
+                region_after.num_dice,
+                region_after.owner
+            )
+        })
+        .collect();
+
+    for player in 0..after.number_of_players {
+        let (reserve_before, reserve_after) = (before.reserve(player), after.reserve(player));
+        if reserve_before != reserve_after {
+            diff.push(format!(
+                "reserve of player {}: {} -> {}",
+                player, reserve_before, reserve_after
+            ));
+        }
+    }
+
+    diff
+}
+
+/// Checks that the dice of `after` are the dice of `before`, minus the dice lost in the battles
+/// logged in between, plus the reinforcements earned by the players whose turn ended. Dice
+/// cut off by the reserve cap are only allowed when a player that ended its turn is at the cap.
+pub fn check_dice_conservation(
+    before: &GameState,
+    after: &GameState,
+    rules: &GameRules,
+) -> Result<(), DiceAuditError> {
+    // battles resolved in between, including one already logged but still rolling in `before`
+    let resolved = after
+        .game_log
+        .iter()
+        .enumerate()
+        .filter(|(index, entry)| {
+            entry.resolved && !matches!(before.game_log.get(*index), Some(entry) if entry.resolved)
+        })
+        .map(|(_, entry)| entry);
+
+    let mut destroyed = 0;
+    for entry in resolved {
+        let (winner_dice, loser_dice) = battle_dice(entry, rules);
+        if !transfer_allowed(rules.transfer_rule, winner_dice, entry.transferred) {
+            return Err(DiceAuditError::Transfer {
+                region_1: entry.region_1,
+                region_2: entry.region_2,
+                winner_dice,
+                transferred: entry.transferred,
+            });
+        }
+
+        // a winner with a single die doesn't split, the conquered region keeps its dice
+        if winner_dice > 1 {
+            destroyed += loser_dice;
+        }
+    }
+
+    // players whose reinforcement phase started in between, the ones whose turn ended first
+    let turns_ended = after.turn_counter.saturating_sub(before.turn_counter);
+    let players = before.number_of_players.max(1);
+    let mut reinforced: Vec<usize> = (0..turns_ended)
+        .filter(|turn| *turn > 0 || before.turn_phase == TurnPhase::Attack)
+        .map(|turn| (before.turn_of_player + turn) % players)
+        .collect();
+    let started_now = after.turn_phase == TurnPhase::Reinforcement
+        && (turns_ended > 0 || before.turn_phase == TurnPhase::Attack);
+    if started_now {
+        reinforced.push(after.turn_of_player);
+    }
+
+    let earned: usize = match rules.reinforcements {
+        ReinforcementRule::None => 0,
+        ReinforcementRule::Automatic | ReinforcementRule::Manual => reinforced
+            .iter()
+            .map(|player| after.board.largest_connected_area(*player).0)
+            .sum(),
+    };
+
+    let expected = (total_dice(before) + earned).saturating_sub(destroyed);
+    let found = total_dice(after);
+    let capped = (0..turns_ended)
+        .map(|turn| (before.turn_of_player + turn) % players)
+        .any(|player| after.reserve(player) == rules.reserve_cap);
+
+    match found == expected || (found < expected && capped) {
+        true => Ok(()),
+        false => Err(DiceAuditError::Total {
+            expected,
+            found,
+            diff: dice_diff(before, after),
+        }),
+    }
+}
+
+/// Checks dice conservation after every frame that changed the game. Changes made by global
+/// events and scenario triggers, new games and restarted puzzles aren't moves, the audit starts
+/// over from them.
+pub(crate) fn audit_dice(
+    settings: Res<DiceAuditSettings>,
+    game_state: Res<GameState>,
+    rules: Res<GameRules>,
+    global_event_log: Res<GlobalEventLog>,
+    triggers: Res<ScenarioTriggers>,
+    mut last: Local<Option<(GameState, usize, usize)>>,
+) {
+    if !settings.enabled || !game_state.is_changed() {
+        return;
+    }
+
+    let events = (global_event_log.entries.len(), triggers.fired_count());
+    if let Some((before, global_events, fired)) = last.as_ref() {
+        let restarted = game_state.game_log.len() < before.game_log.len()
+            || game_state.turn_counter < before.turn_counter;
+        if !restarted && (*global_events, *fired) == events {
+            if let Err(err) = check_dice_conservation(before, &game_state, &rules) {
+                error!("Turn {}: {}", game_state.turn_counter, err);
+            }
+        }
+    }
+
+    *last = Some((game_state.clone(), events.0, events.1));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::BattleOutcome;
+    use crate::rules::TransferRule;
+    use crate::test_support::game_state;
+
+    fn battle(game_state: &mut GameState, outcome: BattleOutcome, rules: &GameRules) {
+        game_state.log_attack(GameLogEntry {
+            region_1: 0,
+            region_2: 1,
+            ..Default::default()
+        });
+        game_state.apply_battle_outcome(0, 1, &outcome, rules);
+    }
+
+    fn outcome(attacker_won: bool, transferred: usize) -> BattleOutcome {
+        BattleOutcome {
+            attacker_won,
+            transferred,
+            attacker_roll: Default::default(),
+            defender_roll: Default::default(),
+        }
+    }
+
+    #[test]
+    fn battles_only_destroy_the_loser_dice() {
+        let rules = GameRules::default();
+        let before = game_state("players 2\nregion 0 5 0,0\nregion 1 3 1,0\n");
+
+        let mut after = before.clone();
+        battle(&mut after, outcome(true, 2), &rules);
+        assert_eq!(check_dice_conservation(&before, &after, &rules), Ok(()));
+
+        // a die lost in the transfer arithmetic
+        after.board.regions[0].num_dice -= 1;
+        let err = check_dice_conservation(&before, &after, &rules).unwrap_err();
+        assert_eq!(
+            err,
+            DiceAuditError::Total {
+                expected: 5,
+                found: 4,
+                diff: vec![
+                    "region 0: 5 dice of player 0 -> 2 dice of player 0".to_string(),
+                    "region 1: 3 dice of player 1 -> 2 dice of player 0".to_string(),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn transfers_follow_the_rule() {
+        let rules = GameRules {
+            transfer_rule: TransferRule::AllButOne,
+            ..Default::default()
+        };
+        let before = game_state("players 2\nregion 0 5 0,0\nregion 1 3 1,0\n");
+
+        let mut after = before.clone();
+        battle(&mut after, outcome(true, 2), &rules);
+        assert_eq!(
+            check_dice_conservation(&before, &after, &rules),
+            Err(DiceAuditError::Transfer {
+                region_1: 0,
+                region_2: 1,
+                winner_dice: 5,
+                transferred: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn transfers_leave_the_garrison_out() {
+        let rules = GameRules {
+            transfer_rule: TransferRule::AllButOne,
+            garrison: 2,
+            ..Default::default()
+        };
+        let before = game_state("players 2\nregion 0 5 0,0\nregion 1 3 1,0\n");
+
+        // the garrison of 2 keeps a die home in place of the die every winner keeps
+        let mut after = before.clone();
+        battle(&mut after, outcome(true, 3), &rules);
+        assert_eq!(check_dice_conservation(&before, &after, &rules), Ok(()));
+    }
+
+    #[test]
+    fn reinforcements_add_the_earned_dice() {
+        let rules = GameRules {
+            reinforcements: ReinforcementRule::Automatic,
+            ..Default::default()
+        };
+        let mut before = game_state("players 2\nregion 0 1 0,0\nregion 0 1 1,0\nregion 1 1 5,5\n");
+        before.board.regions[0].has_moved = true;
+        before.board.regions[1].has_moved = true;
+
+        let mut after = before.clone();
+        assert!(after.finish_turn(&rules));
+        assert_eq!(check_dice_conservation(&before, &after, &rules), Ok(()));
+
+        after.board.regions[2].num_dice += 1;
+        assert!(check_dice_conservation(&before, &after, &rules).is_err());
+    }
+}
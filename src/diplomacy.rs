@@ -0,0 +1,247 @@
+//! Optional diplomacy for games with three or more players.
+//!
+//! On their turn a player presses the number key of another player to propose a truce.
+//! The proposal is shown when that player's turn starts, who accepts with `A` or declines with `D`.
+//! Players bound by a truce can't attack each other until it expires. Truces are recorded in
+//! replays, which couldn't play the attacks around them otherwise.
+
+use bevy::prelude::*;
+
+use crate::events::EventTurnStart;
+use crate::game::{GameState, Pact};
+use crate::profiles::PlayerProfiles;
+use crate::rules::GameRules;
+use crate::ui::{spawn_toast, StackRankDiceUI};
+
+const PLAYER_KEYS: [KeyCode; 8] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+];
+
+/// Event that is fired when two players agreed on a truce
+#[derive(Reflect, FromReflect)]
+pub(crate) struct EventTruceAgreed {
+    pub(crate) pact: Pact,
+}
+
+pub struct TruceProposal {
+    pub from: usize,
+    pub to: usize,
+}
+
+/// Truce proposals waiting for an answer
+#[derive(Resource, Default)]
+pub struct Diplomacy {
+    pub proposals: Vec<TruceProposal>,
+}
+
+impl Diplomacy {
+    pub fn is_enabled(rules: &GameRules) -> bool {
+        rules.diplomacy && rules.number_of_players >= 3
+    }
+}
+
+/// Text area listing the truces in force
+#[derive(Component)]
+pub(crate) struct TruceText;
+
+/// Text area asking the current player to answer a truce proposal
+#[derive(Component)]
+pub(crate) struct TruceProposalText;
+
+fn diplomacy_text(asset_server: &AssetServer, top: f32) -> TextBundle {
+    TextBundle::from_section(
+        "",
+        TextStyle {
+            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+            font_size: 25.0,
+            color: Color::WHITE,
+        },
+    )
+    .with_text_alignment(TextAlignment::TOP_CENTER)
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        position: UiRect {
+            top: Val::Px(top),
+            left: Val::Px(15.0),
+            ..default()
+        },
+        ..default()
+    })
+}
+
+pub(crate) fn setup_diplomacy_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    rules: Res<GameRules>,
+) {
+    if !Diplomacy::is_enabled(&rules) {
+        return;
+    }
+
+    commands
+        .spawn(diplomacy_text(&asset_server, 45.0))
+        .insert(Name::new("Truce Text"))
+        .insert(TruceText)
+        .insert(StackRankDiceUI);
+
+    commands
+        .spawn(diplomacy_text(&asset_server, 75.0))
+        .insert(Name::new("Truce Proposal Text"))
+        .insert(TruceProposalText)
+        .insert(StackRankDiceUI);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn diplomacy_input(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut diplomacy: ResMut<Diplomacy>,
+    mut game_state: ResMut<GameState>,
+    rules: Res<GameRules>,
+    asset_server: Res<AssetServer>,
+    profiles: Res<PlayerProfiles>,
+    mut event_truce_agreed_writer: EventWriter<EventTruceAgreed>,
+) {
+    if !Diplomacy::is_enabled(&rules) {
+        return;
+    }
+
+    let current_player = game_state.turn_of_player;
+
+    // Propose a truce to another player
+    for (player, key) in PLAYER_KEYS
+        .iter()
+        .enumerate()
+        .take(game_state.number_of_players)
+    {
+        let already_proposed = diplomacy
+            .proposals
+            .iter()
+            .any(|proposal| proposal.from == current_player && proposal.to == player);
+
+        if !keys.just_pressed(*key)
+            || player == current_player
+            || already_proposed
+            || game_state.has_pact(current_player, player)
+        {
+            continue;
+        }
+
+        diplomacy.proposals.push(TruceProposal {
+            from: current_player,
+            to: player,
+        });
+        spawn_toast(
+            &mut commands,
+            &asset_server,
+            format!(
+                "{} proposes a truce to {}",
+                profiles.name(current_player),
+                profiles.name(player)
+            ),
+        );
+    }
+
+    // Answer the oldest proposal to the current player
+    let accept = keys.just_pressed(KeyCode::A);
+    if !accept && !keys.just_pressed(KeyCode::D) {
+        return;
+    }
+
+    let index = match diplomacy
+        .proposals
+        .iter()
+        .position(|proposal| proposal.to == current_player)
+    {
+        Some(index) => index,
+        None => return,
+    };
+    let proposal = diplomacy.proposals.remove(index);
+
+    let message = match accept {
+        true => {
+            let expires_at_turn =
+                game_state.turn_counter + rules.truce_rounds * game_state.number_of_players;
+            let pact = Pact {
+                player_1: proposal.from,
+                player_2: proposal.to,
+                expires_at_turn,
+            };
+            game_state.pacts.push(pact.clone());
+            event_truce_agreed_writer.send(EventTruceAgreed { pact });
+            format!(
+                "{} and {} agree on a truce",
+                profiles.name(proposal.from),
+                profiles.name(proposal.to)
+            )
+        }
+        false => format!(
+            "{} declines the truce with {}",
+            profiles.name(proposal.to),
+            profiles.name(proposal.from)
+        ),
+    };
+    spawn_toast(&mut commands, &asset_server, message);
+}
+
+/// Proposals are only valid until the proposing player's next turn
+pub(crate) fn expire_truce_proposals(
+    mut event_turn_start_reader: EventReader<EventTurnStart>,
+    mut diplomacy: ResMut<Diplomacy>,
+) {
+    for e in event_turn_start_reader.iter() {
+        diplomacy
+            .proposals
+            .retain(|proposal| proposal.from != e.player);
+    }
+}
+
+pub(crate) fn diplomacy_text_update(
+    diplomacy: Res<Diplomacy>,
+    game_state: Res<GameState>,
+    mut truce_text_query: Query<&mut Text, (With<TruceText>, Without<TruceProposalText>)>,
+    mut proposal_text_query: Query<&mut Text, (With<TruceProposalText>, Without<TruceText>)>,
+    profiles: Res<PlayerProfiles>,
+) {
+    for mut text in &mut truce_text_query {
+        text.sections[0].value = game_state
+            .pacts
+            .iter()
+            .map(|pact| {
+                let turns_left = pact.expires_at_turn - game_state.turn_counter;
+                format!(
+                    "TRUCE {} - {} ({} turns left)",
+                    profiles.name(pact.player_1).to_uppercase(),
+                    profiles.name(pact.player_2).to_uppercase(),
+                    turns_left
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+    }
+
+    let proposal = diplomacy
+        .proposals
+        .iter()
+        .find(|proposal| proposal.to == game_state.turn_of_player);
+
+    for mut text in &mut proposal_text_query {
+        match proposal {
+            Some(proposal) => {
+                text.sections[0].value = format!(
+                    "{} OFFERS A TRUCE: A TO ACCEPT, D TO DECLINE",
+                    profiles.name(proposal.from).to_uppercase()
+                );
+                text.sections[0].style.color = profiles.color(proposal.from);
+            }
+            None => text.sections[0].value.clear(),
+        }
+    }
+}
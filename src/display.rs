@@ -0,0 +1,374 @@
+//! Window and display settings: window mode, resolution, vertical sync and monitor.
+//!
+//! Settings are read from [`DISPLAY_SETTINGS_PATH`] in the config directory, one `<setting>
+//! <value>` line each, and the command line overrides them. In game `F11` switches between
+//! windowed, borderless and fullscreen, `F3` steps through [`RESOLUTIONS`], `F4` toggles
+//! vertical sync and `F1` moves the window to the next monitor, saving the settings for the next
+//! launch. The settings screen changes them too, see [`crate::menu::SettingsMenu`]. The HUD is laid out in percents of
+//! the window, the render targets of the dice views are resized to the new resolution.
+
+use std::fmt;
+
+use bevy::prelude::*;
+use bevy::render::render_resource::Extent3d;
+use bevy::window::{MonitorSelection, PresentMode, WindowId, WindowMode, WindowPosition};
+use bevy::winit::WinitWindows;
+use bevy_dice::DicePluginSettings;
+
+use crate::storage::{storage, Location};
+use crate::ui::spawn_toast;
+
+pub const DISPLAY_SETTINGS_PATH: &str = "display.txt";
+
+/// Key switching between the window modes
+const WINDOW_MODE_KEY: KeyCode = KeyCode::F11;
+
+/// Key stepping through the resolutions
+const RESOLUTION_KEY: KeyCode = KeyCode::F3;
+
+/// Key toggling vertical sync
+const VSYNC_KEY: KeyCode = KeyCode::F4;
+
+/// Key moving the window to the next monitor
+const MONITOR_KEY: KeyCode = KeyCode::F1;
+
+/// Resolutions offered in game, in pixels
+pub const RESOLUTIONS: [(u32, u32); 5] = [
+    (1280, 720),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+    (3840, 2160),
+];
+
+/// Dice are rendered at this multiple of the size they are shown at, for smooth edges
+const DICE_RENDER_SCALE: u32 = 2;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DisplayMode {
+    #[default]
+    Windowed,
+    /// A window without borders covering the whole monitor, at the resolution of the monitor
+    Borderless,
+    /// Exclusive fullscreen at the resolution of the settings
+    Fullscreen,
+}
+
+impl DisplayMode {
+    pub fn next(self) -> Self {
+        match self {
+            DisplayMode::Windowed => DisplayMode::Borderless,
+            DisplayMode::Borderless => DisplayMode::Fullscreen,
+            DisplayMode::Fullscreen => DisplayMode::Windowed,
+        }
+    }
+
+    pub fn window_mode(self) -> WindowMode {
+        match self {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::Borderless => WindowMode::BorderlessFullscreen,
+            DisplayMode::Fullscreen => WindowMode::SizedFullscreen,
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "windowed" => Some(DisplayMode::Windowed),
+            "borderless" => Some(DisplayMode::Borderless),
+            "fullscreen" => Some(DisplayMode::Fullscreen),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DisplayMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayMode::Windowed => write!(f, "windowed"),
+            DisplayMode::Borderless => write!(f, "borderless"),
+            DisplayMode::Fullscreen => write!(f, "fullscreen"),
+        }
+    }
+}
+
+/// Parses a resolution written as `<width>x<height>`
+pub fn parse_resolution(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or_else(|| "expected <width>x<height>".to_string())?;
+    let size = |value: &str| match value.trim().parse() {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err(format!("invalid size {}", value)),
+    };
+
+    Ok((size(width)?, size(height)?))
+}
+
+#[derive(Resource, Clone, Debug, PartialEq, Eq)]
+pub struct DisplaySettings {
+    pub mode: DisplayMode,
+    /// Size of the window, or of the screen in fullscreen, in pixels
+    pub resolution: (u32, u32),
+    pub vsync: bool,
+    /// Monitor the window opens on, by index, the primary monitor if none
+    pub monitor: Option<usize>,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            mode: DisplayMode::Windowed,
+            resolution: RESOLUTIONS[0],
+            vsync: true,
+            monitor: None,
+        }
+    }
+}
+
+impl DisplaySettings {
+    /// Reads a settings file. Unknown or unreadable lines keep the default of their setting.
+    pub fn parse(source: &str) -> DisplaySettings {
+        let mut settings = DisplaySettings::default();
+
+        for line in source.lines() {
+            let (setting, value) = match line.trim().split_once(' ') {
+                Some((setting, value)) => (setting, value.trim()),
+                None => continue,
+            };
+            match setting {
+                "mode" => {
+                    if let Some(mode) = DisplayMode::parse(value) {
+                        settings.mode = mode;
+                    }
+                }
+                "resolution" => {
+                    if let Ok(resolution) = parse_resolution(value) {
+                        settings.resolution = resolution;
+                    }
+                }
+                "vsync" => settings.vsync = value != "off",
+                "monitor" => settings.monitor = value.parse().ok(),
+                _ => {}
+            }
+        }
+
+        settings
+    }
+
+    /// Settings saved in the config directory, the defaults if there are none
+    pub fn load() -> DisplaySettings {
+        storage(Location::Config)
+            .read(DISPLAY_SETTINGS_PATH)
+            .map(|source| DisplaySettings::parse(&source))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = storage(Location::Config).write(DISPLAY_SETTINGS_PATH, &self.to_string())
+        {
+            warn!("Failed to save {}: {}", DISPLAY_SETTINGS_PATH, err);
+        }
+    }
+
+    fn present_mode(&self) -> PresentMode {
+        match self.vsync {
+            true => PresentMode::AutoVsync,
+            false => PresentMode::AutoNoVsync,
+        }
+    }
+
+    fn monitor_selection(&self) -> MonitorSelection {
+        match self.monitor {
+            Some(index) => MonitorSelection::Index(index),
+            None => MonitorSelection::Primary,
+        }
+    }
+
+    /// Window the app opens with
+    pub fn window_descriptor(&self) -> WindowDescriptor {
+        WindowDescriptor {
+            width: self.resolution.0 as f32,
+            height: self.resolution.1 as f32,
+            mode: self.mode.window_mode(),
+            present_mode: self.present_mode(),
+            position: WindowPosition::Centered,
+            monitor: self.monitor_selection(),
+            ..default()
+        }
+    }
+
+    /// Changes an open window to these settings
+    pub fn apply(&self, window: &mut Window) {
+        // fullscreen modes cover the monitor the window is on, so it's moved there first
+        window.center_window(self.monitor_selection());
+        window.set_mode(self.mode.window_mode());
+        window.set_resolution(self.resolution.0 as f32, self.resolution.1 as f32);
+        window.set_present_mode(self.present_mode());
+    }
+
+    /// The resolution after the current one in [`RESOLUTIONS`], the first one after the last or
+    /// after a resolution that isn't listed
+    pub fn next_resolution(&self) -> (u32, u32) {
+        RESOLUTIONS
+            .iter()
+            .position(|resolution| *resolution == self.resolution)
+            .and_then(|index| RESOLUTIONS.get(index + 1))
+            .copied()
+            .unwrap_or(RESOLUTIONS[0])
+    }
+
+    /// The monitor after the current one of `monitors`, the primary one after the last
+    pub fn next_monitor(&self, monitors: usize) -> Option<usize> {
+        let next = self.monitor.map_or(0, |monitor| monitor + 1);
+        match next < monitors {
+            true => Some(next),
+            false => None,
+        }
+    }
+
+    /// Size of the render target of each of the `fields` dice views, side by side across the
+    /// window
+    pub fn dice_render_size(&self, fields: usize) -> (u32, u32) {
+        let (width, height) = self.resolution;
+        (
+            width / fields.max(1) as u32 * DICE_RENDER_SCALE,
+            height * DICE_RENDER_SCALE,
+        )
+    }
+}
+
+/// Written as the settings file read by [`DisplaySettings::parse`]
+impl fmt::Display for DisplaySettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "mode {}", self.mode)?;
+        writeln!(f, "resolution {}x{}", self.resolution.0, self.resolution.1)?;
+        writeln!(f, "vsync {}", if self.vsync { "on" } else { "off" })?;
+        if let Some(monitor) = self.monitor {
+            writeln!(f, "monitor {}", monitor)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of monitors the window can be moved to, one when the window isn't opened by winit
+pub(crate) fn monitor_count(winit_windows: Option<&WinitWindows>) -> usize {
+    winit_windows
+        .and_then(|winit_windows| winit_windows.get_window(WindowId::primary()))
+        .map_or(1, |window| window.available_monitors().count().max(1))
+}
+
+/// Changes the window mode, the resolution, vertical sync or the monitor on their keys and saves
+/// the settings
+pub(crate) fn display_settings_input(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    mut settings: ResMut<DisplaySettings>,
+    winit_windows: Option<NonSend<WinitWindows>>,
+) {
+    let message = if keys.just_pressed(WINDOW_MODE_KEY) {
+        settings.mode = settings.mode.next();
+        format!("Display: {}", settings.mode)
+    } else if keys.just_pressed(RESOLUTION_KEY) {
+        settings.resolution = settings.next_resolution();
+        format!(
+            "Resolution: {}x{}",
+            settings.resolution.0, settings.resolution.1
+        )
+    } else if keys.just_pressed(VSYNC_KEY) {
+        settings.vsync = !settings.vsync;
+        format!(
+            "Vertical sync: {}",
+            if settings.vsync { "on" } else { "off" }
+        )
+    } else if keys.just_pressed(MONITOR_KEY) {
+        settings.monitor = settings.next_monitor(monitor_count(winit_windows.as_deref()));
+        match settings.monitor {
+            Some(monitor) => format!("Monitor: {}", monitor),
+            None => "Monitor: primary".to_string(),
+        }
+    } else {
+        return;
+    };
+
+    settings.save();
+    spawn_toast(&mut commands, &asset_server, message);
+}
+
+/// Applies changed settings to the window and resizes the render targets of the dice views
+pub(crate) fn apply_display_settings(
+    settings: Res<DisplaySettings>,
+    windows: Option<ResMut<Windows>>,
+    dice_plugin_settings: Res<DicePluginSettings>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    // the window opened with the settings, only changes are applied
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+
+    if let Some(mut windows) = windows {
+        if let Some(window) = windows.get_primary_mut() {
+            settings.apply(window);
+        }
+    }
+
+    let (width, height) = settings.dice_render_size(dice_plugin_settings.render_handles.len());
+    for handle in dice_plugin_settings.render_handles.iter() {
+        if let Some(image) = images.get_mut(handle) {
+            image.resize(Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_files_round_trip() {
+        let settings = DisplaySettings {
+            mode: DisplayMode::Borderless,
+            resolution: (1920, 1080),
+            vsync: false,
+            monitor: Some(1),
+        };
+
+        assert_eq!(DisplaySettings::parse(&settings.to_string()), settings);
+        assert_eq!(
+            DisplaySettings::parse("mode sideways\nresolution big\n"),
+            DisplaySettings::default()
+        );
+        assert!(parse_resolution("0x720").is_err());
+    }
+
+    #[test]
+    fn resolutions_wrap_around() {
+        let mut settings = DisplaySettings::default();
+        assert_eq!(settings.next_resolution(), (1600, 900));
+
+        settings.resolution = (3840, 2160);
+        assert_eq!(settings.next_resolution(), (1280, 720));
+        settings.resolution = (1000, 1000);
+        assert_eq!(settings.next_resolution(), (1280, 720));
+        assert_eq!(settings.dice_render_size(2), (1000, 2000));
+    }
+
+    #[test]
+    fn monitors_wrap_around_to_the_primary_one() {
+        let mut settings = DisplaySettings::default();
+        assert_eq!(settings.next_monitor(2), Some(0));
+
+        settings.monitor = Some(1);
+        assert_eq!(settings.next_monitor(2), None);
+        // a monitor that was unplugged since
+        settings.monitor = Some(4);
+        assert_eq!(settings.next_monitor(2), None);
+        assert_eq!(monitor_count(None), 1);
+    }
+}
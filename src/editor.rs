@@ -0,0 +1,422 @@
+//! Hex-level editing of the regions of a board, the core of a map editor.
+//!
+//! Every operation is checked before it touches the board: a failed edit returns an
+//! [`EditError`] and leaves the board as it was. Regions always keep at least one hex and stay
+//! contiguous.
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+use crate::game::{Board, RegionId};
+use crate::hex::{is_contiguous, HexCoord};
+use crate::map_file::MAX_DICE;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditError {
+    RegionOutOfRange(RegionId),
+    /// The hex isn't part of the region it is taken from
+    HexNotInRegion((isize, isize)),
+    /// The regions don't share a border
+    NotNeighbours(RegionId, RegionId),
+    /// The edit would take every hex of the region
+    EmptiesRegion(RegionId),
+    /// The edit would cut the region in pieces
+    BreaksRegion(RegionId),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::RegionOutOfRange(region) => write!(f, "region {} doesn't exist", region),
+            EditError::HexNotInRegion((q, r)) => {
+                write!(f, "hex {},{} isn't part of the region", q, r)
+            }
+            EditError::NotNeighbours(region_1, region_2) => {
+                write!(
+                    f,
+                    "regions {} and {} don't share a border",
+                    region_1, region_2
+                )
+            }
+            EditError::EmptiesRegion(region) => write!(f, "region {} would have no hexes", region),
+            EditError::BreaksRegion(region) => {
+                write!(f, "region {} would be cut in pieces", region)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+fn check_region(board: &Board, region: RegionId) -> Result<(), EditError> {
+    match region < board.regions.len() {
+        true => Ok(()),
+        false => Err(EditError::RegionOutOfRange(region)),
+    }
+}
+
+/// Moves `hexes` of region `from` to region `to`, taking the owner of `to`. Both regions must be
+/// contiguous afterwards, so the hexes have to border `to`.
+#[allow(dead_code)]
+pub fn reassign_hexes(
+    board: &mut Board,
+    hexes: &[(isize, isize)],
+    from: RegionId,
+    to: RegionId,
+) -> Result<(), EditError> {
+    check_region(board, from)?;
+    check_region(board, to)?;
+    if from == to {
+        return Ok(());
+    }
+
+    let moved: HashSet<(isize, isize)> = hexes.iter().copied().collect();
+    if let Some(hex) = moved
+        .iter()
+        .find(|hex| !board.regions[from].hexes.contains(hex))
+    {
+        return Err(EditError::HexNotInRegion(*hex));
+    }
+
+    let remaining: Vec<(isize, isize)> = board.regions[from]
+        .hexes
+        .iter()
+        .copied()
+        .filter(|hex| !moved.contains(hex))
+        .collect();
+    if remaining.is_empty() {
+        return Err(EditError::EmptiesRegion(from));
+    }
+    if !is_contiguous(&remaining) {
+        return Err(EditError::BreaksRegion(from));
+    }
+
+    let mut grown = board.regions[to].hexes.clone();
+    let mut added: Vec<(isize, isize)> = hexes
+        .iter()
+        .copied()
+        .filter(|hex| !grown.contains(hex))
+        .collect();
+    added.sort_unstable();
+    added.dedup();
+    grown.extend(added);
+    if !is_contiguous(&grown) {
+        return Err(EditError::BreaksRegion(to));
+    }
+
+    let owner = board.regions[to].owner;
+    for hex in moved.iter() {
+        board.hexes.insert(*hex, owner);
+    }
+    board.regions[from].hexes = remaining;
+    board.regions[to].hexes = grown;
+
+    Ok(())
+}
+
+/// Paint bucket: moves up to `limit` hexes of the region holding `start` to region `to`, the
+/// ones closest to `start` first, through neighbouring hexes of the same region
+#[allow(dead_code)]
+pub fn flood_fill(
+    board: &mut Board,
+    start: (isize, isize),
+    to: RegionId,
+    limit: usize,
+) -> Result<(), EditError> {
+    let from = board
+        .regions
+        .iter()
+        .find(|region| region.hexes.contains(&start))
+        .map(|region| region.id)
+        .ok_or(EditError::HexNotInRegion(start))?;
+
+    let mut filled = vec![start];
+    let mut queue = VecDeque::from([start]);
+    while let Some((q, r)) = queue.pop_front() {
+        for neighbor in HexCoord::new(q, r).neighbors() {
+            let coord = (neighbor.q, neighbor.r);
+            if filled.len() < limit
+                && board.regions[from].hexes.contains(&coord)
+                && !filled.contains(&coord)
+            {
+                filled.push(coord);
+                queue.push_back(coord);
+            }
+        }
+    }
+    filled.truncate(limit.max(1));
+
+    reassign_hexes(board, &filled, from, to)
+}
+
+/// Splits `hexes` off `region` into a new region of the same owner, taking half of its dice.
+/// Returns the id of the new region.
+#[allow(dead_code)]
+pub fn split_region(
+    board: &mut Board,
+    region: RegionId,
+    hexes: &[(isize, isize)],
+) -> Result<RegionId, EditError> {
+    check_region(board, region)?;
+    if let Some(hex) = hexes
+        .iter()
+        .find(|hex| !board.regions[region].hexes.contains(hex))
+    {
+        return Err(EditError::HexNotInRegion(*hex));
+    }
+
+    let remaining: Vec<(isize, isize)> = board.regions[region]
+        .hexes
+        .iter()
+        .copied()
+        .filter(|hex| !hexes.contains(hex))
+        .collect();
+    let mut split: Vec<(isize, isize)> = hexes.to_vec();
+    split.sort_unstable();
+    split.dedup();
+    let new_id = board.regions.len();
+    if remaining.is_empty() {
+        return Err(EditError::EmptiesRegion(region));
+    }
+    if split.is_empty() {
+        return Err(EditError::EmptiesRegion(new_id));
+    }
+    if !is_contiguous(&remaining) {
+        return Err(EditError::BreaksRegion(region));
+    }
+    if !is_contiguous(&split) {
+        return Err(EditError::BreaksRegion(new_id));
+    }
+
+    let original = &mut board.regions[region];
+    let split_dice = (original.num_dice / 2).max(1);
+    original.num_dice = original.num_dice.saturating_sub(split_dice).max(1);
+    original.hexes = remaining;
+    let mut new_region = original.clone();
+    new_region.id = new_id;
+    new_region.hexes = split;
+    new_region.num_dice = split_dice;
+    new_region.name = String::new();
+    board.regions.push(new_region);
+
+    Ok(new_id)
+}
+
+/// Merges region `absorbed` into its neighbour `region`, which takes its hexes and dice, up to
+/// [`MAX_DICE`]. The regions after `absorbed` move down one id, objectives follow them.
+#[allow(dead_code)]
+pub fn merge_regions(
+    board: &mut Board,
+    region: RegionId,
+    absorbed: RegionId,
+) -> Result<(), EditError> {
+    check_region(board, region)?;
+    check_region(board, absorbed)?;
+    if region == absorbed || !board.regions[region].is_neighbour(&board.regions[absorbed]) {
+        return Err(EditError::NotNeighbours(region, absorbed));
+    }
+
+    let removed = board.regions.remove(absorbed);
+    for region in board.regions[absorbed..].iter_mut() {
+        region.id -= 1;
+    }
+
+    let region = match region > absorbed {
+        true => region - 1,
+        false => region,
+    };
+    let owner = board.regions[region].owner;
+    for hex in removed.hexes.iter() {
+        board.hexes.insert(*hex, owner);
+    }
+    board.regions[region].hexes.extend(removed.hexes);
+    board.regions[region].num_dice =
+        (board.regions[region].num_dice + removed.num_dice).min(MAX_DICE);
+
+    board.objectives = board
+        .objectives
+        .iter()
+        .map(|objective| match *objective {
+            objective if objective == absorbed => region,
+            objective if objective > absorbed => objective - 1,
+            objective => objective,
+        })
+        .collect();
+    board.objectives.sort_unstable();
+    board.objectives.dedup();
+
+    Ok(())
+}
+
+/// Replaces the right half of the board with a mirror image of its left half, the hexes left of
+/// `column`. Regions reaching `column` or beyond are dropped, every other region gets a mirrored
+/// copy of the same owner and dice right after the original regions, and objectives are
+/// mirrored with their regions. The two halves meet along `column`.
+#[allow(dead_code)]
+pub fn mirror_left_half(board: &mut Board, column: isize) {
+    let kept: Vec<RegionId> = board
+        .regions
+        .iter()
+        .filter(|region| region.hexes.iter().all(|(q, _)| *q < column))
+        .map(|region| region.id)
+        .collect();
+
+    let mut left = Board::default();
+    for (id, region) in kept.iter().enumerate() {
+        let mut region = board.regions[*region].clone();
+        region.id = id;
+        for hex in region.hexes.iter() {
+            left.hexes.insert(*hex, region.owner);
+        }
+        left.regions.push(region);
+    }
+    left.objectives = board
+        .objectives
+        .iter()
+        .filter_map(|objective| kept.iter().position(|region| region == objective))
+        .collect();
+
+    // mirrored across the column of the origin, then moved so column - 1 lands on column
+    let mirrored = left.transformed(|hex| {
+        let mirrored = hex.mirror_horizontally();
+        HexCoord::new(mirrored.q + 2 * column - 1, mirrored.r + 1 - column)
+    });
+
+    let offset = left.regions.len();
+    for mut region in mirrored.regions {
+        region.id += offset;
+        region.name = String::new();
+        left.regions.push(region);
+    }
+    left.hexes.extend(mirrored.hexes);
+    left.objectives = left
+        .objectives
+        .iter()
+        .copied()
+        .chain(
+            mirrored
+                .objectives
+                .iter()
+                .map(|objective| objective + offset),
+        )
+        .collect();
+
+    *board = left;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+
+    fn board() -> Board {
+        // region 0 is a row of four hexes, regions 1 and 2 sit below it
+        MapFile::parse(
+            "players 2\nregion 0 4 0,0 1,0 2,0 3,0\nregion 1 2 0,1\nregion 1 3 2,1\nobjective 2\n",
+        )
+        .unwrap()
+        .board
+    }
+
+    #[test]
+    fn splits_keep_regions_contiguous() {
+        let mut board = board();
+        assert_eq!(
+            split_region(&mut board, 0, &[(1, 0), (2, 0)]),
+            Err(EditError::BreaksRegion(0))
+        );
+        assert_eq!(
+            split_region(&mut board, 0, &[(0, 0), (3, 0)]),
+            Err(EditError::BreaksRegion(3))
+        );
+        assert_eq!(
+            split_region(&mut board, 0, &[(5, 5)]),
+            Err(EditError::HexNotInRegion((5, 5)))
+        );
+        assert_eq!(board.regions[0].hexes.len(), 4);
+
+        assert_eq!(split_region(&mut board, 0, &[(2, 0), (3, 0)]), Ok(3));
+        assert_eq!(board.regions[0].hexes, vec![(0, 0), (1, 0)]);
+        assert_eq!(board.regions[3].hexes, vec![(2, 0), (3, 0)]);
+        assert_eq!(
+            (board.regions[0].num_dice, board.regions[3].num_dice),
+            (2, 2)
+        );
+        assert_eq!(board.regions[3].owner, 0);
+    }
+
+    #[test]
+    fn splits_ignore_repeated_hexes() {
+        let mut board = board();
+
+        assert_eq!(
+            split_region(&mut board, 0, &[(3, 0), (2, 0), (3, 0)]),
+            Ok(3)
+        );
+        assert_eq!(board.regions[3].hexes, vec![(2, 0), (3, 0)]);
+        assert_eq!(board.regions[0].hexes, vec![(0, 0), (1, 0)]);
+    }
+
+    #[test]
+    fn merges_keep_at_most_the_maximum_of_dice() {
+        let mut board = board();
+        board.regions[0].num_dice = 6;
+
+        merge_regions(&mut board, 2, 0).unwrap();
+        assert_eq!(board.regions[1].num_dice, MAX_DICE);
+    }
+
+    #[test]
+    fn merges_neighbours_only() {
+        let mut board = board();
+        assert_eq!(
+            merge_regions(&mut board, 1, 2),
+            Err(EditError::NotNeighbours(1, 2))
+        );
+
+        merge_regions(&mut board, 2, 0).unwrap();
+        assert_eq!(board.regions.len(), 2);
+        assert_eq!(board.regions[1].id, 1);
+        assert_eq!(board.regions[1].hexes.len(), 5);
+        assert_eq!(board.regions[1].num_dice, 7);
+        assert_eq!(board.hexes[&(0, 0)], 1);
+        assert_eq!(board.objectives, vec![1]);
+        assert!(board.regions[1].is_contiguous());
+    }
+
+    #[test]
+    fn mirrored_halves_meet_at_the_column() {
+        let mut board = board();
+        mirror_left_half(&mut board, 3);
+
+        // region 0 reached column 3 and is dropped
+        assert_eq!(board.regions.len(), 4);
+        assert_eq!(board.regions[0].hexes, vec![(0, 1)]);
+        assert_eq!(board.regions[1].hexes, vec![(2, 1)]);
+        assert_eq!(board.regions[2].hexes, vec![(5, -1)]);
+        assert_eq!(board.regions[3].hexes, vec![(3, 1)]);
+        assert_eq!(board.regions[3].num_dice, 3);
+        assert_eq!(board.hexes.len(), 4);
+        assert_eq!(board.objectives, vec![1, 3]);
+        assert!(board.regions[1].is_neighbour(&board.regions[3]));
+        assert!(board
+            .regions
+            .iter()
+            .enumerate()
+            .all(|(id, region)| region.id == id));
+    }
+
+    #[test]
+    fn flood_fill_moves_the_closest_hexes() {
+        let mut board = board();
+        flood_fill(&mut board, (3, 0), 2, 2).unwrap();
+        assert_eq!(board.regions[0].hexes, vec![(0, 0), (1, 0)]);
+        assert_eq!(board.regions[2].hexes, vec![(2, 1), (3, 0), (2, 0)]);
+        assert_eq!(board.hexes[&(3, 0)], 1);
+
+        assert_eq!(
+            flood_fill(&mut board, (0, 0), 2, 10),
+            Err(EditError::EmptiesRegion(0))
+        );
+    }
+}
@@ -1,120 +1,252 @@
-use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::time::Duration;
 
-use rand::Rng;
-
 use bevy::prelude::*;
 use bevy_dice::{DiceRollResult, DiceRollStartEvent};
-use bevy_kira_audio::prelude::*;
-use bevy_mod_picking::{PickingEvent, SelectionEvent};
 
-use crate::board::{draw_board, StackRankDiceGameBoardElement};
-use crate::game::{GameLogEntry, SelectedRegion};
+use crate::board::{BoardDrawer, StackRankDiceGameBoardElement};
+use crate::fair_dice::{FairBattleDice, FairDice};
+use crate::game::{
+    BattleId, DiceRoll, GameLogEntry, RegionId, SelectedRegion, SelectionOutcome, TurnPhase,
+};
 use crate::game::{GameState, Region};
-use crate::tiered_prng::PrngMapResource;
-use crate::ui::{DiceRollUI, StackRankDiceUI};
-
-/// Event that is fired when two regions on a map are entering a clash
-#[allow(dead_code)]
+use crate::input::{EventInputAction, InputAction};
+use crate::logic_step::LOGIC_STEP;
+use crate::profiles::PlayerProfiles;
+use crate::rematch::{spawn_rematch_buttons, GameOverScreen, Rematch};
+use crate::rules::GameRules;
+use crate::seats::Seats;
+use crate::session::{MatchSession, NextGameTimer};
+use crate::sounds::{EventSound, SoundEvent};
+use crate::ui::{spawn_toast, DiceRollUI, StackRankDiceUI};
+
+/// Event that is fired when two regions on a map are entering a clash. Owners and dice of the
+/// regions are read from the [`GameState`] when the clash starts.
+#[derive(Reflect, FromReflect)]
 pub(crate) struct EventPlayerMoveStart {
-    region_1: Region,
-    region_2: Region,
-    player_1: usize,
-    player_2: usize,
+    // Attacking region
+    pub(crate) region_1: RegionId,
+    // Defending region
+    pub(crate) region_2: RegionId,
 }
 
 /// Event that is fired when a clash between two regions on a map is resolved
 /// and the winner is determined
-#[allow(dead_code)]
+#[derive(Reflect, FromReflect)]
 pub(crate) struct EventPlayerMoveEnd {
-    player_1: usize,
-    player_2: usize,
-    region_1: Region,
-    region_2: Region,
-    region_1_dice_result: Vec<usize>,
-    region_2_dice_result: Vec<usize>,
+    battle: BattleId,
+    region_1: RegionId,
+    region_2: RegionId,
+    region_1_dice_result: DiceRoll,
+    region_2_dice_result: DiceRoll,
+}
+
+/// Event that is fired once the outcome of a clash has been applied to the board. Carries what
+/// the battle was, so readers don't have to guess it from the board, which may have changed
+/// again by the time they run.
+#[derive(Reflect, FromReflect)]
+pub(crate) struct EventBattleResolved {
+    pub(crate) region_1: RegionId,
+    pub(crate) region_2: RegionId,
+    // Owner of the attacking region
+    pub(crate) player_1: usize,
+    // Owner of the defending region before the battle
+    pub(crate) player_2: usize,
+    pub(crate) region_1_dice_result: DiceRoll,
+    pub(crate) region_2_dice_result: DiceRoll,
+    pub(crate) attacker_won: bool,
+    // Number of dice the winner moved into the conquered region
+    pub(crate) transferred: usize,
+    // Turn counter of the turn the battle was fought in
+    pub(crate) turn: usize,
+}
+
+/// Event that is fired when a die from the reserve has been placed onto a region
+#[derive(Reflect, FromReflect)]
+pub(crate) struct EventReinforcementPlaced {
+    pub(crate) region: RegionId,
 }
 
+/// Event that is fired when the board changed outside of a clash and has to be redrawn
+#[derive(Reflect, FromReflect)]
+pub(crate) struct EventBoardChanged;
+
 /// Event that is fired when a played has won a game
+#[derive(Reflect, FromReflect)]
 pub(crate) struct EventGameOver {
     // An index of a winner
-    winner: usize,
+    pub(crate) winner: usize,
 }
 
 /// Event that is fired when a turn of a player is started
-#[allow(dead_code)]
+#[derive(Reflect, FromReflect)]
 pub(crate) struct EventTurnStart {
     // An index of a player
-    player: usize,
+    pub(crate) player: usize,
+    // Turn counter of the turn started
+    pub(crate) turn: usize,
 }
 
-/// Event that is fired when a turn of a player is started
+/// Event that is fired when the player in turn chose to end its attacks
+#[derive(Reflect, FromReflect)]
+pub(crate) struct EventTurnPassed {
+    // An index of a player
+    pub(crate) player: usize,
+}
+
+/// Event that is fired when a turn of a player is ended
 #[allow(dead_code)]
+#[derive(Reflect, FromReflect)]
 pub(crate) struct EventTurnEnd {
     // An index of a player
-    player: usize,
+    pub(crate) player: usize,
 }
 
+/// Region entity the player picked this frame, by clicking it or by confirming the hovered one.
+/// Cancelling drops a picked region.
 pub(crate) fn filter_just_selected_event(
-    mut event_reader: EventReader<PickingEvent>,
+    mut event_reader: EventReader<EventInputAction>,
+    hovered_query: &Query<(Entity, &Interaction), With<Region>>,
+    selected_region: &mut SelectedRegion,
 ) -> Option<Entity> {
     for event in event_reader.iter() {
-        if let PickingEvent::Selection(SelectionEvent::JustSelected(selection_event)) = event {
-            return Some(*selection_event);
+        match event.action {
+            InputAction::Select(entity) => return Some(entity),
+            InputAction::Confirm => {
+                let hovered = hovered_query
+                    .iter()
+                    .find(|(_, interaction)| **interaction == Interaction::Hovered);
+                if let Some((entity, _)) = hovered {
+                    return Some(entity);
+                }
+            }
+            InputAction::Cancel => selected_region.cancel(),
+            _ => {}
         }
     }
 
     None
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn event_region_selected(
+    mut commands: Commands,
     mut selected_region: ResMut<SelectedRegion>,
-    picking_events: EventReader<PickingEvent>,
+    event_input_action_reader: EventReader<EventInputAction>,
     regions: Query<(Entity, &Region)>,
-    game_state: Res<GameState>,
+    hovered_query: Query<(Entity, &Interaction), With<Region>>,
+    mut game_state: ResMut<GameState>,
+    rules: Res<GameRules>,
+    asset_server: Res<AssetServer>,
     mut event_writer: EventWriter<EventPlayerMoveStart>,
+    mut event_reinforcement_placed_writer: EventWriter<EventReinforcementPlaced>,
+    mut event_board_changed_writer: EventWriter<EventBoardChanged>,
+    seats: Res<Seats>,
+    profiles: Res<PlayerProfiles>,
 ) {
-    let selected_entity = filter_just_selected_event(picking_events);
-
-    if selected_entity.is_none() {
+    // bots and remote players make their own moves
+    if !seats.is_local_human(game_state.turn_of_player) || game_state.winner().is_some() {
         return;
     }
 
-    let region = regions.get(selected_entity.unwrap()).unwrap().1;
-
-    if region.owner != game_state.turn_of_player {
-        if selected_region.region.is_some() {
-            let region_1 = selected_region.region.clone().unwrap();
-            let region_2 = region.clone();
-            if region_1.is_opponent(&region_2) {
-                // Attack a neighbour
-                let event = EventPlayerMoveStart {
-                    player_1: region_1.owner,
-                    player_2: region_2.owner,
-                    region_1,
-                    region_2,
-                };
-                event_writer.send(event);
-            }
+    let selected_entity = filter_just_selected_event(
+        event_input_action_reader,
+        &hovered_query,
+        &mut selected_region,
+    );
+    let (entity, region) = match selected_entity.and_then(|entity| regions.get(entity).ok()) {
+        Some(selected) => selected,
+        None => return,
+    };
+
+    // During the reinforcement phase a click places a die from the reserve
+    if game_state.turn_phase == TurnPhase::Reinforcement {
+        if game_state.place_reinforcement(region.id, &rules) {
+            event_reinforcement_placed_writer.send(EventReinforcementPlaced { region: region.id });
+            event_board_changed_writer.send(EventBoardChanged);
         }
 
         selected_region.deselect();
-    } else {
-        selected_region.select(selected_entity.unwrap(), region.clone());
+        return;
+    }
+
+    match selected_region.click(entity, region.id, &game_state, &rules) {
+        SelectionOutcome::Attack { attacker, defender } => {
+            // Attack a neighbour
+            event_writer.send(EventPlayerMoveStart {
+                region_1: attacker,
+                region_2: defender,
+            });
+        }
+        SelectionOutcome::Truce { player } => spawn_toast(
+            &mut commands,
+            &asset_server,
+            format!("{} is under a truce", profiles.name(player)),
+        ),
+        SelectionOutcome::None => {}
     }
 }
 
 #[derive(Component)]
 pub(crate) struct DiceRollTimer {
+    battle: BattleId,
     timer: Timer,
 }
 
+/// Roll handed to the dice plugin whose result hasn't come back yet
+struct PendingDiceRoll {
+    battle: BattleId,
+    // Number of dice of each side
+    num_dice: Vec<usize>,
+}
+
+/// Battles waiting for their dice, in the order their rolls were started. The dice plugin
+/// reports results in the same order without saying which roll they belong to, so results are
+/// matched to the oldest pending roll and ignored when nothing is pending.
+#[derive(Resource, Default)]
+pub(crate) struct PendingDiceRolls {
+    rolls: VecDeque<PendingDiceRoll>,
+}
+
+impl PendingDiceRolls {
+    /// Remembers that the dice of `battle` started rolling
+    fn push(&mut self, battle: BattleId, num_dice: Vec<usize>) {
+        self.rolls.push_back(PendingDiceRoll { battle, num_dice });
+    }
+
+    /// Battle the dice `values` of each side were rolled for. Values that don't fit the oldest
+    /// pending roll are spurious and leave it pending.
+    fn match_result(&mut self, values: &[Vec<usize>]) -> Option<BattleId> {
+        let pending = self.rolls.front()?;
+        let fits = values.len() == pending.num_dice.len()
+            && values
+                .iter()
+                .zip(pending.num_dice.iter())
+                .all(|(side, num_dice)| side.len() == *num_dice);
+        if !fits {
+            return None;
+        }
+
+        self.rolls.pop_front().map(|pending| pending.battle)
+    }
+
+    /// Stops waiting for the dice of `battle`, whose roll timed out. Results still coming back
+    /// for it no longer fit the oldest pending roll and are ignored.
+    fn give_up(&mut self, battle: BattleId) {
+        self.rolls.retain(|pending| pending.battle != battle);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn event_player_move_start(
     mut commands: Commands,
     mut region_clash_event_reader: EventReader<EventPlayerMoveStart>,
     mut dice_roll_started_writer: EventWriter<DiceRollStartEvent>,
     mut dice_roll_view_query: Query<(Entity, &mut Visibility, &DiceRollUI)>,
     mut game_state: ResMut<GameState>,
+    mut pending_dice_rolls: ResMut<PendingDiceRolls>,
+    rules: Res<GameRules>,
+    mut fair_dice: Option<ResMut<FairDice>>,
 ) {
     let turn_of_player = game_state.turn_of_player;
     let turn_counter = game_state.turn_counter;
@@ -125,25 +257,46 @@ pub(crate) fn event_player_move_start(
             num_dice: Vec::new(),
         };
 
-        dice_roll_started.num_dice.push(event.region_1.num_dice);
-        dice_roll_started.num_dice.push(event.region_2.num_dice);
-
-        for (_, mut v, _) in dice_roll_view_query.iter_mut() {
-            v.is_visible = true;
-        }
+        let regions = &game_state.board.regions;
+        dice_roll_started
+            .num_dice
+            .push(rules.attacker_roll_size(regions[event.region_1].num_dice));
+        dice_roll_started
+            .num_dice
+            .push(rules.defender_roll_size(regions[event.region_2].num_dice));
 
-        game_state.game_log.push(GameLogEntry {
+        let battle = game_state.log_attack(GameLogEntry {
             turn_of_player,
-            region_1: event.region_1.clone(),
-            region_2: event.region_2.clone(),
-            region_1_dice_result: Vec::new(),
-            region_2_dice_result: Vec::new(),
+            region_1: event.region_1,
+            region_2: event.region_2,
+            region_1_dice_result: DiceRoll::new(),
+            region_2_dice_result: DiceRoll::new(),
             turn_counter,
+            ..Default::default()
         });
 
-        dice_roll_started_writer.send(dice_roll_started);
+        // the host of a network game draws the dice from the seed it committed to
+        let fair_roll = fair_dice.as_mut().and_then(|fair_dice| {
+            fair_dice.roll(event.region_1, event.region_2, &dice_roll_started.num_dice)
+        });
+        match fair_roll {
+            Some(results) => {
+                let log_entry = &mut game_state.game_log[battle];
+                log_entry.region_1_dice_result = results[0].clone();
+                log_entry.region_2_dice_result = results[1].clone();
+            }
+            None => {
+                for (_, mut v, _) in dice_roll_view_query.iter_mut() {
+                    v.is_visible = true;
+                }
+
+                pending_dice_rolls.push(battle, dice_roll_started.num_dice.clone());
+                dice_roll_started_writer.send(dice_roll_started);
+            }
+        }
 
         commands.spawn(()).insert(DiceRollTimer {
+            battle,
             timer: Timer::new(Duration::from_secs(3), TimerMode::Once),
         });
     }
@@ -152,16 +305,27 @@ pub(crate) fn event_player_move_start(
 pub(crate) fn event_dice_roll_result(
     mut dice_rolls: EventReader<DiceRollResult>,
     mut game_state: ResMut<GameState>,
-    asset_server: Res<AssetServer>,
-    audio: Res<bevy_kira_audio::prelude::Audio>,
+    mut pending_dice_rolls: ResMut<PendingDiceRolls>,
+    mut event_sound_writer: EventWriter<EventSound>,
 ) {
     for event in dice_rolls.iter() {
-        let last_log_entry = game_state.game_log.last_mut().unwrap();
+        let log_entry = match pending_dice_rolls
+            .match_result(&event.values)
+            .and_then(|battle| game_state.game_log.get_mut(battle))
+        {
+            Some(log_entry) => log_entry,
+            None => {
+                warn!("Ignoring dice results no battle is waiting for");
+                continue;
+            }
+        };
 
-        audio.play(asset_server.load("sounds/throw.wav"));
+        event_sound_writer.send(EventSound {
+            sound: SoundEvent::DiceThrow,
+        });
 
-        last_log_entry.region_1_dice_result = event.values[0].clone();
-        last_log_entry.region_2_dice_result = event.values[1].clone();
+        log_entry.region_1_dice_result = DiceRoll::from_slice(&event.values[0]);
+        log_entry.region_2_dice_result = DiceRoll::from_slice(&event.values[1]);
     }
 }
 
@@ -169,28 +333,33 @@ pub(crate) fn event_dice_rolls_complete(
     mut commands: Commands,
     mut dice_roll_timer_query: Query<(Entity, &mut DiceRollTimer)>,
     mut dice_roll_ui_query: Query<(Entity, &mut Visibility, &mut DiceRollUI)>,
-    time: Res<Time>,
     mut region_clash_end_event_writer: EventWriter<EventPlayerMoveEnd>,
-    mut game_state: ResMut<GameState>,
+    mut pending_dice_rolls: ResMut<PendingDiceRolls>,
+    game_state: Res<GameState>,
 ) {
     for (entity, mut fuse_timer) in dice_roll_timer_query.iter_mut() {
-        fuse_timer.timer.tick(time.delta());
+        fuse_timer.timer.tick(LOGIC_STEP);
         if fuse_timer.timer.finished() {
             commands.entity(entity).despawn();
+            // the result of the roll never came, later results belong to the next rolls
+            pending_dice_rolls.give_up(fuse_timer.battle);
 
             for (_, mut v, _) in dice_roll_ui_query.iter_mut() {
                 v.is_visible = false;
             }
 
-            let last_log_entry = game_state.game_log.last_mut().unwrap();
+            let battle = fuse_timer.battle;
+            let log_entry = match game_state.game_log.get(battle) {
+                Some(log_entry) => log_entry,
+                None => continue,
+            };
 
             region_clash_end_event_writer.send(EventPlayerMoveEnd {
-                player_1: last_log_entry.region_1.owner,
-                player_2: last_log_entry.region_2.owner,
-                region_1: last_log_entry.region_1.clone(),
-                region_2: last_log_entry.region_2.clone(),
-                region_1_dice_result: last_log_entry.region_1_dice_result.clone(),
-                region_2_dice_result: last_log_entry.region_2_dice_result.clone(),
+                battle,
+                region_1: log_entry.region_1,
+                region_2: log_entry.region_2,
+                region_1_dice_result: log_entry.region_1_dice_result.clone(),
+                region_2_dice_result: log_entry.region_2_dice_result.clone(),
             })
         }
     }
@@ -200,173 +369,346 @@ pub(crate) fn event_dice_rolls_complete(
 pub(crate) fn event_player_move_end(
     mut region_clash_end_event_reader: EventReader<EventPlayerMoveEnd>,
     mut game_state: ResMut<GameState>,
-    mut game_elements_query: Query<(Entity, &StackRankDiceGameBoardElement)>,
-    asset_server: Res<AssetServer>,
-    mut commands: Commands,
-    meshes: ResMut<Assets<Mesh>>,
-    map_prng: ResMut<PrngMapResource>,
-    materials: ResMut<Assets<StandardMaterial>>,
-    mut selected_region: ResMut<SelectedRegion>,
-    audio: Res<bevy_kira_audio::prelude::Audio>,
+    rules: Res<GameRules>,
+    mut board_drawer: BoardDrawer,
+    mut event_sound_writer: EventWriter<EventSound>,
+    mut event_battle_resolved_writer: EventWriter<EventBattleResolved>,
     mut event_game_over_writer: EventWriter<EventGameOver>,
     mut event_turn_end_writer: EventWriter<EventTurnEnd>,
     mut event_turn_start_writer: EventWriter<EventTurnStart>,
+    mut event_board_changed_writer: EventWriter<EventBoardChanged>,
+    profiles: Res<PlayerProfiles>,
+    mut fair_dice: Option<ResMut<FairDice>>,
 ) {
     let mut rng = rand::thread_rng();
     let mut redraw_board = false;
 
     for e in region_clash_end_event_reader.iter() {
-        let result_1: usize = e.region_1_dice_result.iter().sum();
-        let result_2: usize = e.region_2_dice_result.iter().sum();
-
-        if result_1 > result_2 {
-            // win a region
-            game_state.board.regions[e.region_2.id].owner = e.region_1.owner;
-            if e.region_1.num_dice > 1 {
-                game_state.board.regions[e.region_2.id].num_dice =
-                    rng.gen_range(1..e.region_1.num_dice);
-                game_state.board.regions[e.region_1.id].num_dice -=
-                    game_state.board.regions[e.region_2.id].num_dice - 1;
-            }
+        // battles still rolling when the game was decided don't change the final board
+        if game_state.winner().is_some() {
+            continue;
+        }
 
-            audio.play(asset_server.load("sounds/win.wav"));
-        } else {
-            // lose a region
-            game_state.board.regions[e.region_1.id].owner = e.region_2.owner;
-            if e.region_2.num_dice > 1 {
-                game_state.board.regions[e.region_1.id].num_dice =
-                    rng.gen_range(1..e.region_2.num_dice);
-                game_state.board.regions[e.region_2.id].num_dice -=
-                    game_state.board.regions[e.region_1.id].num_dice - 1;
-            }
+        let player_1 = game_state.board.regions[e.region_1].owner;
+        let player_2 = game_state.board.regions[e.region_2].owner;
+        // the host of a network game draws rerolls and transfers from its committed dice too
+        let outcome = match fair_dice.as_deref_mut() {
+            Some(fair_dice) => game_state.resolve_battle(
+                e.region_1,
+                e.region_2,
+                &e.region_1_dice_result,
+                &e.region_2_dice_result,
+                &rules,
+                &mut FairBattleDice {
+                    dice: fair_dice,
+                    region_1: e.region_1,
+                    region_2: e.region_2,
+                },
+            ),
+            None => game_state.resolve_battle(
+                e.region_1,
+                e.region_2,
+                &e.region_1_dice_result,
+                &e.region_2_dice_result,
+                &rules,
+                &mut rng,
+            ),
+        };
 
-            audio.play(asset_server.load("sounds/loss.wav"));
-        }
+        event_sound_writer.send(EventSound {
+            sound: match outcome.attacker_won {
+                true => SoundEvent::BattleWon,
+                false => SoundEvent::BattleLost,
+            },
+        });
 
-        for (e, _) in game_elements_query.iter_mut() {
-            commands.entity(e).despawn_recursive();
+        // a tie may have been rerolled, keep the dice that decided the battle
+        if let Some(log_entry) = game_state.game_log.get_mut(e.battle) {
+            log_entry.region_1_dice_result = outcome.attacker_roll.clone();
+            log_entry.region_2_dice_result = outcome.defender_roll.clone();
         }
 
+        event_battle_resolved_writer.send(EventBattleResolved {
+            region_1: e.region_1,
+            region_2: e.region_2,
+            player_1,
+            player_2,
+            region_1_dice_result: outcome.attacker_roll,
+            region_2_dice_result: outcome.defender_roll,
+            attacker_won: outcome.attacker_won,
+            transferred: outcome.transferred,
+            turn: game_state.turn_counter,
+        });
+
         redraw_board = true;
     }
 
-    // check whether it's time to switch turn
-    let region_made_move_this_turn: Vec<Region> = game_state
-        .game_log
-        .iter()
-        .filter(|gl| {
-            gl.turn_counter == game_state.turn_counter
-                && gl.turn_of_player == game_state.turn_of_player
-        })
-        .map(|gl| gl.region_1.clone())
-        .collect();
-
-    let regions_able_to_move_this_turn = game_state.board.regions.iter().filter(|gl| {
-        gl.owner == game_state.turn_of_player
-            && region_made_move_this_turn
-                .iter()
-                .filter(|r| r.id == gl.id)
-                .count()
-                == 0
-    });
+    // check whether it's time to end the game
+    if let Some(winner) = game_state.winner() {
+        if redraw_board {
+            event_game_over_writer.send(EventGameOver { winner });
+        }
+        return;
+    }
 
     // check whether it's time to switch turn
-    let number_of_unblocked_regions = regions_able_to_move_this_turn
-        .filter(|r1| {
-            game_state
-                .board
-                .regions
-                .iter()
-                .filter(|r2| r2.is_opponent(r1))
-                .count()
-                > 0
-        })
-        .count();
-
-    if number_of_unblocked_regions == 0 {
-        event_turn_end_writer.send(EventTurnEnd {
+    let player = game_state.turn_of_player;
+    let turn_phase = game_state.turn_phase;
+    let mut board_changed = false;
+    if !game_state.current_player_can_move(&rules) && game_state.finish_turn(&rules) {
+        event_turn_end_writer.send(EventTurnEnd { player });
+        event_turn_start_writer.send(EventTurnStart {
             player: game_state.turn_of_player,
+            turn: game_state.turn_counter,
         });
 
-        game_state.turn_of_player += 1;
-        if game_state.turn_of_player >= game_state.number_of_players {
-            game_state.turn_of_player = 0;
-        }
+        // reinforcements may have been spread over the board
+        board_changed = true;
+    }
+
+    // regions that can be clicked differ between the attack and the reinforcement phase
+    if !redraw_board && (board_changed || game_state.turn_phase != turn_phase) {
+        event_board_changed_writer.send(EventBoardChanged);
+    }
+
+    if redraw_board {
+        board_drawer.redraw(&game_state, &profiles);
+    }
+}
+
+/// Ends the attacks of the local player in turn when it asks to
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn event_end_turn(
+    mut event_input_action_reader: EventReader<EventInputAction>,
+    mut game_state: ResMut<GameState>,
+    rules: Res<GameRules>,
+    seats: Res<Seats>,
+    mut selected_region: ResMut<SelectedRegion>,
+    dice_roll_ui_query: Query<&Visibility, With<DiceRollUI>>,
+    mut event_turn_passed_writer: EventWriter<EventTurnPassed>,
+    mut event_turn_end_writer: EventWriter<EventTurnEnd>,
+    mut event_turn_start_writer: EventWriter<EventTurnStart>,
+    mut event_board_changed_writer: EventWriter<EventBoardChanged>,
+) {
+    let end_turn = event_input_action_reader
+        .iter()
+        .any(|event| event.action == InputAction::EndTurn);
+
+    let player = game_state.turn_of_player;
+    // the dice stay on screen until the battle is resolved
+    let battle_pending = dice_roll_ui_query.iter().any(|v| v.is_visible);
+    if !end_turn
+        || battle_pending
+        || game_state.turn_phase != TurnPhase::Attack
+        || game_state.winner().is_some()
+        || !seats.is_local_human(player)
+    {
+        return;
+    }
 
+    selected_region.deselect();
+    event_turn_passed_writer.send(EventTurnPassed { player });
+    // in blitz mode ending the turn is the order to hold, the round goes on
+    if rules.blitz {
+        return;
+    }
+    if game_state.end_turn(&rules) {
+        event_turn_end_writer.send(EventTurnEnd { player });
         event_turn_start_writer.send(EventTurnStart {
             player: game_state.turn_of_player,
+            turn: game_state.turn_counter,
         });
-
-        game_state.turn_counter += 1;
     }
 
-    // check whether it's time to end the game
-    let mut regions_by_player: HashMap<usize, usize> = HashMap::new();
-    for region in game_state.board.regions.iter() {
-        let current_count = *regions_by_player.get(&region.owner).unwrap_or(&0);
-        regions_by_player.insert(region.owner, current_count + 1);
+    event_board_changed_writer.send(EventBoardChanged);
+}
+
+/// Passes the turn of a player that starts it without any legal attack, letting everyone know
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn pass_blocked_player(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut game_state: ResMut<GameState>,
+    rules: Res<GameRules>,
+    mut event_turn_end_writer: EventWriter<EventTurnEnd>,
+    mut event_turn_start_writer: EventWriter<EventTurnStart>,
+    mut event_board_changed_writer: EventWriter<EventBoardChanged>,
+    profiles: Res<PlayerProfiles>,
+) {
+    let turn_counter = game_state.turn_counter;
+    let player = match game_state.pass(&rules) {
+        Some(player) => player,
+        None => return,
+    };
+
+    // players without any region left pass silently
+    if game_state
+        .board
+        .regions
+        .iter()
+        .any(|region| region.owner == player)
+    {
+        spawn_toast(
+            &mut commands,
+            &asset_server,
+            format!("{} passes", profiles.name(player)),
+        );
     }
 
-    for (player, number_of_regions) in regions_by_player.iter() {
-        if *number_of_regions == game_state.board.regions.len() {
-            event_game_over_writer.send(EventGameOver { winner: *player });
-            return;
-        }
+    if game_state.turn_counter != turn_counter {
+        event_turn_end_writer.send(EventTurnEnd { player });
+        event_turn_start_writer.send(EventTurnStart {
+            player: game_state.turn_of_player,
+            turn: game_state.turn_counter,
+        });
     }
 
-    if redraw_board {
-        selected_region.deselect();
-        draw_board(
-            asset_server,
-            commands,
-            meshes,
-            map_prng,
-            materials,
-            game_state,
-        );
+    event_board_changed_writer.send(EventBoardChanged);
+}
+
+pub(crate) fn event_board_changed(
+    mut event_board_changed_reader: EventReader<EventBoardChanged>,
+    mut board_drawer: BoardDrawer,
+    game_state: Res<GameState>,
+    profiles: Res<PlayerProfiles>,
+) {
+    // the board is gone once the game is over, the game-over screen stays
+    if event_board_changed_reader.iter().count() == 0 || game_state.winner().is_some() {
+        return;
     }
+
+    board_drawer.redraw(&game_state, &profiles);
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn event_game_over(
     mut commands: Commands,
     mut event_game_over_reader: EventReader<EventGameOver>,
     mut game_elements_query: Query<(Entity, &StackRankDiceGameBoardElement)>,
-    mut game_ui_elements_query: Query<(Entity, &StackRankDiceUI)>,
+    mut game_ui_elements_query: Query<(Entity, &mut Visibility), With<StackRankDiceUI>>,
+    mut match_session: Option<ResMut<MatchSession>>,
+    mut rematch: ResMut<Rematch>,
+    game_state: Res<GameState>,
     asset_server: Res<AssetServer>,
-    _audio: Res<bevy_kira_audio::prelude::Audio>,
+    profiles: Res<PlayerProfiles>,
+    mut event_sound_writer: EventWriter<EventSound>,
 ) {
     for e in event_game_over_reader.iter() {
         for (e, _) in game_elements_query.iter_mut() {
             commands.entity(e).despawn_recursive();
         }
 
-        for (e, _) in game_ui_elements_query.iter_mut() {
-            commands.entity(e).despawn_recursive();
-        }
+        rematch.record_win(e.winner, game_state.number_of_players);
+
+        let message = match match_session.as_mut() {
+            Some(match_session) => {
+                match_session.record_win(e.winner);
+                let match_winner = match_session.match_winner();
+                if match_winner.is_none() {
+                    // Keep the HUD around, the next game of the match starts shortly
+                    commands
+                        .spawn(game_over_text(
+                            format!(
+                                "{} wins game {}!",
+                                profiles.name(e.winner),
+                                match_session.game_number
+                            ),
+                            &asset_server,
+                        ))
+                        .insert(NextGameTimer::default())
+                        .insert(StackRankDiceUI);
+                    continue;
+                }
+
+                // the last game may be won by someone other than the player with the most wins
+                format!(
+                    "{} wins the match {}!",
+                    profiles.name(match_winner.unwrap_or(e.winner)),
+                    match_session.score_text()
+                )
+            }
+            None => format!("{} wins!", profiles.name(e.winner)),
+        };
+        let message = match rematch.is_series() {
+            true => format!("{}\nSeries {}", message, rematch.score_text()),
+            false => message,
+        };
+
+        // the HUD comes back with a rematch
+        rematch.hide_hud(game_ui_elements_query.iter_mut());
 
         commands
-            .spawn(
-                TextBundle::from_section(
-                    format!("Player {} wins!", e.winner + 1),
-                    TextStyle {
-                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                        font_size: 50.0,
-                        color: Color::WHITE,
-                    },
-                )
-                .with_text_alignment(TextAlignment::TOP_CENTER)
-                .with_style(Style {
-                    position_type: PositionType::Absolute,
-                    position: UiRect {
-                        bottom: Val::Percent(50.0),
-                        left: Val::Percent(45.0),
-                        ..default()
-                    },
-                    ..default()
-                }),
-            )
+            .spawn(game_over_text(message, &asset_server))
+            .insert(GameOverScreen)
             .insert(StackRankDiceUI);
+        spawn_rematch_buttons(&mut commands, &asset_server, &rematch);
+
+        event_sound_writer.send(EventSound {
+            sound: SoundEvent::GameOver,
+        });
+    }
+}
+
+pub(crate) fn game_over_text(message: String, asset_server: &AssetServer) -> TextBundle {
+    TextBundle::from_section(
+        message,
+        TextStyle {
+            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+            font_size: 50.0,
+            color: Color::WHITE,
+        },
+    )
+    .with_text_alignment(TextAlignment::TOP_CENTER)
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        position: UiRect {
+            bottom: Val::Percent(50.0),
+            left: Val::Percent(45.0),
+            ..default()
+        },
+        ..default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // _audio.play(asset_server.load("sounds/game_over.wav"));
+    #[test]
+    fn dice_results_go_to_the_oldest_pending_battle() {
+        let mut pending_dice_rolls = PendingDiceRolls::default();
+        assert_eq!(pending_dice_rolls.match_result(&[vec![1], vec![2]]), None);
+
+        pending_dice_rolls.push(3, vec![2, 1]);
+        pending_dice_rolls.push(4, vec![1, 1]);
+
+        // results not fitting the oldest roll leave it pending
+        assert_eq!(pending_dice_rolls.match_result(&[vec![1], vec![2]]), None);
+        assert_eq!(
+            pending_dice_rolls.match_result(&[vec![6, 6], vec![1]]),
+            Some(3)
+        );
+        assert_eq!(
+            pending_dice_rolls.match_result(&[vec![5], vec![5]]),
+            Some(4)
+        );
+        assert_eq!(pending_dice_rolls.match_result(&[vec![5], vec![5]]), None);
+    }
+
+    #[test]
+    fn timed_out_rolls_stop_waiting() {
+        let mut pending_dice_rolls = PendingDiceRolls::default();
+        pending_dice_rolls.push(3, vec![2, 1]);
+        pending_dice_rolls.push(4, vec![1, 1]);
+
+        pending_dice_rolls.give_up(3);
+        assert_eq!(
+            pending_dice_rolls.match_result(&[vec![6, 6], vec![1]]),
+            None
+        );
+        assert_eq!(
+            pending_dice_rolls.match_result(&[vec![5], vec![5]]),
+            Some(4)
+        );
     }
 }
@@ -0,0 +1,434 @@
+//! Verifiable dice for hosted games: every roll is drawn by the host from a secret seed it
+//! committed to before the first roll, so a modified client can't forge results and the host
+//! can't change them once the game started.
+//!
+//! The secret seed is hashed [`CHAIN_LENGTH`] times with SHA-256, and the last hash is the
+//! [`Commitment`] sent to spectators and joined players. The rolls walk the chain back: the
+//! first roll is drawn from the hash just before the commitment, the next one from the hash
+//! before that, and so on. Every roll is sent with the key it was drawn from, which clients hash
+//! once to find the key of the previous roll, or the commitment, before showing it: nobody but
+//! the host can find a key hashing to the last one, and the results are the ones drawn from the
+//! key. Once the game is over the host reveals the seed, which clients hash down to the last key
+//! they checked.
+//!
+//! Ties rerolled by the tie rule are drawn from the next keys like any roll, and so are random
+//! transfers, from a key of their own sent as a roll without dice, see [`transfer_from_key`].
+//!
+//! The seed comes from the system randomness rather than the world seed, which anyone replaying
+//! the game knows. The host draws the rolls instead of the dice plugin, so its battles are shown
+//! by their results only.
+
+use std::fmt;
+
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::game::{roll_dice, BattleDice, DiceRoll, RegionId};
+
+/// Rolls a seed can draw, far more than the battles of any game
+pub const CHAIN_LENGTH: usize = 1 << 16;
+
+/// Seed of the chain or one of its hashes
+pub type DiceKey = [u8; 32];
+
+/// Last hash of the chain, published before the first roll
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Commitment(pub DiceKey);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RollMismatch {
+    /// The revealed seed doesn't lead to the keys checked
+    Seed,
+    /// The key of the roll doesn't lead to the key of the previous roll
+    Key,
+    /// The results aren't the ones drawn from the key
+    Results { expected: Vec<DiceRoll> },
+}
+
+impl fmt::Display for RollMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RollMismatch::Seed => write!(f, "revealed seed doesn't match the commitment"),
+            RollMismatch::Key => write!(f, "roll wasn't drawn from the committed seed"),
+            RollMismatch::Results { expected } => {
+                write!(f, "roll doesn't come from its key, expected {:?}", expected)
+            }
+        }
+    }
+}
+
+fn hash_key(key: &DiceKey) -> DiceKey {
+    Sha256::digest(key).into()
+}
+
+/// `key` hashed `times` times
+fn hash_times(key: &DiceKey, times: usize) -> DiceKey {
+    (0..times).fold(*key, |key, _| hash_key(&key))
+}
+
+/// Dice of a battle drawn from `key`, `num_dice` dice for each side
+pub fn draw_roll(key: &DiceKey, num_dice: &[usize]) -> Vec<DiceRoll> {
+    let mut rng = ChaCha20Rng::from_seed(*key);
+    num_dice
+        .iter()
+        .map(|num_dice| roll_dice(*num_dice, &mut rng))
+        .collect()
+}
+
+/// Dice a winner holding `winner_dice` dice moves with the random transfer rule, drawn from `key`
+pub fn transfer_from_key(key: &DiceKey, winner_dice: usize) -> usize {
+    ChaCha20Rng::from_seed(*key).gen_range(1..winner_dice)
+}
+
+pub fn key_hex(key: &DiceKey) -> String {
+    key.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn parse_key(hex: &str) -> Option<DiceKey> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut key = [0; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(key)
+}
+
+/// Roll of a battle and the key it was drawn from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FairRoll {
+    /// Number of the roll since the commitment
+    pub index: u64,
+    #[serde(with = "key_serde")]
+    pub key: DiceKey,
+    pub region_1: RegionId,
+    pub region_2: RegionId,
+    pub results: Vec<DiceRoll>,
+}
+
+/// Keys travel as hex strings
+mod key_serde {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::{key_hex, parse_key, DiceKey};
+
+    pub fn serialize<S: Serializer>(key: &DiceKey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&key_hex(key))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DiceKey, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        parse_key(&hex).ok_or_else(|| serde::de::Error::custom("invalid dice key"))
+    }
+}
+
+/// Dice of the host, drawing the rolls of every battle from its secret seed
+#[derive(Resource)]
+pub struct FairDice {
+    seed: DiceKey,
+    /// Hashes of the seed, the seed first and the commitment last
+    chain: Vec<DiceKey>,
+    rolled: usize,
+    /// Rolls drawn since they were last sent
+    unsent: Vec<FairRoll>,
+    revealed: bool,
+    /// Seed of the chain of a game left before its end, revealed with the next messages
+    abandoned: Option<DiceKey>,
+}
+
+impl FairDice {
+    pub fn new(seed: DiceKey) -> Self {
+        let mut chain = Vec::with_capacity(CHAIN_LENGTH + 1);
+        chain.push(seed);
+        for _ in 0..CHAIN_LENGTH {
+            let next = hash_key(chain.last().unwrap());
+            chain.push(next);
+        }
+
+        FairDice {
+            seed,
+            chain,
+            rolled: 0,
+            unsent: Vec::new(),
+            revealed: false,
+            abandoned: None,
+        }
+    }
+
+    /// Dice from a seed of the system randomness
+    pub fn random() -> Self {
+        FairDice::new(rand::random())
+    }
+
+    pub fn commitment(&self) -> Commitment {
+        Commitment(self.chain[CHAIN_LENGTH])
+    }
+
+    /// Key the first roll not sent yet is checked against, and the number of rolls before it
+    pub fn head(&self) -> (DiceKey, u64) {
+        let sent = self.rolled - self.unsent.len();
+        (self.chain[CHAIN_LENGTH - sent], sent as u64)
+    }
+
+    /// Draws the dice of the battle of `region_1` against `region_2`, none once the chain is used
+    /// up
+    pub fn roll(
+        &mut self,
+        region_1: RegionId,
+        region_2: RegionId,
+        num_dice: &[usize],
+    ) -> Option<Vec<DiceRoll>> {
+        self.draw(region_1, region_2, num_dice)
+            .map(|(_, results)| results)
+    }
+
+    /// Draws the dice a winner holding `winner_dice` dice moves out of `region_1` or `region_2`
+    /// with the random transfer rule, none once the chain is used up
+    pub fn transfer(
+        &mut self,
+        region_1: RegionId,
+        region_2: RegionId,
+        winner_dice: usize,
+    ) -> Option<usize> {
+        self.draw(region_1, region_2, &[])
+            .map(|(key, _)| transfer_from_key(&key, winner_dice))
+    }
+
+    /// Draws `num_dice` dice for each side from the next key, which is sent with them
+    fn draw(
+        &mut self,
+        region_1: RegionId,
+        region_2: RegionId,
+        num_dice: &[usize],
+    ) -> Option<(DiceKey, Vec<DiceRoll>)> {
+        if self.rolled == CHAIN_LENGTH {
+            return None;
+        }
+
+        let key = self.chain[CHAIN_LENGTH - 1 - self.rolled];
+        let results = draw_roll(&key, num_dice);
+        self.unsent.push(FairRoll {
+            index: self.rolled as u64,
+            key,
+            region_1,
+            region_2,
+            results: results.clone(),
+        });
+        self.rolled += 1;
+        Some((key, results))
+    }
+
+    /// Rolls drawn since the last call
+    pub fn take_rolls(&mut self) -> Vec<FairRoll> {
+        std::mem::take(&mut self.unsent)
+    }
+
+    /// Seed to reveal now: the one of a chain left before the end of its game, or the current
+    /// one once its game is over
+    pub fn take_reveal(&mut self, game_over: bool) -> Option<DiceKey> {
+        if let Some(seed) = self.abandoned.take() {
+            return Some(seed);
+        }
+        if !game_over || self.revealed {
+            return None;
+        }
+
+        self.revealed = true;
+        Some(self.seed)
+    }
+
+    /// Starts a chain from a new secret seed for a new game
+    pub fn renew(&mut self) {
+        let abandoned = match self.revealed {
+            true => None,
+            false => Some(self.seed),
+        };
+        *self = FairDice {
+            abandoned,
+            ..FairDice::random()
+        };
+    }
+}
+
+/// Rerolls and random transfers of a battle of the host, drawn from its chain like the first roll
+/// of the battle. Once the chain is used up they come from the thread randomness.
+pub struct FairBattleDice<'a> {
+    pub dice: &'a mut FairDice,
+    pub region_1: RegionId,
+    pub region_2: RegionId,
+}
+
+impl BattleDice for FairBattleDice<'_> {
+    fn reroll(&mut self, attacker_dice: usize, defender_dice: usize) -> (DiceRoll, DiceRoll) {
+        match self.dice.roll(
+            self.region_1,
+            self.region_2,
+            &[attacker_dice, defender_dice],
+        ) {
+            Some(mut results) => {
+                let defender_roll = results.pop().unwrap_or_default();
+                (results.pop().unwrap_or_default(), defender_roll)
+            }
+            None => rand::thread_rng().reroll(attacker_dice, defender_dice),
+        }
+    }
+
+    fn transfer(&mut self, winner_dice: usize) -> usize {
+        self.dice
+            .transfer(self.region_1, self.region_2, winner_dice)
+            .unwrap_or_else(|| rand::thread_rng().transfer(winner_dice))
+    }
+}
+
+/// Checks the rolls of the host against the chain it committed to
+#[derive(Debug, Clone)]
+pub struct RollVerifier {
+    /// Key of the last roll checked, or the commitment
+    head: DiceKey,
+    /// Number of rolls before the next one
+    rolled: u64,
+}
+
+impl RollVerifier {
+    pub fn new(head: DiceKey, rolled: u64) -> Self {
+        RollVerifier { head, rolled }
+    }
+
+    /// Checks a roll received during the game, before it is shown. Rolls lost on the way are
+    /// skipped by hashing the key once for each.
+    pub fn check(&mut self, roll: &FairRoll) -> Result<(), RollMismatch> {
+        let skipped = match roll.index.checked_sub(self.rolled) {
+            Some(skipped) if skipped < CHAIN_LENGTH as u64 => skipped as usize,
+            _ => return Err(RollMismatch::Key),
+        };
+        if hash_times(&roll.key, skipped + 1) != self.head {
+            return Err(RollMismatch::Key);
+        }
+
+        let num_dice: Vec<usize> = roll.results.iter().map(|side| side.len()).collect();
+        let expected = draw_roll(&roll.key, &num_dice);
+        if expected != roll.results {
+            return Err(RollMismatch::Results { expected });
+        }
+
+        self.head = roll.key;
+        self.rolled = roll.index + 1;
+        Ok(())
+    }
+
+    /// Checks the seed revealed at the end of the game leads to the rolls checked
+    pub fn check_reveal(&self, seed: &DiceKey) -> Result<(), RollMismatch> {
+        let remaining = (CHAIN_LENGTH as u64).saturating_sub(self.rolled) as usize;
+        match hash_times(seed, remaining) == self.head {
+            true => Ok(()),
+            false => Err(RollMismatch::Seed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn honest_rolls_verify() {
+        let mut dice = FairDice::new([7; 32]);
+        let (head, rolled) = dice.head();
+        assert_eq!(head, dice.commitment().0);
+        assert_eq!(dice.commitment().0, hash_times(&[7; 32], CHAIN_LENGTH));
+        let mut verifier = RollVerifier::new(head, rolled);
+
+        for _ in 0..20 {
+            dice.roll(1, 2, &[8, 3]).unwrap();
+        }
+        let rolls = dice.take_rolls();
+        assert_eq!(rolls.len(), 20);
+        // a spectator may miss a roll
+        for roll in rolls.iter().filter(|roll| roll.index != 5) {
+            assert_eq!(verifier.check(roll), Ok(()));
+            assert_eq!(roll.results[0].len(), 8);
+        }
+
+        assert_eq!(dice.take_reveal(false), None);
+        let seed = dice.take_reveal(true).unwrap();
+        assert_eq!(verifier.check_reveal(&seed), Ok(()));
+        assert_eq!(dice.take_reveal(true), None);
+    }
+
+    #[test]
+    fn forged_rolls_are_caught() {
+        let mut dice = FairDice::new([7; 32]);
+        let (head, rolled) = dice.head();
+        let mut verifier = RollVerifier::new(head, rolled);
+        dice.roll(1, 2, &[4, 4]).unwrap();
+        let roll = dice.take_rolls().remove(0);
+
+        let mut forged = roll.clone();
+        forged.results[0][0] = forged.results[0][0] % 6 + 1;
+        assert!(matches!(
+            verifier.check(&forged),
+            Err(RollMismatch::Results { .. })
+        ));
+        // a client can't draw from a key of its own
+        let made_up = FairRoll {
+            key: [1; 32],
+            results: draw_roll(&[1; 32], &[4, 4]),
+            ..roll.clone()
+        };
+        assert_eq!(verifier.check(&made_up), Err(RollMismatch::Key));
+
+        assert_eq!(verifier.check(&roll), Ok(()));
+        // nor can it send a roll again
+        assert_eq!(verifier.check(&roll), Err(RollMismatch::Key));
+        assert_eq!(verifier.check_reveal(&[8; 32]), Err(RollMismatch::Seed));
+    }
+
+    #[test]
+    fn rerolls_and_transfers_verify() {
+        let mut dice = FairDice::new([7; 32]);
+        let (head, rolled) = dice.head();
+        let mut verifier = RollVerifier::new(head, rolled);
+        dice.roll(1, 2, &[3, 3]).unwrap();
+
+        let mut battle_dice = FairBattleDice {
+            dice: &mut dice,
+            region_1: 1,
+            region_2: 2,
+        };
+        let (attacker_roll, defender_roll) = battle_dice.reroll(3, 2);
+        let transferred = battle_dice.transfer(5);
+        assert!((1..5).contains(&transferred));
+
+        let rolls = dice.take_rolls();
+        assert_eq!(rolls.len(), 3);
+        for roll in rolls.iter() {
+            assert_eq!(verifier.check(roll), Ok(()));
+        }
+        assert_eq!(rolls[1].results, vec![attacker_roll, defender_roll]);
+        assert!(rolls[2].results.is_empty());
+        assert_eq!(transfer_from_key(&rolls[2].key, 5), transferred);
+    }
+
+    #[test]
+    fn abandoned_chains_are_revealed() {
+        let mut dice = FairDice::new([7; 32]);
+        dice.renew();
+
+        assert_ne!(dice.commitment(), FairDice::new([7; 32]).commitment());
+        assert_eq!(dice.take_reveal(false), Some([7; 32]));
+        assert_eq!(dice.take_reveal(false), None);
+    }
+
+    #[test]
+    fn keys_round_trip_through_hex() {
+        let key = hash_key(&[3; 32]);
+        assert_eq!(parse_key(&key_hex(&key)), Some(key));
+        assert_eq!(parse_key("00"), None);
+        assert_eq!(parse_key(&"zz".repeat(32)), None);
+    }
+}
@@ -1,75 +1,864 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
 
-use bevy::prelude::{Component, Entity, Resource};
-use rand::{seq::IteratorRandom, Rng};
+use bevy::prelude::{
+    Component, Entity, FromReflect, Reflect, ReflectComponent, ReflectResource, Resource,
+};
+use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
 
-use crate::hex::HexCoord;
+use crate::generators::{board_generator, PatchGenerator};
+use crate::hex::{is_contiguous, HexCoord};
+use crate::names::name_regions;
+use crate::persistence::{deserialize_hexes, serialize_hexes};
+use crate::rules::{GameRules, ReinforcementRule, TransferRule};
+use crate::zobrist;
 
-const BOARD_SIZE: isize = 20;
-const NUMBER_OF_PATCHES: usize = 16;
-const HALF_BOARD_SIZE: isize = BOARD_SIZE / 2 - 1;
+/// Identifier of a region, its index in [`Board::regions`]
+pub type RegionId = usize;
 
-#[derive(Default, Clone)]
+/// Stream of the randomness of the world seed drawing the first player, apart from the stream
+/// generating the board
+const FIRST_PLAYER_STREAM: u64 = 1;
+
+/// Times a tied battle is rolled again before the tie goes to the defender, for rolls that can't
+/// stop tying such as a side without any dice
+const MAX_REROLLS: usize = 100;
+
+/// Identifier of a battle, the index of its entry in [`GameState::game_log`]
+pub type BattleId = usize;
+
+/// Stable identifier of a region, derived from its hexes. Unlike its [`RegionId`] it doesn't
+/// change when the regions of a board are reordered, so it is what files and messages outliving
+/// the board should refer to. Written as 16 hex digits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct RegionKey(pub u64);
+
+impl RegionKey {
+    /// Key of the region made of `hexes`, in any order
+    pub fn from_hexes(hexes: &[(isize, isize)]) -> Self {
+        let mut sorted = hexes.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+        RegionKey(sorted.iter().fold(zobrist::mix(0), |key, (q, r)| {
+            zobrist::mix(zobrist::mix(key ^ *q as u64) ^ *r as u64)
+        }))
+    }
+}
+
+impl fmt::Display for RegionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for RegionKey {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16).map(RegionKey)
+    }
+}
+
+#[derive(Default, Clone, Debug, Reflect, FromReflect, Serialize, Deserialize)]
 pub struct Board {
-    pub hexes: HashMap<(isize, isize), usize>,
+    #[serde(
+        serialize_with = "serialize_hexes",
+        deserialize_with = "deserialize_hexes"
+    )]
+    pub hexes: bevy::utils::HashMap<(isize, isize), usize>,
     pub regions: Vec<Region>,
+    /// Ids of regions that count as objectives on king-of-the-hill maps
+    pub objectives: Vec<usize>,
+}
+
+impl Board {
+    /// The player owning more than half of the objective regions, if any
+    pub fn objective_majority_holder(&self) -> Option<usize> {
+        let mut objectives_by_player: HashMap<usize, usize> = HashMap::new();
+        for region in self.objectives.iter() {
+            if self.regions[*region].owner == NEUTRAL {
+                continue;
+            }
+            *objectives_by_player
+                .entry(self.regions[*region].owner)
+                .or_insert(0) += 1;
+        }
+
+        objectives_by_player
+            .into_iter()
+            .find(|(_, held)| held * 2 > self.objectives.len())
+            .map(|(player, _)| player)
+    }
+
+    /// Size and ids of the biggest group of neighbouring regions owned by `player`,
+    /// found with a breadth-first search over the region adjacency graph
+    pub fn largest_connected_area(&self, player: usize) -> (usize, Vec<RegionId>) {
+        let mut visited = vec![false; self.regions.len()];
+        let mut largest: Vec<RegionId> = Vec::new();
+
+        for start in self.regions.iter().filter(|region| region.owner == player) {
+            if visited[start.id] {
+                continue;
+            }
+
+            visited[start.id] = true;
+            let mut area = vec![start.id];
+            let mut queue = VecDeque::from([start.id]);
+
+            while let Some(id) = queue.pop_front() {
+                for other in self.regions.iter() {
+                    if other.owner == player
+                        && !visited[other.id]
+                        && self.regions[id].is_neighbour(other)
+                    {
+                        visited[other.id] = true;
+                        area.push(other.id);
+                        queue.push_back(other.id);
+                    }
+                }
+            }
+
+            if area.len() > largest.len() {
+                largest = area;
+            }
+        }
+
+        largest.sort_unstable();
+        (largest.len(), largest)
+    }
+
+    /// Shortest chain of neighbouring regions leading from `from` to `to`, both included
+    #[allow(dead_code)]
+    pub fn path(&self, from: RegionId, to: RegionId) -> Option<Vec<RegionId>> {
+        self.path_through(from, to, |_| true)
+    }
+
+    /// Shortest chain of neighbouring regions owned by `player` leading from `from` to `to`
+    pub fn owned_path(&self, from: RegionId, to: RegionId, player: usize) -> Option<Vec<RegionId>> {
+        self.path_through(from, to, |region| region.owner == player)
+    }
+
+    /// Breadth-first search over the region adjacency graph, only entering `passable` regions
+    fn path_through(
+        &self,
+        from: RegionId,
+        to: RegionId,
+        passable: impl Fn(&Region) -> bool,
+    ) -> Option<Vec<RegionId>> {
+        if !passable(self.regions.get(from)?) || !passable(self.regions.get(to)?) {
+            return None;
+        }
+
+        let mut previous: Vec<Option<RegionId>> = vec![None; self.regions.len()];
+        let mut visited = vec![false; self.regions.len()];
+        let mut queue = VecDeque::from([from]);
+        visited[from] = true;
+
+        while let Some(id) = queue.pop_front() {
+            if id == to {
+                let mut path = vec![to];
+                while let Some(id) = previous[*path.last().unwrap()] {
+                    path.push(id);
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for other in self.regions.iter() {
+                if !visited[other.id] && passable(other) && self.regions[id].is_neighbour(other) {
+                    visited[other.id] = true;
+                    previous[other.id] = Some(id);
+                    queue.push_back(other.id);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Every pair of neighbouring regions, the lower id first
+    pub fn adjacencies(&self) -> Vec<(RegionId, RegionId)> {
+        self.regions
+            .iter()
+            .flat_map(|region_1| {
+                self.regions[region_1.id + 1..]
+                    .iter()
+                    .filter(move |region_2| region_1.is_neighbour(region_2))
+                    .map(move |region_2| (region_1.id, region_2.id))
+            })
+            .collect()
+    }
+
+    /// The board with every hex moved by `transform`, which has to keep neighbouring hexes
+    /// neighbours, like the rotations and mirrors of [`HexCoord`]. Regions keep their ids.
+    pub fn transformed(&self, transform: impl Fn(&HexCoord) -> HexCoord) -> Board {
+        let move_hex = |(q, r): (isize, isize)| {
+            let moved = transform(&HexCoord::new(q, r));
+            (moved.q, moved.r)
+        };
+
+        let mut board = self.clone();
+        board.hexes = self
+            .hexes
+            .iter()
+            .map(|(hex, owner)| (move_hex(*hex), *owner))
+            .collect();
+        for region in board.regions.iter_mut() {
+            region.hexes = region.hexes.iter().copied().map(move_hex).collect();
+        }
+        board
+    }
+
+    /// The board turned `sixths` sixths of a turn clockwise around the origin
+    #[allow(dead_code)]
+    pub fn rotated(&self, sixths: usize) -> Board {
+        self.transformed(|hex| (0..sixths % 6).fold(hex.clone(), |hex, _| hex.rotate_clockwise()))
+    }
+
+    /// The board mirrored left to right across the column of the origin
+    #[allow(dead_code)]
+    pub fn mirrored(&self) -> Board {
+        self.transformed(HexCoord::mirror_horizontally)
+    }
+
+    /// Id of the region with the stable `key`, if the board has it
+    pub fn region_by_key(&self, key: RegionKey) -> Option<RegionId> {
+        self.regions
+            .iter()
+            .find(|region| region.key() == key)
+            .map(|region| region.id)
+    }
+
+    /// Stable keys of every region, indexed by region id
+    pub fn region_keys(&self) -> Vec<RegionKey> {
+        self.regions.iter().map(|region| region.key()).collect()
+    }
+
+    /// Threat to every region, indexed by region id
+    pub fn threat_map(&self) -> Vec<RegionThreat> {
+        self.regions
+            .iter()
+            .map(|region| {
+                let enemies = self
+                    .regions
+                    .iter()
+                    .filter(|other| region.is_opponent(other));
+
+                let mut threat = RegionThreat::default();
+                for enemy in enemies {
+                    threat.enemy_dice += enemy.num_dice;
+                    threat.is_border = true;
+                }
+                threat
+            })
+            .collect()
+    }
+}
+
+/// How exposed a region is to enemy attacks
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RegionThreat {
+    /// Total dice of the enemy regions bordering the region
+    pub enemy_dice: usize,
+    /// Whether the region borders an enemy region. Interior regions can't be attacked.
+    pub is_border: bool,
 }
 
-#[derive(Clone, Resource)]
+#[derive(Default, Clone, Debug, Resource, Reflect, FromReflect, Serialize, Deserialize)]
+#[reflect(Resource)]
 pub struct GameState {
     pub board: Board,
     pub turn_of_player: usize,
+    /// Player that moved first, see [`GameState::seat_players`]
+    #[serde(default)]
+    pub first_player: usize,
+    /// Dice [`GameState::seat_players`] added to every region to make up for moving late, indexed
+    /// by region id
+    #[serde(default)]
+    pub compensation: Vec<usize>,
     pub turn_counter: usize,
     pub number_of_players: usize,
     pub game_log: Vec<GameLogEntry>,
+    /// Non-aggression pacts in force between players
+    pub pacts: Vec<Pact>,
+    /// Dice each player has earned but not placed yet, indexed by player
+    pub reserves: Vec<usize>,
+    pub turn_phase: TurnPhase,
+    /// Winner of a game ended before anyone owned the whole board: by a timeout, objectives, a
+    /// scenario trigger or a stalemate, see [`GameState::decide_winner`]
+    #[serde(default)]
+    pub decided_winner: Option<usize>,
+}
+
+/// Part of a turn the player in turn is in
+#[derive(
+    Default, Clone, Copy, Debug, PartialEq, Eq, Reflect, FromReflect, Serialize, Deserialize,
+)]
+pub enum TurnPhase {
+    /// The player attacks with its regions
+    #[default]
+    Attack,
+    /// The player places the dice of its reserve onto its regions
+    Reinforcement,
+}
+
+/// Non-aggression pact between two players, valid until the turn counter reaches `expires_at_turn`
+#[derive(Default, Clone, Debug, PartialEq, Eq, Reflect, FromReflect, Serialize, Deserialize)]
+pub struct Pact {
+    pub player_1: usize,
+    pub player_2: usize,
+    pub expires_at_turn: usize,
+}
+
+impl Pact {
+    pub fn binds(&self, player_1: usize, player_2: usize) -> bool {
+        (self.player_1 == player_1 && self.player_2 == player_2)
+            || (self.player_1 == player_2 && self.player_2 == player_1)
+    }
 }
 
 impl GameState {
     // Enumerates a list of possible moves for a player
     #[allow(dead_code)]
-    pub fn possible_moves(self) -> Vec<(Region, Region)> {
-        let regions_owned_by_player: Vec<Region> = self
-            .board
+    pub fn possible_moves(&self, rules: &GameRules) -> Vec<(RegionId, RegionId)> {
+        self.attacks(rules)
+            .map(|(region_1, region_2)| (region_1.id, region_2.id))
+            .collect()
+    }
+
+    /// Possible attacks of the player in turn, without cloning any region
+    pub fn attacks<'a>(
+        &'a self,
+        rules: &'a GameRules,
+    ) -> impl Iterator<Item = (&'a Region, &'a Region)> + 'a {
+        self.board
             .regions
             .iter()
             .filter(|region| region.owner == self.turn_of_player)
-            .cloned()
-            .collect();
+            .flat_map(|region_1| {
+                self.board
+                    .regions
+                    .iter()
+                    .filter(|region_2| self.can_attack(region_1, region_2, rules))
+                    .map(move |region_2| (region_1, region_2))
+            })
+    }
+
+    /// Zobrist hash of the region owners, dice counts and player to move. States that only differ
+    /// in their log, pacts or reserves hash the same.
+    pub fn zobrist_hash(&self) -> u64 {
+        self.board
+            .regions
+            .iter()
+            .fold(zobrist::turn_key(self.turn_of_player), |hash, region| {
+                hash ^ zobrist::owner_key(region.id, region.owner)
+                    ^ zobrist::dice_key(region.id, region.num_dice)
+            })
+    }
+
+    /// Whether a region can't attack anymore during the current turn
+    pub fn has_moved_this_turn(&self, region_id: usize) -> bool {
+        self.board.regions[region_id].has_moved
+    }
+
+    /// Records an attack in the game log, along with the regions as they are before the battle.
+    /// The attacking region can't attack again this turn. Returns the id of the battle.
+    pub fn log_attack(&mut self, mut entry: GameLogEntry) -> BattleId {
+        let (region_1, region_2) = (
+            &self.board.regions[entry.region_1],
+            &self.board.regions[entry.region_2],
+        );
+        entry.defending_player = region_2.owner;
+        entry.region_1_num_dice = region_1.num_dice;
+        entry.region_2_num_dice = region_2.num_dice;
 
-        let mut possible_moves: Vec<(Region, Region)> = Vec::new();
+        self.board.regions[entry.region_1].has_moved = true;
+        self.game_log.push(entry);
+        self.game_log.len() - 1
+    }
+
+    /// Whether the player in turn still has a region that can attack an opponent
+    pub fn current_player_can_move(&self, rules: &GameRules) -> bool {
+        self.attacks(rules).any(|(region_1, _)| !region_1.has_moved)
+    }
+
+    /// Whether the players are bound by a pact in force
+    pub fn has_pact(&self, player_1: usize, player_2: usize) -> bool {
+        self.pacts
+            .iter()
+            .any(|pact| pact.binds(player_1, player_2) && pact.expires_at_turn > self.turn_counter)
+    }
 
-        for region1 in regions_owned_by_player.iter() {
-            for region2 in self.board.regions.iter() {
-                if region1.is_opponent(region2) {
-                    possible_moves.push((region1.clone(), region2.clone()));
+    /// Whether `attacker` may attack `defender`: they must be neighbouring opponents not bound by a
+    /// pact, and the attacker must hold more dice than its garrison
+    pub fn can_attack(&self, attacker: &Region, defender: &Region, rules: &GameRules) -> bool {
+        rules.attacker_roll_size(attacker.num_dice) > 0
+            && attacker.is_opponent(defender)
+            && !self.has_pact(attacker.owner, defender.owner)
+    }
+
+    /// Seats the players for the start of the game: `first_player` moves first, and every other
+    /// player gets [`GameRules::compensation_dice`] extra dice for each seat it moves after it,
+    /// spread one at a time onto its regions with the fewest dice. What doesn't fit is lost.
+    pub fn seat_players(&mut self, first_player: usize, rules: &GameRules) {
+        let number_of_players = self.number_of_players.max(1);
+        self.turn_of_player = first_player % number_of_players;
+        self.first_player = self.turn_of_player;
+        self.compensation = vec![0; self.board.regions.len()];
+
+        for player in 0..number_of_players {
+            let seat = (player + number_of_players - self.turn_of_player) % number_of_players;
+            for _ in 0..seat * rules.compensation_dice {
+                let region = self
+                    .board
+                    .regions
+                    .iter_mut()
+                    .filter(|region| region.owner == player && region.num_dice < rules.max_dice)
+                    .min_by_key(|region| region.num_dice);
+
+                match region {
+                    Some(region) => {
+                        region.num_dice += 1;
+                        if let Some(dice) = self.compensation.get_mut(region.id) {
+                            *dice += 1;
+                        }
+                    }
+                    None => break,
                 }
             }
         }
+    }
+
+    /// Board of the game before [`GameState::seat_players`] added the compensation dice, to seat
+    /// the players again on it. Only meant for the start of the game, before any battle.
+    pub fn unseated_board(&self) -> Board {
+        let mut board = self.board.clone();
+        for (region, dice) in board.regions.iter_mut().zip(self.compensation.iter()) {
+            region.num_dice = region.num_dice.saturating_sub(*dice);
+        }
+        board
+    }
+
+    /// Passes the turn to the next player
+    pub fn advance_turn(&mut self) {
+        self.turn_of_player += 1;
+        if self.turn_of_player >= self.number_of_players {
+            self.turn_of_player = 0;
+        }
+
+        self.turn_counter += 1;
+
+        for region in self.board.regions.iter_mut() {
+            region.has_moved = false;
+        }
+
+        self.turn_phase = TurnPhase::Attack;
+
+        let turn_counter = self.turn_counter;
+        self.pacts
+            .retain(|pact| pact.expires_at_turn > turn_counter);
+    }
+
+    /// Ends the turn of a player that can't attack anymore. Automatic reinforcements are spread
+    /// right away, with manual reinforcements the player first gets a reinforcement phase to place
+    /// its reserve. Dice left in the reserve are kept up to the reserve cap. Returns whether the
+    /// turn passed to the next player.
+    pub fn finish_turn(&mut self, rules: &GameRules) -> bool {
+        if self.current_player_can_move(rules) {
+            return false;
+        }
+
+        self.end_turn(rules)
+    }
+
+    /// Ends the attacks of the player in turn, whether it could still attack or not, the same way
+    /// [`GameState::finish_turn`] does. Returns whether the turn passed to the next player.
+    pub fn end_turn(&mut self, rules: &GameRules) -> bool {
+        if self.turn_phase == TurnPhase::Attack {
+            match rules.reinforcements {
+                ReinforcementRule::None => {}
+                ReinforcementRule::Automatic => {
+                    self.start_reinforcement();
+                    self.distribute_reinforcements(rules);
+                }
+                ReinforcementRule::Manual => self.start_reinforcement(),
+            }
+        }
+
+        if self.turn_phase == TurnPhase::Reinforcement && !self.reinforcement_done(rules) {
+            return false;
+        }
+
+        if let Some(reserve) = self.reserves.get_mut(self.turn_of_player) {
+            *reserve = (*reserve).min(rules.reserve_cap);
+        }
+
+        self.advance_turn();
+        true
+    }
+
+    /// Places the whole reserve of the player in turn, one die at a time onto its region with the
+    /// fewest dice, until the reserve is empty or every region is full
+    pub fn distribute_reinforcements(&mut self, rules: &GameRules) {
+        loop {
+            let region = self
+                .board
+                .regions
+                .iter()
+                .filter(|region| self.can_place_reinforcement(region, rules))
+                .min_by_key(|region| region.num_dice)
+                .map(|region| region.id);
+
+            match region {
+                Some(region) => self.place_reinforcement(region, rules),
+                None => return,
+            };
+        }
+    }
+
+    /// Number of dice the player in turn earns at the end of its turn: one per region in its
+    /// largest connected area
+    pub fn earned_reinforcements(&self) -> usize {
+        self.board.largest_connected_area(self.turn_of_player).0
+    }
+
+    /// Dice waiting in the reserve of `player`
+    pub fn reserve(&self, player: usize) -> usize {
+        self.reserves.get(player).copied().unwrap_or(0)
+    }
+
+    /// Adds the earned reinforcements to the reserve of the player in turn and lets it place them
+    pub fn start_reinforcement(&mut self) {
+        let earned = self.earned_reinforcements();
+        if self.reserves.len() < self.number_of_players {
+            self.reserves.resize(self.number_of_players, 0);
+        }
+
+        self.reserves[self.turn_of_player] += earned;
+        self.turn_phase = TurnPhase::Reinforcement;
+    }
+
+    /// Whether the player in turn can place a die from its reserve onto `region`
+    pub fn can_place_reinforcement(&self, region: &Region, rules: &GameRules) -> bool {
+        self.turn_phase == TurnPhase::Reinforcement
+            && region.owner == self.turn_of_player
+            && region.num_dice < rules.max_dice
+            && self.reserve(self.turn_of_player) > 0
+    }
+
+    /// Moves a die from the reserve of the player in turn onto one of its regions
+    pub fn place_reinforcement(&mut self, region_id: usize, rules: &GameRules) -> bool {
+        if !self.can_place_reinforcement(&self.board.regions[region_id], rules) {
+            return false;
+        }
+
+        self.reserves[self.turn_of_player] -= 1;
+        self.board.regions[region_id].num_dice += 1;
+        true
+    }
+
+    /// Whether the player in turn has nothing left to place, or no region with room for it
+    pub fn reinforcement_done(&self, rules: &GameRules) -> bool {
+        !self
+            .board
+            .regions
+            .iter()
+            .any(|region| self.can_place_reinforcement(region, rules))
+    }
+
+    /// Passes the turn until it reaches a player that can still attack or has reinforcements to
+    /// place, giving up after a full round
+    pub fn skip_blocked_players(&mut self, rules: &GameRules) {
+        for _ in 0..self.number_of_players {
+            if !self.finish_turn(rules) {
+                return;
+            }
+        }
+    }
+
+    /// Whether the player in turn starts its turn without any legal attack and has to pass
+    pub fn must_pass(&self, rules: &GameRules) -> bool {
+        self.turn_phase == TurnPhase::Attack
+            && self.winner().is_none()
+            && !self.board.regions.iter().any(|region| region.has_moved)
+            && !self.current_player_can_move(rules)
+    }
+
+    /// Ends the attack phase of a player that must pass, moving on to its reinforcements or to the
+    /// next player. Returns the player that passed.
+    pub fn pass(&mut self, rules: &GameRules) -> Option<usize> {
+        if !self.must_pass(rules) {
+            return None;
+        }
 
-        possible_moves
+        let player = self.turn_of_player;
+        self.finish_turn(rules);
+        Some(player)
+    }
+
+    /// The player the game was decided for, or else the player owning every region on the board
+    /// that isn't neutral, if there is one
+    pub fn winner(&self) -> Option<usize> {
+        if self.decided_winner.is_some() {
+            return self.decided_winner;
+        }
+
+        let mut owners = self
+            .board
+            .regions
+            .iter()
+            .map(|region| region.owner)
+            .filter(|owner| *owner != NEUTRAL);
+        let owner = owners.next()?;
+
+        if owners.all(|other| other == owner) {
+            Some(owner)
+        } else {
+            None
+        }
+    }
+
+    /// Ends the game with `player` as its winner, whoever owns the board. Returns whether the
+    /// game was still going on, a game has a single winner.
+    pub fn decide_winner(&mut self, player: usize) -> bool {
+        if self.winner().is_some() {
+            return false;
+        }
+
+        self.decided_winner = Some(player);
+        true
+    }
+
+    /// Resolves a battle between the `attacker` and `defender` regions given the dice each side
+    /// rolled. The side with the higher total wins and ties are settled by the rules' tie rule,
+    /// rerolling both sides with `dice` if needed, up to [`MAX_REROLLS`] times. The losing region
+    /// is conquered and the winner splits its dice with it as the rules' transfer rule says, see
+    /// [`resolve_transfer`].
+    pub fn resolve_battle(
+        &mut self,
+        attacker: usize,
+        defender: usize,
+        attacker_roll: &[usize],
+        defender_roll: &[usize],
+        rules: &GameRules,
+        dice: &mut impl BattleDice,
+    ) -> BattleOutcome {
+        let mut attacker_roll = DiceRoll::from_slice(attacker_roll);
+        let mut defender_roll = DiceRoll::from_slice(defender_roll);
+
+        let mut rerolls = 0;
+        let attacker_won = loop {
+            let attacker_result: usize = attacker_roll.iter().sum();
+            let defender_result: usize = defender_roll.iter().sum();
+            match rules.attacker_wins(attacker_result, defender_result) {
+                Some(attacker_won) => break attacker_won,
+                None if rerolls == MAX_REROLLS => break false,
+                None => {
+                    rerolls += 1;
+                    (attacker_roll, defender_roll) =
+                        dice.reroll(attacker_roll.len(), defender_roll.len());
+                }
+            }
+        };
+
+        let winner_dice = match attacker_won {
+            true => rules.attacker_transfer_dice(self.board.regions[attacker].num_dice),
+            false => self.board.regions[defender].num_dice,
+        };
+
+        let transferred = resolve_transfer(winner_dice, rules.transfer_rule, dice);
+
+        let outcome = BattleOutcome {
+            attacker_won,
+            transferred,
+            attacker_roll,
+            defender_roll,
+        };
+        self.apply_battle_outcome(attacker, defender, &outcome, rules);
+
+        outcome
+    }
+
+    /// Applies an already decided battle to the board: the losing region changes owner and
+    /// receives `transferred` dice from the winner. No dice are moved when nothing was transferred.
+    /// A region conquered by the attacker sits out the rest of the turn, unless the momentum rule
+    /// lets it carry on the attack.
+    pub fn apply_battle_outcome(
+        &mut self,
+        attacker: usize,
+        defender: usize,
+        outcome: &BattleOutcome,
+        rules: &GameRules,
+    ) {
+        let (winner, loser) = match outcome.attacker_won {
+            true => (attacker, defender),
+            false => (defender, attacker),
+        };
+
+        let winner_owner = self.board.regions[winner].owner;
+
+        self.board.regions[loser].owner = winner_owner;
+        for hex in self.board.regions[loser].hexes.iter() {
+            self.board.hexes.insert(*hex, winner_owner);
+        }
+
+        if outcome.transferred > 0 {
+            self.board.regions[loser].num_dice = outcome.transferred;
+            self.board.regions[winner].num_dice -= outcome.transferred;
+        }
+
+        if outcome.attacker_won {
+            self.board.regions[defender].has_moved = !rules.momentum;
+        }
+
+        if let Some(entry) = self.game_log.last_mut() {
+            if entry.region_1 == attacker && entry.region_2 == defender {
+                entry.resolved = true;
+                entry.attacker_won = outcome.attacker_won;
+                entry.transferred = outcome.transferred;
+            }
+        }
+    }
+}
+
+/// Result of a resolved battle
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BattleOutcome {
+    pub attacker_won: bool,
+    /// Number of dice the winner moved into the conquered region
+    pub transferred: usize,
+    /// Dice that decided the battle, after any reroll the tie rule asked for
+    pub attacker_roll: DiceRoll,
+    pub defender_roll: DiceRoll,
+}
+
+/// Faces rolled by one side of a battle. Fits a full region without allocating.
+pub type DiceRoll = SmallVec<[usize; 8]>;
+
+/// Randomness a battle needs once its first roll is known: the rerolls of a tie and random
+/// transfers. Any [`Rng`] draws them, the host of a network game draws them from the dice it
+/// committed to, see [`crate::fair_dice::FairBattleDice`].
+pub trait BattleDice {
+    /// New dice for both sides of a tied battle
+    fn reroll(&mut self, attacker_dice: usize, defender_dice: usize) -> (DiceRoll, DiceRoll);
+    /// Dice a winner holding `winner_dice` dice moves with [`TransferRule::Random`], from 1 to
+    /// `winner_dice - 1`
+    fn transfer(&mut self, winner_dice: usize) -> usize;
+}
+
+impl<R: Rng> BattleDice for R {
+    fn reroll(&mut self, attacker_dice: usize, defender_dice: usize) -> (DiceRoll, DiceRoll) {
+        (
+            roll_dice(attacker_dice, self),
+            roll_dice(defender_dice, self),
+        )
+    }
+
+    fn transfer(&mut self, winner_dice: usize) -> usize {
+        self.gen_range(1..winner_dice)
+    }
+}
+
+/// Number of dice a winner holding `winner_dice` dice moves into the region it conquered. The
+/// winner always keeps at least one die, so a winner with a single die can't split and the
+/// conquered region keeps its own dice.
+pub fn resolve_transfer(
+    winner_dice: usize,
+    rule: TransferRule,
+    dice: &mut impl BattleDice,
+) -> usize {
+    if winner_dice < 2 {
+        return 0;
+    }
+
+    let movable = winner_dice - 1;
+    match rule {
+        TransferRule::Random => dice.transfer(winner_dice),
+        TransferRule::AllButOne => movable,
+        TransferRule::Percentage(percent) => (winner_dice * percent / 100).clamp(1, movable),
+    }
+}
+
+/// Whether `rule` lets a winner holding `winner_dice` dice move `transferred` of them
+pub fn transfer_allowed(rule: TransferRule, winner_dice: usize, transferred: usize) -> bool {
+    if winner_dice < 2 {
+        return transferred == 0;
+    }
+
+    let movable = winner_dice - 1;
+    match rule {
+        TransferRule::Random => (1..=movable).contains(&transferred),
+        TransferRule::AllButOne => transferred == movable,
+        TransferRule::Percentage(percent) => {
+            transferred == (winner_dice * percent / 100).clamp(1, movable)
+        }
     }
 }
 
-#[derive(Clone)]
+/// Rolls `num_dice` six sided dice
+pub fn roll_dice(num_dice: usize, rng: &mut impl Rng) -> DiceRoll {
+    (0..num_dice).map(|_| rng.gen_range(1..=6)).collect()
+}
+
+/// A battle of the game. Regions are referred to by id, their data lives in [`Board::regions`].
+#[derive(Default, Clone, Debug, Reflect, FromReflect, Serialize, Deserialize)]
 pub struct GameLogEntry {
     pub turn_counter: usize,
     pub turn_of_player: usize,
-    pub region_1: Region,
-    pub region_2: Region,
-    pub region_1_dice_result: Vec<usize>,
-    pub region_2_dice_result: Vec<usize>,
+    /// Attacking region
+    pub region_1: RegionId,
+    /// Defending region
+    pub region_2: RegionId,
+    pub region_1_dice_result: DiceRoll,
+    pub region_2_dice_result: DiceRoll,
+    /// Owner of the defending region when it was attacked
+    pub defending_player: usize,
+    /// Dice in the attacking region before the battle
+    pub region_1_num_dice: usize,
+    /// Dice in the defending region before the battle
+    pub region_2_num_dice: usize,
+    /// The battle has been decided, `attacker_won` and `transferred` are known
+    pub resolved: bool,
+    pub attacker_won: bool,
+    /// Dice the winner moved into the conquered region
+    pub transferred: usize,
 }
 
-#[derive(Default, Component, Clone)]
+/// Owner of the regions no player holds, such as those struck by a plague of the chaos rule.
+/// Neutral regions never attack, but every player can attack them.
+pub const NEUTRAL: usize = usize::MAX;
+
+#[derive(Default, Component, Clone, Debug, Reflect, FromReflect, Serialize, Deserialize)]
+#[reflect(Component)]
 pub struct Region {
     pub hexes: Vec<(isize, isize)>,
     pub owner: usize,
     pub num_dice: usize,
     pub id: usize,
+    /// Whether the region already attacked this turn, or was conquered and sits the turn out
+    pub has_moved: bool,
+    /// Territory name shown to players, empty for unnamed regions
+    pub name: String,
 }
 
 impl Region {
+    /// Name of the region for players, falling back to its id for unnamed regions
+    pub fn label(&self) -> String {
+        match self.name.is_empty() {
+            true => format!("Region {}", self.id),
+            false => self.name.clone(),
+        }
+    }
+
+    /// Stable identifier of the region, see [`RegionKey`]
+    pub fn key(&self) -> RegionKey {
+        RegionKey::from_hexes(&self.hexes)
+    }
+
     pub fn center_of_mass(&self) -> (f32, f32) {
         let mut x = 0.0;
         let mut y = 0.0;
@@ -98,11 +887,17 @@ impl Region {
         HexCoord::new(nearest_hex.0, nearest_hex.1)
     }
 
+    /// Whether the hexes of the region form a single connected patch
+    pub fn is_contiguous(&self) -> bool {
+        is_contiguous(&self.hexes)
+    }
+
     pub fn is_opponent(&self, other: &Region) -> bool {
-        if self.owner == other.owner {
-            return false;
-        }
+        self.owner != other.owner && self.is_neighbour(other)
+    }
 
+    /// Whether any hex of this region borders a hex of `other`
+    pub fn is_neighbour(&self, other: &Region) -> bool {
         for hex in self.hexes.iter() {
             let hex_coord = HexCoord::new(hex.0, hex.1);
             for neighbour_coord in hex_coord.neighbors() {
@@ -119,146 +914,594 @@ impl Region {
     }
 }
 
-pub fn generate_board(number_of_players: usize, mut rng: ChaCha20Rng) -> Board {
-    // Roughly half of the board occupied by patches (regions)
-    let patch_size: isize =
-        (BOARD_SIZE * BOARD_SIZE) / (NUMBER_OF_PATCHES * number_of_players * 2) as isize;
+/// Generates a board with the generator named by [`GameRules::board_generator`], and names its
+/// regions. An unknown name falls back to the default generator.
+pub fn generate_board(rules: &GameRules, mut rng: ChaCha20Rng) -> Board {
+    let generator =
+        board_generator(&rules.board_generator).unwrap_or_else(|| Box::new(PatchGenerator));
 
-    let mut board = Board::default();
+    let mut board = generator.generate(rules, &mut rng);
+    name_regions(&mut board, &mut rng);
 
-    for patch in 0..NUMBER_OF_PATCHES {
-        for player in 0..number_of_players {
-            let mut is_starting_point_valid = false;
-
-            while !is_starting_point_valid {
-                let mut has_neighbours = false;
-
-                while !has_neighbours {
-                    let mut hex_snapshot = board.hexes.clone();
-
-                    // check if starting position is empty
-                    let initial_coord = (
-                        rng.gen_range(-HALF_BOARD_SIZE..HALF_BOARD_SIZE),
-                        rng.gen_range(-HALF_BOARD_SIZE..HALF_BOARD_SIZE),
-                    );
-
-                    if board.hexes.get(&initial_coord).is_none() {
-                        is_starting_point_valid = true;
-                        hex_snapshot.insert(initial_coord, player);
-                    } else {
-                        // try over
-                        continue;
-                    }
+    board
+}
 
-                    // expand until size limit is reached or no more space to grow
-                    let mut patch_hexes: Vec<(isize, isize)> = vec![initial_coord];
-
-                    for _ in 0..patch_size {
-                        // find a bordering hex. use random iterating order to avoid bias
-                        let mut neightbour_hex: Option<HexCoord> = None;
-                        for coord in patch_hexes
-                            .iter()
-                            .choose_multiple(&mut rng, patch_hexes.iter().len())
-                        {
-                            let hex = HexCoord::new(coord.0, coord.1);
-                            // iterate over all neighbors and find a free one
-                            for neighbor in hex.neighbors() {
-                                if hex_snapshot.get(&(neighbor.q, neighbor.r)).is_none() {
-                                    neightbour_hex = Some(hex.clone());
-                                    break;
-                                }
-                            }
-
-                            // continue expanding a border hex
-                            if neightbour_hex.is_some() {
-                                break;
-                            }
-                        }
+/// Player moving first in the game of `world_seed`: the first player, or one drawn from the seed
+/// with [`GameRules::random_first_player`], so replays of the game start with the same player
+pub fn first_player(rules: &GameRules, world_seed: u64) -> usize {
+    if !rules.random_first_player || rules.number_of_players == 0 {
+        return 0;
+    }
 
-                        // no more hex cells in this patch
-                        if neightbour_hex.is_none() {
-                            break;
-                        }
+    let mut rng = ChaCha20Rng::seed_from_u64(world_seed);
+    rng.set_stream(FIRST_PLAYER_STREAM);
+    rng.gen_range(0..rules.number_of_players)
+}
 
-                        // add a new hex to the patch
-                        let mut candidates: Vec<(isize, isize)> = vec![];
-                        for neighbour in neightbour_hex.unwrap().neighbors() {
-                            let neighbour_coord = (neighbour.q, neighbour.r);
-                            if hex_snapshot.get(&neighbour_coord).is_none() {
-                                candidates.push(neighbour_coord);
-                            }
-                        }
-                        let candidate = candidates.iter().choose(&mut rng).unwrap();
-                        patch_hexes.push(*candidate);
-                        hex_snapshot.insert(*candidate, player);
-                    }
+/// Selection of the local player, driven by its clicks on regions
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Resource)]
+pub enum SelectedRegion {
+    #[default]
+    Nothing,
+    /// A region of the player in turn that can still attack was picked
+    FriendlySelected { entity: Entity, region: RegionId },
+    /// An attack was ordered and waits for its battle to resolve
+    AttackPending {
+        attacker_entity: Entity,
+        attacker: RegionId,
+        defender_entity: Entity,
+        defender: RegionId,
+    },
+}
 
-                    if patch_hexes.len() == 1 {
-                        break;
-                    }
+/// What a click on a region asks for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionOutcome {
+    None,
+    Attack {
+        attacker: RegionId,
+        defender: RegionId,
+    },
+    /// The clicked neighbour belongs to a player bound by a pact
+    Truce {
+        player: usize,
+    },
+}
 
-                    // check whether patch has any neighbors or start over
-                    for patch_hex in patch_hexes.iter() {
-                        let hex = HexCoord::new(patch_hex.0, patch_hex.1);
-                        for neighbor in hex.neighbors() {
-                            if board.hexes.get(&(neighbor.q, neighbor.r)).is_some() {
-                                has_neighbours = true;
-                                break;
-                            }
-                        }
-                    }
+impl SelectedRegion {
+    /// Entity of the picked region, or of the attacker while its attack is pending
+    pub fn entity(&self) -> Option<Entity> {
+        match *self {
+            SelectedRegion::Nothing => None,
+            SelectedRegion::FriendlySelected { entity, .. } => Some(entity),
+            SelectedRegion::AttackPending {
+                attacker_entity, ..
+            } => Some(attacker_entity),
+        }
+    }
 
-                    // if could not generate a patch with a neightbours, start over
-                    // except for the first patch
-                    if player == 0 && patch == 0 {
-                        has_neighbours = true;
-                    }
+    /// The picked region, or the attacker while its attack is pending
+    pub fn region(&self) -> Option<RegionId> {
+        match *self {
+            SelectedRegion::Nothing => None,
+            SelectedRegion::FriendlySelected { region, .. } => Some(region),
+            SelectedRegion::AttackPending { attacker, .. } => Some(attacker),
+        }
+    }
 
-                    // if patch has neighbours, add it to the board
-                    // else, start over
-                    if has_neighbours {
-                        board.hexes = hex_snapshot;
-                        board.regions.push(Region {
-                            hexes: patch_hexes,
-                            owner: player,
-                            num_dice: 0,
-                            id: board.regions.len(),
-                        });
-                        break;
-                    }
+    /// Entity of the region under attack while the attack is pending
+    pub fn defender_entity(&self) -> Option<Entity> {
+        match *self {
+            SelectedRegion::AttackPending {
+                defender_entity, ..
+            } => Some(defender_entity),
+            _ => None,
+        }
+    }
+
+    /// Moves the selection on after a click on `region`. Clicking a region of the player in turn
+    /// picks it, or drops it when it was already picked or has no dice to attack with. Clicking a
+    /// neighbouring opponent with a region picked orders an attack, and clicks are ignored until
+    /// its battle resolves.
+    pub fn click(
+        &mut self,
+        entity: Entity,
+        region: RegionId,
+        game_state: &GameState,
+        rules: &GameRules,
+    ) -> SelectionOutcome {
+        let clicked = &game_state.board.regions[region];
+
+        let selected = match *self {
+            SelectedRegion::AttackPending { .. } => return SelectionOutcome::None,
+            SelectedRegion::FriendlySelected {
+                entity: selected_entity,
+                region: selected,
+            } => Some((selected_entity, selected)),
+            SelectedRegion::Nothing => None,
+        };
+
+        if clicked.owner == game_state.turn_of_player {
+            *self = match selected {
+                Some((_, selected)) if selected == region => SelectedRegion::Nothing,
+                _ if clicked.has_moved || rules.attacker_roll_size(clicked.num_dice) == 0 => {
+                    SelectedRegion::Nothing
                 }
+                _ => SelectedRegion::FriendlySelected { entity, region },
+            };
+            return SelectionOutcome::None;
+        }
+
+        let (attacker_entity, attacker) = match selected {
+            Some(selected) => selected,
+            None => return SelectionOutcome::None,
+        };
+        let attacking = &game_state.board.regions[attacker];
+
+        if game_state.can_attack(attacking, clicked, rules) {
+            *self = SelectedRegion::AttackPending {
+                attacker_entity,
+                attacker,
+                defender_entity: entity,
+                defender: region,
+            };
+            SelectionOutcome::Attack {
+                attacker,
+                defender: region,
             }
+        } else if attacking.is_opponent(clicked) {
+            SelectionOutcome::Truce {
+                player: clicked.owner,
+            }
+        } else {
+            *self = SelectedRegion::Nothing;
+            SelectionOutcome::None
         }
     }
 
-    // allocate dice
-    let mut dice_budget: HashMap<usize, usize> = HashMap::new();
-    for p in 0..number_of_players {
-        dice_budget.insert(p, NUMBER_OF_PATCHES * 4);
+    /// Drops a picked region, leaving a pending attack alone
+    pub fn cancel(&mut self) {
+        if let SelectedRegion::FriendlySelected { .. } = self {
+            *self = SelectedRegion::Nothing;
+        }
     }
 
-    for region in board.regions.iter_mut() {
-        region.num_dice = rng.gen_range(1..usize::min(4, dice_budget[&region.owner]));
-        dice_budget.insert(region.owner, dice_budget[&region.owner] - region.num_dice);
+    /// Clears the selection whatever state it is in
+    pub fn deselect(&mut self) {
+        *self = SelectedRegion::Nothing;
     }
-
-    board
 }
 
-#[derive(Default, Resource)]
-pub struct SelectedRegion {
-    pub entity: Option<Entity>,
-    pub region: Option<Region>,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+    use crate::rules::TieRule;
 
-impl SelectedRegion {
-    pub fn select(&mut self, entity: Entity, region: Region) {
-        self.entity = Some(entity);
-        self.region = Some(region);
+    #[test]
+    fn automatic_reinforcements_bank_overflow() {
+        let map =
+            MapFile::parse("players 2\nregion 0 7 0,0\nregion 0 6 1,0\nregion 1 1 5,5\n").unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            reserves: vec![5, 0],
+            ..Default::default()
+        };
+        let rules = GameRules {
+            reinforcements: ReinforcementRule::Automatic,
+            reserve_cap: 3,
+            ..Default::default()
+        };
+
+        assert!(game_state.finish_turn(&rules));
+        assert_eq!(game_state.board.regions[0].num_dice, 8);
+        assert_eq!(game_state.board.regions[1].num_dice, 8);
+        assert_eq!(game_state.reserve(0), 3);
+        assert_eq!(game_state.turn_of_player, 1);
     }
 
-    pub fn deselect(&mut self) {
-        self.entity = None;
-        self.region = None;
+    #[test]
+    fn transformed_boards_play_the_same() {
+        let map = MapFile::parse(
+            "players 2\nregion 0 3 0,0 1,0\nregion 1 2 2,0 2,1\nregion 0 4 0,1\nregion 1 5 5,5\n",
+        )
+        .unwrap();
+        let board = map.board;
+
+        for transformed in [board.rotated(1), board.rotated(4), board.mirrored()] {
+            assert_ne!(transformed.regions[0].hexes, board.regions[0].hexes);
+            assert_eq!(transformed.hexes.len(), board.hexes.len());
+            assert_eq!(transformed.adjacencies(), board.adjacencies());
+            assert_eq!(transformed.threat_map(), board.threat_map());
+            for player in 0..2 {
+                assert_eq!(
+                    transformed.largest_connected_area(player),
+                    board.largest_connected_area(player)
+                );
+            }
+            for region in transformed.regions.iter() {
+                assert!(region.is_contiguous());
+                for hex in region.hexes.iter() {
+                    assert_eq!(transformed.hexes[hex], region.owner);
+                }
+            }
+        }
+
+        assert_eq!(board.rotated(6).regions[1].hexes, board.regions[1].hexes);
+        assert_eq!(
+            board.mirrored().mirrored().regions[1].hexes,
+            board.regions[1].hexes
+        );
+    }
+
+    #[test]
+    fn region_keys_follow_the_hexes() {
+        let map = MapFile::parse("players 2\nregion 0 3 0,0 1,0\nregion 1 2 2,0\nregion 1 1 0,1\n")
+            .unwrap();
+        let mut board = map.board;
+        let keys = board.region_keys();
+
+        assert_eq!(keys[0], RegionKey::from_hexes(&[(1, 0), (0, 0), (1, 0)]));
+        assert_ne!(keys[1], keys[2]);
+        assert_eq!(keys[0].to_string().len(), 16);
+        assert_eq!(keys[0].to_string().parse(), Ok(keys[0]));
+
+        // reordered regions keep their keys
+        board.regions.swap(0, 2);
+        for (id, region) in board.regions.iter_mut().enumerate() {
+            region.id = id;
+        }
+        assert_eq!(board.region_by_key(keys[0]), Some(2));
+        assert_eq!(board.region_by_key(keys[2]), Some(0));
+        assert_eq!(board.region_by_key(RegionKey(0)), None);
+    }
+
+    #[test]
+    fn threat_map_sums_enemy_dice() {
+        let map =
+            MapFile::parse("players 2\nregion 0 1 0,0\nregion 1 3 1,0\nregion 1 2 2,0\n").unwrap();
+        let threats = map.board.threat_map();
+
+        assert_eq!(threats[0].enemy_dice, 3);
+        assert!(threats[1].is_border);
+        assert_eq!(threats[2].enemy_dice, 0);
+        assert!(!threats[2].is_border);
+    }
+
+    #[test]
+    fn path_goes_around_other_players() {
+        let map = MapFile::parse(
+            "players 2\nregion 0 1 0,0\nregion 1 1 1,0\nregion 0 1 2,0\nregion 0 1 0,1 1,1 2,1\n",
+        )
+        .unwrap();
+
+        assert_eq!(map.board.path(0, 2), Some(vec![0, 1, 2]));
+        assert_eq!(map.board.owned_path(0, 2, 0), Some(vec![0, 3, 2]));
+        assert_eq!(map.board.owned_path(0, 1, 0), None);
+    }
+
+    #[test]
+    fn adjacencies_list_every_neighbouring_pair_once() {
+        let map = MapFile::parse(
+            "players 2\nregion 0 1 0,0\nregion 1 1 1,0\nregion 0 1 2,0\nregion 1 1 7,7\n",
+        )
+        .unwrap();
+
+        assert_eq!(map.board.adjacencies(), vec![(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn largest_connected_area_follows_neighbours() {
+        let map = MapFile::parse(
+            "players 2\nregion 0 1 0,0\nregion 1 1 1,0\nregion 0 1 2,0\nregion 0 1 3,0\n",
+        )
+        .unwrap();
+
+        assert_eq!(map.board.largest_connected_area(0), (2, vec![2, 3]));
+        assert_eq!(map.board.largest_connected_area(1), (1, vec![1]));
+    }
+
+    #[test]
+    fn blocked_player_passes_at_turn_start() {
+        let map =
+            MapFile::parse("players 3\nregion 0 3 0,0\nregion 1 3 1,0\nregion 2 3 5,5\n").unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 3,
+            ..Default::default()
+        };
+        let rules = GameRules::default();
+
+        assert!(!game_state.must_pass(&rules));
+        assert_eq!(game_state.pass(&rules), None);
+
+        // player 1 attacked, it doesn't pass even though it can't attack anymore
+        game_state.turn_of_player = 1;
+        game_state.board.regions[1].has_moved = true;
+        assert!(!game_state.must_pass(&rules));
+
+        game_state.turn_of_player = 2;
+        game_state.board.regions[1].has_moved = false;
+        assert_eq!(game_state.pass(&rules), Some(2));
+        assert_eq!(game_state.turn_of_player, 0);
+        assert_eq!(game_state.turn_counter, 1);
+    }
+
+    #[test]
+    fn ending_the_turn_early_still_earns_reinforcements() {
+        let map = MapFile::parse("players 2\nregion 0 3 0,0\nregion 1 1 1,0\n").unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        let rules = GameRules {
+            reinforcements: ReinforcementRule::Manual,
+            ..Default::default()
+        };
+
+        assert!(!game_state.finish_turn(&rules));
+        assert_eq!(game_state.turn_phase, TurnPhase::Attack);
+
+        assert!(!game_state.end_turn(&rules));
+        assert_eq!(game_state.turn_phase, TurnPhase::Reinforcement);
+        assert_eq!(game_state.reserve(0), 1);
+    }
+
+    fn selection_game_state() -> GameState {
+        let map = MapFile::parse(
+            "players 2\nregion 0 3 0,0\nregion 0 3 5,5\nregion 1 2 1,0\nregion 1 2 9,9\n",
+        )
+        .unwrap();
+        GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn clicking_the_selected_region_again_clears_it() {
+        let game_state = selection_game_state();
+        let rules = GameRules::default();
+        let mut selected_region = SelectedRegion::default();
+        let entity = Entity::from_raw(0);
+
+        selected_region.click(entity, 0, &game_state, &rules);
+        assert_eq!(
+            selected_region,
+            SelectedRegion::FriendlySelected { entity, region: 0 }
+        );
+
+        selected_region.click(entity, 0, &game_state, &rules);
+        assert_eq!(selected_region, SelectedRegion::Nothing);
+        assert_eq!(selected_region.region(), None);
+    }
+
+    #[test]
+    fn clicking_another_friendly_region_moves_the_selection() {
+        let game_state = selection_game_state();
+        let rules = GameRules::default();
+        let mut selected_region = SelectedRegion::default();
+
+        selected_region.click(Entity::from_raw(0), 0, &game_state, &rules);
+        selected_region.click(Entity::from_raw(1), 1, &game_state, &rules);
+        assert_eq!(selected_region.entity(), Some(Entity::from_raw(1)));
+        assert_eq!(selected_region.region(), Some(1));
+    }
+
+    #[test]
+    fn attack_stays_pending_until_deselected() {
+        let game_state = selection_game_state();
+        let rules = GameRules::default();
+        let mut selected_region = SelectedRegion::default();
+
+        selected_region.click(Entity::from_raw(0), 0, &game_state, &rules);
+        assert_eq!(
+            selected_region.click(Entity::from_raw(2), 2, &game_state, &rules),
+            SelectionOutcome::Attack {
+                attacker: 0,
+                defender: 2
+            }
+        );
+        assert_eq!(selected_region.defender_entity(), Some(Entity::from_raw(2)));
+
+        // clicks and cancelling wait for the battle
+        selected_region.click(Entity::from_raw(1), 1, &game_state, &rules);
+        selected_region.cancel();
+        assert_eq!(selected_region.region(), Some(0));
+
+        selected_region.deselect();
+        assert_eq!(selected_region, SelectedRegion::Nothing);
+    }
+
+    #[test]
+    fn clicking_an_unreachable_opponent_clears_the_selection() {
+        let mut game_state = selection_game_state();
+        let rules = GameRules::default();
+        let mut selected_region = SelectedRegion::default();
+
+        selected_region.click(Entity::from_raw(0), 0, &game_state, &rules);
+        selected_region.click(Entity::from_raw(3), 3, &game_state, &rules);
+        assert_eq!(selected_region, SelectedRegion::Nothing);
+
+        // a truce keeps the selection so another target can be picked
+        game_state.pacts.push(Pact {
+            player_1: 0,
+            player_2: 1,
+            expires_at_turn: 5,
+        });
+        selected_region.click(Entity::from_raw(0), 0, &game_state, &rules);
+        assert_eq!(
+            selected_region.click(Entity::from_raw(2), 2, &game_state, &rules),
+            SelectionOutcome::Truce { player: 1 }
+        );
+        assert_eq!(selected_region.region(), Some(0));
+    }
+
+    #[test]
+    fn garrisons_stay_behind() {
+        let mut game_state = selection_game_state();
+        let rules = GameRules {
+            garrison: 3,
+            transfer_rule: TransferRule::AllButOne,
+            ..Default::default()
+        };
+        let mut selected_region = SelectedRegion::default();
+
+        // three dice are all garrison, nothing is left to attack with
+        assert!(game_state.must_pass(&rules));
+        selected_region.click(Entity::from_raw(0), 0, &game_state, &rules);
+        assert_eq!(selected_region, SelectedRegion::Nothing);
+
+        game_state.board.regions[0].num_dice = 5;
+        assert_eq!(game_state.possible_moves(&rules), vec![(0, 2)]);
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+        let outcome = game_state.resolve_battle(0, 2, &[6, 6], &[1, 1], &rules, &mut rng);
+
+        assert!(outcome.attacker_won);
+        assert_eq!(outcome.transferred, 2);
+        assert_eq!(game_state.board.regions[0].num_dice, 3);
+        assert_eq!(game_state.board.regions[2].num_dice, 2);
+    }
+
+    #[test]
+    fn rerolls_give_up_on_endless_ties() {
+        let mut game_state = selection_game_state();
+        let rules = GameRules {
+            tie_rule: TieRule::Reroll,
+            ..Default::default()
+        };
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+
+        // without any dice every reroll ties again
+        let outcome = game_state.resolve_battle(0, 2, &[], &[], &rules, &mut rng);
+        assert!(!outcome.attacker_won);
+        assert_eq!(game_state.board.regions[0].owner, 1);
+    }
+
+    #[test]
+    fn transfer_rules_leave_the_winner_a_die() {
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+
+        for winner_dice in 2..=8 {
+            let transferred = resolve_transfer(winner_dice, TransferRule::Random, &mut rng);
+            assert!((1..winner_dice).contains(&transferred));
+            assert_eq!(
+                resolve_transfer(winner_dice, TransferRule::AllButOne, &mut rng),
+                winner_dice - 1
+            );
+        }
+
+        assert_eq!(
+            resolve_transfer(8, TransferRule::Percentage(50), &mut rng),
+            4
+        );
+        assert_eq!(
+            resolve_transfer(5, TransferRule::Percentage(50), &mut rng),
+            2
+        );
+        assert_eq!(
+            resolve_transfer(8, TransferRule::Percentage(10), &mut rng),
+            1
+        );
+        assert_eq!(
+            resolve_transfer(8, TransferRule::Percentage(100), &mut rng),
+            7
+        );
+    }
+
+    #[test]
+    fn single_die_winners_dont_transfer() {
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+
+        for rule in [
+            TransferRule::Random,
+            TransferRule::AllButOne,
+            TransferRule::Percentage(100),
+        ] {
+            assert_eq!(resolve_transfer(1, rule, &mut rng), 0);
+        }
+    }
+
+    #[test]
+    fn later_seats_get_compensation_dice() {
+        let map = MapFile::parse(
+            "players 3\nregion 0 3 0,0\nregion 1 3 2,0\nregion 1 7 3,0\nregion 2 7 5,0\n",
+        )
+        .unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 3,
+            ..Default::default()
+        };
+        let rules = GameRules {
+            compensation_dice: 1,
+            ..Default::default()
+        };
+
+        game_state.seat_players(1, &rules);
+
+        assert_eq!(game_state.turn_of_player, 1);
+        // player 1 moves first, player 2 second and player 0 last
+        assert_eq!(game_state.board.regions[0].num_dice, 5);
+        assert_eq!(game_state.board.regions[1].num_dice, 3);
+        assert_eq!(game_state.board.regions[2].num_dice, 7);
+        // full regions don't take more dice
+        assert_eq!(game_state.board.regions[3].num_dice, 8);
+
+        // seating again on the board from before the compensation gives the same dice
+        let num_dice = |board: &Board| -> Vec<usize> {
+            board.regions.iter().map(|region| region.num_dice).collect()
+        };
+        let unseated = game_state.unseated_board();
+        assert_eq!(num_dice(&unseated), vec![3, 3, 7, 7]);
+        let mut rematch = GameState {
+            board: unseated,
+            number_of_players: 3,
+            ..Default::default()
+        };
+        rematch.seat_players(1, &rules);
+        assert_eq!(num_dice(&rematch.board), num_dice(&game_state.board));
+    }
+
+    #[test]
+    fn random_first_player_follows_the_world_seed() {
+        let rules = GameRules {
+            random_first_player: true,
+            ..GameRules::with_players(4)
+        };
+
+        let first_players: Vec<usize> = (0..32).map(|seed| first_player(&rules, seed)).collect();
+        assert!(first_players.iter().all(|player| *player < 4));
+        assert!(first_players
+            .iter()
+            .any(|player| *player != first_players[0]));
+        assert_eq!(first_player(&rules, 9), first_player(&rules, 9));
+        assert_eq!(first_player(&GameRules::with_players(4), 9), 0);
+    }
+
+    #[test]
+    fn decided_winner_ends_the_game_once() {
+        let map = MapFile::parse("players 2\nregion 0 3 0,0\nregion 1 3 1,0\n").unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        assert_eq!(game_state.winner(), None);
+
+        assert!(game_state.decide_winner(1));
+        assert_eq!(game_state.winner(), Some(1));
+        // a timeout after the objectives were met doesn't end the game again
+        assert!(!game_state.decide_winner(0));
+        assert_eq!(game_state.winner(), Some(1));
+
+        // nor does a stalemate once a player owns the board
+        game_state.decided_winner = None;
+        game_state.board.regions[1].owner = 0;
+        assert!(!game_state.decide_winner(1));
+        assert_eq!(game_state.winner(), Some(0));
     }
 }
@@ -0,0 +1,606 @@
+//! Board generators. Every generator builds the regions of a fresh board and deals their dice,
+//! [`crate::game::generate_board`] picks one by name from [`GameRules::board_generator`].
+//!
+//! A new generator implements [`BoardGenerator`] and is listed in [`board_generators`].
+
+use std::collections::{HashMap, HashSet};
+
+use rand::{seq::IteratorRandom, Rng, RngCore};
+
+use crate::game::{Board, Region};
+use crate::hex::HexCoord;
+use crate::rules::GameRules;
+
+/// Algorithm building the board a game starts on
+pub trait BoardGenerator: Send + Sync {
+    /// Name the generator is selected by
+    fn name(&self) -> &'static str;
+
+    /// Builds a board for `rules`. The same rules and random sequence always build the same board.
+    fn generate(&self, rules: &GameRules, rng: &mut dyn RngCore) -> Board;
+}
+
+/// Every available generator, the default one first
+pub fn board_generators() -> Vec<Box<dyn BoardGenerator>> {
+    vec![Box::new(PatchGenerator), Box::new(NoiseGenerator)]
+}
+
+/// The generator called `name`, if there is one
+pub fn board_generator(name: &str) -> Option<Box<dyn BoardGenerator>> {
+    board_generators()
+        .into_iter()
+        .find(|generator| generator.name() == name)
+}
+
+/// Grows a patch of hexes from a random free starting point for every region, taking turns
+/// between players, then deals each player its share of dice at random
+pub struct PatchGenerator;
+
+impl BoardGenerator for PatchGenerator {
+    fn name(&self) -> &'static str {
+        "patches"
+    }
+
+    fn generate(&self, rules: &GameRules, mut rng: &mut dyn RngCore) -> Board {
+        let number_of_players = rules.number_of_players;
+        let half_board_size = rules.board_size / 2 - 1;
+
+        // Roughly half of the board occupied by patches (regions)
+        let patch_size: isize = (rules.board_size * rules.board_size)
+            / (rules.regions_per_player * number_of_players * 2) as isize;
+
+        let mut board = Board::default();
+        let mut seeds = SeedSpacing::new(rules);
+
+        for patch in 0..rules.regions_per_player {
+            seeds.next_round();
+            for player in 0..number_of_players {
+                let mut is_starting_point_valid = false;
+
+                while !is_starting_point_valid {
+                    let mut has_neighbours = false;
+
+                    while !has_neighbours {
+                        let mut hex_snapshot = board.hexes.clone();
+
+                        // check if starting position is empty
+                        let initial_coord = (
+                            rng.gen_range(-half_board_size..half_board_size),
+                            rng.gen_range(-half_board_size..half_board_size),
+                        );
+
+                        // keep away from the patches the other players just started
+                        let seed = HexCoord::new(initial_coord.0, initial_coord.1);
+                        let on_board = |hex: &HexCoord| board.hexes.contains_key(&(hex.q, hex.r));
+                        if !seeds.is_far_enough(player, &seed, on_board) {
+                            seeds.reject();
+                            continue;
+                        }
+
+                        if board.hexes.get(&initial_coord).is_none() {
+                            is_starting_point_valid = true;
+                            hex_snapshot.insert(initial_coord, player);
+                        } else {
+                            // try over
+                            continue;
+                        }
+
+                        // expand until size limit is reached or no more space to grow
+                        let mut patch_hexes: Vec<(isize, isize)> = vec![initial_coord];
+
+                        for _ in 0..patch_size {
+                            // find a bordering hex. use random iterating order to avoid bias
+                            let mut neightbour_hex: Option<HexCoord> = None;
+                            for coord in patch_hexes
+                                .iter()
+                                .choose_multiple(&mut rng, patch_hexes.iter().len())
+                            {
+                                let hex = HexCoord::new(coord.0, coord.1);
+                                // iterate over all neighbors and find a free one
+                                for neighbor in hex.neighbors() {
+                                    if hex_snapshot.get(&(neighbor.q, neighbor.r)).is_none() {
+                                        neightbour_hex = Some(hex.clone());
+                                        break;
+                                    }
+                                }
+
+                                // continue expanding a border hex
+                                if neightbour_hex.is_some() {
+                                    break;
+                                }
+                            }
+
+                            // no more hex cells in this patch
+                            if neightbour_hex.is_none() {
+                                break;
+                            }
+
+                            // add a new hex to the patch
+                            let mut candidates: Vec<(isize, isize)> = vec![];
+                            for neighbour in neightbour_hex.unwrap().neighbors() {
+                                let neighbour_coord = (neighbour.q, neighbour.r);
+                                if hex_snapshot.get(&neighbour_coord).is_none() {
+                                    candidates.push(neighbour_coord);
+                                }
+                            }
+                            let candidate = candidates.iter().choose(&mut rng).unwrap();
+                            patch_hexes.push(*candidate);
+                            hex_snapshot.insert(*candidate, player);
+                        }
+
+                        if patch_hexes.len() == 1 {
+                            break;
+                        }
+
+                        // check whether patch has any neighbors or start over
+                        for patch_hex in patch_hexes.iter() {
+                            let hex = HexCoord::new(patch_hex.0, patch_hex.1);
+                            for neighbor in hex.neighbors() {
+                                if board.hexes.get(&(neighbor.q, neighbor.r)).is_some() {
+                                    has_neighbours = true;
+                                    break;
+                                }
+                            }
+                        }
+
+                        // if could not generate a patch with a neightbours, start over
+                        // except for the first patch
+                        if player == 0 && patch == 0 {
+                            has_neighbours = true;
+                        }
+
+                        // if patch has neighbours, add it to the board
+                        // else, start over
+                        if has_neighbours {
+                            seeds.place(player, seed);
+                            board.hexes = hex_snapshot;
+                            board.regions.push(Region {
+                                hexes: patch_hexes,
+                                owner: player,
+                                num_dice: 0,
+                                id: board.regions.len(),
+                                has_moved: false,
+                                name: String::new(),
+                            });
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        deal_dice(&mut board, rules, rng);
+
+        board
+    }
+}
+
+/// Number of seeds rejected for being too close before the spacing is relaxed by a hex
+const SPACING_ATTEMPTS: usize = 50;
+
+/// Keeps the seeds of the patches of a round apart from the seeds of other players, so that the
+/// regions of a player don't all face the same opponent. See [`GameRules::min_seed_spacing`].
+///
+/// Seeds are apart by the number of steps from one to the other over the hexes already on the
+/// board, the way the armies would have to march. Seeds that land doesn't connect yet are far
+/// enough apart.
+///
+/// Dense boards can't always honor the spacing: it's relaxed after [`SPACING_ATTEMPTS`] rejected
+/// seeds, and restored for the next patch.
+struct SeedSpacing {
+    min_spacing: isize,
+    spacing: isize,
+    rejected: usize,
+    /// Owner and seed of every patch of the current round
+    seeds: Vec<(usize, HexCoord)>,
+}
+
+impl SeedSpacing {
+    fn new(rules: &GameRules) -> Self {
+        let min_spacing = rules.min_seed_spacing as isize;
+        SeedSpacing {
+            min_spacing,
+            spacing: min_spacing,
+            rejected: 0,
+            seeds: Vec::new(),
+        }
+    }
+
+    fn next_round(&mut self) {
+        self.seeds.clear();
+    }
+
+    /// Whether a patch of `player` may start at `seed`, walking over the hexes `on_board` accepts
+    fn is_far_enough(
+        &self,
+        player: usize,
+        seed: &HexCoord,
+        on_board: impl Fn(&HexCoord) -> bool,
+    ) -> bool {
+        let others: Vec<&HexCoord> = self
+            .seeds
+            .iter()
+            .filter(|(owner, _)| *owner != player)
+            .map(|(_, other)| other)
+            .collect();
+        if others.is_empty() {
+            return true;
+        }
+
+        // hexes `step` steps away from the seed, for every step closer than the spacing
+        let mut reached = HashSet::from([seed.clone()]);
+        let mut frontier = vec![seed.clone()];
+        for _ in 0..self.spacing {
+            if frontier.iter().any(|hex| others.contains(&hex)) {
+                return false;
+            }
+            frontier = frontier
+                .iter()
+                .flat_map(|hex| hex.neighbors())
+                .filter(|neighbor| on_board(neighbor) && reached.insert(neighbor.clone()))
+                .collect();
+        }
+
+        true
+    }
+
+    fn reject(&mut self) {
+        self.rejected += 1;
+        if self.rejected % SPACING_ATTEMPTS == 0 {
+            self.spacing -= 1;
+        }
+    }
+
+    fn place(&mut self, player: usize, seed: HexCoord) {
+        self.seeds.push((player, seed));
+        self.spacing = self.min_spacing;
+        self.rejected = 0;
+    }
+}
+
+/// Deals each player [`GameRules::dice_per_region`] dice for each of its regions: one on every
+/// region, then the rest one at a time onto random regions of the player holding fewer than
+/// [`GameRules::max_dice`]. Dice that don't fit are lost.
+fn deal_dice(board: &mut Board, rules: &GameRules, rng: &mut dyn RngCore) {
+    for region in board.regions.iter_mut() {
+        region.num_dice = 1;
+    }
+
+    for player in 0..rules.number_of_players {
+        let regions = board
+            .regions
+            .iter()
+            .filter(|region| region.owner == player)
+            .count();
+        for _ in regions..regions * rules.dice_per_region {
+            let region = board
+                .regions
+                .iter_mut()
+                .filter(|region| region.owner == player && region.num_dice < rules.max_dice)
+                .choose(rng);
+            match region {
+                Some(region) => region.num_dice += 1,
+                None => break,
+            }
+        }
+    }
+}
+
+/// Smooth 2D value noise: random values on an integer lattice, blended in between
+pub struct ValueNoise {
+    seed: u64,
+}
+
+impl ValueNoise {
+    pub fn new(seed: u64) -> Self {
+        ValueNoise { seed }
+    }
+
+    /// Random value in `0.0..1.0` of a lattice point
+    fn lattice(&self, x: i64, y: i64) -> f32 {
+        // splitmix64 finalizer over the point and the seed
+        let mut z = self
+            .seed
+            .wrapping_add((x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+            .wrapping_add((y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f));
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        (z >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Noise value in `0.0..1.0` at a point, changing smoothly with the point
+    pub fn sample(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+        let (tx, ty) = (smooth(x - x0), smooth(y - y0));
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.lattice(x0, y0) * (1.0 - tx) + self.lattice(x0 + 1, y0) * tx;
+        let bottom = self.lattice(x0, y0 + 1) * (1.0 - tx) + self.lattice(x0 + 1, y0 + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+/// Grows regions one after the other from the edge of the board, sized by their distance to the
+/// center and by noise: small contested regions in the core, large ones on the fringes. Patches
+/// grow along the noise, giving them organic shapes.
+///
+/// Tuned with [`GameRules::noise_scale`] and [`GameRules::noise_strength`].
+pub struct NoiseGenerator;
+
+impl NoiseGenerator {
+    /// Hexes on the edge of the board, closest to the center first
+    fn frontier(hexes: &HashMap<(isize, isize), usize>) -> Vec<(isize, isize)> {
+        let mut frontier: Vec<(isize, isize)> = hexes
+            .keys()
+            .flat_map(|(q, r)| HexCoord::new(*q, *r).neighbors().collect::<Vec<_>>())
+            .map(|hex| (hex.q, hex.r))
+            .filter(|coord| !hexes.contains_key(coord))
+            .collect();
+        // sorted, the iteration order of the map must not leak into the board
+        frontier
+            .sort_by_key(|(q, r)| (HexCoord::new(*q, *r).distance(&HexCoord::origin()), *q, *r));
+        frontier.dedup();
+
+        frontier
+    }
+}
+
+impl BoardGenerator for NoiseGenerator {
+    fn name(&self) -> &'static str {
+        "noise"
+    }
+
+    fn generate(&self, rules: &GameRules, rng: &mut dyn RngCore) -> Board {
+        let number_of_players = rules.number_of_players;
+        let number_of_regions = rules.regions_per_player * number_of_players;
+        let noise = ValueNoise::new(rng.next_u64());
+        let noise_at = |(q, r): (isize, isize)| {
+            let scale = rules.noise_scale.max(1.0);
+            noise.sample(q as f32 / scale, r as f32 / scale)
+        };
+
+        // Same share of the board as the patch generator, on average
+        let patch_size =
+            ((rules.board_size * rules.board_size) as usize / (number_of_regions * 2)).max(1);
+        // Radius of a hexagon holding every region
+        let radius = ((number_of_regions * patch_size) as f32 / 3.0)
+            .sqrt()
+            .max(1.0);
+
+        let mut board = Board::default();
+        // Region of every hex
+        let mut owners: HashMap<(isize, isize), usize> = HashMap::new();
+        let mut seeds = SeedSpacing::new(rules);
+
+        for id in 0..number_of_regions {
+            let owner = id % number_of_players;
+            if owner == 0 {
+                seeds.next_round();
+            }
+
+            let seed = match id {
+                0 => (0, 0),
+                _ => {
+                    // start next to the board, near the center, away from the other players
+                    let frontier = Self::frontier(&owners);
+                    let spaced: Vec<(isize, isize)> = frontier
+                        .iter()
+                        .copied()
+                        .filter(|(q, r)| {
+                            seeds.is_far_enough(owner, &HexCoord::new(*q, *r), |hex| {
+                                owners.contains_key(&(hex.q, hex.r))
+                            })
+                        })
+                        .take(6)
+                        .collect();
+                    let candidates = match spaced.is_empty() {
+                        true => &frontier[..frontier.len().min(6)],
+                        false => &spaced[..],
+                    };
+                    candidates[rng.gen_range(0..candidates.len())]
+                }
+            };
+            seeds.place(owner, HexCoord::new(seed.0, seed.1));
+
+            let radial = (HexCoord::new(seed.0, seed.1).distance(&HexCoord::origin()) as f32
+                / radius)
+                .min(1.0);
+            let strength = rules.noise_strength.clamp(0.0, 1.0);
+            let factor = 1.0 + strength * (radial + noise_at(seed) - 1.0);
+            let target_size = ((patch_size as f32 * factor).round() as usize).max(1);
+
+            let mut patch_hexes = vec![seed];
+            owners.insert(seed, id);
+            while patch_hexes.len() < target_size {
+                let mut candidates: Vec<(isize, isize)> = patch_hexes
+                    .iter()
+                    .flat_map(|(q, r)| HexCoord::new(*q, *r).neighbors().collect::<Vec<_>>())
+                    .map(|hex| (hex.q, hex.r))
+                    .filter(|coord| !owners.contains_key(coord))
+                    .collect();
+                candidates.sort();
+                candidates.dedup();
+                if candidates.is_empty() {
+                    break;
+                }
+
+                // follow the noise contour of the seed, with some jitter
+                let seed_noise = noise_at(seed);
+                let candidate = *candidates
+                    .iter()
+                    .map(|coord| {
+                        let score = (noise_at(*coord) - seed_noise).abs() + rng.gen::<f32>() * 0.2;
+                        (coord, score)
+                    })
+                    .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .map(|(coord, _)| coord)
+                    .unwrap();
+                patch_hexes.push(candidate);
+                owners.insert(candidate, id);
+            }
+
+            for hex in patch_hexes.iter() {
+                board.hexes.insert(*hex, owner);
+            }
+            board.regions.push(Region {
+                hexes: patch_hexes,
+                owner,
+                num_dice: 0,
+                id,
+                has_moved: false,
+                name: String::new(),
+            });
+        }
+
+        deal_dice(&mut board, rules, rng);
+
+        board
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tiered_prng::get_randomness;
+
+    #[test]
+    fn generators_are_found_by_name() {
+        assert_eq!(board_generator("patches").unwrap().name(), "patches");
+        assert!(board_generator("nothing").is_none());
+    }
+
+    #[test]
+    fn noise_stays_in_range_and_is_smooth() {
+        let noise = ValueNoise::new(7);
+        for step in 0..100 {
+            let x = step as f32 * 0.37;
+            let value = noise.sample(x, -x);
+            assert!((0.0..1.0).contains(&value));
+            assert!((noise.sample(x + 0.01, -x) - value).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn noise_generator_builds_connected_regions() {
+        let rules = GameRules {
+            number_of_players: 3,
+            board_generator: "noise".to_string(),
+            ..GameRules::default()
+        };
+        let board = NoiseGenerator.generate(&rules, &mut get_randomness(4242));
+        assert_eq!(board.regions.len(), 3 * rules.regions_per_player);
+
+        for region in board.regions.iter() {
+            assert!(region.is_contiguous());
+            assert!(region.num_dice >= 1);
+
+            // no region is cut off from the rest of the board
+            assert!(board
+                .regions
+                .iter()
+                .any(|other| other.id != region.id && other.is_neighbour(region)));
+        }
+
+        let sizes: Vec<usize> = board.regions.iter().map(|r| r.hexes.len()).collect();
+        assert!(sizes.iter().min() < sizes.iter().max());
+    }
+
+    #[test]
+    fn seeds_of_other_players_are_spaced() {
+        let rules = GameRules {
+            min_seed_spacing: 3,
+            ..GameRules::default()
+        };
+        let mut seeds = SeedSpacing::new(&rules);
+        seeds.place(0, HexCoord::new(0, 0));
+        // a board covering every hex
+        let land = |_: &HexCoord| true;
+
+        assert!(seeds.is_far_enough(0, &HexCoord::new(1, 0), land));
+        assert!(!seeds.is_far_enough(1, &HexCoord::new(2, 0), land));
+        assert!(seeds.is_far_enough(1, &HexCoord::new(3, 0), land));
+
+        // too many rejections relax the spacing
+        for _ in 0..SPACING_ATTEMPTS {
+            seeds.reject();
+        }
+        assert!(seeds.is_far_enough(1, &HexCoord::new(2, 0), land));
+
+        seeds.next_round();
+        assert!(seeds.is_far_enough(1, &HexCoord::new(1, 0), land));
+    }
+
+    #[test]
+    fn seeds_are_spaced_over_the_board() {
+        let rules = GameRules {
+            min_seed_spacing: 3,
+            ..GameRules::default()
+        };
+        let mut seeds = SeedSpacing::new(&rules);
+        seeds.place(0, HexCoord::new(0, 0));
+
+        // two steps over the hex between them
+        let bridge = |hex: &HexCoord| hex.r == 0 && (0..=1).contains(&hex.q);
+        assert!(!seeds.is_far_enough(1, &HexCoord::new(2, 0), bridge));
+        // a gap in the board keeps them apart
+        let gap = |hex: &HexCoord| *hex == HexCoord::origin();
+        assert!(seeds.is_far_enough(1, &HexCoord::new(2, 0), gap));
+        // the long way around is too long
+        let detour = |hex: &HexCoord| hex.distance(&HexCoord::new(1, 0)) == 1;
+        assert!(seeds.is_far_enough(1, &HexCoord::new(2, 0), detour));
+    }
+
+    #[test]
+    fn wide_spacing_still_builds_a_board() {
+        let rules = GameRules {
+            number_of_players: 4,
+            min_seed_spacing: 30,
+            ..GameRules::default()
+        };
+        let board = PatchGenerator.generate(&rules, &mut get_randomness(4242));
+        assert_eq!(board.regions.len(), 4 * rules.regions_per_player);
+    }
+
+    #[test]
+    fn patch_generator_is_deterministic() {
+        let rules = GameRules::default();
+        let board_1 = PatchGenerator.generate(&rules, &mut get_randomness(4242));
+        let board_2 = PatchGenerator.generate(&rules, &mut get_randomness(4242));
+
+        assert_eq!(board_1.hexes, board_2.hexes);
+        assert_eq!(board_1.regions.len(), board_2.regions.len());
+        for region in board_1.regions.iter() {
+            assert!(region.owner < rules.number_of_players);
+            assert!(region.num_dice >= 1);
+        }
+    }
+
+    #[test]
+    fn dice_are_dealt_by_the_rules() {
+        for (dice_per_region, max_dice) in [(1, 8), (3, 8), (5, 6)] {
+            let rules = GameRules {
+                dice_per_region,
+                max_dice,
+                ..GameRules::default()
+            };
+            let board = PatchGenerator.generate(&rules, &mut get_randomness(4242));
+
+            for player in 0..rules.number_of_players {
+                let dice: Vec<usize> = board
+                    .regions
+                    .iter()
+                    .filter(|region| region.owner == player)
+                    .map(|region| region.num_dice)
+                    .collect();
+                assert!(dice.iter().all(|dice| (1..=max_dice).contains(dice)));
+                assert_eq!(
+                    dice.iter().sum::<usize>(),
+                    (dice.len() * dice_per_region).min(dice.len() * max_dice)
+                );
+            }
+        }
+    }
+}
@@ -1,5 +1,7 @@
 // This code taken from https://github.com/Quantumplation/bevy-hex-example
 
+use std::collections::HashMap;
+
 use crate::hex::HexCoord;
 
 /// The ratio between a circle touching the points of a hex grid (the outer radius),
@@ -86,6 +88,19 @@ pub fn south_west_corner(radius: f32, c: &HexCoord, offset: &[f32; 3]) -> [f32;
     [center[0] - inner, center[1] + 0., center[2] - 0.5 * radius]
 }
 
+/// The corners of a hexagon of a specific radius at a specific coordinate, counter-clockwise from the
+/// east corner when seen from above
+pub fn hexagon_corners(radius: f32, c: &HexCoord, offset: &[f32; 3]) -> [[f32; 3]; 6] {
+    [
+        east_corner(radius, c, offset),
+        north_east_corner(radius, c, offset),
+        north_west_corner(radius, c, offset),
+        west_corner(radius, c, offset),
+        south_west_corner(radius, c, offset),
+        south_east_corner(radius, c, offset),
+    ]
+}
+
 /// Fill `pts` with the points around the edge of a flat hexagon of a specific radius at a specific coordinate
 pub fn flat_hexagon_ring(pts: &mut Vec<[f32; 3]>, radius: f32, c: &HexCoord, offset: &[f32; 3]) {
     pts.extend(
@@ -103,10 +118,216 @@ pub fn flat_hexagon_ring(pts: &mut Vec<[f32; 3]>, radius: f32, c: &HexCoord, off
     );
 }
 
-/// Fill `pts` with the points of a flat hexagon of a specific radius at a specific coordinate
-pub fn flat_hexagon_points(pts: &mut Vec<[f32; 3]>, radius: f32, c: &HexCoord) {
-    // We'll create 6 triangles, all sharing a center point
-    pts.push(center(radius, c, &[0., 0., 0.]));
-    flat_hexagon_ring(pts, radius, c, &[0., 0., 0.]);
-    pts.push(center(radius, c, &[0., 0., 0.]));
+/// Vertex and index buffers of a triangle mesh, turned into a Bevy mesh by the board
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshBuffers {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    /// Three indices per triangle, counter-clockwise when seen from outside the mesh
+    pub indices: Vec<u32>,
+}
+
+impl MeshBuffers {
+    #[allow(dead_code)]
+    pub fn triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+    }
+
+    fn push_vertex(&mut self, position: [f32; 3], normal: [f32; 3]) -> u32 {
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.uvs.push([1.0, 1.0]);
+        self.positions.len() as u32 - 1
+    }
+}
+
+/// Top and bottom vertices of a corner of a tile
+type CornerVertices = (u32, u32);
+
+/// Position of a point on the board plane, rounded so corners shared by neighbouring hexes match
+fn grid_key(position: [f32; 3]) -> (i64, i64) {
+    (
+        (position[0] * 1024.0).round() as i64,
+        (position[2] * 1024.0).round() as i64,
+    )
+}
+
+/// Generate the buffers of a tile covering `hexes`, of a specific radius and thickness, with its
+/// top face at the height of `offset`. Corners shared by neighbouring hexes are a single vertex,
+/// and side walls are only generated along the perimeter of the tile, where an edge belongs to a
+/// single hex. Edges are matched by position rather than by neighbouring coordinates, so the
+/// walls follow the tile as it is drawn.
+pub fn hex_tile_buffers(
+    hexes: &[HexCoord],
+    radius: f32,
+    thickness: f32,
+    offset: &[f32; 3],
+) -> MeshBuffers {
+    const UP: [f32; 3] = [0., 1., 0.];
+    const DOWN: [f32; 3] = [0., -1., 0.];
+
+    let mut buffers = MeshBuffers::default();
+    let bottom_offset = [offset[0], offset[1] - thickness, offset[2]];
+
+    // Top and bottom vertices of every corner, by position on the board plane
+    let mut corners: HashMap<(i64, i64), CornerVertices> = HashMap::new();
+    // Center and corners of every hex, counter-clockwise from the east corner
+    let mut faces: Vec<(u32, u32, [CornerVertices; 6])> = vec![];
+    for c in hexes {
+        let top_center = buffers.push_vertex(center(radius, c, offset), UP);
+        let bottom_center = buffers.push_vertex(center(radius, c, &bottom_offset), DOWN);
+        let hex_corners = hexagon_corners(radius, c, offset).map(|corner| {
+            *corners.entry(grid_key(corner)).or_insert_with(|| {
+                let bottom = [corner[0], corner[1] - thickness, corner[2]];
+                (
+                    buffers.push_vertex(corner, UP),
+                    buffers.push_vertex(bottom, DOWN),
+                )
+            })
+        });
+        faces.push((top_center, bottom_center, hex_corners));
+    }
+
+    // Number of hexes every edge belongs to, by the top vertices of its corners
+    let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+    for (_, _, hex_corners) in faces.iter() {
+        for i in 0..6 {
+            let (a, b) = (hex_corners[i].0, hex_corners[(i + 1) % 6].0);
+            *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+        }
+    }
+
+    for (top_center, bottom_center, hex_corners) in faces {
+        for i in 0..6 {
+            // A corner and the next one, counter-clockwise
+            let ((top_a, bottom_a), (top_b, bottom_b)) = (hex_corners[i], hex_corners[(i + 1) % 6]);
+            // Top face, seen from above
+            buffers.indices.extend([top_center, top_a, top_b]);
+            // Bottom face, seen from below
+            buffers.indices.extend([bottom_center, bottom_b, bottom_a]);
+
+            // Side between the two corners, seen from outside
+            if edges[&(top_a.min(top_b), top_a.max(top_b))] == 1 {
+                buffers.indices.extend([top_b, bottom_a, bottom_b]);
+                buffers.indices.extend([top_b, top_a, bottom_a]);
+            }
+        }
+    }
+
+    buffers
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+    }
+
+    fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    }
+
+    fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    /// Checks that every edge of the mesh is shared by exactly two triangles walking it in
+    /// opposite directions, so the mesh is closed and its winding consistent
+    fn check_watertight(buffers: &MeshBuffers) -> Result<(), String> {
+        let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+        for [a, b, c] in buffers.triangles() {
+            for edge in [(a, b), (b, c), (c, a)] {
+                *edges.entry(edge).or_default() += 1;
+            }
+        }
+
+        for (&(a, b), &count) in edges.iter() {
+            if count != 1 || edges.get(&(b, a)) != Some(&1) {
+                return Err(format!("edge {}-{} isn't shared by two triangles", a, b));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn tiles_are_closed_prisms_facing_out() {
+        let c = HexCoord::new(2, 3);
+        let offset = [0.5, 1.0, -2.0];
+        let buffers = hex_tile_buffers(std::slice::from_ref(&c), 1.0, 0.25, &offset);
+
+        assert_eq!(buffers.positions.len(), 14);
+        assert_eq!(buffers.normals.len(), 14);
+        assert_eq!(buffers.uvs.len(), 14);
+        assert_eq!(buffers.triangles().count(), 24);
+        assert_eq!(check_watertight(&buffers), Ok(()));
+
+        // The prism is convex, so every face points away from its middle
+        let top = center(1.0, &c, &offset);
+        let middle = [top[0], top[1] - 0.125, top[2]];
+        for [a, b, c] in buffers.triangles() {
+            let [a, b, c] = [a, b, c].map(|index| buffers.positions[index as usize]);
+            let normal = cross(sub(b, a), sub(c, a));
+            let centroid = [
+                (a[0] + b[0] + c[0]) / 3.0,
+                (a[1] + b[1] + c[1]) / 3.0,
+                (a[2] + b[2] + c[2]) / 3.0,
+            ];
+            assert!(dot(normal, sub(centroid, middle)) > 0.0);
+        }
+    }
+
+    #[test]
+    fn walls_are_only_on_the_perimeter() {
+        // Two hexes side by side share an edge and two corners
+        let hexes = [HexCoord::new(0, 0), HexCoord::new(1, 0)];
+        let buffers = hex_tile_buffers(&hexes, 1.0, 0.25, &[0.0, 0.0, 0.0]);
+
+        assert_eq!(buffers.positions.len(), 2 * 2 + 2 * 10);
+        assert_eq!(buffers.triangles().count(), 2 * 2 * 6 + 2 * 10);
+        assert_eq!(check_watertight(&buffers), Ok(()));
+
+        // No wall stands between the two hexes, all of them are at the outer edge of a hex
+        let middle = center(1.0, &hexes[0], &[0.0, 0.0, 0.0])[0] + HEX_INNER_RADIUS_RATIO;
+        for [a, b, c] in buffers.triangles() {
+            let [a, b, c] = [a, b, c].map(|index| buffers.positions[index as usize]);
+            if a[1] != b[1] || b[1] != c[1] {
+                assert!([a, b, c].iter().any(|p| (p[0] - middle).abs() > 0.01));
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn tiles_have_no_degenerate_triangles(
+            hexes in prop::collection::hash_set((-8isize..8, -8isize..8), 1..12),
+            thickness in 0.0001f32..1.0,
+        ) {
+            let hexes: Vec<HexCoord> = hexes.into_iter().map(|(q, r)| HexCoord::new(q, r)).collect();
+            let buffers = hex_tile_buffers(&hexes, 1.0, thickness, &[0.0, 0.0, 0.0]);
+
+            prop_assert!(buffers.triangles().count() <= 24 * hexes.len());
+            prop_assert_eq!(check_watertight(&buffers), Ok(()));
+            for [a, b, c] in buffers.triangles() {
+                prop_assert!(a != b && b != c && c != a);
+                let [a, b, c] = [a, b, c].map(|index| buffers.positions[index as usize]);
+                let normal = cross(sub(b, a), sub(c, a));
+                prop_assert!(dot(normal, normal) > 0.0);
+                // Top faces point up and bottom faces down
+                if a[1] == b[1] && b[1] == c[1] {
+                    prop_assert_eq!(normal[1] > 0.0, a[1] == 0.0);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,487 @@
+//! GPU picking: finds the region under the cursor by reading its id back from the GPU instead of
+//! casting rays against the tiles, with `--picking gpu`.
+//!
+//! Every region tile and die gets a copy on the [`PICKING_LAYER`], drawn unlit in a color encoding
+//! the id of its region (see [`encode_region_id`]). An id camera follows the board camera and
+//! renders only that layer into an offscreen image the size of the window. After every frame the
+//! pixel under the cursor is copied into a small buffer and read back, so picking costs the same
+//! whatever the number of hexes and dice, and clicking a die picks its region like its tile does.
+//!
+//! The picked region drives the [`Interaction`] of the tiles and the selection events of
+//! bevy_mod_picking, whose raycasts are turned off, so highlights and input work unchanged.
+//!
+//! Ids only survive exactly without blending or color conversion: the id camera renders in HDR,
+//! whose intermediate texture keeps the linear colors the ids are written in, into a linear
+//! `Rgba8Unorm` image, and multisampling is turned off since a resolved edge pixel would average
+//! two ids. [`Msaa`] is a single resource for every camera in this version of Bevy, so the board
+//! loses its antialiasing with `--picking gpu`. The pixel is read back without stalling the
+//! render: its buffer is mapped in the background and read a frame or two later, so the pick lags
+//! behind the cursor, which is why raycasting stays the default.
+
+use std::num::NonZeroU32;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+
+use bevy::core_pipeline::clear_color::ClearColorConfig;
+use bevy::core_pipeline::tonemapping::Tonemapping;
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext};
+use bevy::render::render_resource::{
+    Buffer, BufferDescriptor, BufferUsages, Extent3d, ImageCopyBuffer, ImageCopyTexture,
+    ImageDataLayout, MapMode, Origin3d, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::render::view::RenderLayers;
+use bevy::render::{RenderApp, RenderStage};
+use bevy_mod_picking::{PickingEvent, PickingPluginsState, SelectionEvent};
+use wgpu::{BufferAsyncError, Maintain, COPY_BYTES_PER_ROW_ALIGNMENT};
+
+use crate::board::RegionDie;
+use crate::game::{Region, RegionId};
+use crate::ui::BoardCamera;
+
+/// Render layer of the id copies, only seen by the id camera
+pub const PICKING_LAYER: u8 = 1;
+
+/// Name of the render graph node copying the pixel under the cursor
+const READBACK_NODE: &str = "gpu_picking_readback";
+
+/// How the region under the cursor is found
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PickingMode {
+    /// Rays cast against the top faces of the tiles, by bevy_mod_picking
+    #[default]
+    Raycast,
+    /// Region ids read back from an offscreen image
+    Gpu,
+}
+
+/// Bytes of the id of a region in the id image. Black is left for the background.
+pub fn encode_region_id(id: RegionId) -> [u8; 3] {
+    let value = id as u32 + 1;
+    [(value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+/// Region of a pixel of the id image, none for the background
+pub fn decode_region_id(pixel: [u8; 4]) -> Option<RegionId> {
+    let value = (pixel[0] as u32) << 16 | (pixel[1] as u32) << 8 | pixel[2] as u32;
+    value.checked_sub(1).map(|id| id as RegionId)
+}
+
+/// Pixel of an image of `size` under the cursor at `position`, in logical pixels from the bottom
+/// left corner of a window scaled by `scale_factor`. None outside of the image.
+pub fn cursor_pixel(position: Vec2, scale_factor: f64, size: UVec2) -> Option<UVec2> {
+    let x = (position.x as f64 * scale_factor).floor();
+    let y = size.y as f64 - (position.y as f64 * scale_factor).floor() - 1.0;
+    if x < 0.0 || y < 0.0 || x >= size.x as f64 || y >= size.y as f64 {
+        return None;
+    }
+
+    Some(UVec2::new(x as u32, y as u32))
+}
+
+/// Camera rendering the id copies into the id image
+#[derive(Component)]
+pub(crate) struct IdCamera;
+
+/// Copy of a tile or die on the [`PICKING_LAYER`]
+#[derive(Component)]
+pub(crate) struct PickingId;
+
+/// What the render world reads back: the id image and the pixel under the cursor
+#[derive(Resource, Clone, ExtractResource)]
+pub(crate) struct PickingRequest {
+    image: Handle<Image>,
+    pixel: Option<UVec2>,
+}
+
+/// Last pixel read back, shared by the render world writing it and the main world reading it
+#[derive(Resource, Clone, Default)]
+pub(crate) struct PickedPixel {
+    pixel: Arc<Mutex<Option<[u8; 4]>>>,
+}
+
+/// Color drawing the bytes of an id unchanged into the linear id image
+fn id_color([r, g, b]: [u8; 3]) -> Color {
+    Color::rgba_linear(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0)
+}
+
+/// Where the [`ReadbackBuffer`] is in reading back a pixel
+#[derive(Default)]
+enum ReadbackState {
+    /// Free to copy a new pixel into
+    #[default]
+    Idle,
+    /// A pixel was copied this frame
+    Copied,
+    /// Being mapped for reading, the buffer can't be copied into until it is unmapped
+    Mapping(Receiver<Result<(), BufferAsyncError>>),
+}
+
+/// Buffer the pixel under the cursor is copied into
+#[derive(Resource)]
+struct ReadbackBuffer {
+    buffer: Buffer,
+    state: Mutex<ReadbackState>,
+}
+
+impl FromWorld for ReadbackBuffer {
+    fn from_world(world: &mut World) -> Self {
+        let buffer = world
+            .resource::<RenderDevice>()
+            .create_buffer(&BufferDescriptor {
+                label: Some("gpu_picking_readback"),
+                // a single pixel, on a row as long as copies need
+                size: COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+
+        ReadbackBuffer {
+            buffer,
+            state: Mutex::default(),
+        }
+    }
+}
+
+pub struct GpuPickingPlugin;
+
+impl Plugin for GpuPickingPlugin {
+    fn build(&self, app: &mut App) {
+        let picked = PickedPixel::default();
+
+        // multisampling would blend the ids of neighbouring regions on their edges
+        app.insert_resource(Msaa { samples: 1 })
+            .insert_resource(picked.clone())
+            .add_plugin(ExtractResourcePlugin::<PickingRequest>::default())
+            .add_startup_system(setup_id_camera)
+            .add_system(follow_board_camera)
+            .add_system(spawn_picking_ids)
+            .add_system_to_stage(CoreStage::PreUpdate, pick_regions);
+
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .insert_resource(picked)
+                .init_resource::<ReadbackBuffer>()
+                .add_system_to_stage(RenderStage::Cleanup, read_back_pixel);
+
+            let mut graph = render_app.world.resource_mut::<RenderGraph>();
+            graph.add_node(READBACK_NODE, ReadbackNode);
+            graph
+                .add_node_edge(bevy::render::main_graph::node::CAMERA_DRIVER, READBACK_NODE)
+                .unwrap();
+        }
+    }
+}
+
+fn id_image(size: UVec2) -> Image {
+    let size = Extent3d {
+        width: size.x.max(1),
+        height: size.y.max(1),
+        ..default()
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("gpu_picking_ids"),
+            size,
+            dimension: TextureDimension::D2,
+            // linear, so the bytes read back are the bytes of the ids
+            format: TextureFormat::Rgba8Unorm,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC
+                | TextureUsages::RENDER_ATTACHMENT,
+        },
+        ..default()
+    };
+    image.resize(size);
+    image
+}
+
+fn setup_id_camera(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut picking_state: ResMut<PickingPluginsState>,
+    windows: Res<Windows>,
+) {
+    // the regions under the cursor come from the id image instead of raycasts
+    picking_state.enable_picking = false;
+    picking_state.enable_interacting = false;
+
+    let size = windows.get_primary().map_or(UVec2::ONE, |window| {
+        UVec2::new(window.physical_width(), window.physical_height())
+    });
+    let image = images.add(id_image(size));
+
+    commands
+        .spawn(Camera3dBundle {
+            camera: Camera {
+                target: RenderTarget::Image(image.clone()),
+                priority: -1,
+                // a linear intermediate texture, an sRGB one would round the ids
+                hdr: true,
+                ..default()
+            },
+            camera_3d: Camera3d {
+                clear_color: ClearColorConfig::Custom(Color::BLACK),
+                ..default()
+            },
+            tonemapping: Tonemapping::Disabled,
+            ..default()
+        })
+        .insert(UiCameraConfig { show_ui: false })
+        .insert(RenderLayers::layer(PICKING_LAYER))
+        .insert(Name::new("Id Camera"))
+        .insert(IdCamera);
+    commands.insert_resource(PickingRequest { image, pixel: None });
+}
+
+/// The board camera, apart from the id camera following it
+type BoardCameraOnly = (With<BoardCamera>, Without<IdCamera>);
+
+/// Keeps the id camera on the board camera and the id image the size of the window, and tells
+/// the render world which pixel to read back
+fn follow_board_camera(
+    windows: Res<Windows>,
+    mut images: ResMut<Assets<Image>>,
+    mut request: ResMut<PickingRequest>,
+    board_camera_query: Query<(&Transform, &Projection), BoardCameraOnly>,
+    mut id_camera_query: Query<(&mut Transform, &mut Projection), With<IdCamera>>,
+) {
+    if let (Ok((transform, projection)), Ok((mut id_transform, mut id_projection))) = (
+        board_camera_query.get_single(),
+        id_camera_query.get_single_mut(),
+    ) {
+        *id_transform = *transform;
+        *id_projection = projection.clone();
+    }
+
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let size = UVec2::new(window.physical_width(), window.physical_height());
+    if let Some(image) = images.get_mut(&request.image) {
+        if image.size() != size.as_vec2() && size.x > 0 && size.y > 0 {
+            image.resize(Extent3d {
+                width: size.x,
+                height: size.y,
+                ..default()
+            });
+        }
+    }
+
+    let pixel = window
+        .cursor_position()
+        .and_then(|position| cursor_pixel(position, window.scale_factor(), size));
+    if request.pixel != pixel {
+        request.pixel = pixel;
+    }
+}
+
+/// Gives every new tile and die a copy in the color of its region on the [`PICKING_LAYER`]
+fn spawn_picking_ids(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    tile_query: Query<(Entity, &Region, &Handle<Mesh>), Added<Region>>,
+    die_query: Query<(Entity, &RegionDie, &Handle<Mesh>), Added<RegionDie>>,
+) {
+    let tiles = tile_query
+        .iter()
+        .map(|(entity, region, mesh)| (entity, region.id, mesh));
+    let dice = die_query
+        .iter()
+        .map(|(entity, die, mesh)| (entity, die.0, mesh));
+
+    for (entity, region, mesh) in tiles.chain(dice) {
+        let material = materials.add(StandardMaterial {
+            base_color: id_color(encode_region_id(region)),
+            unlit: true,
+            ..default()
+        });
+
+        commands.entity(entity).with_children(|parent| {
+            parent
+                .spawn(PbrBundle {
+                    mesh: mesh.clone(),
+                    material,
+                    ..default()
+                })
+                .insert(RenderLayers::layer(PICKING_LAYER))
+                .insert(PickingId);
+        });
+    }
+}
+
+/// Hovers and selects the pickable tile of the region under the cursor, the way the raycasts of
+/// bevy_mod_picking do
+fn pick_regions(
+    picked: Res<PickedPixel>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut tile_query: Query<(Entity, &Region, &mut Interaction)>,
+    mut picking_event_writer: EventWriter<PickingEvent>,
+) {
+    let region = picked.pixel.lock().unwrap().and_then(decode_region_id);
+
+    for (entity, tile, mut interaction) in tile_query.iter_mut() {
+        let new_interaction = match region == Some(tile.id) {
+            true if mouse_buttons.pressed(MouseButton::Left) => Interaction::Clicked,
+            true => Interaction::Hovered,
+            false => Interaction::None,
+        };
+        if *interaction != new_interaction {
+            *interaction = new_interaction;
+        }
+
+        if region == Some(tile.id) && mouse_buttons.just_pressed(MouseButton::Left) {
+            picking_event_writer.send(PickingEvent::Selection(SelectionEvent::JustSelected(
+                entity,
+            )));
+        }
+    }
+}
+
+/// Copies the pixel under the cursor into the [`ReadbackBuffer`] once the cameras rendered, unless
+/// the last pixel is still being read back
+struct ReadbackNode;
+
+impl Node for ReadbackNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let (request, readback) = match (
+            world.get_resource::<PickingRequest>(),
+            world.get_resource::<ReadbackBuffer>(),
+        ) {
+            (Some(request), Some(readback)) => (request, readback),
+            _ => return Ok(()),
+        };
+        let image = world.resource::<RenderAssets<Image>>().get(&request.image);
+        let (pixel, image) = match (request.pixel, image) {
+            (Some(pixel), Some(image)) if pixel.as_vec2().cmplt(image.size).all() => (pixel, image),
+            _ => return Ok(()),
+        };
+        let mut state = readback.state.lock().unwrap();
+        if !matches!(*state, ReadbackState::Idle) {
+            return Ok(());
+        }
+
+        render_context.command_encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                texture: &image.texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: pixel.x,
+                    y: pixel.y,
+                    z: 0,
+                },
+                aspect: TextureAspect::All,
+            },
+            ImageCopyBuffer {
+                buffer: &readback.buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        *state = ReadbackState::Copied;
+
+        Ok(())
+    }
+}
+
+/// Starts mapping the pixel copied this frame, and reads it once the GPU is done with it without
+/// waiting for it. Forgets the last pixel when the cursor left the window.
+fn read_back_pixel(
+    render_device: Res<RenderDevice>,
+    readback: Res<ReadbackBuffer>,
+    picked: Res<PickedPixel>,
+) {
+    let mut state = readback.state.lock().unwrap();
+    let slice = readback.buffer.slice(..4);
+    if matches!(*state, ReadbackState::Idle) {
+        *picked.pixel.lock().unwrap() = None;
+        return;
+    }
+    if matches!(*state, ReadbackState::Copied) {
+        let (sender, receiver) = mpsc::channel();
+        render_device.map_buffer(&slice, MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        *state = ReadbackState::Mapping(receiver);
+    }
+
+    render_device.poll(Maintain::Poll);
+    let mapped = match &*state {
+        ReadbackState::Mapping(receiver) => receiver.try_recv(),
+        _ => return,
+    };
+    match mapped {
+        Err(TryRecvError::Empty) => {}
+        Ok(Ok(())) => {
+            let bytes = slice.get_mapped_range();
+            let pixel = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            drop(bytes);
+            readback.buffer.unmap();
+            *picked.pixel.lock().unwrap() = Some(pixel);
+            *state = ReadbackState::Idle;
+        }
+        Ok(Err(_)) | Err(TryRecvError::Disconnected) => *state = ReadbackState::Idle,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_ids_round_trip_through_colors() {
+        for id in [0, 1, 255, 256, 70_000] {
+            let [r, g, b] = encode_region_id(id);
+            assert_eq!(decode_region_id([r, g, b, 255]), Some(id));
+        }
+        assert_eq!(encode_region_id(0), [0, 0, 1]);
+        assert_eq!(decode_region_id([0, 0, 0, 255]), None);
+    }
+
+    #[test]
+    fn id_colors_keep_their_bytes() {
+        for bytes in [[0, 0, 1], [1, 2, 3], [128, 255, 17]] {
+            let [r, g, b, _] = id_color(bytes).as_linear_rgba_f32();
+            let round_trip = [r, g, b].map(|channel| (channel * 255.0).round() as u8);
+            assert_eq!(round_trip, bytes);
+        }
+    }
+
+    #[test]
+    fn cursors_map_to_pixels_from_the_top() {
+        let size = UVec2::new(200, 100);
+
+        assert_eq!(
+            cursor_pixel(Vec2::new(0.0, 0.0), 1.0, size),
+            Some(UVec2::new(0, 99))
+        );
+        assert_eq!(
+            cursor_pixel(Vec2::new(99.9, 49.9), 2.0, size),
+            Some(UVec2::new(199, 0))
+        );
+        assert_eq!(cursor_pixel(Vec2::new(100.0, 10.0), 2.0, size), None);
+        assert_eq!(cursor_pixel(Vec2::new(-1.0, 10.0), 1.0, size), None);
+    }
+}
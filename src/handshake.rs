@@ -0,0 +1,181 @@
+//! Compatibility handshake of games playing together over the network, and of games loading the
+//! saves and replays of another game.
+//!
+//! Releases only play together when they speak the same [`PROTOCOL_VERSION`]: the version of the
+//! API messages and of the game logic, bumped whenever a change would make the same moves end
+//! in another position. Games and files also carry the hash of their rules (see
+//! [`rules_hash`]), so moves are never replayed under other rules than the ones they were played
+//! with. Both are checked before the first move, and a mismatch is refused with a clear
+//! "incompatible version" message instead of the games drifting apart mid-game.
+//!
+//! Saves and replays carry the handshake in their [`ExportStamp`]. Stamps written before the
+//! handshake existed have no protocol and are protocol 1, the first one.
+//!
+//! [`ExportStamp`]: crate::stamp::ExportStamp
+
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+use crate::rules::GameRules;
+use crate::stamp::{rules_hash, APP_VERSION};
+
+/// Version of the API messages and of the game logic
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// What a game or a file tells about the games it can be played with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Handshake {
+    /// Release of the game, shown to players
+    pub version: String,
+    pub protocol: u32,
+    /// Hash of the rules, none when they aren't known yet
+    pub rules_hash: Option<u64>,
+}
+
+/// Why two games can't be played together
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Incompatible {
+    /// The other side speaks another protocol, carries its release and protocol
+    Version { version: String, protocol: u32 },
+    /// The other side plays other rules
+    Rules { expected: u64, found: u64 },
+}
+
+impl fmt::Display for Incompatible {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Incompatible::Version { version, protocol } => write!(
+                f,
+                "incompatible version: {} (protocol {}) can't play with {} (protocol {})",
+                version, protocol, APP_VERSION, PROTOCOL_VERSION
+            ),
+            Incompatible::Rules { expected, found } => write!(
+                f,
+                "incompatible rules: played with rules {:016x}, these rules are {:016x}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl Handshake {
+    /// Handshake of this game, playing `rules` if known
+    pub fn local(rules: Option<&GameRules>) -> Self {
+        Handshake {
+            version: APP_VERSION.to_string(),
+            protocol: PROTOCOL_VERSION,
+            rules_hash: rules.map(rules_hash),
+        }
+    }
+
+    /// Handshake in the stamp of a file, on the first line starting with `stackrankdice` once
+    /// its comment marker is stripped. None for files without a stamp.
+    pub fn from_stamp(source: &str) -> Option<Self> {
+        let stamp = source.lines().find_map(|line| {
+            line.trim_start_matches(['#', '/'])
+                .trim()
+                .strip_prefix("stackrankdice ")
+        })?;
+        let tokens: Vec<&str> = stamp.split_whitespace().collect();
+        let value_of = |key: &str| {
+            tokens
+                .iter()
+                .position(|token| *token == key)
+                .and_then(|index| tokens.get(index + 1))
+        };
+
+        Some(Handshake {
+            version: tokens.first()?.to_string(),
+            protocol: match value_of("protocol") {
+                Some(protocol) => protocol.parse().ok()?,
+                None => 1,
+            },
+            rules_hash: match value_of("rules") {
+                Some(hash) => Some(u64::from_str_radix(hash, 16).ok()?),
+                None => None,
+            },
+        })
+    }
+
+    /// Checks that the game or file of the handshake `other` can be played with this one. Rules
+    /// are only compared when both sides know them.
+    pub fn check(&self, other: &Handshake) -> Result<(), Incompatible> {
+        if other.protocol != self.protocol {
+            return Err(Incompatible::Version {
+                version: other.version.clone(),
+                protocol: other.protocol,
+            });
+        }
+
+        match (self.rules_hash, other.rules_hash) {
+            (Some(expected), Some(found)) if expected != found => {
+                Err(Incompatible::Rules { expected, found })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Writes a rules hash the way stamps do, as 16 hex digits
+pub fn serialize_rules_hash<S: Serializer>(hash: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{:016x}", hash))
+}
+
+/// Reads an optional rules hash written by [`serialize_rules_hash`]
+pub fn deserialize_rules_hash<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|hash| u64::from_str_radix(&hash, 16).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stamp::ExportStamp;
+    use crate::tiered_prng::PrngResource;
+
+    #[test]
+    fn stamps_carry_the_handshake() {
+        let rules = GameRules::default();
+        let prng = PrngResource {
+            world_seed: 12,
+            env_seed: 34,
+        };
+        let source = format!("# {}\nversion 2\n", ExportStamp::new(&prng, &rules));
+        let local = Handshake::local(Some(&rules));
+
+        assert_eq!(Handshake::from_stamp(&source), Some(local.clone()));
+        assert_eq!(Handshake::from_stamp("version 2\nseed 1 2\n"), None);
+
+        // stamps from before the handshake are protocol 1
+        let old = Handshake::from_stamp("// stackrankdice 0.0.1 seed 1 2 rules 00000000000000ff")
+            .unwrap();
+        assert_eq!(old.protocol, 1);
+        assert_eq!(
+            local.check(&old),
+            Err(Incompatible::Rules {
+                expected: rules_hash(&rules),
+                found: 0xff
+            })
+        );
+        assert_eq!(Handshake::local(None).check(&old), Ok(()));
+    }
+
+    #[test]
+    fn other_protocols_are_incompatible() {
+        let future = Handshake {
+            version: "9.0.0".to_string(),
+            protocol: PROTOCOL_VERSION + 1,
+            rules_hash: None,
+        };
+        let err = Handshake::local(None).check(&future).unwrap_err();
+
+        assert!(err.to_string().starts_with(&format!(
+            "incompatible version: 9.0.0 (protocol {}) can't play with ",
+            PROTOCOL_VERSION + 1
+        )));
+    }
+}
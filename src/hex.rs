@@ -1,5 +1,7 @@
 // This code taken from https://github.com/Quantumplation/bevy-hex-example
 
+use std::collections::{HashMap, HashSet, VecDeque};
+
 /// A coordinate on a hex grid, representing distances along the various directions of travel
 /// Invariant: In order to represent a valid hex coordinate, q + r + s must equal 0
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
@@ -117,6 +119,102 @@ impl HexCoord {
             iter: DIRECTIONS.iter(),
         }
     }
+
+    /// The coordinate turned a sixth of a turn clockwise around the origin
+    pub fn rotate_clockwise(&self) -> Self {
+        HexCoord {
+            q: -self.r,
+            r: -self.s,
+            s: -self.q,
+        }
+    }
+
+    /// The coordinate mirrored left to right across the column of the origin
+    pub fn mirror_horizontally(&self) -> Self {
+        HexCoord {
+            q: -self.q,
+            r: -self.s,
+            s: -self.r,
+        }
+    }
+
+    /// The coordinate mirrored top to bottom, north and south swap
+    #[allow(dead_code)]
+    pub fn mirror_vertically(&self) -> Self {
+        HexCoord {
+            q: self.q,
+            r: self.s,
+            s: self.r,
+        }
+    }
+
+    /// Number of steps between two coordinates
+    #[allow(dead_code)]
+    pub fn distance(&self, other: &HexCoord) -> isize {
+        ((self.q - other.q).abs() + (self.r - other.r).abs() + (self.s - other.s).abs()) / 2
+    }
+}
+
+/// Shortest walk from `from` to `to` over neighbouring hexes, both included, only stepping on
+/// hexes accepted by `passable`. `passable` must only accept a finite set of hexes, e.g. those of
+/// a board, otherwise the search never ends when `to` can't be reached.
+#[allow(dead_code)]
+pub fn hex_path(
+    from: &HexCoord,
+    to: &HexCoord,
+    passable: impl Fn(&HexCoord) -> bool,
+) -> Option<Vec<HexCoord>> {
+    if !passable(from) || !passable(to) {
+        return None;
+    }
+
+    let mut previous: HashMap<HexCoord, HexCoord> = HashMap::new();
+    let mut queue = VecDeque::from([from.clone()]);
+    previous.insert(from.clone(), from.clone());
+
+    while let Some(current) = queue.pop_front() {
+        if current == *to {
+            let mut path = vec![current];
+            while path.last() != Some(from) {
+                let step = previous[path.last().unwrap()].clone();
+                path.push(step);
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for neighbor in current.neighbors() {
+            if !previous.contains_key(&neighbor) && passable(&neighbor) {
+                previous.insert(neighbor.clone(), current.clone());
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    None
+}
+
+/// Whether every hex of `hexes` can be reached from any other one through neighbouring hexes of
+/// `hexes`. An empty set counts as contiguous.
+pub fn is_contiguous(hexes: &[(isize, isize)]) -> bool {
+    let first = match hexes.first() {
+        Some(first) => *first,
+        None => return true,
+    };
+
+    let members: HashSet<(isize, isize)> = hexes.iter().copied().collect();
+    let mut reached = HashSet::from([first]);
+    let mut queue = VecDeque::from([first]);
+    while let Some((q, r)) = queue.pop_front() {
+        for neighbor in HexCoord::new(q, r).neighbors() {
+            let coord = (neighbor.q, neighbor.r);
+            if members.contains(&coord) && reached.insert(coord) {
+                queue.push_back(coord);
+            }
+        }
+    }
+
+    reached.len() == members.len()
 }
 
 #[cfg(test)]
@@ -134,6 +232,56 @@ mod tests {
         }
     }
 
+    #[test]
+    fn path_avoids_blocked_hexes() {
+        let from = HexCoord::new(0, 0);
+        let to = HexCoord::new(2, 0);
+        let in_bounds = |c: &HexCoord| c.distance(&HexCoord::origin()) <= 2;
+
+        let path = hex_path(&from, &to, in_bounds).unwrap();
+        assert_eq!(path.len() as isize, from.distance(&to) + 1);
+
+        let blocked = HexCoord::new(1, 0);
+        let path = hex_path(&from, &to, |c| in_bounds(c) && *c != blocked).unwrap();
+        assert_eq!(path.len(), 4);
+        assert!(!path.contains(&blocked));
+    }
+
+    #[test]
+    fn contiguous_hexes() {
+        assert!(is_contiguous(&[]));
+        assert!(is_contiguous(&[(0, 0), (1, 0), (1, 1)]));
+        assert!(!is_contiguous(&[(0, 0), (2, 0)]));
+    }
+
+    #[test]
+    fn transforms_keep_neighbors() {
+        let point = HexCoord::new(3, -5);
+        let transforms: [fn(&HexCoord) -> HexCoord; 3] = [
+            HexCoord::rotate_clockwise,
+            HexCoord::mirror_horizontally,
+            HexCoord::mirror_vertically,
+        ];
+        for transform in transforms {
+            let moved = transform(&point);
+            assert_eq!(moved.q + moved.r + moved.s, 0);
+            for neighbor in point.neighbors() {
+                assert_eq!(transform(&neighbor).distance(&moved), 1);
+            }
+        }
+
+        assert_eq!(
+            HexCoord::origin().north().rotate_clockwise(),
+            HexCoord::origin().northeast()
+        );
+        assert_eq!(point.mirror_horizontally().mirror_horizontally(), point);
+        assert_eq!(point.mirror_vertically().mirror_vertically(), point);
+        assert_eq!(
+            (0..6).fold(point.clone(), |point, _| point.rotate_clockwise()),
+            point
+        );
+    }
+
     #[test]
     fn enumerate_neighbors() {
         let mut rand = rand::thread_rng();
@@ -0,0 +1,149 @@
+//! Debug overlay for map authors: the coordinates of every hex, the id of every region and an
+//! edge between every pair of neighbouring regions, drawn over the board.
+
+use bevy::prelude::*;
+
+use crate::game::{GameState, Region};
+use crate::geometry::center;
+use crate::hex::HexCoord;
+use crate::ui::BoardLabel;
+
+/// Key that toggles the hex debug overlay
+const OVERLAY_KEY: KeyCode = KeyCode::F8;
+
+/// Height the overlay is drawn at, just above the region tiles
+const OVERLAY_HEIGHT: f32 = 1.2;
+
+#[derive(Resource, Default)]
+pub struct HexDebugOverlay {
+    pub enabled: bool,
+}
+
+/// Anything spawned by the overlay
+#[derive(Component)]
+pub(crate) struct HexDebugElement;
+
+fn board_position(hex: &HexCoord) -> Vec3 {
+    let [x, _, z] = center(1.0, hex, &[0.0; 3]);
+    Vec3::new(x, OVERLAY_HEIGHT, z)
+}
+
+fn spawn_label(
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    text: String,
+    font_size: f32,
+    color: Color,
+    world_position: Vec3,
+) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                text,
+                TextStyle {
+                    font: font.clone(),
+                    font_size,
+                    color,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                ..default()
+            }),
+        )
+        .insert(Name::new("Hex Debug Label"))
+        .insert(BoardLabel { world_position })
+        .insert(HexDebugElement);
+}
+
+fn spawn_overlay(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    game_state: &GameState,
+) {
+    let board = &game_state.board;
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    for region in board.regions.iter() {
+        for hex in region.hexes.iter() {
+            spawn_label(
+                commands,
+                &font,
+                format!("{},{}", hex.0, hex.1),
+                12.0,
+                Color::WHITE,
+                board_position(&HexCoord::new(hex.0, hex.1)),
+            );
+        }
+
+        spawn_label(
+            commands,
+            &font,
+            format!("#{}", region.id),
+            24.0,
+            Color::YELLOW,
+            board_position(&region.center_hex()),
+        );
+    }
+
+    let edge_material = materials.add(StandardMaterial {
+        base_color: Color::YELLOW,
+        unlit: true,
+        ..default()
+    });
+    for (region_1, region_2) in board.adjacencies() {
+        let from = board_position(&board.regions[region_1].center_hex());
+        let to = board_position(&board.regions[region_2].center_hex());
+        let length = from.distance(to);
+
+        commands
+            .spawn(PbrBundle {
+                mesh: meshes.add(Mesh::from(shape::Box::new(length, 0.05, 0.05))),
+                material: edge_material.clone(),
+                transform: Transform::from_translation((from + to) / 2.0)
+                    .with_rotation(Quat::from_rotation_arc(Vec3::X, (to - from) / length)),
+                ..default()
+            })
+            .insert(Name::new("Hex Debug Edge"))
+            .insert(HexDebugElement);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn hex_debug_overlay(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut overlay: ResMut<HexDebugOverlay>,
+    game_state: Res<GameState>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    elements: Query<Entity, With<HexDebugElement>>,
+    new_hexes: Query<(), Added<Region>>,
+) {
+    let toggled = keys.just_pressed(OVERLAY_KEY);
+    if toggled {
+        overlay.enabled = !overlay.enabled;
+    }
+
+    // rebuild the overlay whenever the board is redrawn
+    if !toggled && new_hexes.is_empty() {
+        return;
+    }
+
+    for entity in elements.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    if overlay.enabled {
+        spawn_overlay(
+            &mut commands,
+            &asset_server,
+            &mut meshes,
+            &mut materials,
+            &game_state,
+        );
+    }
+}
@@ -4,7 +4,259 @@ use bevy_mod_picking::{
     PausedForBlockers, PickingPlugin, PickingPluginsState, PickingSystem, Selection,
 };
 
-use crate::game::{GameState, Region, SelectedRegion};
+use bevy_mod_outline::Outline;
+
+use crate::game::{GameState, Region, RegionId, SelectedRegion};
+use crate::idle::IdleTracker;
+use crate::profiles::{OutlineStyle, PlayerProfiles};
+use crate::rules::GameRules;
+use crate::seats::BotIntent;
+use crate::turn_banner::TurnStartCue;
+
+/// Highlights a region can get, from the most to the least important
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HighlightKind {
+    Selected,
+    Hovered,
+    /// A region of the attack a bot chose, before its dice roll
+    BotChoice,
+    /// A region the selected region can attack
+    LegalTarget,
+    /// A region a deliberating bot considers attacking from
+    BotCandidate,
+    /// One of the two regions of the last battle
+    LastBattle,
+    /// A region of the player whose turn just started
+    TurnStart,
+    /// A region the idle player in turn could play
+    Playable,
+}
+
+/// Outline drawn around a region
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HighlightStyle {
+    pub colour: Color,
+    pub width: f32,
+}
+
+/// Outline of every kind of highlight, and of regions without any
+#[derive(Resource, Clone, Debug)]
+pub struct HighlightStyles {
+    pub selected: HighlightStyle,
+    pub hovered: HighlightStyle,
+    pub bot_choice: HighlightStyle,
+    pub legal_target: HighlightStyle,
+    pub bot_candidate: HighlightStyle,
+    pub last_battle: HighlightStyle,
+    pub turn_start: HighlightStyle,
+    pub playable: HighlightStyle,
+    pub none: HighlightStyle,
+    /// Outline regions without highlight in the style their owner picked, instead of `none`
+    pub owner_outlines: bool,
+}
+
+impl Default for HighlightStyles {
+    fn default() -> Self {
+        HighlightStyles {
+            selected: HighlightStyle {
+                colour: Color::WHITE,
+                width: 4.0,
+            },
+            hovered: HighlightStyle {
+                colour: Color::rgb(0.85, 0.0, 0.85),
+                width: 3.0,
+            },
+            bot_choice: HighlightStyle {
+                colour: Color::rgb(0.95, 0.2, 0.1),
+                width: 4.0,
+            },
+            legal_target: HighlightStyle {
+                colour: Color::rgb(0.95, 0.2, 0.1),
+                width: 2.5,
+            },
+            bot_candidate: HighlightStyle {
+                colour: Color::CYAN,
+                width: 2.5,
+            },
+            last_battle: HighlightStyle {
+                colour: Color::GOLD,
+                width: 2.0,
+            },
+            turn_start: HighlightStyle {
+                colour: Color::WHITE,
+                width: 3.0,
+            },
+            playable: HighlightStyle {
+                colour: Color::ORANGE,
+                width: 3.0,
+            },
+            none: HighlightStyle {
+                colour: Color::BLACK,
+                width: 0.5,
+            },
+            owner_outlines: true,
+        }
+    }
+}
+
+impl HighlightStyles {
+    pub fn style(&self, kind: Option<HighlightKind>) -> HighlightStyle {
+        match kind {
+            Some(HighlightKind::Selected) => self.selected,
+            Some(HighlightKind::Hovered) => self.hovered,
+            Some(HighlightKind::BotChoice) => self.bot_choice,
+            Some(HighlightKind::LegalTarget) => self.legal_target,
+            Some(HighlightKind::BotCandidate) => self.bot_candidate,
+            Some(HighlightKind::LastBattle) => self.last_battle,
+            Some(HighlightKind::TurnStart) => self.turn_start,
+            Some(HighlightKind::Playable) => self.playable,
+            None => self.none,
+        }
+    }
+
+    /// Outline of a region without highlight, in the `outline` style its owner picked
+    pub fn owner_style(&self, outline: OutlineStyle, owner_color: Color) -> HighlightStyle {
+        match outline {
+            _ if !self.owner_outlines => self.none,
+            OutlineStyle::Thin => self.none,
+            OutlineStyle::Bold => HighlightStyle {
+                width: self.none.width * 4.0,
+                ..self.none
+            },
+            OutlineStyle::Glow => HighlightStyle {
+                colour: owner_color,
+                width: self.none.width * 3.0,
+            },
+        }
+    }
+}
+
+/// Regions highlighted on the board, kept up to date from the selection, the cursor, the
+/// game log, the intent of the bot in turn, the turn start cue and the idle reminder
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HighlightState {
+    pub selected: Option<RegionId>,
+    pub hovered: Option<RegionId>,
+    pub bot_choice: Vec<RegionId>,
+    pub legal_targets: Vec<RegionId>,
+    pub bot_candidates: Vec<RegionId>,
+    pub last_battle: Vec<RegionId>,
+    pub turn_start: Vec<RegionId>,
+    pub playable: Vec<RegionId>,
+}
+
+impl HighlightState {
+    pub fn new(
+        selected_region: &SelectedRegion,
+        hovered: Option<RegionId>,
+        game_state: &GameState,
+        rules: &GameRules,
+    ) -> Self {
+        let selected = selected_region.region();
+        let legal_targets = match selected_region {
+            SelectedRegion::FriendlySelected { region, .. } => game_state
+                .attacks(rules)
+                .filter(|(attacker, _)| attacker.id == *region)
+                .map(|(_, defender)| defender.id)
+                .collect(),
+            _ => Vec::new(),
+        };
+        let last_battle = game_state
+            .game_log
+            .last()
+            .map_or(Vec::new(), |entry| vec![entry.region_1, entry.region_2]);
+
+        HighlightState {
+            selected,
+            hovered,
+            bot_choice: Vec::new(),
+            legal_targets,
+            bot_candidates: Vec::new(),
+            last_battle,
+            turn_start: Vec::new(),
+            playable: Vec::new(),
+        }
+    }
+
+    /// Most important highlight of `region`, if it has any
+    pub fn kind(&self, region: RegionId) -> Option<HighlightKind> {
+        if self.selected == Some(region) {
+            Some(HighlightKind::Selected)
+        } else if self.hovered == Some(region) {
+            Some(HighlightKind::Hovered)
+        } else if self.bot_choice.contains(&region) {
+            Some(HighlightKind::BotChoice)
+        } else if self.legal_targets.contains(&region) {
+            Some(HighlightKind::LegalTarget)
+        } else if self.bot_candidates.contains(&region) {
+            Some(HighlightKind::BotCandidate)
+        } else if self.last_battle.contains(&region) {
+            Some(HighlightKind::LastBattle)
+        } else if self.turn_start.contains(&region) {
+            Some(HighlightKind::TurnStart)
+        } else if self.playable.contains(&region) {
+            Some(HighlightKind::Playable)
+        } else {
+            None
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_highlight_state(
+    mut highlight_state: ResMut<HighlightState>,
+    selected_region: Res<SelectedRegion>,
+    game_state: Res<GameState>,
+    turn_start_cue: Res<TurnStartCue>,
+    idle_tracker: Res<IdleTracker>,
+    rules: Res<GameRules>,
+    bot_intent: Res<BotIntent>,
+    hovered_query: Query<(&Interaction, &Region)>,
+) {
+    let hovered = hovered_query
+        .iter()
+        .find(|(interaction, _)| **interaction != Interaction::None)
+        .map(|(_, region)| region.id);
+
+    let mut state = HighlightState::new(&selected_region, hovered, &game_state, &rules);
+    state.bot_choice = bot_intent
+        .chosen
+        .map_or(Vec::new(), |(attacker, defender)| vec![attacker, defender]);
+    state.bot_candidates = bot_intent.candidates.clone();
+    state.turn_start = turn_start_cue.flashing_regions(&game_state);
+    state.playable = idle_tracker.pulsing_regions(&game_state, &rules);
+    if *highlight_state != state {
+        *highlight_state = state;
+    }
+}
+
+/// Outlines every region with the style of its most important highlight, or the outline style of
+/// its owner
+pub(crate) fn highlight_outlines(
+    highlight_state: Res<HighlightState>,
+    styles: Res<HighlightStyles>,
+    game_state: Res<GameState>,
+    profiles: Res<PlayerProfiles>,
+    mut outline_query: Query<(&Region, &mut Outline)>,
+) {
+    for (region, mut outline) in outline_query.iter_mut() {
+        let style = match highlight_state.kind(region.id) {
+            None => {
+                let owner = game_state
+                    .board
+                    .regions
+                    .get(region.id)
+                    .map_or(region.owner, |region| region.owner);
+                styles.owner_style(profiles.outline(owner), profiles.color(owner))
+            }
+            kind => styles.style(kind),
+        };
+        if outline.colour != style.colour || outline.width != style.width {
+            outline.colour = style.colour;
+            outline.width = style.width;
+        }
+    }
+}
 
 // This code is based on bevy_mod_picking. Standard use-case for bevy_mod_picking is limited
 // and doesn't allow to customize colors of objects highlighted based on their metadata.
@@ -147,6 +399,22 @@ where
     }
 }
 
+/// Keeps the picking selection of region entities in line with [`SelectedRegion`], so their
+/// highlight is refreshed whenever the selection moves on
+pub(crate) fn sync_picking_selection(
+    selected_region: Res<SelectedRegion>,
+    mut selection_query: Query<(Entity, &mut Selection), With<Region>>,
+) {
+    let selected = [selected_region.entity(), selected_region.defender_entity()];
+
+    for (entity, mut selection) in selection_query.iter_mut() {
+        let is_selected = selected.contains(&Some(entity));
+        if selection.selected() != is_selected {
+            selection.set_selected(is_selected);
+        }
+    }
+}
+
 #[allow(clippy::type_complexity)]
 pub fn mesh_highlighting<T: 'static + StackRankDiceHighlightable + Send + Sync>(
     paused: Option<Res<PausedForBlockers>>,
@@ -184,12 +452,14 @@ pub fn mesh_highlighting<T: 'static + StackRankDiceHighlightable + Send + Sync>(
         }
     }
 
+    let selected_entity = selected_region.entity();
+
     for (entity, interaction, mut material, selection, highlight) in interaction_query.iter_mut() {
         let region = regions.get(entity);
 
         *material = match *interaction {
             Interaction::Clicked => {
-                if selected_region.entity.is_some() && selected_region.entity.unwrap() == entity {
+                if selected_entity == Some(entity) {
                     &global_default_highlight.pressed
                 } else if region.is_ok() && region.unwrap().1.owner != game_state.turn_of_player {
                     &highlight.initial
@@ -200,15 +470,15 @@ pub fn mesh_highlighting<T: 'static + StackRankDiceHighlightable + Send + Sync>(
                 }
             }
             Interaction::Hovered => {
-                if selected_region.entity.is_some() && selected_region.entity.unwrap() == entity {
+                if selected_entity == Some(entity) {
                     &global_default_highlight.selected
-                } else if selected_region.entity.is_some()
-                    && region.is_ok()
-                    && region
-                        .unwrap()
-                        .1
-                        .is_opponent(selected_region.region.as_ref().unwrap())
-                {
+                } else if selected_region.region().map_or(false, |selected| {
+                    region.is_ok()
+                        && region
+                            .unwrap()
+                            .1
+                            .is_opponent(&game_state.board.regions[selected])
+                }) {
                     &global_default_highlight.opponent
                 } else if region.is_ok() && region.unwrap().1.owner != game_state.turn_of_player {
                     &highlight.initial
@@ -220,9 +490,10 @@ pub fn mesh_highlighting<T: 'static + StackRankDiceHighlightable + Send + Sync>(
             }
             Interaction::None => {
                 if selection.filter(|s| s.selected()).is_some() {
-                    if selected_region.entity.is_some() && selected_region.entity.unwrap() == entity
-                    {
+                    if selected_entity == Some(entity) {
                         &global_default_highlight.selected
+                    } else if selected_region.defender_entity() == Some(entity) {
+                        &global_default_highlight.opponent
                     } else if let Some(highlight_asset) = &highlight.selected {
                         highlight_asset
                     } else {
@@ -236,3 +507,70 @@ pub fn mesh_highlighting<T: 'static + StackRankDiceHighlightable + Send + Sync>(
         .to_owned();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+
+    #[test]
+    fn every_highlight_shows_at_once() {
+        let map = MapFile::parse(
+            "players 2\nregion 0 3 0,0\nregion 1 2 1,0\nregion 1 2 0,1\nregion 0 2 5,5\nregion 1 1 6,5\n",
+        )
+        .unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        game_state.log_attack(crate::game::GameLogEntry {
+            region_1: 3,
+            region_2: 4,
+            ..Default::default()
+        });
+        let selected_region = SelectedRegion::FriendlySelected {
+            entity: Entity::from_raw(0),
+            region: 0,
+        };
+
+        let state = HighlightState::new(
+            &selected_region,
+            Some(1),
+            &game_state,
+            &GameRules::default(),
+        );
+        assert_eq!(state.kind(0), Some(HighlightKind::Selected));
+        assert_eq!(state.kind(1), Some(HighlightKind::Hovered));
+        assert_eq!(state.kind(2), Some(HighlightKind::LegalTarget));
+        assert_eq!(state.kind(3), Some(HighlightKind::LastBattle));
+        assert_eq!(state.kind(4), Some(HighlightKind::LastBattle));
+    }
+
+    #[test]
+    fn bot_candidates_show_over_the_last_battle() {
+        let map =
+            MapFile::parse("players 2\nregion 0 3 0,0\nregion 1 2 1,0\nregion 1 1 5,5\n").unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        game_state.log_attack(crate::game::GameLogEntry {
+            region_1: 0,
+            region_2: 1,
+            ..Default::default()
+        });
+
+        let mut state = HighlightState::new(
+            &SelectedRegion::default(),
+            None,
+            &game_state,
+            &GameRules::default(),
+        );
+        state.bot_candidates = vec![0, 2];
+        assert_eq!(state.kind(0), Some(HighlightKind::BotCandidate));
+        assert_eq!(state.kind(1), Some(HighlightKind::LastBattle));
+        assert_eq!(state.kind(2), Some(HighlightKind::BotCandidate));
+    }
+}
@@ -0,0 +1,266 @@
+//! Hot reload for map authors: the board is rebuilt in place whenever the map file played with
+//! `--map` changes, and the textures and models of the board whenever their asset files do.
+//!
+//! Built with the `hot-reload` feature. The map file is checked twice a second, every change
+//! restarts the game on the new board with the same rules and seats, and a fresh autosave. A map
+//! that doesn't parse, or asks for another number of players than the game was started with, is
+//! reported in a toast and the board stays as it is until the next change.
+//!
+//! The theme of the board, the textures and models in `assets`, is watched by the asset server.
+//! Models are swapped in place, but a material is only prepared again for rendering when it
+//! changes itself: [`refresh_theme_materials`] rebuilds the materials using a reloaded texture.
+
+use std::fmt;
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+use bevy::asset::HandleId;
+use bevy::prelude::*;
+
+use crate::autosave::{Autosave, AUTOSAVE_PATH};
+use crate::board::BoardDrawer;
+use crate::game::{first_player, GameState};
+use crate::map_file::{MapError, MapFile};
+use crate::new_game::NewGame;
+use crate::profiles::PlayerProfiles;
+use crate::rematch::Rematch;
+use crate::rules::GameRules;
+use crate::tiered_prng::PrngResource;
+use crate::ui::spawn_toast;
+
+/// Time between two checks of the map file
+const MAP_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+pub enum ReloadError {
+    Invalid(MapError),
+    /// The map is for `map` players, the game has `game` seats
+    Players {
+        map: usize,
+        game: usize,
+    },
+}
+
+impl fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReloadError::Invalid(err) => write!(f, "{}", err),
+            ReloadError::Players { map, game } => write!(
+                f,
+                "the map is for {} players, restart the game to play it instead of {}",
+                map, game
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReloadError {}
+
+/// Fresh game on the map of `source`, keeping `rules` and the number of players
+pub fn reload_map(
+    source: &str,
+    rules: &GameRules,
+    world_seed: u64,
+) -> Result<GameState, ReloadError> {
+    let map = MapFile::parse(source).map_err(ReloadError::Invalid)?;
+    if map.number_of_players != rules.number_of_players {
+        return Err(ReloadError::Players {
+            map: map.number_of_players,
+            game: rules.number_of_players,
+        });
+    }
+
+    let mut game_state = GameState {
+        board: map.board,
+        number_of_players: map.number_of_players,
+        ..default()
+    };
+    game_state.seat_players(first_player(rules, world_seed), rules);
+    Ok(game_state)
+}
+
+/// Map file played, watched for changes
+#[derive(Resource)]
+pub struct MapWatch {
+    pub path: String,
+    /// Last modification of the file seen
+    modified: Option<SystemTime>,
+    timer: Timer,
+}
+
+impl MapWatch {
+    pub fn new(path: String) -> Self {
+        let modified = modified(&path);
+        MapWatch {
+            path,
+            modified,
+            timer: Timer::new(MAP_CHECK_INTERVAL, TimerMode::Repeating),
+        }
+    }
+
+    /// Whether the file was modified since it was last seen at `modified`
+    pub fn changed(&mut self, modified: Option<SystemTime>) -> bool {
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Restarts the game on the map file once it changes
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn reload_changed_map(
+    mut commands: Commands,
+    time: Res<Time>,
+    watch: Option<ResMut<MapWatch>>,
+    rules: Res<GameRules>,
+    prng: Res<PrngResource>,
+    mut game_state: ResMut<GameState>,
+    mut rematch: ResMut<Rematch>,
+    mut autosave: ResMut<Autosave>,
+    asset_server: Res<AssetServer>,
+    profiles: Res<PlayerProfiles>,
+    mut board_drawer: BoardDrawer,
+    mut new_game: NewGame,
+) {
+    let mut watch = match watch {
+        Some(watch) => watch,
+        None => return,
+    };
+    if !watch.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let latest = modified(&watch.path);
+    if !watch.changed(latest) {
+        return;
+    }
+
+    // an editor may still be writing the file, the next change reloads it again
+    let reloaded = fs::read_to_string(&watch.path)
+        .map_err(|err| err.to_string())
+        .and_then(|source| {
+            reload_map(&source, &rules, prng.world_seed).map_err(|err| err.to_string())
+        });
+    let reloaded = match reloaded {
+        Ok(reloaded) => reloaded,
+        Err(err) => {
+            warn!("Failed to reload the map {}: {}", watch.path, err);
+            spawn_toast(
+                &mut commands,
+                &asset_server,
+                format!("Can't reload the map: {}", err),
+            );
+            return;
+        }
+    };
+
+    info!("Reloaded the map {}", watch.path);
+    *game_state = reloaded;
+    rematch.new_series(&game_state);
+    new_game.reset();
+    if let Err(err) = autosave.restart() {
+        warn!("Failed to remove {}: {}", AUTOSAVE_PATH, err);
+    }
+
+    spawn_toast(&mut commands, &asset_server, "Map reloaded".to_string());
+    board_drawer.redraw(&game_state, &profiles);
+}
+
+/// Whether `material` uses one of the `textures`
+fn uses_texture(material: &StandardMaterial, textures: &[Handle<Image>]) -> bool {
+    [
+        &material.base_color_texture,
+        &material.emissive_texture,
+        &material.metallic_roughness_texture,
+        &material.normal_map_texture,
+        &material.occlusion_texture,
+    ]
+    .into_iter()
+    .flatten()
+    .any(|texture| textures.contains(texture))
+}
+
+/// Rebuilds the materials using a texture the asset server reloaded
+pub(crate) fn refresh_theme_materials(
+    mut image_events: EventReader<AssetEvent<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let reloaded: Vec<Handle<Image>> = image_events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { handle } => Some(handle.clone_weak()),
+            _ => None,
+        })
+        .collect();
+    if reloaded.is_empty() {
+        return;
+    }
+
+    let stale: Vec<HandleId> = materials
+        .iter()
+        .filter(|(_, material)| uses_texture(material, &reloaded))
+        .map(|(id, _)| id)
+        .collect();
+    for id in stale {
+        // touching the material is enough for it to be prepared again with its new texture
+        materials.get_mut(&Handle::weak(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "players 2\nregion 0 3 0,0 1,0\nregion 1 2 5,5\n";
+
+    #[test]
+    fn reloads_maps_for_the_same_players() {
+        let rules = GameRules::with_players(2);
+        let game_state = reload_map(MAP, &rules, 5).unwrap();
+        assert_eq!(game_state.board.regions.len(), 2);
+        assert_eq!(game_state.board.regions[0].num_dice, 3);
+        assert!(game_state.game_log.is_empty());
+
+        assert!(matches!(
+            reload_map(MAP, &GameRules::with_players(3), 5),
+            Err(ReloadError::Players { map: 2, game: 3 })
+        ));
+        assert!(matches!(
+            reload_map("region 0 1 0,0\n", &rules, 5),
+            Err(ReloadError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn changes_are_seen_once() {
+        let mut watch = MapWatch::new("no/such/map.txt".to_string());
+        let now = SystemTime::now();
+
+        assert!(!watch.changed(None));
+        assert!(watch.changed(Some(now)));
+        assert!(!watch.changed(Some(now)));
+        assert!(watch.changed(Some(now + Duration::from_secs(1))));
+    }
+
+    #[test]
+    fn materials_using_a_reloaded_texture_are_rebuilt() {
+        let texture: Handle<Image> = Handle::weak(HandleId::random::<Image>());
+        let other: Handle<Image> = Handle::weak(HandleId::random::<Image>());
+        let material = StandardMaterial {
+            normal_map_texture: Some(texture.clone()),
+            ..default()
+        };
+
+        assert!(uses_texture(&material, &[other.clone(), texture]));
+        assert!(!uses_texture(&material, &[other]));
+        assert!(!uses_texture(&StandardMaterial::default(), &[]));
+    }
+}
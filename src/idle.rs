@@ -0,0 +1,288 @@
+//! Idle detection: when the local player in turn doesn't do anything for a while, its playable
+//! regions pulse and a soft chime plays. Optionally the turn is then passed for it after a
+//! countdown. Any input action, battle or turn change counts as activity, and no time goes by
+//! while the game is paused.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::events::DiceRollTimer;
+use crate::game::{GameState, RegionId, TurnPhase};
+use crate::input::{EventInputAction, InputAction};
+use crate::rules::GameRules;
+use crate::seats::Seats;
+use crate::sounds::{EventSound, SoundEvent};
+use crate::ui::StackRankDiceUI;
+
+/// Length of a pulse of the playable regions, on then off
+const PULSE_PERIOD: Duration = Duration::from_millis(800);
+
+/// Idle delays, the reminder and the auto-pass are off when unset
+#[derive(Resource, Clone, Debug, Default)]
+pub struct IdleSettings {
+    /// Idle time before the player is reminded to play
+    pub reminder: Option<Duration>,
+    /// Time left to play after the reminder, before the turn is passed for the player
+    pub auto_pass: Option<Duration>,
+}
+
+/// How long the player in turn has been idle
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IdleStage {
+    #[default]
+    Active,
+    /// The player was reminded to play
+    Reminded,
+    /// The player ran out of time after the reminder, its turn is passed
+    AutoPass,
+}
+
+/// Idle time of the player in turn
+#[derive(Resource, Default)]
+pub struct IdleTracker {
+    idle: Duration,
+    pub stage: IdleStage,
+    /// Turn the idle time is counted for, a new turn starts over
+    turn: Option<(usize, usize)>,
+    /// The turn has to be passed for the player
+    auto_pass_due: bool,
+}
+
+impl IdleTracker {
+    /// Forgets the idle time, the player just did something
+    pub fn reset(&mut self) {
+        self.idle = Duration::ZERO;
+        self.stage = IdleStage::Active;
+        self.auto_pass_due = false;
+    }
+
+    /// Adds `delta` of idle time. Returns the stage the player just reached, if any.
+    pub fn tick(&mut self, delta: Duration, settings: &IdleSettings) -> Option<IdleStage> {
+        let reminder = settings.reminder?;
+        self.idle += delta;
+
+        let stage = match settings.auto_pass {
+            Some(auto_pass) if self.idle >= reminder + auto_pass => IdleStage::AutoPass,
+            _ if self.idle >= reminder => IdleStage::Reminded,
+            _ => IdleStage::Active,
+        };
+        match stage != self.stage {
+            true => {
+                self.stage = stage;
+                Some(stage)
+            }
+            false => None,
+        }
+    }
+
+    /// Time left before the turn is passed for the reminded player
+    pub fn auto_pass_in(&self, settings: &IdleSettings) -> Option<Duration> {
+        match (self.stage, settings.reminder, settings.auto_pass) {
+            (IdleStage::Reminded, Some(reminder), Some(auto_pass)) => {
+                Some((reminder + auto_pass).saturating_sub(self.idle))
+            }
+            _ => None,
+        }
+    }
+
+    /// Regions outlined right now to remind the player: the regions it can attack with or
+    /// reinforce, on every other half of the pulse
+    pub fn pulsing_regions(&self, game_state: &GameState, rules: &GameRules) -> Vec<RegionId> {
+        let pulse = self.idle.as_millis() % PULSE_PERIOD.as_millis();
+        if self.stage != IdleStage::Reminded || pulse >= PULSE_PERIOD.as_millis() / 2 {
+            return Vec::new();
+        }
+
+        let mut regions: Vec<RegionId> = match game_state.turn_phase {
+            TurnPhase::Attack => game_state
+                .attacks(rules)
+                .filter(|(attacker, _)| !attacker.has_moved)
+                .map(|(attacker, _)| attacker.id)
+                .collect(),
+            TurnPhase::Reinforcement => game_state
+                .board
+                .regions
+                .iter()
+                .filter(|region| game_state.can_place_reinforcement(region, rules))
+                .map(|region| region.id)
+                .collect(),
+        };
+        regions.dedup();
+        regions
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn track_idle(
+    time: Res<Time>,
+    settings: Res<IdleSettings>,
+    mut tracker: ResMut<IdleTracker>,
+    game_state: Res<GameState>,
+    seats: Res<Seats>,
+    dice_roll_timer_query: Query<(), With<DiceRollTimer>>,
+    mut event_input_action_reader: EventReader<EventInputAction>,
+    mut event_sound_writer: EventWriter<EventSound>,
+) {
+    let turn = Some((game_state.turn_counter, game_state.turn_of_player));
+    let acted = event_input_action_reader.iter().count() > 0;
+    let waiting = seats.is_local_human(game_state.turn_of_player)
+        && dice_roll_timer_query.is_empty()
+        && game_state.winner().is_none();
+    if acted || !waiting || tracker.turn != turn {
+        tracker.turn = turn;
+        tracker.reset();
+        return;
+    }
+
+    match tracker.tick(time.delta(), &settings) {
+        Some(IdleStage::Reminded) => {
+            event_sound_writer.send(EventSound {
+                sound: SoundEvent::IdleReminder,
+            });
+        }
+        Some(IdleStage::AutoPass) => tracker.auto_pass_due = true,
+        _ => {}
+    }
+}
+
+/// Ends the attacks of a player that stayed idle past the auto-pass countdown, as if it pressed
+/// the end turn button. Reinforcements are still left to place by hand.
+pub(crate) fn auto_pass_idle_player(
+    mut tracker: ResMut<IdleTracker>,
+    game_state: Res<GameState>,
+    mut event_input_action_writer: EventWriter<EventInputAction>,
+) {
+    if !tracker.auto_pass_due {
+        return;
+    }
+
+    tracker.auto_pass_due = false;
+    info!(
+        "Turn {}: passing for idle player {}",
+        game_state.turn_counter, game_state.turn_of_player
+    );
+    event_input_action_writer.send(EventInputAction {
+        action: InputAction::EndTurn,
+    });
+}
+
+/// Text area with the auto-pass countdown
+#[derive(Component)]
+pub(crate) struct IdleText;
+
+pub(crate) fn setup_idle_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::ORANGE,
+                },
+            )
+            .with_text_alignment(TextAlignment::TOP_CENTER)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(50.0),
+                    left: Val::Percent(40.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Name::new("Idle Text"))
+        .insert(IdleText)
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn idle_text_update(
+    tracker: Res<IdleTracker>,
+    settings: Res<IdleSettings>,
+    mut query: Query<&mut Text, With<IdleText>>,
+) {
+    for mut text in &mut query {
+        text.sections[0].value = match tracker.auto_pass_in(&settings) {
+            Some(left) => format!("AUTO-PASS IN {}", left.as_secs() + 1),
+            None => String::new(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+
+    fn settings() -> IdleSettings {
+        IdleSettings {
+            reminder: Some(Duration::from_secs(20)),
+            auto_pass: Some(Duration::from_secs(10)),
+        }
+    }
+
+    #[test]
+    fn idle_players_are_reminded_then_passed() {
+        let settings = settings();
+        let mut tracker = IdleTracker::default();
+
+        assert_eq!(tracker.tick(Duration::from_secs(19), &settings), None);
+        assert_eq!(
+            tracker.tick(Duration::from_secs(1), &settings),
+            Some(IdleStage::Reminded)
+        );
+        assert_eq!(
+            tracker.auto_pass_in(&settings),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(tracker.tick(Duration::from_secs(5), &settings), None);
+        assert_eq!(
+            tracker.tick(Duration::from_secs(5), &settings),
+            Some(IdleStage::AutoPass)
+        );
+
+        tracker.reset();
+        assert_eq!(tracker.stage, IdleStage::Active);
+        assert_eq!(tracker.tick(Duration::from_secs(19), &settings), None);
+    }
+
+    #[test]
+    fn nothing_happens_without_a_reminder() {
+        let mut tracker = IdleTracker::default();
+
+        assert_eq!(
+            tracker.tick(Duration::from_secs(600), &IdleSettings::default()),
+            None
+        );
+        assert_eq!(tracker.stage, IdleStage::Active);
+    }
+
+    #[test]
+    fn reminded_players_see_their_playable_regions() {
+        let map = MapFile::parse(
+            "players 2\nregion 0 3 0,0\nregion 1 2 1,0\nregion 0 1 3,3\nregion 0 4 5,5\n",
+        )
+        .unwrap();
+        let game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        let rules = GameRules::default();
+        let settings = IdleSettings {
+            reminder: Some(Duration::from_secs(1)),
+            auto_pass: None,
+        };
+
+        let mut tracker = IdleTracker::default();
+        assert!(tracker.pulsing_regions(&game_state, &rules).is_empty());
+
+        tracker.tick(Duration::from_secs(1), &settings);
+        assert_eq!(tracker.pulsing_regions(&game_state, &rules), vec![0]);
+
+        tracker.tick(PULSE_PERIOD / 2, &settings);
+        assert!(tracker.pulsing_regions(&game_state, &rules).is_empty());
+    }
+}
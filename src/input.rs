@@ -0,0 +1,255 @@
+//! Input actions, decoupling what the player wants to do from the device it used.
+//!
+//! [`emit_input_actions`] turns mouse, keyboard, touch and gamepad input into
+//! [`EventInputAction`]s through the bindings of the [`InputMap`] resource. Game systems only
+//! read the actions.
+
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+use bevy_mod_picking::{PickingEvent, SelectionEvent};
+
+use crate::game::Region;
+use crate::ui::BoardCamera;
+
+/// Something the player wants to do, whatever device it came from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum InputAction {
+    /// Picks the region entity under the cursor or finger
+    Select(Entity),
+    /// Points the other players to the region entity under the cursor
+    Ping(Entity),
+    /// Picks the region under the cursor without clicking it
+    Confirm,
+    /// Drops the current selection
+    Cancel,
+    /// Ends the attacks of the player in turn
+    EndTurn,
+    Pause,
+    /// Moves the board camera, in screen units
+    CameraPan(Vec2),
+    /// Zooms the board camera in (above 1.0) or out (below 1.0)
+    CameraZoom(f32),
+}
+
+/// Event that is fired for every action the player triggered this frame
+pub(crate) struct EventInputAction {
+    pub(crate) action: InputAction,
+}
+
+/// Actions triggered by pressing a button
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonAction {
+    Confirm,
+    Cancel,
+    EndTurn,
+    Pause,
+}
+
+impl From<ButtonAction> for InputAction {
+    fn from(action: ButtonAction) -> Self {
+        match action {
+            ButtonAction::Confirm => InputAction::Confirm,
+            ButtonAction::Cancel => InputAction::Cancel,
+            ButtonAction::EndTurn => InputAction::EndTurn,
+            ButtonAction::Pause => InputAction::Pause,
+        }
+    }
+}
+
+/// Whether either Control key is held, for the keys that do something else with Control
+pub(crate) fn control_pressed(keys: &Input<KeyCode>) -> bool {
+    keys.any_pressed([KeyCode::LControl, KeyCode::RControl])
+}
+
+/// Bindings from devices to actions. Clicks and taps on regions always select them, clicks on
+/// empty space always cancel the selection.
+#[derive(Resource)]
+pub struct InputMap {
+    pub keys: Vec<(KeyCode, ButtonAction)>,
+    pub mouse_buttons: Vec<(MouseButton, ButtonAction)>,
+    pub gamepad_buttons: Vec<(GamepadButtonType, ButtonAction)>,
+    /// Keys panning the camera, with the direction they pan to
+    pub pan_keys: Vec<(KeyCode, Vec2)>,
+    /// Keys zooming the camera, with the zoom factor applied per second
+    pub zoom_keys: Vec<(KeyCode, f32)>,
+    /// Camera pan speed of keys and sticks, in screen units per second
+    pub pan_speed: f32,
+    /// Keys turning a click on a region into a ping while held
+    pub ping_keys: Vec<KeyCode>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        InputMap {
+            keys: vec![
+                (KeyCode::Return, ButtonAction::Confirm),
+                (KeyCode::Space, ButtonAction::Confirm),
+                (KeyCode::Escape, ButtonAction::Cancel),
+                (KeyCode::E, ButtonAction::EndTurn),
+                (KeyCode::P, ButtonAction::Pause),
+            ],
+            mouse_buttons: vec![(MouseButton::Right, ButtonAction::Cancel)],
+            gamepad_buttons: vec![
+                (GamepadButtonType::South, ButtonAction::Confirm),
+                (GamepadButtonType::East, ButtonAction::Cancel),
+                (GamepadButtonType::North, ButtonAction::EndTurn),
+                (GamepadButtonType::Start, ButtonAction::Pause),
+            ],
+            pan_keys: vec![
+                (KeyCode::Left, Vec2::NEG_X),
+                (KeyCode::Right, Vec2::X),
+                (KeyCode::Up, Vec2::Y),
+                (KeyCode::Down, Vec2::NEG_Y),
+            ],
+            zoom_keys: vec![(KeyCode::Equals, 2.0), (KeyCode::Minus, 0.5)],
+            pan_speed: 20.0,
+            ping_keys: vec![KeyCode::LAlt, KeyCode::RAlt],
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn emit_input_actions(
+    input_map: Res<InputMap>,
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    touches: Res<Touches>,
+    region_interaction_query: Query<&Interaction, With<Region>>,
+    mut picking_event_reader: EventReader<PickingEvent>,
+    mut mouse_wheel_reader: EventReader<MouseWheel>,
+    mut event_writer: EventWriter<EventInputAction>,
+) {
+    let mut actions = Vec::new();
+
+    // clicks and taps on regions, pings with the ping modifier held
+    let ping = input_map.ping_keys.iter().any(|key| keys.pressed(*key));
+    for event in picking_event_reader.iter() {
+        if let PickingEvent::Selection(SelectionEvent::JustSelected(entity)) = event {
+            actions.push(match ping {
+                true => InputAction::Ping(*entity),
+                false => InputAction::Select(*entity),
+            });
+        }
+    }
+
+    // clicking empty space cancels the selection
+    if mouse_buttons.just_pressed(MouseButton::Left)
+        && region_interaction_query
+            .iter()
+            .all(|interaction| *interaction == Interaction::None)
+    {
+        actions.push(InputAction::Cancel);
+    }
+
+    // buttons
+    for (key, action) in input_map.keys.iter() {
+        if keys.just_pressed(*key) {
+            actions.push((*action).into());
+        }
+    }
+    for (button, action) in input_map.mouse_buttons.iter() {
+        if mouse_buttons.just_pressed(*button) {
+            actions.push((*action).into());
+        }
+    }
+    for gamepad in gamepads.iter() {
+        for (button_type, action) in input_map.gamepad_buttons.iter() {
+            if gamepad_buttons.just_pressed(GamepadButton::new(gamepad, *button_type)) {
+                actions.push((*action).into());
+            }
+        }
+    }
+
+    // camera pan from the keyboard, gamepad sticks, and dragging two fingers
+    let mut pan: Vec2 = input_map
+        .pan_keys
+        .iter()
+        .filter(|(key, _)| keys.pressed(*key))
+        .map(|(_, direction)| *direction)
+        .sum();
+    for gamepad in gamepads.iter() {
+        let axis = |axis_type| {
+            gamepad_axes
+                .get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or(0.0)
+        };
+        pan += Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+    }
+    pan *= input_map.pan_speed * time.delta_seconds();
+
+    let fingers: Vec<_> = touches.iter().collect();
+    if fingers.len() == 2 {
+        pan -= (fingers[0].delta() + fingers[1].delta()) / 2.0 * Vec2::new(1.0, -1.0);
+    }
+    if pan != Vec2::ZERO {
+        actions.push(InputAction::CameraPan(pan));
+    }
+
+    // camera zoom from the keyboard, the mouse wheel, and pinching two fingers
+    let mut zoom: f32 = input_map
+        .zoom_keys
+        .iter()
+        .filter(|(key, _)| keys.pressed(*key))
+        .map(|(_, factor)| factor.powf(time.delta_seconds()))
+        .product();
+    for wheel in mouse_wheel_reader.iter() {
+        let lines = match wheel.unit {
+            MouseScrollUnit::Line => wheel.y,
+            MouseScrollUnit::Pixel => wheel.y / 20.0,
+        };
+        zoom *= 1.1f32.powf(lines);
+    }
+    if fingers.len() == 2 {
+        let before = fingers[0]
+            .previous_position()
+            .distance(fingers[1].previous_position());
+        let after = fingers[0].position().distance(fingers[1].position());
+        if before > 0.0 {
+            zoom *= after / before;
+        }
+    }
+    if zoom != 1.0 {
+        actions.push(InputAction::CameraZoom(zoom));
+    }
+
+    event_writer.send_batch(
+        actions
+            .into_iter()
+            .map(|action| EventInputAction { action }),
+    );
+}
+
+/// Moves and zooms the board camera
+pub(crate) fn camera_controls(
+    mut event_input_action_reader: EventReader<EventInputAction>,
+    mut camera_query: Query<(&mut Transform, &mut Projection), With<BoardCamera>>,
+) {
+    for event in event_input_action_reader.iter() {
+        for (mut transform, mut projection) in camera_query.iter_mut() {
+            let projection = match projection.as_mut() {
+                Projection::Orthographic(projection) => projection,
+                Projection::Perspective(_) => continue,
+            };
+
+            match event.action {
+                InputAction::CameraPan(pan) => {
+                    let offset = (transform.right() * pan.x + transform.up() * pan.y)
+                        * projection.scale
+                        / 10.0;
+                    transform.translation += offset;
+                }
+                InputAction::CameraZoom(zoom) => {
+                    projection.scale = (projection.scale / zoom).clamp(2.0, 30.0);
+                }
+                _ => {}
+            }
+        }
+    }
+}
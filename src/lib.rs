@@ -1,9 +1,83 @@
+pub mod accessibility;
+pub mod ambient;
+pub mod api;
 pub mod app;
+pub mod autosave;
+pub mod balance;
+pub mod battle_log;
+pub mod blitz;
 pub mod board;
+pub mod bug_report;
+pub mod camera;
+pub mod chaos;
+pub mod chapters;
+pub mod clock;
+pub mod commentary;
+pub mod delta;
+pub mod dice_audit;
+pub mod diplomacy;
+pub mod display;
+pub mod editor;
 pub mod events;
+pub mod fair_dice;
 pub mod game;
+pub mod generators;
 pub mod geometry;
+pub mod gpu_picking;
+pub mod handshake;
 pub mod hex;
+pub mod hex_debug;
 pub mod highlights;
+pub mod hot_reload;
+pub mod idle;
+pub mod input;
+pub mod lobby;
+pub mod logic_step;
+pub mod map_file;
+pub mod memory_budget;
+pub mod menu;
+pub mod names;
+pub mod new_game;
+pub mod objectives;
+pub mod observation;
+pub mod odds;
+pub mod ownership_heatmap;
+pub mod pause;
+pub mod persistence;
+pub mod pings;
+pub mod player_stats;
+pub mod presence;
+pub mod presets;
+pub mod profiles;
+pub mod puzzle;
+pub mod recorder;
+pub mod relay;
+pub mod rematch;
+pub mod replay;
+pub mod replay_binary;
+pub mod rules;
+pub mod scenario;
+pub mod scene;
+pub mod screenshot;
+pub mod seats;
+pub mod session;
+pub mod simulation;
+pub mod snapshots;
+pub mod sounds;
+pub mod spectate;
+pub mod speedrun;
+pub mod stalemate;
+pub mod stamp;
+pub mod storage;
+pub mod telemetry;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+pub mod threats;
+pub mod thumbnails;
 pub mod tiered_prng;
+pub mod timeline;
+pub mod turn_banner;
 pub mod ui;
+pub mod validation;
+pub mod versioning;
+pub mod zobrist;
@@ -0,0 +1,687 @@
+//! Network games: hosting a game for players on other machines and joining one by code or
+//! address.
+//!
+//! A game hosted with `--host` shows its address and join code until every seat handed to the
+//! JSON API of [`crate::api`] is taken. The API isn't authenticated, it is only served to the
+//! local machine unless `--public` serves it on every network interface. A join code is the
+//! address of the host in ten letters and digits, easier to read out than an address:
+//!
+//! ```text
+//! cargo run --features api -- --api-port 7777 --api-seat 2 --host --public
+//! cargo run --features api -- --join 5R2C8-0K7ZH
+//! cargo run --features api -- --join 192.168.1.20:7777
+//! ```
+//!
+//! Players that can't reach the host, both behind a NAT without port forwarding, meet on a relay
+//! instead (see [`crate::relay`]). The host opens a room with `--relay`, and players join the
+//! room code it shows on the same relay:
+//!
+//! ```text
+//! cargo run --features api -- --api-port 7777 --api-seat 2 --host --relay relay.example.org:7800
+//! cargo run --features api -- --join Q4MZ7-1XK2D --relay relay.example.org:7800
+//! ```
+//!
+//! Players can also join from the main menu: `J` opens the join screen, where they type the
+//! join code or address, and the relay of a room code, before Enter joins.
+//!
+//! Joining sends a `join` request with the version and protocol of the client, the handshake of
+//! [`crate::handshake`]. The host hands over a free API seat, or refuses clients speaking another
+//! protocol and clients coming once every API seat is taken, and the client checks the protocol
+//! of the host in turn. The joined seat is then played on the board of the host, followed like a
+//! spectator does (see [`crate::spectate`]), and only takes moves from the client that joined.
+
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::api::{ApiServer, INCOMPATIBLE_VERSION, LOBBY_FULL};
+use crate::board::BoardDrawer;
+use crate::game::GameState;
+use crate::handshake::{Handshake, Incompatible};
+use crate::menu::{spawn_menu_screen, switch_state, AppState, SetupText};
+use crate::new_game::NewGame;
+use crate::profiles::PlayerProfiles;
+use crate::relay::RelayRoom;
+use crate::rematch::Rematch;
+use crate::rules::GameRules;
+use crate::seats::{SeatController, Seats};
+use crate::spectate::{join, SpectatorStream};
+use crate::ui::StackRankDiceUI;
+
+/// Letters and digits of join codes, without the ones easily mistaken for another
+const CODE_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Characters of a join code, without the dash in the middle
+const CODE_LENGTH: usize = 10;
+
+/// Code of [`CODE_LENGTH`] `digits`, each below 32, with a dash in the middle
+fn format_code(digits: impl Iterator<Item = usize>) -> String {
+    let mut code: String = digits.map(|digit| CODE_ALPHABET[digit] as char).collect();
+    code.insert(CODE_LENGTH / 2, '-');
+
+    code
+}
+
+/// Digits of a code of [`CODE_LENGTH`] characters. Case and dashes don't matter, and `O`, `I`
+/// and `L` are read as the digits they look like.
+fn code_digits(code: &str) -> Option<Vec<usize>> {
+    let digits = code
+        .trim()
+        .chars()
+        .filter(|c| *c != '-')
+        .map(|c| {
+            let c = match c.to_ascii_uppercase() {
+                'O' => '0',
+                'I' | 'L' => '1',
+                c => c,
+            };
+            CODE_ALPHABET.iter().position(|letter| *letter as char == c)
+        })
+        .collect::<Option<Vec<usize>>>()?;
+
+    (digits.len() == CODE_LENGTH).then_some(digits)
+}
+
+/// Join code of a game hosted at `address`
+pub fn join_code(address: SocketAddrV4) -> String {
+    let value = (u64::from(u32::from(*address.ip())) << 16) | u64::from(address.port());
+    format_code(
+        (0..CODE_LENGTH)
+            .rev()
+            .map(|digit| ((value >> (digit * 5)) & 31) as usize),
+    )
+}
+
+/// Address of the game of a join code
+pub fn parse_join_code(code: &str) -> Option<SocketAddrV4> {
+    let value = code_digits(code)?
+        .into_iter()
+        .fold(0, |value: u64, digit| (value << 5) | digit as u64);
+    if value >> 48 != 0 {
+        return None;
+    }
+
+    Some(SocketAddrV4::new(
+        Ipv4Addr::from((value >> 16) as u32),
+        value as u16,
+    ))
+}
+
+/// Random code of a room on a relay
+pub fn room_code(rng: &mut impl Rng) -> String {
+    format_code((0..CODE_LENGTH).map(|_| rng.gen_range(0..CODE_ALPHABET.len())))
+}
+
+/// Room code written the way the host shows it
+pub fn parse_room_code(code: &str) -> Option<String> {
+    code_digits(code).map(|digits| format_code(digits.into_iter()))
+}
+
+/// Game to join, checked before connecting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinTarget {
+    /// `<host>:<port>` address of the host
+    Direct(String),
+    Relayed(RelayRoom),
+}
+
+// some errors only happen with the `api` feature, others only without it
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinError {
+    InvalidCode,
+    InvalidRoom,
+    InvalidAddress(String),
+    /// Built without the `api` feature
+    Unsupported,
+    Unreachable(String),
+    /// The host can't play with this game
+    Incompatible(Incompatible),
+    LobbyFull,
+    /// The host answered with an error, carried here
+    Refused(String),
+    Disconnected,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::InvalidCode => write!(
+                f,
+                "invalid join code, expected 10 letters and digits like 5R2C8-0K7ZH"
+            ),
+            JoinError::InvalidRoom => write!(
+                f,
+                "invalid room, expected the 10 letters and digits shown by the host"
+            ),
+            JoinError::InvalidAddress(address) => {
+                write!(f, "invalid address {}, expected <host>:<port>", address)
+            }
+            JoinError::Unsupported => write!(f, "joining a game needs the `api` feature"),
+            JoinError::Unreachable(err) => write!(f, "failed to reach the game: {}", err),
+            JoinError::Incompatible(err) => write!(f, "{}", err),
+            JoinError::LobbyFull => write!(f, "the game is full, every seat is taken"),
+            JoinError::Refused(err) => write!(f, "the host refused: {}", err),
+            JoinError::Disconnected => write!(f, "the host closed the game"),
+        }
+    }
+}
+
+/// Checks a `<host>:<port>` address
+pub fn parse_address(address: &str) -> Result<String, JoinError> {
+    let address = address.trim();
+    let invalid = || JoinError::InvalidAddress(address.to_string());
+    let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+
+    let valid_host = !host.is_empty() && !host.contains(char::is_whitespace);
+    match (valid_host, port.parse::<u16>()) {
+        (true, Ok(port)) if port > 0 => Ok(format!("{}:{}", host, port)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Address to connect to for a join code or a `<host>:<port>` address, checked before
+/// connecting
+pub fn parse_join_target(target: &str) -> Result<String, JoinError> {
+    match target.contains(':') {
+        true => parse_address(target),
+        false => parse_join_code(target)
+            .map(|address| address.to_string())
+            .ok_or(JoinError::InvalidCode),
+    }
+}
+
+/// Game to join for the join code, address or room code `target`, on the relay at the
+/// address `relay` if any
+pub fn join_target(target: &str, relay: Option<&str>) -> Result<JoinTarget, JoinError> {
+    match relay {
+        Some(relay) => Ok(JoinTarget::Relayed(RelayRoom {
+            relay: parse_address(relay)?,
+            room: parse_room_code(target).ok_or(JoinError::InvalidRoom)?,
+        })),
+        None => parse_join_target(target).map(JoinTarget::Direct),
+    }
+}
+
+/// Seat the host handed over in its reply `text` to a join request, none for other messages
+#[allow(dead_code)]
+pub fn join_reply(text: &str) -> Option<Result<usize, JoinError>> {
+    let reply: serde_json::Value = serde_json::from_str(text).ok()?;
+    // hosts from before the handshake send no protocol
+    let host = Handshake {
+        version: reply["version"].as_str().unwrap_or("?").to_string(),
+        protocol: reply["protocol"].as_u64().unwrap_or(0) as u32,
+        rules_hash: None,
+    };
+    match reply["type"].as_str()? {
+        "joined" => Some(match Handshake::local(None).check(&host) {
+            Ok(()) => reply["player"]
+                .as_u64()
+                .map(|player| player as usize)
+                .ok_or(JoinError::Disconnected),
+            Err(err) => Err(JoinError::Incompatible(err)),
+        }),
+        "join_refused" => Some(Err(match reply["reason"].as_str() {
+            Some(INCOMPATIBLE_VERSION) => JoinError::Incompatible(Incompatible::Version {
+                version: host.version,
+                protocol: host.protocol,
+            }),
+            Some(LOBBY_FULL) => JoinError::LobbyFull,
+            _ => JoinError::Disconnected,
+        })),
+        _ => None,
+    }
+}
+
+/// Address of this machine on its local network, as other machines reach it. Connecting a UDP
+/// socket only picks the interface, nothing is sent.
+pub fn local_address() -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9)).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        std::net::IpAddr::V4(ip) if !ip.is_unspecified() => Some(ip),
+        _ => None,
+    }
+}
+
+/// Game hosted for players on other machines
+#[derive(Resource, Clone, Debug, PartialEq, Eq)]
+pub struct HostedGame {
+    pub address: SocketAddrV4,
+    /// Room players join on a relay instead of the address
+    pub relay: Option<RelayRoom>,
+}
+
+impl HostedGame {
+    /// The game served on `port` of the local network address of this machine if `public`, of
+    /// the machine itself otherwise or when it has none
+    pub fn new(port: u16, public: bool, relay: Option<RelayRoom>) -> Self {
+        let ip = match public {
+            true => local_address().unwrap_or(Ipv4Addr::LOCALHOST),
+            false => Ipv4Addr::LOCALHOST,
+        };
+
+        HostedGame {
+            address: SocketAddrV4::new(ip, port),
+            relay,
+        }
+    }
+
+    pub fn code(&self) -> String {
+        join_code(self.address)
+    }
+}
+
+#[cfg(feature = "api")]
+pub(crate) mod client {
+    use std::net::TcpStream;
+
+    use tungstenite::WebSocket;
+
+    use super::{JoinError, JoinTarget};
+    use crate::relay;
+
+    /// WebSocket to the game of `target`, and the address it was reached at
+    pub(crate) fn connect(
+        target: &JoinTarget,
+    ) -> Result<(String, WebSocket<TcpStream>), JoinError> {
+        let (address, stream) = match target {
+            JoinTarget::Direct(address) => (address, TcpStream::connect(address)),
+            JoinTarget::Relayed(room) => (&room.relay, relay::join(room)),
+        };
+        let stream = stream.map_err(|err| JoinError::Unreachable(err.to_string()))?;
+        // the WebSocket runs the same over a relayed stream
+        let (socket, _) = tungstenite::client(format!("ws://{}/", address), stream)
+            .map_err(|err| JoinError::Unreachable(err.to_string()))?;
+
+        Ok((address.clone(), socket))
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct LobbyText;
+
+pub(crate) fn setup_lobby_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    hosted_game: Option<Res<HostedGame>>,
+) {
+    if hosted_game.is_none() {
+        return;
+    }
+
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 24.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(10.0),
+                    left: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Name::new("Lobby Text"))
+        .insert(LobbyText)
+        .insert(StackRankDiceUI);
+}
+
+/// Text of the lobby of a hosted game, empty once every seat handed to the API is taken
+pub fn lobby_text(hosted_game: &HostedGame, joined: usize, api_seats: usize) -> String {
+    if joined >= api_seats {
+        return String::new();
+    }
+
+    let join = match &hosted_game.relay {
+        Some(room) => format!("HOSTING ON RELAY {}\nROOM {}", room.relay, room.room),
+        None => format!(
+            "HOSTING ON {}\nJOIN CODE {}",
+            hosted_game.address,
+            hosted_game.code()
+        ),
+    };
+    format!("{}\nPLAYERS JOINED {} OF {}", join, joined, api_seats)
+}
+
+pub(crate) fn lobby_text_update(
+    hosted_game: Option<Res<HostedGame>>,
+    api: Option<Res<ApiServer>>,
+    seats: Res<Seats>,
+    mut query: Query<&mut Text, With<LobbyText>>,
+) {
+    let (hosted_game, api) = match (hosted_game, api) {
+        (Some(hosted_game), Some(api)) => (hosted_game, api),
+        _ => return,
+    };
+    let api_seats = seats
+        .controllers
+        .iter()
+        .filter(|controller| matches!(controller, SeatController::Remote(_)))
+        .count();
+
+    for mut text in &mut query {
+        text.sections[0].value = lobby_text(&hosted_game, api.joined_players(), api_seats);
+    }
+}
+
+/// Lines of the join screen
+const JOIN_FIELDS: usize = 2;
+
+/// Game to join typed on the join screen of the main menu
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub struct JoinForm {
+    /// Join code, address or room code shown by the host
+    pub target: String,
+    /// `<host>:<port>` address of the relay of a room code, empty to join the host directly
+    pub relay: String,
+    /// Line of the join screen being typed in
+    selected: usize,
+    /// Why the last attempt to join failed, or that it is still connecting
+    status: Option<String>,
+}
+
+impl JoinForm {
+    /// Moves the selection `step` lines down, up for negative steps
+    pub fn select(&mut self, step: isize) {
+        self.selected = (self.selected as isize + step).rem_euclid(JOIN_FIELDS as isize) as usize;
+    }
+
+    fn line(&mut self) -> &mut String {
+        match self.selected {
+            0 => &mut self.target,
+            _ => &mut self.relay,
+        }
+    }
+
+    /// Types `c` on the selected line. Codes and addresses have no spaces.
+    pub fn type_char(&mut self, c: char) {
+        if !c.is_control() && !c.is_whitespace() {
+            self.line().push(c);
+        }
+    }
+
+    /// Erases the last character of the selected line
+    pub fn erase(&mut self) {
+        self.line().pop();
+    }
+
+    /// Game to join, checked before connecting
+    pub fn join_target(&self) -> Result<JoinTarget, JoinError> {
+        let relay = Some(self.relay.trim()).filter(|relay| !relay.is_empty());
+        join_target(&self.target, relay)
+    }
+
+    pub fn text(&self) -> String {
+        let values = [("CODE", &self.target), ("RELAY", &self.relay)];
+        let lines: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(line, (label, value))| {
+                let marker = match line == self.selected {
+                    true => ">",
+                    false => " ",
+                };
+                format!("{} {}  {}_", marker, label, value)
+            })
+            .collect();
+
+        format!(
+            "{}\n\n{}\n\nTYPE THE JOIN CODE OR ADDRESS, AND THE RELAY OF A ROOM\n\
+             UP/DOWN CHOOSE\nENTER JOIN, ESC BACK",
+            lines.join("\n"),
+            self.status.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+/// Join started on the join screen, connecting on a background thread
+#[derive(Resource)]
+pub(crate) struct PendingJoin(Mutex<Receiver<Result<SpectatorStream, JoinError>>>);
+
+pub(crate) fn setup_join_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    form: Res<JoinForm>,
+    mut characters: ResMut<Events<ReceivedCharacter>>,
+) {
+    // the key opening the screen isn't typed in it
+    characters.clear();
+    spawn_menu_screen(&mut commands, &asset_server, "JOIN GAME", form.text());
+}
+
+/// Types the game to join, and starts joining it on Enter
+pub(crate) fn join_screen_input(
+    mut commands: Commands,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut character_reader: EventReader<ReceivedCharacter>,
+    mut app_state: ResMut<State<AppState>>,
+    mut form: ResMut<JoinForm>,
+    pending: Option<Res<PendingJoin>>,
+    mut text_query: Query<&mut Text, With<SetupText>>,
+) {
+    if pending.is_some() {
+        character_reader.clear();
+        return;
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        switch_state(&mut app_state, AppState::MainMenu);
+        keys.reset(KeyCode::Escape);
+        return;
+    }
+
+    let mut changed = form.clone();
+    for event in character_reader.iter() {
+        changed.type_char(event.char);
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        changed.erase();
+    }
+    if keys.just_pressed(KeyCode::Up) {
+        changed.select(-1);
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        changed.select(1);
+    }
+    if keys.just_pressed(KeyCode::Return) {
+        changed.status = match changed.join_target() {
+            Err(err) => Some(err.to_string().to_uppercase()),
+            Ok(_) if !cfg!(feature = "api") => {
+                Some(JoinError::Unsupported.to_string().to_uppercase())
+            }
+            Ok(target) => {
+                let (sender, receiver) = mpsc::channel();
+                std::thread::spawn(move || {
+                    let _ = sender.send(join(&target));
+                });
+                commands.insert_resource(PendingJoin(Mutex::new(receiver)));
+                Some("JOINING...".to_string())
+            }
+        };
+    }
+    if *form == changed {
+        return;
+    }
+
+    *form = changed;
+    for mut text in text_query.iter_mut() {
+        text.sections[1].value = form.text();
+    }
+}
+
+/// Starts following the joined game once the host handed over a seat, or shows why it didn't
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn finish_join(
+    mut commands: Commands,
+    pending: Option<Res<PendingJoin>>,
+    mut form: ResMut<JoinForm>,
+    mut text_query: Query<&mut Text, With<SetupText>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut game_state: ResMut<GameState>,
+    mut rules: ResMut<GameRules>,
+    mut seats: ResMut<Seats>,
+    mut profiles: ResMut<PlayerProfiles>,
+    mut rematch: ResMut<Rematch>,
+    mut board_drawer: BoardDrawer,
+    mut new_game: NewGame,
+) {
+    let pending = match pending {
+        Some(pending) => pending,
+        None => return,
+    };
+    let joined = match pending.0.lock().unwrap().try_recv() {
+        Ok(joined) => joined,
+        Err(mpsc::TryRecvError::Empty) => return,
+        Err(mpsc::TryRecvError::Disconnected) => Err(JoinError::Disconnected),
+    };
+    commands.remove_resource::<PendingJoin>();
+
+    let stream = match joined {
+        Ok(stream) => stream,
+        Err(err) => {
+            form.status = Some(err.to_string().to_uppercase());
+            for mut text in text_query.iter_mut() {
+                text.sections[1].value = form.text();
+            }
+            return;
+        }
+    };
+
+    info!("Joined as player {:?}", stream.seat().map(|seat| seat + 1));
+    *game_state = stream.board().game_state.clone();
+    rules.number_of_players = game_state.number_of_players;
+    *seats = stream.seats();
+    *profiles = PlayerProfiles::new(&[], game_state.number_of_players);
+    rematch.new_series(&game_state);
+    new_game.reset();
+    commands.insert_resource(stream);
+    form.status = None;
+    switch_state(&mut app_state, AppState::InGame);
+    board_drawer.redraw(&game_state, &profiles);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_codes_hold_the_address() {
+        let address = SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 20), 7777);
+        let code = join_code(address);
+
+        assert_eq!(code.len(), CODE_LENGTH + 1);
+        assert_eq!(parse_join_code(&code), Some(address));
+        assert_eq!(
+            parse_join_code(&code.to_lowercase().replace('-', "")),
+            Some(address)
+        );
+        assert_eq!(parse_join_code("5R2C8-0K7Z"), None);
+        assert_eq!(parse_join_code("ZZZZZ-ZZZZZ"), None);
+    }
+
+    #[test]
+    fn join_targets_are_checked_before_connecting() {
+        let address = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 7777);
+
+        assert_eq!(
+            parse_join_target(&join_code(address)),
+            Ok("10.0.0.2:7777".to_string())
+        );
+        assert_eq!(
+            parse_join_target(" example.org:7777 "),
+            Ok("example.org:7777".to_string())
+        );
+        assert_eq!(
+            parse_join_target("example.org:0"),
+            Err(JoinError::InvalidAddress("example.org:0".to_string()))
+        );
+        assert_eq!(parse_join_target("UVWXY"), Err(JoinError::InvalidCode));
+    }
+
+    #[test]
+    fn rooms_are_joined_on_a_relay() {
+        let room = room_code(&mut rand::thread_rng());
+        assert_eq!(parse_room_code(&room.to_lowercase()), Some(room.clone()));
+
+        assert_eq!(
+            join_target(&room, Some("relay.example.org:7800")),
+            Ok(JoinTarget::Relayed(RelayRoom {
+                relay: "relay.example.org:7800".to_string(),
+                room,
+            }))
+        );
+        assert_eq!(
+            join_target("nope", Some("relay.example.org:7800")),
+            Err(JoinError::InvalidRoom)
+        );
+        assert_eq!(
+            join_target("Q4MZ7-1XK2D", Some("relay.example.org")),
+            Err(JoinError::InvalidAddress("relay.example.org".to_string()))
+        );
+    }
+
+    #[test]
+    fn join_screen_checks_what_was_typed() {
+        let mut form = JoinForm::default();
+        for c in "5r2c8 0k7zh!".chars() {
+            form.type_char(c);
+        }
+        form.erase();
+        assert_eq!(form.target, "5r2c80k7zh");
+        assert!(form.text().contains("> CODE  5r2c80k7zh_"));
+        assert!(matches!(form.join_target(), Ok(JoinTarget::Direct(_))));
+
+        form.select(1);
+        for c in "relay.example.org".chars() {
+            form.type_char(c);
+        }
+        assert_eq!(
+            form.join_target(),
+            Err(JoinError::InvalidAddress("relay.example.org".to_string()))
+        );
+        form.type_char(':');
+        form.type_char('9');
+        assert!(matches!(form.join_target(), Ok(JoinTarget::Relayed(_))));
+    }
+
+    #[test]
+    fn join_replies_tell_why_a_game_cant_be_joined() {
+        assert_eq!(
+            join_reply(r#"{"type":"joined","player":1,"version":"9.9.9","protocol":1}"#),
+            Some(Ok(1))
+        );
+        let incompatible = Some(Err(JoinError::Incompatible(Incompatible::Version {
+            version: "9.9.9".to_string(),
+            protocol: 7,
+        })));
+        assert_eq!(
+            join_reply(concat!(
+                r#"{"type":"join_refused","reason":"incompatible_version","#,
+                r#""version":"9.9.9","protocol":7}"#
+            )),
+            incompatible
+        );
+        // the client checks the host too
+        assert_eq!(
+            join_reply(r#"{"type":"joined","player":1,"version":"9.9.9","protocol":7}"#),
+            incompatible
+        );
+        assert_eq!(
+            join_reply(r#"{"type":"join_refused","reason":"lobby_full","version":"9.9.9"}"#),
+            Some(Err(JoinError::LobbyFull))
+        );
+        assert_eq!(join_reply(r#"{"type":"state"}"#), None);
+    }
+}
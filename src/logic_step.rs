@@ -0,0 +1,32 @@
+//! Fixed timestep of the game logic.
+//!
+//! Systems moving the game forward with time, the seats playing their moves, the dice shown
+//! before a battle is resolved, the clocks of the players and the speedrun timer, run in
+//! [`GameLogicStage`] a fixed number of times per second whatever the frame rate. Each run
+//! advances their timers by [`LOGIC_STEP`], so a slow machine catching up and a fast one play
+//! the same moves at the same game time.
+//!
+//! Events are only kept for two frames, so systems in the stage don't read events: they would
+//! miss some whenever a fast machine renders several frames between two steps.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::time::FixedTimestep;
+
+/// Game time advanced by every run of the [`GameLogicStage`], a sixtieth of a second
+pub const LOGIC_STEP: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Stage running after the update stage once per [`LOGIC_STEP`] of elapsed time, several times
+/// in a frame when the previous frame took longer than a step
+#[derive(StageLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GameLogicStage;
+
+/// Adds the [`GameLogicStage`] to `app`
+pub fn add_game_logic_stage(app: &mut App) {
+    app.add_stage_after(
+        CoreStage::Update,
+        GameLogicStage,
+        SystemStage::parallel().with_run_criteria(FixedTimestep::step(LOGIC_STEP.as_secs_f64())),
+    );
+}
@@ -1,32 +1,779 @@
+mod accessibility;
+mod ambient;
+mod api;
 mod app;
+mod autosave;
+mod balance;
+mod battle_log;
+mod blitz;
 mod board;
+mod bug_report;
+mod camera;
+mod chaos;
+mod chapters;
+mod clock;
+mod commentary;
+mod delta;
+mod dice_audit;
+mod diplomacy;
+mod display;
+mod editor;
 mod events;
+mod fair_dice;
 mod game;
+mod generators;
 mod geometry;
+mod gpu_picking;
+mod handshake;
 mod hex;
+mod hex_debug;
 mod highlights;
+mod hot_reload;
+mod idle;
+mod input;
+mod lobby;
+mod logic_step;
+mod map_file;
+mod memory_budget;
+mod menu;
+mod names;
+mod new_game;
+mod objectives;
+mod observation;
+mod odds;
+mod ownership_heatmap;
+mod pause;
+mod persistence;
+mod pings;
+mod player_stats;
+mod presence;
+mod presets;
+mod profiles;
+mod puzzle;
+mod recorder;
+mod relay;
+mod rematch;
+mod replay;
+mod replay_binary;
+mod rules;
+mod scenario;
+mod scene;
+mod screenshot;
+mod seats;
+mod session;
+mod simulation;
+mod snapshots;
+mod sounds;
+mod spectate;
+mod speedrun;
+mod stalemate;
+mod stamp;
+mod storage;
+mod telemetry;
+#[cfg(test)]
+mod test_support;
+mod threats;
+mod thumbnails;
 mod tiered_prng;
+mod timeline;
+mod turn_banner;
 mod ui;
+mod validation;
+mod versioning;
+mod zobrist;
 
+use std::path::Path;
+use std::time::Duration;
+
+use accessibility::AccessibilitySettings;
+use ambient::AmbientAnimation;
+use api::ApiServer;
 use app::build_app;
-use clap::Parser;
+use balance::{balance_report, grid, to_csv, to_markdown};
+use chapters::TimelineExport;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use display::{parse_resolution, DisplayMode, DisplaySettings};
+use fair_dice::FairDice;
+use game::{first_player, GameState};
+use generators::{board_generator, board_generators};
+use gpu_picking::PickingMode;
+use hot_reload::MapWatch;
+use idle::IdleSettings;
+use lobby::{join_target, parse_address, room_code, HostedGame};
+use map_file::{MapError, MapFile, MAX_PLAYERS};
+use memory_budget::{MemoryBudget, LOW_MEMORY_SNAPSHOTS};
+use menu::OpenMainMenu;
+use presence::RichPresence;
+use presets::{list_presets, load_preset, parse_preset_name, save_preset};
+use profiles::{
+    parse_named, Banner, DiceSkin, OutlineStyle, PlayerProfiles, ProfileRequest, BANNERS,
+    COLOR_NAMES, DICE_SKINS, OUTLINE_STYLES,
+};
+use puzzle::{Puzzle, PuzzleState};
 use rand::rngs::OsRng;
 use rand::RngCore;
+use recorder::BattleRecorder;
+use relay::RelayRoom;
+use rematch::Rematch;
+use replay_binary::{encode, read_replay, BINARY_REPLAY_EXTENSION};
+use rules::{GameRules, ReinforcementRule, TieRule, TransferRule};
+use scenario::{Scenario, ScenarioTriggers};
+use scene::BoardSceneSource;
+use seats::{BotDifficulty, BotPacing, RemoteConnection, SeatController, Seats};
+use session::MatchSession;
+use snapshots::SnapshotRing;
+use spectate::{join, spectate};
+use speedrun::{Speedrun, SPEEDRUN_PLAYER};
+use storage::{storage, storage_dir, Location};
+use telemetry::{Endpoint, Telemetry};
+use thumbnails::{cached_thumbnail, THUMBNAILS_DIR};
+use validation::validate_map;
 
 use bevy::prelude::*;
 
+// options given after a preset override the ones it saved
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_override_self = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Play the preset saved under this name, options given next to it override its own
+    #[arg(long, value_parser = parse_preset_name)]
+    preset: Option<String>,
+
+    /// Save the options of this game as a preset under this name, then play it
+    #[arg(long, value_parser = parse_preset_name)]
+    save_preset: Option<String>,
+
+    /// List the saved presets and exit
+    #[arg(long)]
+    list_presets: bool,
+
     #[arg(short, long, default_value_t = 0)]
     world_seed: u64,
 
     #[arg(short, long, default_value_t = 0)]
     env_seed: u64,
+
+    /// Number of players on a generated board
+    #[arg(short, long, default_value_t = 2)]
+    players: usize,
+
+    /// Scene file (relative to the assets folder) to load the board from
+    #[arg(short, long)]
+    board_scene: Option<String>,
+
+    /// Generator building the board, one of the names listed by `--list-generators`
+    #[arg(long, default_value = "patches")]
+    generator: String,
+
+    /// Size in hexes of the noise features shaping regions, with `--generator noise`
+    #[arg(long, default_value_t = 4.0)]
+    noise_scale: f32,
+
+    /// How much region sizes vary with `--generator noise`, from 0 (uniform) to 1
+    #[arg(long, default_value_t = 0.6)]
+    noise_strength: f32,
+
+    /// Minimum number of steps over the board between the regions different players start
+    /// growing in the same round of generation, zero places them anywhere
+    #[arg(long, default_value_t = 0)]
+    seed_spacing: usize,
+
+    /// List the available board generators and exit
+    #[arg(long)]
+    list_generators: bool,
+
+    /// Map file to play on instead of a generated board
+    #[arg(short, long)]
+    map: Option<String>,
+
+    /// Scenario file (a map file with triggers) to play
+    #[arg(long, conflicts_with = "map")]
+    scenario: Option<String>,
+
+    /// Puzzle file (a map file with a move limit) to solve
+    #[arg(long, conflicts_with_all = ["map", "scenario"])]
+    puzzle: Option<String>,
+
+    /// Fire a random global event every this many turns
+    #[arg(long, default_value_t = 0)]
+    chaos: usize,
+
+    /// Allow truces between players in games with three or more players
+    #[arg(long)]
+    diplomacy: bool,
+
+    /// Make regions conquered by the attacker sit out the rest of the turn instead of attacking
+    /// again
+    #[arg(long)]
+    no_momentum: bool,
+
+    /// Experimental: every round each player gives one attack order, then the orders resolve
+    /// together in initiative order
+    #[arg(long)]
+    blitz: bool,
+
+    /// Earn a die per region of the largest connected area at the end of each turn,
+    /// spread automatically or placed by hand
+    #[arg(long, value_enum, default_value_t = Reinforcements::None)]
+    reinforcements: Reinforcements,
+
+    /// Maximum number of earned dice a player can keep in reserve between turns
+    #[arg(long, default_value_t = 64)]
+    reserve_cap: usize,
+
+    /// Who wins a battle when both sides roll the same total
+    #[arg(long, value_enum, default_value_t = Ties::Defender)]
+    ties: Ties,
+
+    /// Extra dice the defender rolls on top of the dice in its region
+    #[arg(long, default_value_t = 0)]
+    defender_bonus: usize,
+
+    /// Dice an attacking region has to leave behind, rolling only the rest
+    #[arg(long, default_value_t = 0)]
+    garrison: usize,
+
+    /// How many of its dice the winner of a battle moves into the conquered region
+    #[arg(long, value_enum, default_value_t = Transfers::Random)]
+    transfer: Transfers,
+
+    /// Percentage of its dice the winner moves with `--transfer percentage`
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u8).range(1..=100))]
+    transfer_percent: u8,
+
+    /// Play a match of this many games, won by whoever wins the majority of them, or the most of
+    /// them once they are all played
+    #[arg(long, default_value_t = 1)]
+    best_of: usize,
+
+    /// Play rematches offered at the end of the game on the same board instead of a fresh one
+    #[arg(long)]
+    rematch_same_board: bool,
+
+    /// Total thinking time of each player in seconds, a player running out of time loses.
+    /// Zero disables the budget.
+    #[arg(long, default_value_t = 0)]
+    time_budget: u64,
+
+    /// End the game by territory count once no region changed hands for this many rounds, or once
+    /// the same position comes back a third time. Zero never calls a stalemate.
+    #[arg(long, default_value_t = 0)]
+    stalemate_rounds: usize,
+
+    /// Draw the player moving first from the world seed instead of always starting with the
+    /// first player
+    #[arg(long)]
+    random_first_player: bool,
+
+    /// Extra dice each player gets at the start for every seat it moves after the first player
+    #[arg(long, default_value_t = 0)]
+    compensation_dice: usize,
+
+    /// Remind a local player that did nothing for this many seconds to play, pulsing its
+    /// playable regions
+    #[arg(long)]
+    idle_reminder: Option<u64>,
+
+    /// Pass the turn of a reminded player that still did nothing after this many seconds
+    #[arg(long, requires = "idle_reminder")]
+    auto_pass: Option<u64>,
+
+    /// Save a short GIF clip of conquests in the clips folder, at most one every 15 seconds
+    #[arg(long)]
+    record_battles: bool,
+
+    /// Save a timestamped timeline of turns, battles and eliminations with chapter markers in the
+    /// timelines folder when a game is over, to line up with a recording of the session
+    #[arg(long)]
+    export_timeline: bool,
+
+    /// Discord application publishing the game status to the Discord profile, needs the
+    /// `discord` feature. Defaults to the DISCORD_APP_ID environment variable.
+    #[arg(long)]
+    discord_app_id: Option<String>,
+
+    /// Send anonymous summaries of finished games (board parameters, rules, winner, length) to
+    /// the telemetry endpoint, to help balance generators and rules. Off by default.
+    #[arg(long, requires = "telemetry_endpoint")]
+    telemetry: bool,
+
+    /// http:// URL receiving telemetry batches
+    #[arg(long, value_parser = Endpoint::parse)]
+    telemetry_endpoint: Option<Endpoint>,
+
+    /// Keep the camera still during battles, and the board still between moves
+    #[arg(long)]
+    reduced_motion: bool,
+
+    /// Draw the board in solid saturated colors with thick borders and dice counts, toggled with
+    /// F5
+    #[arg(long)]
+    high_contrast: bool,
+
+    /// Large print for playing on a TV across the room: bigger text and dice totals, and wider
+    /// borders between regions
+    #[arg(long)]
+    couch_mode: bool,
+
+    /// Window mode: windowed, borderless or fullscreen. Defaults to the saved display settings,
+    /// switched in game with F11.
+    #[arg(long, value_parser = parse_window_mode)]
+    window_mode: Option<DisplayMode>,
+
+    /// Size of the window, or resolution in fullscreen, as <width>x<height>
+    #[arg(long, value_parser = parse_resolution)]
+    resolution: Option<(u32, u32)>,
+
+    /// Turn vertical sync on or off
+    #[arg(long, value_parser = clap::builder::BoolishValueParser::new())]
+    vsync: Option<bool>,
+
+    /// Monitor to open the window on, counting from 0
+    #[arg(long)]
+    monitor: Option<usize>,
+
+    /// How the region under the cursor is found: raycast against the tiles, or gpu to read the
+    /// region ids back from an offscreen image, which also picks regions by their dice
+    #[arg(long, value_enum, default_value_t = Picking::Raycast)]
+    picking: Picking,
+
+    /// Save memory for old laptops: battles are rolled without the 3D dice views, the dice lose
+    /// their detailed textures and stacks past four dice are counted instead of drawn. Always on
+    /// in the browser.
+    #[arg(long)]
+    low_memory: bool,
+
+    /// Let a bot play this player (counting from 1), can be repeated
+    #[arg(long = "bot")]
+    bots: Vec<usize>,
+
+    /// How well bots play: easy bots attack at random, normal ones when they are likely to win,
+    /// hard ones only when they are very likely to
+    #[arg(long, value_enum, default_value_t = Difficulty::Easy)]
+    bot_difficulty: Difficulty,
+
+    /// Milliseconds bots take for every action, 0 plays instantly. Somewhere between 500 and
+    /// 2000 makes their games easy to follow.
+    #[arg(long, default_value_t = 500)]
+    bot_think_time: u64,
+
+    /// Serve the JSON API over WebSocket on this local port, needs the `api` feature
+    #[arg(long)]
+    api_port: Option<u16>,
+
+    /// Let a client of the API play this player (counting from 1), can be repeated
+    #[arg(long = "api-seat", requires = "api_port")]
+    api_seats: Vec<usize>,
+
+    /// Host a network game: show the address and join code players join the API seats with
+    #[arg(long, requires = "api_port")]
+    host: bool,
+
+    /// Serve the hosted game on every network interface instead of the local machine only, for
+    /// players on other machines. Anyone reaching the port can join.
+    #[arg(long, requires = "host")]
+    public: bool,
+
+    /// Host the network game on the relay at this `<host>:<port>` address, for players that
+    /// can't reach this machine. With `--spectate` or `--join`, the relay the game is hosted on.
+    #[arg(long, value_parser = parse_relay)]
+    relay: Option<String>,
+
+    /// Join the network game of this join code or `<host>:<port>` address, or of this room code
+    /// with `--relay`, and play the seat the host hands over
+    #[arg(
+        long,
+        conflicts_with_all = ["api_port", "map", "scenario", "puzzle", "board_scene", "spectate"]
+    )]
+    join: Option<String>,
+
+    /// Watch the network game of this join code or `<host>:<port>` address, or of this room
+    /// code with `--relay`, from its current position
+    #[arg(long, conflicts_with_all = ["api_port", "map", "scenario", "puzzle", "board_scene"])]
+    spectate: Option<String>,
+
+    /// Race the clock to conquer the board of the world seed against bots playing every other
+    /// player
+    #[arg(long, conflicts_with_all = ["map", "scenario", "board_scene", "best_of"])]
+    speedrun: bool,
+
+    /// Display name of the next player, in seat order, can be repeated
+    #[arg(long = "name")]
+    names: Vec<String>,
+
+    /// Color of the next player, in seat order, can be repeated. Taken colors are replaced by a
+    /// free one.
+    #[arg(long = "color", value_parser = parse_color)]
+    colors: Vec<usize>,
+
+    /// Dice of the next player, in seat order, can be repeated: classic, tinted or gilded
+    #[arg(long = "dice-skin", value_parser = parse_dice_skin)]
+    dice_skins: Vec<DiceSkin>,
+
+    /// Border of the regions of the next player, in seat order, can be repeated: thin, bold or
+    /// glow
+    #[arg(long = "outline", value_parser = parse_outline)]
+    outlines: Vec<OutlineStyle>,
+
+    /// Icon next to the name of the next player, in seat order, can be repeated: none, dagger,
+    /// lozenge, infinity, omega or arrow
+    #[arg(long = "banner", value_parser = parse_banner)]
+    banners: Vec<Banner>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check a map file and list its problems
+    Validate {
+        /// Map file to check
+        path: String,
+    },
+    /// Draw the preview images of map files and replays, unless they are up to date
+    Thumbnail {
+        /// Map files and replays to draw
+        paths: Vec<String>,
+    },
+    /// Simulate games for every combination of the given rules and print the win rate of every
+    /// seat and the average game length, for the number of players of `--players`
+    Balance {
+        /// Average dice per region to try, comma separated
+        #[arg(long, value_delimiter = ',', default_values_t = [3, 4, 5])]
+        dice_per_region: Vec<usize>,
+
+        /// Maximum dice per region to try, comma separated
+        #[arg(long, value_delimiter = ',', default_values_t = [6, 8])]
+        max_dice: Vec<usize>,
+
+        /// Tie rules to try, comma separated
+        #[arg(
+            long,
+            value_enum,
+            value_delimiter = ',',
+            default_values_t = [Ties::Defender, Ties::Attacker]
+        )]
+        ties: Vec<Ties>,
+
+        /// Games simulated for every combination
+        #[arg(long, default_value_t = 100)]
+        games: usize,
+
+        /// Print CSV instead of a markdown table
+        #[arg(long)]
+        csv: bool,
+    },
+    /// Convert a replay between the text format and the compressed binary format, binary when
+    /// the output ends in `.srdr`
+    Replay {
+        /// Replay to read, in either format
+        input: String,
+        /// Where to write the converted replay
+        output: String,
+    },
+    /// Relay network games for hosts and players that can't reach each other
+    Relay {
+        /// Port to relay games on
+        #[arg(long, default_value_t = 7800)]
+        port: u16,
+    },
+}
+
+fn parse_relay(value: &str) -> Result<String, String> {
+    parse_address(value).map_err(|err| err.to_string())
+}
+
+fn parse_color(value: &str) -> Result<usize, String> {
+    COLOR_NAMES
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(value))
+        .ok_or_else(|| format!("expected one of {}", COLOR_NAMES.join(", ")))
+}
+
+fn parse_dice_skin(value: &str) -> Result<DiceSkin, String> {
+    parse_named(&DICE_SKINS, value)
+}
+
+fn parse_outline(value: &str) -> Result<OutlineStyle, String> {
+    parse_named(&OUTLINE_STYLES, value)
+}
+
+fn parse_banner(value: &str) -> Result<Banner, String> {
+    parse_named(&BANNERS, value)
+}
+
+fn parse_window_mode(value: &str) -> Result<DisplayMode, String> {
+    DisplayMode::parse(value).ok_or_else(|| "expected windowed, borderless or fullscreen".into())
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Ties {
+    Defender,
+    Attacker,
+    Reroll,
+}
+
+impl From<Ties> for TieRule {
+    fn from(ties: Ties) -> Self {
+        match ties {
+            Ties::Defender => TieRule::DefenderWins,
+            Ties::Attacker => TieRule::AttackerWins,
+            Ties::Reroll => TieRule::Reroll,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Picking {
+    Raycast,
+    Gpu,
+}
+
+impl From<Picking> for PickingMode {
+    fn from(picking: Picking) -> Self {
+        match picking {
+            Picking::Raycast => PickingMode::Raycast,
+            Picking::Gpu => PickingMode::Gpu,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl From<Difficulty> for BotDifficulty {
+    fn from(difficulty: Difficulty) -> Self {
+        match difficulty {
+            Difficulty::Easy => BotDifficulty::Easy,
+            Difficulty::Normal => BotDifficulty::Normal,
+            Difficulty::Hard => BotDifficulty::Hard,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Transfers {
+    Random,
+    AllButOne,
+    Percentage,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Reinforcements {
+    None,
+    Automatic,
+    Manual,
+}
+
+impl From<Reinforcements> for ReinforcementRule {
+    fn from(reinforcements: Reinforcements) -> Self {
+        match reinforcements {
+            Reinforcements::None => ReinforcementRule::None,
+            Reinforcements::Automatic => ReinforcementRule::Automatic,
+            Reinforcements::Manual => ReinforcementRule::Manual,
+        }
+    }
+}
+
+fn read_file(path: &str) -> String {
+    std::fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("Failed to read {}: {}", path, err);
+        std::process::exit(1);
+    })
+}
+
+fn exit_invalid(path: &str, err: MapError) -> ! {
+    eprintln!("Invalid map {}: {}", path, err);
+    std::process::exit(1);
+}
+
+fn validate(path: &str) -> ! {
+    let diagnostics = validate_map(&read_file(path));
+    for diagnostic in diagnostics.iter() {
+        println!("{}: {}", path, diagnostic);
+    }
+
+    match diagnostics.is_empty() {
+        true => {
+            println!("{}: no problems found", path);
+            std::process::exit(0);
+        }
+        false => std::process::exit(1),
+    }
+}
+
+fn thumbnails(paths: &[String]) -> ! {
+    let profiles = PlayerProfiles::default();
+    let mut failed = false;
+    for path in paths {
+        match cached_thumbnail(
+            Path::new(path),
+            &storage_dir(Location::Cache).join(THUMBNAILS_DIR),
+            &profiles,
+        ) {
+            Ok(thumbnail) => println!("{}: {}", path, thumbnail.display()),
+            Err(err) => {
+                eprintln!("Failed to draw {}: {}", path, err);
+                failed = true;
+            }
+        }
+    }
+
+    std::process::exit(failed as i32);
+}
+
+fn convert_replay(input: &str, output: &str) -> ! {
+    let bytes = std::fs::read(input).unwrap_or_else(|err| {
+        exit_with(format!("Failed to read {}: {}", input, err));
+    });
+    let replay = read_replay(&bytes)
+        .unwrap_or_else(|err| exit_with(format!("Invalid replay {}: {}", input, err)));
+
+    let converted = match output.ends_with(BINARY_REPLAY_EXTENSION) {
+        true => encode(&replay).unwrap_or_else(|err| exit_with(err.to_string())),
+        false => replay.to_string().into_bytes(),
+    };
+    if let Err(err) = std::fs::write(output, &converted) {
+        exit_with(format!("Failed to write {}: {}", output, err));
+    }
+
+    println!(
+        "{}: {} moves, {} bytes",
+        output,
+        replay.moves.len(),
+        converted.len()
+    );
+    std::process::exit(0);
+}
+
+/// Rules of a game of `number_of_players` players set by the command line
+fn game_rules(args: &Args, number_of_players: usize) -> GameRules {
+    let mut rules = GameRules::with_players(number_of_players);
+    rules.board_generator = args.generator.clone();
+    rules.noise_scale = args.noise_scale;
+    rules.noise_strength = args.noise_strength;
+    rules.min_seed_spacing = args.seed_spacing;
+    rules.chaos_interval = args.chaos;
+    rules.diplomacy = args.diplomacy;
+    rules.momentum = !args.no_momentum;
+    rules.blitz = args.blitz;
+    rules.reinforcements = args.reinforcements.into();
+    rules.reserve_cap = args.reserve_cap;
+    rules.tie_rule = args.ties.into();
+    rules.defender_bonus_dice = args.defender_bonus;
+    rules.garrison = args.garrison;
+    rules.transfer_rule = match args.transfer {
+        Transfers::Random => TransferRule::Random,
+        Transfers::AllButOne => TransferRule::AllButOne,
+        Transfers::Percentage => TransferRule::Percentage(args.transfer_percent as usize),
+    };
+    rules.time_budget_secs = args.time_budget;
+    rules.stalemate_rounds = args.stalemate_rounds;
+    rules.random_first_player = args.random_first_player;
+    rules.compensation_dice = args.compensation_dice;
+    rules
+}
+
+fn balance(
+    base_rules: &GameRules,
+    dice_per_region: &[usize],
+    max_dice: &[usize],
+    ties: &[Ties],
+    games: usize,
+    csv: bool,
+) -> ! {
+    if !(2..=MAX_PLAYERS).contains(&base_rules.number_of_players) {
+        eprintln!("Number of players must be between 2 and {}", MAX_PLAYERS);
+        std::process::exit(1);
+    }
+
+    let tie_rules: Vec<TieRule> = ties.iter().map(|ties| (*ties).into()).collect();
+    let cells = grid(dice_per_region, max_dice, &tie_rules);
+    let rows = balance_report(base_rules, &cells, games);
+    match csv {
+        true => print!("{}", to_csv(&rows)),
+        false => print!("{}", to_markdown(&rows)),
+    }
+
+    std::process::exit(0);
+}
+
+fn serve_relay(port: u16) -> ! {
+    match relay::serve(port) {
+        Ok(()) => std::process::exit(0),
+        Err(err) => exit_with(format!("Failed to relay games on port {}: {}", port, err)),
+    }
+}
+
+/// Whether the option `flag` of the command line is followed by a value
+fn takes_value(flag: &str) -> bool {
+    Args::command().get_arguments().any(|arg| {
+        let long = arg.get_long().map(|long| format!("--{}", long));
+        let short = arg.get_short().map(|short| format!("-{}", short));
+        (long.as_deref() == Some(flag) || short.as_deref() == Some(flag))
+            && arg.get_action().takes_values()
+    })
+}
+
+fn exit_with(err: String) -> ! {
+    eprintln!("{}", err);
+    std::process::exit(1);
 }
 
 fn main() {
     let mut args = Args::parse();
+    if args.list_presets {
+        for name in list_presets(storage(Location::Config).as_ref()) {
+            println!("{}", name);
+        }
+        return;
+    }
+
+    let mut command_line: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(name) = args.preset.clone() {
+        let mut preset = load_preset(storage(Location::Config).as_ref(), &name)
+            .unwrap_or_else(|err| exit_with(err));
+        preset.append(&mut command_line);
+        command_line = preset;
+        args = Args::parse_from(std::env::args().take(1).chain(command_line.iter().cloned()));
+    }
+    if let Some(name) = args.save_preset.as_ref() {
+        save_preset(
+            storage(Location::Config).as_ref(),
+            name,
+            &command_line,
+            takes_value,
+        )
+        .unwrap_or_else(|err| exit_with(err));
+        println!("Saved preset {}", name);
+    }
+    match &args.command {
+        Some(Command::Validate { path }) => validate(path),
+        Some(Command::Thumbnail { paths }) => thumbnails(paths),
+        Some(Command::Balance {
+            dice_per_region,
+            max_dice,
+            ties,
+            games,
+            csv,
+        }) => balance(
+            &game_rules(&args, args.players),
+            dice_per_region,
+            max_dice,
+            ties,
+            *games,
+            *csv,
+        ),
+        Some(Command::Replay { input, output }) => convert_replay(input, output),
+        Some(Command::Relay { port }) => serve_relay(*port),
+        None => {}
+    }
+
     if args.world_seed == 0 || args.env_seed == 0 {
         let mut key = [0u8; 16];
         OsRng.fill_bytes(&mut key);
@@ -42,7 +789,215 @@ fn main() {
         }
     }
 
+    if args.list_generators {
+        for generator in board_generators() {
+            println!("{}", generator.name());
+        }
+        return;
+    }
+    if board_generator(&args.generator).is_none() {
+        eprintln!(
+            "Unknown board generator {}, see --list-generators",
+            args.generator
+        );
+        std::process::exit(1);
+    }
+
+    if !(2..=MAX_PLAYERS).contains(&args.players) {
+        eprintln!("Number of players must be between 2 and {}", MAX_PLAYERS);
+        std::process::exit(1);
+    }
+
+    let map_path = args.map.clone();
+    let mut map = args
+        .map
+        .as_ref()
+        .map(|path| MapFile::parse(&read_file(path)).unwrap_or_else(|err| exit_invalid(path, err)));
+
+    let scenario = args.scenario.as_ref().map(|path| {
+        Scenario::parse(&read_file(path)).unwrap_or_else(|err| exit_invalid(path, err))
+    });
+    let triggers = scenario.map(|scenario| {
+        map = Some(scenario.map);
+        ScenarioTriggers::new(scenario.triggers)
+    });
+
+    let puzzle = args
+        .puzzle
+        .as_ref()
+        .map(|path| Puzzle::parse(&read_file(path)).unwrap_or_else(|err| exit_invalid(path, err)));
+    if let Some(puzzle) = puzzle.as_ref() {
+        map = Some(puzzle.map.clone());
+    }
+
+    if args.relay.is_some() && !args.host && args.spectate.is_none() && args.join.is_none() {
+        exit_with("--relay needs --host, --spectate or --join".to_string());
+    }
+    // a joined game is followed like a spectated one, with a seat of its own
+    let spectator_stream = match (args.spectate.as_ref(), args.join.as_ref()) {
+        (Some(target), _) | (None, Some(target)) => {
+            let target = join_target(target, args.relay.as_deref())
+                .unwrap_or_else(|err| exit_with(err.to_string()));
+            let stream = match args.join.is_some() {
+                true => join(&target),
+                false => spectate(&target),
+            };
+            Some(stream.unwrap_or_else(|err| exit_with(err.to_string())))
+        }
+        (None, None) => None,
+    };
+
+    let number_of_players = match spectator_stream.as_ref() {
+        Some(stream) => stream.board().game_state.number_of_players,
+        None => map
+            .as_ref()
+            .map_or(args.players, |map| map.number_of_players),
+    };
+
+    let mut display = DisplaySettings::load();
+    display.mode = args.window_mode.unwrap_or(display.mode);
+    display.resolution = args.resolution.unwrap_or(display.resolution);
+    display.vsync = args.vsync.unwrap_or(display.vsync);
+    display.monitor = args.monitor.or(display.monitor);
+
     let app = &mut App::new();
-    build_app(app, args.world_seed, args.env_seed, 2, false);
+    app.insert_resource(display);
+    app.insert_resource(PickingMode::from(args.picking));
+    let budget = MemoryBudget::new(args.low_memory);
+    app.insert_resource(budget);
+    if budget == MemoryBudget::Low {
+        app.insert_resource(SnapshotRing::new(LOW_MEMORY_SNAPSHOTS));
+    }
+    // without options the game opens on the main menu to set it up
+    let open_main_menu = OpenMainMenu(command_line.is_empty());
+    app.insert_resource(open_main_menu);
+    let rules = game_rules(&args, number_of_players);
+    // build_app generates the board with these rules
+    app.insert_resource(rules.clone());
+    build_app(
+        app,
+        args.world_seed,
+        args.env_seed,
+        number_of_players,
+        false,
+    );
+
+    let mut game_state = app.world.resource_mut::<GameState>();
+    match (spectator_stream.as_ref(), map) {
+        (Some(stream), _) => *game_state = stream.board().game_state.clone(),
+        // the setup screen generates the board once the players are chosen
+        (None, None) if open_main_menu.0 => {}
+        (None, map) => {
+            if let Some(map) = map {
+                game_state.board = map.board;
+            }
+            game_state.seat_players(first_player(&rules, args.world_seed), &rules);
+        }
+    }
+
+    if let Some(triggers) = triggers {
+        app.insert_resource(triggers);
+    }
+    if let Some(path) = map_path.filter(|_| cfg!(feature = "hot-reload")) {
+        app.insert_resource(MapWatch::new(path));
+    }
+    if let Some(puzzle) = puzzle {
+        app.insert_resource(PuzzleState::new(puzzle));
+    }
+    app.insert_resource(Rematch::new(args.rematch_same_board));
+    if args.best_of > 1 {
+        app.insert_resource(MatchSession::new(args.best_of, number_of_players));
+    }
+    if let Some(player) = args
+        .bots
+        .iter()
+        .chain(args.api_seats.iter())
+        .find(|player| !(1..=number_of_players).contains(player))
+    {
+        eprintln!("There is no player {} to hand to a bot or the API", player);
+        std::process::exit(1);
+    }
+    let controllers = (0..number_of_players)
+        .map(|player| {
+            // in a speedrun every other player is a bot
+            let bot =
+                args.bots.contains(&(player + 1)) || (args.speedrun && player != SPEEDRUN_PLAYER);
+            let remote = args.api_seats.contains(&(player + 1));
+            match (remote, bot) {
+                (true, _) => SeatController::Remote(RemoteConnection::default()),
+                (false, true) => SeatController::Bot(
+                    BotDifficulty::from(args.bot_difficulty)
+                        .bot(args.env_seed.wrapping_add(player as u64)),
+                ),
+                (false, false) => SeatController::LocalHuman,
+            }
+        })
+        .collect();
+    // spectators watch every seat played elsewhere, players joining play the one handed over
+    match spectator_stream.as_ref() {
+        Some(stream) => app.insert_resource(stream.seats()),
+        None => app.insert_resource(Seats { controllers }),
+    };
+    app.insert_resource(BotPacing {
+        think_time: Duration::from_millis(args.bot_think_time),
+    });
+    let requests: Vec<ProfileRequest> = (0..number_of_players)
+        .map(|player| ProfileRequest {
+            name: args.names.get(player).cloned(),
+            color: args.colors.get(player).copied(),
+            dice_skin: args.dice_skins.get(player).copied(),
+            outline: args.outlines.get(player).copied(),
+            banner: args.banners.get(player).copied(),
+        })
+        .collect();
+    app.insert_resource(PlayerProfiles::new(&requests, number_of_players));
+    app.insert_resource(AccessibilitySettings {
+        reduced_motion: args.reduced_motion,
+        high_contrast: args.high_contrast,
+        couch_mode: args.couch_mode,
+    });
+    app.insert_resource(AmbientAnimation::new(!args.reduced_motion));
+
+    app.insert_resource(IdleSettings {
+        reminder: args.idle_reminder.map(Duration::from_secs),
+        auto_pass: args.auto_pass.map(Duration::from_secs),
+    });
+
+    app.insert_resource(BattleRecorder::new(args.record_battles));
+    app.insert_resource(TimelineExport::new(args.export_timeline));
+
+    if let Some(app_id) = args
+        .discord_app_id
+        .or_else(|| std::env::var("DISCORD_APP_ID").ok())
+    {
+        app.insert_resource(RichPresence::start(app_id));
+    }
+
+    if let (true, Some(endpoint)) = (args.telemetry, args.telemetry_endpoint) {
+        app.insert_resource(Telemetry::enabled(endpoint));
+    }
+
+    if let Some(port) = args.api_port {
+        let relay = args.relay.map(|relay| RelayRoom {
+            relay,
+            room: room_code(&mut rand::thread_rng()),
+        });
+        app.insert_resource(ApiServer::start(port, args.public, relay.clone()));
+        if args.host {
+            app.insert_resource(HostedGame::new(port, args.public, relay));
+            app.insert_resource(FairDice::random());
+        }
+    }
+
+    if args.speedrun {
+        app.insert_resource(Speedrun::new(args.world_seed));
+    }
+
+    if let Some(board_scene) = args.board_scene {
+        app.insert_resource(BoardSceneSource(board_scene));
+    }
+    if let Some(stream) = spectator_stream {
+        app.insert_resource(stream);
+    }
     app.run();
 }
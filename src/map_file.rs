@@ -0,0 +1,387 @@
+//! Plain text map format for handcrafted and community-shared boards.
+//!
+//! ```text
+//! # comments start with a hash
+//! # format version, see crate::versioning
+//! version 1
+//! players 2
+//! # region <owner> <dice> <q>,<r> <q>,<r> ...
+//! region 0 3 0,0 1,0 1,-1
+//! region 1 2 -1,0 -1,1
+//! # regions that count as objectives on king-of-the-hill maps
+//! objective 1
+//! # name <region> <territory name>
+//! name 0 Karthac
+//! ```
+//!
+//! Regions get their ids in the order they are listed.
+
+use std::fmt;
+
+use crate::game::{Board, Region};
+use crate::versioning::{from_unversioned, migrate, Migration, VersionError};
+
+/// Directory of the data directory the setup screen lists map files from
+pub const MAPS_DIR: &str = "maps";
+
+/// Upper bound on the number of players a map may declare
+pub const MAX_PLAYERS: usize = 8;
+
+/// Upper bound on the number of dice in a single region
+pub const MAX_DICE: usize = 8;
+
+/// Migrations of older map files, the `n`th one turns version `n` into version `n + 1`
+const MIGRATIONS: &[Migration] = &[from_unversioned];
+
+/// Version of the map files written by this release
+pub const VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// A parsed map file
+#[derive(Clone, Debug)]
+pub struct MapFile {
+    pub number_of_players: usize,
+    pub board: Board,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapErrorKind {
+    UnknownDirective(String),
+    MissingPlayers,
+    DuplicatePlayers,
+    MissingValue(&'static str),
+    InvalidNumber(String),
+    InvalidCoordinate(String),
+    PlayersOutOfRange(usize),
+    OwnerOutOfRange(usize),
+    DiceOutOfRange(usize),
+    EmptyRegion,
+    OverlappingHex((isize, isize)),
+    NoRegions,
+    InvalidTrigger(String),
+    RegionOutOfRange(usize),
+    DuplicateName(usize),
+    DuplicateObjective(usize),
+    InvalidGoal(String),
+    Version(VersionError),
+}
+
+/// Error found while parsing a map file, with the 1-based line it was found on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapError {
+    pub line: usize,
+    pub kind: MapErrorKind,
+}
+
+impl fmt::Display for MapErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapErrorKind::UnknownDirective(directive) => {
+                write!(f, "unknown directive `{}`", directive)
+            }
+            MapErrorKind::MissingPlayers => write!(f, "`players` must be declared before regions"),
+            MapErrorKind::DuplicatePlayers => write!(f, "`players` is declared more than once"),
+            MapErrorKind::MissingValue(name) => write!(f, "missing {}", name),
+            MapErrorKind::InvalidNumber(value) => write!(f, "`{}` is not a valid number", value),
+            MapErrorKind::InvalidCoordinate(value) => {
+                write!(f, "`{}` is not a valid `q,r` hex coordinate", value)
+            }
+            MapErrorKind::PlayersOutOfRange(players) => write!(
+                f,
+                "{} players declared, expected 2 to {}",
+                players, MAX_PLAYERS
+            ),
+            MapErrorKind::OwnerOutOfRange(owner) => {
+                write!(f, "owner {} is not one of the declared players", owner)
+            }
+            MapErrorKind::DiceOutOfRange(dice) => {
+                write!(f, "{} dice in a region, expected 1 to {}", dice, MAX_DICE)
+            }
+            MapErrorKind::EmptyRegion => write!(f, "region has no hexes"),
+            MapErrorKind::OverlappingHex((q, r)) => {
+                write!(f, "hex {},{} belongs to more than one region", q, r)
+            }
+            MapErrorKind::NoRegions => write!(f, "map has no regions"),
+            MapErrorKind::InvalidTrigger(trigger) => write!(f, "invalid trigger `{}`", trigger),
+            MapErrorKind::RegionOutOfRange(region) => {
+                write!(f, "region {} doesn't exist", region)
+            }
+            MapErrorKind::InvalidGoal(goal) => write!(f, "invalid goal `{}`", goal),
+            MapErrorKind::DuplicateName(region) => {
+                write!(f, "region {} is named more than once", region)
+            }
+            MapErrorKind::DuplicateObjective(region) => {
+                write!(f, "region {} is an objective more than once", region)
+            }
+            MapErrorKind::Version(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for MapError {}
+
+pub(crate) fn parse_number<T: std::str::FromStr>(
+    value: Option<&str>,
+    name: &'static str,
+) -> Result<T, MapErrorKind> {
+    let value = value.ok_or(MapErrorKind::MissingValue(name))?;
+    value
+        .parse()
+        .map_err(|_| MapErrorKind::InvalidNumber(value.to_string()))
+}
+
+pub(crate) fn parse_coordinate(value: &str) -> Result<(isize, isize), MapErrorKind> {
+    let invalid = || MapErrorKind::InvalidCoordinate(value.to_string());
+    let (q, r) = value.split_once(',').ok_or_else(invalid)?;
+
+    Ok((
+        q.trim().parse().map_err(|_| invalid())?,
+        r.trim().parse().map_err(|_| invalid())?,
+    ))
+}
+
+impl MapFile {
+    pub fn parse(source: &str) -> Result<MapFile, MapError> {
+        let source = migrate(source, MIGRATIONS).map_err(|(line, err)| MapError {
+            line,
+            kind: MapErrorKind::Version(err),
+        })?;
+        let mut number_of_players: Option<usize> = None;
+        let mut board = Board::default();
+        let mut objective_lines = Vec::new();
+        let mut names: Vec<(usize, usize, String)> = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let error = |kind| MapError {
+                line: index + 1,
+                kind,
+            };
+
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut tokens = line.split_whitespace();
+
+            match tokens.next() {
+                // checked by the migration
+                None | Some("version") => continue,
+                Some("players") => {
+                    if number_of_players.is_some() {
+                        return Err(error(MapErrorKind::DuplicatePlayers));
+                    }
+
+                    let players: usize =
+                        parse_number(tokens.next(), "number of players").map_err(error)?;
+                    if !(2..=MAX_PLAYERS).contains(&players) {
+                        return Err(error(MapErrorKind::PlayersOutOfRange(players)));
+                    }
+
+                    number_of_players = Some(players);
+                }
+                Some("region") => {
+                    let players = number_of_players.ok_or(error(MapErrorKind::MissingPlayers))?;
+
+                    let owner: usize =
+                        parse_number(tokens.next(), "region owner").map_err(error)?;
+                    if owner >= players {
+                        return Err(error(MapErrorKind::OwnerOutOfRange(owner)));
+                    }
+
+                    let num_dice: usize =
+                        parse_number(tokens.next(), "number of dice").map_err(error)?;
+                    if !(1..=MAX_DICE).contains(&num_dice) {
+                        return Err(error(MapErrorKind::DiceOutOfRange(num_dice)));
+                    }
+
+                    let mut hexes = Vec::new();
+                    for token in tokens {
+                        let hex = parse_coordinate(token).map_err(error)?;
+                        if board.hexes.insert(hex, owner).is_some() {
+                            return Err(error(MapErrorKind::OverlappingHex(hex)));
+                        }
+                        hexes.push(hex);
+                    }
+
+                    if hexes.is_empty() {
+                        return Err(error(MapErrorKind::EmptyRegion));
+                    }
+
+                    board.regions.push(Region {
+                        hexes,
+                        owner,
+                        num_dice,
+                        id: board.regions.len(),
+                        has_moved: false,
+                        name: String::new(),
+                    });
+                }
+                Some("objective") => {
+                    let region: usize = parse_number(tokens.next(), "region").map_err(error)?;
+                    objective_lines.push(index + 1);
+                    board.objectives.push(region);
+                }
+                Some("name") => {
+                    let region: usize = parse_number(tokens.next(), "region").map_err(error)?;
+                    let name = tokens.collect::<Vec<_>>().join(" ");
+                    if name.is_empty() {
+                        return Err(error(MapErrorKind::MissingValue("region name")));
+                    }
+                    names.push((index + 1, region, name));
+                }
+                Some(directive) => {
+                    return Err(error(MapErrorKind::UnknownDirective(directive.to_string())));
+                }
+            }
+        }
+
+        let last_line = source.lines().count().max(1);
+        let number_of_players = number_of_players.ok_or(MapError {
+            line: last_line,
+            kind: MapErrorKind::MissingPlayers,
+        })?;
+
+        if board.regions.is_empty() {
+            return Err(MapError {
+                line: last_line,
+                kind: MapErrorKind::NoRegions,
+            });
+        }
+
+        for (index, (line, region)) in objective_lines
+            .iter()
+            .zip(board.objectives.iter())
+            .enumerate()
+        {
+            if *region >= board.regions.len() {
+                return Err(MapError {
+                    line: *line,
+                    kind: MapErrorKind::RegionOutOfRange(*region),
+                });
+            }
+            // a region listed twice would count twice towards the majority
+            if board.objectives[..index].contains(region) {
+                return Err(MapError {
+                    line: *line,
+                    kind: MapErrorKind::DuplicateObjective(*region),
+                });
+            }
+        }
+
+        for (line, region, name) in names {
+            let error = |kind| MapError { line, kind };
+            let region = board
+                .regions
+                .get_mut(region)
+                .ok_or(error(MapErrorKind::RegionOutOfRange(region)))?;
+            if !region.name.is_empty() {
+                return Err(error(MapErrorKind::DuplicateName(region.id)));
+            }
+            region.name = name;
+        }
+
+        Ok(MapFile {
+            number_of_players,
+            board,
+        })
+    }
+}
+
+impl fmt::Display for MapFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "version {}", VERSION)?;
+        writeln!(f, "players {}", self.number_of_players)?;
+        for region in self.board.regions.iter() {
+            write!(f, "region {} {}", region.owner, region.num_dice)?;
+            for (q, r) in region.hexes.iter() {
+                write!(f, " {},{}", q, r)?;
+            }
+            writeln!(f)?;
+        }
+        for region in self.board.objectives.iter() {
+            writeln!(f, "objective {}", region)?;
+        }
+        for region in self.board.regions.iter() {
+            if !region.name.is_empty() {
+                writeln!(f, "name {} {}", region.id, region.name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::generate_board;
+    use crate::rules::GameRules;
+    use crate::tiered_prng::get_randomness;
+
+    #[test]
+    fn round_trip_generated_board() {
+        let rules = GameRules::default();
+        let map = MapFile {
+            number_of_players: rules.number_of_players,
+            board: generate_board(&rules, get_randomness(4242)),
+        };
+
+        let parsed = MapFile::parse(&map.to_string()).unwrap();
+        assert_eq!(parsed.to_string(), map.to_string());
+        assert_eq!(parsed.board.hexes, map.board.hexes);
+    }
+
+    #[test]
+    fn reports_line_of_error() {
+        let source = "players 2\n\nregion 0 3 0,0\nregion 2 1 1,0\n";
+        let error = MapFile::parse(source).unwrap_err();
+        assert_eq!(error.line, 4);
+        assert_eq!(error.kind, MapErrorKind::OwnerOutOfRange(2));
+    }
+
+    #[test]
+    fn parses_region_names() {
+        let source = "version 1\nplayers 2\nregion 0 3 0,0\nregion 1 1 1,0\nname 1 Upper Karthac\n";
+        let map = MapFile::parse(source).unwrap();
+        assert_eq!(map.board.regions[0].label(), "Region 0");
+        assert_eq!(map.board.regions[1].label(), "Upper Karthac");
+        assert_eq!(map.to_string(), source);
+
+        let error = MapFile::parse("players 2\nregion 0 3 0,0\nname 3 Karthac\n").unwrap_err();
+        assert_eq!(error.line, 3);
+        assert_eq!(error.kind, MapErrorKind::RegionOutOfRange(3));
+    }
+
+    #[test]
+    fn loads_older_versions() {
+        let unversioned = MapFile::parse("players 2\nregion 0 3 0,0\nregion 1 1 1,0\n").unwrap();
+        assert!(unversioned.to_string().starts_with("version 1\n"));
+
+        let error = MapFile::parse("# from the future\nversion 9\nplayers 2\n").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(
+            error.kind,
+            MapErrorKind::Version(VersionError::UnsupportedVersion {
+                found: 9,
+                supported: VERSION
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_objectives() {
+        let source = "players 2\nregion 0 3 0,0\nregion 1 1 1,0\nobjective 1\nobjective 1\n";
+        let error = MapFile::parse(source).unwrap_err();
+        assert_eq!(error.line, 5);
+        assert_eq!(error.kind, MapErrorKind::DuplicateObjective(1));
+    }
+
+    #[test]
+    fn rejects_overlapping_hexes() {
+        let source = "players 2\nregion 0 3 0,0 1,0\nregion 1 1 1,0\n";
+        let error = MapFile::parse(source).unwrap_err();
+        assert_eq!(error.kind, MapErrorKind::OverlappingHex((1, 0)));
+    }
+}
@@ -0,0 +1,104 @@
+//! Memory budget for old laptops and the browser build, lowered with `--low-memory` and always
+//! low in the browser.
+//!
+//! The low budget leaves out what takes the most memory for the least gameplay:
+//!
+//! - the dice views and their render targets: [`roll_numeric_dice`] rolls the battles instead of
+//!   the dice plugin, and only the totals are shown
+//! - the normal and metallic-roughness textures of the dice, which keep their base color
+//! - the beveled meshes of the tiles: every region is drawn with the flat mesh it's picked with
+//! - the dice models past the first stack of a region, a label over the stack counts them instead
+//! - most of the snapshots kept to rewind the game
+//!
+//! The budget is chosen before the app is built, the plugins read it to add the dice plugin or
+//! not.
+
+use bevy::prelude::*;
+use bevy_dice::{DiceRollResult, DiceRollStartEvent};
+use rand::Rng;
+
+use crate::tiered_prng::PrngEnvResource;
+
+/// Dice models stacked on a region with the low budget, a single stack
+pub const LOW_MEMORY_DICE_PER_REGION: usize = 4;
+
+/// Snapshots kept with the low budget
+pub const LOW_MEMORY_SNAPSHOTS: usize = 4;
+
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoryBudget {
+    #[default]
+    Full,
+    Low,
+}
+
+impl MemoryBudget {
+    /// Low for `--low-memory` and in the browser
+    pub fn new(low_memory: bool) -> Self {
+        match low_memory || cfg!(target_arch = "wasm32") {
+            true => MemoryBudget::Low,
+            false => MemoryBudget::Full,
+        }
+    }
+
+    /// Whether battles are thrown by the dice plugin and shown in its views
+    pub fn dice_views(self) -> bool {
+        self == MemoryBudget::Full
+    }
+
+    /// Whether the dice get their normal and metallic-roughness textures
+    pub fn detailed_textures(self) -> bool {
+        self == MemoryBudget::Full
+    }
+
+    /// Whether the tiles get a flat mesh of their own to be picked with, rather than being picked
+    /// against their rendered mesh
+    pub fn pick_meshes(self) -> bool {
+        self == MemoryBudget::Full
+    }
+
+    /// Dice models stacked on a region holding `num_dice`
+    pub fn dice_shown(self, num_dice: usize) -> usize {
+        match self {
+            MemoryBudget::Full => num_dice,
+            MemoryBudget::Low => num_dice.min(LOW_MEMORY_DICE_PER_REGION),
+        }
+    }
+}
+
+/// Rolls the dice of every battle with the environment randomness, in place of the dice plugin
+pub(crate) fn roll_numeric_dice(
+    mut dice_roll_started_reader: EventReader<DiceRollStartEvent>,
+    mut dice_roll_result_writer: EventWriter<DiceRollResult>,
+    mut env_prng: ResMut<PrngEnvResource>,
+) {
+    for event in dice_roll_started_reader.iter() {
+        let values = event
+            .num_dice
+            .iter()
+            .map(|num_dice| {
+                (0..*num_dice)
+                    .map(|_| env_prng.rng.gen_range(1..=6))
+                    .collect()
+            })
+            .collect();
+
+        dice_roll_result_writer.send(DiceRollResult { values });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_budget_caps_the_dice_shown() {
+        assert_eq!(MemoryBudget::Full.dice_shown(8), 8);
+        assert_eq!(MemoryBudget::Low.dice_shown(8), LOW_MEMORY_DICE_PER_REGION);
+        assert_eq!(MemoryBudget::Low.dice_shown(2), 2);
+
+        assert!(MemoryBudget::Full.dice_views());
+        assert!(!MemoryBudget::Low.detailed_textures());
+        assert_eq!(MemoryBudget::new(true), MemoryBudget::Low);
+    }
+}
@@ -0,0 +1,929 @@
+//! Main menu and the states of the app around a game.
+//!
+//! Launched without options, the game opens on the [`AppState::MainMenu`]. From there the
+//! [`AppState::Setup`] screen picks the number of players, the size of the board and how well
+//! the bots play before the board is generated and the game starts in [`AppState::InGame`]. It
+//! can also pick one of the map files in [`MAPS_DIR`] of the data directory, shown by its
+//! thumbnail (see [`crate::thumbnails`]), which is played instead of a generated board, or one
+//! of the saved presets (see [`crate::presets`]), which starts the game again with its options.
+//! Player 1 plays with the mouse, every other seat is a bot. [`AppState::Join`] joins a game
+//! hosted on another machine instead, see [`crate::lobby`], and [`AppState::Settings`] turns the
+//! [`AccessibilitySettings`] on and off and changes the [`DisplaySettings`]. Once the game is over the app is in
+//! [`AppState::GameOver`] until a rematch starts, or `M` goes back to the main menu.
+//!
+//! Launched with options, the game starts right away in [`AppState::InGame`] as the options
+//! tell. Launched without, there is no board until the setup is done. The game stands paused
+//! while the menus hide the board.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::winit::WinitWindows;
+use rand::RngCore;
+
+use crate::accessibility::AccessibilitySettings;
+use crate::ambient::AmbientAnimation;
+use crate::board::BoardDrawer;
+use crate::display::{monitor_count, DisplaySettings};
+use crate::events::EventGameOver;
+use crate::game::{first_player, GameState};
+use crate::map_file::{MapFile, MAPS_DIR, MAX_PLAYERS};
+use crate::new_game::NewGame;
+use crate::pause::Pause;
+use crate::presets::list_presets;
+use crate::profiles::PlayerProfiles;
+use crate::rematch::{GameOverScreen, Rematch};
+use crate::rules::GameRules;
+use crate::seats::{BotDifficulty, SeatController, Seats};
+use crate::session::{new_game_state, MatchSession};
+use crate::spectate::SpectatorStream;
+use crate::storage::{storage, storage_dir, Location};
+use crate::thumbnails::{cached_thumbnail, Thumbnail, THUMBNAILS_DIR};
+use crate::tiered_prng::{get_randomness, PrngResource};
+
+/// Key going back to the main menu from the game-over screen
+const MAIN_MENU_KEY: KeyCode = KeyCode::M;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AppState {
+    MainMenu,
+    /// Choosing the players, board and bots of a new game
+    Setup,
+    /// Typing the join code or address of a network game
+    Join,
+    /// Turning the accessibility settings on and off, changing the display settings
+    Settings,
+    InGame,
+    GameOver,
+}
+
+/// Whether the app opens on the main menu instead of starting a game, read when the app is built
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OpenMainMenu(pub bool);
+
+impl OpenMainMenu {
+    /// State the app starts in
+    pub fn initial_state(self) -> AppState {
+        match self.0 {
+            true => AppState::MainMenu,
+            false => AppState::InGame,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoardSize {
+    Small,
+    Medium,
+    Large,
+}
+
+impl BoardSize {
+    /// Board size of the rules
+    pub fn rows(self) -> isize {
+        match self {
+            BoardSize::Small => 14,
+            BoardSize::Medium => 20,
+            BoardSize::Large => 28,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BoardSize::Small => "small",
+            BoardSize::Medium => "medium",
+            BoardSize::Large => "large",
+        }
+    }
+}
+
+/// Lines of the setup screen
+const SETUP_FIELDS: usize = 5;
+
+/// Width and height of the thumbnail of the picked map on the setup screen, in pixels
+const MAP_THUMBNAIL_SIZE: f32 = 192.0;
+
+/// Game picked on the setup screen
+#[derive(Resource, Clone, Debug, PartialEq, Eq)]
+pub struct GameSetup {
+    pub number_of_players: usize,
+    pub board_size: BoardSize,
+    pub difficulty: BotDifficulty,
+    /// Map files that can be picked instead of a generated board
+    pub maps: Vec<PathBuf>,
+    /// Index in `maps` of the map picked, a generated board when `None`
+    pub map: Option<usize>,
+    /// Names of the saved presets
+    pub presets: Vec<String>,
+    /// Index in `presets` of the preset picked, whose options replace the other lines
+    pub preset: Option<usize>,
+    /// Line of the setup screen being changed
+    selected: usize,
+}
+
+impl Default for GameSetup {
+    fn default() -> Self {
+        GameSetup {
+            number_of_players: 4,
+            board_size: BoardSize::Medium,
+            difficulty: BotDifficulty::Normal,
+            maps: Vec::new(),
+            map: None,
+            presets: Vec::new(),
+            preset: None,
+            selected: 0,
+        }
+    }
+}
+
+/// Picks the next of `len` choices `step` choices after `pick`, where nothing picked comes before
+/// the first choice
+fn cycle_pick(pick: Option<usize>, len: usize, step: isize) -> Option<usize> {
+    let index = pick.map_or(0, |pick| pick as isize + 1);
+    match (index + step).rem_euclid(len as isize + 1) {
+        0 => None,
+        index => Some(index as usize - 1),
+    }
+}
+
+/// Map files in `dir`, sorted by name, none when the directory can't be read
+pub fn list_maps(dir: &Path) -> Vec<PathBuf> {
+    let mut maps: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    maps.sort();
+
+    maps
+}
+
+impl GameSetup {
+    /// Moves the selection `step` lines down, up for negative steps
+    pub fn select(&mut self, step: isize) {
+        self.selected = (self.selected as isize + step).rem_euclid(SETUP_FIELDS as isize) as usize;
+    }
+
+    /// Changes the selected line `step` values forward, back for negative steps
+    pub fn change(&mut self, step: isize) {
+        const SIZES: [BoardSize; 3] = [BoardSize::Small, BoardSize::Medium, BoardSize::Large];
+        const DIFFICULTIES: [BotDifficulty; 3] = [
+            BotDifficulty::Easy,
+            BotDifficulty::Normal,
+            BotDifficulty::Hard,
+        ];
+        let cycle = |index: usize, len: usize| (index as isize + step).rem_euclid(len as isize);
+
+        match self.selected {
+            0 => {
+                self.number_of_players =
+                    (self.number_of_players as isize + step).clamp(2, MAX_PLAYERS as isize) as usize
+            }
+            1 => {
+                let index = SIZES.iter().position(|size| *size == self.board_size);
+                self.board_size = SIZES[cycle(index.unwrap_or(0), SIZES.len()) as usize];
+            }
+            // a generated board comes before the first map, and no preset before the first one
+            2 => self.map = cycle_pick(self.map, self.maps.len(), step),
+            3 => self.preset = cycle_pick(self.preset, self.presets.len(), step),
+            _ => {
+                let index = DIFFICULTIES.iter().position(|d| *d == self.difficulty);
+                self.difficulty =
+                    DIFFICULTIES[cycle(index.unwrap_or(0), DIFFICULTIES.len()) as usize];
+            }
+        }
+    }
+
+    /// Path of the map file picked, if any
+    pub fn map_path(&self) -> Option<&Path> {
+        self.map
+            .and_then(|map| self.maps.get(map))
+            .map(PathBuf::as_path)
+    }
+
+    /// Name of the preset picked, if any
+    pub fn preset_name(&self) -> Option<&str> {
+        self.preset
+            .and_then(|preset| self.presets.get(preset))
+            .map(String::as_str)
+    }
+
+    /// Lists `presets`, keeping the picked preset if it's still there
+    pub fn refresh_presets(&mut self, presets: Vec<String>) {
+        let picked = self.preset_name().map(str::to_string);
+        self.presets = presets;
+        self.preset = picked.and_then(|picked| self.presets.iter().position(|p| *p == picked));
+    }
+
+    /// Lists the map files of `dir`, keeping the picked map if it's still there
+    pub fn refresh_maps(&mut self, dir: &Path) {
+        let picked = self.map_path().map(Path::to_path_buf);
+        self.maps = list_maps(dir);
+        self.map = picked.and_then(|picked| self.maps.iter().position(|map| *map == picked));
+    }
+
+    /// `rules` for the picked game
+    pub fn rules(&self, rules: &GameRules) -> GameRules {
+        GameRules {
+            number_of_players: self.number_of_players,
+            board_size: self.board_size.rows(),
+            ..rules.clone()
+        }
+    }
+
+    /// Player 1 with the mouse and bots everywhere else, drawing their random choices from
+    /// `seed`
+    pub fn seats(&self, seed: u64) -> Seats {
+        let controllers = (0..self.number_of_players)
+            .map(|player| match player {
+                0 => SeatController::LocalHuman,
+                _ => SeatController::Bot(self.difficulty.bot(seed.wrapping_add(player as u64))),
+            })
+            .collect();
+
+        Seats { controllers }
+    }
+
+    pub fn text(&self) -> String {
+        let map = match self.map_path() {
+            Some(path) => path
+                .file_stem()
+                .map_or(String::new(), |name| name.to_string_lossy().to_uppercase()),
+            None => "GENERATED".to_string(),
+        };
+        let values = [
+            ("PLAYERS", self.number_of_players.to_string()),
+            ("BOARD", self.board_size.name().to_uppercase()),
+            ("MAP", map),
+            (
+                "PRESET",
+                self.preset_name()
+                    .map_or("NONE".to_string(), str::to_uppercase),
+            ),
+            ("BOTS", self.difficulty.name().to_uppercase()),
+        ];
+        let lines: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(line, (label, value))| {
+                let marker = match line == self.selected {
+                    true => ">",
+                    false => " ",
+                };
+                format!("{} {}  < {} >", marker, label, value)
+            })
+            .collect();
+
+        format!(
+            "{}\n\nPLAYER 1 PLAYS, BOTS PLAY THE OTHER SEATS, MAPS SET THE PLAYERS\nA PRESET PLAYS ITS OWN OPTIONS\nUP/DOWN CHOOSE, LEFT/RIGHT CHANGE\nENTER START, ESC BACK",
+            lines.join("\n")
+        )
+    }
+}
+
+/// Lines of the settings screen
+const SETTINGS_FIELDS: usize = 7;
+
+/// First line of the display settings, after the accessibility settings
+const DISPLAY_LINE: usize = 3;
+
+/// Line of the settings screen being changed
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SettingsMenu {
+    selected: usize,
+}
+
+impl SettingsMenu {
+    /// Moves the selection `step` lines down, up for negative steps
+    pub fn select(&mut self, step: isize) {
+        self.selected =
+            (self.selected as isize + step).rem_euclid(SETTINGS_FIELDS as isize) as usize;
+    }
+
+    /// Turns the setting of the selected line on or off
+    pub fn toggle(&self, settings: &mut AccessibilitySettings) {
+        let setting = match self.selected {
+            0 => &mut settings.high_contrast,
+            1 => &mut settings.couch_mode,
+            _ => &mut settings.reduced_motion,
+        };
+        *setting = !*setting;
+    }
+
+    /// Whether the selected line is one of the display settings
+    pub fn on_display_line(&self) -> bool {
+        self.selected >= DISPLAY_LINE
+    }
+
+    /// Changes the display setting of the selected line to its next value, the monitor to the
+    /// next of `monitors`
+    pub fn change_display(&self, display: &mut DisplaySettings, monitors: usize) {
+        match self.selected {
+            3 => display.mode = display.mode.next(),
+            4 => display.resolution = display.next_resolution(),
+            5 => display.vsync = !display.vsync,
+            6 => display.monitor = display.next_monitor(monitors),
+            _ => {}
+        }
+    }
+
+    pub fn text(&self, settings: &AccessibilitySettings, display: &DisplaySettings) -> String {
+        let on_off = |on: bool| match on {
+            true => "ON".to_string(),
+            false => "OFF".to_string(),
+        };
+        let values = [
+            ("HIGH CONTRAST", on_off(settings.high_contrast)),
+            ("COUCH MODE", on_off(settings.couch_mode)),
+            ("REDUCED MOTION", on_off(settings.reduced_motion)),
+            ("DISPLAY", display.mode.to_string().to_uppercase()),
+            (
+                "RESOLUTION",
+                format!("{}x{}", display.resolution.0, display.resolution.1),
+            ),
+            ("VSYNC", on_off(display.vsync)),
+            (
+                "MONITOR",
+                display
+                    .monitor
+                    .map_or("PRIMARY".to_string(), |monitor| monitor.to_string()),
+            ),
+        ];
+        let lines: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(line, (label, value))| {
+                let marker = match line == self.selected {
+                    true => ">",
+                    false => " ",
+                };
+                format!("{} {}  < {} >", marker, label, value)
+            })
+            .collect();
+
+        format!(
+            "{}\n\nUP/DOWN CHOOSE, LEFT/RIGHT/ENTER CHANGE\nESC BACK",
+            lines.join("\n")
+        )
+    }
+}
+
+/// Full screen menu hiding the board, despawned when leaving its state
+#[derive(Component)]
+pub(crate) struct MenuScreen;
+
+/// Text of the setup screen
+#[derive(Component)]
+pub(crate) struct SetupText;
+
+/// Thumbnail of the map picked on the setup screen
+#[derive(Component)]
+pub(crate) struct MapThumbnail;
+
+/// `thumbnail` as a texture for the UI
+fn thumbnail_image(thumbnail: &Thumbnail) -> Image {
+    let data = thumbnail
+        .pixels
+        .iter()
+        .flat_map(|[r, g, b]| [*r, *g, *b, 255])
+        .collect();
+    Image::new(
+        Extent3d {
+            width: thumbnail.width,
+            height: thumbnail.height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// Thumbnail of the map file at `path`, drawn and cached first if the cached one is stale
+fn load_map_thumbnail(path: &Path, profiles: &PlayerProfiles) -> Option<Thumbnail> {
+    let cache_dir = storage_dir(Location::Cache).join(THUMBNAILS_DIR);
+    let thumbnail = cached_thumbnail(path, &cache_dir, profiles)
+        .and_then(fs::read)
+        .map(|ppm| Thumbnail::from_ppm(&ppm));
+    match thumbnail {
+        Ok(thumbnail) => thumbnail,
+        Err(err) => {
+            warn!("Can't draw the thumbnail of {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Switches to `next` at the end of the frame, unless the app is already there. The systems of
+/// `next` run in the same frame, the key switching states is reset so they don't see it pressed.
+pub(crate) fn switch_state(app_state: &mut State<AppState>, next: AppState) {
+    if *app_state.current() == next {
+        return;
+    }
+    if let Err(err) = app_state.set(next) {
+        warn!("Can't switch to {:?}: {:?}", next, err);
+    }
+}
+
+pub(crate) fn spawn_menu_screen(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    title: &str,
+    text: String,
+) -> Entity {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: Color::rgb(0.05, 0.05, 0.08).into(),
+            z_index: ZIndex::Global(10),
+            ..default()
+        })
+        .insert(Name::new("Menu Screen"))
+        .insert(MenuScreen)
+        .with_children(|parent| {
+            parent
+                .spawn(
+                    TextBundle::from_sections([
+                        TextSection::new(
+                            format!("{}\n\n", title),
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 80.0,
+                                color: Color::WHITE,
+                            },
+                        ),
+                        TextSection::new(
+                            text,
+                            TextStyle {
+                                font,
+                                font_size: 36.0,
+                                color: Color::WHITE,
+                            },
+                        ),
+                    ])
+                    .with_text_alignment(TextAlignment::CENTER),
+                )
+                .insert(SetupText);
+        })
+        .id()
+}
+
+pub(crate) fn setup_main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_menu_screen(
+        &mut commands,
+        &asset_server,
+        "STACKRANKDICE",
+        "ENTER NEW GAME\nJ JOIN GAME\nS SETTINGS\nESC QUIT".to_string(),
+    );
+}
+
+pub(crate) fn setup_game_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut setup: ResMut<GameSetup>,
+) {
+    setup.refresh_maps(&storage_dir(Location::Data).join(MAPS_DIR));
+    setup.refresh_presets(list_presets(storage(Location::Config).as_ref()));
+    let screen = spawn_menu_screen(&mut commands, &asset_server, "NEW GAME", setup.text());
+    commands.entity(screen).with_children(|parent| {
+        parent
+            .spawn(ImageBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(40.0),
+                        bottom: Val::Px(40.0),
+                        ..default()
+                    },
+                    size: Size::new(Val::Px(MAP_THUMBNAIL_SIZE), Val::Px(MAP_THUMBNAIL_SIZE)),
+                    ..default()
+                },
+                visibility: Visibility::INVISIBLE,
+                ..default()
+            })
+            .insert(Name::new("Map Thumbnail"))
+            .insert(MapThumbnail);
+    });
+}
+
+/// Shows the thumbnail of the picked map, nothing for a generated board
+pub(crate) fn map_thumbnail_update(
+    setup: Res<GameSetup>,
+    profiles: Res<PlayerProfiles>,
+    mut images: ResMut<Assets<Image>>,
+    mut thumbnail_query: Query<(&mut UiImage, &mut Visibility), With<MapThumbnail>>,
+    added_query: Query<(), Added<MapThumbnail>>,
+) {
+    if !setup.is_changed() && added_query.is_empty() {
+        return;
+    }
+
+    let thumbnail = setup
+        .map_path()
+        .and_then(|path| load_map_thumbnail(path, &profiles));
+    for (mut image, mut visibility) in thumbnail_query.iter_mut() {
+        match thumbnail.as_ref() {
+            Some(thumbnail) => {
+                *image = UiImage(images.add(thumbnail_image(thumbnail)));
+                *visibility = Visibility::VISIBLE;
+            }
+            None => *visibility = Visibility::INVISIBLE,
+        }
+    }
+}
+
+pub(crate) fn setup_settings_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    menu: Res<SettingsMenu>,
+    settings: Res<AccessibilitySettings>,
+    display: Res<DisplaySettings>,
+) {
+    spawn_menu_screen(
+        &mut commands,
+        &asset_server,
+        "SETTINGS",
+        menu.text(&settings, &display),
+    );
+}
+
+pub(crate) fn despawn_menu_screen(
+    mut commands: Commands,
+    menu_query: Query<Entity, With<MenuScreen>>,
+) {
+    for entity in menu_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Stands the game still while the menus hide the board
+pub(crate) fn pause_in_main_menu(app_state: Res<State<AppState>>, mut pause: ResMut<Pause>) {
+    let in_main_menu = matches!(
+        app_state.current(),
+        AppState::MainMenu | AppState::Setup | AppState::Join | AppState::Settings
+    );
+    if pause.in_main_menu != in_main_menu {
+        pause.in_main_menu = in_main_menu;
+    }
+}
+
+pub(crate) fn main_menu_input(
+    mut keys: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut app_exit_writer: EventWriter<AppExit>,
+) {
+    if keys.just_pressed(KeyCode::Return) {
+        switch_state(&mut app_state, AppState::Setup);
+        keys.reset(KeyCode::Return);
+    } else if keys.just_pressed(KeyCode::J) {
+        switch_state(&mut app_state, AppState::Join);
+        keys.reset(KeyCode::J);
+    } else if keys.just_pressed(KeyCode::S) {
+        switch_state(&mut app_state, AppState::Settings);
+        keys.reset(KeyCode::S);
+    } else if keys.just_pressed(KeyCode::Escape) {
+        app_exit_writer.send(AppExit);
+    }
+}
+
+pub(crate) fn setup_input(
+    mut keys: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut setup: ResMut<GameSetup>,
+    mut text_query: Query<&mut Text, With<SetupText>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        switch_state(&mut app_state, AppState::MainMenu);
+        keys.reset(KeyCode::Escape);
+        return;
+    }
+
+    let mut changed = setup.clone();
+    if keys.just_pressed(KeyCode::Up) {
+        changed.select(-1);
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        changed.select(1);
+    }
+    if keys.just_pressed(KeyCode::Left) {
+        changed.change(-1);
+    }
+    if keys.just_pressed(KeyCode::Right) {
+        changed.change(1);
+    }
+    if *setup == changed {
+        return;
+    }
+
+    *setup = changed;
+    for mut text in text_query.iter_mut() {
+        text.sections[1].value = setup.text();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn settings_input(
+    mut keys: ResMut<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut menu: ResMut<SettingsMenu>,
+    mut settings: ResMut<AccessibilitySettings>,
+    mut display: ResMut<DisplaySettings>,
+    winit_windows: Option<NonSend<WinitWindows>>,
+    mut ambient: ResMut<AmbientAnimation>,
+    mut text_query: Query<&mut Text, With<SetupText>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        switch_state(&mut app_state, AppState::MainMenu);
+        keys.reset(KeyCode::Escape);
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::Up) {
+        menu.select(-1);
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        menu.select(1);
+    }
+    if keys.any_just_pressed([KeyCode::Left, KeyCode::Right, KeyCode::Return]) {
+        match menu.on_display_line() {
+            true => {
+                menu.change_display(&mut display, monitor_count(winit_windows.as_deref()));
+                display.save();
+            }
+            false => {
+                menu.toggle(&mut settings);
+                ambient.enabled = !settings.reduced_motion;
+            }
+        }
+    }
+    if !menu.is_changed() && !settings.is_changed() && !display.is_changed() {
+        return;
+    }
+
+    for mut text in text_query.iter_mut() {
+        text.sections[1].value = menu.text(&settings, &display);
+    }
+}
+
+/// Starts the game again with the options of the preset `name`, which the menus can't all set
+fn play_preset(name: &str) -> io::Result<()> {
+    std::process::Command::new(std::env::current_exe()?)
+        .args(["--preset", name])
+        .spawn()?;
+
+    Ok(())
+}
+
+/// Generates the board of the game picked on the setup screen and starts it, or plays the preset
+/// picked
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn start_game(
+    mut keys: ResMut<Input<KeyCode>>,
+    setup: Res<GameSetup>,
+    mut app_state: ResMut<State<AppState>>,
+    mut prng: ResMut<PrngResource>,
+    mut rules: ResMut<GameRules>,
+    mut seats: ResMut<Seats>,
+    mut game_state: ResMut<GameState>,
+    profiles: Res<PlayerProfiles>,
+    mut rematch: ResMut<Rematch>,
+    match_session: Option<ResMut<MatchSession>>,
+    mut board_drawer: BoardDrawer,
+    mut new_game: NewGame,
+    mut app_exit_writer: EventWriter<AppExit>,
+) {
+    if !keys.just_pressed(KeyCode::Return) {
+        return;
+    }
+
+    if let Some(name) = setup.preset_name() {
+        match play_preset(name) {
+            Ok(()) => app_exit_writer.send(AppExit),
+            Err(err) => warn!("Can't play the preset {}: {}", name, err),
+        }
+        return;
+    }
+
+    let map = match setup.map_path() {
+        Some(path) => match fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|source| MapFile::parse(&source).map_err(|err| err.to_string()))
+        {
+            Ok(map) => Some(map),
+            Err(err) => {
+                warn!("Can't play the map {}: {}", path.display(), err);
+                return;
+            }
+        },
+        None => None,
+    };
+    // a map sets the number of players
+    let mut setup = setup.clone();
+    if let Some(map) = map.as_ref() {
+        setup.number_of_players = map.number_of_players;
+    }
+
+    *rules = setup.rules(&rules);
+    prng.world_seed = get_randomness(prng.world_seed).next_u64();
+    *game_state = match map {
+        Some(map) => {
+            let mut game_state = GameState {
+                board: map.board,
+                number_of_players: rules.number_of_players,
+                ..default()
+            };
+            game_state.seat_players(first_player(&rules, prng.world_seed), &rules);
+            game_state
+        }
+        None => new_game_state(&rules, prng.world_seed),
+    };
+    *seats = setup.seats(prng.env_seed);
+    rematch.new_series(&game_state);
+    if let Some(mut match_session) = match_session {
+        *match_session = MatchSession::new(match_session.best_of, setup.number_of_players);
+    }
+    new_game.reset();
+    switch_state(&mut app_state, AppState::InGame);
+    keys.reset(KeyCode::Return);
+    board_drawer.redraw(&game_state, &profiles);
+}
+
+pub(crate) fn enter_game_over(
+    mut event_game_over_reader: EventReader<EventGameOver>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    if event_game_over_reader.iter().next().is_some() {
+        switch_state(&mut app_state, AppState::GameOver);
+    }
+}
+
+/// Goes back to the game once a rematch starts, or to the main menu on [`MAIN_MENU_KEY`]
+pub(crate) fn game_over_input(
+    mut commands: Commands,
+    mut keys: ResMut<Input<KeyCode>>,
+    game_state: Res<GameState>,
+    mut app_state: ResMut<State<AppState>>,
+    mut rematch: ResMut<Rematch>,
+    game_over_query: Query<Entity, With<GameOverScreen>>,
+    mut visibility_query: Query<&mut Visibility>,
+) {
+    if game_state.is_changed() && game_state.game_log.is_empty() {
+        switch_state(&mut app_state, AppState::InGame);
+        return;
+    }
+
+    if keys.just_pressed(MAIN_MENU_KEY) {
+        // a game followed from another machine is left for good
+        commands.remove_resource::<SpectatorStream>();
+        for entity in game_over_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        rematch.show_hud(&mut visibility_query);
+        switch_state(&mut app_state, AppState::MainMenu);
+        keys.reset(MAIN_MENU_KEY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::DisplayMode;
+
+    #[test]
+    fn setup_changes_the_selected_line() {
+        let mut setup = GameSetup::default();
+
+        setup.change(-1);
+        setup.change(-5);
+        assert_eq!(setup.number_of_players, 2);
+        setup.change(20);
+        assert_eq!(setup.number_of_players, MAX_PLAYERS);
+
+        setup.select(1);
+        setup.change(1);
+        assert_eq!(setup.board_size, BoardSize::Large);
+        setup.change(1);
+        assert_eq!(setup.board_size, BoardSize::Small);
+
+        // the selection wraps around
+        setup.select(-2);
+        setup.change(-1);
+        assert_eq!(setup.difficulty, BotDifficulty::Easy);
+        assert!(setup.text().contains("> BOTS  < EASY >"));
+
+        let rules = setup.rules(&GameRules::default());
+        assert_eq!(rules.number_of_players, MAX_PLAYERS);
+        assert_eq!(rules.board_size, BoardSize::Small.rows());
+
+        let seats = setup.seats(7);
+        assert!(seats.is_local_human(0));
+        assert!(!seats.is_local_human(1));
+        assert_eq!(seats.controllers.len(), MAX_PLAYERS);
+    }
+
+    #[test]
+    fn setup_picks_a_preset() {
+        let mut setup = GameSetup::default();
+        setup.refresh_presets(vec!["friday".to_string(), "sunday".to_string()]);
+        assert!(setup.text().contains("PRESET  < NONE >"));
+
+        setup.select(3);
+        setup.change(-1);
+        assert_eq!(setup.preset_name(), Some("sunday"));
+        assert!(setup.text().contains("> PRESET  < SUNDAY >"));
+
+        // the picked preset is kept while it's still saved
+        setup.refresh_presets(vec!["saturday".to_string(), "sunday".to_string()]);
+        assert_eq!(setup.preset_name(), Some("sunday"));
+        setup.refresh_presets(vec!["friday".to_string()]);
+        assert_eq!(setup.preset_name(), None);
+    }
+
+    #[test]
+    fn setup_picks_a_map_or_a_generated_board() {
+        let mut setup = GameSetup {
+            maps: vec![
+                PathBuf::from("maps/arena.txt"),
+                PathBuf::from("maps/isles.txt"),
+            ],
+            ..default()
+        };
+        assert!(setup.text().contains("MAP  < GENERATED >"));
+
+        setup.select(2);
+        setup.change(1);
+        assert_eq!(setup.map_path(), Some(Path::new("maps/arena.txt")));
+        assert!(setup.text().contains("> MAP  < ARENA >"));
+        setup.change(2);
+        assert_eq!(setup.map_path(), None);
+        setup.change(-1);
+        assert_eq!(setup.map_path(), Some(Path::new("maps/isles.txt")));
+
+        // a map that isn't listed anymore can't stay picked
+        setup.refresh_maps(Path::new("no such directory"));
+        assert!(setup.maps.is_empty());
+        assert_eq!(setup.map_path(), None);
+    }
+
+    #[test]
+    fn settings_toggle_the_selected_line() {
+        let mut menu = SettingsMenu::default();
+        let mut settings = AccessibilitySettings::default();
+
+        menu.toggle(&mut settings);
+        assert!(settings.high_contrast);
+        // the display settings come after reduced motion
+        menu.select(2);
+        menu.toggle(&mut settings);
+        assert!(settings.reduced_motion);
+        assert!(!settings.couch_mode);
+        let display = DisplaySettings::default();
+        assert!(menu
+            .text(&settings, &display)
+            .contains("> REDUCED MOTION  < ON >"));
+        assert!(menu
+            .text(&settings, &display)
+            .contains("  COUCH MODE  < OFF >"));
+    }
+
+    #[test]
+    fn settings_change_the_display() {
+        let mut menu = SettingsMenu::default();
+        let settings = AccessibilitySettings::default();
+        let mut display = DisplaySettings::default();
+
+        menu.select(3);
+        assert!(menu.on_display_line());
+        menu.change_display(&mut display, 2);
+        assert_eq!(display.mode, DisplayMode::Borderless);
+        menu.select(1);
+        menu.change_display(&mut display, 2);
+        assert_eq!(display.resolution, (1600, 900));
+        assert!(menu
+            .text(&settings, &display)
+            .contains("> RESOLUTION  < 1600x900 >"));
+
+        // the selection wraps around to the monitor
+        menu.select(-5);
+        assert!(menu
+            .text(&settings, &display)
+            .contains("> MONITOR  < PRIMARY >"));
+        menu.change_display(&mut display, 2);
+        assert_eq!(display.monitor, Some(0));
+        assert!(menu.text(&settings, &display).contains("> MONITOR  < 0 >"));
+    }
+}
@@ -0,0 +1,156 @@
+//! Territory names, so battles read "Karthac falls to Player 2" instead of "region 14".
+//!
+//! Generated boards name their regions from syllables drawn with the board seed, map files store
+//! the names with `name` lines.
+
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::events::EventBattleResolved;
+use crate::game::{Board, GameState, Region};
+use crate::profiles::PlayerProfiles;
+use crate::ui::{spawn_toast, StackRankDiceUI};
+
+const FIRST_SYLLABLES: [&str; 16] = [
+    "Kar", "Vel", "Dor", "Ash", "Mor", "Tal", "Bren", "Ul", "Zan", "Eld", "Gor", "Sil", "Thar",
+    "Ost", "Ryn", "Fen",
+];
+
+const MIDDLE_SYLLABLES: [&str; 8] = ["a", "e", "i", "o", "an", "er", "is", "ov"];
+
+const LAST_SYLLABLES: [&str; 16] = [
+    "thac", "mar", "dun", "wick", "hold", "var", "mere", "gard", "ros", "tor", "heim", "ford",
+    "vale", "moor", "rith", "stead",
+];
+
+/// A random territory name of two or three syllables
+pub fn generate_region_name(rng: &mut impl Rng) -> String {
+    let mut name = FIRST_SYLLABLES.choose(rng).unwrap().to_string();
+    if rng.gen_bool(0.5) {
+        name.push_str(MIDDLE_SYLLABLES.choose(rng).unwrap());
+    }
+    name.push_str(LAST_SYLLABLES.choose(rng).unwrap());
+
+    name
+}
+
+/// Gives every region of the board a name no other region has
+pub fn name_regions(board: &mut Board, rng: &mut impl Rng) {
+    let mut taken = Vec::with_capacity(board.regions.len());
+    for region in board.regions.iter_mut() {
+        let mut name = generate_region_name(rng);
+        while taken.contains(&name) {
+            name = generate_region_name(rng);
+        }
+        taken.push(name.clone());
+        region.name = name;
+    }
+}
+
+/// Name of the hovered region, next to the cursor
+#[derive(Component)]
+pub(crate) struct RegionTooltip;
+
+pub(crate) fn setup_region_tooltip(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 24.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                ..default()
+            }),
+        )
+        .insert(Name::new("Region Tooltip"))
+        .insert(RegionTooltip)
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn region_tooltip_update(
+    windows: Res<Windows>,
+    regions: Query<(&Region, &Interaction)>,
+    mut tooltip_query: Query<(&mut Text, &mut Style, &mut Visibility), With<RegionTooltip>>,
+) {
+    let hovered = regions
+        .iter()
+        .find(|(_, interaction)| **interaction == Interaction::Hovered);
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| window.cursor_position());
+
+    for (mut text, mut style, mut visibility) in tooltip_query.iter_mut() {
+        match (hovered, cursor) {
+            (Some((region, _)), Some(cursor)) => {
+                text.sections[0].value = format!("{} ({} dice)", region.label(), region.num_dice);
+                style.position = UiRect {
+                    left: Val::Px(cursor.x + 16.0),
+                    bottom: Val::Px(cursor.y + 16.0),
+                    ..default()
+                };
+                visibility.is_visible = true;
+            }
+            _ => visibility.is_visible = false,
+        }
+    }
+}
+
+/// Announces every region changing hands
+pub(crate) fn announce_conquests(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut event_battle_resolved_reader: EventReader<EventBattleResolved>,
+    game_state: Res<GameState>,
+    profiles: Res<PlayerProfiles>,
+) {
+    for event in event_battle_resolved_reader
+        .iter()
+        .filter(|event| event.attacker_won)
+    {
+        spawn_toast(
+            &mut commands,
+            &asset_server,
+            format!(
+                "{} falls to {}",
+                game_state.board.regions[event.region_2].label(),
+                profiles.name(event.player_1)
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+    use crate::tiered_prng::get_randomness;
+
+    #[test]
+    fn names_are_unique_and_deterministic() {
+        let source = "players 2\nregion 0 1 0,0\nregion 1 1 1,0\nregion 0 1 2,0\nregion 1 1 3,0\n";
+        let mut board_1 = MapFile::parse(source).unwrap().board;
+        let mut board_2 = board_1.clone();
+        name_regions(&mut board_1, &mut get_randomness(7));
+        name_regions(&mut board_2, &mut get_randomness(7));
+
+        let names: Vec<_> = board_1.regions.iter().map(|r| r.name.clone()).collect();
+        for (i, name) in names.iter().enumerate() {
+            assert!(!name.is_empty());
+            assert!(!names[i + 1..].contains(name));
+        }
+        assert_eq!(
+            names,
+            board_2
+                .regions
+                .iter()
+                .map(|r| r.name.clone())
+                .collect::<Vec<_>>()
+        );
+    }
+}
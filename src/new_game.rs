@@ -0,0 +1,85 @@
+//! Starting a new game in place of the current one: a rematch, the next game of a match, a
+//! puzzle retry, a loaded save, a resumed autosave, a reloaded map or a game started from the
+//! menu.
+//!
+//! Besides the [`GameState`] they replace, a game in progress leaves its trail in many resources:
+//! the timeline, the clocks, the snapshots, the battles waiting for their dice, the turn driven
+//! for a bot, the seed the host draws its dice from... [`NewGame::reset`] forgets all of them at once, so no new game starts with what
+//! was left over from the last one, and sends an [`EventNewGame`] for the views to follow.
+//!
+//! [`GameState`]: crate::game::GameState
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::blitz::BlitzRound;
+use crate::chapters::TimelineExport;
+use crate::clock::PlayerClocks;
+use crate::events::{DiceRollTimer, PendingDiceRolls};
+use crate::fair_dice::FairDice;
+use crate::seats::{BotIntent, TurnDriver};
+use crate::snapshots::SnapshotRing;
+use crate::stalemate::StalemateTracker;
+use crate::timeline::Timeline;
+use crate::ui::DiceRollUI;
+
+/// Event that is fired when a new game replaced the current one
+#[derive(Reflect, FromReflect)]
+pub(crate) struct EventNewGame;
+
+/// State of the game in progress kept outside of the [`crate::game::GameState`]
+#[derive(SystemParam)]
+pub(crate) struct NewGame<'w, 's> {
+    commands: Commands<'w, 's>,
+    timeline: ResMut<'w, Timeline>,
+    timeline_export: ResMut<'w, TimelineExport>,
+    clocks: ResMut<'w, PlayerClocks>,
+    stalemate: ResMut<'w, StalemateTracker>,
+    snapshots: ResMut<'w, SnapshotRing>,
+    blitz_round: ResMut<'w, BlitzRound>,
+    pending_dice_rolls: ResMut<'w, PendingDiceRolls>,
+    turn_driver: ResMut<'w, TurnDriver>,
+    bot_intent: ResMut<'w, BotIntent>,
+    fair_dice: Option<ResMut<'w, FairDice>>,
+    dice_roll_timer_query: Query<'w, 's, Entity, With<DiceRollTimer>>,
+    event_new_game_writer: EventWriter<'w, 's, EventNewGame>,
+}
+
+impl<'w, 's> NewGame<'w, 's> {
+    /// Forgets the game in progress. Dice still rolling are dropped, their results are ignored
+    /// once they come back.
+    pub(crate) fn reset(&mut self) {
+        self.timeline.reset();
+        self.timeline_export.reset();
+        self.clocks.reset();
+        self.stalemate.reset();
+        self.snapshots.reset();
+        self.blitz_round.reset();
+        *self.pending_dice_rolls = PendingDiceRolls::default();
+        *self.turn_driver = TurnDriver::default();
+        *self.bot_intent = BotIntent::default();
+        if let Some(fair_dice) = self.fair_dice.as_mut() {
+            fair_dice.renew();
+        }
+
+        for entity in self.dice_roll_timer_query.iter() {
+            self.commands.entity(entity).despawn();
+        }
+
+        self.event_new_game_writer.send(EventNewGame);
+    }
+}
+
+/// Hides the dice views of a battle the new game dropped
+pub(crate) fn hide_dropped_dice_rolls(
+    mut event_new_game_reader: EventReader<EventNewGame>,
+    mut dice_roll_view_query: Query<&mut Visibility, With<DiceRollUI>>,
+) {
+    if event_new_game_reader.iter().last().is_none() {
+        return;
+    }
+
+    for mut visibility in dice_roll_view_query.iter_mut() {
+        visibility.is_visible = false;
+    }
+}
@@ -0,0 +1,157 @@
+//! King-of-the-hill: on maps with objective regions, holding the majority of them at the start
+//! of enough consecutive turns wins the game.
+
+use bevy::prelude::*;
+
+use crate::events::{EventGameOver, EventTurnStart};
+use crate::game::{Board, GameState};
+use crate::profiles::PlayerProfiles;
+use crate::rules::GameRules;
+use crate::ui::StackRankDiceUI;
+
+#[derive(Resource, Default)]
+pub struct ObjectiveTracker {
+    /// Player holding the majority of objectives
+    pub holder: Option<usize>,
+    /// Number of consecutive turn starts the holder kept the majority
+    pub turns_held: usize,
+}
+
+impl ObjectiveTracker {
+    /// Updates the streak at the start of a turn. Returns the winner once a streak is long enough.
+    pub fn update(&mut self, board: &Board, rules: &GameRules) -> Option<usize> {
+        if board.objectives.is_empty() {
+            return None;
+        }
+
+        let holder = board.objective_majority_holder();
+        if holder.is_some() && holder == self.holder {
+            self.turns_held += 1;
+        } else {
+            self.holder = holder;
+            self.turns_held = usize::from(holder.is_some());
+        }
+
+        match self.turns_held >= rules.objective_turns {
+            true => self.holder,
+            false => None,
+        }
+    }
+}
+
+/// Text area with the objective standings
+#[derive(Component)]
+pub(crate) struct ObjectiveText;
+
+pub(crate) fn setup_objective_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game_state: Res<GameState>,
+) {
+    if game_state.board.objectives.is_empty() {
+        return;
+    }
+
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_text_alignment(TextAlignment::TOP_CENTER)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(60.0),
+                    left: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Name::new("Objective Text"))
+        .insert(ObjectiveText)
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn objective_turn_start(
+    mut event_turn_start_reader: EventReader<EventTurnStart>,
+    mut event_game_over_writer: EventWriter<EventGameOver>,
+    mut tracker: ResMut<ObjectiveTracker>,
+    mut game_state: ResMut<GameState>,
+    rules: Res<GameRules>,
+) {
+    for _ in event_turn_start_reader.iter() {
+        if let Some(winner) = tracker.update(&game_state.board, &rules) {
+            if game_state.decide_winner(winner) {
+                event_game_over_writer.send(EventGameOver { winner });
+            }
+            return;
+        }
+    }
+}
+
+pub(crate) fn objective_text_update(
+    tracker: Res<ObjectiveTracker>,
+    rules: Res<GameRules>,
+    game_state: Res<GameState>,
+    profiles: Res<PlayerProfiles>,
+    mut query: Query<&mut Text, With<ObjectiveText>>,
+) {
+    for mut text in &mut query {
+        match tracker.holder {
+            Some(holder) => {
+                let held = game_state
+                    .board
+                    .objectives
+                    .iter()
+                    .filter(|region| game_state.board.regions[**region].owner == holder)
+                    .count();
+
+                text.sections[0].value = format!(
+                    "{} HOLDS {} OF {} OBJECTIVES ({}/{} TURNS)",
+                    profiles.name(holder).to_uppercase(),
+                    held,
+                    game_state.board.objectives.len(),
+                    tracker.turns_held,
+                    rules.objective_turns
+                );
+                text.sections[0].style.color = profiles.color(holder);
+            }
+            None => {
+                text.sections[0].value = "OBJECTIVES CONTESTED".to_string();
+                text.sections[0].style.color = Color::WHITE;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+
+    #[test]
+    fn majority_streak_wins() {
+        let map = MapFile::parse(
+            "players 2\nregion 0 1 0,0\nregion 1 1 1,0\nregion 0 1 2,0\nobjective 0\nobjective 1\nobjective 2\n",
+        )
+        .unwrap();
+        let mut board = map.board;
+        let rules = GameRules {
+            objective_turns: 2,
+            ..Default::default()
+        };
+        let mut tracker = ObjectiveTracker::default();
+
+        assert_eq!(tracker.update(&board, &rules), None);
+        board.regions[2].owner = 1;
+        assert_eq!(tracker.update(&board, &rules), None);
+        assert_eq!(tracker.holder, Some(1));
+        assert_eq!(tracker.update(&board, &rules), Some(1));
+    }
+}
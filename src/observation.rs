@@ -0,0 +1,191 @@
+//! Flat tensor encoding of a board state for machine learning.
+//!
+//! Layout of version [`OBSERVATION_VERSION`], for a square grid of `grid_size` hexes centered on
+//! hex `(0, 0)` and `max_players` players. All values are `f32`.
+//!
+//! | Offset                      | Size                      | Content                                      |
+//! |-----------------------------|---------------------------|----------------------------------------------|
+//! | 0                           | `max_players * cells`     | owner planes, 1.0 where the hex belongs to the player |
+//! | `max_players * cells`       | `cells`                   | dice plane, dice of the hex's region / `MAX_DICE` |
+//! | `(max_players + 1) * cells` | `max_players`             | one-hot player to move                       |
+//! | `+ max_players`             | 1                         | turn counter                                 |
+//!
+//! `cells` is `grid_size * grid_size`. The cell of hex `(q, r)` is
+//! `(r + grid_size / 2) * grid_size + q + grid_size / 2`. Hexes outside the grid are left out.
+//! Any change to this layout must bump [`OBSERVATION_VERSION`].
+
+use std::fmt;
+
+use bevy::utils::HashMap;
+
+use crate::game::{GameState, NEUTRAL};
+use crate::map_file::MAX_DICE;
+
+/// Version of the tensor layout produced by [`encode`]
+#[allow(dead_code)]
+pub const OBSERVATION_VERSION: u32 = 1;
+
+/// Dimensions of the encoded tensor
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ObservationShape {
+    pub grid_size: usize,
+    pub max_players: usize,
+}
+
+#[allow(dead_code)]
+impl ObservationShape {
+    pub fn cells(&self) -> usize {
+        self.grid_size * self.grid_size
+    }
+
+    /// Number of values in an encoded observation
+    pub fn size(&self) -> usize {
+        (self.max_players + 1) * self.cells() + self.max_players + 1
+    }
+
+    /// Tensor cell of a hex, if it lies on the grid
+    pub fn cell(&self, hex: (isize, isize)) -> Option<usize> {
+        let half = (self.grid_size / 2) as isize;
+        let (q, r) = (hex.0 + half, hex.1 + half);
+        let range = 0..self.grid_size as isize;
+        match range.contains(&q) && range.contains(&r) {
+            true => Some(r as usize * self.grid_size + q as usize),
+            false => None,
+        }
+    }
+
+    /// Hex at a tensor cell
+    pub fn hex(&self, cell: usize) -> (isize, isize) {
+        let half = (self.grid_size / 2) as isize;
+        (
+            (cell % self.grid_size) as isize - half,
+            (cell / self.grid_size) as isize - half,
+        )
+    }
+}
+
+/// What can be read back from an encoded observation
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Observation {
+    /// Owner and dice of the region of every hex on the grid
+    pub hexes: HashMap<(isize, isize), (usize, usize)>,
+    pub turn_of_player: usize,
+    pub turn_counter: usize,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObservationError {
+    LengthMismatch { expected: usize, found: usize },
+    TooManyPlayers(usize),
+}
+
+impl fmt::Display for ObservationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObservationError::LengthMismatch { expected, found } => {
+                write!(f, "expected {} values, found {}", expected, found)
+            }
+            ObservationError::TooManyPlayers(players) => {
+                write!(f, "{} players don't fit in the observation", players)
+            }
+        }
+    }
+}
+
+/// Encodes `game_state` into a flat tensor with the layout described in the module docs
+#[allow(dead_code)]
+pub fn encode(
+    game_state: &GameState,
+    shape: &ObservationShape,
+) -> Result<Vec<f32>, ObservationError> {
+    if game_state.number_of_players > shape.max_players {
+        return Err(ObservationError::TooManyPlayers(
+            game_state.number_of_players,
+        ));
+    }
+
+    let cells = shape.cells();
+    let mut tensor = vec![0.0; shape.size()];
+
+    for region in game_state.board.regions.iter() {
+        for cell in region.hexes.iter().filter_map(|hex| shape.cell(*hex)) {
+            if region.owner != NEUTRAL {
+                tensor[region.owner * cells + cell] = 1.0;
+            }
+            tensor[shape.max_players * cells + cell] = region.num_dice as f32 / MAX_DICE as f32;
+        }
+    }
+
+    let features = (shape.max_players + 1) * cells;
+    tensor[features + game_state.turn_of_player] = 1.0;
+    tensor[features + shape.max_players] = game_state.turn_counter as f32;
+
+    Ok(tensor)
+}
+
+/// Reads back an observation produced by [`encode`] with the same shape
+#[allow(dead_code)]
+pub fn decode(tensor: &[f32], shape: &ObservationShape) -> Result<Observation, ObservationError> {
+    if tensor.len() != shape.size() {
+        return Err(ObservationError::LengthMismatch {
+            expected: shape.size(),
+            found: tensor.len(),
+        });
+    }
+
+    let cells = shape.cells();
+    let mut hexes = HashMap::default();
+
+    for cell in 0..cells {
+        let owner = (0..shape.max_players).find(|player| tensor[player * cells + cell] > 0.5);
+        if let Some(owner) = owner {
+            let num_dice = (tensor[shape.max_players * cells + cell] * MAX_DICE as f32).round();
+            hexes.insert(shape.hex(cell), (owner, num_dice as usize));
+        }
+    }
+
+    let features = &tensor[(shape.max_players + 1) * cells..];
+    let turn_of_player = (0..shape.max_players)
+        .find(|player| features[*player] > 0.5)
+        .unwrap_or(0);
+
+    Ok(Observation {
+        hexes,
+        turn_of_player,
+        turn_counter: features[shape.max_players].round() as usize,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+
+    #[test]
+    fn decode_reverses_encode() {
+        let map = MapFile::parse("players 2\nregion 0 3 0,0 1,0\nregion 1 5 -1,2\n").unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        game_state.advance_turn();
+        let shape = ObservationShape {
+            grid_size: 6,
+            max_players: 4,
+        };
+
+        let tensor = encode(&game_state, &shape).unwrap();
+        assert_eq!(tensor.len(), shape.size());
+
+        let observation = decode(&tensor, &shape).unwrap();
+        assert_eq!(observation.hexes.len(), 3);
+        assert_eq!(observation.hexes[&(1, 0)], (0, 3));
+        assert_eq!(observation.hexes[&(-1, 2)], (1, 5));
+        assert_eq!(observation.turn_of_player, 1);
+        assert_eq!(observation.turn_counter, 1);
+    }
+}
@@ -0,0 +1,168 @@
+//! Battle odds: exact probability of winning an attack under the current rules, and the UI
+//! showing it while a region is selected and an opponent region is hovered.
+
+use bevy::prelude::*;
+
+use crate::game::{GameState, Region, SelectedRegion};
+use crate::rules::{GameRules, TieRule};
+use crate::ui::StackRankDiceUI;
+
+/// Probability distribution of the total rolled with `num_dice` six sided dice, indexed by total
+pub fn roll_distribution(num_dice: usize) -> Vec<f64> {
+    let mut distribution = vec![1.0];
+
+    for _ in 0..num_dice {
+        let mut next = vec![0.0; distribution.len() + 6];
+        for (total, probability) in distribution.iter().enumerate() {
+            for face in 1..=6 {
+                next[total + face] += probability / 6.0;
+            }
+        }
+        distribution = next;
+    }
+
+    distribution
+}
+
+/// Probability that a region with `attacker_dice` dice conquers a region with `defender_dice` dice,
+/// rolling only the dice left over by its garrison
+pub fn attack_win_probability(
+    attacker_dice: usize,
+    defender_dice: usize,
+    rules: &GameRules,
+) -> f64 {
+    let attacker = roll_distribution(rules.attacker_roll_size(attacker_dice));
+    let defender = roll_distribution(rules.defender_roll_size(defender_dice));
+
+    let mut higher = 0.0;
+    let mut tie = 0.0;
+    for (attacker_total, attacker_probability) in attacker.iter().enumerate() {
+        for (defender_total, defender_probability) in defender.iter().enumerate() {
+            let probability = attacker_probability * defender_probability;
+            match attacker_total.cmp(&defender_total) {
+                std::cmp::Ordering::Greater => higher += probability,
+                std::cmp::Ordering::Equal => tie += probability,
+                std::cmp::Ordering::Less => {}
+            }
+        }
+    }
+
+    match rules.tie_rule {
+        TieRule::DefenderWins => higher,
+        TieRule::AttackerWins => higher + tie,
+        TieRule::Reroll => match tie < 1.0 {
+            true => higher / (1.0 - tie),
+            false => 0.5,
+        },
+    }
+}
+
+/// Odds of an attack as shown while hovering its target. With a garrison the number of dice
+/// actually rolled is shown too, since it differs from the dice in the region.
+pub fn odds_text(attacker_dice: usize, defender_dice: usize, rules: &GameRules) -> String {
+    let probability = attack_win_probability(attacker_dice, defender_dice, rules) * 100.0;
+    match rules.garrison {
+        0 => format!("WIN CHANCE {:.0}%", probability),
+        _ => format!(
+            "WIN CHANCE {:.0}% ROLLING {} OF {} DICE",
+            probability,
+            rules.attacker_roll_size(attacker_dice),
+            attacker_dice
+        ),
+    }
+}
+
+/// Text area with the odds of the attack under the cursor
+#[derive(Component)]
+pub(crate) struct OddsText;
+
+pub(crate) fn setup_odds_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_text_alignment(TextAlignment::TOP_CENTER)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(15.0),
+                    right: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Name::new("Odds Text"))
+        .insert(OddsText)
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn odds_text_update(
+    selected_region: Res<SelectedRegion>,
+    game_state: Res<GameState>,
+    rules: Res<GameRules>,
+    hovered_query: Query<(&Interaction, &Region)>,
+    mut query: Query<&mut Text, With<OddsText>>,
+) {
+    let hovered = hovered_query
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Hovered)
+        .map(|(_, region)| region);
+
+    let attacker = selected_region
+        .region()
+        .map(|region| &game_state.board.regions[region]);
+    let odds = match (attacker, hovered) {
+        (Some(attacker), Some(defender)) if game_state.can_attack(attacker, defender, &rules) => {
+            Some(odds_text(attacker.num_dice, defender.num_dice, &rules))
+        }
+        _ => None,
+    };
+
+    for mut text in &mut query {
+        text.sections[0].value = odds.clone().unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn odds(tie_rule: TieRule, defender_bonus_dice: usize) -> f64 {
+        let rules = GameRules {
+            tie_rule,
+            defender_bonus_dice,
+            ..Default::default()
+        };
+        attack_win_probability(1, 1, &rules)
+    }
+
+    #[test]
+    fn single_die_odds() {
+        assert!((odds(TieRule::DefenderWins, 0) - 15.0 / 36.0).abs() < 1e-9);
+        assert!((odds(TieRule::AttackerWins, 0) - 21.0 / 36.0).abs() < 1e-9);
+        assert!((odds(TieRule::Reroll, 0) - 0.5).abs() < 1e-9);
+        assert!(odds(TieRule::DefenderWins, 1) < odds(TieRule::DefenderWins, 0));
+    }
+
+    #[test]
+    fn garrison_dice_dont_roll() {
+        let rules = GameRules {
+            garrison: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            attack_win_probability(5, 3, &rules),
+            attack_win_probability(3, 3, &GameRules::default())
+        );
+        assert!(!odds_text(5, 3, &GameRules::default()).contains("ROLLING"));
+        assert!(odds_text(5, 3, &rules).ends_with("ROLLING 3 OF 5 DICE"));
+    }
+}
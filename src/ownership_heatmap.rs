@@ -0,0 +1,151 @@
+//! Post-game heatmap of how contested every region was: the number of times it changed hands is
+//! counted from the battle log, and the regions are colored from cold to hot by temporarily
+//! swapping their materials. Helps map authors spot the chokepoints of a map.
+
+use bevy::prelude::*;
+
+use crate::events::{EventBoardChanged, EventGameOver};
+use crate::game::{GameLogEntry, GameState, Region};
+
+/// Key that toggles the heatmap once the game is over
+const OVERLAY_KEY: KeyCode = KeyCode::F6;
+
+#[derive(Resource, Default)]
+pub struct OwnershipHeatmap {
+    pub enabled: bool,
+    /// Turn counter and number of battles of the game that ended, the heatmap is offered until
+    /// another game starts
+    ended: Option<(usize, usize)>,
+}
+
+/// Material a region had before the heatmap colored it, put back when the heatmap is hidden
+#[derive(Component)]
+pub(crate) struct SwappedMaterial(Handle<StandardMaterial>);
+
+/// Number of times every region changed hands in the battles of `game_log`, indexed by region id.
+/// Every battle hands the region of the loser to the winner, the attacking region when the
+/// defender wins.
+pub fn ownership_changes(game_log: &[GameLogEntry], region_count: usize) -> Vec<usize> {
+    let mut changes = vec![0; region_count];
+    for entry in game_log.iter().filter(|entry| entry.resolved) {
+        let loser = match entry.attacker_won {
+            true => entry.region_2,
+            false => entry.region_1,
+        };
+        if let Some(region_changes) = changes.get_mut(loser) {
+            *region_changes += 1;
+        }
+    }
+    changes
+}
+
+/// Blue for regions that never changed hands, up to red for the most contested ones
+fn heat_color(changes: usize, max_changes: usize) -> Color {
+    let heat = changes as f32 / max_changes.max(1) as f32;
+    Color::rgb(heat, 0.2, 1.0 - heat)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn ownership_heatmap(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut heatmap: ResMut<OwnershipHeatmap>,
+    game_state: Res<GameState>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut hexes: Query<(
+        Entity,
+        &Region,
+        &mut Handle<StandardMaterial>,
+        Option<&SwappedMaterial>,
+    )>,
+    mut event_game_over_reader: EventReader<EventGameOver>,
+    mut event_board_changed_writer: EventWriter<EventBoardChanged>,
+) {
+    let game = (game_state.turn_counter, game_state.game_log.len());
+    if event_game_over_reader.iter().count() > 0 {
+        heatmap.ended = Some(game);
+    }
+
+    match heatmap.ended == Some(game) {
+        true if keys.just_pressed(OVERLAY_KEY) => {
+            heatmap.enabled = !heatmap.enabled;
+
+            // the board is cleared at the end of the game, draw it again to color it
+            if heatmap.enabled && hexes.is_empty() {
+                event_board_changed_writer.send(EventBoardChanged);
+            }
+        }
+        true => {}
+        false => {
+            heatmap.ended = None;
+            heatmap.enabled = false;
+        }
+    }
+
+    if !heatmap.enabled {
+        for (entity, _, mut material, swapped) in hexes.iter_mut() {
+            if let Some(SwappedMaterial(original)) = swapped {
+                *material = original.clone();
+                commands.entity(entity).remove::<SwappedMaterial>();
+            }
+        }
+        return;
+    }
+
+    let changes = ownership_changes(&game_state.game_log, game_state.board.regions.len());
+    let max_changes = changes.iter().copied().max().unwrap_or(0);
+
+    // hexes of a board drawn again since the heatmap was shown are colored as they appear
+    for (entity, region, mut material, swapped) in hexes.iter_mut() {
+        if swapped.is_some() {
+            continue;
+        }
+
+        let mut heat_material = materials.get(&*material).cloned().unwrap_or_default();
+        heat_material.base_color =
+            heat_color(changes.get(region.id).copied().unwrap_or(0), max_changes);
+        let original = std::mem::replace(&mut *material, materials.add(heat_material));
+        commands.entity(entity).insert(SwappedMaterial(original));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conquest(region_1: usize, region_2: usize, attacker_won: bool) -> GameLogEntry {
+        GameLogEntry {
+            region_1,
+            region_2,
+            resolved: true,
+            attacker_won,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn the_loser_region_changes_hands() {
+        let game_log = vec![
+            conquest(0, 1, true),
+            conquest(2, 1, true),
+            // the defender wins and takes the attacking region
+            conquest(0, 2, false),
+            conquest(1, 0, true),
+            // still rolling
+            GameLogEntry {
+                region_1: 1,
+                region_2: 2,
+                ..Default::default()
+            },
+        ];
+
+        assert_eq!(ownership_changes(&game_log, 3), vec![2, 2, 0]);
+    }
+
+    #[test]
+    fn the_most_contested_regions_are_hottest() {
+        assert_eq!(heat_color(0, 4), Color::rgb(0.0, 0.2, 1.0));
+        assert_eq!(heat_color(4, 4), Color::rgb(1.0, 0.2, 0.0));
+        assert_eq!(heat_color(0, 0), Color::rgb(0.0, 0.2, 1.0));
+    }
+}
@@ -0,0 +1,158 @@
+//! Pausing the game, from the pause key, when the window loses the focus or while the main menu
+//! is open.
+//!
+//! While paused the dice physics and the [`GameLogicStage`] stand still: dice in the air stay
+//! where they are, and the battle they were thrown for, the seats and the clocks wait until the
+//! game resumes. Nothing is skipped on resume, the game picks up at the game time it stopped.
+//!
+//! [`GameLogicStage`]: crate::logic_step::GameLogicStage
+
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+use bevy::window::WindowFocused;
+use bevy_rapier3d::prelude::RapierConfiguration;
+
+use crate::input::{EventInputAction, InputAction};
+use crate::ui::StackRankDiceUI;
+
+#[derive(Resource, Default, Debug, Clone, PartialEq, Eq)]
+pub struct Pause {
+    /// Paused with the pause key, until it is pressed again
+    pub menu: bool,
+    /// Paused while the window doesn't have the focus
+    pub unfocused: bool,
+    /// Paused while the main menu or the game setup hides the board
+    pub in_main_menu: bool,
+}
+
+impl Pause {
+    pub fn is_paused(&self) -> bool {
+        self.menu || self.unfocused || self.in_main_menu
+    }
+
+    /// Whether the pause overlay shows, the main menu shows instead of it
+    pub fn shows_overlay(&self) -> bool {
+        (self.menu || self.unfocused) && !self.in_main_menu
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct PauseOverlay;
+
+/// Runs the systems moving the game forward while it isn't paused
+pub(crate) fn game_running(pause: Res<Pause>) -> ShouldRun {
+    match pause.is_paused() {
+        true => ShouldRun::No,
+        false => ShouldRun::Yes,
+    }
+}
+
+/// Pauses or resumes the game on the pause action
+pub(crate) fn pause_input(
+    mut event_input_action_reader: EventReader<EventInputAction>,
+    mut pause: ResMut<Pause>,
+) {
+    for event in event_input_action_reader.iter() {
+        if event.action == InputAction::Pause && !pause.in_main_menu {
+            pause.menu = !pause.menu;
+        }
+    }
+}
+
+/// Pauses the game while the window doesn't have the focus
+pub(crate) fn pause_on_focus_loss(
+    mut window_focused_reader: EventReader<WindowFocused>,
+    mut pause: ResMut<Pause>,
+) {
+    if let Some(event) = window_focused_reader.iter().last() {
+        if pause.unfocused == event.focused {
+            pause.unfocused = !event.focused;
+        }
+    }
+}
+
+/// Stops the physics pipeline while paused, so rolling dice keep their place and velocity
+pub(crate) fn freeze_physics(pause: Res<Pause>, mut rapier: ResMut<RapierConfiguration>) {
+    if pause.is_changed() {
+        rapier.physics_pipeline_active = !pause.is_paused();
+    }
+}
+
+pub(crate) fn setup_pause_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "PAUSED\nPRESS P TO RESUME",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 60.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_text_alignment(TextAlignment::CENTER)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Percent(40.0),
+                    left: Val::Percent(35.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Visibility { is_visible: false })
+        .insert(Name::new("Pause Overlay"))
+        .insert(PauseOverlay)
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn pause_overlay_update(
+    pause: Res<Pause>,
+    mut query: Query<&mut Visibility, With<PauseOverlay>>,
+) {
+    if !pause.is_changed() {
+        return;
+    }
+
+    for mut visibility in &mut query {
+        visibility.is_visible = pause.shows_overlay();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pausing_freezes_the_physics() {
+        let mut app = App::new();
+        app.init_resource::<Pause>()
+            .insert_resource(RapierConfiguration::default())
+            .add_system(freeze_physics);
+
+        app.world.resource_mut::<Pause>().unfocused = true;
+        app.update();
+        assert!(
+            !app.world
+                .resource::<RapierConfiguration>()
+                .physics_pipeline_active
+        );
+
+        app.world.resource_mut::<Pause>().menu = true;
+        app.world.resource_mut::<Pause>().unfocused = false;
+        app.update();
+        assert!(
+            !app.world
+                .resource::<RapierConfiguration>()
+                .physics_pipeline_active
+        );
+
+        app.world.resource_mut::<Pause>().menu = false;
+        app.update();
+        assert!(
+            app.world
+                .resource::<RapierConfiguration>()
+                .physics_pipeline_active
+        );
+    }
+}
@@ -0,0 +1,489 @@
+//! Saving the game in progress to disk and loading it back, to resume long matches.
+//!
+//! Control+F5 sends a [`SaveGameEvent`] and Control+F9 a [`LoadGameEvent`] for the quicksave,
+//! other systems may send them with another path. A save holds the whole [`GameState`], game log
+//! included, with the seeds and the state of the random generators, so a loaded game goes on as
+//! the saved one would have, and the [`GameRules`] and [`PlayerProfiles`] it was played with.
+//! Saves are written in RON, or in JSON when their name ends in `.json`, and carry an
+//! [`ExportStamp`]: saves of another protocol, or whose rules don't match their stamp, are refused
+//! (see [`crate::handshake`]). Saves made before the rules were saved go on with the rules of the
+//! game running, and are refused when those differ.
+//!
+//! The keys need Control held since plain F5 toggles the high contrast board and plain F9 the
+//! threat overlay.
+//!
+//! The autosave replays games from their world seed, which a loaded game doesn't start from, so
+//! it stays off until the loaded game is over.
+
+use std::fmt;
+use std::io;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::autosave::Autosave;
+use crate::board::BoardDrawer;
+use crate::game::GameState;
+use crate::handshake::{Handshake, Incompatible};
+use crate::input::control_pressed;
+use crate::menu::{switch_state, AppState};
+use crate::new_game::NewGame;
+use crate::profiles::PlayerProfiles;
+use crate::rules::GameRules;
+use crate::stamp::ExportStamp;
+use crate::storage::{storage, Location};
+use crate::tiered_prng::{PrngEnvResource, PrngMapResource, PrngResource};
+use crate::ui::spawn_toast;
+
+/// Save written and loaded by the keys, relative to the data directory
+pub const QUICKSAVE_PATH: &str = "quicksave.ron";
+
+/// Key that saves the game with Control held
+const SAVE_KEY: KeyCode = KeyCode::F5;
+
+/// Key that loads the quicksave with Control held
+const LOAD_KEY: KeyCode = KeyCode::F9;
+
+/// Event that is fired to save the game in progress
+pub struct SaveGameEvent {
+    /// Relative to the data directory
+    pub path: String,
+}
+
+impl Default for SaveGameEvent {
+    fn default() -> Self {
+        SaveGameEvent {
+            path: QUICKSAVE_PATH.to_string(),
+        }
+    }
+}
+
+/// Event that is fired to replace the game in progress with a saved one
+pub struct LoadGameEvent {
+    /// Relative to the data directory
+    pub path: String,
+}
+
+impl Default for LoadGameEvent {
+    fn default() -> Self {
+        LoadGameEvent {
+            path: QUICKSAVE_PATH.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaveFormat {
+    Ron,
+    Json,
+}
+
+impl SaveFormat {
+    /// Format of the save at `path`, JSON for `.json` files and RON otherwise
+    pub fn of(path: &str) -> Self {
+        match path.ends_with(".json") {
+            true => SaveFormat::Json,
+            false => SaveFormat::Ron,
+        }
+    }
+}
+
+/// Position of a ChaCha generator in its stream
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RngState {
+    pub seed: [u8; 32],
+    pub stream: u64,
+    /// Written as a string, not every format takes 128 bit integers
+    #[serde(
+        serialize_with = "serialize_word_pos",
+        deserialize_with = "deserialize_word_pos"
+    )]
+    pub word_pos: u128,
+}
+
+impl RngState {
+    pub fn new(rng: &ChaCha20Rng) -> Self {
+        RngState {
+            seed: rng.get_seed(),
+            stream: rng.get_stream(),
+            word_pos: rng.get_word_pos(),
+        }
+    }
+
+    /// Generator going on from this position
+    pub fn rng(&self) -> ChaCha20Rng {
+        let mut rng = ChaCha20Rng::from_seed(self.seed);
+        rng.set_stream(self.stream);
+        rng.set_word_pos(self.word_pos);
+        rng
+    }
+}
+
+/// Everything needed to go on with a game
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedGame {
+    /// Provenance of the save, see [`ExportStamp`]
+    pub stamp: String,
+    pub world_seed: u64,
+    pub env_seed: u64,
+    pub map_rng: RngState,
+    pub env_rng: RngState,
+    pub game_state: GameState,
+    /// Missing from saves made before the rules were saved
+    #[serde(default)]
+    pub rules: Option<GameRules>,
+    #[serde(default)]
+    pub profiles: Option<PlayerProfiles>,
+}
+
+#[derive(Debug)]
+pub enum PersistenceError {
+    Io(io::Error),
+    /// The save isn't valid RON or JSON, or not a saved game
+    Format(String),
+    /// The save has no stamp to check it against
+    Unstamped,
+    Incompatible(Incompatible),
+}
+
+impl fmt::Display for PersistenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistenceError::Io(err) => write!(f, "{}", err),
+            PersistenceError::Format(err) => write!(f, "unreadable save: {}", err),
+            PersistenceError::Unstamped => write!(f, "the save has no stamp"),
+            PersistenceError::Incompatible(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for PersistenceError {
+    fn from(err: io::Error) -> Self {
+        PersistenceError::Io(err)
+    }
+}
+
+impl SavedGame {
+    pub fn new(
+        game_state: &GameState,
+        rules: &GameRules,
+        profiles: &PlayerProfiles,
+        prng: &PrngResource,
+        map_rng: &ChaCha20Rng,
+        env_rng: &ChaCha20Rng,
+    ) -> Self {
+        SavedGame {
+            stamp: ExportStamp::new(prng, rules).to_string(),
+            world_seed: prng.world_seed,
+            env_seed: prng.env_seed,
+            map_rng: RngState::new(map_rng),
+            env_rng: RngState::new(env_rng),
+            game_state: game_state.clone(),
+            rules: Some(rules.clone()),
+            profiles: Some(profiles.clone()),
+        }
+    }
+
+    pub fn encode(&self, format: SaveFormat) -> Result<String, PersistenceError> {
+        match format {
+            SaveFormat::Ron => ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(|err| PersistenceError::Format(err.to_string())),
+            SaveFormat::Json => serde_json::to_string_pretty(self)
+                .map_err(|err| PersistenceError::Format(err.to_string())),
+        }
+    }
+
+    pub fn decode(source: &str, format: SaveFormat) -> Result<Self, PersistenceError> {
+        match format {
+            SaveFormat::Ron => {
+                ron::from_str(source).map_err(|err| PersistenceError::Format(err.to_string()))
+            }
+            SaveFormat::Json => serde_json::from_str(source)
+                .map_err(|err| PersistenceError::Format(err.to_string())),
+        }
+    }
+
+    /// Rules the saved game goes on with: the saved ones, or `rules` for its number of players
+    /// when the save has none
+    pub fn rules(&self, rules: &GameRules) -> GameRules {
+        match &self.rules {
+            Some(saved) => saved.clone(),
+            None => GameRules {
+                number_of_players: self.game_state.number_of_players,
+                ..rules.clone()
+            },
+        }
+    }
+
+    /// Checks that this game plays the saved game with the protocol and rules it was saved with
+    pub fn check(&self, rules: &GameRules) -> Result<(), PersistenceError> {
+        let saved = Handshake::from_stamp(&self.stamp).ok_or(PersistenceError::Unstamped)?;
+        Handshake::local(Some(&self.rules(rules)))
+            .check(&saved)
+            .map_err(PersistenceError::Incompatible)
+    }
+}
+
+/// Writes the hexes of a board as a list of `((q, r), region)` entries, sorted so the same board
+/// always gives the same save. Formats like JSON only take strings as map keys.
+pub(crate) fn serialize_hexes<S: Serializer>(
+    hexes: &HashMap<(isize, isize), usize>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut entries: Vec<((isize, isize), usize)> =
+        hexes.iter().map(|(hex, region)| (*hex, *region)).collect();
+    entries.sort_unstable();
+    entries.serialize(serializer)
+}
+
+/// Reads the hexes of a board written by [`serialize_hexes`]
+pub(crate) fn deserialize_hexes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<(isize, isize), usize>, D::Error> {
+    let entries = Vec::<((isize, isize), usize)>::deserialize(deserializer)?;
+    Ok(entries.into_iter().collect())
+}
+
+fn serialize_word_pos<S: Serializer>(word_pos: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&word_pos.to_string())
+}
+
+fn deserialize_word_pos<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+pub(crate) fn persistence_input(
+    keys: Res<Input<KeyCode>>,
+    mut save_game_writer: EventWriter<SaveGameEvent>,
+    mut load_game_writer: EventWriter<LoadGameEvent>,
+) {
+    if !control_pressed(&keys) {
+        return;
+    }
+
+    if keys.just_pressed(SAVE_KEY) {
+        save_game_writer.send(SaveGameEvent::default());
+    }
+    if keys.just_pressed(LOAD_KEY) {
+        load_game_writer.send(LoadGameEvent::default());
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn save_game(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut save_game_reader: EventReader<SaveGameEvent>,
+    game_state: Res<GameState>,
+    rules: Res<GameRules>,
+    profiles: Res<PlayerProfiles>,
+    prng: Res<PrngResource>,
+    map_prng: Res<PrngMapResource>,
+    env_prng: Res<PrngEnvResource>,
+) {
+    for event in save_game_reader.iter() {
+        let saved = SavedGame::new(
+            &game_state,
+            &rules,
+            &profiles,
+            &prng,
+            &map_prng.rng,
+            &env_prng.rng,
+        );
+        let written = saved
+            .encode(SaveFormat::of(&event.path))
+            .and_then(|source| {
+                storage(Location::Data)
+                    .write(&event.path, &source)
+                    .map_err(PersistenceError::from)
+            });
+
+        let message = match written {
+            Ok(()) => format!("Game saved to {}", event.path),
+            Err(err) => {
+                warn!("Failed to save the game to {}: {}", event.path, err);
+                format!("Can't save the game: {}", err)
+            }
+        };
+        spawn_toast(&mut commands, &asset_server, message);
+    }
+}
+
+/// Reads and checks the save at `path`
+fn read_save(path: &str, rules: &GameRules) -> Result<SavedGame, PersistenceError> {
+    let source = storage(Location::Data).read(path)?;
+    let saved = SavedGame::decode(&source, SaveFormat::of(path))?;
+    saved.check(rules)?;
+
+    Ok(saved)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn load_game(
+    mut commands: Commands,
+    mut load_game_reader: EventReader<LoadGameEvent>,
+    mut app_state: ResMut<State<AppState>>,
+    mut autosave: ResMut<Autosave>,
+    mut prng: ResMut<PrngResource>,
+    mut env_prng: ResMut<PrngEnvResource>,
+    mut rules: ResMut<GameRules>,
+    asset_server: Res<AssetServer>,
+    mut game_state: ResMut<GameState>,
+    mut profiles: ResMut<PlayerProfiles>,
+    mut board_drawer: BoardDrawer,
+    mut new_game: NewGame,
+) {
+    let path = match load_game_reader.iter().last() {
+        Some(event) => event.path.clone(),
+        None => return,
+    };
+
+    let saved = match read_save(&path, &rules) {
+        Ok(saved) => saved,
+        Err(err) => {
+            warn!("Failed to load the game from {}: {}", path, err);
+            spawn_toast(
+                &mut commands,
+                &asset_server,
+                format!("Can't load the game: {}", err),
+            );
+            return;
+        }
+    };
+
+    if let Err(err) = autosave.detach() {
+        warn!("Failed to set the autosave aside: {}", err);
+    }
+
+    *rules = saved.rules(&rules);
+    *game_state = saved.game_state;
+    if let Some(saved_profiles) = saved.profiles {
+        *profiles = saved_profiles;
+    }
+    new_game.reset();
+    prng.world_seed = saved.world_seed;
+    prng.env_seed = saved.env_seed;
+    board_drawer.map_prng.rng = saved.map_rng.rng();
+    env_prng.rng = saved.env_rng.rng();
+
+    switch_state(&mut app_state, AppState::InGame);
+    spawn_toast(
+        &mut commands,
+        &asset_server,
+        format!("Game loaded from {}", path),
+    );
+    board_drawer.redraw(&game_state, &profiles);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::RngCore;
+
+    use super::*;
+    use crate::map_file::MapFile;
+    use crate::profiles::ProfileRequest;
+
+    fn saved_game() -> (SavedGame, ChaCha20Rng) {
+        let map = MapFile::parse("players 2\nregion 0 6 0,0 1,0\nregion 1 2 5,5\n").unwrap();
+        let game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            turn_counter: 7,
+            reserves: vec![0, 3],
+            ..default()
+        };
+        let prng = PrngResource {
+            world_seed: 12,
+            env_seed: 34,
+        };
+        let mut env_rng = ChaCha20Rng::seed_from_u64(34);
+        env_rng.next_u64();
+        let rules = GameRules::with_players(2);
+        let profiles = PlayerProfiles::new(
+            &[ProfileRequest {
+                name: Some("Ada".to_string()),
+                ..default()
+            }],
+            2,
+        );
+        let saved = SavedGame::new(
+            &game_state,
+            &rules,
+            &profiles,
+            &prng,
+            &ChaCha20Rng::seed_from_u64(12),
+            &env_rng,
+        );
+
+        (saved, env_rng)
+    }
+
+    #[test]
+    fn saves_round_trip_in_both_formats() {
+        let (saved, env_rng) = saved_game();
+
+        for format in [SaveFormat::Ron, SaveFormat::Json] {
+            let source = saved.encode(format).unwrap();
+            let loaded = SavedGame::decode(&source, format).unwrap();
+
+            assert_eq!(loaded.encode(format).unwrap(), source);
+            assert_eq!(loaded.game_state.board.hexes, saved.game_state.board.hexes);
+            assert_eq!(loaded.game_state.reserves, vec![0, 3]);
+            // the generators go on where they were saved
+            assert_eq!(loaded.env_rng.rng().next_u64(), env_rng.clone().next_u64());
+        }
+
+        assert_eq!(SaveFormat::of("games/long_match.json"), SaveFormat::Json);
+        assert_eq!(SaveFormat::of(QUICKSAVE_PATH), SaveFormat::Ron);
+    }
+
+    #[test]
+    fn saves_with_other_rules_are_refused() {
+        let (mut saved, _) = saved_game();
+        // made before the rules were saved
+        saved.rules = None;
+
+        assert!(saved.check(&GameRules::with_players(4)).is_ok());
+        let no_momentum = GameRules {
+            momentum: false,
+            ..GameRules::with_players(2)
+        };
+        assert!(matches!(
+            saved.check(&no_momentum),
+            Err(PersistenceError::Incompatible(Incompatible::Rules { .. }))
+        ));
+        assert!(matches!(
+            SavedGame::decode("(stamp: 3)", SaveFormat::Ron),
+            Err(PersistenceError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn saves_bring_their_rules_and_profiles() {
+        let (mut saved, _) = saved_game();
+        let source = saved.encode(SaveFormat::Ron).unwrap();
+        let loaded = SavedGame::decode(&source, SaveFormat::Ron).unwrap();
+
+        // the game goes on with the rules it was saved with, whatever the rules running
+        let no_momentum = GameRules {
+            momentum: false,
+            ..GameRules::with_players(3)
+        };
+        assert!(loaded.check(&no_momentum).is_ok());
+        assert_eq!(loaded.rules(&no_momentum).number_of_players, 2);
+        assert!(loaded.rules(&no_momentum).momentum);
+        assert_eq!(loaded.profiles.unwrap().name(0), "Ada");
+
+        // rules edited after the save don't match its stamp
+        saved.rules.as_mut().unwrap().momentum = false;
+        assert!(matches!(
+            saved.check(&GameRules::default()),
+            Err(PersistenceError::Incompatible(Incompatible::Rules { .. }))
+        ));
+    }
+}
@@ -0,0 +1,119 @@
+//! Pings: Alt+click on a region drops a short-lived marker and a sound on it for everyone
+//! watching the board, to point at it without playing a move. Pings are recorded in replays.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::game::{GameState, Region, RegionId};
+use crate::geometry::center;
+use crate::input::{EventInputAction, InputAction};
+use crate::profiles::PlayerProfiles;
+use crate::sounds::{EventSound, SoundEvent};
+
+const PING_DURATION: Duration = Duration::from_secs(2);
+
+/// Event that is fired when a player pinged a region
+#[derive(Reflect, FromReflect)]
+pub(crate) struct EventRegionPinged {
+    // An index of a player
+    pub(crate) player: usize,
+    pub(crate) region: RegionId,
+}
+
+/// Ring growing and fading over a pinged region
+#[derive(Component)]
+pub(crate) struct PingMarker {
+    timer: Timer,
+}
+
+/// Pings the regions Alt+clicked by the player in turn
+pub(crate) fn ping_regions(
+    mut event_input_action_reader: EventReader<EventInputAction>,
+    game_state: Res<GameState>,
+    regions: Query<&Region>,
+    mut event_region_pinged_writer: EventWriter<EventRegionPinged>,
+) {
+    for event in event_input_action_reader.iter() {
+        if let InputAction::Ping(entity) = event.action {
+            if let Ok(region) = regions.get(entity) {
+                event_region_pinged_writer.send(EventRegionPinged {
+                    player: game_state.turn_of_player,
+                    region: region.id,
+                });
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn spawn_ping_markers(
+    mut commands: Commands,
+    mut event_region_pinged_reader: EventReader<EventRegionPinged>,
+    game_state: Res<GameState>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    profiles: Res<PlayerProfiles>,
+    mut event_sound_writer: EventWriter<EventSound>,
+) {
+    for event in event_region_pinged_reader.iter() {
+        let region = match game_state.board.regions.get(event.region) {
+            Some(region) => region,
+            None => continue,
+        };
+        let [x, _, z] = center(1.0, &region.center_hex(), &[0.0; 3]);
+        let color = profiles.color(event.player);
+
+        commands
+            .spawn(PbrBundle {
+                mesh: meshes.add(Mesh::from(shape::Torus {
+                    radius: 1.0,
+                    ring_radius: 0.1,
+                    ..default()
+                })),
+                material: materials.add(StandardMaterial {
+                    base_color: color,
+                    emissive: color,
+                    alpha_mode: AlphaMode::Blend,
+                    unlit: true,
+                    ..default()
+                }),
+                transform: Transform::from_xyz(x, 1.3, z),
+                ..default()
+            })
+            .insert(Name::new("Ping"))
+            .insert(PingMarker {
+                timer: Timer::new(PING_DURATION, TimerMode::Once),
+            });
+
+        event_sound_writer.send(EventSound {
+            sound: SoundEvent::Ping,
+        });
+    }
+}
+
+pub(crate) fn ping_marker_update(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut marker_query: Query<(
+        Entity,
+        &mut PingMarker,
+        &mut Transform,
+        &Handle<StandardMaterial>,
+    )>,
+) {
+    for (entity, mut marker, mut transform, material) in marker_query.iter_mut() {
+        marker.timer.tick(time.delta());
+        if marker.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let progress = marker.timer.percent();
+        transform.scale = Vec3::splat(0.5 + progress * 1.5);
+        if let Some(material) = materials.get_mut(material) {
+            material.base_color.set_a(1.0 - progress);
+        }
+    }
+}
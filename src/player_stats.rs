@@ -0,0 +1,223 @@
+//! Statistics of every player, shown in a table while Tab is held: regions, dice, largest
+//! connected area, battles won and lost and an estimate of the chance to win the game.
+
+use bevy::prelude::*;
+
+use crate::game::GameState;
+use crate::profiles::PlayerProfiles;
+use crate::ui::StackRankDiceUI;
+
+/// Key that shows the statistics table while held
+const STATS_KEY: KeyCode = KeyCode::Tab;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlayerStats {
+    pub regions: usize,
+    pub dice: usize,
+    pub largest_area: usize,
+    /// Battles won, attacking or defending
+    pub battles_won: usize,
+    /// Battles lost, attacking or defending
+    pub battles_lost: usize,
+    /// Estimated chance to win the game, see [`win_estimates`]
+    pub win_estimate: f64,
+}
+
+/// Chance of every player to win the game, estimated from its share of the dice on the board
+/// weighted by the size of its largest connected area, which sets its reinforcements. A player
+/// without regions has no chance left.
+pub fn win_estimates(game_state: &GameState) -> Vec<f64> {
+    let strengths: Vec<f64> = (0..game_state.number_of_players)
+        .map(|player| {
+            let dice: usize = game_state
+                .board
+                .regions
+                .iter()
+                .filter(|region| region.owner == player)
+                .map(|region| region.num_dice)
+                .sum();
+            let (largest_area, _) = game_state.board.largest_connected_area(player);
+            (dice * largest_area) as f64
+        })
+        .collect();
+
+    let total: f64 = strengths.iter().sum();
+    strengths
+        .iter()
+        .map(|strength| match total > 0.0 {
+            true => strength / total,
+            false => 0.0,
+        })
+        .collect()
+}
+
+/// Statistics of every player, indexed by player
+pub fn player_stats(game_state: &GameState) -> Vec<PlayerStats> {
+    let mut stats = vec![PlayerStats::default(); game_state.number_of_players];
+
+    for region in game_state.board.regions.iter() {
+        if let Some(player_stats) = stats.get_mut(region.owner) {
+            player_stats.regions += 1;
+            player_stats.dice += region.num_dice;
+        }
+    }
+
+    for entry in game_state.game_log.iter().filter(|entry| entry.resolved) {
+        let (winner, loser) = match entry.attacker_won {
+            true => (entry.turn_of_player, entry.defending_player),
+            false => (entry.defending_player, entry.turn_of_player),
+        };
+        if let Some(player_stats) = stats.get_mut(winner) {
+            player_stats.battles_won += 1;
+        }
+        if let Some(player_stats) = stats.get_mut(loser) {
+            player_stats.battles_lost += 1;
+        }
+    }
+
+    for (player, (player_stats, win_estimate)) in
+        stats.iter_mut().zip(win_estimates(game_state)).enumerate()
+    {
+        player_stats.largest_area = game_state.board.largest_connected_area(player).0;
+        player_stats.win_estimate = win_estimate;
+    }
+
+    stats
+}
+
+/// Text table of the statistics of every player, a line per player
+pub fn stats_table(stats: &[PlayerStats], profiles: &PlayerProfiles) -> String {
+    let mut lines = vec!["PLAYER  REGIONS  DICE  AREA  WON  LOST  WIN".to_string()];
+    lines.extend(stats.iter().enumerate().map(|(player, player_stats)| {
+        format!(
+            "{}  {}  {}  {}  {}  {}  {:.0}%",
+            profiles.name(player),
+            player_stats.regions,
+            player_stats.dice,
+            player_stats.largest_area,
+            player_stats.battles_won,
+            player_stats.battles_lost,
+            player_stats.win_estimate * 100.0
+        )
+    }));
+    lines.join("\n")
+}
+
+/// Text area with the statistics table
+#[derive(Component)]
+pub(crate) struct PlayerStatsText;
+
+pub(crate) fn setup_player_stats_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Percent(25.0),
+                    left: Val::Percent(30.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Visibility { is_visible: false })
+        .insert(Name::new("Player Stats Text"))
+        .insert(PlayerStatsText)
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn player_stats_text_update(
+    keys: Res<Input<KeyCode>>,
+    game_state: Res<GameState>,
+    profiles: Res<PlayerProfiles>,
+    mut query: Query<(&mut Text, &mut Visibility), With<PlayerStatsText>>,
+) {
+    let shown = keys.pressed(STATS_KEY);
+
+    for (mut text, mut visibility) in &mut query {
+        visibility.is_visible = shown;
+        if shown && (keys.just_pressed(STATS_KEY) || game_state.is_changed()) {
+            text.sections[0].value = stats_table(&player_stats(&game_state), &profiles);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameLogEntry;
+    use crate::map_file::MapFile;
+
+    #[test]
+    fn stats_count_regions_dice_and_battles() {
+        let map = MapFile::parse(
+            "players 2\nregion 0 3 0,0\nregion 0 2 1,0\nregion 1 4 5,5\nregion 1 1 7,7\n",
+        )
+        .unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        game_state.game_log = vec![
+            GameLogEntry {
+                turn_of_player: 0,
+                defending_player: 1,
+                resolved: true,
+                attacker_won: true,
+                ..Default::default()
+            },
+            GameLogEntry {
+                turn_of_player: 1,
+                defending_player: 0,
+                resolved: true,
+                attacker_won: true,
+                ..Default::default()
+            },
+            GameLogEntry {
+                turn_of_player: 0,
+                defending_player: 1,
+                resolved: true,
+                attacker_won: false,
+                ..Default::default()
+            },
+            // still rolling
+            GameLogEntry {
+                turn_of_player: 1,
+                defending_player: 0,
+                ..Default::default()
+            },
+        ];
+
+        let stats = player_stats(&game_state);
+
+        assert_eq!((stats[0].regions, stats[0].dice), (2, 5));
+        assert_eq!((stats[1].regions, stats[1].dice), (2, 5));
+        assert_eq!((stats[0].battles_won, stats[0].battles_lost), (1, 2));
+        assert_eq!((stats[1].battles_won, stats[1].battles_lost), (2, 1));
+        assert_eq!(stats[0].largest_area, 2);
+        assert_eq!(stats[1].largest_area, 1);
+        assert!(stats[0].win_estimate > stats[1].win_estimate);
+        assert!((stats[0].win_estimate + stats[1].win_estimate - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn eliminated_players_have_no_chance() {
+        let map = MapFile::parse("players 2\nregion 0 3 0,0\nregion 0 2 1,0\n").unwrap();
+        let game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(win_estimates(&game_state), vec![1.0, 0.0]);
+    }
+}
@@ -0,0 +1,168 @@
+//! Discord rich presence: shows the board size, the turn and the players left on the Discord
+//! profile of the player.
+//!
+//! Built with the `discord` feature and enabled with `--discord-app-id` (or the `DISCORD_APP_ID`
+//! environment variable). Talking to Discord happens on a background thread, a missing or
+//! closed Discord client never slows the game down.
+
+use std::sync::mpsc::{self, Sender};
+
+use bevy::prelude::*;
+
+use crate::game::GameState;
+
+/// What the profile of the player shows
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PresenceStatus {
+    pub regions: usize,
+    pub turn: usize,
+    pub players_remaining: usize,
+    pub number_of_players: usize,
+}
+
+impl PresenceStatus {
+    pub fn new(game_state: &GameState) -> Self {
+        let players_remaining = (0..game_state.number_of_players)
+            .filter(|player| {
+                game_state
+                    .board
+                    .regions
+                    .iter()
+                    .any(|region| region.owner == *player)
+            })
+            .count();
+
+        PresenceStatus {
+            regions: game_state.board.regions.len(),
+            turn: game_state.turn_counter + 1,
+            players_remaining,
+            number_of_players: game_state.number_of_players,
+        }
+    }
+
+    /// First line of the presence
+    #[allow(dead_code)]
+    pub fn details(&self) -> String {
+        format!("Turn {} on a {} region board", self.turn, self.regions)
+    }
+
+    /// Second line of the presence
+    #[allow(dead_code)]
+    pub fn state(&self) -> String {
+        format!(
+            "{} of {} players remaining",
+            self.players_remaining, self.number_of_players
+        )
+    }
+}
+
+/// Connection to the background thread publishing the presence
+#[derive(Resource, Default)]
+pub struct RichPresence {
+    sender: Option<Sender<PresenceStatus>>,
+    last: Option<PresenceStatus>,
+}
+
+impl RichPresence {
+    /// Starts publishing to the Discord application `app_id`
+    pub fn start(app_id: String) -> Self {
+        let (sender, receiver) = mpsc::channel::<PresenceStatus>();
+
+        #[cfg(feature = "discord")]
+        std::thread::spawn(move || discord::publish(app_id, receiver));
+
+        #[cfg(not(feature = "discord"))]
+        {
+            warn!(
+                "Discord application {} ignored, rich presence needs the `discord` feature",
+                app_id
+            );
+            drop(receiver);
+        }
+
+        RichPresence {
+            sender: Some(sender),
+            last: None,
+        }
+    }
+}
+
+#[cfg(feature = "discord")]
+mod discord {
+    use std::sync::mpsc::Receiver;
+
+    use bevy::prelude::*;
+    use discord_rich_presence::{activity::Activity, DiscordIpc, DiscordIpcClient};
+
+    use super::PresenceStatus;
+
+    /// Publishes every status received until the game closes, connecting again after errors
+    pub(super) fn publish(app_id: String, receiver: Receiver<PresenceStatus>) {
+        let mut client: Option<DiscordIpcClient> = None;
+
+        for status in receiver.iter() {
+            if client.is_none() {
+                client = DiscordIpcClient::new(&app_id)
+                    .ok()
+                    .and_then(|mut new_client| new_client.connect().ok().map(|_| new_client));
+            }
+
+            if let Some(connected) = client.as_mut() {
+                let (details, state) = (status.details(), status.state());
+                let activity = Activity::new().details(&details).state(&state);
+                if let Err(err) = connected.set_activity(activity) {
+                    warn!("Failed to update the Discord presence: {}", err);
+                    client = None;
+                }
+            }
+        }
+
+        if let Some(mut connected) = client {
+            let _ = connected.close();
+        }
+    }
+}
+
+pub(crate) fn update_rich_presence(
+    game_state: Res<GameState>,
+    mut rich_presence: ResMut<RichPresence>,
+) {
+    if !game_state.is_changed() {
+        return;
+    }
+
+    let status = PresenceStatus::new(&game_state);
+    if rich_presence.last.as_ref() == Some(&status) {
+        return;
+    }
+
+    if let Some(sender) = rich_presence.sender.as_ref() {
+        // a closed channel means the background thread gave up, the game goes on without it
+        if sender.send(status.clone()).is_err() {
+            rich_presence.sender = None;
+        }
+    }
+    rich_presence.last = Some(status);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+
+    #[test]
+    fn describes_the_game() {
+        let map =
+            MapFile::parse("players 3\nregion 0 1 0,0\nregion 1 1 1,0\nregion 0 1 2,0\n").unwrap();
+        let game_state = GameState {
+            board: map.board,
+            number_of_players: 3,
+            turn_counter: 4,
+            ..Default::default()
+        };
+        let status = PresenceStatus::new(&game_state);
+
+        assert_eq!(status.details(), "Turn 5 on a 3 region board");
+        assert_eq!(status.state(), "2 of 3 players remaining");
+    }
+}
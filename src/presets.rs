@@ -0,0 +1,205 @@
+//! Named game setups, for game nights played with the same seats and rules every time.
+//!
+//! A preset is the command-line options of a game, saved with `--save-preset <name>` and played
+//! again with `--preset <name>`. Presets are kept in [`PRESETS_DIR`] of the config directory, one
+//! `<name>.preset` file each holding one option and its value per line, so they can be written
+//! by hand too:
+//!
+//! ```text
+//! --players 4
+//! --bot 3
+//! --bot 4
+//! --reinforcements automatic
+//! --name Alex
+//! ```
+//!
+//! Options given on the command line next to `--preset` override the ones of the preset, or add
+//! to them for options that can be repeated. The new game screen of the main menu also lists the
+//! presets, and plays the one picked (see [`crate::menu`]).
+
+use crate::storage::Storage;
+
+/// Directory of the presets in the config directory
+pub const PRESETS_DIR: &str = "presets";
+
+/// Options choosing, saving or listing presets, which a preset doesn't keep
+const PRESET_OPTIONS: [&str; 3] = ["--preset", "--save-preset", "--list-presets"];
+
+/// Checks that `name` can name a preset file: letters, digits, `-` and `_`
+pub fn parse_preset_name(name: &str) -> Result<String, String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    match valid {
+        true => Ok(name.to_string()),
+        false => Err("expected letters, digits, '-' and '_'".to_string()),
+    }
+}
+
+fn preset_path(name: &str) -> String {
+    format!("{}/{}.preset", PRESETS_DIR, name)
+}
+
+/// Options of the command line `args` worth keeping in a preset, one option and its value per
+/// entry. Options about presets themselves are left out. `takes_value` tells the options
+/// followed by a value from the flags, so values starting with `-` are kept with their option.
+pub fn preset_options(args: &[String], takes_value: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut options: Vec<String> = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let flag = arg.split('=').next().unwrap_or(arg);
+        let value = match !arg.contains('=') && takes_value(flag) {
+            true => args.next(),
+            false => None,
+        };
+
+        if PRESET_OPTIONS.contains(&flag) {
+            continue;
+        }
+        match value {
+            Some(value) => options.push(format!("{} {}", arg, value)),
+            None => options.push(arg.clone()),
+        }
+    }
+
+    options
+}
+
+/// Text of a preset file holding `options`
+pub fn to_text(options: &[String]) -> String {
+    let mut text = String::from("# stackrankdice preset\n");
+    for option in options {
+        text.push_str(option);
+        text.push('\n');
+    }
+
+    text
+}
+
+/// Command-line arguments of a preset file. Blank lines and lines starting with `#` are
+/// skipped, and an option is split from its value at the first space.
+pub fn parse(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .flat_map(|line| match line.split_once(' ') {
+            Some((option, value)) => vec![option.to_string(), value.trim().to_string()],
+            None => vec![line.to_string()],
+        })
+        .collect()
+}
+
+pub fn save_preset(
+    storage: &dyn Storage,
+    name: &str,
+    args: &[String],
+    takes_value: impl Fn(&str) -> bool,
+) -> Result<(), String> {
+    storage
+        .write(
+            &preset_path(name),
+            &to_text(&preset_options(args, takes_value)),
+        )
+        .map_err(|err| format!("failed to save preset {}: {}", name, err))
+}
+
+/// Command-line arguments saved in the preset `name`
+pub fn load_preset(storage: &dyn Storage, name: &str) -> Result<Vec<String>, String> {
+    storage
+        .read(&preset_path(name))
+        .map(|text| parse(&text))
+        .map_err(|err| format!("failed to read preset {}: {}", name, err))
+}
+
+/// Names of the saved presets, sorted
+pub fn list_presets(storage: &dyn Storage) -> Vec<String> {
+    let mut names: Vec<String> = storage
+        .list(PRESETS_DIR)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|file| file.strip_suffix(".preset"))
+        .filter(|name| parse_preset_name(name).is_ok())
+        .map(str::to_string)
+        .collect();
+    names.sort();
+
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileStorage;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split(' ').map(str::to_string).collect()
+    }
+
+    /// Options of the tests followed by a value
+    fn takes_value(option: &str) -> bool {
+        [
+            "--players",
+            "--bot",
+            "--save-preset",
+            "--preset",
+            "--name",
+            "--ties",
+        ]
+        .contains(&option)
+    }
+
+    #[test]
+    fn presets_keep_the_game_options() {
+        let options = preset_options(
+            &args("--players 4 --save-preset friday --bot 3 --momentum --ties=reroll --preset=old"),
+            takes_value,
+        );
+
+        assert_eq!(
+            options,
+            vec!["--players 4", "--bot 3", "--momentum", "--ties=reroll"]
+        );
+        assert_eq!(
+            parse(&to_text(&options)),
+            args("--players 4 --bot 3 --momentum --ties=reroll")
+        );
+        assert_eq!(
+            parse("# names\n--name Alex Smith\n\n"),
+            ["--name", "Alex Smith"]
+        );
+    }
+
+    #[test]
+    fn values_starting_with_a_dash_stay_values() {
+        assert_eq!(
+            preset_options(&args("--name -Alex- --momentum --players 2"), takes_value),
+            vec!["--name -Alex-", "--momentum", "--players 2"]
+        );
+    }
+
+    #[test]
+    fn presets_are_saved_by_name() {
+        let dir =
+            std::env::temp_dir().join(format!("stackrankdice-presets-{}", std::process::id()));
+        let storage = FileStorage { root: dir.clone() };
+
+        save_preset(
+            &storage,
+            "friday",
+            &args("--players 3 --list-presets"),
+            takes_value,
+        )
+        .unwrap();
+        assert_eq!(
+            load_preset(&storage, "friday").unwrap(),
+            args("--players 3")
+        );
+        assert!(load_preset(&storage, "saturday").is_err());
+        assert_eq!(list_presets(&storage), vec!["friday"]);
+        assert!(parse_preset_name("../friday").is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,275 @@
+//! Display names, colors and looks of the players.
+//!
+//! Every seat may ask for a name, a color, a dice skin, an outline style and a banner icon.
+//! [`PlayerProfiles::new`] resolves conflicts: a color already taken goes to the first free one, a
+//! name already taken gets the player number appended. Skins, outlines and banners can be shared.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::board::{NEUTRAL_COLOR, PLAYER_COLORS};
+use crate::game::NEUTRAL;
+
+/// Names of [`PLAYER_COLORS`], in the same order
+pub const COLOR_NAMES: [&str; 8] = [
+    "purple", "cyan", "green", "yellow", "red", "orange", "pink", "olive",
+];
+
+/// Look of the dice of a player
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiceSkin {
+    /// White dice with black pips
+    #[default]
+    Classic,
+    /// Dice in the color of the player
+    Tinted,
+    /// Shiny golden dice
+    Gilded,
+}
+
+/// Border of the regions of a player while they aren't highlighted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutlineStyle {
+    /// A thin black line
+    #[default]
+    Thin,
+    /// A thick black line
+    Bold,
+    /// A line in the color of the player
+    Glow,
+}
+
+/// Icon next to the name of a player in the HUD
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Banner {
+    #[default]
+    None,
+    Dagger,
+    Lozenge,
+    Infinity,
+    Omega,
+    Arrow,
+}
+
+impl Banner {
+    /// Glyph of the icon, all of them are in the font of the HUD
+    pub fn icon(self) -> Option<char> {
+        match self {
+            Banner::None => None,
+            Banner::Dagger => Some('\u{2020}'),
+            Banner::Lozenge => Some('\u{25ca}'),
+            Banner::Infinity => Some('\u{221e}'),
+            Banner::Omega => Some('\u{2126}'),
+            Banner::Arrow => Some('\u{27a1}'),
+        }
+    }
+}
+
+/// Names of the dice skins, outline styles and banners, as players pick them
+pub const DICE_SKINS: [(&str, DiceSkin); 3] = [
+    ("classic", DiceSkin::Classic),
+    ("tinted", DiceSkin::Tinted),
+    ("gilded", DiceSkin::Gilded),
+];
+pub const OUTLINE_STYLES: [(&str, OutlineStyle); 3] = [
+    ("thin", OutlineStyle::Thin),
+    ("bold", OutlineStyle::Bold),
+    ("glow", OutlineStyle::Glow),
+];
+pub const BANNERS: [(&str, Banner); 6] = [
+    ("none", Banner::None),
+    ("dagger", Banner::Dagger),
+    ("lozenge", Banner::Lozenge),
+    ("infinity", Banner::Infinity),
+    ("omega", Banner::Omega),
+    ("arrow", Banner::Arrow),
+];
+
+/// Value named `value` in `names`, ignoring case
+pub fn parse_named<T: Copy>(names: &[(&str, T)], value: &str) -> Result<T, String> {
+    names
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(value))
+        .map(|(_, named)| *named)
+        .ok_or_else(|| {
+            let names: Vec<&str> = names.iter().map(|(name, _)| *name).collect();
+            format!("expected one of {}", names.join(", "))
+        })
+}
+
+/// What a seat asked for, if anything
+#[derive(Clone, Debug, Default)]
+pub struct ProfileRequest {
+    pub name: Option<String>,
+    /// Index into [`PLAYER_COLORS`]
+    pub color: Option<usize>,
+    pub dice_skin: Option<DiceSkin>,
+    pub outline: Option<OutlineStyle>,
+    pub banner: Option<Banner>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerProfile {
+    pub name: String,
+    /// Index into [`PLAYER_COLORS`]
+    pub color: usize,
+    pub dice_skin: DiceSkin,
+    pub outline: OutlineStyle,
+    pub banner: Banner,
+}
+
+/// Profiles of every player, indexed by player. Players without a profile show as "Player N"
+/// in their default color.
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PlayerProfiles {
+    pub profiles: Vec<PlayerProfile>,
+}
+
+impl PlayerProfiles {
+    pub fn new(requests: &[ProfileRequest], number_of_players: usize) -> Self {
+        let mut profiles: Vec<PlayerProfile> = Vec::with_capacity(number_of_players);
+
+        for player in 0..number_of_players {
+            let request = requests.get(player).cloned().unwrap_or_default();
+            let color_taken = |color: &usize| profiles.iter().any(|p| p.color == *color);
+
+            let color = request
+                .color
+                .filter(|color| *color < PLAYER_COLORS.len() && !color_taken(color))
+                .or_else(|| (player..PLAYER_COLORS.len()).find(|color| !color_taken(color)))
+                .or_else(|| (0..PLAYER_COLORS.len()).find(|color| !color_taken(color)))
+                .unwrap_or(player % PLAYER_COLORS.len());
+
+            let name = match request.name.map(|name| name.trim().to_string()) {
+                Some(name) if !name.is_empty() => {
+                    match profiles.iter().any(|p| p.name.eq_ignore_ascii_case(&name)) {
+                        true => format!("{} ({})", name, player + 1),
+                        false => name,
+                    }
+                }
+                _ => default_name(player),
+            };
+
+            profiles.push(PlayerProfile {
+                name,
+                color,
+                dice_skin: request.dice_skin.unwrap_or_default(),
+                outline: request.outline.unwrap_or_default(),
+                banner: request.banner.unwrap_or_default(),
+            });
+        }
+
+        PlayerProfiles { profiles }
+    }
+
+    /// Display name of `player`
+    pub fn name(&self, player: usize) -> String {
+        if player == NEUTRAL {
+            return "Neutral".to_string();
+        }
+        self.profiles
+            .get(player)
+            .map_or_else(|| default_name(player), |profile| profile.name.clone())
+    }
+
+    /// Display name of `player` behind its banner, if it has one
+    pub fn title(&self, player: usize) -> String {
+        match self
+            .profile(player)
+            .and_then(|profile| profile.banner.icon())
+        {
+            Some(icon) => format!("{} {}", icon, self.name(player)),
+            None => self.name(player),
+        }
+    }
+
+    pub fn dice_skin(&self, player: usize) -> DiceSkin {
+        self.profile(player)
+            .map_or_else(DiceSkin::default, |profile| profile.dice_skin)
+    }
+
+    pub fn outline(&self, player: usize) -> OutlineStyle {
+        self.profile(player)
+            .map_or_else(OutlineStyle::default, |profile| profile.outline)
+    }
+
+    fn profile(&self, player: usize) -> Option<&PlayerProfile> {
+        self.profiles.get(player)
+    }
+
+    /// Color of `player` on the board and in the HUD
+    pub fn color(&self, player: usize) -> Color {
+        if player == NEUTRAL {
+            return NEUTRAL_COLOR;
+        }
+        let color = self
+            .profiles
+            .get(player)
+            .map_or(player, |profile| profile.color);
+        PLAYER_COLORS[color % PLAYER_COLORS.len()]
+    }
+}
+
+fn default_name(player: usize) -> String {
+    format!("Player {}", player + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_conflicts() {
+        let requests = [
+            ProfileRequest {
+                name: Some("Alex".to_string()),
+                color: Some(1),
+                ..default()
+            },
+            ProfileRequest {
+                name: Some("alex".to_string()),
+                color: Some(1),
+                ..default()
+            },
+            ProfileRequest {
+                name: Some("  ".to_string()),
+                color: None,
+                ..default()
+            },
+        ];
+        let profiles = PlayerProfiles::new(&requests, 4);
+
+        assert_eq!(profiles.name(0), "Alex");
+        assert_eq!(profiles.name(1), "alex (2)");
+        assert_eq!(profiles.name(2), "Player 3");
+        assert_eq!(profiles.name(3), "Player 4");
+        assert_eq!(profiles.name(5), "Player 6");
+
+        let colors: Vec<_> = profiles.profiles.iter().map(|p| p.color).collect();
+        assert_eq!(colors, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn looks_are_picked_by_name() {
+        let requests = [ProfileRequest {
+            name: Some("Alex".to_string()),
+            dice_skin: Some(parse_named(&DICE_SKINS, "Gilded").unwrap()),
+            outline: Some(parse_named(&OUTLINE_STYLES, "glow").unwrap()),
+            banner: Some(parse_named(&BANNERS, "omega").unwrap()),
+            ..default()
+        }];
+        let profiles = PlayerProfiles::new(&requests, 2);
+
+        assert_eq!(profiles.title(0), "\u{2126} Alex");
+        assert_eq!(profiles.dice_skin(0), DiceSkin::Gilded);
+        assert_eq!(profiles.outline(0), OutlineStyle::Glow);
+        // players without a request get the defaults
+        assert_eq!(profiles.title(1), "Player 2");
+        assert_eq!(profiles.dice_skin(1), DiceSkin::Classic);
+        assert_eq!(profiles.outline(3), OutlineStyle::Thin);
+        assert_eq!(
+            parse_named(&BANNERS, "crown"),
+            Err("expected one of none, dagger, lozenge, infinity, omega, arrow".to_string())
+        );
+    }
+}
@@ -0,0 +1,337 @@
+//! Puzzles: handcrafted positions where the first player has to reach a goal within a number of
+//! attacks, retried instantly with `R`.
+//!
+//! A puzzle file is a map file (see [`crate::map_file`]) with a move limit and an optional goal:
+//!
+//! ```text
+//! # the number of attacks allowed
+//! moves 3
+//! # conquer region 4, without this line the whole board has to be conquered
+//! goal capture 4
+//! ```
+//!
+//! Attacks go through the same validation as in a regular game, and the turn ending before the
+//! goal is reached fails the puzzle.
+
+use bevy::prelude::*;
+
+use crate::events::{DiceRollTimer, EventBattleResolved, EventBoardChanged, EventTurnEnd};
+use crate::game::{GameState, RegionId};
+use crate::map_file::{parse_number, MapError, MapErrorKind, MapFile};
+use crate::new_game::NewGame;
+use crate::ui::{spawn_toast, StackRankDiceUI};
+
+/// The player solving the puzzle
+pub const PUZZLE_PLAYER: usize = 0;
+
+/// Key that restarts the puzzle from its initial position
+const RETRY_KEY: KeyCode = KeyCode::R;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PuzzleGoal {
+    /// Own every region of the board
+    ConquerBoard,
+    /// Own the given region
+    Capture(RegionId),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PuzzleOutcome {
+    Solved,
+    Failed,
+}
+
+#[derive(Clone)]
+pub struct Puzzle {
+    pub map: MapFile,
+    /// Number of attacks the player may make
+    pub moves_allowed: usize,
+    pub goal: PuzzleGoal,
+}
+
+fn parse_goal(line: &str) -> Result<PuzzleGoal, MapErrorKind> {
+    let mut tokens = line.split_whitespace().skip(1);
+    let goal = match tokens.next() {
+        Some("win") => PuzzleGoal::ConquerBoard,
+        Some("capture") => PuzzleGoal::Capture(parse_number(tokens.next(), "region")?),
+        _ => return Err(MapErrorKind::InvalidGoal(line.to_string())),
+    };
+
+    match tokens.next() {
+        Some(_) => Err(MapErrorKind::InvalidGoal(line.to_string())),
+        None => Ok(goal),
+    }
+}
+
+impl Puzzle {
+    pub fn parse(source: &str) -> Result<Puzzle, MapError> {
+        let mut moves_allowed = None;
+        let mut goal = (0, PuzzleGoal::ConquerBoard);
+        let mut map_source = String::with_capacity(source.len());
+
+        // Puzzle lines are blanked out for the map parser, so it reports the same line numbers
+        for (index, line) in source.lines().enumerate() {
+            let error = |kind| MapError {
+                line: index + 1,
+                kind,
+            };
+
+            let content = line.split('#').next().unwrap_or("").trim();
+            match content.split_whitespace().next() {
+                Some("moves") => {
+                    let mut tokens = content.split_whitespace().skip(1);
+                    moves_allowed =
+                        Some(parse_number(tokens.next(), "number of moves").map_err(error)?);
+                }
+                Some("goal") => goal = (index + 1, parse_goal(content).map_err(error)?),
+                _ => map_source.push_str(line),
+            }
+            map_source.push('\n');
+        }
+
+        let map = MapFile::parse(&map_source)?;
+
+        let moves_allowed = moves_allowed.ok_or(MapError {
+            line: source.lines().count().max(1),
+            kind: MapErrorKind::MissingValue("number of moves"),
+        })?;
+
+        if let (line, PuzzleGoal::Capture(region)) = goal {
+            if region >= map.board.regions.len() {
+                return Err(MapError {
+                    line,
+                    kind: MapErrorKind::RegionOutOfRange(region),
+                });
+            }
+        }
+
+        Ok(Puzzle {
+            map,
+            moves_allowed,
+            goal: goal.1,
+        })
+    }
+
+    /// The position the puzzle starts from
+    pub fn initial_state(&self) -> GameState {
+        GameState {
+            board: self.map.board.clone(),
+            number_of_players: self.map.number_of_players,
+            ..default()
+        }
+    }
+
+    pub fn goal_reached(&self, game_state: &GameState) -> bool {
+        match self.goal {
+            PuzzleGoal::ConquerBoard => game_state.winner() == Some(PUZZLE_PLAYER),
+            PuzzleGoal::Capture(region) => game_state.board.regions[region].owner == PUZZLE_PLAYER,
+        }
+    }
+
+    /// Judges the position after `moves_made` attacks. `None` while the puzzle goes on.
+    pub fn judge(&self, game_state: &GameState, moves_made: usize) -> Option<PuzzleOutcome> {
+        if self.goal_reached(game_state) {
+            Some(PuzzleOutcome::Solved)
+        } else if moves_made >= self.moves_allowed || game_state.turn_of_player != PUZZLE_PLAYER {
+            Some(PuzzleOutcome::Failed)
+        } else {
+            None
+        }
+    }
+}
+
+/// Puzzle being played
+#[derive(Resource)]
+pub struct PuzzleState {
+    pub puzzle: Puzzle,
+    pub moves_made: usize,
+    pub outcome: Option<PuzzleOutcome>,
+}
+
+impl PuzzleState {
+    pub fn new(puzzle: Puzzle) -> Self {
+        PuzzleState {
+            puzzle,
+            moves_made: 0,
+            outcome: None,
+        }
+    }
+}
+
+pub(crate) fn judge_puzzle(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut event_battle_resolved_reader: EventReader<EventBattleResolved>,
+    mut event_turn_end_reader: EventReader<EventTurnEnd>,
+    game_state: Res<GameState>,
+    puzzle_state: Option<ResMut<PuzzleState>>,
+) {
+    let mut puzzle_state = match puzzle_state {
+        Some(puzzle_state) => puzzle_state,
+        None => return,
+    };
+
+    let moves = event_battle_resolved_reader.iter().count();
+    let turn_ended = event_turn_end_reader.iter().count() > 0;
+    if puzzle_state.outcome.is_some() || (moves == 0 && !turn_ended) {
+        return;
+    }
+
+    puzzle_state.moves_made += moves;
+    puzzle_state.outcome = puzzle_state
+        .puzzle
+        .judge(&game_state, puzzle_state.moves_made);
+
+    let message = match puzzle_state.outcome {
+        Some(PuzzleOutcome::Solved) => {
+            format!("Puzzle solved in {} moves!", puzzle_state.moves_made)
+        }
+        Some(PuzzleOutcome::Failed) => "Puzzle failed, press R to retry".to_string(),
+        None => return,
+    };
+    spawn_toast(&mut commands, &asset_server, message);
+}
+
+/// Puts the puzzle back to its initial position, once the dice of the battle being rolled
+/// settled so its result isn't applied to the fresh position
+pub(crate) fn retry_puzzle(
+    keys: Res<Input<KeyCode>>,
+    puzzle_state: Option<ResMut<PuzzleState>>,
+    mut game_state: ResMut<GameState>,
+    dice_roll_timer_query: Query<(), With<DiceRollTimer>>,
+    mut new_game: NewGame,
+    mut event_board_changed_writer: EventWriter<EventBoardChanged>,
+) {
+    let mut puzzle_state = match puzzle_state {
+        Some(puzzle_state) if keys.just_pressed(RETRY_KEY) => puzzle_state,
+        _ => return,
+    };
+    if !dice_roll_timer_query.is_empty() {
+        return;
+    }
+
+    // a solved puzzle ends the game when the whole board was conquered
+    if game_state.winner().is_some() {
+        return;
+    }
+
+    *game_state = puzzle_state.puzzle.initial_state();
+    puzzle_state.moves_made = 0;
+    puzzle_state.outcome = None;
+    new_game.reset();
+    event_board_changed_writer.send(EventBoardChanged);
+}
+
+/// Text area with the moves left to solve the puzzle
+#[derive(Component)]
+pub(crate) struct PuzzleText;
+
+pub(crate) fn setup_puzzle_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    puzzle_state: Option<Res<PuzzleState>>,
+) {
+    if puzzle_state.is_none() {
+        return;
+    }
+
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(40.0),
+                    left: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Name::new("Puzzle Text"))
+        .insert(PuzzleText)
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn puzzle_text_update(
+    puzzle_state: Option<Res<PuzzleState>>,
+    mut query: Query<&mut Text, With<PuzzleText>>,
+) {
+    let puzzle_state = match puzzle_state {
+        Some(puzzle_state) => puzzle_state,
+        None => return,
+    };
+
+    let goal = match puzzle_state.puzzle.goal {
+        PuzzleGoal::ConquerBoard => "CONQUER THE BOARD".to_string(),
+        PuzzleGoal::Capture(region) => format!(
+            "CAPTURE {}",
+            puzzle_state.puzzle.map.board.regions[region]
+                .label()
+                .to_uppercase()
+        ),
+    };
+    let moves_left = puzzle_state
+        .puzzle
+        .moves_allowed
+        .saturating_sub(puzzle_state.moves_made);
+    for mut text in &mut query {
+        text.sections[0].value = format!("{} IN {} MOVES", goal, moves_left);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUZZLE: &str =
+        "players 2\nregion 0 4 0,0\nregion 1 1 1,0\nregion 1 2 2,0\nmoves 2\ngoal capture 2\n";
+
+    #[test]
+    fn parses_moves_and_goal() {
+        let puzzle = Puzzle::parse(PUZZLE).unwrap();
+        assert_eq!(puzzle.moves_allowed, 2);
+        assert_eq!(puzzle.goal, PuzzleGoal::Capture(2));
+        assert_eq!(puzzle.map.board.regions.len(), 3);
+
+        let error = Puzzle::parse("players 2\nregion 0 4 0,0\nregion 1 1 1,0\n")
+            .err()
+            .unwrap();
+        assert_eq!(error.kind, MapErrorKind::MissingValue("number of moves"));
+
+        let error = Puzzle::parse("players 2\nregion 0 4 0,0\nmoves 1\ngoal capture 5\n")
+            .err()
+            .unwrap();
+        assert_eq!(error.line, 4);
+        assert_eq!(error.kind, MapErrorKind::RegionOutOfRange(5));
+    }
+
+    #[test]
+    fn judges_goal_and_move_limit() {
+        let puzzle = Puzzle::parse(PUZZLE).unwrap();
+        let mut game_state = puzzle.initial_state();
+        assert_eq!(puzzle.judge(&game_state, 0), None);
+
+        game_state.board.regions[1].owner = PUZZLE_PLAYER;
+        assert_eq!(puzzle.judge(&game_state, 1), None);
+        assert_eq!(puzzle.judge(&game_state, 2), Some(PuzzleOutcome::Failed));
+
+        game_state.board.regions[2].owner = PUZZLE_PLAYER;
+        assert_eq!(puzzle.judge(&game_state, 2), Some(PuzzleOutcome::Solved));
+    }
+
+    #[test]
+    fn ending_the_turn_fails() {
+        let puzzle = Puzzle::parse(PUZZLE).unwrap();
+        let mut game_state = puzzle.initial_state();
+        game_state.advance_turn();
+        assert_eq!(puzzle.judge(&game_state, 0), Some(PuzzleOutcome::Failed));
+    }
+}
@@ -0,0 +1,332 @@
+//! Battle clips: short animated GIFs of conquests, to share a memorable battle.
+//!
+//! Frames are drawn with the thumbnail renderer (see [`crate::thumbnails`]): the board when the
+//! dice start rolling, the two regions fighting, and the board once the region fell. Clips are
+//! saved in [`CLIPS_DIR`] of the data directory, at most one every [`CLIP_INTERVAL`], when enabled with
+//! `--record-battles`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::events::{EventBattleResolved, EventPlayerMoveStart};
+use crate::game::{GameState, RegionId};
+use crate::profiles::PlayerProfiles;
+use crate::rules::GameRules;
+use crate::stamp::ExportStamp;
+use crate::storage::{storage_dir, Location};
+use crate::thumbnails::{render_highlighted, render_thumbnail, Thumbnail};
+use crate::tiered_prng::PrngResource;
+use crate::ui::spawn_toast;
+
+pub const CLIPS_DIR: &str = "clips";
+
+/// Minimum time between two saved clips
+pub const CLIP_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Width and height of clip frames, in pixels
+const CLIP_SIZE: u32 = 192;
+
+/// Time every frame of a clip is shown, in hundredths of a second
+const FRAME_DELAY: u16 = 80;
+
+/// Literal codes written between two clear codes, few enough that the code size stays at 9 bits
+const CODES_PER_CLEAR: usize = 250;
+
+/// Colors of `frames`, at most 256. Colors past the 256th are drawn with the closest one.
+fn palette(frames: &[Thumbnail]) -> Vec<[u8; 3]> {
+    let mut colors: Vec<[u8; 3]> = Vec::new();
+    for pixel in frames.iter().flat_map(|frame| frame.pixels.iter()) {
+        if colors.len() < 256 && !colors.contains(pixel) {
+            colors.push(*pixel);
+        }
+    }
+
+    colors
+}
+
+fn closest_color(palette: &[[u8; 3]], color: &[u8; 3]) -> u8 {
+    let distance = |other: &[u8; 3]| -> i32 {
+        (0..3)
+            .map(|channel| (other[channel] as i32 - color[channel] as i32).pow(2))
+            .sum()
+    };
+
+    (0..palette.len())
+        .min_by_key(|index| distance(&palette[*index]))
+        .unwrap_or(0) as u8
+}
+
+/// Packs palette indices as 9-bit LZW codes without compressing them: every index is written as
+/// a literal, and the code table is cleared before it needs wider codes
+fn lzw_codes(indices: &[u8]) -> Vec<u8> {
+    const CLEAR: u16 = 256;
+    const END: u16 = 257;
+
+    let mut bytes = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    let mut write = |code: u16| {
+        buffer |= (code as u32) << bits;
+        bits += 9;
+        while bits >= 8 {
+            bytes.push(buffer as u8);
+            buffer >>= 8;
+            bits -= 8;
+        }
+    };
+
+    for chunk in indices.chunks(CODES_PER_CLEAR) {
+        write(CLEAR);
+        for index in chunk {
+            write(*index as u16);
+        }
+    }
+    write(END);
+    if bits > 0 {
+        bytes.push(buffer as u8);
+    }
+
+    bytes
+}
+
+/// Encodes same sized frames as a looping animated GIF, with `comment` in a comment extension
+pub fn encode_gif(frames: &[Thumbnail], delay: u16, comment: &str) -> Vec<u8> {
+    let (width, height) = frames
+        .first()
+        .map_or((0, 0), |frame| (frame.width as u16, frame.height as u16));
+    let palette = palette(frames);
+
+    let mut gif = b"GIF89a".to_vec();
+    // logical screen with a global table of 256 colors
+    gif.extend(width.to_le_bytes());
+    gif.extend(height.to_le_bytes());
+    gif.extend([0xf7, 0, 0]);
+    for index in 0..256 {
+        gif.extend(palette.get(index).unwrap_or(&[0, 0, 0]));
+    }
+    // loop forever
+    gif.extend([0x21, 0xff, 0x0b]);
+    gif.extend(b"NETSCAPE2.0");
+    gif.extend([0x03, 0x01, 0x00, 0x00, 0x00]);
+    if !comment.is_empty() {
+        gif.extend([0x21, 0xfe]);
+        for block in comment.as_bytes().chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend(block);
+        }
+        gif.push(0);
+    }
+
+    let mut indices: HashMap<[u8; 3], u8> = HashMap::new();
+    for frame in frames {
+        // graphic control extension with the delay of the frame
+        gif.extend([0x21, 0xf9, 0x04, 0x00]);
+        gif.extend(delay.to_le_bytes());
+        gif.extend([0x00, 0x00]);
+        // image descriptor covering the whole screen
+        gif.push(0x2c);
+        gif.extend([0, 0, 0, 0]);
+        gif.extend(width.to_le_bytes());
+        gif.extend(height.to_le_bytes());
+        gif.push(0x00);
+
+        let frame_indices: Vec<u8> = frame
+            .pixels
+            .iter()
+            .map(|pixel| {
+                *indices
+                    .entry(*pixel)
+                    .or_insert_with(|| closest_color(&palette, pixel))
+            })
+            .collect();
+        gif.push(8);
+        for block in lzw_codes(&frame_indices).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend(block);
+        }
+        gif.push(0);
+    }
+    gif.push(0x3b);
+
+    gif
+}
+
+/// Records conquests as clips
+#[derive(Resource, Default)]
+pub struct BattleRecorder {
+    pub enabled: bool,
+    /// The board when the dice of the current battle started rolling
+    opening: Option<Thumbnail>,
+    /// When the last clip was saved, since startup
+    last_clip: Option<Duration>,
+    clips_saved: usize,
+}
+
+impl BattleRecorder {
+    pub fn new(enabled: bool) -> Self {
+        BattleRecorder {
+            enabled,
+            ..default()
+        }
+    }
+
+    /// Whether a clip may be saved at `now`, since startup
+    pub fn can_record(&self, now: Duration) -> bool {
+        self.enabled
+            && match self.last_clip {
+                Some(last_clip) => now >= last_clip + CLIP_INTERVAL,
+                None => true,
+            }
+    }
+
+    fn save(
+        &mut self,
+        frames: &[Thumbnail],
+        stamp: &ExportStamp,
+        now: Duration,
+    ) -> io::Result<PathBuf> {
+        let dir = storage_dir(Location::Data).join(CLIPS_DIR);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!(
+            "battle_{}_{}.gif",
+            std::process::id(),
+            self.clips_saved + 1
+        ));
+        fs::write(&path, encode_gif(frames, FRAME_DELAY, &stamp.to_string()))?;
+
+        self.last_clip = Some(now);
+        self.clips_saved += 1;
+        Ok(path)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn record_battles(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut recorder: ResMut<BattleRecorder>,
+    game_state: Res<GameState>,
+    profiles: Res<PlayerProfiles>,
+    prng: Res<PrngResource>,
+    rules: Res<GameRules>,
+    mut event_player_move_start_reader: EventReader<EventPlayerMoveStart>,
+    mut event_battle_resolved_reader: EventReader<EventBattleResolved>,
+) {
+    let battle_started = event_player_move_start_reader.iter().count() > 0;
+    let conquests: Vec<(RegionId, RegionId)> = event_battle_resolved_reader
+        .iter()
+        .filter(|e| e.attacker_won)
+        .map(|e| (e.region_1, e.region_2))
+        .collect();
+    if !recorder.can_record(time.elapsed()) {
+        return;
+    }
+
+    if battle_started {
+        recorder.opening = Some(render_thumbnail(&game_state.board, &profiles, CLIP_SIZE));
+    }
+
+    for (attacker, defender) in conquests {
+        let opening = match recorder.opening.take() {
+            Some(opening) => opening,
+            None => continue,
+        };
+        let board = &game_state.board;
+
+        let frames = [
+            opening,
+            render_highlighted(board, &profiles, CLIP_SIZE, &[attacker, defender]),
+            render_thumbnail(board, &profiles, CLIP_SIZE),
+        ];
+        match recorder.save(&frames, &ExportStamp::new(&prng, &rules), time.elapsed()) {
+            Ok(path) => spawn_toast(
+                &mut commands,
+                &asset_server,
+                format!("Battle saved to {}", path.display()),
+            ),
+            Err(err) => warn!("Failed to save a battle clip: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(pixels: Vec<[u8; 3]>) -> Thumbnail {
+        Thumbnail {
+            width: 2,
+            height: 2,
+            pixels,
+        }
+    }
+
+    /// Reads back the 9-bit codes of an LZW stream
+    fn read_codes(bytes: &[u8]) -> Vec<u16> {
+        let mut codes = Vec::new();
+        let (mut buffer, mut bits) = (0u32, 0u32);
+        for byte in bytes {
+            buffer |= (*byte as u32) << bits;
+            bits += 8;
+            while bits >= 9 {
+                codes.push((buffer & 0x1ff) as u16);
+                buffer >>= 9;
+                bits -= 9;
+            }
+        }
+
+        codes
+    }
+
+    #[test]
+    fn codes_are_literal_with_regular_clears() {
+        let indices: Vec<u8> = (0..300).map(|i| (i % 7) as u8).collect();
+        let codes = read_codes(&lzw_codes(&indices));
+
+        assert_eq!(codes[0], 256);
+        assert_eq!(codes[CODES_PER_CLEAR + 1], 256);
+        assert_eq!(codes[codes.len() - 1], 257);
+        let literals: Vec<u8> = codes
+            .iter()
+            .filter(|code| **code < 256)
+            .map(|code| *code as u8)
+            .collect();
+        assert_eq!(literals, indices);
+    }
+
+    #[test]
+    fn encodes_looping_gif() {
+        let red = [255, 0, 0];
+        let blue = [0, 0, 255];
+        let gif = encode_gif(
+            &[frame(vec![red, red, blue, blue]), frame(vec![blue; 4])],
+            FRAME_DELAY,
+            "seed 1 2",
+        );
+
+        assert!(gif.starts_with(b"GIF89a\x02\x00\x02\x00"));
+        // red and blue open the global color table
+        assert_eq!(&gif[13..19], &[255, 0, 0, 0, 0, 255]);
+        // the comment follows the looping extension
+        let comment = 13 + 256 * 3 + 19;
+        assert_eq!(&gif[comment..comment + 3], &[0x21, 0xfe, 8]);
+        assert_eq!(&gif[comment + 3..comment + 11], b"seed 1 2");
+        assert_eq!(gif.last(), Some(&0x3b));
+    }
+
+    #[test]
+    fn clips_are_rate_limited() {
+        let mut recorder = BattleRecorder::default();
+        assert!(!recorder.can_record(Duration::ZERO));
+
+        recorder.enabled = true;
+        recorder.last_clip = Some(Duration::from_secs(10));
+        assert!(!recorder.can_record(Duration::from_secs(20)));
+        assert!(recorder.can_record(Duration::from_secs(25)));
+    }
+}
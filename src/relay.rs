@@ -0,0 +1,334 @@
+//! Relay for network games between machines behind NATs, which can't reach each other without
+//! port forwarding but can both reach the relay.
+//!
+//! The host and its players only open connections to the relay, which pairs them by room and
+//! then copies bytes both ways. A relayed connection is a plain TCP stream once paired, so the
+//! WebSocket of the API runs over it exactly as over a direct connection. The relay speaks a
+//! line-based protocol before pairing:
+//!
+//! ```text
+//! host -> relay   HOST <room>           opens a room, answered OK or ERR <reason>, kept open
+//! relay -> host   CONNECT <id>          a player is waiting in the room
+//! host -> relay   ACCEPT <room> <id>    on a new connection, paired with player <id>
+//! player -> relay JOIN <room>           answered OK once paired with the host, or ERR <reason>
+//! ```
+//!
+//! Run a relay with `cargo run -- relay --port 7800`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+/// Longest line of the protocol, longer ones are refused
+const MAX_LINE: usize = 128;
+
+/// How long a player waits for the host to accept it before the relay gives up on it
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Room of a hosted game on a relay
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelayRoom {
+    /// `<host>:<port>` address of the relay
+    pub relay: String,
+    pub room: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RelayCommand {
+    Host(String),
+    Join(String),
+    Accept(String, u64),
+}
+
+impl RelayCommand {
+    pub fn parse(line: &str) -> Option<RelayCommand> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["HOST", room] => Some(RelayCommand::Host(room.to_string())),
+            ["JOIN", room] => Some(RelayCommand::Join(room.to_string())),
+            ["ACCEPT", room, id] => Some(RelayCommand::Accept(room.to_string(), id.parse().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RelayCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelayCommand::Host(room) => write!(f, "HOST {}", room),
+            RelayCommand::Join(room) => write!(f, "JOIN {}", room),
+            RelayCommand::Accept(room, id) => write!(f, "ACCEPT {} {}", room, id),
+        }
+    }
+}
+
+/// Reads a line without reading past it, the bytes after it belong to the WebSocket
+fn read_line(stream: &mut TcpStream) -> io::Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        match byte[0] {
+            b'\n' => break,
+            byte if line.len() < MAX_LINE => line.push(byte),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long")),
+        }
+    }
+
+    String::from_utf8(line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_line(stream: &mut TcpStream, line: &str) -> io::Result<()> {
+    stream.write_all(format!("{}\n", line).as_bytes())
+}
+
+/// Reads the answer of the relay, an error for anything else than `OK`
+fn expect_ok(stream: &mut TcpStream) -> io::Result<()> {
+    match read_line(stream)?.as_str() {
+        "OK" => Ok(()),
+        answer => Err(io::Error::other(
+            answer.strip_prefix("ERR ").unwrap_or(answer).to_string(),
+        )),
+    }
+}
+
+/// Opens `room` on the relay and hands `accept` a connection paired with every player joining
+/// it, until the relay closes the room
+// hosts and players only reach the relay with the `api` feature
+#[allow(dead_code)]
+pub fn host(room: &RelayRoom, mut accept: impl FnMut(TcpStream)) -> io::Result<()> {
+    let mut control = TcpStream::connect(&room.relay)?;
+    write_line(
+        &mut control,
+        &RelayCommand::Host(room.room.clone()).to_string(),
+    )?;
+    expect_ok(&mut control)?;
+    info!("Hosting room {} on the relay {}", room.room, room.relay);
+
+    loop {
+        let line = read_line(&mut control)?;
+        let id = match line.strip_prefix("CONNECT ").map(str::parse::<u64>) {
+            Some(Ok(id)) => id,
+            _ => continue,
+        };
+
+        let mut stream = TcpStream::connect(&room.relay)?;
+        write_line(
+            &mut stream,
+            &RelayCommand::Accept(room.room.clone(), id).to_string(),
+        )?;
+        accept(stream);
+    }
+}
+
+/// Joins `room` on the relay, the stream is paired with the host once this returns
+#[allow(dead_code)]
+pub fn join(room: &RelayRoom) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(&room.relay)?;
+    write_line(
+        &mut stream,
+        &RelayCommand::Join(room.room.clone()).to_string(),
+    )?;
+    expect_ok(&mut stream)?;
+
+    Ok(stream)
+}
+
+/// Room open on the relay
+struct OpenRoom {
+    /// Connection the host is told about joining players on
+    control: TcpStream,
+    /// Players waiting for the host to accept them, by id
+    waiting: HashMap<u64, TcpStream>,
+}
+
+#[derive(Default)]
+struct Rooms {
+    rooms: HashMap<String, OpenRoom>,
+    next_id: u64,
+}
+
+/// Relays games on `port` of every network interface until the process ends
+pub fn serve(port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    println!("Relaying games on port {}", port);
+    serve_listener(listener);
+
+    Ok(())
+}
+
+pub fn serve_listener(listener: TcpListener) {
+    let rooms = Arc::new(Mutex::new(Rooms::default()));
+    for stream in listener.incoming().flatten() {
+        let rooms = rooms.clone();
+        std::thread::spawn(move || {
+            let _ = relay_connection(stream, &rooms);
+        });
+    }
+}
+
+fn relay_connection(mut stream: TcpStream, rooms: &Mutex<Rooms>) -> io::Result<()> {
+    let command = match RelayCommand::parse(&read_line(&mut stream)?) {
+        Some(command) => command,
+        None => return write_line(&mut stream, "ERR unknown command"),
+    };
+
+    match command {
+        RelayCommand::Host(room) => {
+            {
+                let mut rooms = rooms.lock().unwrap();
+                if rooms.rooms.contains_key(&room) {
+                    return write_line(&mut stream, "ERR room taken");
+                }
+                let control = stream.try_clone()?;
+                rooms.rooms.insert(
+                    room.clone(),
+                    OpenRoom {
+                        control,
+                        waiting: HashMap::new(),
+                    },
+                );
+            }
+            write_line(&mut stream, "OK")?;
+
+            // the room stays open as long as the host keeps its connection
+            while read_line(&mut stream).is_ok() {}
+            rooms.lock().unwrap().rooms.remove(&room);
+            Ok(())
+        }
+        RelayCommand::Join(room) => {
+            // the lock is released before writing, a stalled host must not stall every room
+            let (id, mut control) = {
+                let mut rooms = rooms.lock().unwrap();
+                let id = rooms.next_id;
+                rooms.next_id += 1;
+                let open_room = match rooms.rooms.get_mut(&room) {
+                    Some(open_room) => open_room,
+                    None => {
+                        drop(rooms);
+                        return write_line(&mut stream, "ERR no such game");
+                    }
+                };
+                let control = open_room.control.try_clone()?;
+                open_room.waiting.insert(id, stream.try_clone()?);
+                (id, control)
+            };
+
+            if write_line(&mut control, &format!("CONNECT {}", id)).is_err() {
+                take_waiting(rooms, &room, id);
+                return write_line(&mut stream, "ERR host unreachable");
+            }
+
+            // players the host never accepts are dropped rather than kept in the room forever
+            std::thread::sleep(ACCEPT_TIMEOUT);
+            match take_waiting(rooms, &room, id) {
+                Some(mut player) => write_line(&mut player, "ERR host did not accept"),
+                None => Ok(()),
+            }
+        }
+        RelayCommand::Accept(room, id) => {
+            let mut player = match take_waiting(rooms, &room, id) {
+                Some(player) => player,
+                None => return Ok(()),
+            };
+
+            write_line(&mut player, "OK")?;
+            splice(player, stream)
+        }
+    }
+}
+
+/// Removes player `id` from the players waiting in `room`
+fn take_waiting(rooms: &Mutex<Rooms>, room: &str, id: u64) -> Option<TcpStream> {
+    rooms
+        .lock()
+        .unwrap()
+        .rooms
+        .get_mut(room)
+        .and_then(|open_room| open_room.waiting.remove(&id))
+}
+
+/// Copies bytes both ways between two streams until either side closes
+fn splice(a: TcpStream, b: TcpStream) -> io::Result<()> {
+    let (mut a_reader, mut b_writer) = (a.try_clone()?, b.try_clone()?);
+    let forward = std::thread::spawn(move || {
+        let _ = io::copy(&mut a_reader, &mut b_writer);
+        let _ = b_writer.shutdown(Shutdown::Write);
+    });
+
+    let (mut b_reader, mut a_writer) = (b, a);
+    let _ = io::copy(&mut b_reader, &mut a_writer);
+    let _ = a_writer.shutdown(Shutdown::Write);
+    let _ = forward.join();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        for command in [
+            RelayCommand::Host("5R2C8-0K7ZH".to_string()),
+            RelayCommand::Join("5R2C8-0K7ZH".to_string()),
+            RelayCommand::Accept("5R2C8-0K7ZH".to_string(), 3),
+        ] {
+            assert_eq!(RelayCommand::parse(&command.to_string()), Some(command));
+        }
+        assert_eq!(RelayCommand::parse("ACCEPT room first"), None);
+        assert_eq!(RelayCommand::parse("HOST"), None);
+    }
+
+    #[test]
+    fn relays_players_to_the_host() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let room = RelayRoom {
+            relay: listener.local_addr().unwrap().to_string(),
+            room: "ROOM".to_string(),
+        };
+        std::thread::spawn(move || serve_listener(listener));
+
+        // the host echoes what players send
+        let hosted = room.clone();
+        std::thread::spawn(move || {
+            host(&hosted, |mut stream| {
+                std::thread::spawn(move || {
+                    let mut reader = stream.try_clone().unwrap();
+                    let _ = io::copy(&mut reader, &mut stream);
+                });
+            })
+        });
+        // joining before the host opened the room finds no game
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        let mut stream = loop {
+            match join(&room) {
+                Ok(stream) => break stream,
+                Err(err) if std::time::Instant::now() > deadline => {
+                    panic!("couldn't join the room: {}", err)
+                }
+                Err(_) => std::thread::sleep(Duration::from_millis(10)),
+            }
+        };
+
+        stream.write_all(b"hello\n").unwrap();
+        assert_eq!(read_line(&mut stream).unwrap(), "hello");
+
+        let missing = RelayRoom {
+            room: "NOWHERE".to_string(),
+            ..room
+        };
+        assert_eq!(
+            join(&missing).unwrap_err().to_string(),
+            "no such game".to_string()
+        );
+    }
+}
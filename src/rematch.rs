@@ -0,0 +1,283 @@
+//! Rematches: once a game, or the match it belongs to, is over the game-over screen offers to
+//! play again right away, on a fresh board or on the same one. The player after the one that
+//! started the last game starts the rematch, so nobody always moves first, and the wins of every
+//! game played since launch are tallied as the series score.
+
+use bevy::prelude::*;
+use rand::RngCore;
+
+use crate::board::BoardDrawer;
+use crate::game::{generate_board, Board, GameState};
+use crate::new_game::NewGame;
+use crate::profiles::PlayerProfiles;
+use crate::rules::GameRules;
+use crate::session::MatchSession;
+use crate::tiered_prng::{get_randomness, PrngResource};
+use crate::ui::StackRankDiceUI;
+
+#[derive(Resource, Default)]
+pub struct Rematch {
+    /// Play rematches on the board of the last game instead of a fresh one
+    pub same_board: bool,
+    /// Player that started the current game
+    pub first_player: usize,
+    /// Games won since launch, by player
+    pub scores: Vec<usize>,
+    /// Board the current game started on
+    board: Option<Board>,
+    /// HUD elements hidden by the game-over screen, shown again by a rematch
+    hidden_hud: Vec<Entity>,
+}
+
+impl Rematch {
+    pub fn new(same_board: bool) -> Self {
+        Rematch {
+            same_board,
+            ..default()
+        }
+    }
+
+    pub fn record_win(&mut self, player: usize, number_of_players: usize) {
+        if self.scores.len() < number_of_players {
+            self.scores.resize(number_of_players, 0);
+        }
+        self.scores[player] += 1;
+    }
+
+    /// Whether more than one game was played, so a series score means something
+    pub fn is_series(&self) -> bool {
+        self.scores.iter().sum::<usize>() > 1
+    }
+
+    /// Series score formatted as "2 - 1"
+    pub fn score_text(&self) -> String {
+        self.scores
+            .iter()
+            .map(|score| score.to_string())
+            .collect::<Vec<String>>()
+            .join(" - ")
+    }
+
+    /// Moves the first move on to the next player, who starts the rematch
+    pub fn rotate_first_player(&mut self, number_of_players: usize) -> usize {
+        self.first_player = (self.first_player + 1) % number_of_players.max(1);
+        self.first_player
+    }
+
+    /// Hides the HUD for the game-over screen, remembering what to show again
+    pub(crate) fn hide_hud<'a>(
+        &mut self,
+        hud: impl Iterator<Item = (Entity, Mut<'a, Visibility>)>,
+    ) {
+        for (entity, mut visibility) in hud {
+            if visibility.is_visible {
+                visibility.is_visible = false;
+                self.hidden_hud.push(entity);
+            }
+        }
+    }
+
+    /// Starts a series of games on the board of `game_state`, remembering the board as it was
+    /// before the players were seated and who moves first on it, for a rematch on the same board
+    pub(crate) fn new_series(&mut self, game_state: &GameState) {
+        self.board = Some(game_state.unseated_board());
+        self.first_player = game_state.first_player;
+        self.scores.clear();
+    }
+
+    /// Shows the HUD hidden by the game-over screen again
+    pub(crate) fn show_hud(&mut self, visibility_query: &mut Query<&mut Visibility>) {
+        for entity in std::mem::take(&mut self.hidden_hud) {
+            if let Ok(mut visibility) = visibility_query.get_mut(entity) {
+                visibility.is_visible = true;
+            }
+        }
+    }
+}
+
+/// Anything spawned by the final game-over screen, despawned by a rematch
+#[derive(Component)]
+pub(crate) struct GameOverScreen;
+
+/// Buttons of the game-over screen
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RematchButton {
+    Rematch,
+    /// Switches between a fresh board and the same board for the rematch
+    ToggleBoard,
+}
+
+fn board_choice_text(same_board: bool) -> String {
+    match same_board {
+        true => "SAME BOARD".to_string(),
+        false => "NEW BOARD".to_string(),
+    }
+}
+
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    button: RematchButton,
+    label: String,
+) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                margin: UiRect::horizontal(Val::Px(8.0)),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
+            ..default()
+        })
+        .insert(button)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+            ));
+        });
+}
+
+/// Spawns the rematch buttons under the game-over message
+pub(crate) fn spawn_rematch_buttons(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    rematch: &Rematch,
+) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Percent(38.0),
+                    left: Val::Percent(45.0),
+                    ..default()
+                },
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Name::new("Rematch Buttons"))
+        .insert(GameOverScreen)
+        .insert(StackRankDiceUI)
+        .with_children(|parent| {
+            spawn_button(
+                parent,
+                asset_server,
+                RematchButton::Rematch,
+                "REMATCH".to_string(),
+            );
+            spawn_button(
+                parent,
+                asset_server,
+                RematchButton::ToggleBoard,
+                board_choice_text(rematch.same_board),
+            );
+        });
+}
+
+/// Remembers the board the first game starts on, before the players were seated, and who moves
+/// first in it, for a rematch on the same board
+pub(crate) fn remember_first_board(mut rematch: ResMut<Rematch>, game_state: Res<GameState>) {
+    rematch.new_series(&game_state);
+}
+
+/// Starts a rematch, or switches the board it is played on, when a button of the game-over
+/// screen is clicked
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn rematch_clicked(
+    mut commands: Commands,
+    interaction_query: Query<(&Interaction, &RematchButton, &Children), Changed<Interaction>>,
+    mut text_query: Query<&mut Text>,
+    mut rematch: ResMut<Rematch>,
+    mut prng: ResMut<PrngResource>,
+    rules: Res<GameRules>,
+    match_session: Option<ResMut<MatchSession>>,
+    mut game_state: ResMut<GameState>,
+    game_over_query: Query<Entity, With<GameOverScreen>>,
+    mut visibility_query: Query<&mut Visibility>,
+    profiles: Res<PlayerProfiles>,
+    mut board_drawer: BoardDrawer,
+    mut new_game: NewGame,
+) {
+    let mut start = false;
+    for (interaction, button, children) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        match button {
+            RematchButton::Rematch => start = true,
+            RematchButton::ToggleBoard => {
+                rematch.same_board = !rematch.same_board;
+                for child in children.iter() {
+                    if let Ok(mut text) = text_query.get_mut(*child) {
+                        text.sections[0].value = board_choice_text(rematch.same_board);
+                    }
+                }
+            }
+        }
+    }
+
+    if !start {
+        return;
+    }
+
+    let number_of_players = game_state.number_of_players;
+    let board = match (rematch.same_board, rematch.board.take()) {
+        (true, Some(board)) => board,
+        _ => {
+            prng.world_seed = get_randomness(prng.world_seed).next_u64();
+            generate_board(&rules, get_randomness(prng.world_seed))
+        }
+    };
+    rematch.board = Some(board.clone());
+
+    *game_state = GameState {
+        board,
+        number_of_players,
+        ..default()
+    };
+    game_state.seat_players(rematch.rotate_first_player(number_of_players), &rules);
+    if let Some(mut match_session) = match_session {
+        *match_session = MatchSession::new(match_session.best_of, number_of_players);
+    }
+    new_game.reset();
+
+    for entity in game_over_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    rematch.show_hud(&mut visibility_query);
+    board_drawer.redraw(&game_state, &profiles);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_move_rotates_through_the_players() {
+        let mut rematch = Rematch::default();
+
+        assert_eq!(rematch.rotate_first_player(3), 1);
+        assert_eq!(rematch.rotate_first_player(3), 2);
+        assert_eq!(rematch.rotate_first_player(3), 0);
+    }
+
+    #[test]
+    fn series_score_counts_every_game() {
+        let mut rematch = Rematch::default();
+
+        rematch.record_win(1, 2);
+        assert!(!rematch.is_series());
+        rematch.record_win(1, 2);
+        rematch.record_win(0, 2);
+        assert!(rematch.is_series());
+        assert_eq!(rematch.score_text(), "1 - 2");
+    }
+}
@@ -0,0 +1,1141 @@
+//! Plain text move log of a game, enough to reconstruct it move by move.
+//!
+//! ```text
+//! # format version, see crate::versioning
+//! version 4
+//! seed <world_seed> <env_seed>
+//! players 2
+//! # first <player moving first>, optional
+//! first 1
+//! # board <generator> <board size> <regions per player> <dice per region> <noise scale>
+//! #   <noise strength> <seed spacing>, optional
+//! board noise 20 16 4 6 0.5 4
+//! # regions <key of region 0> <key of region 1> ..., optional
+//! regions 9b1c0e2f4d6a8b3c 05e7d9f1a3c5b7e9
+//! # attack <attacker> <defender> <attacker roll> <defender roll> <transferred dice>
+//! attack 4 7 6,2,3 1,5 2
+//! # reinforce <region>
+//! reinforce 4
+//! pass
+//! # ping <player> <region>
+//! ping 1 4
+//! # truce <player> <player> <turn it expires at>
+//! truce 0 2 9
+//! # winner <player>, the game ended on a timeout, objectives, a scenario or a stalemate
+//! winner 1
+//! # hash <zobrist hash of the state after the previous move>
+//! hash 3f2a9c0d1e4b5a67
+//! ```
+//!
+//! The `board` header names the generator of the board and the rules it was generated with, see
+//! [`crate::generators`], so the board is rebuilt from the seed whatever rules the replay is played
+//! with. Moves refer to regions by their index on the board. The `regions` header lists the stable
+//! [`RegionKey`] of every index, so the replay still finds its regions on a board whose regions
+//! were reordered, and fails on a board that lost one instead of playing on the wrong regions.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    first_player, transfer_allowed, BattleOutcome, Board, DiceRoll, GameLogEntry, GameState, Pact,
+    RegionId, RegionKey,
+};
+use crate::generators::board_generator;
+use crate::map_file::MAX_PLAYERS;
+use crate::rules::GameRules;
+use crate::stamp::ExportStamp;
+use crate::versioning::{from_unversioned, migrate, Migration, VersionError};
+
+/// Migrations of older replays, the `n`th one turns version `n` into version `n + 1`
+const MIGRATIONS: &[Migration] = &[
+    from_unversioned,
+    with_region_keys,
+    with_first_player,
+    with_board_header,
+];
+
+/// Version 2 added the optional `regions` header, older replays only refer to region indices
+fn with_region_keys(source: &str) -> String {
+    source.to_string()
+}
+
+/// Version 3 added the optional `first` header, older replays start with the player the rules
+/// draw from the world seed
+fn with_first_player(source: &str) -> String {
+    source.to_string()
+}
+
+/// Version 4 added the optional `board` header, older replays are played on the board the rules
+/// they are played with generate
+fn with_board_header(source: &str) -> String {
+    source.to_string()
+}
+
+/// Version of the replays written by this release
+pub const VERSION: u32 = MIGRATIONS.len() as u32;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplayMove {
+    Attack {
+        attacker: usize,
+        defender: usize,
+        attacker_roll: Vec<usize>,
+        defender_roll: Vec<usize>,
+        transferred: usize,
+    },
+    /// Places a die from the reserve onto a region during the reinforcement phase
+    Reinforce(usize),
+    /// The player in turn ends its attacks
+    Pass,
+    /// Expected [`GameState::zobrist_hash`] of the game after the previous moves
+    Checkpoint(u64),
+    /// A player pointed the other players to a region, without changing the game
+    Ping { player: usize, region: usize },
+    /// The game was decided for a player before it owned the whole board
+    Winner(usize),
+    /// Two players agreed on a truce, lasting until the turn counter reaches `expires_at_turn`
+    Truce {
+        player_1: usize,
+        player_2: usize,
+        expires_at_turn: usize,
+    },
+}
+
+/// Generator of the board of a replay, and the rules it was generated with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoardHeader {
+    pub generator: String,
+    pub board_size: isize,
+    pub regions_per_player: usize,
+    pub dice_per_region: usize,
+    pub noise_scale: f32,
+    pub noise_strength: f32,
+    pub min_seed_spacing: usize,
+}
+
+impl BoardHeader {
+    /// Generator of the boards of `rules`
+    pub fn new(rules: &GameRules) -> Self {
+        BoardHeader {
+            generator: rules.board_generator.clone(),
+            board_size: rules.board_size,
+            regions_per_player: rules.regions_per_player,
+            dice_per_region: rules.dice_per_region,
+            noise_scale: rules.noise_scale,
+            noise_strength: rules.noise_strength,
+            min_seed_spacing: rules.min_seed_spacing,
+        }
+    }
+
+    /// `rules` generating their boards with this generator
+    pub fn rules(&self, rules: &GameRules) -> GameRules {
+        GameRules {
+            board_generator: self.generator.clone(),
+            board_size: self.board_size,
+            regions_per_player: self.regions_per_player,
+            dice_per_region: self.dice_per_region,
+            noise_scale: self.noise_scale,
+            noise_strength: self.noise_strength,
+            min_seed_spacing: self.min_seed_spacing,
+            ..rules.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub world_seed: u64,
+    pub env_seed: u64,
+    pub number_of_players: usize,
+    /// Player that moved first, none for replays starting with the player the rules draw from
+    /// the world seed
+    pub first_player: Option<usize>,
+    /// Keys of the regions the moves refer to, indexed by the region ids of the moves. Empty
+    /// for replays that only refer to indices.
+    pub region_keys: Vec<RegionKey>,
+    pub moves: Vec<ReplayMove>,
+    /// Generator of the board, none for replays played on the board of the rules they are
+    /// played with
+    pub board: Option<BoardHeader>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayErrorKind {
+    UnknownDirective(String),
+    MissingHeader(&'static str),
+    DuplicateHeader(&'static str),
+    MissingValue(&'static str),
+    InvalidNumber(String),
+    InvalidDieFace(String),
+    PlayersOutOfRange(usize),
+    UnknownRegion(usize),
+    InvalidRegionKey(String),
+    UnknownGenerator(String),
+    /// The board has no region with the key of the replay
+    MissingRegion(RegionKey),
+    NotOwnedByPlayerInTurn(usize),
+    NotOpponents(usize, usize),
+    AlreadyMoved(usize),
+    RollSizeMismatch {
+        region: usize,
+        expected: usize,
+    },
+    InvalidTransfer(usize),
+    UnresolvedTie,
+    CannotReinforce(usize),
+    InvalidHash(String),
+    HashMismatch {
+        expected: u64,
+        found: u64,
+    },
+    PlayersMismatch {
+        expected: usize,
+    },
+    Version(VersionError),
+}
+
+/// Error found while parsing a replay, with the 1-based line it was found on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayError {
+    pub line: usize,
+    pub kind: ReplayErrorKind,
+}
+
+/// Error found while applying a replay, with the 1-based number of the offending move
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayMoveError {
+    pub move_number: usize,
+    pub kind: ReplayErrorKind,
+}
+
+impl fmt::Display for ReplayErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayErrorKind::UnknownDirective(directive) => {
+                write!(f, "unknown directive `{}`", directive)
+            }
+            ReplayErrorKind::MissingHeader(name) => write!(f, "missing `{}` header", name),
+            ReplayErrorKind::DuplicateHeader(name) => {
+                write!(f, "`{}` header is declared more than once", name)
+            }
+            ReplayErrorKind::MissingValue(name) => write!(f, "missing {}", name),
+            ReplayErrorKind::InvalidNumber(value) => {
+                write!(f, "`{}` is not a valid number", value)
+            }
+            ReplayErrorKind::InvalidDieFace(value) => {
+                write!(f, "`{}` is not a die face between 1 and 6", value)
+            }
+            ReplayErrorKind::PlayersOutOfRange(players) => write!(
+                f,
+                "{} players declared, expected 2 to {}",
+                players, MAX_PLAYERS
+            ),
+            ReplayErrorKind::UnknownRegion(region) => write!(f, "there is no region {}", region),
+            ReplayErrorKind::InvalidRegionKey(key) => {
+                write!(f, "`{}` is not a valid region key", key)
+            }
+            ReplayErrorKind::MissingRegion(key) => {
+                write!(f, "the board has no region with key {}", key)
+            }
+            ReplayErrorKind::NotOwnedByPlayerInTurn(region) => {
+                write!(f, "region {} is not owned by the player in turn", region)
+            }
+            ReplayErrorKind::NotOpponents(region_1, region_2) => {
+                write!(
+                    f,
+                    "regions {} and {} can't attack each other",
+                    region_1, region_2
+                )
+            }
+            ReplayErrorKind::AlreadyMoved(region) => {
+                write!(f, "region {} has already attacked this turn", region)
+            }
+            ReplayErrorKind::RollSizeMismatch { region, expected } => {
+                write!(f, "region {} must roll {} dice", region, expected)
+            }
+            ReplayErrorKind::InvalidTransfer(transferred) => {
+                write!(f, "{} dice can't be transferred", transferred)
+            }
+            ReplayErrorKind::InvalidHash(hash) => write!(f, "'{}' is not a valid hash", hash),
+            ReplayErrorKind::UnknownGenerator(name) => {
+                write!(f, "there is no board generator {}", name)
+            }
+            ReplayErrorKind::HashMismatch { expected, found } => write!(
+                f,
+                "game diverged from the replay, expected hash {:016x} but found {:016x}",
+                expected, found
+            ),
+            ReplayErrorKind::CannotReinforce(region) => {
+                write!(f, "region {} can't be reinforced", region)
+            }
+            ReplayErrorKind::UnresolvedTie => {
+                write!(f, "tied rolls have to be rerolled")
+            }
+            ReplayErrorKind::PlayersMismatch { expected } => {
+                write!(f, "replay is for {} players", expected)
+            }
+            ReplayErrorKind::Version(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl fmt::Display for ReplayMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "move {}: {}", self.move_number, self.kind)
+    }
+}
+
+impl std::error::Error for ReplayMoveError {}
+
+fn parse_number<T: std::str::FromStr>(
+    value: Option<&str>,
+    name: &'static str,
+) -> Result<T, ReplayErrorKind> {
+    let value = value.ok_or(ReplayErrorKind::MissingValue(name))?;
+    value
+        .parse()
+        .map_err(|_| ReplayErrorKind::InvalidNumber(value.to_string()))
+}
+
+fn parse_roll(value: Option<&str>, name: &'static str) -> Result<Vec<usize>, ReplayErrorKind> {
+    let value = value.ok_or(ReplayErrorKind::MissingValue(name))?;
+    value
+        .split(',')
+        .map(|face| match face.parse::<usize>() {
+            Ok(face) if (1..=6).contains(&face) => Ok(face),
+            _ => Err(ReplayErrorKind::InvalidDieFace(face.to_string())),
+        })
+        .collect()
+}
+
+fn write_roll(f: &mut fmt::Formatter<'_>, roll: &[usize]) -> fmt::Result {
+    for (i, face) in roll.iter().enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
+        }
+        write!(f, "{}", face)?;
+    }
+
+    Ok(())
+}
+
+impl Replay {
+    pub fn new(world_seed: u64, env_seed: u64, number_of_players: usize) -> Self {
+        Replay {
+            world_seed,
+            env_seed,
+            number_of_players,
+            first_player: None,
+            region_keys: Vec::new(),
+            moves: Vec::new(),
+            board: None,
+        }
+    }
+
+    /// Player that moved first in the game of the replay played with `rules`
+    pub fn first_player(&self, rules: &GameRules) -> usize {
+        self.first_player
+            .unwrap_or_else(|| first_player(rules, self.world_seed))
+    }
+
+    /// `rules` generating the board of the replay
+    pub fn rules(&self, rules: &GameRules) -> GameRules {
+        match &self.board {
+            Some(board) => board.rules(rules),
+            None => rules.clone(),
+        }
+    }
+
+    pub fn parse(source: &str) -> Result<Replay, ReplayError> {
+        let source = migrate(source, MIGRATIONS).map_err(|(line, err)| ReplayError {
+            line,
+            kind: ReplayErrorKind::Version(err),
+        })?;
+        let mut seeds: Option<(u64, u64)> = None;
+        let mut number_of_players: Option<usize> = None;
+        let mut first: Option<usize> = None;
+        let mut region_keys: Option<Vec<RegionKey>> = None;
+        let mut board: Option<BoardHeader> = None;
+        let mut moves = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let error = |kind| ReplayError {
+                line: index + 1,
+                kind,
+            };
+
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut tokens = line.split_whitespace();
+
+            match tokens.next() {
+                // checked by the migration
+                None | Some("version") => continue,
+                Some("seed") => {
+                    if seeds.is_some() {
+                        return Err(error(ReplayErrorKind::DuplicateHeader("seed")));
+                    }
+
+                    let world_seed = parse_number(tokens.next(), "world seed").map_err(error)?;
+                    let env_seed = parse_number(tokens.next(), "env seed").map_err(error)?;
+                    seeds = Some((world_seed, env_seed));
+                }
+                Some("players") => {
+                    if number_of_players.is_some() {
+                        return Err(error(ReplayErrorKind::DuplicateHeader("players")));
+                    }
+
+                    let players: usize =
+                        parse_number(tokens.next(), "number of players").map_err(error)?;
+                    if !(2..=MAX_PLAYERS).contains(&players) {
+                        return Err(error(ReplayErrorKind::PlayersOutOfRange(players)));
+                    }
+
+                    number_of_players = Some(players);
+                }
+                Some("first") => {
+                    if first.is_some() {
+                        return Err(error(ReplayErrorKind::DuplicateHeader("first")));
+                    }
+
+                    first = Some(parse_number(tokens.next(), "first player").map_err(error)?);
+                }
+                Some("board") => {
+                    if board.is_some() {
+                        return Err(error(ReplayErrorKind::DuplicateHeader("board")));
+                    }
+
+                    let generator = tokens
+                        .next()
+                        .ok_or(ReplayErrorKind::MissingValue("generator"))
+                        .map_err(error)?;
+                    if board_generator(generator).is_none() {
+                        return Err(error(ReplayErrorKind::UnknownGenerator(
+                            generator.to_string(),
+                        )));
+                    }
+
+                    board = Some(BoardHeader {
+                        generator: generator.to_string(),
+                        board_size: parse_number(tokens.next(), "board size").map_err(error)?,
+                        regions_per_player: parse_number(tokens.next(), "regions per player")
+                            .map_err(error)?,
+                        dice_per_region: parse_number(tokens.next(), "dice per region")
+                            .map_err(error)?,
+                        noise_scale: parse_number(tokens.next(), "noise scale").map_err(error)?,
+                        noise_strength: parse_number(tokens.next(), "noise strength")
+                            .map_err(error)?,
+                        min_seed_spacing: parse_number(tokens.next(), "seed spacing")
+                            .map_err(error)?,
+                    });
+                }
+                Some("regions") => {
+                    if region_keys.is_some() {
+                        return Err(error(ReplayErrorKind::DuplicateHeader("regions")));
+                    }
+
+                    let keys = tokens
+                        .map(|key| {
+                            key.parse()
+                                .map_err(|_| ReplayErrorKind::InvalidRegionKey(key.to_string()))
+                        })
+                        .collect::<Result<Vec<RegionKey>, ReplayErrorKind>>()
+                        .map_err(error)?;
+                    region_keys = Some(keys);
+                }
+                Some("attack") => moves.push(ReplayMove::Attack {
+                    attacker: parse_number(tokens.next(), "attacker").map_err(error)?,
+                    defender: parse_number(tokens.next(), "defender").map_err(error)?,
+                    attacker_roll: parse_roll(tokens.next(), "attacker roll").map_err(error)?,
+                    defender_roll: parse_roll(tokens.next(), "defender roll").map_err(error)?,
+                    transferred: parse_number(tokens.next(), "transferred dice").map_err(error)?,
+                }),
+                Some("reinforce") => moves.push(ReplayMove::Reinforce(
+                    parse_number(tokens.next(), "region").map_err(error)?,
+                )),
+                Some("pass") => moves.push(ReplayMove::Pass),
+                Some("ping") => moves.push(ReplayMove::Ping {
+                    player: parse_number(tokens.next(), "player").map_err(error)?,
+                    region: parse_number(tokens.next(), "region").map_err(error)?,
+                }),
+                Some("winner") => moves.push(ReplayMove::Winner(
+                    parse_number(tokens.next(), "winner").map_err(error)?,
+                )),
+                Some("truce") => moves.push(ReplayMove::Truce {
+                    player_1: parse_number(tokens.next(), "player").map_err(error)?,
+                    player_2: parse_number(tokens.next(), "player").map_err(error)?,
+                    expires_at_turn: parse_number(tokens.next(), "expiry turn").map_err(error)?,
+                }),
+                Some("hash") => {
+                    let hash = tokens
+                        .next()
+                        .ok_or(ReplayErrorKind::MissingValue("hash"))
+                        .map_err(error)?;
+                    let hash = u64::from_str_radix(hash, 16)
+                        .map_err(|_| error(ReplayErrorKind::InvalidHash(hash.to_string())))?;
+                    moves.push(ReplayMove::Checkpoint(hash));
+                }
+                Some(directive) => {
+                    return Err(error(ReplayErrorKind::UnknownDirective(
+                        directive.to_string(),
+                    )));
+                }
+            }
+        }
+
+        let last_line = source.lines().count().max(1);
+        let (world_seed, env_seed) = seeds.ok_or(ReplayError {
+            line: last_line,
+            kind: ReplayErrorKind::MissingHeader("seed"),
+        })?;
+        let number_of_players = number_of_players.ok_or(ReplayError {
+            line: last_line,
+            kind: ReplayErrorKind::MissingHeader("players"),
+        })?;
+
+        Ok(Replay {
+            world_seed,
+            env_seed,
+            number_of_players,
+            first_player: first,
+            region_keys: region_keys.unwrap_or_default(),
+            moves,
+            board,
+        })
+    }
+
+    /// Region ids of `board` for every region id of the moves, found through the region keys.
+    /// `None` when the replay has no keys and its ids are taken as they are.
+    pub fn region_ids(&self, board: &Board) -> Result<Option<Vec<RegionId>>, ReplayErrorKind> {
+        if self.region_keys.is_empty() {
+            return Ok(None);
+        }
+
+        self.region_keys
+            .iter()
+            .map(|key| {
+                board
+                    .region_by_key(*key)
+                    .ok_or(ReplayErrorKind::MissingRegion(*key))
+            })
+            .collect::<Result<Vec<RegionId>, ReplayErrorKind>>()
+            .map(Some)
+    }
+
+    /// Checks that `replay_move` is legal in `game_state` and plays it, the same way the
+    /// event handlers would
+    pub fn apply_move(
+        game_state: &mut GameState,
+        rules: &GameRules,
+        replay_move: &ReplayMove,
+    ) -> Result<(), ReplayErrorKind> {
+        let (attacker, defender, attacker_roll, defender_roll, transferred) = match replay_move {
+            ReplayMove::Pass => {
+                game_state.end_turn(rules);
+                return Ok(());
+            }
+            ReplayMove::Ping { region, .. } => {
+                if *region >= game_state.board.regions.len() {
+                    return Err(ReplayErrorKind::UnknownRegion(*region));
+                }
+                return Ok(());
+            }
+            ReplayMove::Winner(player) => {
+                if *player >= game_state.number_of_players {
+                    return Err(ReplayErrorKind::PlayersOutOfRange(*player));
+                }
+                game_state.decide_winner(*player);
+                return Ok(());
+            }
+            ReplayMove::Truce {
+                player_1,
+                player_2,
+                expires_at_turn,
+            } => {
+                for player in [*player_1, *player_2] {
+                    if player >= game_state.number_of_players {
+                        return Err(ReplayErrorKind::PlayersOutOfRange(player));
+                    }
+                }
+                game_state.pacts.push(Pact {
+                    player_1: *player_1,
+                    player_2: *player_2,
+                    expires_at_turn: *expires_at_turn,
+                });
+                return Ok(());
+            }
+            ReplayMove::Checkpoint(expected) => {
+                let found = game_state.zobrist_hash();
+                if found != *expected {
+                    return Err(ReplayErrorKind::HashMismatch {
+                        expected: *expected,
+                        found,
+                    });
+                }
+                return Ok(());
+            }
+            ReplayMove::Reinforce(region) => {
+                game_state.skip_blocked_players(rules);
+                if *region >= game_state.board.regions.len() {
+                    return Err(ReplayErrorKind::UnknownRegion(*region));
+                }
+                if !game_state.place_reinforcement(*region, rules) {
+                    return Err(ReplayErrorKind::CannotReinforce(*region));
+                }
+
+                game_state.finish_turn(rules);
+                return Ok(());
+            }
+            ReplayMove::Attack {
+                attacker,
+                defender,
+                attacker_roll,
+                defender_roll,
+                transferred,
+            } => (
+                *attacker,
+                *defender,
+                attacker_roll,
+                defender_roll,
+                *transferred,
+            ),
+        };
+
+        // The game passes the turn of blocked players on its own, without recording it
+        game_state.skip_blocked_players(rules);
+
+        let regions = &game_state.board.regions;
+        let region_1 = regions
+            .get(attacker)
+            .ok_or(ReplayErrorKind::UnknownRegion(attacker))?;
+        let region_2 = regions
+            .get(defender)
+            .ok_or(ReplayErrorKind::UnknownRegion(defender))?;
+
+        if region_1.owner != game_state.turn_of_player {
+            return Err(ReplayErrorKind::NotOwnedByPlayerInTurn(attacker));
+        }
+        if !game_state.can_attack(region_1, region_2, rules) {
+            return Err(ReplayErrorKind::NotOpponents(attacker, defender));
+        }
+        if game_state.has_moved_this_turn(attacker) {
+            return Err(ReplayErrorKind::AlreadyMoved(attacker));
+        }
+        for (region, roll, expected) in [
+            (
+                region_1,
+                attacker_roll,
+                rules.attacker_roll_size(region_1.num_dice),
+            ),
+            (
+                region_2,
+                defender_roll,
+                rules.defender_roll_size(region_2.num_dice),
+            ),
+        ] {
+            if expected != roll.len() {
+                return Err(ReplayErrorKind::RollSizeMismatch {
+                    region: region.id,
+                    expected,
+                });
+            }
+        }
+
+        let attacker_won = rules
+            .attacker_wins(
+                attacker_roll.iter().sum::<usize>(),
+                defender_roll.iter().sum::<usize>(),
+            )
+            .ok_or(ReplayErrorKind::UnresolvedTie)?;
+        let winner_dice = match attacker_won {
+            true => rules.attacker_transfer_dice(region_1.num_dice),
+            false => region_2.num_dice,
+        };
+        if !transfer_allowed(rules.transfer_rule, winner_dice, transferred) {
+            return Err(ReplayErrorKind::InvalidTransfer(transferred));
+        }
+
+        let log_entry = GameLogEntry {
+            turn_counter: game_state.turn_counter,
+            turn_of_player: game_state.turn_of_player,
+            region_1: attacker,
+            region_2: defender,
+            region_1_dice_result: DiceRoll::from_slice(attacker_roll),
+            region_2_dice_result: DiceRoll::from_slice(defender_roll),
+            ..Default::default()
+        };
+        game_state.log_attack(log_entry);
+
+        game_state.apply_battle_outcome(
+            attacker,
+            defender,
+            &BattleOutcome {
+                attacker_won,
+                transferred,
+                attacker_roll: DiceRoll::from_slice(attacker_roll),
+                defender_roll: DiceRoll::from_slice(defender_roll),
+            },
+            rules,
+        );
+
+        game_state.finish_turn(rules);
+
+        Ok(())
+    }
+
+    /// Plays every move of the replay on top of `game_state`, stopping at the first illegal one
+    pub fn apply(
+        &self,
+        game_state: &mut GameState,
+        rules: &GameRules,
+    ) -> Result<(), ReplayMoveError> {
+        if game_state.number_of_players != self.number_of_players {
+            return Err(ReplayMoveError {
+                move_number: 0,
+                kind: ReplayErrorKind::PlayersMismatch {
+                    expected: self.number_of_players,
+                },
+            });
+        }
+        if let Some(first) = self
+            .first_player
+            .filter(|first| *first >= self.number_of_players)
+        {
+            return Err(ReplayMoveError {
+                move_number: 0,
+                kind: ReplayErrorKind::PlayersOutOfRange(first),
+            });
+        }
+
+        let region_ids = self
+            .region_ids(&game_state.board)
+            .map_err(|kind| ReplayMoveError {
+                move_number: 0,
+                kind,
+            })?;
+
+        for (index, replay_move) in self.moves.iter().enumerate() {
+            let error = |kind| ReplayMoveError {
+                move_number: index + 1,
+                kind,
+            };
+            let replay_move = match region_ids.as_ref() {
+                Some(region_ids) => replay_move.with_region_ids(region_ids).map_err(error)?,
+                None => replay_move.clone(),
+            };
+            Replay::apply_move(game_state, rules, &replay_move).map_err(error)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ReplayMove {
+    /// The move with every region id `id` replaced by `region_ids[id]`
+    pub fn with_region_ids(&self, region_ids: &[RegionId]) -> Result<ReplayMove, ReplayErrorKind> {
+        let id = |region: usize| {
+            region_ids
+                .get(region)
+                .copied()
+                .ok_or(ReplayErrorKind::UnknownRegion(region))
+        };
+
+        Ok(match self {
+            ReplayMove::Attack {
+                attacker,
+                defender,
+                attacker_roll,
+                defender_roll,
+                transferred,
+            } => ReplayMove::Attack {
+                attacker: id(*attacker)?,
+                defender: id(*defender)?,
+                attacker_roll: attacker_roll.clone(),
+                defender_roll: defender_roll.clone(),
+                transferred: *transferred,
+            },
+            ReplayMove::Reinforce(region) => ReplayMove::Reinforce(id(*region)?),
+            ReplayMove::Ping { player, region } => ReplayMove::Ping {
+                player: *player,
+                region: id(*region)?,
+            },
+            ReplayMove::Pass
+            | ReplayMove::Checkpoint(_)
+            | ReplayMove::Winner(_)
+            | ReplayMove::Truce { .. } => self.clone(),
+        })
+    }
+}
+
+impl fmt::Display for ReplayMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayMove::Attack {
+                attacker,
+                defender,
+                attacker_roll,
+                defender_roll,
+                transferred,
+            } => {
+                write!(f, "attack {} {} ", attacker, defender)?;
+                write_roll(f, attacker_roll)?;
+                write!(f, " ")?;
+                write_roll(f, defender_roll)?;
+                write!(f, " {}", transferred)
+            }
+            ReplayMove::Reinforce(region) => write!(f, "reinforce {}", region),
+            ReplayMove::Pass => write!(f, "pass"),
+            ReplayMove::Checkpoint(hash) => write!(f, "hash {:016x}", hash),
+            ReplayMove::Ping { player, region } => write!(f, "ping {} {}", player, region),
+            ReplayMove::Winner(player) => write!(f, "winner {}", player),
+            ReplayMove::Truce {
+                player_1,
+                player_2,
+                expires_at_turn,
+            } => write!(f, "truce {} {} {}", player_1, player_2, expires_at_turn),
+        }
+    }
+}
+
+impl Replay {
+    /// The replay as text, opening with a comment holding `stamp`
+    #[allow(dead_code)]
+    pub fn stamped(&self, stamp: &ExportStamp) -> String {
+        format!("# {}\n{}", stamp, self)
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "version {}", VERSION)?;
+        writeln!(f, "seed {} {}", self.world_seed, self.env_seed)?;
+        writeln!(f, "players {}", self.number_of_players)?;
+        if let Some(first) = self.first_player {
+            writeln!(f, "first {}", first)?;
+        }
+        if let Some(board) = self.board.as_ref() {
+            writeln!(
+                f,
+                "board {} {} {} {} {} {} {}",
+                board.generator,
+                board.board_size,
+                board.regions_per_player,
+                board.dice_per_region,
+                board.noise_scale,
+                board.noise_strength,
+                board.min_seed_spacing
+            )?;
+        }
+        if !self.region_keys.is_empty() {
+            write!(f, "regions")?;
+            for key in self.region_keys.iter() {
+                write!(f, " {}", key)?;
+            }
+            writeln!(f)?;
+        }
+        for replay_move in self.moves.iter() {
+            writeln!(f, "{}", replay_move)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::generate_board;
+    use crate::map_file::MapFile;
+    use crate::rules::{ReinforcementRule, TransferRule};
+    use crate::tiered_prng::get_randomness;
+
+    #[test]
+    fn round_trip() {
+        let source = "version 4\nseed 4242 17\nplayers 2\nattack 4 7 6,2,3 1,5 2\npass\n";
+        let replay = Replay::parse(source).unwrap();
+
+        assert_eq!(replay.moves.len(), 2);
+        assert_eq!(replay.to_string(), source);
+
+        // replays from before versions and region keys load the same
+        let unversioned = Replay::parse(source.strip_prefix("version 4\n").unwrap()).unwrap();
+        assert_eq!(unversioned, replay);
+        let without_keys = source.replace("version 4", "version 1");
+        assert_eq!(Replay::parse(&without_keys).unwrap(), replay);
+
+        let keyed = source.replace(
+            "players 2\n",
+            "players 2\nregions 00000000000000ff 0123456789abcdef\n",
+        );
+        let replay = Replay::parse(&keyed).unwrap();
+        assert_eq!(
+            replay.region_keys,
+            vec![RegionKey(0xff), RegionKey(0x0123_4567_89ab_cdef)]
+        );
+        assert_eq!(replay.to_string(), keyed);
+    }
+
+    #[test]
+    fn stamps_are_left_out_when_loading() {
+        let source = "version 4\nseed 4242 17\nplayers 2\npass\n";
+        let replay = Replay::parse(source).unwrap();
+        let stamp = ExportStamp::new(
+            &crate::tiered_prng::PrngResource {
+                world_seed: 4242,
+                env_seed: 17,
+            },
+            &GameRules::default(),
+        );
+
+        let stamped = replay.stamped(&stamp);
+        assert!(stamped.starts_with(&format!("# {}\n", stamp)));
+        assert_eq!(Replay::parse(&stamped).unwrap(), replay);
+    }
+
+    #[test]
+    fn pings_round_trip() {
+        let source = "version 4\nseed 4242 17\nplayers 2\npass\nping 1 4\n";
+        let replay = Replay::parse(source).unwrap();
+
+        assert_eq!(
+            replay.moves[1],
+            ReplayMove::Ping {
+                player: 1,
+                region: 4
+            }
+        );
+        assert_eq!(replay.to_string(), source);
+    }
+
+    #[test]
+    fn truces_are_replayed() {
+        let source = "version 4\nseed 4242 17\nplayers 3\ntruce 0 2 9\n";
+        let replay = Replay::parse(source).unwrap();
+        assert_eq!(replay.to_string(), source);
+
+        let rules = GameRules::with_players(3);
+        let mut game_state = GameState {
+            number_of_players: 3,
+            ..Default::default()
+        };
+        replay.apply(&mut game_state, &rules).unwrap();
+        assert!(game_state.has_pact(2, 0));
+        assert!(!game_state.has_pact(0, 1));
+
+        let out_of_range = Replay::parse(&source.replace("truce 0 2", "truce 0 3")).unwrap();
+        assert_eq!(
+            out_of_range
+                .apply(&mut game_state, &rules)
+                .unwrap_err()
+                .kind,
+            ReplayErrorKind::PlayersOutOfRange(3)
+        );
+    }
+
+    #[test]
+    fn rematches_start_with_their_first_player() {
+        let source = "version 4\nseed 4242 17\nplayers 3\nfirst 2\npass\n";
+        let replay = Replay::parse(source).unwrap();
+        let rules = GameRules::with_players(3);
+
+        assert_eq!(replay.first_player(&rules), 2);
+        assert_eq!(replay.to_string(), source);
+        let drawn = Replay::parse(&source.replace("first 2\n", "")).unwrap();
+        assert_eq!(drawn.first_player(&rules), first_player(&rules, 4242));
+
+        let mut game_state = GameState {
+            number_of_players: 3,
+            ..Default::default()
+        };
+        let out_of_range = Replay::parse(&source.replace("first 2", "first 3")).unwrap();
+        assert_eq!(
+            out_of_range
+                .apply(&mut game_state, &rules)
+                .unwrap_err()
+                .kind,
+            ReplayErrorKind::PlayersOutOfRange(3)
+        );
+    }
+
+    #[test]
+    fn decided_winners_are_replayed() {
+        let replay = Replay::parse("seed 4242 17\nplayers 2\nwinner 1\n").unwrap();
+        assert_eq!(replay.moves, vec![ReplayMove::Winner(1)]);
+        assert_eq!(replay.moves[0].to_string(), "winner 1");
+
+        let map = MapFile::parse("players 2\nregion 0 3 0,0\nregion 1 3 1,0\n").unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        let rules = GameRules::default();
+        assert_eq!(
+            Replay::apply_move(&mut game_state, &rules, &ReplayMove::Winner(2)),
+            Err(ReplayErrorKind::PlayersOutOfRange(2))
+        );
+        Replay::apply_move(&mut game_state, &rules, &ReplayMove::Winner(1)).unwrap();
+        assert_eq!(game_state.winner(), Some(1));
+    }
+
+    #[test]
+    fn rejects_invalid_die_face() {
+        let source = "seed 4242 17\nplayers 2\nattack 4 7 6,0,3 1,5 2\n";
+        let error = Replay::parse(source).unwrap_err();
+
+        assert_eq!(error.line, 3);
+        assert_eq!(error.kind, ReplayErrorKind::InvalidDieFace("0".to_string()));
+    }
+
+    #[test]
+    fn reinforcements_follow_the_last_attack() {
+        let map =
+            MapFile::parse("players 2\nregion 0 3 0,0\nregion 1 1 1,0\nregion 1 2 3,0\n").unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        let rules = GameRules {
+            reinforcements: ReinforcementRule::Manual,
+            ..Default::default()
+        };
+        let replay = Replay::parse(
+            "seed 1 1\nplayers 2\nattack 0 1 6,6,6 1 1\nreinforce 1\nreinforce 1\nreinforce 0\n",
+        )
+        .unwrap();
+
+        for replay_move in replay.moves[..3].iter() {
+            Replay::apply_move(&mut game_state, &rules, replay_move).unwrap();
+        }
+        assert_eq!(game_state.board.regions[1].num_dice, 3);
+        assert_eq!(game_state.turn_of_player, 1);
+
+        assert_eq!(
+            Replay::apply_move(&mut game_state, &rules, &replay.moves[3]),
+            Err(ReplayErrorKind::CannotReinforce(0))
+        );
+    }
+
+    #[test]
+    fn region_keys_follow_reordered_regions() {
+        let map =
+            MapFile::parse("players 2\nregion 0 3 0,0\nregion 1 1 1,0\nregion 1 2 3,0\n").unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        let rules = GameRules::default();
+        let mut replay = Replay::parse("seed 1 1\nplayers 2\nattack 0 1 6,6,6 1 1\n").unwrap();
+        replay.region_keys = game_state.board.region_keys();
+
+        // the board lists its regions the other way around
+        game_state.board.regions.reverse();
+        for (id, region) in game_state.board.regions.iter_mut().enumerate() {
+            region.id = id;
+        }
+        let mut reordered = game_state.clone();
+        replay.apply(&mut reordered, &rules).unwrap();
+        assert_eq!(reordered.board.regions[1].owner, 0);
+        assert_eq!(reordered.board.regions[1].num_dice, 1);
+
+        game_state.board.regions[1].hexes = vec![(1, 1)];
+        assert_eq!(
+            replay.apply(&mut game_state, &rules).unwrap_err().kind,
+            ReplayErrorKind::MissingRegion(RegionKey::from_hexes(&[(1, 0)]))
+        );
+    }
+
+    #[test]
+    fn checkpoint_detects_divergence() {
+        let map = MapFile::parse("players 2\nregion 0 3 0,0\nregion 1 1 1,0\n").unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        let rules = GameRules::default();
+
+        let checkpoint = ReplayMove::Checkpoint(game_state.zobrist_hash());
+        assert_eq!(checkpoint.to_string().len(), "hash ".len() + 16);
+        assert_eq!(
+            Replay::apply_move(&mut game_state, &rules, &checkpoint),
+            Ok(())
+        );
+
+        game_state.board.regions[0].num_dice = 2;
+        assert!(matches!(
+            Replay::apply_move(&mut game_state, &rules, &checkpoint),
+            Err(ReplayErrorKind::HashMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn transfers_follow_the_transfer_rule() {
+        let map = MapFile::parse("players 2\nregion 0 4 0,0\nregion 1 1 1,0\n").unwrap();
+        let game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        let rules = GameRules {
+            transfer_rule: TransferRule::AllButOne,
+            ..Default::default()
+        };
+        let attack = |transferred| ReplayMove::Attack {
+            attacker: 0,
+            defender: 1,
+            attacker_roll: vec![6, 6, 6, 6],
+            defender_roll: vec![1],
+            transferred,
+        };
+
+        assert_eq!(
+            Replay::apply_move(&mut game_state.clone(), &rules, &attack(1)),
+            Err(ReplayErrorKind::InvalidTransfer(1))
+        );
+        let mut conquered = game_state.clone();
+        Replay::apply_move(&mut conquered, &rules, &attack(3)).unwrap();
+        assert_eq!(conquered.board.regions[1].num_dice, 3);
+    }
+
+    #[test]
+    fn boards_are_rebuilt_with_their_generator() {
+        let rules = GameRules {
+            board_generator: "noise".to_string(),
+            board_size: 12,
+            noise_scale: 2.5,
+            min_seed_spacing: 0,
+            ..GameRules::with_players(3)
+        };
+        let mut replay = Replay::new(4242, 17, 3);
+        replay.board = Some(BoardHeader::new(&rules));
+
+        let parsed = Replay::parse(&replay.to_string()).unwrap();
+        assert_eq!(parsed, replay);
+        assert!(replay.to_string().contains("\nboard noise 12 "));
+
+        // played with other rules, the replay still rebuilds its board
+        let replayed = parsed.rules(&GameRules::with_players(3));
+        assert_eq!(replayed, rules);
+        assert_eq!(
+            generate_board(&replayed, get_randomness(4242)).region_keys(),
+            generate_board(&rules, get_randomness(4242)).region_keys()
+        );
+
+        let unknown = replay.to_string().replace("board noise", "board swirl");
+        assert_eq!(
+            Replay::parse(&unknown).unwrap_err().kind,
+            ReplayErrorKind::UnknownGenerator("swirl".to_string())
+        );
+    }
+}
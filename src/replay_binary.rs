@@ -0,0 +1,213 @@
+//! Compact binary encoding of replays, for long games and slow connections.
+//!
+//! The [`Replay`] is serialized with bincode, whose variable length integers keep region ids,
+//! dice faces and counts to a byte, and compressed with zstd. It starts with the `SRDR` magic and
+//! the text format version it matches (see [`crate::replay::VERSION`]), little endian, so a file
+//! is told apart from a text replay by its first bytes:
+//!
+//! ```text
+//! magic version zstd(bincode(replay))
+//! ```
+//!
+//! [`read_replay`] reads replays in either format, and `cargo run -- replay <input> <output>`
+//! converts between them.
+
+use std::fmt;
+use std::io::Read;
+
+use bincode::Options;
+
+use crate::replay::{Replay, VERSION};
+
+const MAGIC: &[u8; 4] = b"SRDR";
+
+/// Extension of the files written in the binary format
+pub const BINARY_REPLAY_EXTENSION: &str = ".srdr";
+
+/// Compression level of zstd, its default
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Largest replay decoded, far more than the moves of any game, so a damaged or crafted file
+/// can't exhaust the memory
+const MAX_DECODED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Version of the binary replays written before the `board` header, which they lack at their end
+const BOARDLESS_VERSION: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BinaryError {
+    NotAReplay,
+    UnsupportedVersion(u32),
+    /// The compressed replay can't be read back
+    Damaged(String),
+}
+
+impl fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryError::NotAReplay => write!(f, "not a binary replay"),
+            BinaryError::UnsupportedVersion(version) => write!(
+                f,
+                "version {} isn't the supported version {}",
+                version, VERSION
+            ),
+            BinaryError::Damaged(err) => write!(f, "damaged replay: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for BinaryError {}
+
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_DECODED_SIZE)
+}
+
+/// Whether `bytes` start like a binary replay
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Encodes `replay` in the binary format
+pub fn encode(replay: &Replay) -> Result<Vec<u8>, BinaryError> {
+    let serialized = bincode_options()
+        .serialize(replay)
+        .map_err(|err| BinaryError::Damaged(err.to_string()))?;
+    let compressed = zstd::encode_all(serialized.as_slice(), COMPRESSION_LEVEL)
+        .map_err(|err| BinaryError::Damaged(err.to_string()))?;
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(VERSION.to_le_bytes());
+    bytes.extend(compressed);
+    Ok(bytes)
+}
+
+/// Decodes a replay encoded with [`encode`]. Moves are only checked to be well formed, use
+/// [`Replay::apply`] to check they are legal.
+pub fn decode(bytes: &[u8]) -> Result<Replay, BinaryError> {
+    let rest = bytes
+        .strip_prefix(MAGIC.as_slice())
+        .ok_or(BinaryError::NotAReplay)?;
+    let version = rest
+        .get(..4)
+        .map(|version| u32::from_le_bytes([version[0], version[1], version[2], version[3]]))
+        .ok_or_else(|| BinaryError::Damaged("missing version".to_string()))?;
+    // older versions only differ by what the text format accepts, the binary one came after them
+    // but before the board header
+    if version != VERSION && version != BOARDLESS_VERSION {
+        return Err(BinaryError::UnsupportedVersion(version));
+    }
+
+    let mut serialized = Vec::new();
+    zstd::Decoder::new(&rest[4..])
+        .and_then(|decoder| decoder.take(MAX_DECODED_SIZE).read_to_end(&mut serialized))
+        .map_err(|err| BinaryError::Damaged(err.to_string()))?;
+    if version == BOARDLESS_VERSION {
+        // the tag of a missing option, read as no board header
+        serialized.push(0);
+    }
+    bincode_options()
+        .deserialize(&serialized)
+        .map_err(|err| BinaryError::Damaged(err.to_string()))
+}
+
+/// Reads a replay in the binary format or in the text format
+pub fn read_replay(bytes: &[u8]) -> Result<Replay, String> {
+    if is_binary(bytes) {
+        return decode(bytes).map_err(|err| err.to_string());
+    }
+
+    let source = std::str::from_utf8(bytes).map_err(|_| "not a replay".to_string())?;
+    Replay::parse(source).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::RegionKey;
+    use crate::replay::ReplayMove;
+
+    fn replay() -> Replay {
+        let mut replay = Replay::new(u64::MAX, 17, 3);
+        replay.region_keys = vec![RegionKey(0x0123_4567_89ab_cdef), RegionKey(u64::MAX)];
+        replay.moves = vec![
+            ReplayMove::Attack {
+                attacker: 4,
+                defender: 200,
+                attacker_roll: vec![6, 2, 3],
+                defender_roll: vec![1, 5],
+                transferred: 2,
+            },
+            ReplayMove::Reinforce(4),
+            ReplayMove::Pass,
+            ReplayMove::Checkpoint(0x3f2a_9c0d_1e4b_5a67),
+            ReplayMove::Ping {
+                player: 1,
+                region: 4,
+            },
+            ReplayMove::Winner(2),
+        ];
+        replay
+    }
+
+    #[test]
+    fn round_trip() {
+        let replay = replay();
+        let bytes = encode(&replay).unwrap();
+
+        assert_eq!(decode(&bytes), Ok(replay.clone()));
+        assert!(is_binary(&bytes));
+    }
+
+    #[test]
+    fn long_games_compress() {
+        let mut replay = replay();
+        let moves = replay.moves.clone();
+        for _ in 0..200 {
+            replay.moves.extend(moves.iter().cloned());
+        }
+        let bytes = encode(&replay).unwrap();
+
+        assert_eq!(decode(&bytes), Ok(replay.clone()));
+        assert!(bytes.len() * 10 < replay.to_string().len());
+    }
+
+    #[test]
+    fn reads_both_formats() {
+        let replay = replay();
+
+        assert_eq!(read_replay(&encode(&replay).unwrap()), Ok(replay.clone()));
+        assert_eq!(read_replay(replay.to_string().as_bytes()), Ok(replay));
+        assert!(read_replay(&[0xff, 0xfe]).is_err());
+    }
+
+    #[test]
+    fn rejects_damaged_replays() {
+        let bytes = encode(&replay()).unwrap();
+
+        assert_eq!(decode(b"seed 1 2\n"), Err(BinaryError::NotAReplay));
+        assert!(matches!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(BinaryError::Damaged(_))
+        ));
+        assert!(matches!(decode(MAGIC), Err(BinaryError::Damaged(_))));
+
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            decode(&newer),
+            Err(BinaryError::UnsupportedVersion(VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn replays_from_before_the_board_header_load() {
+        let replay = replay();
+        let mut serialized = bincode_options().serialize(&replay).unwrap();
+        assert_eq!(serialized.pop(), Some(0));
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(BOARDLESS_VERSION.to_le_bytes());
+        bytes.extend(zstd::encode_all(serialized.as_slice(), COMPRESSION_LEVEL).unwrap());
+        assert_eq!(decode(&bytes), Ok(replay));
+    }
+}
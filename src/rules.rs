@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Parameters of a game that stay fixed for its whole duration
+#[derive(Clone, Debug, PartialEq, Resource, Reflect, FromReflect, Serialize, Deserialize)]
+#[reflect(Resource)]
+#[serde(default)]
+pub struct GameRules {
+    /// Number of players taking part in the game
+    pub number_of_players: usize,
+    /// Width and height of the hex grid the board is generated on
+    pub board_size: isize,
+    /// Number of regions generated for each player
+    pub regions_per_player: usize,
+    /// Average number of dice each player gets per region when the board is generated
+    pub dice_per_region: usize,
+    /// Maximum number of dice a region can hold
+    pub max_dice: usize,
+    /// A random global event fires every this many turns. Zero disables global events.
+    pub chaos_interval: usize,
+    /// Players can agree on truces. Only used in games with three or more players.
+    pub diplomacy: bool,
+    /// Number of rounds a truce lasts
+    pub truce_rounds: usize,
+    /// Number of consecutive turns a player has to hold the majority of objectives to win
+    pub objective_turns: usize,
+    /// A region conquered by the attacker may keep attacking in the same turn, as it always could.
+    /// Without momentum it sits out the rest of the turn.
+    pub momentum: bool,
+    /// How dice earned at the end of a turn reach the board
+    pub reinforcements: ReinforcementRule,
+    /// Maximum number of dice a player can keep in its reserve between turns
+    pub reserve_cap: usize,
+    /// Decides who wins a battle when both sides roll the same total
+    pub tie_rule: TieRule,
+    /// Number of extra dice the defender rolls on top of the dice in its region
+    pub defender_bonus_dice: usize,
+    /// Number of dice an attacking region has to leave behind: they don't roll, and they stay
+    /// home when the attacker moves dice into a conquered region. Zero lets every die attack.
+    pub garrison: usize,
+    /// How many dice the winner of a battle moves into the conquered region
+    pub transfer_rule: TransferRule,
+    /// Total thinking time each player gets for the whole game, in seconds. A player running out
+    /// of time loses. Zero disables the budget.
+    pub time_budget_secs: u64,
+    /// The game ends by territory count once no region changed hands for this many rounds, see
+    /// [`crate::stalemate`]. Zero turns stalemates off.
+    pub stalemate_rounds: usize,
+    /// The player moving first is drawn from the world seed instead of always being the first
+    /// player, see [`crate::game::first_player`]
+    pub random_first_player: bool,
+    /// Extra dice a player gets at the start of the game for every seat it moves after the first
+    /// player, to make up for the advantage of moving first
+    pub compensation_dice: usize,
+    /// Experimental: instead of taking turns, every round each player gives one attack order and
+    /// the orders resolve together, see [`crate::blitz`]
+    pub blitz: bool,
+    /// Name of the generator building the board, see [`crate::generators`]
+    pub board_generator: String,
+    /// Size in hexes of the features of the noise shaping regions of the noise generator
+    pub noise_scale: f32,
+    /// How much the noise generator varies region sizes, from 0 (uniform) to 1
+    pub noise_strength: f32,
+    /// Minimum number of steps over the board between the seeds of regions of different players
+    /// generated in the same round, relaxed when the board is too crowded. Zero, the default,
+    /// disables the spacing so boards stay the ones their seeds always generated.
+    pub min_seed_spacing: usize,
+}
+
+/// How dice earned at the end of a turn reach the board
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Reflect, FromReflect, Serialize, Deserialize,
+)]
+pub enum ReinforcementRule {
+    /// No dice are earned
+    #[default]
+    None,
+    /// Earned dice are spread over the player's regions, what doesn't fit is kept in the reserve
+    Automatic,
+    /// Earned dice go to the reserve and the player places them by hand
+    Manual,
+}
+
+/// Who wins a battle that ends with both sides rolling the same total
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Reflect, FromReflect, Serialize, Deserialize,
+)]
+pub enum TieRule {
+    #[default]
+    DefenderWins,
+    AttackerWins,
+    /// Both sides roll again until the totals differ
+    Reroll,
+}
+
+/// How many of its dice the winner of a battle moves into the conquered region, see
+/// [`crate::game::resolve_transfer`]. The winner always keeps at least one die.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Reflect, FromReflect, Serialize, Deserialize,
+)]
+pub enum TransferRule {
+    /// A random number of dice, from one to all but one
+    #[default]
+    Random,
+    /// All dice but one
+    AllButOne,
+    /// This percentage of the winner's dice, rounded down, but at least one
+    Percentage(usize),
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        GameRules {
+            number_of_players: 2,
+            board_size: 20,
+            regions_per_player: 16,
+            dice_per_region: 4,
+            max_dice: 8,
+            chaos_interval: 0,
+            diplomacy: false,
+            truce_rounds: 3,
+            objective_turns: 3,
+            momentum: true,
+            reinforcements: ReinforcementRule::None,
+            reserve_cap: 64,
+            tie_rule: TieRule::DefenderWins,
+            defender_bonus_dice: 0,
+            garrison: 0,
+            transfer_rule: TransferRule::Random,
+            time_budget_secs: 0,
+            stalemate_rounds: 0,
+            random_first_player: false,
+            compensation_dice: 0,
+            blitz: false,
+            board_generator: "patches".to_string(),
+            noise_scale: 4.0,
+            noise_strength: 0.6,
+            min_seed_spacing: 0,
+        }
+    }
+}
+
+impl GameRules {
+    pub fn with_players(number_of_players: usize) -> Self {
+        GameRules {
+            number_of_players,
+            ..default()
+        }
+    }
+
+    /// Number of dice the defender rolls when defending a region holding `num_dice` dice
+    pub fn defender_roll_size(&self, num_dice: usize) -> usize {
+        num_dice + self.defender_bonus_dice
+    }
+
+    /// Number of dice the attacker rolls when attacking from a region holding `num_dice` dice,
+    /// zero when the region can't attack without emptying its garrison
+    pub fn attacker_roll_size(&self, num_dice: usize) -> usize {
+        num_dice.saturating_sub(self.garrison)
+    }
+
+    /// Dice a winning attacker holding `num_dice` dice splits with the region it conquered, see
+    /// [`crate::game::resolve_transfer`]. The garrison stays home, in place of the die every
+    /// winner keeps.
+    pub fn attacker_transfer_dice(&self, num_dice: usize) -> usize {
+        num_dice.saturating_sub(self.garrison.saturating_sub(1))
+    }
+
+    /// Total thinking time of each player, if the game has a budget
+    pub fn time_budget(&self) -> Option<Duration> {
+        match self.time_budget_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /// Decides a battle from the rolled totals. Returns `None` when the tie rule asks for a reroll.
+    pub fn attacker_wins(&self, attacker_result: usize, defender_result: usize) -> Option<bool> {
+        match attacker_result.cmp(&defender_result) {
+            std::cmp::Ordering::Greater => Some(true),
+            std::cmp::Ordering::Less => Some(false),
+            std::cmp::Ordering::Equal => match self.tie_rule {
+                TieRule::DefenderWins => Some(false),
+                TieRule::AttackerWins => Some(true),
+                TieRule::Reroll => None,
+            },
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Scenarios are maps with scripted triggers, evaluated at the start of every turn.
+//!
+//! A scenario file is a map file (see [`crate::map_file`]) with extra `trigger` lines:
+//!
+//! ```text
+//! # on turn 5 every region of player 2 gains 2 dice
+//! trigger turn 5 add_dice 1 2
+//! # whoever holds region 7 for 3 consecutive turns wins
+//! trigger hold 7 3 win
+//! # whoever links regions 2 and 9 with a chain of its own regions wins
+//! trigger connect 2 9 win
+//! ```
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::events::{EventBoardChanged, EventGameOver, EventTurnStart};
+use crate::game::{GameState, NEUTRAL};
+use crate::map_file::{parse_number, MapError, MapErrorKind, MapFile, MAX_DICE};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TriggerCondition {
+    /// The turn counter reached the given turn
+    Turn(usize),
+    /// The same player owned `region` at the start of `turns` consecutive turns
+    Hold { region: usize, turns: usize },
+    /// A player owned a chain of neighbouring regions linking `region_1` and `region_2`
+    Connect { region_1: usize, region_2: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TriggerAction {
+    /// Every region of `player` gains `amount` dice, up to the dice limit
+    AddDice { player: usize, amount: usize },
+    /// The player satisfying the condition wins. Only meaningful for conditions with an owner.
+    Win,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trigger {
+    pub condition: TriggerCondition,
+    pub action: TriggerAction,
+}
+
+/// What the game has to do in response to fired triggers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TriggerEffect {
+    BoardChanged,
+    Winner(usize),
+}
+
+pub struct Scenario {
+    pub map: MapFile,
+    pub triggers: Vec<Trigger>,
+}
+
+fn parse_trigger(line: &str) -> Result<Trigger, MapErrorKind> {
+    let invalid = || MapErrorKind::InvalidTrigger(line.to_string());
+    let mut tokens = line.split_whitespace().skip(1);
+
+    let condition = match tokens.next() {
+        Some("turn") => TriggerCondition::Turn(parse_number(tokens.next(), "turn")?),
+        Some("hold") => TriggerCondition::Hold {
+            region: parse_number(tokens.next(), "region")?,
+            turns: parse_number(tokens.next(), "number of turns")?,
+        },
+        Some("connect") => TriggerCondition::Connect {
+            region_1: parse_number(tokens.next(), "region")?,
+            region_2: parse_number(tokens.next(), "region")?,
+        },
+        _ => return Err(invalid()),
+    };
+
+    let action = match tokens.next() {
+        Some("add_dice") => TriggerAction::AddDice {
+            player: parse_number(tokens.next(), "player")?,
+            amount: parse_number(tokens.next(), "number of dice")?,
+        },
+        Some("win") if !matches!(condition, TriggerCondition::Turn(_)) => TriggerAction::Win,
+        _ => return Err(invalid()),
+    };
+
+    if tokens.next().is_some() {
+        return Err(invalid());
+    }
+
+    Ok(Trigger { condition, action })
+}
+
+impl Scenario {
+    pub fn parse(source: &str) -> Result<Scenario, MapError> {
+        let mut triggers = Vec::new();
+        let mut map_source = String::with_capacity(source.len());
+
+        // Triggers are blanked out for the map parser, so it reports the same line numbers
+        for (index, line) in source.lines().enumerate() {
+            let content = line.split('#').next().unwrap_or("").trim();
+            if content.split_whitespace().next() == Some("trigger") {
+                let trigger = parse_trigger(content).map_err(|kind| MapError {
+                    line: index + 1,
+                    kind,
+                })?;
+                triggers.push((index + 1, trigger));
+            } else {
+                map_source.push_str(line);
+            }
+            map_source.push('\n');
+        }
+
+        let map = MapFile::parse(&map_source)?;
+
+        for (line, trigger) in triggers.iter() {
+            let error = |kind| MapError { line: *line, kind };
+
+            let regions = match trigger.condition {
+                TriggerCondition::Turn(_) => vec![],
+                TriggerCondition::Hold { region, .. } => vec![region],
+                TriggerCondition::Connect { region_1, region_2 } => vec![region_1, region_2],
+            };
+            for region in regions {
+                if region >= map.board.regions.len() {
+                    return Err(error(MapErrorKind::RegionOutOfRange(region)));
+                }
+            }
+
+            if let TriggerAction::AddDice { player, .. } = trigger.action {
+                if player >= map.number_of_players {
+                    return Err(error(MapErrorKind::OwnerOutOfRange(player)));
+                }
+            }
+        }
+
+        Ok(Scenario {
+            map,
+            triggers: triggers.into_iter().map(|(_, trigger)| trigger).collect(),
+        })
+    }
+}
+
+/// Triggers of the scenario being played, with the state needed to evaluate them
+#[derive(Resource, Default)]
+pub struct ScenarioTriggers {
+    triggers: Vec<Trigger>,
+    fired: Vec<bool>,
+    /// Owner and number of consecutive turns it held the region, by region
+    hold_streaks: HashMap<usize, (usize, usize)>,
+}
+
+impl ScenarioTriggers {
+    pub fn new(triggers: Vec<Trigger>) -> Self {
+        ScenarioTriggers {
+            fired: vec![false; triggers.len()],
+            triggers,
+            hold_streaks: HashMap::new(),
+        }
+    }
+
+    /// Number of triggers that fired so far
+    pub fn fired_count(&self) -> usize {
+        self.fired.iter().filter(|fired| **fired).count()
+    }
+
+    /// Evaluates the triggers at the start of a turn, applying board changes to `game_state`.
+    /// Every trigger fires at most once.
+    pub fn evaluate(&mut self, game_state: &mut GameState) -> Vec<TriggerEffect> {
+        for trigger in self.triggers.iter() {
+            if let TriggerCondition::Hold { region, .. } = trigger.condition {
+                let owner = game_state.board.regions[region].owner;
+                let streak = self.hold_streaks.entry(region).or_insert((owner, 0));
+                if streak.0 == owner {
+                    streak.1 += 1;
+                } else {
+                    *streak = (owner, 1);
+                }
+            }
+        }
+
+        let mut effects = Vec::new();
+
+        for (trigger, fired) in self.triggers.iter().zip(self.fired.iter_mut()) {
+            if *fired {
+                continue;
+            }
+
+            let holder = match trigger.condition {
+                TriggerCondition::Turn(turn) if game_state.turn_counter >= turn => None,
+                TriggerCondition::Hold { region, turns } => match self.hold_streaks[&region] {
+                    (owner, held) if held >= turns && owner != NEUTRAL => Some(owner),
+                    _ => continue,
+                },
+                TriggerCondition::Connect { region_1, region_2 } => {
+                    let owner = game_state.board.regions[region_1].owner;
+                    match game_state.board.owned_path(region_1, region_2, owner) {
+                        Some(_) if owner != NEUTRAL => Some(owner),
+                        _ => continue,
+                    }
+                }
+                _ => continue,
+            };
+
+            *fired = true;
+
+            match trigger.action {
+                TriggerAction::AddDice { player, amount } => {
+                    for region in game_state.board.regions.iter_mut() {
+                        if region.owner == player {
+                            region.num_dice = usize::min(region.num_dice + amount, MAX_DICE);
+                        }
+                    }
+                    effects.push(TriggerEffect::BoardChanged);
+                }
+                TriggerAction::Win => {
+                    if let Some(holder) = holder {
+                        effects.push(TriggerEffect::Winner(holder));
+                    }
+                }
+            }
+        }
+
+        effects
+    }
+}
+
+pub(crate) fn scenario_triggers(
+    mut event_turn_start_reader: EventReader<EventTurnStart>,
+    mut event_board_changed_writer: EventWriter<EventBoardChanged>,
+    mut event_game_over_writer: EventWriter<EventGameOver>,
+    mut triggers: ResMut<ScenarioTriggers>,
+    mut game_state: ResMut<GameState>,
+) {
+    for _ in event_turn_start_reader.iter() {
+        for effect in triggers.evaluate(&mut game_state) {
+            match effect {
+                TriggerEffect::BoardChanged => event_board_changed_writer.send(EventBoardChanged),
+                TriggerEffect::Winner(winner) => {
+                    if game_state.decide_winner(winner) {
+                        event_game_over_writer.send(EventGameOver { winner });
+                    }
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "players 2\nregion 0 1 0,0\nregion 1 1 1,0\n";
+
+    fn scenario_state(triggers: &str) -> (GameState, ScenarioTriggers) {
+        let scenario = Scenario::parse(&format!("{}{}", MAP, triggers)).unwrap();
+        let game_state = GameState {
+            board: scenario.map.board,
+            number_of_players: scenario.map.number_of_players,
+            ..Default::default()
+        };
+
+        (game_state, ScenarioTriggers::new(scenario.triggers))
+    }
+
+    #[test]
+    fn turn_trigger_adds_dice_once() {
+        let (mut game_state, mut triggers) = scenario_state("trigger turn 2 add_dice 1 2\n");
+
+        game_state.advance_turn();
+        assert!(triggers.evaluate(&mut game_state).is_empty());
+
+        game_state.advance_turn();
+        assert_eq!(
+            triggers.evaluate(&mut game_state),
+            vec![TriggerEffect::BoardChanged]
+        );
+        assert_eq!(game_state.board.regions[1].num_dice, 3);
+
+        game_state.advance_turn();
+        assert!(triggers.evaluate(&mut game_state).is_empty());
+        assert_eq!(game_state.board.regions[1].num_dice, 3);
+    }
+
+    #[test]
+    fn hold_trigger_resets_on_owner_change() {
+        let (mut game_state, mut triggers) = scenario_state("trigger hold 1 2 win\n");
+
+        assert!(triggers.evaluate(&mut game_state).is_empty());
+        game_state.board.regions[1].owner = 0;
+        assert!(triggers.evaluate(&mut game_state).is_empty());
+        assert_eq!(
+            triggers.evaluate(&mut game_state),
+            vec![TriggerEffect::Winner(0)]
+        );
+    }
+
+    #[test]
+    fn connect_trigger_needs_a_chain_of_owned_regions() {
+        let scenario = Scenario::parse(
+            "players 2\nregion 0 1 0,0\nregion 1 1 1,0\nregion 0 1 2,0\ntrigger connect 0 2 win\n",
+        )
+        .unwrap();
+        let mut game_state = GameState {
+            board: scenario.map.board,
+            number_of_players: scenario.map.number_of_players,
+            ..Default::default()
+        };
+        let mut triggers = ScenarioTriggers::new(scenario.triggers);
+
+        assert!(triggers.evaluate(&mut game_state).is_empty());
+        game_state.board.regions[1].owner = 0;
+        assert_eq!(
+            triggers.evaluate(&mut game_state),
+            vec![TriggerEffect::Winner(0)]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_region() {
+        let error = Scenario::parse(&format!("{}trigger hold 9 2 win\n", MAP))
+            .err()
+            .unwrap();
+        assert_eq!(error.line, 4);
+        assert_eq!(error.kind, MapErrorKind::RegionOutOfRange(9));
+    }
+}
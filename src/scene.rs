@@ -0,0 +1,148 @@
+use std::path::Path;
+
+use bevy::{
+    prelude::*,
+    scene::{DynamicEntity, DynamicScene},
+};
+
+use crate::board::{BoardDrawer, StackRankDiceGameBoardElement};
+use crate::game::{Board, GameState, Region};
+use crate::profiles::PlayerProfiles;
+use crate::rules::GameRules;
+use crate::stamp::ExportStamp;
+use crate::tiered_prng::PrngResource;
+
+/// Path of the scene file written by the board exporter, relative to the working directory
+pub const BOARD_SCENE_EXPORT_PATH: &str = "assets/scenes/board.scn.ron";
+
+/// Key that exports the currently spawned board as a scene
+const EXPORT_KEY: KeyCode = KeyCode::F10;
+
+/// Scene asset (relative to the assets folder) to spawn the board from instead of the generated one
+#[derive(Resource)]
+pub struct BoardSceneSource(pub String);
+
+/// Root entity of a board scene being loaded. Despawned once its regions are taken over
+#[derive(Component)]
+pub(crate) struct BoardSceneRoot;
+
+/// Collects every board element with all of its reflected components into a scene.
+/// Meshes and materials are generated at runtime, so only their handles end up in the scene;
+/// the loader rebuilds them from the region data.
+pub fn board_scene(world: &mut World) -> DynamicScene {
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let type_registry = type_registry.read();
+
+    let mut query = world.query_filtered::<Entity, With<StackRankDiceGameBoardElement>>();
+    let mut entities = Vec::new();
+
+    for entity in query.iter(world) {
+        let mut dynamic_entity = DynamicEntity {
+            entity: entity.index(),
+            components: Vec::new(),
+        };
+
+        for registration in type_registry.iter() {
+            if let Some(component) = registration
+                .data::<ReflectComponent>()
+                .and_then(|reflect_component| reflect_component.reflect(world, entity))
+            {
+                dynamic_entity.components.push(component.clone_value());
+            }
+        }
+
+        entities.push(dynamic_entity);
+    }
+
+    DynamicScene { entities }
+}
+
+pub(crate) fn export_board_scene(world: &mut World) {
+    let export_requested = world
+        .get_resource::<Input<KeyCode>>()
+        .map(|keys| keys.just_pressed(EXPORT_KEY))
+        .unwrap_or(false);
+
+    if !export_requested {
+        return;
+    }
+
+    let scene = board_scene(world);
+    let type_registry = world.resource::<AppTypeRegistry>();
+
+    let serialized = match scene.serialize_ron(type_registry) {
+        Ok(serialized) => match (
+            world.get_resource::<PrngResource>(),
+            world.get_resource::<GameRules>(),
+        ) {
+            (Some(prng), Some(rules)) => {
+                format!("// {}\n{}", ExportStamp::new(prng, rules), serialized)
+            }
+            _ => serialized,
+        },
+        Err(err) => {
+            error!("Failed to serialize board scene: {}", err);
+            return;
+        }
+    };
+
+    let path = Path::new(BOARD_SCENE_EXPORT_PATH);
+    if let Some(parent) = path.parent() {
+        if let Err(err) = std::fs::create_dir_all(parent) {
+            error!("Failed to create {}: {}", parent.display(), err);
+            return;
+        }
+    }
+
+    match std::fs::write(path, serialized) {
+        Ok(()) => info!("Board scene exported to {}", path.display()),
+        Err(err) => error!("Failed to write {}: {}", path.display(), err),
+    }
+}
+
+pub(crate) fn spawn_board_scene(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    source: Option<Res<BoardSceneSource>>,
+) {
+    if let Some(source) = source {
+        commands
+            .spawn(DynamicSceneBundle {
+                scene: asset_server.load(source.0.as_str()),
+                ..default()
+            })
+            .insert(Name::new("Board Scene"))
+            .insert(BoardSceneRoot);
+    }
+}
+
+/// Takes over the regions of a spawned board scene as the game board and redraws it
+pub(crate) fn board_from_scene(
+    mut commands: Commands,
+    scene_roots: Query<(Entity, &Children), With<BoardSceneRoot>>,
+    scene_regions: Query<&Region>,
+    mut board_drawer: BoardDrawer,
+    mut game_state: ResMut<GameState>,
+    profiles: Res<PlayerProfiles>,
+) {
+    let (root, children) = match scene_roots.get_single() {
+        Ok(scene_root) => scene_root,
+        Err(_) => return,
+    };
+
+    let mut regions: Vec<Region> = scene_regions.iter_many(children.iter()).cloned().collect();
+    regions.sort_by_key(|region| region.id);
+
+    let mut board = Board::default();
+    for region in regions.iter() {
+        for hex in region.hexes.iter() {
+            board.hexes.insert(*hex, region.owner);
+        }
+    }
+    board.regions = regions;
+
+    game_state.board = board;
+
+    commands.entity(root).despawn_recursive();
+    board_drawer.redraw(&game_state, &profiles);
+}
@@ -0,0 +1,506 @@
+//! Screenshots of the frame drawn in the window, for bug reports and to share.
+//!
+//! Bevy can't read the window back in this version, so asking for a screenshot with
+//! [`Screenshots::ask`] spawns a capture camera on the board camera, drawing the board and the UI
+//! into an offscreen image the size of the window. Once the cameras rendered, the image is copied
+//! into a buffer and read back without stalling the render, like the pixel of
+//! [`crate::gpu_picking`], and the capture camera is despawned. The frame is ready a frame or two
+//! after it was asked for, see [`Screenshots::take`].
+//!
+//! Pressing [`EXPORT_KEY`] saves the frame as a PNG in [`SCREENSHOTS_DIR`] of the data directory,
+//! next to an SVG drawing of the board (see [`crate::thumbnails::render_svg`]). Both are stamped
+//! with the game (see [`crate::stamp`]), in a `Comment` text chunk of the PNG and an XML comment
+//! of the SVG.
+
+use std::fs;
+use std::io;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext};
+use bevy::render::render_resource::{
+    Buffer, BufferDescriptor, BufferUsages, Extent3d, ImageCopyBuffer, ImageCopyTexture,
+    ImageDataLayout, MapMode, Origin3d, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::render::{RenderApp, RenderStage};
+use wgpu::{BufferAsyncError, Maintain, COPY_BYTES_PER_ROW_ALIGNMENT};
+
+use crate::game::GameState;
+use crate::profiles::PlayerProfiles;
+use crate::rules::GameRules;
+use crate::stamp::ExportStamp;
+use crate::storage::{storage_dir, Location};
+use crate::thumbnails::render_svg;
+use crate::tiered_prng::PrngResource;
+use crate::ui::{spawn_toast, BoardCamera};
+
+pub const SCREENSHOTS_DIR: &str = "screenshots";
+
+/// Key saving a screenshot and a drawing of the board, Print Screen
+const EXPORT_KEY: KeyCode = KeyCode::Snapshot;
+
+/// Name of the render graph node copying the captured frame
+const CAPTURE_NODE: &str = "screenshot_capture";
+
+/// Priority of the capture camera, after the board camera so the dice views it shows are drawn
+const CAPTURE_PRIORITY: isize = 2;
+
+/// Pixels of a captured frame, in sRGB, rows from the top
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    /// Red, green, blue and alpha of every pixel
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    /// The frame as a PNG file, with `stamp` in its `Comment` text chunk
+    pub fn to_png(&self, stamp: &ExportStamp) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let result = encoder
+            .add_text_chunk("Comment".to_string(), stamp.to_string())
+            .and_then(|_| encoder.write_header())
+            .and_then(|mut writer| writer.write_image_data(&self.pixels));
+        if let Err(err) = result {
+            warn!("Failed to encode a screenshot: {}", err);
+        }
+
+        bytes
+    }
+}
+
+/// Bytes of a row of `width` pixels in a buffer the GPU copies an image into
+pub fn padded_row_bytes(width: u32) -> u32 {
+    let row = width * 4;
+    let align = COPY_BYTES_PER_ROW_ALIGNMENT;
+    (row + align - 1) / align * align
+}
+
+/// Screenshots asked for and taken in the main world
+#[derive(Resource, Default)]
+pub struct Screenshots {
+    asked: bool,
+    taken: Option<Frame>,
+}
+
+impl Screenshots {
+    /// Captures the next frame
+    pub fn ask(&mut self) {
+        self.asked = true;
+    }
+
+    /// The frame captured since it was asked for, if it was read back already
+    pub fn take(&mut self) -> Option<Frame> {
+        self.taken.take()
+    }
+}
+
+/// Image the capture camera draws into, extracted to the render world. Every capture has its
+/// own serial, so each is copied only once.
+#[derive(Resource, Clone, Default, ExtractResource)]
+struct CaptureRequest {
+    image: Option<Handle<Image>>,
+    serial: u64,
+}
+
+/// Last frame read back with the serial of its capture, shared by the render world writing it
+/// and the main world reading it
+#[derive(Resource, Clone, Default)]
+struct CapturedFrame {
+    frame: Arc<Mutex<Option<(u64, Frame)>>>,
+}
+
+/// Camera drawing the frame being captured
+#[derive(Component)]
+struct CaptureCamera;
+
+pub struct ScreenshotPlugin;
+
+impl Plugin for ScreenshotPlugin {
+    fn build(&self, app: &mut App) {
+        let captured = CapturedFrame::default();
+
+        app.init_resource::<Screenshots>()
+            .init_resource::<CaptureRequest>()
+            .insert_resource(captured.clone())
+            .add_plugin(ExtractResourcePlugin::<CaptureRequest>::default())
+            .add_system(capture_frames)
+            .add_system(export_screenshots.after(capture_frames));
+
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .insert_resource(captured)
+                .init_resource::<CaptureReadback>()
+                .add_system_to_stage(RenderStage::Cleanup, read_back_frame);
+
+            let mut graph = render_app.world.resource_mut::<RenderGraph>();
+            graph.add_node(CAPTURE_NODE, CaptureNode);
+            graph
+                .add_node_edge(bevy::render::main_graph::node::CAMERA_DRIVER, CAPTURE_NODE)
+                .unwrap();
+        }
+    }
+}
+
+fn capture_image(size: UVec2) -> Image {
+    let size = Extent3d {
+        width: size.x,
+        height: size.y,
+        ..default()
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("screenshot"),
+            size,
+            dimension: TextureDimension::D2,
+            // the format of the window, so the picture has its colors
+            format: TextureFormat::Rgba8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC
+                | TextureUsages::RENDER_ATTACHMENT,
+        },
+        ..default()
+    };
+    image.resize(size);
+    image
+}
+
+/// Spawns the capture camera once a screenshot is asked for, and hands the frame over to
+/// [`Screenshots`] once it was read back
+#[allow(clippy::too_many_arguments)]
+fn capture_frames(
+    mut commands: Commands,
+    mut screenshots: ResMut<Screenshots>,
+    mut request: ResMut<CaptureRequest>,
+    mut images: ResMut<Assets<Image>>,
+    captured: Res<CapturedFrame>,
+    windows: Res<Windows>,
+    board_camera_query: Query<(&Transform, &Projection), With<BoardCamera>>,
+    capture_camera_query: Query<Entity, With<CaptureCamera>>,
+) {
+    if let Some(image) = request.image.clone() {
+        let frame = match captured.frame.lock().unwrap().take() {
+            Some((serial, frame)) if serial == request.serial => frame,
+            _ => return,
+        };
+
+        screenshots.taken = Some(frame);
+        request.image = None;
+        images.remove(&image);
+        for e in capture_camera_query.iter() {
+            commands.entity(e).despawn_recursive();
+        }
+        return;
+    }
+
+    if !screenshots.asked {
+        return;
+    }
+    screenshots.asked = false;
+
+    let size = windows.get_primary().map_or(UVec2::ZERO, |window| {
+        UVec2::new(window.physical_width(), window.physical_height())
+    });
+    let (transform, projection) = match board_camera_query.get_single() {
+        Ok(camera) if size.x > 0 && size.y > 0 => camera,
+        _ => {
+            warn!("No frame to capture, the board isn't drawn in a window");
+            return;
+        }
+    };
+
+    let image = images.add(capture_image(size));
+    commands
+        .spawn(Camera3dBundle {
+            camera: Camera {
+                target: RenderTarget::Image(image.clone()),
+                priority: CAPTURE_PRIORITY,
+                ..default()
+            },
+            projection: projection.clone(),
+            transform: *transform,
+            ..default()
+        })
+        .insert(Name::new("Capture Camera"))
+        .insert(CaptureCamera);
+    request.image = Some(image);
+    request.serial += 1;
+}
+
+/// Saves the PNG of `frame` and the SVG of `game_state` in [`SCREENSHOTS_DIR`], named after
+/// `serial`. Returns the path of the PNG.
+fn save_export(
+    frame: &Frame,
+    game_state: &GameState,
+    profiles: &PlayerProfiles,
+    stamp: &ExportStamp,
+    serial: u64,
+) -> io::Result<PathBuf> {
+    let dir = storage_dir(Location::Data).join(SCREENSHOTS_DIR);
+    fs::create_dir_all(&dir)?;
+    let name = format!("screenshot_{}_{}", std::process::id(), serial);
+    let path = dir.join(format!("{}.png", name));
+    fs::write(&path, frame.to_png(stamp))?;
+    fs::write(
+        dir.join(format!("{}.svg", name)),
+        render_svg(&game_state.board, profiles, &stamp.to_string()),
+    )?;
+
+    Ok(path)
+}
+
+/// Asks for a screenshot on [`EXPORT_KEY`], and saves it with a drawing of the board once the
+/// frame was read back
+#[allow(clippy::too_many_arguments)]
+fn export_screenshots(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    keys: Res<Input<KeyCode>>,
+    mut screenshots: ResMut<Screenshots>,
+    game_state: Res<GameState>,
+    profiles: Res<PlayerProfiles>,
+    prng: Res<PrngResource>,
+    rules: Res<GameRules>,
+    mut waiting: Local<bool>,
+    mut saved: Local<u64>,
+) {
+    if keys.just_pressed(EXPORT_KEY) && !*waiting {
+        screenshots.ask();
+        *waiting = true;
+    }
+    if !*waiting {
+        return;
+    }
+    let frame = match screenshots.take() {
+        Some(frame) => frame,
+        None => return,
+    };
+    *waiting = false;
+
+    *saved += 1;
+    let stamp = ExportStamp::new(&prng, &rules);
+    match save_export(&frame, &game_state, &profiles, &stamp, *saved) {
+        Ok(path) => spawn_toast(
+            &mut commands,
+            &asset_server,
+            format!("Screenshot saved to {}", path.display()),
+        ),
+        Err(err) => warn!("Failed to save a screenshot: {}", err),
+    }
+}
+
+/// Where the [`CaptureReadback`] is in reading back a frame
+#[derive(Default)]
+enum CaptureState {
+    /// Free to copy a new frame into a buffer
+    #[default]
+    Idle,
+    /// The frame was copied into the buffer this frame
+    Copied {
+        buffer: Buffer,
+        size: UVec2,
+        serial: u64,
+    },
+    /// Being mapped for reading
+    Mapping {
+        buffer: Buffer,
+        size: UVec2,
+        serial: u64,
+        mapped: Receiver<Result<(), BufferAsyncError>>,
+    },
+}
+
+/// Frame being read back in the render world
+#[derive(Resource, Default)]
+struct CaptureReadback {
+    state: Mutex<CaptureState>,
+    /// Serial of the last capture copied, which isn't copied again
+    copied: Mutex<u64>,
+}
+
+/// Copies the captured frame into a buffer once the cameras rendered
+struct CaptureNode;
+
+impl Node for CaptureNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let (request, readback) = match (
+            world.get_resource::<CaptureRequest>(),
+            world.get_resource::<CaptureReadback>(),
+        ) {
+            (Some(request), Some(readback)) => (request, readback),
+            _ => return Ok(()),
+        };
+        let image = match request.image.as_ref() {
+            Some(image) => world.resource::<RenderAssets<Image>>().get(image),
+            None => return Ok(()),
+        };
+        let image = match image {
+            Some(image) => image,
+            None => return Ok(()),
+        };
+        let mut state = readback.state.lock().unwrap();
+        let mut copied = readback.copied.lock().unwrap();
+        if !matches!(*state, CaptureState::Idle) || *copied == request.serial {
+            return Ok(());
+        }
+
+        let size = image.size.as_uvec2();
+        let row_bytes = padded_row_bytes(size.x);
+        let buffer = world
+            .resource::<RenderDevice>()
+            .create_buffer(&BufferDescriptor {
+                label: Some("screenshot_readback"),
+                size: row_bytes as u64 * size.y as u64,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+        render_context.command_encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                texture: &image.texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+        *copied = request.serial;
+        *state = CaptureState::Copied {
+            buffer,
+            size,
+            serial: request.serial,
+        };
+
+        Ok(())
+    }
+}
+
+/// Starts mapping the frame copied this frame, and reads it once the GPU is done with it without
+/// waiting for it
+fn read_back_frame(
+    render_device: Res<RenderDevice>,
+    readback: Res<CaptureReadback>,
+    captured: Res<CapturedFrame>,
+) {
+    let mut state = readback.state.lock().unwrap();
+    if let CaptureState::Copied {
+        buffer,
+        size,
+        serial,
+    } = std::mem::take(&mut *state)
+    {
+        let (sender, receiver) = mpsc::channel();
+        render_device.map_buffer(&buffer.slice(..), MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        *state = CaptureState::Mapping {
+            buffer,
+            size,
+            serial,
+            mapped: receiver,
+        };
+    }
+
+    render_device.poll(Maintain::Poll);
+    let result = match &*state {
+        CaptureState::Mapping { mapped, .. } => mapped.try_recv(),
+        _ => return,
+    };
+    match result {
+        Err(TryRecvError::Empty) => {}
+        Ok(Ok(())) => {
+            if let CaptureState::Mapping {
+                buffer,
+                size,
+                serial,
+                ..
+            } = std::mem::take(&mut *state)
+            {
+                let bytes = buffer.slice(..).get_mapped_range();
+                let row_bytes = padded_row_bytes(size.x) as usize;
+                let pixels = bytes
+                    .chunks(row_bytes)
+                    .flat_map(|row| &row[..size.x as usize * 4])
+                    .copied()
+                    .collect();
+                drop(bytes);
+                buffer.unmap();
+
+                let frame = Frame {
+                    width: size.x,
+                    height: size.y,
+                    pixels,
+                };
+                *captured.frame.lock().unwrap() = Some((serial, frame));
+            }
+        }
+        Ok(Err(_)) | Err(TryRecvError::Disconnected) => *state = CaptureState::Idle,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_encode_as_png() {
+        assert_eq!(padded_row_bytes(1), 256);
+        assert_eq!(padded_row_bytes(64), 256);
+        assert_eq!(padded_row_bytes(65), 512);
+
+        let frame = Frame {
+            width: 2,
+            height: 1,
+            pixels: vec![255, 0, 0, 255, 0, 0, 255, 255],
+        };
+        let stamp = ExportStamp::new(
+            &PrngResource {
+                world_seed: 12,
+                env_seed: 34,
+            },
+            &GameRules::default(),
+        );
+        let encoded = frame.to_png(&stamp);
+        assert!(encoded.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        let mut reader = png::Decoder::new(encoded.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(pixels, frame.pixels);
+
+        // the stamp is kept in a text chunk
+        let text = &reader.info().uncompressed_latin1_text;
+        assert_eq!(text[0].keyword, "Comment");
+        assert_eq!(text[0].text, stamp.to_string());
+    }
+}
@@ -0,0 +1,662 @@
+//! Who plays each seat of the game.
+//!
+//! Every player is controlled by a [`SeatController`]: the local mouse, a bot, or a remote
+//! connection feeding it actions. Clicks on the board only act for local human seats, the other
+//! seats are played by [`drive_seats`], one action at a time once the previous battle resolved.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::seq::IteratorRandom;
+use rand_chacha::ChaCha20Rng;
+
+use crate::events::{
+    EventBoardChanged, EventPlayerMoveStart, EventReinforcementPlaced, EventTurnEnd,
+    EventTurnPassed, EventTurnStart,
+};
+use crate::game::{GameState, RegionId, TurnPhase};
+use crate::logic_step::LOGIC_STEP;
+use crate::odds::attack_win_probability;
+use crate::rules::GameRules;
+use crate::tiered_prng::get_randomness;
+
+/// Something a seat does on its turn
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeatAction {
+    Attack {
+        from: RegionId,
+        to: RegionId,
+    },
+    Reinforce(RegionId),
+    /// Stops attacking for this turn
+    EndTurn,
+}
+
+/// Number of candidate attacks of a deliberating bot shown on the board
+pub const CANDIDATES_SHOWN: usize = 3;
+
+/// Time the attack chosen by a bot is shown before its dice roll
+const MOVE_PREVIEW: Duration = Duration::from_millis(600);
+
+/// Time bots take for every action unless set otherwise
+pub const DEFAULT_THINK_TIME: Duration = Duration::from_millis(500);
+
+/// Player driven by code instead of a person
+pub trait BotPlayer: Send + Sync {
+    /// Next action for the player in turn, `None` when the bot has nothing to do
+    fn next_action(&mut self, game_state: &GameState, rules: &GameRules) -> Option<SeatAction>;
+
+    /// Attacks the bot weighs for the player in turn, strongest first, shown to the other
+    /// players while it thinks. Bots that don't tell show nothing.
+    fn candidate_attacks(
+        &self,
+        _game_state: &GameState,
+        _rules: &GameRules,
+    ) -> Vec<(RegionId, RegionId)> {
+        Vec::new()
+    }
+}
+
+/// Bot attacking with a random legal move and placing reinforcements on random regions
+pub struct RandomBot {
+    rng: ChaCha20Rng,
+}
+
+impl RandomBot {
+    pub fn new(seed: u64) -> Self {
+        RandomBot {
+            rng: get_randomness(seed),
+        }
+    }
+}
+
+impl BotPlayer for RandomBot {
+    fn next_action(&mut self, game_state: &GameState, rules: &GameRules) -> Option<SeatAction> {
+        match game_state.turn_phase {
+            TurnPhase::Attack => game_state
+                .attacks(rules)
+                .filter(|(region_1, _)| !region_1.has_moved)
+                .choose(&mut self.rng)
+                .map(|(region_1, region_2)| SeatAction::Attack {
+                    from: region_1.id,
+                    to: region_2.id,
+                }),
+            TurnPhase::Reinforcement => game_state
+                .board
+                .regions
+                .iter()
+                .filter(|region| game_state.can_place_reinforcement(region, rules))
+                .choose(&mut self.rng)
+                .map(|region| SeatAction::Reinforce(region.id)),
+        }
+    }
+
+    /// Every attack it may pick, the ones with the biggest lead in dice first
+    fn candidate_attacks(
+        &self,
+        game_state: &GameState,
+        rules: &GameRules,
+    ) -> Vec<(RegionId, RegionId)> {
+        if game_state.turn_phase != TurnPhase::Attack {
+            return Vec::new();
+        }
+
+        let mut attacks: Vec<_> = game_state
+            .attacks(rules)
+            .filter(|(region_1, _)| !region_1.has_moved)
+            .collect();
+        attacks.sort_by_key(|(region_1, region_2)| {
+            std::cmp::Reverse(region_1.num_dice as isize - region_2.num_dice as isize)
+        });
+        attacks
+            .into_iter()
+            .map(|(region_1, region_2)| (region_1.id, region_2.id))
+            .collect()
+    }
+}
+
+/// Bot taking the attack it is the most likely to win, as long as its odds are good enough, and
+/// placing reinforcements like [`RandomBot`]
+pub struct OddsBot {
+    random: RandomBot,
+    /// Attacks less likely to succeed are left alone, ending the turn
+    min_win_probability: f64,
+}
+
+impl OddsBot {
+    pub fn new(seed: u64, min_win_probability: f64) -> Self {
+        OddsBot {
+            random: RandomBot::new(seed),
+            min_win_probability,
+        }
+    }
+}
+
+impl BotPlayer for OddsBot {
+    fn next_action(&mut self, game_state: &GameState, rules: &GameRules) -> Option<SeatAction> {
+        if game_state.turn_phase != TurnPhase::Attack {
+            return self.random.next_action(game_state, rules);
+        }
+
+        let attacks = self.candidate_attacks(game_state, rules);
+        let can_attack = game_state
+            .attacks(rules)
+            .any(|(region_1, _)| !region_1.has_moved);
+        match (attacks.first(), can_attack) {
+            (Some((from, to)), _) => Some(SeatAction::Attack {
+                from: *from,
+                to: *to,
+            }),
+            // passing instead of taking bad odds
+            (None, true) => Some(SeatAction::EndTurn),
+            (None, false) => None,
+        }
+    }
+
+    /// Every attack good enough to take, the most likely to succeed first
+    fn candidate_attacks(
+        &self,
+        game_state: &GameState,
+        rules: &GameRules,
+    ) -> Vec<(RegionId, RegionId)> {
+        if game_state.turn_phase != TurnPhase::Attack {
+            return Vec::new();
+        }
+
+        let mut attacks: Vec<_> = game_state
+            .attacks(rules)
+            .filter(|(region_1, _)| !region_1.has_moved)
+            .map(|(region_1, region_2)| {
+                let probability =
+                    attack_win_probability(region_1.num_dice, region_2.num_dice, rules);
+                (probability, region_1.id, region_2.id)
+            })
+            .filter(|(probability, _, _)| *probability >= self.min_win_probability)
+            .collect();
+        attacks.sort_by(|(probability_1, _, _), (probability_2, _, _)| {
+            probability_2.total_cmp(probability_1)
+        });
+        attacks
+            .into_iter()
+            .map(|(_, region_1, region_2)| (region_1, region_2))
+            .collect()
+    }
+}
+
+/// How well the bots of a game play
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BotDifficulty {
+    /// Attacks at random
+    #[default]
+    Easy,
+    /// Takes the best attack while it is more likely to win than to lose
+    Normal,
+    /// Only takes attacks it is very likely to win
+    Hard,
+}
+
+impl BotDifficulty {
+    pub fn name(self) -> &'static str {
+        match self {
+            BotDifficulty::Easy => "easy",
+            BotDifficulty::Normal => "normal",
+            BotDifficulty::Hard => "hard",
+        }
+    }
+
+    /// Bot of this difficulty drawing its random choices from `seed`
+    pub fn bot(self, seed: u64) -> Box<dyn BotPlayer> {
+        match self {
+            BotDifficulty::Easy => Box::new(RandomBot::new(seed)),
+            BotDifficulty::Normal => Box::new(OddsBot::new(seed, 0.5)),
+            BotDifficulty::Hard => Box::new(OddsBot::new(seed, 0.7)),
+        }
+    }
+}
+
+/// Actions received for a remote seat, filled by whatever talks to the other side
+#[derive(Clone, Default)]
+pub struct RemoteConnection {
+    inbox: Arc<Mutex<VecDeque<SeatAction>>>,
+}
+
+impl RemoteConnection {
+    /// Queues an action received from the remote player
+    pub fn push(&self, action: SeatAction) {
+        self.inbox.lock().unwrap().push_back(action);
+    }
+
+    fn pop(&self) -> Option<SeatAction> {
+        self.inbox.lock().unwrap().pop_front()
+    }
+}
+
+/// Who controls a seat
+pub enum SeatController {
+    LocalHuman,
+    Bot(Box<dyn BotPlayer>),
+    Remote(RemoteConnection),
+}
+
+/// Controllers of every seat, indexed by player. Seats without a controller are local humans.
+#[derive(Resource, Default)]
+pub struct Seats {
+    pub controllers: Vec<SeatController>,
+}
+
+impl Seats {
+    /// Whether clicks on the board act for `player`
+    pub fn is_local_human(&self, player: usize) -> bool {
+        matches!(
+            self.controllers.get(player),
+            None | Some(SeatController::LocalHuman)
+        )
+    }
+
+    /// Whether anyone plays with the local mouse, otherwise the game is only watched
+    pub fn has_local_human(&self) -> bool {
+        self.controllers.is_empty()
+            || self
+                .controllers
+                .iter()
+                .any(|controller| matches!(controller, SeatController::LocalHuman))
+    }
+}
+
+/// How fast seats that aren't played with the local mouse act
+#[derive(Resource, Clone, Debug, PartialEq, Eq)]
+pub struct BotPacing {
+    /// Time the turn driver waits before every action of a bot. Zero acts at every step of the
+    /// game logic, without previewing attacks.
+    pub think_time: Duration,
+}
+
+impl Default for BotPacing {
+    fn default() -> Self {
+        BotPacing {
+            think_time: DEFAULT_THINK_TIME,
+        }
+    }
+}
+
+impl BotPacing {
+    /// Time the turn driver waits before every action of `controller`. Remote seats act as soon
+    /// as their moves arrive, they already took their time on the other end.
+    pub fn think_time(&self, controller: &SeatController) -> Duration {
+        match controller {
+            SeatController::Bot(_) => self.think_time,
+            SeatController::LocalHuman | SeatController::Remote(_) => Duration::ZERO,
+        }
+    }
+
+    /// Time the attack chosen by a bot is shown before its dice roll, never longer than the bot
+    /// thinks. `None` for instant bots.
+    pub fn preview_time(&self) -> Option<Duration> {
+        match self.think_time.is_zero() {
+            true => None,
+            false => Some(MOVE_PREVIEW.min(self.think_time)),
+        }
+    }
+}
+
+/// What the bot in turn is deliberating, so its opponents can follow its play
+#[derive(Resource, Default, Clone, Debug, PartialEq, Eq)]
+pub struct BotIntent {
+    /// Bot deliberating, `None` while no bot is
+    pub player: Option<usize>,
+    /// Regions the bot considers attacking from, strongest first, at most [`CANDIDATES_SHOWN`]
+    pub candidates: Vec<RegionId>,
+    /// Attacker and defender of the attack the bot chose, shown until its dice roll
+    pub chosen: Option<(RegionId, RegionId)>,
+}
+
+impl BotIntent {
+    /// Intent of `player` weighing `attacks`, strongest first
+    pub fn thinking(player: usize, attacks: &[(RegionId, RegionId)]) -> Self {
+        let mut candidates: Vec<RegionId> = Vec::new();
+        for (attacker, _) in attacks {
+            if candidates.len() < CANDIDATES_SHOWN && !candidates.contains(attacker) {
+                candidates.push(*attacker);
+            }
+        }
+
+        BotIntent {
+            player: Some(player),
+            candidates,
+            chosen: None,
+        }
+    }
+}
+
+/// Paces actions of seats that aren't played with the local mouse
+#[derive(Resource)]
+pub(crate) struct TurnDriver {
+    timer: Timer,
+    /// Length of the game log when the last attack was ordered, until its battle is resolved
+    battle_pending: Option<usize>,
+    /// Attack chosen by a bot, ordered once it was shown for [`MOVE_PREVIEW`]
+    preview: Option<(RegionId, RegionId, Timer)>,
+}
+
+impl Default for TurnDriver {
+    fn default() -> Self {
+        TurnDriver {
+            timer: Timer::new(DEFAULT_THINK_TIME, TimerMode::Repeating),
+            battle_pending: None,
+            preview: None,
+        }
+    }
+}
+
+impl TurnDriver {
+    /// Whether the battle of the last ordered attack is still to be logged or resolved. Read from
+    /// the game log rather than from events, which the fixed timestep may skip.
+    fn battle_pending(&self, game_state: &GameState) -> bool {
+        match self.battle_pending {
+            Some(log_len) => {
+                !matches!(game_state.game_log.get(log_len), Some(entry) if entry.resolved)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Starts the battle of an attack of the player in turn, if it is still legal
+fn order_attack(
+    turn_driver: &mut TurnDriver,
+    game_state: &GameState,
+    rules: &GameRules,
+    event_player_move_start_writer: &mut EventWriter<EventPlayerMoveStart>,
+    from: RegionId,
+    to: RegionId,
+) {
+    let regions = &game_state.board.regions;
+    let (region_1, region_2) = match (regions.get(from), regions.get(to)) {
+        (Some(region_1), Some(region_2)) => (region_1, region_2),
+        _ => return,
+    };
+    if region_1.owner == game_state.turn_of_player
+        && !region_1.has_moved
+        && game_state.can_attack(region_1, region_2, rules)
+    {
+        event_player_move_start_writer.send(EventPlayerMoveStart {
+            region_1: from,
+            region_2: to,
+        });
+        // in blitz mode the attack is only an order, no battle starts yet
+        if !rules.blitz {
+            turn_driver.battle_pending = Some(game_state.game_log.len());
+        }
+    }
+}
+
+/// Plays the next action of the seat in turn when it isn't a local human. A bot attack is
+/// previewed on the board for a moment before it is ordered.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn drive_seats(
+    mut seats: ResMut<Seats>,
+    mut turn_driver: ResMut<TurnDriver>,
+    mut game_state: ResMut<GameState>,
+    rules: Res<GameRules>,
+    mut event_player_move_start_writer: EventWriter<EventPlayerMoveStart>,
+    mut event_reinforcement_placed_writer: EventWriter<EventReinforcementPlaced>,
+    mut event_board_changed_writer: EventWriter<EventBoardChanged>,
+    mut event_turn_passed_writer: EventWriter<EventTurnPassed>,
+    mut event_turn_end_writer: EventWriter<EventTurnEnd>,
+    mut event_turn_start_writer: EventWriter<EventTurnStart>,
+    mut bot_intent: ResMut<BotIntent>,
+    pacing: Res<BotPacing>,
+) {
+    if !turn_driver.battle_pending(&game_state) {
+        turn_driver.battle_pending = None;
+    }
+
+    let player = game_state.turn_of_player;
+    if turn_driver.battle_pending.is_some()
+        || game_state.winner().is_some()
+        || seats.is_local_human(player)
+    {
+        turn_driver.preview = None;
+        if bot_intent.player.is_some() {
+            *bot_intent = BotIntent::default();
+        }
+        return;
+    }
+
+    // the attack chosen by a bot is shown for a moment before its dice roll
+    if let Some((from, to, timer)) = &mut turn_driver.preview {
+        if !timer.tick(LOGIC_STEP).finished() {
+            return;
+        }
+
+        let (from, to) = (*from, *to);
+        turn_driver.preview = None;
+        *bot_intent = BotIntent::default();
+        order_attack(
+            &mut turn_driver,
+            &game_state,
+            &rules,
+            &mut event_player_move_start_writer,
+            from,
+            to,
+        );
+        return;
+    }
+
+    if let SeatController::Bot(bot) = &seats.controllers[player] {
+        if bot_intent.player != Some(player) {
+            *bot_intent = BotIntent::thinking(player, &bot.candidate_attacks(&game_state, &rules));
+        }
+    }
+
+    let think_time = pacing.think_time(&seats.controllers[player]);
+    if turn_driver.timer.duration() != think_time {
+        turn_driver.timer.set_duration(think_time);
+    }
+    // a timer can't repeat without a duration, instant seats act at every step
+    if !think_time.is_zero() && !turn_driver.timer.tick(LOGIC_STEP).just_finished() {
+        return;
+    }
+
+    let (action, is_bot) = match &mut seats.controllers[player] {
+        SeatController::LocalHuman => (None, false),
+        SeatController::Bot(bot) => (bot.next_action(&game_state, &rules), true),
+        SeatController::Remote(connection) => (connection.pop(), false),
+    };
+    if action.is_some() {
+        *bot_intent = BotIntent::default();
+    }
+
+    match (action, pacing.preview_time()) {
+        (Some(SeatAction::Attack { from, to }), Some(preview_time)) if is_bot => {
+            bot_intent.player = Some(player);
+            bot_intent.chosen = Some((from, to));
+            turn_driver.preview = Some((from, to, Timer::new(preview_time, TimerMode::Once)));
+        }
+        (Some(SeatAction::Attack { from, to }), _) => {
+            order_attack(
+                &mut turn_driver,
+                &game_state,
+                &rules,
+                &mut event_player_move_start_writer,
+                from,
+                to,
+            );
+        }
+        (Some(SeatAction::Reinforce(region)), _) => {
+            if game_state.place_reinforcement(region, &rules) {
+                event_reinforcement_placed_writer.send(EventReinforcementPlaced { region });
+                event_board_changed_writer.send(EventBoardChanged);
+            }
+        }
+        (Some(SeatAction::EndTurn), _) => {
+            if game_state.turn_phase == TurnPhase::Attack {
+                event_turn_passed_writer.send(EventTurnPassed { player });
+                if rules.blitz {
+                    return;
+                }
+                if game_state.end_turn(&rules) {
+                    event_turn_end_writer.send(EventTurnEnd { player });
+                    event_turn_start_writer.send(EventTurnStart {
+                        player: game_state.turn_of_player,
+                        turn: game_state.turn_counter,
+                    });
+                }
+                event_board_changed_writer.send(EventBoardChanged);
+            }
+        }
+        (None, _) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameLogEntry;
+    use crate::map_file::MapFile;
+
+    #[test]
+    fn random_bot_only_plays_legal_actions() {
+        let map =
+            MapFile::parse("players 2\nregion 0 3 0,0\nregion 1 2 1,0\nregion 1 2 5,5\n").unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        let rules = GameRules::default();
+        let mut bot = RandomBot::new(1);
+
+        assert_eq!(
+            bot.next_action(&game_state, &rules),
+            Some(SeatAction::Attack { from: 0, to: 1 })
+        );
+
+        game_state.board.regions[0].has_moved = true;
+        assert_eq!(bot.next_action(&game_state, &rules), None);
+    }
+
+    #[test]
+    fn bot_intent_shows_the_strongest_attackers() {
+        let map = MapFile::parse(
+            "players 2\nregion 0 2 0,0\nregion 1 1 1,0\nregion 0 6 2,0\nregion 1 5 3,0\n",
+        )
+        .unwrap();
+        let game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        let attacks = RandomBot::new(1).candidate_attacks(&game_state, &GameRules::default());
+
+        // region 2 leads region 1 by five dice, the other attacks only by one
+        assert_eq!(attacks.len(), 3);
+        assert_eq!(attacks[0], (2, 1));
+
+        let intent = BotIntent::thinking(0, &attacks);
+        assert_eq!(intent.player, Some(0));
+        assert_eq!(intent.candidates, vec![2, 0]);
+        assert_eq!(intent.chosen, None);
+    }
+
+    #[test]
+    fn odds_bot_passes_on_bad_odds() {
+        let map = MapFile::parse(
+            "players 2\nregion 0 2 0,0\nregion 1 1 1,0\nregion 0 6 2,0\nregion 1 5 3,0\n",
+        )
+        .unwrap();
+        let mut game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+        let rules = GameRules::default();
+        let mut bot = OddsBot::new(1, 0.7);
+
+        // six dice against one is the safest attack
+        assert_eq!(
+            bot.next_action(&game_state, &rules),
+            Some(SeatAction::Attack { from: 2, to: 1 })
+        );
+
+        // two dice against five is too risky, the bot passes instead
+        game_state.board.regions[0].num_dice = 2;
+        game_state.board.regions[1].num_dice = 5;
+        game_state.board.regions[2].has_moved = true;
+        assert_eq!(
+            bot.next_action(&game_state, &rules),
+            Some(SeatAction::EndTurn)
+        );
+        assert!(BotDifficulty::Easy
+            .bot(1)
+            .next_action(&game_state, &rules)
+            .is_some());
+
+        game_state.board.regions[0].has_moved = true;
+        assert_eq!(bot.next_action(&game_state, &rules), None);
+    }
+
+    #[test]
+    fn battle_stays_pending_until_its_entry_is_resolved() {
+        let mut game_state = GameState::default();
+        let mut turn_driver = TurnDriver::default();
+        assert!(!turn_driver.battle_pending(&game_state));
+
+        game_state.game_log.push(GameLogEntry {
+            resolved: true,
+            ..Default::default()
+        });
+        turn_driver.battle_pending = Some(game_state.game_log.len());
+        assert!(turn_driver.battle_pending(&game_state));
+
+        game_state.game_log.push(GameLogEntry::default());
+        assert!(turn_driver.battle_pending(&game_state));
+
+        game_state.game_log[1].resolved = true;
+        assert!(!turn_driver.battle_pending(&game_state));
+    }
+
+    #[test]
+    fn previews_never_outlast_the_think_time() {
+        let pacing = |millis| BotPacing {
+            think_time: Duration::from_millis(millis),
+        };
+
+        assert_eq!(pacing(0).preview_time(), None);
+        assert_eq!(pacing(200).preview_time(), Some(Duration::from_millis(200)));
+        assert_eq!(pacing(2000).preview_time(), Some(MOVE_PREVIEW));
+    }
+
+    #[test]
+    fn only_bots_take_their_think_time() {
+        let pacing = BotPacing {
+            think_time: Duration::from_millis(800),
+        };
+
+        let bot = SeatController::Bot(Box::new(RandomBot::new(1)));
+        assert_eq!(pacing.think_time(&bot), Duration::from_millis(800));
+        let remote = SeatController::Remote(RemoteConnection::default());
+        assert_eq!(pacing.think_time(&remote), Duration::ZERO);
+        assert_eq!(
+            pacing.think_time(&SeatController::LocalHuman),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn missing_seats_are_local_humans() {
+        let seats = Seats {
+            controllers: vec![
+                SeatController::LocalHuman,
+                SeatController::Bot(Box::new(RandomBot::new(1))),
+            ],
+        };
+
+        assert!(seats.is_local_human(0));
+        assert!(!seats.is_local_human(1));
+        assert!(seats.is_local_human(2));
+    }
+}
@@ -0,0 +1,250 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::RngCore;
+
+use crate::board::BoardDrawer;
+use crate::game::{first_player, generate_board, GameState};
+use crate::new_game::NewGame;
+use crate::profiles::PlayerProfiles;
+use crate::rules::GameRules;
+use crate::tiered_prng::{get_randomness, PrngResource};
+use crate::ui::StackRankDiceUI;
+
+/// A series of games played on fresh boards until one player has won the majority of them, or
+/// the most of them once every game was played
+#[derive(Clone, Resource, Reflect, FromReflect)]
+#[reflect(Resource)]
+pub struct MatchSession {
+    /// Maximum number of games in the match
+    pub best_of: usize,
+    /// Number of games won, by player
+    pub scores: Vec<usize>,
+    /// 1-based number of the game being played
+    pub game_number: usize,
+}
+
+impl Default for MatchSession {
+    fn default() -> Self {
+        MatchSession::new(1, 2)
+    }
+}
+
+impl MatchSession {
+    pub fn new(best_of: usize, number_of_players: usize) -> Self {
+        MatchSession {
+            best_of,
+            scores: vec![0; number_of_players],
+            game_number: 1,
+        }
+    }
+
+    /// Number of game wins that decide the match
+    pub fn wins_needed(&self) -> usize {
+        self.best_of / 2 + 1
+    }
+
+    pub fn record_win(&mut self, player: usize) {
+        self.scores[player] += 1;
+    }
+
+    /// Winner of the match: the first player to win the majority of the games, or once every
+    /// game was played the player with the most wins. With more than two players, or an even
+    /// number of games, nobody may get the majority, and players tied for the most wins play
+    /// deciding games until one of them leads.
+    pub fn match_winner(&self) -> Option<usize> {
+        if let Some(player) = self
+            .scores
+            .iter()
+            .position(|score| *score >= self.wins_needed())
+        {
+            return Some(player);
+        }
+        if self.scores.iter().sum::<usize>() < self.best_of {
+            return None;
+        }
+
+        let most_wins = self.scores.iter().max()?;
+        let mut leaders =
+            (0..self.scores.len()).filter(|player| self.scores[*player] == *most_wins);
+        match (leaders.next(), leaders.next()) {
+            (Some(leader), None) => Some(leader),
+            _ => None,
+        }
+    }
+
+    /// Score formatted as "2 - 1"
+    pub fn score_text(&self) -> String {
+        self.scores
+            .iter()
+            .map(|score| score.to_string())
+            .collect::<Vec<String>>()
+            .join(" - ")
+    }
+}
+
+/// Text area with the match score
+#[derive(Component)]
+pub(crate) struct MatchScoreText;
+
+/// Counts down from the end of a game to the start of the next game of the match
+#[derive(Component)]
+pub(crate) struct NextGameTimer {
+    timer: Timer,
+}
+
+impl Default for NextGameTimer {
+    fn default() -> Self {
+        NextGameTimer {
+            timer: Timer::new(Duration::from_secs(3), TimerMode::Once),
+        }
+    }
+}
+
+pub(crate) fn setup_match_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    match_session: Option<Res<MatchSession>>,
+) {
+    if match_session.is_none() {
+        return;
+    }
+
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_text_alignment(TextAlignment::TOP_CENTER)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(5.0),
+                    left: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(MatchScoreText)
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn match_score_text_update(
+    match_session: Option<Res<MatchSession>>,
+    mut query: Query<&mut Text, With<MatchScoreText>>,
+) {
+    let match_session = match match_session {
+        Some(match_session) => match_session,
+        None => return,
+    };
+
+    for mut text in &mut query {
+        text.sections[0].value = match match_session.game_number > match_session.best_of {
+            true => format!("DECIDING GAME   {}", match_session.score_text()),
+            false => format!(
+                "GAME {} OF {}   {}",
+                match_session.game_number,
+                match_session.best_of,
+                match_session.score_text()
+            ),
+        };
+    }
+}
+
+/// Builds a fresh game on a board generated from `world_seed`
+pub fn new_game_state(rules: &GameRules, world_seed: u64) -> GameState {
+    new_game_state_started_by(rules, world_seed, first_player(rules, world_seed))
+}
+
+/// Builds a fresh game on a board generated from `world_seed`, started by `first_player` instead
+/// of the player the rules draw, as in a rematch
+pub fn new_game_state_started_by(
+    rules: &GameRules,
+    world_seed: u64,
+    first_player: usize,
+) -> GameState {
+    let mut game_state = GameState {
+        board: generate_board(rules, get_randomness(world_seed)),
+        number_of_players: rules.number_of_players,
+        ..default()
+    };
+    game_state.seat_players(first_player, rules);
+    game_state
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn start_next_game(
+    mut commands: Commands,
+    mut next_game_timer_query: Query<(Entity, &mut NextGameTimer)>,
+    time: Res<Time>,
+    match_session: Option<ResMut<MatchSession>>,
+    mut prng: ResMut<PrngResource>,
+    rules: Res<GameRules>,
+    mut board_drawer: BoardDrawer,
+    mut game_state: ResMut<GameState>,
+    profiles: Res<PlayerProfiles>,
+    mut new_game: NewGame,
+) {
+    let mut next_game = false;
+
+    for (entity, mut next_game_timer) in next_game_timer_query.iter_mut() {
+        next_game_timer.timer.tick(time.delta());
+        if next_game_timer.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            next_game = true;
+        }
+    }
+
+    let mut match_session = match match_session {
+        Some(match_session) if next_game => match_session,
+        _ => return,
+    };
+
+    // Every game of the match gets its own board, derived from the seed of the previous one
+    prng.world_seed = get_randomness(prng.world_seed).next_u64();
+    match_session.game_number += 1;
+    *game_state = new_game_state(&rules, prng.world_seed);
+    new_game.reset();
+    board_drawer.redraw(&game_state, &profiles);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_majority_wins_the_match() {
+        let mut match_session = MatchSession::new(5, 2);
+
+        match_session.record_win(1);
+        match_session.record_win(0);
+        match_session.record_win(1);
+        assert_eq!(match_session.match_winner(), None);
+        match_session.record_win(1);
+        assert_eq!(match_session.match_winner(), Some(1));
+    }
+
+    #[test]
+    fn matches_without_a_majority_end_on_the_most_wins() {
+        let mut match_session = MatchSession::new(3, 3);
+        for player in 0..3 {
+            match_session.record_win(player);
+        }
+        // everyone won a game, the match goes on until someone leads
+        assert_eq!(match_session.match_winner(), None);
+        match_session.record_win(2);
+        assert_eq!(match_session.match_winner(), Some(2));
+
+        let mut even = MatchSession::new(4, 3);
+        for player in [0, 1, 0, 2] {
+            even.record_win(player);
+        }
+        assert_eq!(even.match_winner(), Some(0));
+    }
+}
@@ -0,0 +1,126 @@
+//! Headless games played without the Bevy app, for balance testing and tournaments.
+//!
+//! Every player attacks with a random legal move until it can't attack anymore, and places
+//! manual reinforcements on random regions. Games are independent and run in parallel.
+
+use rand::seq::IteratorRandom;
+use rayon::prelude::*;
+
+use crate::game::{roll_dice, GameLogEntry, TurnPhase};
+use crate::rules::GameRules;
+use crate::session::new_game_state;
+use crate::tiered_prng::get_randomness;
+
+/// Setup of one simulated game
+#[derive(Clone, Debug)]
+pub struct SimulationConfig {
+    pub rules: GameRules,
+    /// Seed the board is generated from
+    pub world_seed: u64,
+    /// Seed of the moves and dice rolls
+    pub env_seed: u64,
+    /// The game is stopped without a winner after this many turns
+    pub max_turns: usize,
+}
+
+/// Result of a simulated game
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameSummary {
+    pub world_seed: u64,
+    pub env_seed: u64,
+    pub winner: Option<usize>,
+    /// Player that moved first
+    pub first_player: usize,
+    pub turns: usize,
+    pub battles: usize,
+}
+
+/// Plays a whole game for `config`. The same config always plays the same game.
+pub fn simulate(config: &SimulationConfig) -> GameSummary {
+    let rules = &config.rules;
+    let mut game_state = new_game_state(rules, config.world_seed);
+    let first_player = game_state.turn_of_player;
+    let mut rng = get_randomness(config.env_seed);
+
+    while game_state.winner().is_none() && game_state.turn_counter < config.max_turns {
+        if game_state.turn_phase == TurnPhase::Reinforcement {
+            let region = game_state
+                .board
+                .regions
+                .iter()
+                .filter(|region| game_state.can_place_reinforcement(region, rules))
+                .map(|region| region.id)
+                .choose(&mut rng);
+            if let Some(region) = region {
+                game_state.place_reinforcement(region, rules);
+            }
+
+            game_state.finish_turn(rules);
+            continue;
+        }
+
+        let attack = game_state
+            .attacks(rules)
+            .filter(|(region_1, _)| !region_1.has_moved)
+            .choose(&mut rng)
+            .map(|(region_1, region_2)| (region_1.id, region_2.id));
+        let (attacker, defender) = match attack {
+            Some(attack) => attack,
+            None => {
+                game_state.skip_blocked_players(rules);
+                // nobody can attack or place anything, the game is stuck
+                if !game_state.current_player_can_move(rules)
+                    && game_state.turn_phase == TurnPhase::Attack
+                {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        let regions = &game_state.board.regions;
+        let attacker_roll = roll_dice(
+            rules.attacker_roll_size(regions[attacker].num_dice),
+            &mut rng,
+        );
+        let defender_roll = roll_dice(
+            rules.defender_roll_size(regions[defender].num_dice),
+            &mut rng,
+        );
+
+        let log_entry = GameLogEntry {
+            turn_counter: game_state.turn_counter,
+            turn_of_player: game_state.turn_of_player,
+            region_1: attacker,
+            region_2: defender,
+            region_1_dice_result: attacker_roll.clone(),
+            region_2_dice_result: defender_roll.clone(),
+            ..Default::default()
+        };
+        game_state.log_attack(log_entry);
+        game_state.resolve_battle(
+            attacker,
+            defender,
+            &attacker_roll,
+            &defender_roll,
+            rules,
+            &mut rng,
+        );
+        game_state.finish_turn(rules);
+    }
+
+    GameSummary {
+        world_seed: config.world_seed,
+        env_seed: config.env_seed,
+        winner: game_state.winner(),
+        first_player,
+        turns: game_state.turn_counter,
+        battles: game_state.game_log.len(),
+    }
+}
+
+/// Plays every game of `configs` across all cores, keeping the order of `configs`
+pub fn simulate_batch(configs: &[SimulationConfig]) -> Vec<GameSummary> {
+    configs.par_iter().map(simulate).collect()
+}
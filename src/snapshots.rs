@@ -0,0 +1,155 @@
+//! Snapshots of the last turns of the current game.
+//!
+//! [`record_snapshots`] copies the [`GameState`] at the start of every turn into a
+//! [`SnapshotRing`] holding the last few of them, so tools looking back at earlier turns, like
+//! undoing a move in a casual game or comparing states with [`GameState::diff`] to find where two
+//! clients diverged, don't have to replay the game log from the start.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::events::EventTurnStart;
+use crate::game::GameState;
+
+/// Number of turn snapshots kept by default
+pub const SNAPSHOT_CAPACITY: usize = 32;
+
+/// The game state at the start of each of the last turns, oldest first. Once full, every new
+/// snapshot pushes out the oldest one.
+#[derive(Resource)]
+pub struct SnapshotRing {
+    capacity: usize,
+    snapshots: VecDeque<GameState>,
+}
+
+impl Default for SnapshotRing {
+    fn default() -> Self {
+        SnapshotRing::new(SNAPSHOT_CAPACITY)
+    }
+}
+
+// stepping back is only used by debugging tools for now
+#[allow(dead_code)]
+impl SnapshotRing {
+    pub fn new(capacity: usize) -> Self {
+        SnapshotRing {
+            capacity: capacity.max(1),
+            snapshots: VecDeque::with_capacity(capacity.max(1)),
+        }
+    }
+
+    /// Forgets the current game, for a fresh one
+    pub fn reset(&mut self) {
+        self.snapshots.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Keeps a copy of `game_state`, replacing the snapshot of the same turn if there is one
+    pub fn push(&mut self, game_state: &GameState) {
+        if self.latest().map(|latest| latest.turn_counter) == Some(game_state.turn_counter) {
+            self.snapshots.pop_back();
+        }
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(game_state.clone());
+    }
+
+    /// Snapshot of the turn being played
+    pub fn latest(&self) -> Option<&GameState> {
+        self.snapshots.back()
+    }
+
+    /// Snapshot `steps` turns before the latest one, if it is still kept
+    pub fn back(&self, steps: usize) -> Option<&GameState> {
+        let index = self.snapshots.len().checked_sub(steps + 1)?;
+        self.snapshots.get(index)
+    }
+
+    /// Snapshot of the start of turn `turn_counter`, if it is still kept
+    pub fn at_turn(&self, turn_counter: usize) -> Option<&GameState> {
+        self.snapshots
+            .iter()
+            .find(|snapshot| snapshot.turn_counter == turn_counter)
+    }
+
+    /// Steps `steps` turns back: drops the newer snapshots and returns the state to play on from.
+    /// Nothing is dropped when that turn isn't kept anymore.
+    pub fn rewind(&mut self, steps: usize) -> Option<GameState> {
+        let snapshot = self.back(steps)?.clone();
+        self.snapshots.truncate(self.snapshots.len() - steps);
+        Some(snapshot)
+    }
+}
+
+/// Snapshots the game at the start of every turn, and at the start of every game
+pub(crate) fn record_snapshots(
+    mut event_turn_start_reader: EventReader<EventTurnStart>,
+    mut snapshots: ResMut<SnapshotRing>,
+    game_state: Res<GameState>,
+) {
+    if event_turn_start_reader.iter().count() > 0 || snapshots.is_empty() {
+        snapshots.push(&game_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_state(turn_counter: usize) -> GameState {
+        GameState {
+            turn_counter,
+            number_of_players: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn oldest_snapshots_are_dropped() {
+        let mut snapshots = SnapshotRing::new(3);
+        for turn in 0..5 {
+            snapshots.push(&game_state(turn));
+        }
+
+        assert_eq!(snapshots.len(), 3);
+        assert_eq!(snapshots.latest().unwrap().turn_counter, 4);
+        assert_eq!(snapshots.back(2).unwrap().turn_counter, 2);
+        assert!(snapshots.back(3).is_none());
+        assert!(snapshots.at_turn(1).is_none());
+        assert_eq!(snapshots.at_turn(3).unwrap().turn_counter, 3);
+    }
+
+    #[test]
+    fn a_turn_is_snapshotted_once() {
+        let mut snapshots = SnapshotRing::new(3);
+        snapshots.push(&game_state(0));
+        let mut later = game_state(0);
+        later.turn_of_player = 1;
+        snapshots.push(&later);
+
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots.latest().unwrap().turn_of_player, 1);
+    }
+
+    #[test]
+    fn rewinding_drops_newer_snapshots() {
+        let mut snapshots = SnapshotRing::new(4);
+        for turn in 0..4 {
+            snapshots.push(&game_state(turn));
+        }
+
+        assert!(snapshots.rewind(4).is_none());
+        assert_eq!(snapshots.len(), 4);
+        assert_eq!(snapshots.rewind(2).unwrap().turn_counter, 1);
+        assert_eq!(snapshots.latest().unwrap().turn_counter, 1);
+    }
+}
@@ -0,0 +1,311 @@
+//! Sound effects of the game events, routed through a manifest with a cooldown per event.
+//!
+//! Systems send an [`EventSound`] instead of playing a sample themselves, and [`play_sounds`]
+//! looks the event up in the [`SoundManifest`]: the sample it plays, how loud, and how long the
+//! event stays quiet after playing. A burst of conquests in a fast bot game plays one sample
+//! instead of dozens of overlapping ones.
+//!
+//! The manifest is loaded as an asset from [`SOUND_MANIFEST_PATH`], like the samples, so a mod
+//! can remap every event to its own samples and the browser build reads it too. The manifest
+//! shipped with the game plays until it is loaded, or when it can't be. One event per line:
+//!
+//! ```text
+//! # <event> <sample in the assets, - for none> <cooldown in seconds> [volume]
+//! battle_won sounds/win.wav 0.15
+//! idle_reminder sounds/chime.wav 1.0 0.3
+//! game_over - 0
+//! ```
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use bevy_kira_audio::prelude::*;
+
+/// Path of the manifest in the assets
+pub const SOUND_MANIFEST_PATH: &str = "sounds/sounds.manifest";
+
+/// Manifest shipped with the game, played when the one of the assets can't be read
+const DEFAULT_MANIFEST: &str = include_str!("../assets/sounds/sounds.manifest");
+
+/// Game events with a sound
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SoundEvent {
+    /// The dice of a battle came to rest
+    DiceThrow,
+    /// The attacker conquered the region
+    BattleWon,
+    /// The defender held the region
+    BattleLost,
+    Ping,
+    /// The local player in turn stayed idle for a while
+    IdleReminder,
+    GameOver,
+}
+
+impl SoundEvent {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "dice_throw" => Some(SoundEvent::DiceThrow),
+            "battle_won" => Some(SoundEvent::BattleWon),
+            "battle_lost" => Some(SoundEvent::BattleLost),
+            "ping" => Some(SoundEvent::Ping),
+            "idle_reminder" => Some(SoundEvent::IdleReminder),
+            "game_over" => Some(SoundEvent::GameOver),
+            _ => None,
+        }
+    }
+}
+
+/// Event that is fired for every game event that makes a sound
+pub struct EventSound {
+    pub sound: SoundEvent,
+}
+
+/// How an event sounds
+#[derive(Clone, Debug, PartialEq)]
+pub struct SoundCue {
+    /// Path of the sample in the assets, none for a silent event
+    pub sample: Option<String>,
+    /// Time the event stays quiet after playing
+    pub cooldown: Duration,
+    pub volume: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, TypeUuid)]
+#[uuid = "069d5d2f-a9d6-4b74-a3a1-6c43836aa0f8"]
+pub struct SoundManifest {
+    cues: HashMap<SoundEvent, SoundCue>,
+}
+
+impl SoundManifest {
+    /// Parses a manifest. Blank lines and lines starting with `#` are skipped, events missing
+    /// from the manifest are silent.
+    pub fn parse(source: &str) -> Result<SoundManifest, String> {
+        let mut cues = HashMap::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |what: &str| format!("line {}: {}", index + 1, what);
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (event, sample, cooldown, volume) = match fields[..] {
+                [event, sample, cooldown] => (event, sample, cooldown, "1.0"),
+                [event, sample, cooldown, volume] => (event, sample, cooldown, volume),
+                _ => return Err(invalid("expected <event> <sample> <cooldown> [volume]")),
+            };
+            let event = SoundEvent::parse(event)
+                .ok_or_else(|| invalid(&format!("unknown event {}", event)))?;
+            let cooldown = cooldown
+                .parse::<f64>()
+                .ok()
+                .filter(|cooldown| *cooldown >= 0.0)
+                .ok_or_else(|| invalid(&format!("invalid cooldown {}", cooldown)))?;
+            let volume = volume
+                .parse::<f64>()
+                .ok()
+                .filter(|volume| *volume >= 0.0)
+                .ok_or_else(|| invalid(&format!("invalid volume {}", volume)))?;
+
+            cues.insert(
+                event,
+                SoundCue {
+                    sample: (sample != "-").then(|| sample.to_string()),
+                    cooldown: Duration::from_secs_f64(cooldown),
+                    volume,
+                },
+            );
+        }
+
+        Ok(SoundManifest { cues })
+    }
+
+    /// The manifest shipped with the game
+    pub fn shipped() -> SoundManifest {
+        SoundManifest::parse(DEFAULT_MANIFEST).unwrap_or_default()
+    }
+
+    pub fn cue(&self, sound: SoundEvent) -> Option<&SoundCue> {
+        self.cues.get(&sound)
+    }
+}
+
+/// Loads `.manifest` files of the assets as [`SoundManifest`]s
+#[derive(Default)]
+pub struct SoundManifestLoader;
+
+impl AssetLoader for SoundManifestLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let manifest = SoundManifest::parse(std::str::from_utf8(bytes)?)
+                .map_err(bevy::asset::Error::msg)?;
+            load_context.set_default_asset(LoadedAsset::new(manifest));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["manifest"]
+    }
+}
+
+/// The manifest and when each event last played
+#[derive(Resource)]
+pub struct SoundRouter {
+    manifest: SoundManifest,
+    /// Manifest of the assets, routed once loaded
+    handle: Option<Handle<SoundManifest>>,
+    last_played: HashMap<SoundEvent, Duration>,
+}
+
+impl Default for SoundRouter {
+    fn default() -> Self {
+        SoundRouter::new(SoundManifest::shipped())
+    }
+}
+
+impl SoundRouter {
+    pub fn new(manifest: SoundManifest) -> Self {
+        SoundRouter {
+            manifest,
+            handle: None,
+            last_played: HashMap::new(),
+        }
+    }
+
+    /// Sample and volume `sound` plays at the time `now`, none while the event cools down or
+    /// when it is silent
+    pub fn route(&mut self, sound: SoundEvent, now: Duration) -> Option<(&str, f64)> {
+        let cue = self.manifest.cue(sound)?;
+        let sample = cue.sample.as_ref()?;
+        if let Some(last_played) = self.last_played.get(&sound) {
+            if now < *last_played + cue.cooldown {
+                return None;
+            }
+        }
+
+        self.last_played.insert(sound, now);
+        Some((sample.as_str(), cue.volume))
+    }
+}
+
+/// Starts loading the manifest of the assets
+pub(crate) fn load_sound_manifest(asset_server: Res<AssetServer>, mut router: ResMut<SoundRouter>) {
+    router.handle = Some(asset_server.load(SOUND_MANIFEST_PATH));
+}
+
+/// Routes the manifest of the assets once it is loaded, and again whenever it changes
+pub(crate) fn update_sound_manifest(
+    mut asset_events: EventReader<AssetEvent<SoundManifest>>,
+    manifests: Res<Assets<SoundManifest>>,
+    mut router: ResMut<SoundRouter>,
+) {
+    for event in asset_events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+        if router.handle.as_ref() != Some(handle) {
+            continue;
+        }
+        if let Some(manifest) = manifests.get(handle) {
+            router.manifest = manifest.clone();
+        }
+    }
+}
+
+pub(crate) fn play_sounds(
+    mut event_sound_reader: EventReader<EventSound>,
+    time: Res<Time>,
+    mut router: ResMut<SoundRouter>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+) {
+    for event in event_sound_reader.iter() {
+        if let Some((sample, volume)) = router.route(event.sound, time.elapsed()) {
+            audio.play(asset_server.load(sample)).with_volume(volume);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_cool_down_between_samples() {
+        let manifest = SoundManifest::parse(DEFAULT_MANIFEST).unwrap();
+        assert_eq!(manifest.cue(SoundEvent::IdleReminder).unwrap().volume, 0.3);
+        let mut router = SoundRouter::new(manifest);
+
+        let second = Duration::from_secs(1);
+        assert_eq!(
+            router.route(SoundEvent::BattleWon, second),
+            Some(("sounds/win.wav", 1.0))
+        );
+        assert_eq!(router.route(SoundEvent::BattleWon, second), None);
+        assert!(router.route(SoundEvent::BattleLost, second).is_some());
+        assert!(router
+            .route(SoundEvent::BattleWon, second + Duration::from_millis(150))
+            .is_some());
+        assert_eq!(router.route(SoundEvent::GameOver, second), None);
+    }
+
+    #[test]
+    fn manifests_load_as_assets() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<SoundManifest>()
+            .init_asset_loader::<SoundManifestLoader>()
+            .init_resource::<SoundRouter>()
+            .add_startup_system(load_sound_manifest)
+            .add_system(update_sound_manifest);
+
+        // the manifest of the assets is the one shipped with the game
+        let mut loaded = false;
+        for _ in 0..1000 {
+            app.update();
+            let router = app.world.resource::<SoundRouter>();
+            let manifests = app.world.resource::<Assets<SoundManifest>>();
+            if let Some(manifest) = router
+                .handle
+                .as_ref()
+                .and_then(|handle| manifests.get(handle))
+            {
+                assert_eq!(*manifest, SoundManifest::shipped());
+                loaded = true;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(loaded);
+    }
+
+    #[test]
+    fn manifests_remap_events() {
+        let manifest = SoundManifest::parse("# mod\nping sounds/bell.ogg 2 0.5\n").unwrap();
+        let mut router = SoundRouter::new(manifest);
+
+        assert_eq!(
+            router.route(SoundEvent::Ping, Duration::ZERO),
+            Some(("sounds/bell.ogg", 0.5))
+        );
+        assert_eq!(router.route(SoundEvent::DiceThrow, Duration::ZERO), None);
+        assert_eq!(
+            SoundManifest::parse("ping sounds/bell.ogg\n"),
+            Err("line 1: expected <event> <sample> <cooldown> [volume]".to_string())
+        );
+        assert!(SoundManifest::parse("boom sounds/bell.ogg 1\n").is_err());
+    }
+}
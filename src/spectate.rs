@@ -0,0 +1,713 @@
+//! Spectating a network game in progress from another machine.
+//!
+//! `--spectate` reaches a hosted game like `--join` does, by join code, address or room code on a
+//! relay, and asks for the board stream of the API (see [`crate::api`]): a snapshot of the whole
+//! board, then a delta whenever the game changes. The board is rebuilt from the snapshot, kept up
+//! to date from the deltas and drawn like a local game in which no seat takes input. A delta out
+//! of sequence means a message was lost, and a board that doesn't match the hash of the delta
+//! diverged from the host: the spectator asks for a new snapshot and skips the stream until it
+//! arrives. The board is then resynced through the delta to the snapshot, which logs where it
+//! diverged.
+//!
+//! ```text
+//! cargo run --features api -- --spectate 5R2C8-0K7ZH
+//! cargo run --features api -- --spectate Q4MZ7-1XK2D --relay relay.example.org:7800
+//! ```
+//!
+//! A player joining a hosted game, with `--join` or from the join screen of the main menu, follows
+//! the board the same way. The seat the host handed over takes the clicks on the board, and its
+//! moves are sent to the host, which plays the battles of every seat.
+//!
+//! The host draws the dice of every battle from a seed it committed to, see
+//! [`crate::fair_dice`]. Every roll is checked against the commitment before it is shown, and the
+//! revealed seed once the game is over: a roll that doesn't check out is reported in a toast.
+
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Mutex;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::api::{ApiAction, DeltaView, StateView};
+use crate::board::BoardDrawer;
+use crate::delta::StateDelta;
+use crate::events::{EventPlayerMoveStart, EventReinforcementPlaced, EventTurnPassed};
+use crate::fair_dice::{parse_key, FairRoll, RollMismatch, RollVerifier};
+use crate::game::{Board, GameState, Region, TurnPhase};
+use crate::lobby::{JoinError, JoinTarget};
+use crate::profiles::PlayerProfiles;
+use crate::seats::{RemoteConnection, SeatController, Seats};
+use crate::ui::spawn_toast;
+
+/// Message of the board stream
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamMessage {
+    Snapshot { seq: u64, state: StateView },
+    Delta(DeltaView),
+    DiceChain { head: String, rolled: u64 },
+    Roll(FairRoll),
+    Reveal { seed: String },
+}
+
+impl StreamMessage {
+    /// Whether the message is about the dice of the host rather than the board
+    pub fn is_dice(&self) -> bool {
+        matches!(
+            self,
+            StreamMessage::DiceChain { .. } | StreamMessage::Roll(_) | StreamMessage::Reveal { .. }
+        )
+    }
+}
+
+/// The stream lost a message or the board diverged from the host, the board needs a new snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfSync;
+
+/// Board of the spectated game, rebuilt from the board stream
+#[derive(Clone, Default)]
+pub struct SpectatedBoard {
+    /// Number of the last message applied
+    pub seq: u64,
+    pub game_state: GameState,
+}
+
+fn parse_phase(phase: &str) -> Option<TurnPhase> {
+    match phase {
+        "attack" => Some(TurnPhase::Attack),
+        "reinforcement" => Some(TurnPhase::Reinforcement),
+        _ => None,
+    }
+}
+
+impl SpectatedBoard {
+    /// The board of a snapshot. Objectives, pacts and the game log aren't streamed.
+    pub fn from_snapshot(seq: u64, state: &StateView) -> Result<Self, OutOfSync> {
+        let regions: Vec<Region> = state
+            .regions
+            .iter()
+            .map(|region| Region {
+                hexes: region.hexes.clone(),
+                owner: region.owner,
+                num_dice: region.dice,
+                id: region.id,
+                has_moved: region.has_moved,
+                // unnamed regions are streamed with their label
+                name: match region.name == format!("Region {}", region.id) {
+                    true => String::new(),
+                    false => region.name.clone(),
+                },
+            })
+            .collect();
+        if regions
+            .iter()
+            .enumerate()
+            .any(|(id, region)| region.id != id)
+        {
+            return Err(OutOfSync);
+        }
+
+        let mut board = Board {
+            regions,
+            ..default()
+        };
+        for region in board.regions.iter() {
+            for hex in region.hexes.iter() {
+                board.hexes.insert(*hex, region.owner);
+            }
+        }
+
+        Ok(SpectatedBoard {
+            seq,
+            game_state: GameState {
+                board,
+                turn_of_player: state.player,
+                turn_counter: state.turn,
+                number_of_players: state.reserves.len(),
+                reserves: state.reserves.clone(),
+                turn_phase: parse_phase(&state.phase).ok_or(OutOfSync)?,
+                ..default()
+            },
+        })
+    }
+
+    /// Brings the board to the state of `message`. Deltas older than the board are skipped, and
+    /// the dice don't change it. A board that doesn't hash like the host once changed is out of
+    /// sync.
+    pub fn apply(&mut self, message: &StreamMessage) -> Result<(), OutOfSync> {
+        let delta = match message {
+            StreamMessage::DiceChain { .. }
+            | StreamMessage::Roll(_)
+            | StreamMessage::Reveal { .. } => return Ok(()),
+            StreamMessage::Snapshot { seq, state } => {
+                *self = SpectatedBoard::from_snapshot(*seq, state)?;
+                return Ok(());
+            }
+            StreamMessage::Delta(delta) if delta.seq <= self.seq => return Ok(()),
+            StreamMessage::Delta(delta) if delta.seq > self.seq + 1 => return Err(OutOfSync),
+            StreamMessage::Delta(delta) => delta,
+        };
+
+        let game_state = &mut self.game_state;
+        for change in delta.regions.iter() {
+            let region = game_state
+                .board
+                .regions
+                .get_mut(change.id)
+                .ok_or(OutOfSync)?;
+            region.owner = change.owner;
+            region.num_dice = change.dice;
+            region.has_moved = change.has_moved;
+
+            for hex in region.hexes.iter() {
+                game_state.board.hexes.insert(*hex, region.owner);
+            }
+        }
+
+        if let Some(turn) = delta.turn {
+            game_state.turn_counter = turn;
+        }
+        if let Some(player) = delta.player {
+            game_state.turn_of_player = player;
+        }
+        if let Some(phase) = delta.phase.as_ref() {
+            game_state.turn_phase = parse_phase(phase).ok_or(OutOfSync)?;
+        }
+        if let Some(reserves) = delta.reserves.as_ref() {
+            game_state.reserves = reserves.clone();
+        }
+
+        if let Some(hash) = delta.hash.as_ref() {
+            let hash = u64::from_str_radix(hash, 16).map_err(|_| OutOfSync)?;
+            if game_state.zobrist_hash() != hash {
+                return Err(OutOfSync);
+            }
+        }
+
+        self.seq = delta.seq;
+        Ok(())
+    }
+
+    /// Brings a board out of sync to the snapshot numbered `seq`, through the delta between them
+    /// which lists where the board diverged. The snapshot of another board replaces it.
+    pub fn resync(&mut self, seq: u64, state: &StateView) -> Result<StateDelta, OutOfSync> {
+        let snapshot = SpectatedBoard::from_snapshot(seq, state)?;
+        if snapshot.game_state.board.region_keys() != self.game_state.board.region_keys() {
+            *self = snapshot;
+            return Ok(StateDelta::default());
+        }
+
+        let delta = self.game_state.diff(&snapshot.game_state);
+        self.game_state.apply_delta(&delta);
+        self.seq = seq;
+        Ok(delta)
+    }
+}
+
+/// Board stream of the spectated game, read on a background thread
+#[derive(Resource)]
+pub struct SpectatorStream {
+    board: SpectatedBoard,
+    messages: Mutex<Receiver<StreamMessage>>,
+    /// Asks the background thread for a new snapshot
+    resync: Mutex<Sender<()>>,
+    /// Waiting for the snapshot asked for after losing a message
+    resyncing: bool,
+    /// Seat the host handed over to this player, none for a spectator
+    seat: Option<usize>,
+    /// Moves of the seat, sent to the host by the background thread
+    moves: Mutex<Sender<String>>,
+    /// Checks the dice of the host, once it told where their chain is
+    dice: Option<RollVerifier>,
+}
+
+impl SpectatorStream {
+    // only connected with the `api` feature
+    #[allow(dead_code)]
+    fn new(
+        board: SpectatedBoard,
+        messages: Receiver<StreamMessage>,
+        resync: Sender<()>,
+        seat: Option<usize>,
+        moves: Sender<String>,
+    ) -> Self {
+        SpectatorStream {
+            board,
+            messages: Mutex::new(messages),
+            resync: Mutex::new(resync),
+            resyncing: false,
+            seat,
+            moves: Mutex::new(moves),
+            dice: None,
+        }
+    }
+
+    pub fn board(&self) -> &SpectatedBoard {
+        &self.board
+    }
+
+    /// Seat the host handed over to this player, none for a spectator
+    pub fn seat(&self) -> Option<usize> {
+        self.seat
+    }
+
+    /// Seats of the followed game: the clicks on the board play the seat handed over, if any,
+    /// every other seat is played elsewhere
+    pub fn seats(&self) -> Seats {
+        let controllers = (0..self.board.game_state.number_of_players)
+            .map(|player| match Some(player) == self.seat {
+                true => SeatController::LocalHuman,
+                false => SeatController::Remote(RemoteConnection::default()),
+            })
+            .collect();
+
+        Seats { controllers }
+    }
+
+    /// Sends `action` of the seat handed over to the host, which answers with the new board
+    fn send_move(&self, action: ApiAction) {
+        let player = match self.seat {
+            Some(player) => player,
+            None => return,
+        };
+
+        let mut request = serde_json::to_value(action).unwrap_or_default();
+        request["type"] = "move".into();
+        request["player"] = player.into();
+        // a closed channel means the host closed the game, which the stream shows
+        let _ = self.moves.lock().unwrap().send(request.to_string());
+    }
+
+    /// Checks a message about the dice of the host. Gives the roll to show once it checked out.
+    pub fn check_dice(
+        &mut self,
+        message: &StreamMessage,
+    ) -> Result<Option<FairRoll>, RollMismatch> {
+        match message {
+            StreamMessage::DiceChain { head, rolled } => {
+                // a chain the host can't even write down has no roll to check
+                self.dice = parse_key(head).map(|head| RollVerifier::new(head, *rolled));
+                Ok(None)
+            }
+            StreamMessage::Roll(roll) => match self.dice.as_mut() {
+                Some(verifier) => verifier.check(roll).map(|()| Some(roll.clone())),
+                None => Err(RollMismatch::Key),
+            },
+            StreamMessage::Reveal { seed } => match (self.dice.as_ref(), parse_key(seed)) {
+                (Some(verifier), Some(seed)) => verifier.check_reveal(&seed).map(|()| None),
+                _ => Err(RollMismatch::Seed),
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Starts spectating the game of `target`, once its first snapshot arrived
+pub fn spectate(target: &JoinTarget) -> Result<SpectatorStream, JoinError> {
+    #[cfg(feature = "api")]
+    {
+        client::follow(target, false)
+    }
+
+    #[cfg(not(feature = "api"))]
+    {
+        let _ = target;
+        Err(JoinError::Unsupported)
+    }
+}
+
+/// Joins the game of `target` and follows its board, once the host handed over a seat and the
+/// first snapshot arrived
+pub fn join(target: &JoinTarget) -> Result<SpectatorStream, JoinError> {
+    #[cfg(feature = "api")]
+    {
+        client::follow(target, true)
+    }
+
+    #[cfg(not(feature = "api"))]
+    {
+        let _ = target;
+        Err(JoinError::Unsupported)
+    }
+}
+
+#[cfg(feature = "api")]
+mod client {
+    use std::io::ErrorKind;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use tungstenite::{Error, Message};
+
+    use super::{SpectatedBoard, SpectatorStream, StreamMessage};
+    use crate::handshake::PROTOCOL_VERSION;
+    use crate::lobby::client::connect;
+    use crate::lobby::{join_reply, JoinError, JoinTarget};
+    use crate::stamp::APP_VERSION;
+
+    /// How long to wait for a message of the host before checking for resync requests and moves
+    const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+    /// Follows the board of the game of `target`, joining it first to play a seat if `join`
+    pub(super) fn follow(target: &JoinTarget, join: bool) -> Result<SpectatorStream, JoinError> {
+        let (_, mut socket) = connect(target)?;
+        let unreachable = |err: Error| JoinError::Unreachable(err.to_string());
+
+        let seat = match join {
+            true => {
+                let request = serde_json::json!({
+                    "type": "join",
+                    "version": APP_VERSION,
+                    "protocol": PROTOCOL_VERSION,
+                });
+                socket
+                    .send(Message::Text(request.to_string()))
+                    .map_err(unreachable)?;
+                loop {
+                    match socket.read().map_err(unreachable)? {
+                        Message::Text(text) => match join_reply(&text) {
+                            Some(reply) => break Some(reply?),
+                            None => continue,
+                        },
+                        Message::Close(_) => return Err(JoinError::Disconnected),
+                        _ => {}
+                    }
+                }
+            }
+            false => None,
+        };
+
+        let request = serde_json::json!({
+            "type": "spectate",
+            "version": APP_VERSION,
+            "protocol": PROTOCOL_VERSION,
+        })
+        .to_string();
+        socket
+            .send(Message::Text(request.clone()))
+            .map_err(unreachable)?;
+        let board = loop {
+            let text = match socket.read().map_err(unreachable)? {
+                Message::Text(text) => text,
+                Message::Close(_) => return Err(JoinError::Disconnected),
+                _ => continue,
+            };
+            if let Ok(StreamMessage::Snapshot { seq, state }) = serde_json::from_str(&text) {
+                break SpectatedBoard::from_snapshot(seq, &state)
+                    .map_err(|_| JoinError::Disconnected)?;
+            }
+            if let Some(Err(err)) = join_reply(&text) {
+                return Err(err);
+            }
+            // hosts from before spectating don't know the request
+            let reply: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+            if reply["type"] == "error" {
+                let message = reply["message"].as_str().unwrap_or("?");
+                return Err(JoinError::Refused(message.to_string()));
+            }
+        };
+        socket
+            .get_mut()
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .map_err(|err| JoinError::Unreachable(err.to_string()))?;
+
+        let (messages, received) = mpsc::channel();
+        let (resync, resyncs) = mpsc::channel::<()>();
+        let (moves, moved) = mpsc::channel::<String>();
+        std::thread::spawn(move || loop {
+            if resyncs.try_iter().count() > 0
+                && socket.send(Message::Text(request.clone())).is_err()
+            {
+                return;
+            }
+            for text in moved.try_iter() {
+                if socket.send(Message::Text(text)).is_err() {
+                    return;
+                }
+            }
+
+            match socket.read() {
+                Ok(Message::Text(text)) => {
+                    if let Ok(message) = serde_json::from_str::<StreamMessage>(&text) {
+                        if messages.send(message).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Ok(Message::Close(_)) => return,
+                Ok(_) => {}
+                Err(Error::Io(err))
+                    if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(_) => return,
+            }
+        });
+
+        Ok(SpectatorStream::new(board, received, resync, seat, moves))
+    }
+}
+
+/// Sends the moves of the seat handed over by the host
+pub(crate) fn send_joined_moves(
+    stream: Option<Res<SpectatorStream>>,
+    mut event_player_move_start_reader: EventReader<EventPlayerMoveStart>,
+    mut event_reinforcement_placed_reader: EventReader<EventReinforcementPlaced>,
+    mut event_turn_passed_reader: EventReader<EventTurnPassed>,
+) {
+    let stream = match stream {
+        Some(stream) => stream,
+        None => return,
+    };
+
+    for event in event_player_move_start_reader.iter() {
+        stream.send_move(ApiAction::Attack {
+            from: event.region_1,
+            to: event.region_2,
+        });
+    }
+    for event in event_reinforcement_placed_reader.iter() {
+        stream.send_move(ApiAction::Reinforce {
+            region: event.region,
+        });
+    }
+    for event in event_turn_passed_reader.iter() {
+        if Some(event.player) == stream.seat {
+            stream.send_move(ApiAction::EndTurn);
+        }
+    }
+}
+
+/// Checks a message about the dice of the host, and shows the roll it brings
+fn show_dice(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    profiles: &PlayerProfiles,
+    stream: &mut SpectatorStream,
+    message: &StreamMessage,
+) {
+    let roll = match stream.check_dice(message) {
+        Ok(Some(roll)) => roll,
+        Ok(None) => return,
+        Err(err) => {
+            warn!("Rejected the dice of the host: {}", err);
+            let message = format!("The host sent forged dice: {}", err);
+            spawn_toast(commands, asset_server, message);
+            return;
+        }
+    };
+
+    let attacker = stream.board.game_state.board.regions.get(roll.region_1);
+    let totals: Vec<usize> = roll.results.iter().map(|side| side.iter().sum()).collect();
+    if let (Some(attacker), [attack, defense]) = (attacker, totals.as_slice()) {
+        let message = format!(
+            "{} rolls {} against {}",
+            profiles.name(attacker.owner),
+            attack,
+            defense
+        );
+        spawn_toast(commands, asset_server, message);
+    }
+}
+
+/// Applies the board stream to the game state and draws the board again when it changed. Shows
+/// the rolls of the host once they checked out.
+pub(crate) fn follow_spectated_game(
+    mut commands: Commands,
+    stream: Option<ResMut<SpectatorStream>>,
+    mut board_drawer: BoardDrawer,
+    mut game_state: ResMut<GameState>,
+    profiles: Res<PlayerProfiles>,
+    asset_server: Res<AssetServer>,
+) {
+    let mut stream = match stream {
+        Some(stream) => stream,
+        None => return,
+    };
+
+    let messages: Vec<StreamMessage> = stream.messages.lock().unwrap().try_iter().collect();
+    let mut changed = false;
+    for message in messages.iter() {
+        if message.is_dice() {
+            show_dice(
+                &mut commands,
+                &asset_server,
+                &profiles,
+                &mut stream,
+                message,
+            );
+            continue;
+        }
+
+        let snapshot = matches!(message, StreamMessage::Snapshot { .. });
+        if stream.resyncing && !snapshot {
+            continue;
+        }
+
+        let applied = match message {
+            StreamMessage::Snapshot { seq, state } if stream.resyncing => {
+                stream.board.resync(*seq, state).map(|delta| {
+                    if !delta.is_empty() {
+                        warn!("Resynced the board stream, the board diverged:\n{}", delta);
+                    }
+                })
+            }
+            message => stream.board.apply(message),
+        };
+        match applied {
+            Ok(()) => {
+                stream.resyncing = false;
+                changed = true;
+            }
+            Err(OutOfSync) => {
+                warn!("The board stream lost a message or diverged, asking for a new snapshot");
+                stream.resyncing = true;
+                let _ = stream.resync.lock().unwrap().send(());
+            }
+        }
+    }
+    if !changed {
+        return;
+    }
+
+    *game_state = stream.board.game_state.clone();
+    board_drawer.redraw(&game_state, &profiles);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{dice_chain, ApiMessage};
+    use crate::fair_dice::{key_hex, FairDice};
+    use crate::test_support;
+
+    fn game_state() -> GameState {
+        GameState {
+            reserves: vec![0, 0],
+            ..test_support::game_state(
+                "players 2\nregion 0 3 0,0\nregion 1 2 1,0\nregion 1 2 5,5\nname 2 Upper Karthac\n",
+            )
+        }
+    }
+
+    /// The message as a spectator receives it
+    fn received(message: &ApiMessage) -> StreamMessage {
+        serde_json::from_str(&serde_json::to_string(message).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn boards_are_rebuilt_from_the_stream() {
+        let before = game_state();
+        let snapshot = received(&ApiMessage::Snapshot {
+            seq: 4,
+            state: StateView::new(&before),
+        });
+        let mut board = SpectatedBoard::default();
+        board.apply(&snapshot).unwrap();
+        assert_eq!(board.game_state.board.regions[2].name, "Upper Karthac");
+        assert!(board.game_state.board.regions[1].name.is_empty());
+
+        let mut after = before.clone();
+        after.board.regions[1].owner = 0;
+        after.board.hexes.insert((1, 0), 0);
+        after.board.regions[1].num_dice = 1;
+        after.advance_turn();
+        let delta = received(&ApiMessage::Delta(DeltaView::new(
+            5,
+            &before.diff(&after),
+            after.zobrist_hash(),
+        )));
+
+        board.apply(&delta).unwrap();
+        assert_eq!(board.seq, 5);
+        assert_eq!(StateView::new(&board.game_state), StateView::new(&after));
+        assert_eq!(board.game_state.zobrist_hash(), after.zobrist_hash());
+        // a delta seen twice changes nothing
+        board.apply(&delta).unwrap();
+        assert_eq!(board.seq, 5);
+    }
+
+    #[test]
+    fn dice_of_the_host_are_checked() {
+        let (_, received_messages) = std::sync::mpsc::channel();
+        let (resync, _) = std::sync::mpsc::channel();
+        let (moves, _) = std::sync::mpsc::channel();
+        let mut stream = SpectatorStream::new(
+            SpectatedBoard::default(),
+            received_messages,
+            resync,
+            None,
+            moves,
+        );
+        let mut fair_dice = FairDice::new([9; 32]);
+        fair_dice.roll(0, 1, &[3, 2]).unwrap();
+
+        let chain = received(&dice_chain(&fair_dice));
+        assert!(chain.is_dice());
+        assert_eq!(stream.check_dice(&chain), Ok(None));
+        let roll = fair_dice.take_rolls().remove(0);
+        let message = received(&ApiMessage::Roll(roll.clone()));
+        assert_eq!(stream.check_dice(&message), Ok(Some(roll.clone())));
+
+        let mut forged = roll;
+        forged.results[1][0] = forged.results[1][0] % 6 + 1;
+        assert!(stream
+            .check_dice(&received(&ApiMessage::Roll(forged)))
+            .is_err());
+        let seed = fair_dice.take_reveal(true).unwrap();
+        let reveal = received(&ApiMessage::Reveal {
+            seed: key_hex(&seed),
+        });
+        assert_eq!(stream.check_dice(&reveal), Ok(None));
+    }
+
+    #[test]
+    fn lost_messages_need_a_new_snapshot() {
+        let state = game_state();
+        let mut board = SpectatedBoard::from_snapshot(1, &StateView::new(&state)).unwrap();
+        let delta = |seq| {
+            StreamMessage::Delta(DeltaView {
+                seq,
+                regions: Vec::new(),
+                turn: Some(7),
+                player: None,
+                phase: None,
+                reserves: None,
+                hash: None,
+            })
+        };
+
+        assert_eq!(board.apply(&delta(3)), Err(OutOfSync));
+        assert_eq!(board.game_state.turn_counter, 0);
+        board
+            .apply(&StreamMessage::Snapshot {
+                seq: 3,
+                state: StateView::new(&state),
+            })
+            .unwrap();
+        board.apply(&delta(4)).unwrap();
+        assert_eq!(board.game_state.turn_counter, 7);
+    }
+
+    #[test]
+    fn diverged_boards_resync_through_a_delta() {
+        let state = game_state();
+        let mut board = SpectatedBoard::from_snapshot(1, &StateView::new(&state)).unwrap();
+        // the spectator missed a change the host made without streaming it
+        let mut host = state.clone();
+        host.board.regions[2].num_dice = 4;
+
+        let mut after = host.clone();
+        after.board.regions[0].num_dice = 1;
+        let delta = received(&ApiMessage::Delta(DeltaView::new(
+            2,
+            &host.diff(&after),
+            after.zobrist_hash(),
+        )));
+        assert_eq!(board.apply(&delta), Err(OutOfSync));
+        assert_eq!(board.seq, 1);
+
+        let delta = board.resync(2, &StateView::new(&after)).unwrap();
+        assert_eq!(delta.to_string(), "region 2: dice 2 -> 4\n");
+        assert_eq!(board.seq, 2);
+        assert_eq!(board.game_state.zobrist_hash(), after.zobrist_hash());
+    }
+}
@@ -0,0 +1,260 @@
+//! Time attack: the first player conquers the board against bots as fast as it can.
+//!
+//! Boards are generated from the world seed, so the seed names the map. The best time of every
+//! seed is kept in [`BEST_TIMES_PATH`] of the data directory, one `<world seed> <milliseconds>` line per seed.
+//! The clock stops when the game is over, or as soon as the player is eliminated while the bots
+//! play on.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::autosave::ResumePrompt;
+use crate::events::EventGameOver;
+use crate::logic_step::LOGIC_STEP;
+use crate::storage::{storage, Location};
+use crate::timeline::EventPlayerEliminated;
+use crate::ui::{spawn_toast, StackRankDiceUI};
+
+pub const BEST_TIMES_PATH: &str = "best_times.txt";
+
+/// The player racing the clock, the other seats are bots
+pub const SPEEDRUN_PLAYER: usize = 0;
+
+/// Best time of every board seed played in time attack
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BestTimes {
+    times: BTreeMap<u64, Duration>,
+}
+
+impl BestTimes {
+    /// Reads a best times file. Unreadable lines are skipped, losing a record beats refusing to
+    /// start the game.
+    pub fn parse(source: &str) -> BestTimes {
+        let times = source
+            .lines()
+            .filter_map(|line| {
+                let (seed, millis) = line.trim().split_once(' ')?;
+                Some((
+                    seed.parse().ok()?,
+                    Duration::from_millis(millis.trim().parse().ok()?),
+                ))
+            })
+            .collect();
+
+        BestTimes { times }
+    }
+
+    pub fn best(&self, world_seed: u64) -> Option<Duration> {
+        self.times.get(&world_seed).copied()
+    }
+
+    /// Records a finished run. Returns whether it beat the best time of the seed.
+    pub fn record(&mut self, world_seed: u64, time: Duration) -> bool {
+        match self.best(world_seed) {
+            Some(best) if best <= time => false,
+            _ => {
+                self.times.insert(world_seed, time);
+                true
+            }
+        }
+    }
+}
+
+impl fmt::Display for BestTimes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (seed, time) in self.times.iter() {
+            writeln!(f, "{} {}", seed, time.as_millis())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Run in progress
+#[derive(Resource)]
+pub struct Speedrun {
+    pub world_seed: u64,
+    pub elapsed: Duration,
+    pub finished: bool,
+    pub best_times: BestTimes,
+}
+
+impl Speedrun {
+    /// Starts a run on the board of `world_seed`, with the best times found in the data directory
+    pub fn new(world_seed: u64) -> Self {
+        let best_times = storage(Location::Data)
+            .read(BEST_TIMES_PATH)
+            .map(|source| BestTimes::parse(&source))
+            .unwrap_or_default();
+
+        Speedrun {
+            world_seed,
+            elapsed: Duration::ZERO,
+            finished: false,
+            best_times,
+        }
+    }
+}
+
+fn format_run_time(time: Duration) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{}:{:02}.{}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000 / 100
+    )
+}
+
+/// Whether the run is over, and won: it's lost once the player is eliminated, and decided by the
+/// `winner` of the game otherwise
+fn run_result(eliminated: bool, winner: Option<usize>) -> Option<bool> {
+    match eliminated {
+        true => Some(false),
+        false => winner.map(|winner| winner == SPEEDRUN_PLAYER),
+    }
+}
+
+pub(crate) fn speedrun_timer(
+    speedrun: Option<ResMut<Speedrun>>,
+    resume_prompt: Option<Res<ResumePrompt>>,
+) {
+    if let Some(mut speedrun) = speedrun {
+        if !speedrun.finished && resume_prompt.is_none() {
+            speedrun.elapsed += LOGIC_STEP;
+        }
+    }
+}
+
+pub(crate) fn speedrun_game_over(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut event_game_over_reader: EventReader<EventGameOver>,
+    mut event_player_eliminated_reader: EventReader<EventPlayerEliminated>,
+    speedrun: Option<ResMut<Speedrun>>,
+) {
+    let eliminated = event_player_eliminated_reader
+        .iter()
+        .any(|e| e.player == SPEEDRUN_PLAYER);
+    let winner = event_game_over_reader.iter().last().map(|e| e.winner);
+    let mut speedrun = match speedrun {
+        Some(speedrun) if !speedrun.finished => speedrun,
+        _ => return,
+    };
+    let won = match run_result(eliminated, winner) {
+        Some(won) => won,
+        None => return,
+    };
+    speedrun.finished = true;
+
+    let elapsed = speedrun.elapsed;
+    let message = match won {
+        true => {
+            let world_seed = speedrun.world_seed;
+            match speedrun.best_times.record(world_seed, elapsed) {
+                true => {
+                    if let Err(err) = storage(Location::Data)
+                        .write(BEST_TIMES_PATH, &speedrun.best_times.to_string())
+                    {
+                        warn!("Failed to save {}: {}", BEST_TIMES_PATH, err);
+                    }
+                    format!("New best time: {}!", format_run_time(elapsed))
+                }
+                false => format!("Conquered in {}", format_run_time(elapsed)),
+            }
+        }
+        false => format!("Defeated after {}", format_run_time(elapsed)),
+    };
+    spawn_toast(&mut commands, &asset_server, message);
+}
+
+/// Text area with the time of the run and the best time of the board
+#[derive(Component)]
+pub(crate) struct SpeedrunText;
+
+pub(crate) fn setup_speedrun_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    speedrun: Option<Res<Speedrun>>,
+) {
+    if speedrun.is_none() {
+        return;
+    }
+
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(40.0),
+                    left: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Name::new("Speedrun Text"))
+        .insert(SpeedrunText)
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn speedrun_text_update(
+    speedrun: Option<Res<Speedrun>>,
+    mut query: Query<&mut Text, With<SpeedrunText>>,
+) {
+    let speedrun = match speedrun {
+        Some(speedrun) => speedrun,
+        None => return,
+    };
+
+    let best = speedrun
+        .best_times
+        .best(speedrun.world_seed)
+        .map_or("--".to_string(), format_run_time);
+    for mut text in &mut query {
+        text.sections[0].value =
+            format!("TIME {}   BEST {}", format_run_time(speedrun.elapsed), best);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_best_time_of_each_seed() {
+        let mut best_times = BestTimes::parse("42 90000\nnot a record\n7 1500\n");
+        assert_eq!(best_times.best(42), Some(Duration::from_secs(90)));
+
+        assert!(!best_times.record(42, Duration::from_secs(95)));
+        assert!(best_times.record(42, Duration::from_secs(80)));
+        assert!(best_times.record(3, Duration::from_secs(200)));
+
+        assert_eq!(best_times.to_string(), "3 200000\n7 1500\n42 80000\n");
+        assert_eq!(BestTimes::parse(&best_times.to_string()), best_times);
+    }
+
+    #[test]
+    fn runs_end_when_the_player_is_eliminated() {
+        assert_eq!(run_result(false, None), None);
+        assert_eq!(run_result(true, None), Some(false));
+        assert_eq!(run_result(false, Some(SPEEDRUN_PLAYER)), Some(true));
+        assert_eq!(run_result(false, Some(2)), Some(false));
+    }
+
+    #[test]
+    fn formats_run_times() {
+        assert_eq!(format_run_time(Duration::from_millis(83_456)), "1:23.4");
+    }
+}
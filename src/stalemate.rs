@@ -0,0 +1,215 @@
+//! Stalemates: games where nobody makes progress anymore end by territory count instead of
+//! looping forever. A game is a stalemate once no region changed hands for
+//! [`GameRules::stalemate_rounds`] full rounds, or once the same position comes back at the start
+//! of a turn for the [`REPETITIONS`]th time, compared by its Zobrist hash. Stalemates are off
+//! unless a number of rounds is set.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::events::{EventBattleResolved, EventGameOver, EventTurnStart};
+use crate::game::GameState;
+use crate::profiles::PlayerProfiles;
+use crate::rules::GameRules;
+use crate::ui::spawn_toast;
+
+/// Number of times a position has to be seen at the start of a turn to be a stalemate
+pub const REPETITIONS: usize = 3;
+
+/// Why a game was called a stalemate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stalemate {
+    /// No region changed hands for this many rounds
+    NoConquest(usize),
+    /// The position at the start of the turn was seen [`REPETITIONS`] times
+    Repetition,
+}
+
+/// Progress of the current game, updated at every conquest and turn start
+#[derive(Resource, Default)]
+pub struct StalemateTracker {
+    /// Turns started since a region last changed hands
+    pub quiet_turns: usize,
+    /// Number of turn starts each position was seen at, by Zobrist hash
+    positions: HashMap<u64, usize>,
+    /// The game was called, nothing is checked until the next one
+    pub called: bool,
+}
+
+impl StalemateTracker {
+    /// Forgets the current game, for a fresh one
+    pub fn reset(&mut self) {
+        *self = StalemateTracker::default();
+    }
+
+    /// A region changed hands, the game is making progress
+    pub fn record_conquest(&mut self) {
+        self.quiet_turns = 0;
+        self.positions.clear();
+    }
+
+    /// Checks the game at the start of a turn. Returns the stalemate the first time the game is
+    /// one.
+    pub fn record_turn_start(
+        &mut self,
+        game_state: &GameState,
+        rules: &GameRules,
+    ) -> Option<Stalemate> {
+        if rules.stalemate_rounds == 0 || self.called || game_state.winner().is_some() {
+            return None;
+        }
+
+        self.quiet_turns += 1;
+        let seen = self.positions.entry(game_state.zobrist_hash()).or_insert(0);
+        *seen += 1;
+
+        let stalemate = if *seen >= REPETITIONS {
+            Some(Stalemate::Repetition)
+        } else if self.quiet_turns >= rules.stalemate_rounds * game_state.number_of_players.max(1) {
+            Some(Stalemate::NoConquest(rules.stalemate_rounds))
+        } else {
+            None
+        };
+
+        self.called = stalemate.is_some();
+        stalemate
+    }
+}
+
+/// Winner of a game ended by a stalemate: the player owning the most regions, then the most
+/// dice, the first of them on a tie
+pub fn territory_winner(game_state: &GameState) -> usize {
+    (0..game_state.number_of_players)
+        .max_by_key(|player| {
+            let (regions, dice) = game_state
+                .board
+                .regions
+                .iter()
+                .filter(|region| region.owner == *player)
+                .fold((0, 0), |(regions, dice), region| {
+                    (regions + 1, dice + region.num_dice)
+                });
+            (regions, dice, std::cmp::Reverse(*player))
+        })
+        .unwrap_or(0)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn detect_stalemate(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut event_battle_resolved_reader: EventReader<EventBattleResolved>,
+    mut event_turn_start_reader: EventReader<EventTurnStart>,
+    mut event_game_over_writer: EventWriter<EventGameOver>,
+    mut tracker: ResMut<StalemateTracker>,
+    mut game_state: ResMut<GameState>,
+    rules: Res<GameRules>,
+    profiles: Res<PlayerProfiles>,
+) {
+    if event_battle_resolved_reader
+        .iter()
+        .any(|event| event.attacker_won)
+    {
+        tracker.record_conquest();
+    }
+
+    for _ in event_turn_start_reader.iter() {
+        let stalemate = match tracker.record_turn_start(&game_state, &rules) {
+            Some(stalemate) => stalemate,
+            None => continue,
+        };
+
+        let winner = territory_winner(&game_state);
+        if !game_state.decide_winner(winner) {
+            return;
+        }
+
+        let reason = match stalemate {
+            Stalemate::NoConquest(rounds) => format!("no conquest in {} rounds", rounds),
+            Stalemate::Repetition => "the same position came back".to_string(),
+        };
+        info!("Turn {}: stalemate, {}", game_state.turn_counter, reason);
+        spawn_toast(
+            &mut commands,
+            &asset_server,
+            format!(
+                "Stalemate, {}: {} wins on territory",
+                reason,
+                profiles.name(winner)
+            ),
+        );
+        event_game_over_writer.send(EventGameOver { winner });
+        return;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::game_state;
+
+    #[test]
+    fn quiet_rounds_end_the_game() {
+        let mut game_state = game_state("players 2\nregion 0 1 0,0\nregion 1 1 1,0\n");
+        let rules = GameRules {
+            stalemate_rounds: 2,
+            ..Default::default()
+        };
+        let mut tracker = StalemateTracker::default();
+
+        for turn in 0..6 {
+            // dice counts change so the positions never repeat
+            game_state.board.regions[0].num_dice = turn + 1;
+            assert_eq!(tracker.record_turn_start(&game_state, &rules), None);
+            if turn == 2 {
+                tracker.record_conquest();
+            }
+        }
+        game_state.board.regions[0].num_dice = 7;
+        assert_eq!(
+            tracker.record_turn_start(&game_state, &rules),
+            Some(Stalemate::NoConquest(2))
+        );
+
+        // the game is only called once
+        assert_eq!(tracker.record_turn_start(&game_state, &rules), None);
+    }
+
+    #[test]
+    fn repeated_positions_end_the_game() {
+        let game_state = game_state("players 2\nregion 0 1 0,0\nregion 1 1 1,0\n");
+        let rules = GameRules {
+            stalemate_rounds: 20,
+            ..Default::default()
+        };
+        let mut tracker = StalemateTracker::default();
+
+        assert_eq!(tracker.record_turn_start(&game_state, &rules), None);
+        assert_eq!(tracker.record_turn_start(&game_state, &rules), None);
+        assert_eq!(
+            tracker.record_turn_start(&game_state, &rules),
+            Some(Stalemate::Repetition)
+        );
+    }
+
+    #[test]
+    fn stalemates_are_off_by_default() {
+        let game_state = game_state("players 2\nregion 0 1 0,0\nregion 1 1 1,0\n");
+        let rules = GameRules::default();
+        let mut tracker = StalemateTracker::default();
+
+        for _ in 0..REPETITIONS * 2 {
+            assert_eq!(tracker.record_turn_start(&game_state, &rules), None);
+        }
+    }
+
+    #[test]
+    fn territory_decides_the_winner() {
+        let game_state = game_state(
+            "players 3\nregion 0 5 0,0\nregion 1 1 1,0\nregion 1 1 2,0\nregion 2 2 3,0\nregion 2 1 4,0\n",
+        );
+
+        assert_eq!(territory_winner(&game_state), 2);
+    }
+}
@@ -0,0 +1,147 @@
+//! Provenance stamps of exported files.
+//!
+//! Replays, battle clips, board scenes, screenshots and SVG boards carry an [`ExportStamp`]: the
+//! seeds of the game, a hash of its rules and the version and protocol of the game that wrote
+//! them, so a shared file tells how to play the same game again, and games refuse the files they
+//! can't play (see [`crate::handshake`]).
+
+use std::fmt;
+
+use crate::handshake::PROTOCOL_VERSION;
+use crate::rules::GameRules;
+use crate::tiered_prng::PrngResource;
+use crate::zobrist;
+
+/// Version of the game writing the files
+pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportStamp {
+    pub world_seed: u64,
+    pub env_seed: u64,
+    pub rules_hash: u64,
+    pub version: &'static str,
+    pub protocol: u32,
+}
+
+impl ExportStamp {
+    pub fn new(prng: &PrngResource, rules: &GameRules) -> Self {
+        ExportStamp {
+            world_seed: prng.world_seed,
+            env_seed: prng.env_seed,
+            rules_hash: rules_hash(rules),
+            version: APP_VERSION,
+            protocol: PROTOCOL_VERSION,
+        }
+    }
+}
+
+/// Written as `stackrankdice 0.1.0 seed <world_seed> <env_seed> rules <rules hash> protocol 1`
+impl fmt::Display for ExportStamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stackrankdice {} seed {} {} rules {:016x} protocol {}",
+            self.version, self.world_seed, self.env_seed, self.rules_hash, self.protocol
+        )
+    }
+}
+
+/// Version of the field list of [`rules_fields`], bumped only when the hash of rules that
+/// were already hashed has to change
+const RULES_HASH_VERSION: u64 = 1;
+
+/// Every rule of `rules` by name, in the order they are hashed. Rules added later go at the
+/// end, and only when they differ from their default: the hash of games that don't use them
+/// stays the one older releases wrote.
+fn rules_fields(rules: &GameRules) -> Vec<(&'static str, String)> {
+    vec![
+        ("number_of_players", rules.number_of_players.to_string()),
+        ("board_size", rules.board_size.to_string()),
+        ("regions_per_player", rules.regions_per_player.to_string()),
+        ("dice_per_region", rules.dice_per_region.to_string()),
+        ("max_dice", rules.max_dice.to_string()),
+        ("chaos_interval", rules.chaos_interval.to_string()),
+        ("diplomacy", rules.diplomacy.to_string()),
+        ("truce_rounds", rules.truce_rounds.to_string()),
+        ("objective_turns", rules.objective_turns.to_string()),
+        ("momentum", rules.momentum.to_string()),
+        ("reinforcements", format!("{:?}", rules.reinforcements)),
+        ("reserve_cap", rules.reserve_cap.to_string()),
+        ("tie_rule", format!("{:?}", rules.tie_rule)),
+        ("defender_bonus_dice", rules.defender_bonus_dice.to_string()),
+        ("garrison", rules.garrison.to_string()),
+        ("transfer_rule", format!("{:?}", rules.transfer_rule)),
+        ("time_budget_secs", rules.time_budget_secs.to_string()),
+        ("stalemate_rounds", rules.stalemate_rounds.to_string()),
+        ("random_first_player", rules.random_first_player.to_string()),
+        ("compensation_dice", rules.compensation_dice.to_string()),
+        ("blitz", rules.blitz.to_string()),
+        ("board_generator", rules.board_generator.clone()),
+        ("noise_scale", rules.noise_scale.to_bits().to_string()),
+        ("noise_strength", rules.noise_strength.to_bits().to_string()),
+        ("min_seed_spacing", rules.min_seed_spacing.to_string()),
+    ]
+}
+
+/// Hash of every rule of `rules`, the same for the same rules in any run of any release
+/// hashing the same [`RULES_HASH_VERSION`]
+pub fn rules_hash(rules: &GameRules) -> u64 {
+    rules_fields(rules)
+        .iter()
+        .flat_map(|(name, value)| {
+            name.bytes()
+                .chain(std::iter::once(b'='))
+                .chain(value.bytes())
+                .chain(std::iter::once(b';'))
+        })
+        .fold(zobrist::mix(RULES_HASH_VERSION), |hash, byte| {
+            zobrist::mix(hash ^ byte as u64)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamps_tell_rules_apart() {
+        let prng = PrngResource {
+            world_seed: 12,
+            env_seed: 34,
+        };
+        let stamp = ExportStamp::new(&prng, &GameRules::default());
+        let no_momentum = GameRules {
+            momentum: false,
+            ..Default::default()
+        };
+
+        assert_eq!(stamp, ExportStamp::new(&prng, &GameRules::default()));
+        assert_ne!(stamp.rules_hash, rules_hash(&no_momentum));
+        assert!(stamp
+            .to_string()
+            .starts_with(&format!("stackrankdice {} seed 12 34 rules ", APP_VERSION)));
+    }
+
+    #[test]
+    fn rules_are_hashed_by_name() {
+        let fields = rules_fields(&GameRules::default());
+        let mut names: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+        names.sort_unstable();
+        names.dedup();
+
+        assert_eq!(names.len(), fields.len());
+        // the value of one rule can't pass for the value of the next one
+        let players = GameRules {
+            number_of_players: 21,
+            board_size: 4,
+            ..Default::default()
+        };
+        let board = GameRules {
+            number_of_players: 2,
+            board_size: 14,
+            ..Default::default()
+        };
+        assert_ne!(rules_hash(&players), rules_hash(&board));
+    }
+}
@@ -0,0 +1,289 @@
+//! Where the game keeps its files: saves, best times, clips and caches.
+//!
+//! Files live in the platform directories of the player (XDG directories on Linux, `AppData` on
+//! Windows, `Application Support` on macOS), or all in the directory named by the
+//! `STACKRANKDICE_DIR` environment variable. In the browser text files go to `localStorage`.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Name of the game directory inside the platform directories
+pub const APP_NAME: &str = "stackrankdice";
+
+/// Overrides the platform directories, e.g. for a portable install
+pub const DIR_VARIABLE: &str = "STACKRANKDICE_DIR";
+
+/// Kind of files, each kind has its own platform directory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    /// Files the player would miss: saves, best times, clips
+    Data,
+    /// Settings, profiles and presets
+    Config,
+    /// Files that can be made again, like thumbnails
+    Cache,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Location {
+    fn name(&self) -> &'static str {
+        match self {
+            Location::Data => "data",
+            Location::Config => "config",
+            Location::Cache => "cache",
+        }
+    }
+}
+
+/// Platform directory of `location` on `os` (as in [`std::env::consts::OS`]), reading the
+/// environment with `var`. `None` when the environment doesn't tell where home is.
+pub fn platform_dir(
+    location: Location,
+    os: &str,
+    var: impl Fn(&str) -> Option<String>,
+) -> Option<PathBuf> {
+    if let Some(dir) = var(DIR_VARIABLE) {
+        return Some(PathBuf::from(dir));
+    }
+
+    let home = || var("HOME").map(PathBuf::from);
+    let base = match (os, location) {
+        ("windows", Location::Cache) => var("LOCALAPPDATA").map(PathBuf::from),
+        ("windows", _) => var("APPDATA").map(PathBuf::from),
+        ("macos", Location::Cache) => home().map(|home| home.join("Library/Caches")),
+        ("macos", _) => home().map(|home| home.join("Library/Application Support")),
+        (_, Location::Data) => var("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".local/share"))),
+        (_, Location::Config) => var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".config"))),
+        (_, Location::Cache) => var("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".cache"))),
+    }?;
+
+    Some(base.join(APP_NAME))
+}
+
+/// Directory of `location` on this machine, the working directory when there is no home
+pub fn storage_dir(location: Location) -> PathBuf {
+    platform_dir(location, std::env::consts::OS, |name| {
+        std::env::var(name).ok()
+    })
+    .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Text files of a [`Location`], named by paths relative to it
+pub trait Storage: Send + Sync {
+    fn read(&self, name: &str) -> io::Result<String>;
+
+    /// Replaces the file with `contents`
+    fn write(&self, name: &str, contents: &str) -> io::Result<()>;
+
+    /// Adds `contents` to the end of the file, and makes sure they are kept before returning
+    fn append(&self, name: &str, contents: &str) -> io::Result<()>;
+
+    fn remove(&self, name: &str) -> io::Result<()>;
+
+    /// Names of the files in the directory `dir`, relative to it, in no particular order
+    fn list(&self, dir: &str) -> io::Result<Vec<String>>;
+}
+
+/// Files in a directory of the file system
+pub struct FileStorage {
+    pub root: PathBuf,
+}
+
+impl FileStorage {
+    fn path(&self, name: &str) -> io::Result<PathBuf> {
+        let path = self.root.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        Ok(path)
+    }
+}
+
+impl Storage for FileStorage {
+    fn read(&self, name: &str) -> io::Result<String> {
+        fs::read_to_string(self.root.join(name))
+    }
+
+    fn write(&self, name: &str, contents: &str) -> io::Result<()> {
+        let mut file = fs::File::create(self.path(name)?)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_data()
+    }
+
+    fn append(&self, name: &str, contents: &str) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(name)?)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_data()
+    }
+
+    fn remove(&self, name: &str) -> io::Result<()> {
+        fs::remove_file(self.root.join(name))
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        fs::read_dir(self.root.join(dir))?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect()
+    }
+}
+
+/// Entries of the `localStorage` of the browser, keyed by `<prefix>/<name>`
+#[cfg(target_arch = "wasm32")]
+pub struct LocalStorage {
+    pub prefix: String,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl LocalStorage {
+    fn storage(&self) -> io::Result<web_sys::Storage> {
+        web_sys::window()
+            .and_then(|window| window.local_storage().ok().flatten())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "no local storage"))
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}/{}", self.prefix, name)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn js_error(err: wasm_bindgen::JsValue) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("{:?}", err))
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Storage for LocalStorage {
+    fn read(&self, name: &str) -> io::Result<String> {
+        self.storage()?
+            .get_item(&self.key(name))
+            .map_err(js_error)?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn write(&self, name: &str, contents: &str) -> io::Result<()> {
+        self.storage()?
+            .set_item(&self.key(name), contents)
+            .map_err(js_error)
+    }
+
+    fn append(&self, name: &str, contents: &str) -> io::Result<()> {
+        let existing = self.read(name).unwrap_or_default();
+        self.write(name, &(existing + contents))
+    }
+
+    fn remove(&self, name: &str) -> io::Result<()> {
+        self.storage()?
+            .remove_item(&self.key(name))
+            .map_err(js_error)
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        let storage = self.storage()?;
+        let prefix = self.key(&format!("{}/", dir));
+        let mut names = Vec::new();
+        for index in 0..storage.length().map_err(js_error)? {
+            if let Some(key) = storage.key(index).map_err(js_error)? {
+                if let Some(name) = key.strip_prefix(&prefix) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        Ok(names)
+    }
+}
+
+/// Text files of `location` on this platform
+pub fn storage(location: Location) -> Box<dyn Storage> {
+    #[cfg(target_arch = "wasm32")]
+    return Box::new(LocalStorage {
+        prefix: format!("{}/{}", APP_NAME, location.name()),
+    });
+
+    #[cfg(not(target_arch = "wasm32"))]
+    Box::new(FileStorage {
+        root: storage_dir(location),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn resolves_platform_directories() {
+        let home = [("HOME", "/home/alex")];
+        assert_eq!(
+            platform_dir(Location::Data, "linux", env(&home)),
+            Some(PathBuf::from("/home/alex/.local/share/stackrankdice"))
+        );
+        assert_eq!(
+            platform_dir(
+                Location::Config,
+                "linux",
+                env(&[("HOME", "/home/alex"), ("XDG_CONFIG_HOME", "/cfg")])
+            ),
+            Some(PathBuf::from("/cfg/stackrankdice"))
+        );
+        assert_eq!(
+            platform_dir(Location::Data, "macos", env(&home)),
+            Some(PathBuf::from(
+                "/home/alex/Library/Application Support/stackrankdice"
+            ))
+        );
+        assert_eq!(
+            platform_dir(
+                Location::Cache,
+                "windows",
+                env(&[("LOCALAPPDATA", "C:/Local")])
+            ),
+            Some(PathBuf::from("C:/Local/stackrankdice"))
+        );
+        assert_eq!(platform_dir(Location::Data, "linux", env(&[])), None);
+        assert_eq!(
+            platform_dir(
+                Location::Cache,
+                "linux",
+                env(&[("HOME", "/home/alex"), (DIR_VARIABLE, "/portable")])
+            ),
+            Some(PathBuf::from("/portable"))
+        );
+    }
+
+    #[test]
+    fn file_storage_round_trip() {
+        let storage = FileStorage {
+            root: std::env::temp_dir().join(format!("stackrankdice-{}", std::process::id())),
+        };
+
+        storage.write("saves/game.replay", "seed 1 2\n").unwrap();
+        storage.append("saves/game.replay", "pass\n").unwrap();
+        assert_eq!(
+            storage.read("saves/game.replay").unwrap(),
+            "seed 1 2\npass\n"
+        );
+
+        storage.remove("saves/game.replay").unwrap();
+        assert!(storage.read("saves/game.replay").is_err());
+        let _ = fs::remove_dir_all(&storage.root);
+    }
+}
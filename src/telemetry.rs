@@ -0,0 +1,383 @@
+//! Anonymous balance data: a summary of every finished game, sent in batches to an endpoint so
+//! board generators and rules can be tuned on real games.
+//!
+//! Off unless the player passes `--telemetry`. Summaries hold no names, seeds or moves, only the
+//! board parameters, the rules, the winning seat and the length of the game. They wait in
+//! [`PENDING_PATH`] of the data directory until [`BATCH_SIZE`] games are finished, then the batch
+//! is posted as JSON on a background thread. They are only forgotten once the endpoint accepted
+//! them, a failed post is tried again with the next finished game.
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::clock::PlayerClocks;
+use crate::events::EventGameOver;
+use crate::game::GameState;
+use crate::rules::GameRules;
+use crate::storage::{storage, Location, Storage};
+
+/// Summaries of finished games not sent yet, one JSON object per line, relative to the data
+/// directory
+pub const PENDING_PATH: &str = "telemetry_pending.jsonl";
+
+/// Number of games sent together
+pub const BATCH_SIZE: usize = 5;
+
+/// Time given to the endpoint to accept a batch
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What is sent about a finished game
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameSummary {
+    pub generator: String,
+    pub board_size: isize,
+    pub regions: usize,
+    pub number_of_players: usize,
+    pub max_dice: usize,
+    pub reinforcements: String,
+    pub tie_rule: String,
+    pub defender_bonus_dice: usize,
+    pub winner: usize,
+    pub turns: usize,
+    /// Thinking time of all players together, dice rolls left out
+    pub thinking_secs: u64,
+}
+
+impl GameSummary {
+    pub fn new(
+        rules: &GameRules,
+        game_state: &GameState,
+        clocks: &PlayerClocks,
+        winner: usize,
+    ) -> Self {
+        GameSummary {
+            generator: rules.board_generator.clone(),
+            board_size: rules.board_size,
+            regions: game_state.board.regions.len(),
+            number_of_players: game_state.number_of_players,
+            max_dice: rules.max_dice,
+            reinforcements: format!("{:?}", rules.reinforcements),
+            tie_rule: format!("{:?}", rules.tie_rule),
+            defender_bonus_dice: rules.defender_bonus_dice,
+            winner,
+            turns: game_state.turn_counter,
+            thinking_secs: clocks.used.iter().sum::<Duration>().as_secs(),
+        }
+    }
+
+    /// The summary as a single line JSON object
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"generator\":{},\"board_size\":{},\"regions\":{},\"number_of_players\":{},\
+             \"max_dice\":{},\"reinforcements\":{},\"tie_rule\":{},\"defender_bonus_dice\":{},\
+             \"winner\":{},\"turns\":{},\"thinking_secs\":{}}}",
+            json_string(&self.generator),
+            self.board_size,
+            self.regions,
+            self.number_of_players,
+            self.max_dice,
+            json_string(&self.reinforcements),
+            json_string(&self.tie_rule),
+            self.defender_bonus_dice,
+            self.winner,
+            self.turns,
+            self.thinking_secs
+        )
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Body posted for a batch of summaries, each given as a JSON object
+pub fn batch_json(summaries: &[&str]) -> String {
+    format!("{{\"games\":[{}]}}", summaries.join(","))
+}
+
+/// Where batches are posted, a plain `http://host[:port]/path` URL
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Endpoint {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("{} is not an http:// URL", url))?;
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse::<u16>()
+                    .map_err(|_| format!("{} is not a valid port", port))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("{} has no host", url));
+        }
+
+        Ok(Endpoint {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// Posts `body` as JSON, successful when the endpoint answers with a 2xx status
+    fn post(&self, body: &str) -> io::Result<()> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.path,
+            self.host,
+            body.len(),
+            body
+        )?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let status = response.split_whitespace().nth(1).unwrap_or("");
+        match status.starts_with('2') {
+            true => Ok(()),
+            false => Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("endpoint answered {}", status),
+            )),
+        }
+    }
+}
+
+/// Balance data collection, disabled unless given an endpoint
+#[derive(Resource)]
+pub struct Telemetry {
+    endpoint: Option<Endpoint>,
+    storage: Box<dyn Storage>,
+    /// Number of summaries in the batch being posted, and the result of the post once known
+    sending: Option<(usize, Mutex<Receiver<io::Result<()>>>)>,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Telemetry {
+            endpoint: None,
+            storage: storage(Location::Data),
+            sending: None,
+        }
+    }
+}
+
+impl Telemetry {
+    /// Collects summaries and sends them to `endpoint`
+    pub fn enabled(endpoint: Endpoint) -> Self {
+        Telemetry {
+            endpoint: Some(endpoint),
+            ..default()
+        }
+    }
+
+    /// Keeps the summary until the batch is full, then returns the number of summaries in the
+    /// batch and the batch to send. Nothing is sent while the last batch is still being posted.
+    fn record(&mut self, summary: &GameSummary) -> io::Result<Option<(usize, String)>> {
+        self.storage
+            .append(PENDING_PATH, &format!("{}\n", summary.to_json()))?;
+
+        let pending = self.storage.read(PENDING_PATH)?;
+        let summaries: Vec<&str> = pending.lines().filter(|line| !line.is_empty()).collect();
+        if summaries.len() < BATCH_SIZE || self.sending.is_some() {
+            return Ok(None);
+        }
+
+        Ok(Some((summaries.len(), batch_json(&summaries))))
+    }
+
+    /// Forgets the first `count` summaries, the endpoint accepted them. Summaries kept since
+    /// wait for the next batch.
+    fn sent(&mut self, count: usize) -> io::Result<()> {
+        let pending = self.storage.read(PENDING_PATH)?;
+        let left: Vec<&str> = pending
+            .lines()
+            .filter(|line| !line.is_empty())
+            .skip(count)
+            .collect();
+        match left.is_empty() {
+            true => self.storage.remove(PENDING_PATH),
+            false => self
+                .storage
+                .write(PENDING_PATH, &format!("{}\n", left.join("\n"))),
+        }
+    }
+}
+
+pub(crate) fn telemetry_game_over(
+    mut event_game_over_reader: EventReader<EventGameOver>,
+    mut telemetry: ResMut<Telemetry>,
+    rules: Res<GameRules>,
+    game_state: Res<GameState>,
+    clocks: Res<PlayerClocks>,
+) {
+    for e in event_game_over_reader.iter() {
+        let endpoint = match telemetry.endpoint.clone() {
+            Some(endpoint) => endpoint,
+            None => return,
+        };
+
+        let summary = GameSummary::new(&rules, &game_state, &clocks, e.winner);
+        match telemetry.record(&summary) {
+            Ok(Some((count, batch))) => {
+                let (sender, receiver) = mpsc::channel();
+                std::thread::spawn(move || {
+                    let _ = sender.send(endpoint.post(&batch));
+                });
+                telemetry.sending = Some((count, Mutex::new(receiver)));
+            }
+            Ok(None) => {}
+            Err(err) => warn!("Failed to keep telemetry in {}: {}", PENDING_PATH, err),
+        }
+    }
+}
+
+/// Forgets the summaries of a batch once the endpoint accepted it, keeps them for the next
+/// batch otherwise
+pub(crate) fn telemetry_sent(mut telemetry: ResMut<Telemetry>) {
+    let posted = match telemetry.sending.as_ref() {
+        Some((_, receiver)) => match receiver.lock().unwrap().try_recv() {
+            Ok(posted) => posted,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "the post was interrupted",
+            )),
+        },
+        None => return,
+    };
+    let count = match telemetry.sending.take() {
+        Some((count, _)) => count,
+        None => return,
+    };
+
+    match posted {
+        Ok(()) => {
+            if let Err(err) = telemetry.sent(count) {
+                warn!("Failed to update {}: {}", PENDING_PATH, err);
+            }
+        }
+        Err(err) => warn!("Failed to send telemetry, kept for the next batch: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+    use crate::storage::FileStorage;
+
+    #[test]
+    fn summarizes_games_as_json() {
+        let game_state = GameState {
+            board: MapFile::parse("players 2\nregion 0 1 0,0\nregion 0 1 1,0\n")
+                .unwrap()
+                .board,
+            number_of_players: 2,
+            turn_counter: 12,
+            ..Default::default()
+        };
+        let clocks = PlayerClocks {
+            used: vec![Duration::from_secs(30), Duration::from_millis(45_500)],
+            stopped: true,
+        };
+        let summary = GameSummary::new(&GameRules::default(), &game_state, &clocks, 0);
+
+        assert_eq!(
+            summary.to_json(),
+            "{\"generator\":\"patches\",\"board_size\":20,\"regions\":2,\"number_of_players\":2,\
+             \"max_dice\":8,\"reinforcements\":\"None\",\"tie_rule\":\"DefenderWins\",\
+             \"defender_bonus_dice\":0,\"winner\":0,\"turns\":12,\"thinking_secs\":75}"
+        );
+        assert_eq!(json_string("a \"b\"\n"), "\"a \\\"b\\\"\\u000a\"");
+        assert_eq!(batch_json(&["{}", "{}"]), "{\"games\":[{},{}]}");
+    }
+
+    #[test]
+    fn batches_are_kept_until_sent() {
+        let root =
+            std::env::temp_dir().join(format!("stackrankdice-telemetry-{}", std::process::id()));
+        let mut telemetry = Telemetry {
+            endpoint: None,
+            storage: Box::new(FileStorage { root: root.clone() }),
+            sending: None,
+        };
+        let game_state = GameState {
+            number_of_players: 2,
+            ..Default::default()
+        };
+        let summary = GameSummary::new(
+            &GameRules::default(),
+            &game_state,
+            &PlayerClocks::default(),
+            1,
+        );
+
+        for _ in 1..BATCH_SIZE {
+            assert_eq!(telemetry.record(&summary).unwrap(), None);
+        }
+        let (count, _) = telemetry.record(&summary).unwrap().unwrap();
+        assert_eq!(count, BATCH_SIZE);
+
+        // a game finished while the batch was posted waits for the next one
+        let (_, receiver) = mpsc::channel();
+        telemetry.sending = Some((count, Mutex::new(receiver)));
+        assert_eq!(telemetry.record(&summary).unwrap(), None);
+        telemetry.sent(count).unwrap();
+        let pending = telemetry.storage.read(PENDING_PATH).unwrap();
+        assert_eq!(pending, format!("{}\n", summary.to_json()));
+
+        telemetry.sent(1).unwrap();
+        assert!(telemetry.storage.read(PENDING_PATH).is_err());
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn parses_endpoints() {
+        assert_eq!(
+            Endpoint::parse("http://stats.example.org:8080/games"),
+            Ok(Endpoint {
+                host: "stats.example.org".to_string(),
+                port: 8080,
+                path: "/games".to_string(),
+            })
+        );
+        assert_eq!(Endpoint::parse("http://localhost").unwrap().path, "/");
+        assert!(Endpoint::parse("https://stats.example.org").is_err());
+        assert!(Endpoint::parse("http://:80/").is_err());
+    }
+}
@@ -0,0 +1,307 @@
+//! Helpers for property-based testing of the game rules.
+//!
+//! Generates random boards and move sequences, plays them out against [`GameState`]
+//! the same way the event handlers do, and checks rule invariants after every move.
+
+use proptest::prelude::*;
+use rand::{
+    seq::{IteratorRandom, SliceRandom},
+    SeedableRng,
+};
+use rand_chacha::ChaCha20Rng;
+
+use crate::game::{generate_board, roll_dice, GameLogEntry, GameState, TurnPhase, NEUTRAL};
+use crate::map_file::MapFile;
+use crate::rules::{GameRules, ReinforcementRule, TieRule, TransferRule};
+use crate::tiered_prng::get_randomness;
+
+/// A single step of a simulated game. Seeds pick the attack among legal moves and roll the dice.
+#[derive(Clone, Debug)]
+pub struct SimulatedMove {
+    pub move_seed: u64,
+    pub roll_seed: u64,
+}
+
+/// Rules for a random game. Player count is kept small so boards generate quickly.
+pub fn arb_rules() -> impl Strategy<Value = GameRules> {
+    (
+        2usize..=4,
+        any::<bool>(),
+        prop_oneof![
+            Just(ReinforcementRule::None),
+            Just(ReinforcementRule::Automatic),
+            Just(ReinforcementRule::Manual)
+        ],
+        prop_oneof![
+            Just(TieRule::DefenderWins),
+            Just(TieRule::AttackerWins),
+            Just(TieRule::Reroll)
+        ],
+        0usize..=1,
+        prop_oneof![
+            Just(TransferRule::Random),
+            Just(TransferRule::AllButOne),
+            (0usize..=100).prop_map(TransferRule::Percentage)
+        ],
+    )
+        .prop_map(
+            |(
+                number_of_players,
+                momentum,
+                reinforcements,
+                tie_rule,
+                defender_bonus_dice,
+                transfer_rule,
+            )| {
+                GameRules {
+                    momentum,
+                    reinforcements,
+                    tie_rule,
+                    defender_bonus_dice,
+                    transfer_rule,
+                    ..GameRules::with_players(number_of_players)
+                }
+            },
+        )
+}
+
+/// A game starting on the board of the map file `source`, for its players
+pub fn game_state(source: &str) -> GameState {
+    let map = MapFile::parse(source).unwrap();
+    GameState {
+        board: map.board,
+        number_of_players: map.number_of_players,
+        ..Default::default()
+    }
+}
+
+/// A freshly generated game for random rules and world seed
+pub fn arb_game() -> impl Strategy<Value = (GameRules, GameState)> {
+    (arb_rules(), any::<u64>()).prop_map(|(rules, world_seed)| {
+        let game_state = GameState {
+            board: generate_board(&rules, get_randomness(world_seed)),
+            number_of_players: rules.number_of_players,
+            ..Default::default()
+        };
+
+        (rules, game_state)
+    })
+}
+
+pub fn arb_moves(max_moves: usize) -> impl Strategy<Value = Vec<SimulatedMove>> {
+    prop::collection::vec(
+        (any::<u64>(), any::<u64>()).prop_map(|(move_seed, roll_seed)| SimulatedMove {
+            move_seed,
+            roll_seed,
+        }),
+        0..max_moves,
+    )
+}
+
+/// Plays one move the way the event handlers do: logs the attack, resolves the battle and
+/// passes the turn once the player has no region left to attack with. A player without any
+/// legal move passes immediately.
+pub fn play_move(game_state: &mut GameState, rules: &GameRules, simulated_move: &SimulatedMove) {
+    let legal_moves: Vec<_> = game_state
+        .possible_moves(rules)
+        .into_iter()
+        .filter(|(region_1, _)| !game_state.has_moved_this_turn(*region_1))
+        .collect();
+
+    let mut move_rng = ChaCha20Rng::seed_from_u64(simulated_move.move_seed);
+
+    if game_state.turn_phase == TurnPhase::Reinforcement {
+        let region = game_state
+            .board
+            .regions
+            .iter()
+            .filter(|region| game_state.can_place_reinforcement(region, rules))
+            .map(|region| region.id)
+            .choose(&mut move_rng);
+        if let Some(region) = region {
+            game_state.place_reinforcement(region, rules);
+        }
+
+        game_state.finish_turn(rules);
+        return;
+    }
+
+    let (region_1, region_2) = match legal_moves.choose(&mut move_rng) {
+        Some(chosen) => *chosen,
+        None => {
+            game_state.end_turn(rules);
+            return;
+        }
+    };
+
+    let mut roll_rng = ChaCha20Rng::seed_from_u64(simulated_move.roll_seed);
+    let regions = &game_state.board.regions;
+    let region_1_dice_result = roll_dice(
+        rules.attacker_roll_size(regions[region_1].num_dice),
+        &mut roll_rng,
+    );
+    let region_2_dice_result = roll_dice(
+        rules.defender_roll_size(regions[region_2].num_dice),
+        &mut roll_rng,
+    );
+
+    game_state.log_attack(GameLogEntry {
+        turn_counter: game_state.turn_counter,
+        turn_of_player: game_state.turn_of_player,
+        region_1,
+        region_2,
+        region_1_dice_result: region_1_dice_result.clone(),
+        region_2_dice_result: region_2_dice_result.clone(),
+        ..Default::default()
+    });
+
+    let outcome = game_state.resolve_battle(
+        region_1,
+        region_2,
+        &region_1_dice_result,
+        &region_2_dice_result,
+        rules,
+        &mut roll_rng,
+    );
+
+    let last_log_entry = game_state.game_log.last_mut().unwrap();
+    last_log_entry.region_1_dice_result = outcome.attacker_roll;
+    last_log_entry.region_2_dice_result = outcome.defender_roll;
+
+    game_state.finish_turn(rules);
+}
+
+/// Checks invariants that must hold for any reachable game state
+pub fn check_state_invariants(game_state: &GameState) -> Result<(), String> {
+    for region in game_state.board.regions.iter() {
+        if region.owner >= game_state.number_of_players && region.owner != NEUTRAL {
+            return Err(format!(
+                "region {} is owned by player {}, but there are only {} players",
+                region.id, region.owner, game_state.number_of_players
+            ));
+        }
+
+        if region.num_dice == 0 {
+            return Err(format!("region {} has no dice", region.id));
+        }
+
+        for hex in region.hexes.iter() {
+            if game_state.board.hexes.get(hex) != Some(&region.owner) {
+                return Err(format!(
+                    "hex {:?} of region {} is not marked as owned by player {}",
+                    hex, region.id, region.owner
+                ));
+            }
+        }
+    }
+
+    if game_state.turn_of_player >= game_state.number_of_players {
+        return Err(format!(
+            "turn of player {}, but there are only {} players",
+            game_state.turn_of_player, game_state.number_of_players
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks invariants between the states before and after a single move
+pub fn check_move_invariants(before: &GameState, after: &GameState) -> Result<(), String> {
+    if after.turn_counter < before.turn_counter {
+        return Err(format!(
+            "turn counter went back from {} to {}",
+            before.turn_counter, after.turn_counter
+        ));
+    }
+
+    let last_battle = match after.game_log.last() {
+        Some(entry) if after.game_log.len() > before.game_log.len() => entry,
+        _ => return Ok(()),
+    };
+
+    let (id_1, id_2) = (last_battle.region_1, last_battle.region_2);
+    let dice_before = [
+        before.board.regions[id_1].num_dice,
+        before.board.regions[id_2].num_dice,
+    ];
+    let dice_after = after.board.regions[id_1].num_dice + after.board.regions[id_2].num_dice;
+
+    let winner = match after.board.regions[id_1].owner == before.board.regions[id_1].owner {
+        true => 0,
+        false => 1,
+    };
+
+    // The winner's dice are split over both regions, the loser's dice are destroyed.
+    // A winner with a single die doesn't split, the conquered region keeps its dice.
+    let expected_dice = match dice_before[winner] > 1 {
+        true => dice_before[winner],
+        false => dice_before[0] + dice_before[1],
+    };
+
+    // A battle ending the turn spreads the reinforcements earned, maybe onto these regions. They
+    // are the dice the board gained apart from the ones the battle destroyed.
+    let total_dice = |game_state: &GameState| -> usize {
+        game_state
+            .board
+            .regions
+            .iter()
+            .map(|region| region.num_dice)
+            .sum()
+    };
+    let destroyed = dice_before[0] + dice_before[1] - expected_dice;
+    let earned = (total_dice(after) + destroyed).saturating_sub(total_dice(before));
+
+    if dice_after < expected_dice || dice_after > expected_dice + earned {
+        return Err(format!(
+            "battle between regions {} and {} with {:?} dice left {} dice, expected {} and up to \
+             {} reinforcements",
+            id_1, id_2, dice_before, dice_after, expected_dice, earned
+        ));
+    }
+
+    if after.board.regions[id_1].owner != after.board.regions[id_2].owner {
+        return Err(format!(
+            "regions {} and {} have different owners after a battle",
+            id_1, id_2
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dice_audit::check_dice_conservation;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn generated_boards_are_valid((_, game_state) in arb_game()) {
+            prop_assert_eq!(check_state_invariants(&game_state), Ok(()));
+        }
+
+        #[test]
+        fn moves_preserve_invariants(
+            (rules, mut game_state) in arb_game(),
+            moves in arb_moves(200),
+        ) {
+            for simulated_move in moves.iter() {
+                if game_state.winner().is_some() {
+                    break;
+                }
+
+                let before = game_state.clone();
+                play_move(&mut game_state, &rules, simulated_move);
+
+                prop_assert_eq!(check_state_invariants(&game_state), Ok(()));
+                prop_assert_eq!(check_move_invariants(&before, &game_state), Ok(()));
+                prop_assert_eq!(
+                    check_dice_conservation(&before, &game_state, &rules),
+                    Ok(())
+                );
+            }
+        }
+    }
+}
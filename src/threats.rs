@@ -0,0 +1,73 @@
+//! Debug overlay coloring every region by the threat it faces: interior regions are grey,
+//! border regions go from yellow to red with the enemy dice next to them.
+
+use bevy::prelude::*;
+
+use crate::events::EventBoardChanged;
+use crate::game::{GameState, Region, RegionThreat};
+use crate::input::control_pressed;
+
+/// Key that toggles the threat overlay
+const OVERLAY_KEY: KeyCode = KeyCode::F9;
+
+#[derive(Resource, Default)]
+pub struct ThreatOverlay {
+    pub enabled: bool,
+}
+
+fn threat_color(threat: &RegionThreat, max_enemy_dice: usize) -> Color {
+    if !threat.is_border {
+        return Color::DARK_GRAY;
+    }
+
+    let heat = threat.enemy_dice as f32 / max_enemy_dice.max(1) as f32;
+    Color::rgb(1.0, 1.0 - heat, 0.0)
+}
+
+pub(crate) fn threat_overlay(
+    keys: Res<Input<KeyCode>>,
+    mut overlay: ResMut<ThreatOverlay>,
+    game_state: Res<GameState>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    all_hexes: Query<(&Region, &Handle<StandardMaterial>)>,
+    new_hexes: Query<(&Region, &Handle<StandardMaterial>), Added<Region>>,
+    mut event_board_changed_writer: EventWriter<EventBoardChanged>,
+) {
+    let toggled = keys.just_pressed(OVERLAY_KEY) && !control_pressed(&keys);
+    if toggled {
+        overlay.enabled = !overlay.enabled;
+
+        // redraw the board with the player colors
+        if !overlay.enabled {
+            event_board_changed_writer.send(EventBoardChanged);
+        }
+    }
+
+    if !overlay.enabled {
+        return;
+    }
+
+    // recolor everything when the overlay is turned on, then only hexes of redrawn boards
+    let hexes: Vec<_> = match toggled {
+        true => all_hexes.iter().collect(),
+        false => new_hexes.iter().collect(),
+    };
+    if hexes.is_empty() {
+        return;
+    }
+
+    let threats = game_state.board.threat_map();
+    let max_enemy_dice = threats
+        .iter()
+        .map(|threat| threat.enemy_dice)
+        .max()
+        .unwrap_or(0);
+
+    for (region, material) in hexes {
+        if let (Some(material), Some(threat)) =
+            (materials.get_mut(material), threats.get(region.id))
+        {
+            material.base_color = threat_color(threat, max_enemy_dice);
+        }
+    }
+}
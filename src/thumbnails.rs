@@ -0,0 +1,385 @@
+//! Small preview images of boards, drawn on the CPU so they can be made without a window, and
+//! SVG drawings of boards to share (see [`render_svg`]).
+//!
+//! Thumbnails of map files and replays (saves included) are cached in [`THUMBNAILS_DIR`] of the
+//! cache directory as binary PPM images, and drawn again when their file is newer than the cached
+//! image. The setup screen shows the thumbnail of the map file picked, see [`crate::menu`].
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::game::{Board, GameState, RegionId};
+use crate::geometry;
+use crate::hex::HexCoord;
+use crate::map_file::MapFile;
+use crate::profiles::PlayerProfiles;
+use crate::replay::Replay;
+use crate::replay_binary::{decode, is_binary};
+use crate::rules::GameRules;
+use crate::session::new_game_state_started_by;
+use crate::zobrist;
+
+pub const THUMBNAILS_DIR: &str = "thumbnails";
+
+/// Width and height of cached thumbnails, in pixels
+pub const THUMBNAIL_SIZE: u32 = 96;
+
+const BACKGROUND: [u8; 3] = [0, 0, 0];
+
+/// Color of highlighted regions
+const HIGHLIGHT: [u8; 3] = [255, 255, 255];
+
+/// Pixels closer than this to the border of two regions are drawn as the border, in hex radii
+const BORDER_WIDTH: f32 = 0.12;
+
+/// An RGB image, row by row
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Thumbnail {
+    #[allow(dead_code)]
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// The image as a binary PPM file
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        ppm.extend(self.pixels.iter().flatten());
+        ppm
+    }
+
+    /// Reads back an image written by [`Thumbnail::to_ppm`]
+    pub fn from_ppm(ppm: &[u8]) -> Option<Thumbnail> {
+        // the header is three lines: the magic number, the size and the largest channel value
+        let mut lines = ppm.splitn(4, |byte| *byte == b'\n');
+        if lines.next()? != b"P6" {
+            return None;
+        }
+        let size = std::str::from_utf8(lines.next()?).ok()?;
+        let (width, height) = size.split_once(' ')?;
+        let (width, height): (u32, u32) = (width.parse().ok()?, height.parse().ok()?);
+        if lines.next()? != b"255" {
+            return None;
+        }
+
+        let data = lines.next()?;
+        if data.len() != (width * height * 3) as usize {
+            return None;
+        }
+        let pixels = data
+            .chunks_exact(3)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect();
+
+        Some(Thumbnail {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+/// Color of the regions of `owner`, as 8 bit channels
+fn owner_color(profiles: &PlayerProfiles, owner: usize) -> [u8; 3] {
+    let [r, g, b, _] = profiles.color(owner).as_rgba_f32();
+    [r, g, b].map(|channel| (channel * 255.0) as u8)
+}
+
+/// Draws `board` from above in a `size` by `size` image, every region in the color of its owner
+pub fn render_thumbnail(board: &Board, profiles: &PlayerProfiles, size: u32) -> Thumbnail {
+    render_highlighted(board, profiles, size, &[])
+}
+
+/// Draws `board` like [`render_thumbnail`], with the `highlighted` regions in white
+pub fn render_highlighted(
+    board: &Board,
+    profiles: &PlayerProfiles,
+    size: u32,
+    highlighted: &[RegionId],
+) -> Thumbnail {
+    // center on the board plane, region and color of every hex
+    let hexes: Vec<(f32, f32, usize, [u8; 3])> = board
+        .regions
+        .iter()
+        .flat_map(|region| {
+            let color = match highlighted.contains(&region.id) {
+                true => HIGHLIGHT,
+                false => owner_color(profiles, region.owner),
+            };
+            region.hexes.iter().map(move |(q, r)| {
+                let [x, _, z] = geometry::center(1.0, &HexCoord::new(*q, *r), &[0.0, 0.0, 0.0]);
+                (x, z, region.id, color)
+            })
+        })
+        .collect();
+
+    let mut thumbnail = Thumbnail {
+        width: size,
+        height: size,
+        pixels: vec![BACKGROUND; (size * size) as usize],
+    };
+    if hexes.is_empty() || size == 0 {
+        return thumbnail;
+    }
+
+    // fit the board in the image, keeping its proportions
+    let (mut min_x, mut min_z, mut max_x, mut max_z) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for (x, z, _, _) in hexes.iter() {
+        min_x = min_x.min(x - 1.0);
+        min_z = min_z.min(z - 1.0);
+        max_x = max_x.max(x + 1.0);
+        max_z = max_z.max(z + 1.0);
+    }
+    let scale = (max_x - min_x).max(max_z - min_z) / size as f32;
+    let (offset_x, offset_z) = (
+        min_x - ((size as f32 * scale) - (max_x - min_x)) / 2.0,
+        min_z - ((size as f32 * scale) - (max_z - min_z)) / 2.0,
+    );
+
+    for y in 0..size {
+        for x in 0..size {
+            let point_x = offset_x + (x as f32 + 0.5) * scale;
+            let point_z = offset_z + (y as f32 + 0.5) * scale;
+
+            // the nearest hex center holds the point, the second nearest tells about borders
+            let mut nearest: Option<(f32, usize, [u8; 3])> = None;
+            let mut second: Option<(f32, usize)> = None;
+            for (hex_x, hex_z, region, color) in hexes.iter() {
+                let distance = ((point_x - hex_x).powi(2) + (point_z - hex_z).powi(2)).sqrt();
+                match nearest {
+                    Some((nearest_distance, _, _)) if distance >= nearest_distance => {
+                        if !matches!(second, Some((d, _)) if d <= distance) {
+                            second = Some((distance, *region));
+                        }
+                    }
+                    _ => {
+                        second = nearest.map(|(d, r, _)| (d, r));
+                        nearest = Some((distance, *region, *color));
+                    }
+                }
+            }
+
+            if let Some((distance, region, color)) = nearest {
+                if distance > geometry::HEX_INNER_RADIUS_RATIO + BORDER_WIDTH {
+                    continue;
+                }
+                let on_border = match second {
+                    Some((second_distance, second_region)) => {
+                        second_region != region && second_distance - distance < BORDER_WIDTH
+                    }
+                    None => false,
+                };
+                thumbnail.pixels[(y * size + x) as usize] = match on_border {
+                    true => BACKGROUND,
+                    false => color,
+                };
+            }
+        }
+    }
+
+    thumbnail
+}
+
+/// Draws `board` from above as an SVG image, every hex in the color of the owner of its region,
+/// with `comment` in an XML comment at the top
+pub fn render_svg(board: &Board, profiles: &PlayerProfiles, comment: &str) -> String {
+    let (mut min_x, mut min_z, mut max_x, mut max_z) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    let mut hexes = Vec::new();
+    for region in board.regions.iter() {
+        let [r, g, b] = owner_color(profiles, region.owner);
+        for (q, hex_r) in region.hexes.iter() {
+            let mut corners = Vec::new();
+            geometry::flat_hexagon_ring(&mut corners, 1.0, &HexCoord::new(*q, *hex_r), &[0.0; 3]);
+            // the ring closes on its first corner, polygons close on their own
+            corners.pop();
+
+            let points: Vec<String> = corners
+                .iter()
+                .map(|[x, _, z]| {
+                    min_x = min_x.min(*x);
+                    min_z = min_z.min(*z);
+                    max_x = max_x.max(*x);
+                    max_z = max_z.max(*z);
+                    format!("{:.3},{:.3}", x, z)
+                })
+                .collect();
+            hexes.push(format!(
+                "<polygon points=\"{}\" fill=\"#{:02x}{:02x}{:02x}\"/>",
+                points.join(" "),
+                r,
+                g,
+                b
+            ));
+        }
+    }
+
+    if hexes.is_empty() {
+        (min_x, min_z, max_x, max_z) = (0.0, 0.0, 0.0, 0.0);
+    }
+
+    // comments can't hold a double dash
+    let comment = comment.replace("--", "- -");
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!-- {} -->\n\
+         <svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{:.3} {:.3} {:.3} {:.3}\" \
+         stroke=\"#000000\" stroke-width=\"0.05\">\n{}\n</svg>\n",
+        comment,
+        min_x,
+        min_z,
+        max_x - min_x,
+        max_z - min_z,
+        hexes.join("\n")
+    )
+}
+
+/// The board described by a map file or reached at the end of a replay
+pub fn load_board(source: &str) -> Option<Board> {
+    if let Ok(map) = MapFile::parse(source) {
+        return Some(map.board);
+    }
+
+    Replay::parse(source)
+        .ok()
+        .map(|replay| replay_board(&replay))
+}
+
+/// The board reached at the end of `replay`
+fn replay_board(replay: &Replay) -> Board {
+    let rules = replay.rules(&GameRules::with_players(replay.number_of_players));
+    let mut game_state: GameState =
+        new_game_state_started_by(&rules, replay.world_seed, replay.first_player(&rules));
+    // a replay cut short by an illegal move still shows where it stopped
+    let _ = replay.apply(&mut game_state, &rules);
+
+    game_state.board
+}
+
+/// Where the thumbnail of `source_path` is cached in `cache_dir`. Files of the same name in
+/// different directories are told apart by a hash of their full path.
+pub fn thumbnail_path(source_path: &Path, cache_dir: &Path) -> PathBuf {
+    let full_path = fs::canonicalize(source_path).unwrap_or_else(|_| source_path.to_path_buf());
+    let hash = full_path
+        .to_string_lossy()
+        .bytes()
+        .fold(zobrist::mix(0), |hash, byte| {
+            zobrist::mix(hash ^ byte as u64)
+        });
+    let name = source_path
+        .file_name()
+        .map_or("board".into(), |name| name.to_string_lossy());
+    cache_dir.join(format!("{}-{:016x}.ppm", name, hash))
+}
+
+/// Whether the thumbnail is missing or older than its source
+pub fn is_stale(source_path: &Path, thumbnail_path: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(source_path), modified(thumbnail_path)) {
+        (Ok(source), Ok(thumbnail)) => source > thumbnail,
+        _ => true,
+    }
+}
+
+/// Path of the thumbnail of a map file or replay, drawing it first if the cached one is stale
+pub fn cached_thumbnail(
+    source_path: &Path,
+    cache_dir: &Path,
+    profiles: &PlayerProfiles,
+) -> io::Result<PathBuf> {
+    let path = thumbnail_path(source_path, cache_dir);
+    if !is_stale(source_path, &path) {
+        return Ok(path);
+    }
+
+    let bytes = fs::read(source_path)?;
+    let board = match is_binary(&bytes) {
+        true => decode(&bytes).ok().map(|replay| replay_board(&replay)),
+        false => load_board(&String::from_utf8_lossy(&bytes)),
+    };
+    let board = board.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "neither a map file nor a replay",
+        )
+    })?;
+    fs::create_dir_all(cache_dir)?;
+    fs::write(
+        &path,
+        render_thumbnail(&board, profiles, THUMBNAIL_SIZE).to_ppm(),
+    )?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_regions_in_owner_colors() {
+        let board = load_board("players 2\nregion 0 1 0,0\nregion 1 1 1,0\n").unwrap();
+        let profiles = PlayerProfiles::default();
+        let thumbnail = render_thumbnail(&board, &profiles, 32);
+
+        assert_eq!(thumbnail.pixels.len(), 32 * 32);
+        assert_eq!(thumbnail.pixel(0, 0), BACKGROUND);
+
+        // the two hexes sit side by side in the middle row
+        let colors: Vec<[u8; 3]> = (0..32).map(|x| thumbnail.pixel(x, 16)).collect();
+        let [r, g, b, _] = profiles.color(0).as_rgba_f32();
+        assert!(colors.contains(&[r, g, b].map(|channel| (channel * 255.0) as u8)));
+        let [r, g, b, _] = profiles.color(1).as_rgba_f32();
+        assert!(colors.contains(&[r, g, b].map(|channel| (channel * 255.0) as u8)));
+
+        assert!(thumbnail.to_ppm().starts_with(b"P6\n32 32\n255\n"));
+        assert_eq!(Thumbnail::from_ppm(&thumbnail.to_ppm()), Some(thumbnail));
+        assert_eq!(Thumbnail::from_ppm(b"P6\n2 2\n255\n"), None);
+    }
+
+    #[test]
+    fn draws_svg_hexes_in_owner_colors() {
+        let board = load_board("players 2\nregion 0 1 0,0\nregion 1 1 1,0 2,0\n").unwrap();
+        let profiles = PlayerProfiles::default();
+        let svg = render_svg(&board, &profiles, "seed 1 2");
+
+        assert!(svg.contains("<!-- seed 1 2 -->"));
+        assert_eq!(svg.matches("<polygon ").count(), 3);
+        let [r, g, b] = owner_color(&profiles, 1);
+        let fill = format!("fill=\"#{:02x}{:02x}{:02x}\"", r, g, b);
+        assert_eq!(svg.matches(&fill).count(), 2);
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn loads_replays() {
+        let board = load_board("seed 4242 7\nplayers 2\n").unwrap();
+        assert!(!board.regions.is_empty());
+        assert!(load_board("nothing to see").is_none());
+    }
+
+    #[test]
+    fn missing_thumbnails_are_stale() {
+        let source = Path::new("maps/arena.txt");
+        let path = thumbnail_path(source, Path::new(THUMBNAILS_DIR));
+        assert!(path.starts_with(THUMBNAILS_DIR));
+        assert!(path.to_string_lossy().contains("arena.txt-"));
+        assert!(is_stale(source, &path));
+    }
+
+    #[test]
+    fn maps_of_the_same_name_keep_their_own_thumbnails() {
+        let cache_dir = Path::new(THUMBNAILS_DIR);
+        let path = thumbnail_path(Path::new("maps/arena.txt"), cache_dir);
+
+        assert_eq!(path, thumbnail_path(Path::new("maps/arena.txt"), cache_dir));
+        assert_ne!(
+            path,
+            thumbnail_path(Path::new("downloads/arena.txt"), cache_dir)
+        );
+    }
+}
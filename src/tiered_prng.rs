@@ -15,13 +15,20 @@ pub struct PrngMapResource {
     pub rng: ChaCha20Rng,
 }
 
+/// Randomness for in-game events that don't shape the map
+#[derive(Resource)]
+pub struct PrngEnvResource {
+    pub rng: ChaCha20Rng,
+}
+
 impl Plugin for PrngPlugin {
     fn build(&self, app: &mut App) {
         let seeds = app.world.get_resource::<PrngResource>().unwrap();
+        let map_rng = get_randomness(seeds.world_seed);
+        let env_rng = get_randomness(seeds.env_seed);
 
-        app.insert_resource(PrngMapResource {
-            rng: get_randomness(seeds.world_seed),
-        });
+        app.insert_resource(PrngMapResource { rng: map_rng })
+            .insert_resource(PrngEnvResource { rng: env_rng });
     }
 }
 
@@ -0,0 +1,379 @@
+//! Turning points of a game: eliminations, broken areas and lead changes.
+//!
+//! [`track_standings`] compares the standings of the players every time the game changes and
+//! fires a dedicated event for every turning point, also kept on the [`Timeline`] shown in the
+//! HUD and on the end screen.
+
+use bevy::prelude::*;
+
+use crate::events::EventGameOver;
+use crate::game::GameState;
+use crate::profiles::PlayerProfiles;
+use crate::session::MatchSession;
+use crate::ui::{spawn_toast, StackRankDiceUI};
+
+/// Connected areas of at least this many regions count as big
+const BIG_AREA: usize = 4;
+
+/// Number of latest turning points shown in the HUD
+const HUD_ENTRIES: usize = 5;
+
+/// Event that is fired when a player lost its last region
+#[derive(Reflect, FromReflect)]
+pub(crate) struct EventPlayerEliminated {
+    // An index of a player
+    pub(crate) player: usize,
+}
+
+/// Event that is fired when the largest connected area of a player stopped being big
+#[derive(Reflect, FromReflect)]
+pub(crate) struct EventAreaBroken {
+    // An index of a player
+    pub(crate) player: usize,
+    // Size of the largest connected area left to the player
+    pub(crate) largest_area: usize,
+}
+
+/// Event that is fired when a player took the lead from another one
+#[derive(Reflect, FromReflect)]
+pub(crate) struct EventLeadChanged {
+    // An index of the new leader
+    pub(crate) player: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TurningPoint {
+    Eliminated { player: usize },
+    AreaBroken { player: usize, largest_area: usize },
+    LeadChanged { player: usize },
+}
+
+impl TurningPoint {
+    pub fn description(&self, profiles: &PlayerProfiles) -> String {
+        match self {
+            TurningPoint::Eliminated { player } => {
+                format!("{} is eliminated", profiles.name(*player))
+            }
+            TurningPoint::AreaBroken {
+                player,
+                largest_area,
+            } => format!(
+                "{} is cut down to {} connected regions",
+                profiles.name(*player),
+                largest_area
+            ),
+            TurningPoint::LeadChanged { player } => {
+                format!("{} takes the lead", profiles.name(*player))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimelineEntry {
+    pub turn_counter: usize,
+    pub turning_point: TurningPoint,
+}
+
+/// What turning points are measured against
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Standings {
+    /// Number of regions owned, indexed by player
+    pub regions: Vec<usize>,
+    /// Size of the largest connected area, indexed by player
+    pub largest_areas: Vec<usize>,
+    /// The player owning the most regions, kept through ties
+    pub leader: Option<usize>,
+}
+
+impl Standings {
+    pub fn new(game_state: &GameState, previous_leader: Option<usize>) -> Self {
+        let board = &game_state.board;
+        let regions: Vec<usize> = (0..game_state.number_of_players)
+            .map(|player| board.regions.iter().filter(|r| r.owner == player).count())
+            .collect();
+        let largest_areas = (0..game_state.number_of_players)
+            .map(|player| board.largest_connected_area(player).0)
+            .collect();
+
+        let most = regions.iter().copied().max().unwrap_or(0);
+        let mut leaders = (0..regions.len()).filter(|player| regions[*player] == most);
+        let leader = match (leaders.next(), leaders.next()) {
+            (Some(leader), None) => Some(leader),
+            _ => previous_leader,
+        };
+
+        Standings {
+            regions,
+            largest_areas,
+            leader,
+        }
+    }
+
+    /// Turning points leading from `self` to `next`
+    pub fn turning_points(&self, next: &Standings) -> Vec<TurningPoint> {
+        let mut turning_points = Vec::new();
+
+        for player in 0..next.regions.len().min(self.regions.len()) {
+            if self.regions[player] > 0 && next.regions[player] == 0 {
+                turning_points.push(TurningPoint::Eliminated { player });
+            } else if self.largest_areas[player] >= BIG_AREA
+                && next.largest_areas[player] < BIG_AREA
+            {
+                turning_points.push(TurningPoint::AreaBroken {
+                    player,
+                    largest_area: next.largest_areas[player],
+                });
+            }
+        }
+
+        if let (Some(_), Some(player)) = (self.leader, next.leader) {
+            if self.leader != next.leader {
+                turning_points.push(TurningPoint::LeadChanged { player });
+            }
+        }
+
+        turning_points
+    }
+}
+
+/// Turning points of the current game, oldest first
+#[derive(Resource, Default)]
+pub struct Timeline {
+    pub entries: Vec<TimelineEntry>,
+    standings: Option<Standings>,
+}
+
+impl Timeline {
+    /// Forgets the current game, for a fresh one
+    pub fn reset(&mut self) {
+        self.entries.clear();
+        self.standings = None;
+    }
+
+    /// Records the turning points since the last update and returns them
+    pub fn update(&mut self, game_state: &GameState) -> Vec<TurningPoint> {
+        let previous_leader = self.standings.as_ref().and_then(|s| s.leader);
+        let standings = Standings::new(game_state, previous_leader);
+        let turning_points = match &self.standings {
+            Some(previous) => previous.turning_points(&standings),
+            None => Vec::new(),
+        };
+
+        self.entries
+            .extend(turning_points.iter().map(|turning_point| TimelineEntry {
+                turn_counter: game_state.turn_counter,
+                turning_point: *turning_point,
+            }));
+        self.standings = Some(standings);
+
+        turning_points
+    }
+
+    fn text(&self, entries: &[TimelineEntry], profiles: &PlayerProfiles) -> String {
+        entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "Turn {}: {}",
+                    entry.turn_counter,
+                    entry.turning_point.description(profiles)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+pub(crate) fn track_standings(
+    game_state: Res<GameState>,
+    mut timeline: ResMut<Timeline>,
+    mut event_player_eliminated_writer: EventWriter<EventPlayerEliminated>,
+    mut event_area_broken_writer: EventWriter<EventAreaBroken>,
+    mut event_lead_changed_writer: EventWriter<EventLeadChanged>,
+) {
+    if !game_state.is_changed() {
+        return;
+    }
+
+    for turning_point in timeline.update(&game_state) {
+        match turning_point {
+            TurningPoint::Eliminated { player } => {
+                event_player_eliminated_writer.send(EventPlayerEliminated { player })
+            }
+            TurningPoint::AreaBroken {
+                player,
+                largest_area,
+            } => event_area_broken_writer.send(EventAreaBroken {
+                player,
+                largest_area,
+            }),
+            TurningPoint::LeadChanged { player } => {
+                event_lead_changed_writer.send(EventLeadChanged { player })
+            }
+        }
+    }
+}
+
+pub(crate) fn announce_turning_points(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    profiles: Res<PlayerProfiles>,
+    mut event_player_eliminated_reader: EventReader<EventPlayerEliminated>,
+    mut event_area_broken_reader: EventReader<EventAreaBroken>,
+    mut event_lead_changed_reader: EventReader<EventLeadChanged>,
+) {
+    let eliminations = event_player_eliminated_reader
+        .iter()
+        .map(|e| TurningPoint::Eliminated { player: e.player });
+    let broken_areas = event_area_broken_reader
+        .iter()
+        .map(|e| TurningPoint::AreaBroken {
+            player: e.player,
+            largest_area: e.largest_area,
+        });
+    let lead_changes = event_lead_changed_reader
+        .iter()
+        .map(|e| TurningPoint::LeadChanged { player: e.player });
+
+    for turning_point in eliminations.chain(broken_areas).chain(lead_changes) {
+        spawn_toast(
+            &mut commands,
+            &asset_server,
+            turning_point.description(&profiles),
+        );
+    }
+}
+
+/// Text area with the latest turning points
+#[derive(Component)]
+pub(crate) struct TimelineText;
+
+pub(crate) fn setup_timeline_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(5.0),
+                    right: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Name::new("Timeline Text"))
+        .insert(TimelineText)
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn timeline_text_update(
+    timeline: Res<Timeline>,
+    profiles: Res<PlayerProfiles>,
+    mut query: Query<&mut Text, With<TimelineText>>,
+) {
+    if !timeline.is_changed() {
+        return;
+    }
+
+    let latest = timeline.entries.len().saturating_sub(HUD_ENTRIES);
+    for mut text in &mut query {
+        text.sections[0].value = timeline.text(&timeline.entries[latest..], &profiles);
+    }
+}
+
+/// Lists every turning point of the game on the end screen
+pub(crate) fn timeline_game_over(
+    mut commands: Commands,
+    mut event_game_over_reader: EventReader<EventGameOver>,
+    match_session: Option<Res<MatchSession>>,
+    timeline: Res<Timeline>,
+    profiles: Res<PlayerProfiles>,
+    asset_server: Res<AssetServer>,
+) {
+    if event_game_over_reader.iter().count() == 0 {
+        return;
+    }
+
+    // games in the middle of a match go straight to the next one
+    if let Some(match_session) = match_session {
+        if match_session.match_winner().is_none() {
+            return;
+        }
+    }
+
+    commands
+        .spawn(
+            TextBundle::from_section(
+                timeline.text(&timeline.entries, &profiles),
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 24.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Percent(55.0),
+                    left: Val::Percent(45.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Name::new("Timeline"))
+        .insert(StackRankDiceUI);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::game_state;
+
+    #[test]
+    fn records_turning_points() {
+        let mut timeline = Timeline::default();
+        let mut state = game_state(
+            "players 3\nregion 0 1 0,0\nregion 0 1 1,0\nregion 0 1 2,0\nregion 0 1 3,0\n\
+             region 1 1 0,1\nregion 1 1 1,1\nregion 2 1 3,1\n",
+        );
+        assert_eq!(timeline.update(&state), vec![]);
+
+        // player 2 conquers the last region of player 3, cuts the area of player 1 and takes the lead
+        state.board.regions[6].owner = 1;
+        state.board.regions[1].owner = 1;
+        state.turn_counter = 3;
+        assert_eq!(
+            timeline.update(&state),
+            vec![
+                TurningPoint::AreaBroken {
+                    player: 0,
+                    largest_area: 2
+                },
+                TurningPoint::Eliminated { player: 2 },
+                TurningPoint::LeadChanged { player: 1 },
+            ]
+        );
+
+        // a tie keeps the lead, overtaking changes it
+        state.board.regions[6].owner = 2;
+        assert_eq!(timeline.update(&state), vec![]);
+        state.board.regions[4].owner = 0;
+        assert_eq!(
+            timeline.update(&state),
+            vec![TurningPoint::LeadChanged { player: 0 }]
+        );
+
+        assert_eq!(timeline.entries.len(), 4);
+        assert_eq!(timeline.entries[0].turn_counter, 3);
+    }
+}
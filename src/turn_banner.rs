@@ -0,0 +1,171 @@
+//! Turn start cue: a banner with the name of the player in turn, in its color, and a flashing
+//! outline around its regions, so hotseat players notice when control changed hands.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::accessibility::AccessibilitySettings;
+use crate::events::EventTurnStart;
+use crate::game::{GameState, RegionId};
+use crate::profiles::PlayerProfiles;
+use crate::ui::StackRankDiceUI;
+
+const BANNER_DURATION: Duration = Duration::from_millis(1500);
+
+/// How long the regions of the player in turn flash
+const FLASH_DURATION: Duration = Duration::from_secs(1);
+
+/// Number of times the regions flash on
+const FLASHES: f32 = 3.0;
+
+/// Banner announcing the player in turn, growing in and fading out
+#[derive(Component)]
+pub(crate) struct TurnBanner {
+    timer: Timer,
+}
+
+/// Regions of the player whose turn just started, flashing for a moment
+#[derive(Resource)]
+pub struct TurnStartCue {
+    pub player: usize,
+    timer: Timer,
+}
+
+impl Default for TurnStartCue {
+    fn default() -> Self {
+        let mut timer = Timer::new(FLASH_DURATION, TimerMode::Once);
+        timer.tick(FLASH_DURATION);
+        TurnStartCue { player: 0, timer }
+    }
+}
+
+impl TurnStartCue {
+    pub fn start(&mut self, player: usize) {
+        self.player = player;
+        self.timer.reset();
+    }
+
+    pub fn tick(&mut self, delta: Duration) {
+        self.timer.tick(delta);
+    }
+
+    /// Regions outlined right now: the regions of the player, on every other beat of the flash
+    pub fn flashing_regions(&self, game_state: &GameState) -> Vec<RegionId> {
+        let beat = (self.timer.percent() * FLASHES * 2.0) as usize;
+        if self.timer.finished() || beat % 2 == 1 {
+            return Vec::new();
+        }
+
+        game_state
+            .board
+            .regions
+            .iter()
+            .filter(|region| region.owner == self.player)
+            .map(|region| region.id)
+            .collect()
+    }
+}
+
+pub(crate) fn announce_turn_start(
+    mut commands: Commands,
+    mut event_turn_start_reader: EventReader<EventTurnStart>,
+    asset_server: Res<AssetServer>,
+    profiles: Res<PlayerProfiles>,
+    mut cue: ResMut<TurnStartCue>,
+    banner_query: Query<Entity, With<TurnBanner>>,
+) {
+    // a turn passed over several players only announces the last one
+    let player = match event_turn_start_reader.iter().last() {
+        Some(event) => event.player,
+        None => return,
+    };
+
+    for banner in banner_query.iter() {
+        commands.entity(banner).despawn_recursive();
+    }
+
+    commands
+        .spawn(
+            TextBundle::from_section(
+                format!("{}'s turn", profiles.title(player)),
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 60.0,
+                    color: profiles.color(player),
+                },
+            )
+            .with_text_alignment(TextAlignment::CENTER)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Percent(40.0),
+                    left: Val::Percent(35.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Name::new("Turn Banner"))
+        .insert(TurnBanner {
+            timer: Timer::new(BANNER_DURATION, TimerMode::Once),
+        })
+        .insert(StackRankDiceUI);
+
+    cue.start(player);
+}
+
+pub(crate) fn turn_banner_update(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut cue: ResMut<TurnStartCue>,
+    settings: Res<AccessibilitySettings>,
+    mut banner_query: Query<(Entity, &mut TurnBanner, &mut Text)>,
+) {
+    cue.tick(time.delta());
+
+    for (entity, mut banner, mut text) in banner_query.iter_mut() {
+        banner.timer.tick(time.delta());
+        if banner.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        // grows in over the first fifth, fades out over the last half
+        let progress = banner.timer.percent();
+        let section = &mut text.sections[0];
+        section.style.font_size =
+            60.0 * settings.text_scale() * (0.6 + 0.4 * (progress * 5.0).min(1.0));
+        section.style.color.set_a(((1.0 - progress) * 2.0).min(1.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_file::MapFile;
+
+    #[test]
+    fn regions_flash_then_stop() {
+        let map =
+            MapFile::parse("players 2\nregion 0 3 0,0\nregion 1 2 1,0\nregion 1 2 5,5\n").unwrap();
+        let game_state = GameState {
+            board: map.board,
+            number_of_players: 2,
+            ..Default::default()
+        };
+
+        let mut cue = TurnStartCue::default();
+        assert!(cue.flashing_regions(&game_state).is_empty());
+
+        cue.start(1);
+        assert_eq!(cue.flashing_regions(&game_state), vec![1, 2]);
+
+        // second beat of the flash, off
+        cue.tick(FLASH_DURATION.mul_f32(1.5 / (FLASHES * 2.0)));
+        assert!(cue.flashing_regions(&game_state).is_empty());
+
+        cue.tick(FLASH_DURATION);
+        assert!(cue.flashing_regions(&game_state).is_empty());
+    }
+}
@@ -1,10 +1,16 @@
+use std::time::Duration;
+
 use bevy::{prelude::*, render::camera::ScalingMode};
 use bevy_dice::DicePluginSettings;
 use bevy_kira_audio::prelude::*;
 use bevy_mod_picking::PickingCameraBundle;
 
-use crate::board::PLAYER_COLORS;
-use crate::game::GameState;
+use crate::camera::CameraRig;
+use crate::game::{GameState, TurnPhase};
+use crate::geometry::center;
+use crate::profiles::PlayerProfiles;
+use crate::rules::{GameRules, ReinforcementRule};
+use crate::seats::BotIntent;
 
 /// Text area with a title for the game
 #[derive(Component)]
@@ -18,17 +24,172 @@ pub(crate) struct CurrentTurnText;
 #[derive(Component)]
 pub(crate) struct DiceRollUI;
 
+/// Camera looking at the game board
+#[derive(Component)]
+pub(crate) struct BoardCamera;
+
 /// UI element for a game. Used for end-game screen to destroy all UI elements
 #[derive(Component)]
 pub(crate) struct StackRankDiceUI;
 
+/// Text pinned to a point of the board, kept over it by [`board_labels_follow_camera`]
+#[derive(Component)]
+pub(crate) struct BoardLabel {
+    pub(crate) world_position: Vec3,
+}
+
+/// Keeps the labels over their point of the board as the camera moves
+pub(crate) fn board_labels_follow_camera(
+    camera_query: Query<(&Camera, &GlobalTransform), With<BoardCamera>>,
+    mut label_query: Query<(&BoardLabel, &mut Style, &mut Visibility)>,
+) {
+    let (camera, camera_transform) = match camera_query.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+
+    for (label, mut style, mut visibility) in label_query.iter_mut() {
+        match camera.world_to_viewport(camera_transform, label.world_position) {
+            Some(position) => {
+                style.position = UiRect {
+                    left: Val::Px(position.x),
+                    bottom: Val::Px(position.y),
+                    ..default()
+                };
+                visibility.is_visible = true;
+            }
+            None => visibility.is_visible = false,
+        }
+    }
+}
+
+/// Height of the thinking indicator of a bot, above the tallest stack of dice
+const THINKING_LABEL_HEIGHT: f32 = 4.6;
+
+/// Indicator over the strongest region a bot in turn considers attacking from
+#[derive(Component)]
+pub(crate) struct BotThinkingLabel;
+
+/// Shows the thinking indicator while a bot deliberates, over its strongest candidate region
+pub(crate) fn bot_thinking_label(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    bot_intent: Res<BotIntent>,
+    game_state: Res<GameState>,
+    profiles: Res<PlayerProfiles>,
+    label_query: Query<Entity, With<BotThinkingLabel>>,
+) {
+    if !bot_intent.is_changed() {
+        return;
+    }
+
+    for entity in label_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let (player, region) = match (bot_intent.player, bot_intent.candidates.first()) {
+        (Some(player), Some(region)) if bot_intent.chosen.is_none() => (player, *region),
+        _ => return,
+    };
+    let region = match game_state.board.regions.get(region) {
+        Some(region) => region,
+        None => return,
+    };
+    let [x, _, z] = center(1.0, &region.center_hex(), &[0.0; 3]);
+
+    commands
+        .spawn(
+            TextBundle::from_section(
+                format!("{} is thinking...", profiles.name(player)),
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 24.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                ..default()
+            }),
+        )
+        .insert(Name::new("Bot Thinking Label"))
+        .insert(BoardLabel {
+            world_position: Vec3::new(x, THINKING_LABEL_HEIGHT, z),
+        })
+        .insert(BotThinkingLabel)
+        .insert(StackRankDiceUI);
+}
+
+/// Short lived notification at the top of the screen
+#[derive(Component)]
+pub(crate) struct Toast {
+    timer: Timer,
+}
+
+pub(crate) fn spawn_toast(commands: &mut Commands, asset_server: &AssetServer, message: String) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                message,
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 40.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_text_alignment(TextAlignment::TOP_CENTER)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Percent(15.0),
+                    left: Val::Percent(35.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Name::new("Toast"))
+        .insert(Toast {
+            timer: Timer::new(Duration::from_secs(3), TimerMode::Once),
+        })
+        .insert(StackRankDiceUI);
+}
+
+pub(crate) fn toast_update(
+    mut commands: Commands,
+    mut toast_query: Query<(Entity, &mut Toast)>,
+    time: Res<Time>,
+) {
+    for (entity, mut toast) in toast_query.iter_mut() {
+        toast.timer.tick(time.delta());
+        if toast.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
 pub(crate) fn player_turn_text_update(
     game_state: Res<GameState>,
+    rules: Res<GameRules>,
+    profiles: Res<PlayerProfiles>,
     mut query: Query<&mut Text, With<CurrentTurnText>>,
 ) {
+    let player = game_state.turn_of_player;
+    let name = profiles.title(player).to_uppercase();
     for mut text in &mut query {
-        text.sections[0].value = format!("PLAYER {} TURN", game_state.turn_of_player + 1,);
-        text.sections[0].style.color = PLAYER_COLORS[game_state.turn_of_player as usize];
+        text.sections[0].value = match (game_state.turn_phase, rules.reinforcements) {
+            (TurnPhase::Reinforcement, ReinforcementRule::Manual) => {
+                format!("{} PLACE {} DICE", name, game_state.reserve(player))
+            }
+            (_, ReinforcementRule::Manual | ReinforcementRule::Automatic) => format!(
+                "{} TURN (AREA {}, RESERVE {})",
+                name,
+                game_state.board.largest_connected_area(player).0,
+                game_state.reserve(player)
+            ),
+            (_, ReinforcementRule::None) => format!("{} TURN", name),
+        };
+        text.sections[0].style.color = profiles.color(player);
     }
 }
 
@@ -80,7 +241,9 @@ pub(crate) fn setup_ui(
         })
         .insert(PickingCameraBundle::default())
         // .insert(UiCameraConfig { show_ui: false })
-        .insert(Name::new("Board Camera"));
+        .insert(Name::new("Board Camera"))
+        .insert(BoardCamera)
+        .insert(CameraRig::default());
 
     // Current Turn Text
     commands
@@ -116,20 +279,23 @@ pub(crate) fn setup_ui(
         ..default()
     });
 
-    for (i, dice_camera) in dice_plugin_settings.render_handles.iter().enumerate() {
-        commands
-            .spawn(ImageBundle {
-                image: UiImage(dice_camera.clone()),
-                style: Style {
-                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+    // without the dice plugin, on a low memory budget, the sums are shown on their own
+    for i in 0..dice_plugin_settings.number_of_fields {
+        if let Some(dice_camera) = dice_plugin_settings.render_handles.get(i) {
+            commands
+                .spawn(ImageBundle {
+                    image: UiImage(dice_camera.clone()),
+                    style: Style {
+                        size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                        ..default()
+                    },
                     ..default()
-                },
-                ..default()
-            })
-            .insert(Name::new("Dice Roll View"))
-            .insert(DiceRollUI)
-            .insert(Visibility { is_visible: false })
-            .insert(StackRankDiceUI);
+                })
+                .insert(Name::new("Dice Roll View"))
+                .insert(DiceRollUI)
+                .insert(Visibility { is_visible: false })
+                .insert(StackRankDiceUI);
+        }
 
         // Dice Throw Sum Text
         commands
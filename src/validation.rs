@@ -0,0 +1,315 @@
+//! Lints for handcrafted maps: problems that don't stop a map from loading but make it unfair or
+//! unwinnable, such as regions in pieces or players cut off from the rest of the board.
+//!
+//! [`validate_board`] checks a board being edited, [`validate_map`] checks a map file and points
+//! every problem to the line it comes from. Run it with `stackrankdice validate <map file>`.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use crate::game::{Board, RegionId};
+use crate::map_file::{MapErrorKind, MapFile, MAX_DICE};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapProblem {
+    /// The map file can't be parsed
+    Invalid(MapErrorKind),
+    EmptyRegion(RegionId),
+    /// The hexes of the region don't form a single patch
+    DisconnectedRegion(RegionId),
+    DiceOutOfRange {
+        region: RegionId,
+        dice: usize,
+    },
+    OverlappingHex {
+        hex: (isize, isize),
+        regions: (RegionId, RegionId),
+    },
+    OwnerOutOfRange {
+        region: RegionId,
+        owner: usize,
+    },
+    ObjectiveOutOfRange(RegionId),
+    /// A declared player owns no region
+    MissingPlayer(usize),
+    /// The regions of the player don't border any opponent, even through other regions
+    UnreachablePlayer(usize),
+    /// The board falls apart in this many groups of regions, no player can conquer all of them
+    SplitBoard(usize),
+}
+
+impl fmt::Display for MapProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapProblem::Invalid(kind) => write!(f, "{}", kind),
+            MapProblem::EmptyRegion(region) => write!(f, "region {} has no hexes", region),
+            MapProblem::DisconnectedRegion(region) => {
+                write!(f, "region {} is split in disconnected pieces", region)
+            }
+            MapProblem::DiceOutOfRange { region, dice } => write!(
+                f,
+                "region {} holds {} dice, expected 1 to {}",
+                region, dice, MAX_DICE
+            ),
+            MapProblem::OverlappingHex {
+                hex: (q, r),
+                regions: (region_1, region_2),
+            } => write!(
+                f,
+                "hex {},{} belongs to both regions {} and {}",
+                q, r, region_1, region_2
+            ),
+            MapProblem::OwnerOutOfRange { region, owner } => write!(
+                f,
+                "region {} is owned by player {} who isn't declared",
+                region, owner
+            ),
+            MapProblem::ObjectiveOutOfRange(region) => {
+                write!(f, "objective region {} doesn't exist", region)
+            }
+            MapProblem::MissingPlayer(player) => write!(f, "player {} owns no region", player),
+            MapProblem::UnreachablePlayer(player) => {
+                write!(f, "player {} can't reach any opponent", player)
+            }
+            MapProblem::SplitBoard(groups) => write!(
+                f,
+                "the board is split in {} groups of regions and can't be conquered",
+                groups
+            ),
+        }
+    }
+}
+
+/// A problem, with the 1-based line of the map file it comes from when there is one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapDiagnostic {
+    pub line: Option<usize>,
+    pub problem: MapProblem,
+}
+
+impl fmt::Display for MapDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.problem),
+            None => write!(f, "{}", self.problem),
+        }
+    }
+}
+
+/// Groups of regions connected through neighbouring regions, as lists of region ids
+fn region_groups(board: &Board) -> Vec<Vec<RegionId>> {
+    let mut neighbours: Vec<Vec<RegionId>> = vec![Vec::new(); board.regions.len()];
+    for (region_1, region_2) in board.adjacencies() {
+        neighbours[region_1].push(region_2);
+        neighbours[region_2].push(region_1);
+    }
+
+    let mut visited = vec![false; board.regions.len()];
+    let mut groups = Vec::new();
+    for start in 0..board.regions.len() {
+        if visited[start] {
+            continue;
+        }
+
+        visited[start] = true;
+        let mut group = vec![start];
+        let mut queue = VecDeque::from([start]);
+        while let Some(region) = queue.pop_front() {
+            for other in neighbours[region].iter() {
+                if !visited[*other] {
+                    visited[*other] = true;
+                    group.push(*other);
+                    queue.push_back(*other);
+                }
+            }
+        }
+        groups.push(group);
+    }
+
+    groups
+}
+
+/// Every problem of a board declared for `number_of_players` players, region problems first
+pub fn validate_board(board: &Board, number_of_players: usize) -> Vec<MapProblem> {
+    let mut problems = Vec::new();
+
+    let mut hex_regions: HashMap<(isize, isize), RegionId> = HashMap::new();
+    for (id, region) in board.regions.iter().enumerate() {
+        if region.hexes.is_empty() {
+            problems.push(MapProblem::EmptyRegion(id));
+        } else if !region.is_contiguous() {
+            problems.push(MapProblem::DisconnectedRegion(id));
+        }
+        if !(1..=MAX_DICE).contains(&region.num_dice) {
+            problems.push(MapProblem::DiceOutOfRange {
+                region: id,
+                dice: region.num_dice,
+            });
+        }
+        if region.owner >= number_of_players {
+            problems.push(MapProblem::OwnerOutOfRange {
+                region: id,
+                owner: region.owner,
+            });
+        }
+        for hex in region.hexes.iter() {
+            if let Some(other) = hex_regions.insert(*hex, id) {
+                if other != id {
+                    problems.push(MapProblem::OverlappingHex {
+                        hex: *hex,
+                        regions: (other, id),
+                    });
+                }
+            }
+        }
+    }
+
+    for objective in board.objectives.iter() {
+        if *objective >= board.regions.len() {
+            problems.push(MapProblem::ObjectiveOutOfRange(*objective));
+        }
+    }
+
+    let groups = region_groups(board);
+    for player in 0..number_of_players {
+        let owns = |group: &Vec<RegionId>| group.iter().any(|r| board.regions[*r].owner == player);
+        let faces_opponent = |group: &Vec<RegionId>| {
+            group
+                .iter()
+                .any(|region| board.regions[*region].owner != player)
+        };
+
+        match groups.iter().any(owns) {
+            false => problems.push(MapProblem::MissingPlayer(player)),
+            true => {
+                if !groups.iter().filter(|g| owns(g)).any(faces_opponent) {
+                    problems.push(MapProblem::UnreachablePlayer(player));
+                }
+            }
+        }
+    }
+    if groups.len() > 1 {
+        problems.push(MapProblem::SplitBoard(groups.len()));
+    }
+
+    problems
+}
+
+/// Line of every `region` directive of a map file, indexed by region id
+fn region_lines(source: &str) -> Vec<usize> {
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let content = line.split('#').next().unwrap_or("");
+            content.split_whitespace().next() == Some("region")
+        })
+        .map(|(index, _)| index + 1)
+        .collect()
+}
+
+/// Every problem of a map file. A map that can't be parsed only reports why.
+pub fn validate_map(source: &str) -> Vec<MapDiagnostic> {
+    let map = match MapFile::parse(source) {
+        Ok(map) => map,
+        Err(err) => {
+            return vec![MapDiagnostic {
+                line: Some(err.line),
+                problem: MapProblem::Invalid(err.kind),
+            }]
+        }
+    };
+
+    let lines = region_lines(source);
+    validate_board(&map.board, map.number_of_players)
+        .into_iter()
+        .map(|problem| {
+            let region = match &problem {
+                MapProblem::EmptyRegion(region)
+                | MapProblem::DisconnectedRegion(region)
+                | MapProblem::DiceOutOfRange { region, .. }
+                | MapProblem::OwnerOutOfRange { region, .. }
+                | MapProblem::OverlappingHex {
+                    regions: (_, region),
+                    ..
+                } => Some(*region),
+                _ => None,
+            };
+            MapDiagnostic {
+                line: region.and_then(|region| lines.get(region).copied()),
+                problem,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_problems_with_their_line() {
+        let source = "players 3\n# a row and a lonely region\nregion 0 2 0,0 2,0\n\
+                      region 1 3 1,0\nregion 0 1 5,5\n";
+        let diagnostics = validate_map(source);
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                MapDiagnostic {
+                    line: Some(3),
+                    problem: MapProblem::DisconnectedRegion(0),
+                },
+                MapDiagnostic {
+                    line: None,
+                    problem: MapProblem::MissingPlayer(2),
+                },
+                MapDiagnostic {
+                    line: None,
+                    problem: MapProblem::SplitBoard(2),
+                },
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "line 3: region 0 is split in disconnected pieces"
+        );
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        let diagnostics = validate_map("players 2\nregion 0 9 0,0\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, Some(2));
+        assert_eq!(
+            diagnostics[0].problem,
+            MapProblem::Invalid(MapErrorKind::DiceOutOfRange(9))
+        );
+    }
+
+    #[test]
+    fn checks_edited_boards() {
+        let mut board = MapFile::parse("players 2\nregion 0 2 0,0\nregion 1 1 1,0\n")
+            .unwrap()
+            .board;
+        assert_eq!(validate_board(&board, 2), vec![]);
+
+        board.regions[1].hexes.push((0, 0));
+        board.regions[1].num_dice = 0;
+        board.regions.push(board.regions[0].clone());
+        board.regions[2].id = 2;
+        board.regions[2].hexes = vec![(9, 9)];
+        board.regions[2].owner = 0;
+        assert_eq!(
+            validate_board(&board, 2),
+            vec![
+                MapProblem::DiceOutOfRange { region: 1, dice: 0 },
+                MapProblem::OverlappingHex {
+                    hex: (0, 0),
+                    regions: (0, 1)
+                },
+                MapProblem::SplitBoard(2),
+            ]
+        );
+    }
+}
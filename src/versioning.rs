@@ -0,0 +1,154 @@
+//! Format versions of map files and replays (saves included), so files written by older releases
+//! keep loading as the formats evolve.
+//!
+//! A file declares its version with a `version <n>` line before any other directive. Files
+//! without one were written before versions existed and are version 0. Loading a file runs the
+//! migrations from its version up to the current one. A migration rewrites the source one version
+//! forward and keeps every line in place, so errors still point to the lines of the original file.
+
+use std::borrow::Cow;
+use std::fmt;
+
+/// Turns the source of a file of version `n` into the source of version `n + 1`
+pub type Migration = fn(&str) -> String;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionError {
+    InvalidVersion(String),
+    /// The file comes from a newer release
+    UnsupportedVersion {
+        found: u32,
+        supported: u32,
+    },
+    /// The `version` line comes after other directives
+    MisplacedVersion,
+}
+
+impl fmt::Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionError::InvalidVersion(value) => {
+                write!(f, "`{}` is not a valid version", value)
+            }
+            VersionError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "version {} is newer than the supported version {}, update the game",
+                found, supported
+            ),
+            VersionError::MisplacedVersion => {
+                write!(f, "`version` must come before any other directive")
+            }
+        }
+    }
+}
+
+/// Version 1 only added the `version` line, the rest of the format is unchanged
+pub fn from_unversioned(source: &str) -> String {
+    source.to_string()
+}
+
+/// Version declared by `source` and its 1-based line, version 0 on line 1 when it declares none.
+/// Errors come with their line.
+pub fn declared_version(source: &str) -> Result<(u32, usize), (usize, VersionError)> {
+    let mut version = None;
+    let mut first_directive = true;
+
+    for (index, line) in source.lines().enumerate() {
+        let content = line.split('#').next().unwrap_or("").trim();
+        let mut tokens = content.split_whitespace();
+        match tokens.next() {
+            None => continue,
+            Some("version") => {
+                if !first_directive {
+                    return Err((index + 1, VersionError::MisplacedVersion));
+                }
+
+                let value = tokens.next().unwrap_or("");
+                let declared = value
+                    .parse::<u32>()
+                    .map_err(|_| (index + 1, VersionError::InvalidVersion(value.to_string())))?;
+                version = Some((declared, index + 1));
+            }
+            Some(_) => {}
+        }
+        first_directive = false;
+    }
+
+    Ok(version.unwrap_or((0, 1)))
+}
+
+/// Brings `source` to the current version, the number of `migrations`
+pub fn migrate<'a>(
+    source: &'a str,
+    migrations: &[Migration],
+) -> Result<Cow<'a, str>, (usize, VersionError)> {
+    let (version, line) = declared_version(source)?;
+    let version = version as usize;
+    if version > migrations.len() {
+        return Err((
+            line,
+            VersionError::UnsupportedVersion {
+                found: version as u32,
+                supported: migrations.len() as u32,
+            },
+        ));
+    }
+
+    let mut migrated = Cow::Borrowed(source);
+    for migration in migrations[version..].iter() {
+        migrated = Cow::Owned(migration(&migrated));
+    }
+
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename_pass(source: &str) -> String {
+        source.replace("skip", "pass")
+    }
+
+    #[test]
+    fn reads_declared_versions() {
+        assert_eq!(declared_version("players 2\n"), Ok((0, 1)));
+        assert_eq!(
+            declared_version("# saved game\n\nversion 3\nplayers 2\n"),
+            Ok((3, 3))
+        );
+        assert_eq!(
+            declared_version("version x\n"),
+            Err((1, VersionError::InvalidVersion("x".to_string())))
+        );
+        assert_eq!(
+            declared_version("players 2\nversion 1\n"),
+            Err((2, VersionError::MisplacedVersion))
+        );
+    }
+
+    #[test]
+    fn runs_migrations_from_the_declared_version() {
+        let migrations: [Migration; 2] = [from_unversioned, rename_pass];
+
+        assert_eq!(migrate("skip\n", &migrations).unwrap(), "pass\n");
+        assert_eq!(
+            migrate("version 1\nskip\n", &migrations).unwrap(),
+            "version 1\npass\n"
+        );
+        assert!(matches!(
+            migrate("version 2\nskip\n", &migrations),
+            Ok(Cow::Borrowed("version 2\nskip\n"))
+        ));
+        assert_eq!(
+            migrate("version 3\n", &migrations).unwrap_err(),
+            (
+                1,
+                VersionError::UnsupportedVersion {
+                    found: 3,
+                    supported: 2
+                }
+            )
+        );
+    }
+}
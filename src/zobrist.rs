@@ -0,0 +1,73 @@
+//! Zobrist hashing of game states.
+//!
+//! The hash of a state is the XOR of one key per region owner, one key per region dice count and
+//! one key for the player to move, so it can be updated incrementally by XORing out the keys of a
+//! changed feature and XORing in the new ones. Keys are derived from the feature itself instead
+//! of a random table, so they are the same for any board size and across runs.
+
+const OWNER_FEATURE: u64 = 1;
+const DICE_FEATURE: u64 = 2;
+const TURN_FEATURE: u64 = 3;
+
+/// SplitMix64 finalizer, spreads the bits of `x` over the whole word
+pub fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn key(feature: u64, index: usize, value: usize) -> u64 {
+    mix(mix(mix(feature) ^ index as u64) ^ value as u64)
+}
+
+/// Key of `region` being owned by `owner`
+pub fn owner_key(region: usize, owner: usize) -> u64 {
+    key(OWNER_FEATURE, region, owner)
+}
+
+/// Key of `region` holding `num_dice` dice
+pub fn dice_key(region: usize, num_dice: usize) -> u64 {
+    key(DICE_FEATURE, region, num_dice)
+}
+
+/// Key of `player` being the player to move
+pub fn turn_key(player: usize) -> u64 {
+    key(TURN_FEATURE, 0, player)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameState;
+    use crate::test_support;
+
+    fn game_state() -> GameState {
+        test_support::game_state("players 2\nregion 0 3 0,0\nregion 1 2 1,0\n")
+    }
+
+    #[test]
+    fn hash_tracks_owners_dice_and_turn() {
+        let original = game_state();
+        let mut changed = original.clone();
+
+        changed.board.regions[1].owner = 0;
+        assert_ne!(changed.zobrist_hash(), original.zobrist_hash());
+        changed.board.regions[1].owner = 1;
+        assert_eq!(changed.zobrist_hash(), original.zobrist_hash());
+
+        changed.advance_turn();
+        assert_ne!(changed.zobrist_hash(), original.zobrist_hash());
+    }
+
+    #[test]
+    fn incremental_update_matches_full_hash() {
+        let mut game_state = game_state();
+        let mut hash = game_state.zobrist_hash();
+
+        game_state.board.regions[0].num_dice = 5;
+        hash ^= dice_key(0, 3) ^ dice_key(0, 5);
+
+        assert_eq!(hash, game_state.zobrist_hash());
+    }
+}
@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use stackrankdice::{app::build_app, game::GameState};
+use stackrankdice::{app::build_app, game::GameState, rules::GameRules};
 
 #[test]
 fn fixed_world_undef_env_seed() {
@@ -9,7 +9,7 @@ fn fixed_world_undef_env_seed() {
 
     let game_state = app.world.get_resource::<GameState>().unwrap().clone();
 
-    let possible_moves = game_state.possible_moves();
+    let possible_moves = game_state.possible_moves(&GameRules::default());
 
     println!("Number of possible moves: {:?}", possible_moves.len());
     assert!(!possible_moves.is_empty());
@@ -0,0 +1,22 @@
+use stackrankdice::rules::GameRules;
+use stackrankdice::simulation::{simulate, simulate_batch, SimulationConfig};
+
+#[test]
+fn batch_matches_sequential_games() {
+    let configs: Vec<SimulationConfig> = (0..8)
+        .map(|seed| SimulationConfig {
+            rules: GameRules::with_players(2 + seed as usize % 3),
+            world_seed: 4242 + seed,
+            env_seed: 17 + seed,
+            max_turns: 200,
+        })
+        .collect();
+
+    let summaries = simulate_batch(&configs);
+
+    assert_eq!(summaries.len(), configs.len());
+    for (config, summary) in configs.iter().zip(summaries.iter()) {
+        assert_eq!(*summary, simulate(config));
+        assert!(summary.winner.is_some() || summary.turns >= config.max_turns);
+    }
+}